@@ -0,0 +1,53 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use weighted_graph::chain::block::{Block, BlockData, BlockDataType, EdgeData};
+use weighted_graph::chain::hasher::HashAlgorithm;
+use weighted_graph::chain::wallet::Wallet;
+use weighted_graph::chain::Chain;
+
+const BLOCKS: usize = 10_000;
+
+fn edge_data(id: usize) -> BlockData {
+    let edge = EdgeData::new(format!("{id}:User"), format!("{id}:Song"), 5);
+
+    BlockData::new(BlockDataType::EdgeData, Some(edge), None)
+}
+
+/// Chain of honestly generated blocks following the genesis block of default chain.
+fn blocks() -> Vec<Block> {
+    let mut wallet = Wallet::default();
+    let mut blocks = Chain::default().blocks;
+
+    for id in 1..=BLOCKS {
+        let previous_hash = blocks.last().unwrap().hash.clone();
+        blocks.push(Block::new(id, previous_hash, edge_data(id), &mut wallet, 0, HashAlgorithm::Sha256).unwrap());
+    }
+
+    blocks
+}
+
+fn create_block(criterion: &mut Criterion) {
+    let mut wallet = Wallet::default();
+
+    criterion.bench_function("create_block", |bencher| {
+        bencher.iter(|| Block::new(1, "previous".to_string(), edge_data(1), &mut wallet, 0, HashAlgorithm::Sha256).unwrap())
+    });
+}
+
+fn validate_block_hash(criterion: &mut Criterion) {
+    let block = Block::new(1, "previous".to_string(), edge_data(1), &mut Wallet::default(), 0, HashAlgorithm::Sha256).unwrap();
+
+    criterion.bench_function("validate_block_hash", |bencher| {
+        bencher.iter(|| Block::validate_block_hash(&block, HashAlgorithm::Sha256).unwrap())
+    });
+}
+
+fn replace_chain(criterion: &mut Criterion) {
+    let blocks = blocks();
+
+    criterion.bench_function("replace_chain_10k", |bencher| {
+        bencher.iter_batched(Chain::default, |mut chain| chain.replace_chain(&blocks).unwrap(), BatchSize::LargeInput)
+    });
+}
+
+criterion_group!(benches, create_block, validate_block_hash, replace_chain);
+criterion_main!(benches);
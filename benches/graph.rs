@@ -0,0 +1,101 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rustc_hash::FxHashMap;
+use std::time::{Duration, Instant};
+use weighted_graph::chain::Chain;
+use weighted_graph::graph::Graph;
+use weighted_graph::query_processor::QueryProcessor;
+
+const NODES: usize = 10_000;
+
+fn attributes(values: &[(&str, &str)]) -> FxHashMap<String, String> {
+    values.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+}
+
+/// Graph with users and songs, where each user is connected to one song.
+fn graph() -> (Graph, Vec<String>, Vec<String>) {
+    let mut graph = Graph::default();
+    graph.create_definition("User".to_string(), vec!["name".to_string()]).unwrap();
+    graph.create_definition("Song".to_string(), vec!["name".to_string()]).unwrap();
+
+    let mut users = vec![];
+    let mut songs = vec![];
+
+    for i in 0..NODES {
+        let name = i.to_string();
+        let user = graph.add_node("User".to_string(), attributes(&[("name", &name)])).unwrap();
+        let song = graph.add_node("Song".to_string(), attributes(&[("name", &name)])).unwrap();
+        users.push(user[0]["$id"].clone());
+        songs.push(song[0]["$id"].clone());
+    }
+
+    for (user, song) in users.iter().zip(&songs) {
+        graph
+            .add_edge(
+                ("User".to_string(), attributes(&[("$id", user)])),
+                ("Song".to_string(), attributes(&[("$id", song)])),
+                5,
+            )
+            .unwrap();
+    }
+
+    (graph, users, songs)
+}
+
+fn add_node(criterion: &mut Criterion) {
+    let (mut graph, _, _) = graph();
+
+    criterion.bench_function("add_node", |bencher| {
+        bencher.iter(|| graph.add_node("User".to_string(), attributes(&[("name", "John")])).unwrap())
+    });
+}
+
+fn add_edge(criterion: &mut Criterion) {
+    let (mut graph, _, songs) = graph();
+    let mut songs = songs.iter().cycle();
+
+    criterion.bench_function("add_edge", |bencher| {
+        bencher.iter_custom(|iterations| {
+            let mut elapsed = Duration::ZERO;
+
+            for _ in 0..iterations {
+                // Edge is added from new user, so it doesn't exist yet
+                let user = graph.add_node("User".to_string(), attributes(&[("name", "John")])).unwrap();
+                let from = ("User".to_string(), attributes(&[("$id", &user[0]["$id"])]));
+                let to = ("Song".to_string(), attributes(&[("$id", songs.next().unwrap())]));
+
+                let start = Instant::now();
+                black_box(graph.add_edge(from, to, 5).unwrap());
+                elapsed += start.elapsed();
+            }
+
+            elapsed
+        })
+    });
+}
+
+fn search(criterion: &mut Criterion) {
+    let (graph, users, _) = graph();
+    let mut users = users.iter().cycle();
+
+    criterion.bench_function("search_with_join", |bencher| {
+        bencher.iter(|| {
+            let user = users.next().unwrap();
+            graph
+                .search("User".to_string(), attributes(&[("$id", user)]), vec![("Song".to_string(), 3)])
+                .unwrap()
+        })
+    });
+}
+
+fn parse_command(criterion: &mut Criterion) {
+    let (mut graph, users, _) = graph();
+    let mut chain = Chain::default();
+    let command = format!("fetch node User($id=\"{}\") join Song($weight>\"3\")", users[0]);
+
+    criterion.bench_function("parse_fetch_command", |bencher| {
+        bencher.iter(|| QueryProcessor::parse_command(&mut graph, &mut chain, &command).unwrap().unwrap())
+    });
+}
+
+criterion_group!(benches, add_node, add_edge, search, parse_command);
+criterion_main!(benches);
@@ -0,0 +1,38 @@
+use std::fmt::{Debug, Display, Formatter};
+
+pub enum ClientError {
+    /// Connection to the server failed or was closed
+    Connection(String),
+    /// Response of the server could not be parsed, or could not be converted to requested type
+    Response(String),
+    /// Command was rejected by the database, with error code (e.g. `NODE_NOT_FOUND`) and message
+    Database(String, String),
+}
+
+impl ClientError {
+    /// Error code of the database, e.g. `NODE_NOT_FOUND`, or `None` if the command didn't reach the database.
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            ClientError::Database(code, _) => Some(code),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ClientError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Connection(reason) => write!(formatter, "Connection to the database failed: {reason}."),
+            ClientError::Response(reason) => write!(formatter, "Response of the database is not valid: {reason}."),
+            ClientError::Database(code, message) => write!(formatter, "{code}: {message}"),
+        }
+    }
+}
+
+impl Debug for ClientError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, formatter)
+    }
+}
+
+impl std::error::Error for ClientError {}
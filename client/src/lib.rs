@@ -0,0 +1,281 @@
+//! Client of the Blockchain Weighted Graph Database query server (started with `--query-address` argument).
+//!
+//! Commands are sent as JSON envelopes with request identifier, so each response is matched to its command. Typed
+//! methods build commands from structs implementing [`Definition`], and convert result rows back to them:
+//!
+//! ```no_run
+//! use bwgd_client::{Client, Definition};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Person {
+//!     name: String,
+//!     age: u32,
+//! }
+//!
+//! impl Definition for Person {
+//!     const NAME: &'static str = "Person";
+//! }
+//!
+//! let mut client = Client::connect("127.0.0.1:7878").unwrap();
+//! let john = client.add_node(&Person { name: "John".to_string(), age: 42 }).unwrap();
+//! let person = client.fetch::<Person>(&john.id).unwrap();
+//! ```
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+pub mod error;
+pub mod row;
+
+pub use error::ClientError;
+pub use row::Row;
+
+/// Node definition, whose fields are attributes of the node.
+pub trait Definition: Serialize + DeserializeOwned {
+    /// Name of the node definition, e.g. `Person`
+    const NAME: &'static str;
+}
+
+/// Node stored in the database, with its identifier.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Stored<T> {
+    pub id: String,
+    pub node: T,
+}
+
+/// Connection between two nodes.
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+pub struct Connection {
+    #[serde(rename = "$from")]
+    pub from: String,
+    #[serde(rename = "$to")]
+    pub to: String,
+    #[serde(rename = "$weight")]
+    pub weight: i8,
+}
+
+pub struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    next_id: u64,
+}
+
+impl Client {
+    const ID_ATTRIBUTE: &'static str = "$id";
+    /// Time for which command is sent or its response is awaited, so stalled server doesn't block the caller forever.
+    const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub fn connect(address: impl ToSocketAddrs) -> Result<Client, ClientError> {
+        let writer = TcpStream::connect(address).map_err(|error| ClientError::Connection(error.to_string()))?;
+        let reader = BufReader::new(writer.try_clone().map_err(|error| ClientError::Connection(error.to_string()))?);
+
+        Client { reader, writer, next_id: 0 }.with_timeout(Self::DEFAULT_TIMEOUT)
+    }
+
+    /// Set read and write timeout of the connection (default is 30 seconds), e.g. for commands which run longer.
+    pub fn with_timeout(self, timeout: Duration) -> Result<Client, ClientError> {
+        let connection = |error: std::io::Error| ClientError::Connection(error.to_string());
+        self.writer.set_read_timeout(Some(timeout)).map_err(connection)?;
+        self.writer.set_write_timeout(Some(timeout)).map_err(connection)?;
+
+        Ok(self)
+    }
+
+    /// Authenticate with token of the access file, so following commands are executed with role of the client.
+    pub fn authenticate(&mut self, token: &str) -> Result<(), ClientError> {
+        self.execute(&format!("authenticate {token}")).map(|_| ())
+    }
+
+    /// Execute any command and return its result rows.
+    pub fn execute(&mut self, command: &str) -> Result<Vec<Row>, ClientError> {
+        self.next_id += 1;
+        let request = json!({ "id": self.next_id, "command": command });
+        writeln!(self.writer, "{request}").map_err(|error| ClientError::Connection(error.to_string()))?;
+
+        let mut line = String::new();
+        if self
+            .reader
+            .read_line(&mut line)
+            .map_err(|error| ClientError::Connection(error.to_string()))?
+            == 0
+        {
+            return Err(ClientError::Connection("connection was closed".to_string()));
+        }
+
+        let mut response: Value = serde_json::from_str(&line).map_err(|error| ClientError::Response(error.to_string()))?;
+        if response["id"] != json!(self.next_id) {
+            return Err(ClientError::Response(format!(
+                "response {} doesn't match request {}",
+                response["id"], self.next_id
+            )));
+        }
+        if let Some(error) = response.get("error") {
+            let text = |key: &str| error[key].as_str().unwrap_or_default().to_string();
+            return Err(ClientError::Database(text("code"), text("message")));
+        }
+
+        serde_json::from_value(response["result"].take()).map_err(|error| ClientError::Response(error.to_string()))
+    }
+
+    /// Add node with attributes given by its fields, and return it with generated identifier.
+    pub fn add_node<T: Definition>(&mut self, node: &T) -> Result<Stored<T>, ClientError> {
+        let attributes = Self::attributes(&row::to_attributes(node)?);
+        let rows = self.execute(&format!("add node {}({attributes})", T::NAME))?;
+
+        Self::single(rows)
+    }
+
+    /// Fetch node by its identifier.
+    pub fn fetch<T: Definition>(&mut self, id: &str) -> Result<Stored<T>, ClientError> {
+        let rows = self.execute(&format!("fetch node {}($id=\"{}\")", T::NAME, Self::escape(id)))?;
+
+        Self::single(rows)
+    }
+
+    /// Fetch nodes whose attributes meet given conditions, e.g. `age>"18"`.
+    ///
+    /// Conditions are put into the command as they are, so they must be trusted input. Values given by users must be
+    /// escaped first, otherwise they can change the command (e.g. end the condition and join other nodes).
+    pub fn fetch_where<T: Definition>(&mut self, conditions: &str) -> Result<Vec<Stored<T>>, ClientError> {
+        let rows = self.execute(&format!("fetch node {} where ({conditions})", T::NAME))?;
+
+        rows.iter().map(Self::stored).collect()
+    }
+
+    /// Replace all attributes of the node with given identifier.
+    pub fn replace_node<T: Definition>(&mut self, id: &str, node: &T) -> Result<Stored<T>, ClientError> {
+        let attributes = Self::attributes(&row::to_attributes(node)?);
+        let rows = self.execute(&format!("replace node {}($id=\"{}\") with ({attributes})", T::NAME, Self::escape(id)))?;
+
+        Self::single(rows)
+    }
+
+    pub fn delete_node<T: Definition>(&mut self, id: &str) -> Result<(), ClientError> {
+        self.execute(&format!("delete node {}($id=\"{}\")", T::NAME, Self::escape(id)))
+            .map(|_| ())
+    }
+
+    pub fn add_connection<F: Definition, T: Definition>(&mut self, from: &str, to: &str, weight: i8) -> Result<Connection, ClientError> {
+        let rows = self.execute(&format!(
+            "add connection from {}($id=\"{}\") to {}($id=\"{}\") with weight {weight}",
+            F::NAME,
+            Self::escape(from),
+            T::NAME,
+            Self::escape(to)
+        ))?;
+
+        rows.first()
+            .ok_or(ClientError::Response("connection is missing".to_string()))
+            .and_then(row::from_row)
+    }
+
+    fn single<T: Definition>(rows: Vec<Row>) -> Result<Stored<T>, ClientError> {
+        rows.first()
+            .ok_or(ClientError::Response("node is missing".to_string()))
+            .and_then(Self::stored)
+    }
+
+    fn stored<T: Definition>(row: &Row) -> Result<Stored<T>, ClientError> {
+        let id = row
+            .get(Self::ID_ATTRIBUTE)
+            .ok_or(ClientError::Response("node identifier is missing".to_string()))?;
+
+        Ok(Stored {
+            id: id.clone(),
+            node: row::from_row(row)?,
+        })
+    }
+
+    fn attributes(attributes: &[(String, String)]) -> String {
+        attributes
+            .iter()
+            .map(|(key, value)| format!("{key}=\"{}\"", Self::escape(value)))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    fn escape(value: &str) -> String {
+        value.replace('\\', "\\\\").replace('"', "\\\"")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Person {
+        name: String,
+        age: u32,
+    }
+
+    impl Definition for Person {
+        const NAME: &'static str = "Person";
+    }
+
+    #[test]
+    fn should_send_typed_commands() {
+        // Given
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let responses = [
+                json!({ "id": 1, "result": [{ "$id": "1", "$name": "Person", "$edges": "0", "name": "Jo \"Jo\"", "age": "42" }] }),
+                json!({ "id": 2, "error": { "code": "NODE_NOT_FOUND", "message": "Node not found." } }),
+            ];
+
+            let mut commands = vec![];
+            for response in responses {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                commands.push(serde_json::from_str::<Value>(&line).unwrap()["command"].as_str().unwrap().to_string());
+                writeln!(writer, "{response}").unwrap();
+            }
+            commands
+        });
+        let mut client = Client::connect(address).unwrap();
+
+        // When
+        let added = client
+            .add_node(&Person {
+                name: "Jo \"Jo\"".to_string(),
+                age: 42,
+            })
+            .unwrap();
+        let missing = client.fetch::<Person>("2");
+
+        // Then
+        let commands = server.join().unwrap();
+        assert_eq!(commands[0], "add node Person(age=\"42\", name=\"Jo \\\"Jo\\\"\")");
+        assert_eq!(commands[1], "fetch node Person($id=\"2\")");
+        assert_eq!(added.id, "1");
+        assert_eq!(added.node.age, 42);
+        assert_eq!(missing.err().unwrap().code(), Some("NODE_NOT_FOUND"));
+    }
+
+    #[test]
+    fn should_time_out_when_server_does_not_respond() {
+        // Given
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = Client::connect(listener.local_addr().unwrap())
+            .unwrap()
+            .with_timeout(Duration::from_millis(100))
+            .unwrap();
+
+        // When
+        let result = client.execute("fetch agents");
+
+        // Then
+        assert!(matches!(result, Err(ClientError::Connection(_))));
+    }
+}
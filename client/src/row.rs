@@ -0,0 +1,140 @@
+use crate::error::ClientError;
+use serde::de::value::{MapDeserializer, StrDeserializer};
+use serde::de::{DeserializeOwned, Error, IntoDeserializer, Visitor};
+use serde::{forward_to_deserialize_any, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Result row, where all attribute values are strings (e.g. `{"$id":"1","name":"John","age":"42"}`).
+pub type Row = HashMap<String, String>;
+
+/// Convert result row to given type, where values are parsed to the type of each field, e.g. `"42"` to `u32`.
+/// Attributes which are not fields of the type (e.g. internal `$edges`) are ignored, unless the type denies them.
+pub fn from_row<T: DeserializeOwned>(row: &Row) -> Result<T, ClientError> {
+    let attributes = row.iter().map(|(key, value)| (key.as_str(), Attribute(value.as_str())));
+
+    T::deserialize(MapDeserializer::<_, serde::de::value::Error>::new(attributes)).map_err(|error| ClientError::Response(error.to_string()))
+}
+
+/// Convert given value (struct with simple fields) to attributes of the node, where `None` fields are skipped.
+/// Attributes are sorted by their name, so the same value is always converted to the same command.
+pub fn to_attributes<T: Serialize>(value: &T) -> Result<Vec<(String, String)>, ClientError> {
+    let Value::Object(fields) = serde_json::to_value(value).map_err(|error| ClientError::Response(error.to_string()))? else {
+        return Err(ClientError::Response("node must be serialized as object".to_string()));
+    };
+
+    let mut attributes = fields
+        .into_iter()
+        .filter(|(_, value)| !value.is_null())
+        .map(|(key, value)| match value {
+            Value::String(value) => Ok((key, value)),
+            Value::Bool(_) | Value::Number(_) => Ok((key, value.to_string())),
+            _ => Err(ClientError::Response(format!("attribute {key} must be simple value"))),
+        })
+        .collect::<Result<Vec<(String, String)>, ClientError>>()?;
+    attributes.sort();
+
+    Ok(attributes)
+}
+
+/// Attribute value, which is parsed when the field type is known.
+struct Attribute<'de>(&'de str);
+
+impl<'de> IntoDeserializer<'de, serde::de::value::Error> for Attribute<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! parse_value {
+    ($($method:ident => $visit:ident),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                visitor.$visit(self.0.parse().map_err(|_| Self::Error::custom(format!("value {} is not valid", self.0)))?)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Attribute<'de> {
+    type Error = serde::de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_borrowed_str(self.0)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, name: &'static str, variants: &'static [&'static str], visitor: V) -> Result<V::Value, Self::Error> {
+        StrDeserializer::<Self::Error>::new(self.0).deserialize_enum(name, variants, visitor)
+    }
+
+    parse_value!(
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64
+    );
+
+    forward_to_deserialize_any! {
+        i128 u128 char str string bytes byte_buf unit unit_struct newtype_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Person {
+        name: String,
+        age: u32,
+        premium: bool,
+        nickname: Option<String>,
+    }
+
+    #[test]
+    fn should_convert_row_to_type_and_back() {
+        // Given
+        let row: Row = [("$id", "1"), ("$edges", "0"), ("name", "John"), ("age", "42"), ("premium", "true")]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+
+        // When
+        let person: Person = from_row(&row).unwrap();
+        let attributes = to_attributes(&person).unwrap();
+
+        // Then
+        assert_eq!(
+            person,
+            Person {
+                name: "John".to_string(),
+                age: 42,
+                premium: true,
+                nickname: None
+            }
+        );
+        assert_eq!(
+            attributes,
+            vec![
+                ("age".to_string(), "42".to_string()),
+                ("name".to_string(), "John".to_string()),
+                ("premium".to_string(), "true".to_string())
+            ]
+        );
+        assert!(from_row::<Person>(&Row::from([("age".to_string(), "old".to_string())])).is_err());
+    }
+}
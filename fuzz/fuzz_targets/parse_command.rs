@@ -0,0 +1,52 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustc_hash::FxHashMap;
+use weighted_graph::chain::Chain;
+use weighted_graph::graph::Graph;
+use weighted_graph::query_processor::QueryProcessor;
+
+/// Definitions created before each input, so mutated commands can reach existing nodes and edges.
+const DEFINITIONS: [&str; 2] = [
+    "define node User(name,premium) with agent(premium=\"true\")",
+    "define node Song(name)",
+];
+
+fn attributes(values: &[(&str, &str)]) -> FxHashMap<String, String> {
+    values.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+}
+
+/// Graph with known node identifiers (`1:User` connected to `2:Song`), which can be used in corpus commands.
+fn setup() -> (Graph, Chain) {
+    let mut graph = Graph::default();
+    let mut chain = Chain::default();
+
+    for definition in DEFINITIONS {
+        let _ = QueryProcessor::parse_command(&mut graph, &mut chain, definition);
+    }
+
+    let _ = graph.insert_node("User".to_string(), attributes(&[("$id", "1"), ("name", "John"), ("premium", "true")]));
+    let _ = graph.insert_node("Song".to_string(), attributes(&[("$id", "2"), ("name", "Song")]));
+    let _ = graph.add_edge(
+        ("User".to_string(), attributes(&[("$id", "1")])),
+        ("Song".to_string(), attributes(&[("$id", "2")])),
+        50,
+    );
+
+    (graph, chain)
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(command) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    // Import and export commands access files by arbitrary paths, so they are not fuzzed
+    let lowercase = command.to_ascii_lowercase();
+    if lowercase.contains("import") || lowercase.contains("export") {
+        return;
+    }
+
+    let (mut graph, mut chain) = setup();
+    let _ = QueryProcessor::parse_command(&mut graph, &mut chain, command);
+});
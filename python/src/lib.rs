@@ -0,0 +1,127 @@
+//! Python module of the embedded database, built with `maturin develop` (or `maturin build --release`):
+//!
+//! ```python
+//! import bwgd
+//!
+//! database = bwgd.Database()
+//! database.execute('define node User(name)')
+//! john = database.add_node("User", name="John")
+//! edges = database.edges()  # e.g. networkx.DiGraph((f, t, {"weight": w}) for f, t, w in edges)
+//! ```
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::collections::HashMap;
+use weighted_graph::database;
+
+create_exception!(
+    bwgd,
+    DatabaseError,
+    PyException,
+    "Command was rejected by the database, arguments are error code (e.g. `NODE_NOT_FOUND`) and message."
+);
+
+type Row = HashMap<String, String>;
+
+/// Embedded database, whose graph and chain are kept in memory of the Python process.
+#[pyclass(frozen)]
+struct Database {
+    database: database::Database,
+}
+
+#[pymethods]
+impl Database {
+    #[new]
+    fn new() -> Database {
+        Database {
+            database: database::Database::default(),
+        }
+    }
+
+    /// Execute any command and return its result rows. GIL is released while command is executed, so other Python
+    /// threads can run (read-only commands of different threads are executed in parallel).
+    fn execute(&self, py: Python<'_>, command: &str) -> PyResult<Vec<Row>> {
+        match py.allow_threads(|| self.database.execute(command)) {
+            Err(error) => Err(DatabaseError::new_err(("PARSE_ERROR", error.to_string()))),
+            Ok(Err(error)) => Err(DatabaseError::new_err((error.code(), error.to_string()))),
+            Ok(Ok(rows)) => Ok(rows.into_iter().map(|row| row.into_iter().collect()).collect()),
+        }
+    }
+
+    /// Load nodes and connections exported with `export data` command.
+    fn load(&self, py: Python<'_>, path: &str) -> PyResult<Vec<Row>> {
+        self.execute(py, &format!("import data \"{}\"", escape(path)))
+    }
+
+    /// Add node with given attributes, where values are converted to strings (e.g. `age=42`).
+    #[pyo3(signature = (name, **attributes))]
+    fn add_node(&self, py: Python<'_>, name: &str, attributes: Option<&Bound<'_, PyDict>>) -> PyResult<Row> {
+        let attributes = match attributes {
+            None => String::new(),
+            Some(attributes) => attributes
+                .iter()
+                .map(|(key, value)| Ok(format!("{key}=\"{}\"", escape(&value.str()?.to_cow()?))))
+                .collect::<PyResult<Vec<String>>>()?
+                .join(", "),
+        };
+
+        single(self.execute(py, &format!("add node {name}({attributes})"))?)
+    }
+
+    fn fetch_node(&self, py: Python<'_>, name: &str, id: &str) -> PyResult<Row> {
+        single(self.execute(py, &format!("fetch node {name}($id=\"{}\")", escape(id)))?)
+    }
+
+    fn fetch_nodes(&self, py: Python<'_>, name: &str) -> PyResult<Vec<Row>> {
+        self.execute(py, &format!("fetch node {name}()"))
+    }
+
+    fn add_connection(&self, py: Python<'_>, from: (&str, &str), to: (&str, &str), weight: i8) -> PyResult<Row> {
+        let command = format!(
+            "add connection from {}($id=\"{}\") to {}($id=\"{}\") with weight {weight}",
+            from.0,
+            escape(from.1),
+            to.0,
+            escape(to.1)
+        );
+
+        single(self.execute(py, &command)?)
+    }
+
+    /// Connections between nodes which are not soft deleted, as `(from, to, weight)` tuples where nodes are given by
+    /// their keys (e.g. `1:User`), so they can be loaded into graph libraries without parsing result rows.
+    fn edges(&self, py: Python<'_>) -> Vec<(String, String, i8)> {
+        py.allow_threads(|| {
+            let graph = self.database.graph.read().unwrap();
+
+            graph
+                .nodes
+                .iter()
+                .filter(|(_, node)| !node.is_deleted())
+                .flat_map(|(key, node)| node.edges.iter().map(move |edge| (key, edge)))
+                .filter(|(_, edge)| graph.get_visible(&edge.to_node, &edge.to_node_id).is_ok())
+                .map(|(key, edge)| (key.clone(), format!("{}:{}", edge.to_node_id, edge.to_node), edge.weight))
+                .collect()
+        })
+    }
+}
+
+fn single(rows: Vec<Row>) -> PyResult<Row> {
+    rows.into_iter()
+        .next()
+        .ok_or_else(|| DatabaseError::new_err(("NODE_NOT_FOUND", "Command didn't return any row.")))
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[pymodule]
+fn bwgd(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Database>()?;
+    module.add("DatabaseError", module.py().get_type_bound::<DatabaseError>())?;
+
+    Ok(())
+}
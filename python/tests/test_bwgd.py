@@ -0,0 +1,36 @@
+import bwgd
+import pytest
+
+
+def test_should_add_and_fetch_nodes():
+    # Given
+    database = bwgd.Database()
+    database.execute('define node User(name, age)')
+
+    # When
+    john = database.add_node("User", name="John", age=42)
+    fetched = database.fetch_node("User", john["$id"])
+    with pytest.raises(bwgd.DatabaseError) as missing:
+        database.fetch_node("User", "unknown")
+
+    # Then
+    assert fetched["name"] == "John"
+    assert fetched["age"] == "42"
+    assert missing.value.args[0] == "NODE_NOT_FOUND"
+
+
+def test_should_return_edges_between_visible_nodes():
+    # Given
+    database = bwgd.Database()
+    database.execute('define node User(name)')
+    for identifier in ["1", "2", "3"]:
+        database.execute(f'add node User($id="{identifier}")')
+    database.add_connection(("User", "1"), ("User", "2"), 5)
+    database.add_connection(("User", "1"), ("User", "3"), 7)
+
+    # When
+    database.execute('delete node User($id="3") soft')
+    edges = database.edges()
+
+    # Then
+    assert edges == [("1:User", "2:User", 5)]
@@ -0,0 +1,316 @@
+use crate::chain::error::ChainError;
+use crate::chain::scheme::SignatureAlgorithm;
+use crate::chain::signer::Signer;
+use crate::graph::error::DatabaseError;
+use crate::graph::expiry::Expiry;
+use crate::graph::GraphResults;
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs;
+
+/// Role of query client - readers can only execute read-only commands, writers can also change nodes and edges, while
+/// admins can define nodes, export files on the server and execute agent, wallet and peer commands.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Reader,
+    Writer,
+    Admin,
+}
+
+impl Display for Role {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::Reader => write!(formatter, "reader"),
+            Role::Writer => write!(formatter, "writer"),
+            Role::Admin => write!(formatter, "admin"),
+        }
+    }
+}
+
+impl Role {
+    pub fn from_name(name: &str) -> Option<Role> {
+        match name.to_lowercase().as_str() {
+            "reader" => Some(Role::Reader),
+            "writer" => Some(Role::Writer),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+
+    /// First keyword of the command (after comments).
+    fn keyword(command: &str) -> String {
+        command
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with("--") && !line.starts_with("//"))
+            .and_then(|line| line.split_whitespace().next())
+            .unwrap_or_default()
+            .to_lowercase()
+    }
+
+    /// Role required by the command, recognized by its first keyword.
+    pub fn required(command: &str) -> Role {
+        match Self::keyword(command).as_str() {
+            "create" | "define" | "delegate" | "drop" | "export" | "rotate" | "wallet" | "peer" => Role::Admin,
+            "add" | "update" | "replace" | "upsert" | "increment" | "decrement" | "delete" | "restore" | "import" => Role::Writer,
+            _ => Role::Reader,
+        }
+    }
+
+    /// Wallet commands (which expose or replace key of the node) are executed only on local console, regardless of role
+    /// of remote client.
+    pub fn is_console_only(command: &str) -> bool {
+        matches!(Self::keyword(command).as_str(), "wallet" | "rotate")
+    }
+
+    /// Check if client with given role (or unauthenticated client) can execute the command.
+    pub fn authorize(role: Option<Role>, command: &str) -> Result<(), DatabaseError> {
+        let required = Self::required(command);
+
+        match role {
+            None => Err(DatabaseError::PermissionDenied("client is not authenticated".to_string())),
+            Some(role) if role < required => Err(DatabaseError::PermissionDenied(format!("command requires {required} role"))),
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+/// Token of query client, signed by wallet of the node (created by `create token for role <role> expires <duration>`),
+/// so client can authenticate without being listed in access file. Token contains role and expiry time, followed by
+/// signature of both (e.g. `writer.1767225600.<signature>`), and it's valid until it expires or wallet key is rotated.
+pub struct ClientToken {
+    pub role: Role,
+    /// Seconds since Unix epoch
+    pub expires_at: u64,
+}
+
+impl ClientToken {
+    /// Prefix of signed message, so token can't be used as signature of anything else (e.g. network token).
+    const MESSAGE_PREFIX: &'static str = "weighted-graph/client-token/";
+
+    pub fn issue(&self, signer: &mut dyn Signer) -> Result<String, ChainError> {
+        let signature = signer.sign(&self.message())?;
+
+        Ok(format!("{}.{}.{signature}", self.role, self.expires_at))
+    }
+
+    /// Verify token signed by wallet with given public key, and return its role if it hasn't expired.
+    pub fn verify(token: &str, authority: &str) -> Result<ClientToken, DatabaseError> {
+        let invalid = || DatabaseError::PermissionDenied("token is not valid".to_string());
+
+        let mut parts = token.splitn(3, '.');
+        let (Some(role), Some(expires_at), Some(signature)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(invalid());
+        };
+        let token = ClientToken {
+            role: Role::from_name(role).ok_or_else(invalid)?,
+            expires_at: expires_at.parse().map_err(|_| invalid())?,
+        };
+
+        SignatureAlgorithm::verify(authority, token.message().as_bytes(), signature).map_err(|_| invalid())?;
+        if token.expires_at < Expiry::now() {
+            return Err(DatabaseError::PermissionDenied("token has expired".to_string()));
+        }
+
+        Ok(token)
+    }
+
+    fn message(&self) -> String {
+        format!("{}{}/{}", Self::MESSAGE_PREFIX, self.role, self.expires_at)
+    }
+}
+
+#[derive(Deserialize)]
+struct Client {
+    name: String,
+    token: String,
+    role: Role,
+}
+
+/// Roles of query clients, loaded from access file given by `--access-file="access.json"` argument. Clients of query
+/// server have default role (or no role, if it's not given) until they authenticate with `authenticate <token>`
+/// command, where token is either token of the client in access file or token signed by the node (`ClientToken`),
+/// while local console has console role (admin by default). Without access file all clients are readers, unless
+/// `--require-token` argument is given, in which case clients have no role until they authenticate.
+#[derive(Deserialize)]
+pub struct AccessControl {
+    #[serde(default)]
+    default_role: Option<Role>,
+    #[serde(default = "AccessControl::admin")]
+    console_role: Role,
+    #[serde(default)]
+    clients: Vec<Client>,
+}
+
+impl AccessControl {
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<Option<AccessControl>, DatabaseError> {
+        let access = match arguments.get("access-file").and_then(|values| values.first()) {
+            None => None,
+            Some(path) => Some(Self::load(path)?),
+        };
+
+        if !arguments.contains_key("require-token") {
+            return Ok(access);
+        }
+
+        let access = access.unwrap_or(AccessControl {
+            default_role: None,
+            console_role: Role::Admin,
+            clients: vec![],
+        });
+        Ok(Some(AccessControl {
+            default_role: None,
+            ..access
+        }))
+    }
+
+    pub fn load(path: &str) -> Result<AccessControl, DatabaseError> {
+        let content = fs::read_to_string(path).map_err(|error| DatabaseError::FileError(path.to_string(), error.to_string()))?;
+
+        serde_json::from_str(&content).map_err(|error| DatabaseError::FileError(path.to_string(), error.to_string()))
+    }
+
+    fn admin() -> Role {
+        Role::Admin
+    }
+
+    pub fn default_role(access: Option<&AccessControl>) -> Option<Role> {
+        access.map_or(Some(Role::Reader), |access| access.default_role)
+    }
+
+    pub fn console_role(access: Option<&AccessControl>) -> Role {
+        access.map_or(Role::Admin, |access| access.console_role)
+    }
+
+    /// Authenticate client with `authenticate <token>` command, or return `None` if command is not authentication.
+    /// Token which isn't in access file is verified as token signed by wallet with public key given by authority.
+    pub fn authenticate(&self, command: &str, authority: impl FnOnce() -> String) -> Option<Result<(Role, GraphResults), DatabaseError>> {
+        let mut words = command.trim().trim_end_matches(';').split_whitespace();
+        if !words.next()?.eq_ignore_ascii_case("authenticate") {
+            return None;
+        }
+
+        let token = words.next().unwrap_or_default().trim_matches('"');
+
+        Some(self.verify(token, authority).map(|(role, result)| (role, Ok(vec![result]))))
+    }
+
+    /// Role of client with given token, with name of the client (and expiry time of signed token). Token which isn't in
+    /// access file is verified as token signed by wallet with public key given by authority.
+    pub fn verify(&self, token: &str, authority: impl FnOnce() -> String) -> Result<(Role, FxHashMap<String, String>), DatabaseError> {
+        let mut result = FxHashMap::default();
+        let role = match self.clients.iter().find(|client| client.token == token) {
+            Some(client) => {
+                result.insert("client".to_string(), client.name.clone());
+                client.role
+            }
+            None => {
+                let token = ClientToken::verify(token, &authority())?;
+                result.insert("client".to_string(), "token".to_string());
+                result.insert("expires_at".to_string(), token.expires_at.to_string());
+                token.role
+            }
+        };
+        result.insert("role".to_string(), role.to_string());
+
+        Ok((role, result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::wallet::Wallet;
+
+    #[test]
+    fn should_require_role_by_command() {
+        // Given
+        let commands = [
+            ("fetch nodes", Role::Reader),
+            ("MATCH (user:User) RETURN user", Role::Reader),
+            ("-- comment\nadd node User(name=\"John\")", Role::Writer),
+            ("DELETE node User($id=\"1\")", Role::Writer),
+            ("upsert node User(name=\"John\") on (name)", Role::Writer),
+            ("decrement connection from User($id=\"1\") to User($id=\"2\") by 5", Role::Writer),
+            ("define node User(name)", Role::Admin),
+            ("wallet export mnemonic", Role::Admin),
+            ("create database staging", Role::Admin),
+            ("drop trigger 0", Role::Admin),
+            ("export data \"database.ndjson\"", Role::Admin),
+            ("use staging", Role::Reader),
+        ];
+
+        // When
+        let roles: Vec<Role> = commands.iter().map(|(command, _)| Role::required(command)).collect();
+
+        // Then
+        for ((_, expected), role) in commands.iter().zip(roles) {
+            assert_eq!(role, *expected);
+        }
+        assert!(Role::authorize(Some(Role::Reader), "add node User(name=\"John\")").is_err());
+        assert!(Role::authorize(Some(Role::Writer), "add node User(name=\"John\")").is_ok());
+        assert!(Role::authorize(None, "fetch nodes").is_err());
+        assert!(Role::is_console_only("wallet export mnemonic"));
+        assert!(Role::is_console_only("-- comment\nROTATE key"));
+        assert!(!Role::is_console_only("fetch nodes"));
+    }
+
+    #[test]
+    fn should_authenticate_client_by_token() {
+        // Given
+        let access: AccessControl =
+            serde_json::from_str(r#"{"default_role":"reader","clients":[{"name":"ingest","token":"secret","role":"writer"}]}"#).unwrap();
+
+        // When
+        let (role, result) = access.authenticate("authenticate \"secret\";", String::new).unwrap().unwrap();
+
+        // Then
+        assert_eq!(role, Role::Writer);
+        assert_eq!(result.unwrap()[0]["client"], "ingest");
+        assert!(access.authenticate("authenticate wrong", String::new).unwrap().is_err());
+        assert!(access.authenticate("fetch nodes", String::new).is_none());
+        assert_eq!(AccessControl::default_role(Some(&access)), Some(Role::Reader));
+        assert_eq!(AccessControl::console_role(Some(&access)), Role::Admin);
+        assert_eq!(AccessControl::default_role(None), Some(Role::Reader));
+    }
+
+    #[test]
+    fn should_authenticate_client_by_signed_token() {
+        // Given
+        let mut wallet = Wallet::default();
+        let authority = wallet.get_public_key();
+        let access = AccessControl::from_arguments(&HashMap::from([("require-token".to_string(), vec![])]))
+            .unwrap()
+            .unwrap();
+        let token = ClientToken {
+            role: Role::Writer,
+            expires_at: Expiry::now() + 60,
+        }
+        .issue(&mut wallet)
+        .unwrap();
+        let expired = ClientToken {
+            role: Role::Writer,
+            expires_at: Expiry::now() - 60,
+        }
+        .issue(&mut wallet)
+        .unwrap();
+
+        // When
+        let (role, result) = access
+            .authenticate(&format!("authenticate {token}"), || authority.clone())
+            .unwrap()
+            .unwrap();
+
+        // Then
+        assert_eq!(role, Role::Writer);
+        assert_eq!(result.unwrap()[0]["client"], "token");
+        assert_eq!(AccessControl::default_role(Some(&access)), None);
+        assert!(ClientToken::verify(&expired, &authority).err().unwrap().to_string().contains("expired"));
+        assert!(ClientToken::verify(&token.replacen("writer", "admin", 1), &authority).is_err());
+        assert!(ClientToken::verify(&token, &Wallet::default().get_public_key()).is_err());
+        assert!(ClientToken::verify("writer", &authority).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::error::DatabaseError;
+use crate::graph::Graph;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use rustc_hash::FxHashMap;
+use std::collections::HashMap;
+
+pub mod centrality;
+pub mod community;
+pub mod order;
+pub mod tree;
+
+/// Thread pool used by analytics algorithms.
+pub struct ThreadPool;
+
+impl ThreadPool {
+    /// Configure number of threads with `-analytics_threads="4"` argument, by default one thread per CPU core is used.
+    pub fn init(arguments: &HashMap<String, Vec<String>>) -> Result<(), DatabaseError> {
+        let Some(value) = arguments.get("analytics_threads").and_then(|values| values.first()) else {
+            return Ok(());
+        };
+
+        let threads = value
+            .parse()
+            .ok()
+            .filter(|threads| *threads > 0)
+            .ok_or(DatabaseError::InvalidAnalyticsConfiguration(format!("analytics_threads={value}")))?;
+
+        ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(|error| DatabaseError::InvalidAnalyticsConfiguration(error.to_string()))
+    }
+}
+
+/// Weighted view of the graph used by analytics algorithms.
+///
+/// Nodes are addressed by index (sorted by node key so results are deterministic) and every edge
+/// is stored as (from, to, weight) pair. Soft deleted nodes are not included, and edges pointing to
+/// them or to nodes which are no longer present in the graph are skipped.
+pub struct Projection {
+    pub keys: Vec<String>,
+    pub edges: Vec<(usize, usize, f64)>,
+}
+
+impl Projection {
+    pub fn new(graph: &Graph) -> Projection {
+        Self::from_keys(graph, graph.nodes.keys().cloned().collect())
+    }
+
+    /// Projection of nodes with given definition name only (edges to other nodes are skipped), so other shards of
+    /// the graph are not iterated.
+    pub fn of_shard(graph: &Graph, name: &str) -> Projection {
+        Self::from_keys(
+            graph,
+            graph.nodes.shard(name).map(|shard| shard.keys().cloned().collect()).unwrap_or_default(),
+        )
+    }
+
+    fn from_keys(graph: &Graph, mut keys: Vec<String>) -> Projection {
+        // Soft deleted nodes are hidden until restored, so they and their edges are not part of the projection
+        keys.retain(|key| !graph.nodes[key].is_deleted());
+        keys.par_sort();
+
+        let indices: FxHashMap<&String, usize> = keys.iter().enumerate().map(|(index, key)| (key, index)).collect();
+        let indices = &indices;
+
+        let edges = keys
+            .par_iter()
+            .enumerate()
+            .flat_map_iter(|(from, key)| {
+                graph.nodes[key].edges.iter().filter_map(move |edge| {
+                    indices
+                        .get(&format!("{}:{}", edge.to_node_id, edge.to_node))
+                        .map(|to| (from, *to, edge.weight as f64))
+                })
+            })
+            .collect();
+
+        Projection { keys, edges }
+    }
+
+    /// Undirected adjacency list where weights of edges in both directions are summed.
+    /// Only edges with positive weight are taken into account, as negative weight means that
+    /// nodes are repelling each other.
+    pub fn undirected(&self) -> Vec<Vec<(usize, f64)>> {
+        let mut adjacency: Vec<FxHashMap<usize, f64>> = vec![FxHashMap::default(); self.keys.len()];
+
+        for (from, to, weight) in self.edges.iter().filter(|(_, _, weight)| *weight > 0.0) {
+            *adjacency[*from].entry(*to).or_default() += weight;
+
+            if from != to {
+                *adjacency[*to].entry(*from).or_default() += weight;
+            }
+        }
+
+        adjacency
+            .into_iter()
+            .map(|neighbours| {
+                let mut neighbours: Vec<(usize, f64)> = neighbours.into_iter().collect();
+                neighbours.sort_by_key(|(index, _)| *index);
+                neighbours
+            })
+            .collect()
+    }
+
+    /// Create result row for the node with given index, containing node identifier, name and given value.
+    pub fn as_result(&self, graph: &Graph, index: usize, attribute: &str, value: String) -> FxHashMap<String, String> {
+        let node = &graph.nodes[&self.keys[index]];
+        let mut result = FxHashMap::default();
+
+        for internal in [InternalNodeAttribute::ID_ATTRIBUTE, InternalNodeAttribute::NAME_ATTRIBUTE] {
+            if let Some(value) = node.attributes.get(internal) {
+                result.insert(internal.to_string(), value.clone());
+            }
+        }
+        result.insert(attribute.to_string(), value);
+
+        result
+    }
+}
@@ -0,0 +1,154 @@
+use crate::analytics::Projection;
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::{Graph, GraphResults};
+use rayon::prelude::*;
+use std::collections::VecDeque;
+
+pub enum CentralityType {
+    Degree,
+    WeightedDegree,
+    Betweenness,
+}
+
+pub struct Centrality;
+
+impl Centrality {
+    /// Compute centrality
+    ///
+    /// Compute centrality of the given type for each node in the graph:
+    ///  - degree: number of incoming and outgoing edges
+    ///  - weighted degree: sum of weights of incoming and outgoing edges
+    ///  - betweenness: number of shortest paths (by number of hops) passing through node (Brandes algorithm)
+    ///
+    /// If node name is given, only nodes with that name (and edges between them) are taken into account.
+    pub fn compute(graph: &Graph, centrality_type: CentralityType, name: Option<&str>) -> GraphResults {
+        let projection = name.map_or_else(|| Projection::new(graph), |name| Projection::of_shard(graph, name));
+
+        let values = match centrality_type {
+            CentralityType::Degree => Self::degree(&projection, false),
+            CentralityType::WeightedDegree => Self::degree(&projection, true),
+            CentralityType::Betweenness => Self::betweenness(&projection),
+        };
+
+        Ok(values
+            .into_par_iter()
+            .enumerate()
+            .map(|(index, value)| projection.as_result(graph, index, InternalNodeAttribute::CENTRALITY_ATTRIBUTE, value.to_string()))
+            .collect())
+    }
+
+    fn degree(projection: &Projection, weighted: bool) -> Vec<f64> {
+        let mut degrees = vec![0.0; projection.keys.len()];
+
+        for (from, to, weight) in &projection.edges {
+            let value = if weighted { *weight } else { 1.0 };
+
+            degrees[*from] += value;
+            degrees[*to] += value;
+        }
+
+        degrees
+    }
+
+    fn betweenness(projection: &Projection) -> Vec<f64> {
+        let count = projection.keys.len();
+        let mut successors = vec![vec![]; count];
+
+        for (from, to, _) in &projection.edges {
+            if from != to {
+                successors[*from].push(*to);
+            }
+        }
+
+        // Paths from each source are independent, so they are counted in parallel and summed at the end
+        (0..count)
+            .into_par_iter()
+            .fold(
+                || vec![0.0; count],
+                |mut centrality, source| {
+                    let mut stack = vec![];
+                    let mut predecessors: Vec<Vec<usize>> = vec![vec![]; count];
+                    let mut paths = vec![0.0; count];
+                    let mut distances: Vec<Option<usize>> = vec![None; count];
+                    let mut queue = VecDeque::from([source]);
+
+                    paths[source] = 1.0;
+                    distances[source] = Some(0);
+
+                    while let Some(node) = queue.pop_front() {
+                        stack.push(node);
+
+                        for &next in &successors[node] {
+                            let distance = distances[node].unwrap() + 1;
+
+                            if distances[next].is_none() {
+                                distances[next] = Some(distance);
+                                queue.push_back(next);
+                            }
+
+                            if distances[next] == Some(distance) {
+                                paths[next] += paths[node];
+                                predecessors[next].push(node);
+                            }
+                        }
+                    }
+
+                    // Accumulate dependencies in order of non-increasing distance from source
+                    let mut dependencies = vec![0.0; count];
+                    while let Some(node) = stack.pop() {
+                        for &previous in &predecessors[node] {
+                            dependencies[previous] += paths[previous] / paths[node] * (1.0 + dependencies[node]);
+                        }
+
+                        if node != source {
+                            centrality[node] += dependencies[node];
+                        }
+                    }
+
+                    centrality
+                },
+            )
+            .reduce(
+                || vec![0.0; count],
+                |first, second| first.iter().zip(second).map(|(first, second)| first + second).collect(),
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_compute_degree() {
+        // Given
+        let projection = path_projection();
+
+        // When
+        let degrees = Centrality::degree(&projection, false);
+        let weighted_degrees = Centrality::degree(&projection, true);
+
+        // Then
+        assert_eq!(degrees, vec![1.0, 2.0, 1.0]);
+        assert_eq!(weighted_degrees, vec![10.0, 30.0, 20.0]);
+    }
+
+    #[test]
+    fn should_compute_betweenness() {
+        // Given
+        let projection = path_projection();
+
+        // When
+        let centrality = Centrality::betweenness(&projection);
+
+        // Then
+        assert_eq!(centrality, vec![0.0, 1.0, 0.0]);
+    }
+
+    fn path_projection() -> Projection {
+        Projection {
+            keys: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            edges: vec![(0, 1, 10.0), (1, 2, 20.0)],
+        }
+    }
+}
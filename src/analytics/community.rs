@@ -0,0 +1,182 @@
+use crate::analytics::Projection;
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::{Graph, GraphResults};
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+
+pub struct Community;
+
+impl Community {
+    /// Minimal modularity gain for node to be moved to another community (prevents endless loops on float errors)
+    const MIN_GAIN: f64 = 1e-9;
+
+    /// Detect communities
+    ///
+    /// Communities are detected with Louvain method over undirected view of the graph where weights of
+    /// edges in both directions are summed. Edges with zero or negative weight are ignored.
+    /// Each node is returned with its identifier, name and community id (numbered from 0). If node name is given,
+    /// only nodes with that name are taken into account.
+    pub fn detect(graph: &Graph, name: Option<&str>) -> GraphResults {
+        let projection = name.map_or_else(|| Projection::new(graph), |name| Projection::of_shard(graph, name));
+        let communities = Self::louvain(projection.undirected());
+
+        Ok((0..projection.keys.len())
+            .into_par_iter()
+            .map(|index| projection.as_result(graph, index, InternalNodeAttribute::COMMUNITY_ATTRIBUTE, communities[index].to_string()))
+            .collect())
+    }
+
+    /// Run Louvain method over adjacency list and return community of each node.
+    ///
+    /// After each local moving phase, communities are aggregated into single nodes and local moving is
+    /// repeated over aggregated graph until modularity can't be improved anymore.
+    fn louvain(mut adjacency: Vec<Vec<(usize, f64)>>) -> Vec<usize> {
+        let mut membership: Vec<usize> = (0..adjacency.len()).collect();
+
+        while let Some((communities, count)) = Self::move_nodes(&adjacency) {
+            membership.iter_mut().for_each(|community| *community = communities[*community]);
+            adjacency = Self::aggregate(&adjacency, &communities, count);
+        }
+
+        membership
+    }
+
+    /// Local moving phase - every node is moved to the neighbouring community with the highest modularity gain.
+    /// Returns renumbered community of each node and number of communities, or none if nothing was moved.
+    fn move_nodes(adjacency: &[Vec<(usize, f64)>]) -> Option<(Vec<usize>, usize)> {
+        let degrees: Vec<f64> = adjacency
+            .iter()
+            .map(|neighbours| neighbours.iter().map(|(_, weight)| weight).sum())
+            .collect();
+        let total: f64 = degrees.iter().sum();
+
+        if total == 0.0 {
+            return None;
+        }
+
+        let mut communities: Vec<usize> = (0..adjacency.len()).collect();
+        let mut community_degrees = degrees.clone();
+        let mut moved = false;
+
+        loop {
+            let mut improved = false;
+
+            for node in 0..adjacency.len() {
+                let current = communities[node];
+
+                let mut neighbour_weights: FxHashMap<usize, f64> = FxHashMap::default();
+                for (neighbour, weight) in adjacency[node].iter().filter(|(neighbour, _)| *neighbour != node) {
+                    *neighbour_weights.entry(communities[*neighbour]).or_default() += weight;
+                }
+
+                community_degrees[current] -= degrees[node];
+
+                let gain = |community: usize, weight: f64| weight - community_degrees[community] * degrees[node] / total;
+                let mut best = (current, gain(current, neighbour_weights.get(&current).copied().unwrap_or_default()));
+
+                let mut candidates: Vec<(usize, f64)> = neighbour_weights.into_iter().collect();
+                candidates.sort_by_key(|(community, _)| *community);
+
+                for (community, weight) in candidates {
+                    let community_gain = gain(community, weight);
+
+                    if community_gain > best.1 + Self::MIN_GAIN {
+                        best = (community, community_gain);
+                    }
+                }
+
+                community_degrees[best.0] += degrees[node];
+
+                if best.0 != current {
+                    communities[node] = best.0;
+                    improved = true;
+                    moved = true;
+                }
+            }
+
+            if !improved {
+                break;
+            }
+        }
+
+        if !moved {
+            return None;
+        }
+
+        // Renumber communities so they are in range 0..count
+        let mut numbers: FxHashMap<usize, usize> = FxHashMap::default();
+        for community in communities.iter_mut() {
+            let next = numbers.len();
+            *community = *numbers.entry(*community).or_insert(next);
+        }
+
+        Some((communities, numbers.len()))
+    }
+
+    /// Aggregate nodes of the same community into single node. Edges inside community become self loop.
+    fn aggregate(adjacency: &[Vec<(usize, f64)>], communities: &[usize], count: usize) -> Vec<Vec<(usize, f64)>> {
+        let mut aggregated: Vec<FxHashMap<usize, f64>> = vec![FxHashMap::default(); count];
+
+        for (node, neighbours) in adjacency.iter().enumerate() {
+            for (neighbour, weight) in neighbours {
+                *aggregated[communities[node]].entry(communities[*neighbour]).or_default() += weight;
+            }
+        }
+
+        aggregated
+            .into_iter()
+            .map(|neighbours| {
+                let mut neighbours: Vec<(usize, f64)> = neighbours.into_iter().collect();
+                neighbours.sort_by_key(|(community, _)| *community);
+                neighbours
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_detect_communities() {
+        // Given
+        let mut adjacency = vec![vec![]; 6];
+        let edges = [
+            (0, 1, 90.0),
+            (1, 2, 90.0),
+            (0, 2, 90.0),
+            (3, 4, 90.0),
+            (4, 5, 90.0),
+            (3, 5, 90.0),
+            (2, 3, 10.0),
+        ];
+
+        for (from, to, weight) in edges {
+            adjacency[from].push((to, weight));
+            adjacency[to].push((from, weight));
+        }
+
+        // When
+        let communities = Community::louvain(adjacency);
+
+        // Then
+        assert_eq!(communities[0], communities[1]);
+        assert_eq!(communities[1], communities[2]);
+        assert_eq!(communities[3], communities[4]);
+        assert_eq!(communities[4], communities[5]);
+        assert_ne!(communities[0], communities[3]);
+    }
+
+    #[test]
+    fn should_keep_isolated_nodes_in_own_community() {
+        // Given
+        let adjacency = vec![vec![]; 3];
+
+        // When
+        let communities = Community::louvain(adjacency);
+
+        // Then
+        assert_eq!(communities, vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::analytics::Projection;
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::error::DatabaseError;
+use crate::graph::{Graph, GraphResults};
+use rustc_hash::FxHashMap;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+pub struct Order;
+
+impl Order {
+    /// Topological order
+    ///
+    /// Sort nodes with given name so each node comes before all nodes it is connected to, e.g. tasks of workflow before
+    /// tasks which depend on them. Only edges between nodes with given name are taken into account, regardless of
+    /// their weight. Nodes without mutual dependency are sorted by their key, so order is deterministic. Each node is
+    /// returned with its identifier, name and position in the order, or error with the cycle if nodes are not acyclic.
+    pub fn sort(graph: &Graph, name: &str) -> GraphResults {
+        if !graph.definitions.contains_key(name) {
+            return Err(DatabaseError::NodeNotDefined(name.to_string()));
+        }
+
+        let projection = Projection::of_shard(graph, name);
+        let order = Self::kahn(&projection).map_err(|cycle| {
+            let cycle: Vec<&str> = cycle
+                .into_iter()
+                .map(|index| projection.keys[index].rsplit_once(':').map_or("", |(identifier, _)| identifier))
+                .collect();
+
+            DatabaseError::CycleDetected(name.to_string(), cycle.join(" -> "))
+        })?;
+
+        Ok(order
+            .into_iter()
+            .enumerate()
+            .map(|(position, index)| projection.as_result(graph, index, InternalNodeAttribute::ORDER_ATTRIBUTE, position.to_string()))
+            .collect())
+    }
+
+    /// Kahn's algorithm, where node with the lowest index is taken first. Returns indices of nodes in topological
+    /// order, or indices of nodes forming a cycle (where the first node is repeated at the end).
+    fn kahn(projection: &Projection) -> Result<Vec<usize>, Vec<usize>> {
+        let count = projection.keys.len();
+        let mut successors = vec![vec![]; count];
+        let mut predecessors = vec![vec![]; count];
+        let mut degrees = vec![0usize; count];
+
+        for (from, to, _) in &projection.edges {
+            successors[*from].push(*to);
+            predecessors[*to].push(*from);
+            degrees[*to] += 1;
+        }
+
+        let mut ready: BinaryHeap<Reverse<usize>> = (0..count).filter(|index| degrees[*index] == 0).map(Reverse).collect();
+        let mut order = Vec::with_capacity(count);
+
+        while let Some(Reverse(node)) = ready.pop() {
+            order.push(node);
+
+            for successor in &successors[node] {
+                degrees[*successor] -= 1;
+                if degrees[*successor] == 0 {
+                    ready.push(Reverse(*successor));
+                }
+            }
+        }
+
+        match (0..count).find(|index| degrees[*index] > 0) {
+            None => Ok(order),
+            Some(node) => Err(Self::cycle(&predecessors, &degrees, node)),
+        }
+    }
+
+    /// Each node which was not sorted has a predecessor which was not sorted either, so walking over predecessors
+    /// from such node must return to already visited node.
+    fn cycle(predecessors: &[Vec<usize>], degrees: &[usize], mut node: usize) -> Vec<usize> {
+        let mut path = vec![];
+        let mut positions: FxHashMap<usize, usize> = FxHashMap::default();
+
+        while !positions.contains_key(&node) {
+            positions.insert(node, path.len());
+            path.push(node);
+            node = *predecessors[node].iter().filter(|predecessor| degrees[**predecessor] > 0).min().unwrap();
+        }
+
+        // Cycle starts with its lowest node, so the same cycle is always reported in the same way
+        let mut cycle = path.split_off(positions[&node]);
+        cycle.reverse();
+        let lowest = (0..cycle.len()).min_by_key(|position| cycle[*position]).unwrap();
+        cycle.rotate_left(lowest);
+        cycle.push(cycle[0]);
+
+        cycle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_sort_acyclic_nodes() {
+        // Given
+        let projection = projection(vec![(2, 0, 1.0), (0, 1, 1.0), (2, 3, -5.0), (3, 1, 1.0)]);
+
+        // When
+        let order = Order::kahn(&projection);
+
+        // Then
+        assert_eq!(order, Ok(vec![2, 0, 3, 1]));
+    }
+
+    #[test]
+    fn should_return_cycle() {
+        // Given
+        let projection = projection(vec![(0, 1, 1.0), (1, 2, 1.0), (2, 3, 1.0), (3, 1, 1.0)]);
+
+        // When
+        let order = Order::kahn(&projection);
+
+        // Then
+        assert_eq!(order, Err(vec![1, 2, 3, 1]));
+    }
+
+    fn projection(edges: Vec<(usize, usize, f64)>) -> Projection {
+        Projection {
+            keys: vec!["a".to_string(), "b".to_string(), "c".to_string(), "d".to_string()],
+            edges,
+        }
+    }
+}
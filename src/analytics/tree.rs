@@ -0,0 +1,109 @@
+use crate::analytics::Projection;
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::{Graph, GraphResults};
+use rustc_hash::FxHashMap;
+
+pub struct SpanningTree;
+
+impl SpanningTree {
+    /// Compute minimum spanning tree
+    ///
+    /// Spanning tree is computed with Kruskal's algorithm over undirected view of the graph, where connection in either
+    /// direction connects both nodes and self connections are skipped. If graph is not connected, spanning tree of each
+    /// connected part is returned (spanning forest). Each connection of the tree is returned with its nodes and weight.
+    /// If maximum is set, tree with maximum total weight is computed instead. If node name is given, only nodes with
+    /// that name (and edges between them) are taken into account.
+    pub fn compute(graph: &Graph, maximum: bool, name: Option<&str>) -> GraphResults {
+        let projection = name.map_or_else(|| Projection::new(graph), |name| Projection::of_shard(graph, name));
+
+        Ok(Self::kruskal(&projection, maximum)
+            .into_iter()
+            .map(|edge| Self::as_result(&projection, edge))
+            .collect())
+    }
+
+    /// Indices of edges in the tree, sorted as they were added to the tree. Edges with the same weight are taken by
+    /// their index, so the tree is deterministic.
+    fn kruskal(projection: &Projection, maximum: bool) -> Vec<usize> {
+        let mut edges: Vec<usize> = (0..projection.edges.len())
+            .filter(|edge| projection.edges[*edge].0 != projection.edges[*edge].1)
+            .collect();
+        edges.sort_by(|first, second| {
+            let (first_weight, second_weight) = (projection.edges[*first].2, projection.edges[*second].2);
+            let ordering = if maximum {
+                second_weight.total_cmp(&first_weight)
+            } else {
+                first_weight.total_cmp(&second_weight)
+            };
+
+            ordering.then(first.cmp(second))
+        });
+
+        let mut parents: Vec<usize> = (0..projection.keys.len()).collect();
+        let mut tree = Vec::with_capacity(projection.keys.len().saturating_sub(1));
+
+        for edge in edges {
+            let (from, to, _) = projection.edges[edge];
+            let (from, to) = (Self::find(&mut parents, from), Self::find(&mut parents, to));
+
+            if from != to {
+                parents[from] = to;
+                tree.push(edge);
+            }
+        }
+
+        tree
+    }
+
+    /// Root of the set containing given node, where path to the root is compressed.
+    fn find(parents: &mut [usize], node: usize) -> usize {
+        let mut root = node;
+        while parents[root] != root {
+            root = parents[root];
+        }
+
+        let mut node = node;
+        while parents[node] != root {
+            (node, parents[node]) = (parents[node], root);
+        }
+
+        root
+    }
+
+    fn as_result(projection: &Projection, edge: usize) -> FxHashMap<String, String> {
+        let (from, to, weight) = projection.edges[edge];
+        let split = |index: usize| projection.keys[index].rsplit_once(':').unwrap_or_default();
+        let ((from_id, from_name), (to_id, to_name)) = (split(from), split(to));
+
+        let mut result = FxHashMap::default();
+        result.insert(InternalNodeAttribute::FROM_ATTRIBUTE.to_string(), from_name.to_string());
+        result.insert(InternalNodeAttribute::FROM_ID_ATTRIBUTE.to_string(), from_id.to_string());
+        result.insert(InternalNodeAttribute::TO_ATTRIBUTE.to_string(), to_name.to_string());
+        result.insert(InternalNodeAttribute::TO_ID_ATTRIBUTE.to_string(), to_id.to_string());
+        result.insert(InternalNodeAttribute::WEIGHT_ATTRIBUTE.to_string(), weight.to_string());
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_compute_minimum_and_maximum_spanning_tree() {
+        // Given
+        let projection = Projection {
+            keys: vec!["a".to_string(), "b".to_string(), "c".to_string(), "d".to_string()],
+            edges: vec![(0, 1, 10.0), (1, 2, 20.0), (2, 0, 5.0), (0, 0, -50.0), (2, 1, -10.0)],
+        };
+
+        // When
+        let minimum = SpanningTree::kruskal(&projection, false);
+        let maximum = SpanningTree::kruskal(&projection, true);
+
+        // Then
+        assert_eq!(minimum, vec![4, 2]);
+        assert_eq!(maximum, vec![1, 0]);
+    }
+}
@@ -0,0 +1,348 @@
+extern crate peg;
+
+use crate::access::Role;
+use crate::chain::block::AuditData;
+use crate::chain::encryption::{LineFile, StorageEncryption};
+use crate::chain::Chain;
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::error::DatabaseError;
+use crate::graph::GraphResults;
+use crate::parser::command_parser;
+use regex::Regex;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{LazyLock, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static IDENTIFIER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"\$id\s*=\s*"((\\.|[^"\\])*)""#).unwrap());
+
+enum AuditCommand {
+    Fetch,
+    Verify,
+}
+
+command_parser! {
+    grammar audit_parser() for str {
+        pub rule command() -> AuditCommand = command:(fetch_audit() / verify_audit()) _ ";"? _ { command }
+
+        rule fetch_audit() -> AuditCommand = _ keyword("fetch") _ keyword("audit") { AuditCommand::Fetch }
+
+        rule verify_audit() -> AuditCommand = _ keyword("verify") _ keyword("audit") { AuditCommand::Verify }
+    }
+}
+
+/// Successful mutating command, linked to the previous entry by its hash.
+#[derive(Serialize, Deserialize, Clone)]
+struct AuditEntry {
+    sequence: u64,
+    /// Milliseconds since Unix epoch
+    timestamp: u128,
+    /// Where the command came from, e.g. `console` or `query 127.0.0.1:52000`
+    source: String,
+    role: String,
+    command: String,
+    /// Identifiers of nodes changed by the command
+    ids: Vec<String>,
+    previous_hash: String,
+    hash: String,
+}
+
+impl AuditEntry {
+    fn digest(&self) -> String {
+        let content = json!([
+            self.sequence,
+            self.timestamp,
+            self.source,
+            self.role,
+            self.command,
+            self.ids,
+            self.previous_hash
+        ]);
+
+        sha256::digest(content.to_string())
+    }
+
+    fn as_result(&self) -> FxHashMap<String, String> {
+        let mut result = FxHashMap::default();
+        result.insert("sequence".to_string(), self.sequence.to_string());
+        result.insert("timestamp".to_string(), self.timestamp.to_string());
+        result.insert("source".to_string(), self.source.clone());
+        result.insert("role".to_string(), self.role.clone());
+        result.insert("command".to_string(), self.command.clone());
+        result.insert("ids".to_string(), self.ids.join(","));
+        result.insert("previous_hash".to_string(), self.previous_hash.clone());
+        result.insert("hash".to_string(), self.hash.clone());
+
+        result
+    }
+}
+
+#[derive(Default)]
+struct AuditState {
+    /// Latest entries, older entries are kept only in the audit log file
+    entries: VecDeque<AuditEntry>,
+    sequence: u64,
+    last_hash: String,
+}
+
+/// Tamper-evident log of successful mutating commands with their source, role and changed node identifiers. Each entry
+/// contains hash of the previous entry, so changed or removed entries are found by `verify audit`. Entries are appended
+/// as JSON lines to file given by `--audit-log="audit.jsonl"` argument (encrypted as archive), whose chain is verified
+/// on start. Hash of each entry of the file is also anchored in the chain, so entries removed from the end of the file,
+/// or changed with all later hashes recomputed, are found by `verify audit` after restart too. Admins can list latest
+/// entries with `fetch audit`.
+#[derive(Default)]
+pub struct AuditLog {
+    state: Mutex<AuditState>,
+    path: Option<String>,
+    // File is locked together with the state, so entries are appended in order of their sequence
+    file: Option<Mutex<LineFile>>,
+}
+
+impl AuditLog {
+    const MAX_ENTRIES: usize = 10_000;
+    pub const CONSOLE_SOURCE: &'static str = "console";
+    /// Source of commands executed directly on the database, e.g. by bootstrap or tests
+    pub const LOCAL_SOURCE: &'static str = "local";
+
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>, encryption: Option<StorageEncryption>) -> Result<AuditLog, DatabaseError> {
+        let Some(path) = arguments.get("audit-log").and_then(|values| values.first()) else {
+            return Ok(AuditLog::default());
+        };
+
+        let mut state = AuditState::default();
+        let mut file = LineFile::new(path, encryption);
+        for entry in Self::read(path, &mut file)? {
+            state.sequence = entry.sequence + 1;
+            state.last_hash = entry.hash.clone();
+            state.entries.push_back(entry);
+            if state.entries.len() > Self::MAX_ENTRIES {
+                state.entries.pop_front();
+            }
+        }
+
+        Ok(AuditLog {
+            state: Mutex::new(state),
+            path: Some(path.clone()),
+            file: Some(Mutex::new(file)),
+        })
+    }
+
+    /// Record successful mutating command with identifiers of nodes from its results and the command itself. Entries
+    /// stored to file are anchored in the given chain.
+    pub fn record(&self, chain: &mut Chain, source: &str, role: Option<Role>, command: &str, results: &[FxHashMap<String, String>]) {
+        let mut ids: Vec<String> = results
+            .iter()
+            .flat_map(|result| {
+                [
+                    InternalNodeAttribute::ID_ATTRIBUTE,
+                    InternalNodeAttribute::FROM_ID_ATTRIBUTE,
+                    InternalNodeAttribute::TO_ID_ATTRIBUTE,
+                ]
+                .into_iter()
+                .filter_map(|attribute| result.get(attribute).cloned())
+            })
+            .chain(Self::command_ids(command))
+            .collect();
+        ids.sort();
+        ids.dedup();
+
+        let mut state = self.state.lock().unwrap();
+        let mut entry = AuditEntry {
+            sequence: state.sequence,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis(),
+            source: source.to_string(),
+            role: role.map(|role| role.to_string()).unwrap_or_default(),
+            command: command.trim().to_string(),
+            ids,
+            previous_hash: state.last_hash.clone(),
+            hash: String::new(),
+        };
+        entry.hash = entry.digest();
+
+        if let (Some(path), Some(file)) = (&self.path, &self.file) {
+            let line = serde_json::to_string(&entry).unwrap();
+            if let Err(error) = file.lock().unwrap().append(&[line]) {
+                eprintln!("{}", DatabaseError::FileError(path.clone(), error));
+            }
+            if let Err(error) = chain.add_audit_anchor(AuditData::new(entry.sequence, entry.hash.clone())) {
+                eprintln!("Chain error: {error}");
+            }
+        }
+
+        state.sequence += 1;
+        state.last_hash = entry.hash.clone();
+        state.entries.push_back(entry);
+        if state.entries.len() > Self::MAX_ENTRIES {
+            state.entries.pop_front();
+        }
+    }
+
+    /// Identifiers of nodes referenced by the command, e.g. both nodes of `add connection`, whose result contains only
+    /// names of the nodes.
+    fn command_ids(command: &str) -> Vec<String> {
+        IDENTIFIER
+            .captures_iter(command)
+            .map(|captures| captures[1].replace("\\\"", "\"").replace("\\\\", "\\"))
+            .collect()
+    }
+
+    /// Execute audit command if client with given role is admin, or return `None` if given command is not audit
+    /// command. Audit log file is verified against its latest anchor in the given chain.
+    pub fn execute(&self, role: Option<Role>, command: &str, chain: &RwLock<Chain>) -> Option<GraphResults> {
+        let command = audit_parser::command(command).ok()?;
+        if role < Some(Role::Admin) {
+            return Some(Err(DatabaseError::PermissionDenied("command requires admin role".to_string())));
+        }
+
+        Some(match command {
+            AuditCommand::Fetch => Ok(self.state.lock().unwrap().entries.iter().map(AuditEntry::as_result).collect()),
+            AuditCommand::Verify => self.verify(chain.read().unwrap().audit_anchor().cloned()),
+        })
+    }
+
+    /// Verify hash chain of the audit log file (or of entries in memory, if log isn't stored to file), and that the
+    /// file contains the entry of the latest anchor.
+    fn verify(&self, anchor: Option<AuditData>) -> GraphResults {
+        let state = self.state.lock().unwrap();
+        let entries = match (&self.path, &self.file) {
+            (Some(path), Some(file)) => Self::read(path, &mut file.lock().unwrap())?,
+            _ => {
+                let entries: Vec<AuditEntry> = state.entries.iter().cloned().collect();
+                Self::validate(&entries, "memory")?;
+                entries
+            }
+        };
+
+        if let Some(anchor) = anchor.filter(|_| self.file.is_some()) {
+            if !entries.iter().any(|entry| entry.sequence == anchor.sequence && entry.hash == anchor.hash) {
+                return Err(DatabaseError::InvalidAuditLog(
+                    self.path.clone().unwrap_or_default(),
+                    format!("entry {} anchored in the chain was changed or removed", anchor.sequence),
+                ));
+            }
+        }
+        if entries.last().map(|entry| &entry.hash).unwrap_or(&String::new()) != &state.last_hash {
+            return Err(DatabaseError::InvalidAuditLog(
+                self.path.clone().unwrap_or_default(),
+                "latest entries are missing".to_string(),
+            ));
+        }
+
+        let mut result = FxHashMap::default();
+        result.insert("entries".to_string(), entries.len().to_string());
+        result.insert("hash".to_string(), state.last_hash.clone());
+        Ok(vec![result])
+    }
+
+    /// Read entries of the audit log file and verify that each entry is linked to the previous one.
+    fn read(path: &str, file: &mut LineFile) -> Result<Vec<AuditEntry>, DatabaseError> {
+        let error = |reason: String| DatabaseError::InvalidAuditLog(path.to_string(), reason);
+
+        let entries = file
+            .read()
+            .map_err(error)?
+            .iter()
+            .map(|line| serde_json::from_str(line).map_err(|e| error(e.to_string())))
+            .collect::<Result<Vec<AuditEntry>, DatabaseError>>()?;
+
+        if entries
+            .first()
+            .is_some_and(|entry| entry.sequence != 0 || !entry.previous_hash.is_empty())
+        {
+            return Err(error("first entries are missing".to_string()));
+        }
+        Self::validate(&entries, path)?;
+        Ok(entries)
+    }
+
+    fn validate(entries: &[AuditEntry], path: &str) -> Result<(), DatabaseError> {
+        // Entries in memory could start after the first entry, so the first entry is linked to its own previous hash
+        let mut previous = entries.first().map(|entry| (entry.sequence, entry.previous_hash.clone()));
+
+        for entry in entries {
+            let (sequence, previous_hash) = previous.unwrap();
+            if entry.sequence != sequence || entry.previous_hash != previous_hash || entry.hash != entry.digest() {
+                return Err(DatabaseError::InvalidAuditLog(
+                    path.to_string(),
+                    format!("entry {} was changed or removed", entry.sequence),
+                ));
+            }
+            previous = Some((sequence + 1, entry.hash.clone()));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn should_record_mutating_commands_in_audit_log() {
+        // Given
+        let path = env::temp_dir().join("should_record_mutating_commands_in_audit_log.jsonl");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let arguments = HashMap::from([("audit-log".to_string(), vec![path.to_string()])]);
+        let database = Database {
+            audit: AuditLog::from_arguments(&arguments, Some(StorageEncryption::from_passphrase("secret"))).unwrap(),
+            ..Database::default()
+        };
+        database.execute("define node User(name)").unwrap().unwrap();
+        database.execute("add node User($id=\"1\", name=\"John\")").unwrap().unwrap();
+        database.execute("add node User($id=\"2\", name=\"Jane\")").unwrap().unwrap();
+        database.execute("fetch node User($id=\"1\")").unwrap().unwrap();
+        database.execute("add node User($id=\"1\", name=\"John\")").unwrap().unwrap_err();
+        database
+            .execute_from(
+                "query 127.0.0.1:4000",
+                Some(Role::Writer),
+                "add connection from User($id=\"1\") to User($id=\"2\") with weight 5",
+            )
+            .unwrap()
+            .unwrap();
+
+        // When
+        let entries = database.execute("fetch audit;").unwrap().unwrap();
+        let verified = database.execute("verify audit").unwrap().unwrap();
+        let denied = database.execute_as(Some(Role::Writer), "fetch audit").unwrap();
+        let reloaded = AuditLog::from_arguments(&arguments, Some(StorageEncryption::from_passphrase("secret"))).unwrap();
+
+        let content = fs::read_to_string(path).unwrap();
+        let mut lines: Vec<&str> = content.lines().collect();
+        lines.remove(1);
+        fs::write(path, lines.join("\n")).unwrap();
+        let tampered = database.execute("verify audit").unwrap();
+
+        // Last entry is removed, so the rest of the file is valid, but it doesn't contain the anchored entry
+        fs::write(path, content.lines().take(4).collect::<Vec<&str>>().join("\n")).unwrap();
+        let truncated = AuditLog::from_arguments(&arguments, Some(StorageEncryption::from_passphrase("secret"))).unwrap();
+        let truncated = truncated.execute(Some(Role::Admin), "verify audit", &database.chain).unwrap();
+
+        // Then
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[1]["command"], "add node User($id=\"1\", name=\"John\")");
+        assert_eq!(entries[1]["ids"], "1");
+        assert_eq!(entries[1]["source"], "local");
+        assert_eq!(entries[1]["previous_hash"], entries[0]["hash"]);
+        assert_eq!(entries[3]["ids"], "1,2");
+        assert_eq!(entries[3]["source"], "query 127.0.0.1:4000");
+        assert_eq!(entries[3]["role"], "writer");
+        assert_eq!(verified[0]["entries"], "4");
+        assert_eq!(denied.err().unwrap().code(), "PERMISSION_DENIED");
+        assert_eq!(reloaded.state.lock().unwrap().last_hash, entries[3]["hash"]);
+        assert!(!content.contains("John"));
+        assert_eq!(tampered.err().unwrap().code(), "INVALID_AUDIT_LOG");
+        assert_eq!(truncated.err().unwrap().code(), "INVALID_AUDIT_LOG");
+        assert_eq!(database.chain.read().unwrap().audit_anchor().unwrap().hash, entries[3]["hash"]);
+        assert!(AuditLog::from_arguments(&arguments, None).is_err());
+    }
+}
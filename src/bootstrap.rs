@@ -11,26 +11,27 @@ use std::env;
 pub struct Bootstrap;
 
 impl Bootstrap {
-    pub fn init(mut graph: &mut Graph, mut chain: &mut Chain) -> Result<(), DatabaseError> {
+    pub fn init(graph: &mut Graph, chain: &mut Chain) -> Result<(), DatabaseError> {
         let username: String = rand::thread_rng()
             .sample_iter(&rand::distributions::Alphanumeric)
             .take(7)
             .map(char::from)
             .collect();
 
-        let user = format!("add node User(name=\"{username}\",premium=\"true\",key=\"{}\")",  chain.wallet.get_public_key().clone());
+        let user = format!(
+            "add node User(name=\"{username}\",premium=\"true\",key=\"{}\")",
+            chain.signer.get_public_key().clone()
+        );
 
-        let commands = vec![
+        let commands = [
             "define node User(name,premium,key) with agent(premium=\"true\")",
             "define node Playlist(name)",
             "add node Playlist(name=\"Party Mix\")",
             user.as_str(),
         ];
 
-        let mut commands_iter = commands.iter().peekable();
-
-        while let Some(command) = commands_iter.next() {
-            let result = QueryProcessor::parse_command(&mut graph, &mut chain, command)
+        for command in commands.iter() {
+            let result = QueryProcessor::parse_command(graph, chain, command)
                 .expect("BOOTSTRAP :: Failed to parse command")
                 .expect("BOOTSTRAP :: Failed to parse command")
                 .first()
@@ -44,7 +45,7 @@ impl Bootstrap {
         for n in 1..4 {
             if users.contains_key(format!("username{n}").as_str()) && users.contains_key(format!("key{n}").as_str()) {
                 Self::insert_node(
-                    &mut graph,
+                    graph,
                     users.get(format!("username{n}").as_str()).unwrap().first().unwrap(),
                     users.get(format!("key{n}").as_str()).unwrap().first().unwrap(),
                 );
@@ -1,260 +1,2680 @@
+use crate::access::{ClientToken, Role};
 use crate::chain::agent::AgentService;
-use crate::chain::block::{Block, BlockData, BlockDataType, EdgeData, ValidatorData};
+use crate::chain::archive::Archive;
+use crate::chain::block::{
+    AuditData, Block, BlockData, BlockDataType, BlockHeader, DelegationData, EdgeData, KeyRotationData, NodeData, NodeOperation, RewardData,
+    SchemaData, SlashData, ValidatorData,
+};
+use crate::chain::cdc::ChangeStream;
+use crate::chain::clock::ClockTolerance;
+use crate::chain::consensus::Consensus;
+use crate::chain::delivery::DeliveryJob;
+use crate::chain::difficulty::DifficultyAlgorithm;
+use crate::chain::encryption::StorageEncryption;
+use crate::chain::epoch::Epochs;
+use crate::chain::finality::{CheckpointVote, Finality};
+use crate::chain::genesis::Genesis;
+use crate::chain::hasher::HashAlgorithm;
+use crate::chain::history::History;
+use crate::chain::mempool::Mempool;
+use crate::chain::mine::MiningJob;
+use crate::chain::mode::NodeMode;
+use crate::chain::schedule::ProposerSchedule;
+use crate::chain::scheme::{SignatureAlgorithm, SignatureError};
+use crate::chain::signer::{RemoteSigner, Signer};
 use crate::chain::wallet::Wallet;
+use crate::chain::webhook::Webhooks;
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::error::DatabaseError;
+use crate::graph::expiry::{Expiring, Expiry};
 use crate::graph::{Graph, GraphResults};
-use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use crate::import::identifier_attributes;
+use crate::protocol::authentication::Authentication;
+use ed25519_dalek::{Signature, VerifyingKey};
 use error::ChainError;
+use libp2p::PeerId;
+use rayon::prelude::*;
 use rustc_hash::FxHashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ops::Range;
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 mod agent;
+pub mod archive;
 pub mod block;
-pub(crate) mod error;
-mod wallet;
+pub mod cdc;
+pub mod clock;
+pub mod consensus;
+pub mod delivery;
+pub mod difficulty;
+pub mod encoding;
+pub mod encryption;
+pub mod epoch;
+pub mod error;
+pub mod finality;
+pub mod genesis;
+pub mod hasher;
+pub mod history;
+pub(crate) mod http;
+pub mod mempool;
+pub mod mine;
+pub mod mode;
+pub mod schedule;
+pub mod scheme;
+pub mod signer;
+pub mod wallet;
+pub mod webhook;
 
 pub struct Chain {
     pub blocks: Vec<Block>,
-    pub(crate) wallet: Wallet,
+    pub signer: Box<dyn Signer>,
     pub(crate) agent_service: AgentService,
+    pub mode: NodeMode,
+    pub consensus: Consensus,
+    pub difficulty: DifficultyAlgorithm,
+    pub clock: ClockTolerance,
+    pub epochs: Epochs,
+    pub schedule: ProposerSchedule,
+    pub finality: Finality,
+    pub mempool: Mempool,
+    pub history: History,
+    pub archive: Option<Archive>,
+    /// Encryption of archive and exported data
+    pub encryption: Option<StorageEncryption>,
+    pub changes: Option<ChangeStream>,
+    pub webhooks: Option<Webhooks>,
+    /// Secret attributes of each definition, which are removed from node changes before they are added to the chain
+    secret_attributes: FxHashMap<String, Vec<String>>,
+    /// Blocks produced by local validator which are not published to peers yet
+    pub(crate) outbound: VecDeque<Block>,
+    /// Changes of local validator (with difficulty of their accounts) waiting for its slot in the proposer schedule
+    queued: VecDeque<(BlockData, usize)>,
+    abandoned: Vec<Block>,
+    /// State before changes of local validator started to be held (e.g. by a transaction), so they can be discarded
+    held: Option<Held>,
+}
+
+struct Held {
+    mempool: Mempool,
+    queued: usize,
+    secret_attributes: FxHashMap<String, Vec<String>>,
 }
 
 impl Default for Chain {
     fn default() -> Self {
-        Chain {
-            blocks: vec![Block::default()],
-            wallet: Wallet::default(),
-            agent_service: AgentService::default(),
-        }
+        Chain::new(&Genesis::default())
     }
 }
 
 impl Chain {
-    pub fn define_agent(&mut self, node_name: String, conditions: FxHashMap<String, String>) {
-        self.agent_service.define_agent(node_name, conditions)
+    /// Amount credited to the validator for each produced block.
+    const BLOCK_REWARD: u64 = 1;
+    /// Number of signatures verified together while validating the chain.
+    const SIGNATURE_BATCH_SIZE: usize = 256;
+    /// Unpublished blocks kept for publishing, older blocks are received by peers when they sync the chain.
+    const MAX_OUTBOUND_BLOCKS: usize = 1024;
+
+    pub fn new(genesis: &Genesis) -> Chain {
+        let mut agent_service = AgentService::default();
+
+        for validator in &genesis.validators {
+            agent_service.genesis_stake.insert(validator.public_key.clone(), validator.stake);
+        }
+
+        let genesis_block = Block::genesis(genesis);
+        let mut epochs = Epochs::new(genesis.epoch_length);
+        epochs.snapshot(&genesis_block, &agent_service);
+
+        Chain {
+            blocks: vec![genesis_block],
+            signer: Box::new(Wallet::default()),
+            agent_service,
+            mode: NodeMode::default(),
+            consensus: Consensus::default(),
+            difficulty: DifficultyAlgorithm::default(),
+            clock: ClockTolerance::default(),
+            epochs,
+            schedule: ProposerSchedule::default(),
+            finality: Finality::default(),
+            mempool: Mempool::default(),
+            history: History::default(),
+            archive: None,
+            encryption: None,
+            changes: None,
+            webhooks: None,
+            secret_attributes: FxHashMap::default(),
+            outbound: VecDeque::new(),
+            queued: VecDeque::new(),
+            abandoned: vec![],
+            held: None,
+        }
     }
 
-    pub fn add_or_update_agent(&mut self, graph: &mut Graph, node_name: String, identifier: String) -> Result<(), ChainError> {
-        let (p_key, difficulty) = self.agent_service.add_or_update_agent(graph, node_name, &identifier)?;
+    /// Create chain from genesis, with signer and settings given by command line arguments.
+    pub fn from_arguments(genesis: &Genesis, arguments: &HashMap<String, Vec<String>>) -> Result<Chain, ChainError> {
+        let mut chain = Chain::new(genesis);
+        if let Some(wallet) = Wallet::from_arguments(arguments)? {
+            chain.signer = Box::new(wallet);
+        }
+        if let Some(signer) = RemoteSigner::from_arguments(arguments)? {
+            chain.signer = Box::new(signer);
+        }
+        chain.mode = NodeMode::from_arguments(arguments)?;
+        chain.consensus = Consensus::from_arguments(arguments)?;
+        chain.difficulty = DifficultyAlgorithm::from_arguments(arguments)?;
+        chain.clock = ClockTolerance::from_arguments(arguments)?;
+        chain.schedule = ProposerSchedule::from_arguments(arguments)?;
+        // Block of other validator would be accepted before the primary validator could be sure that its slot has passed
+        if chain.schedule.timeout() <= chain.clock.max_drift() {
+            return Err(ChainError::InvalidScheduleConfiguration(format!(
+                "proposer_timeout={} must be greater than max_clock_drift={}",
+                chain.schedule.timeout(),
+                chain.clock.max_drift()
+            )));
+        }
+        chain.finality = Finality::from_arguments(arguments)?;
+        chain.mempool = Mempool::from_arguments(arguments)?;
+        chain.encryption = StorageEncryption::from_arguments(arguments)?;
+        chain.archive = Archive::from_arguments(arguments, &chain.blocks[0], chain.encryption.clone())?;
+        chain.changes = ChangeStream::from_arguments(arguments, chain.encryption.clone())?;
+        chain.webhooks = Webhooks::from_arguments(arguments)?;
 
-        if p_key == self.wallet.get_public_key() {
-            let validator_data = ValidatorData::new(self.wallet.get_public_key(), identifier.clone());
-            let block_data = BlockData::new(BlockDataType::ValidatorData, None, Some(validator_data));
+        Ok(chain)
+    }
 
-            let block = Block::new(
-                self.blocks.len(),
-                self.blocks.last().unwrap().hash.clone(),
-                block_data,
-                &mut self.wallet,
-                difficulty,
-            );
+    /// Register accounts of local validator which aren't registered yet, by adding validator block for each of them.
+    /// Agents are derived from changes in the chain, so this is done after local block is added.
+    fn register_local_agents(&mut self) -> Result<(), ChainError> {
+        let public_key = self.signer.get_public_key();
+        let mut accounts: Vec<(String, usize)> = self
+            .agent_service
+            .accounts
+            .iter()
+            .filter(|(identifier, account)| account.public_key == public_key && !self.agent_service.registered.contains(*identifier))
+            .filter(|(identifier, _)| !self.is_queued_registration(identifier))
+            .map(|(identifier, account)| (identifier.clone(), account.difficulty))
+            .collect();
+        accounts.sort();
 
-            self.add_new_block(block)?
+        for (identifier, difficulty) in accounts {
+            let validator_data = ValidatorData::new(public_key.clone(), identifier);
+            let block_data = BlockData::new(BlockDataType::ValidatorData, None, Some(validator_data));
+
+            self.add_local_block(block_data, difficulty)?
         }
 
         Ok(())
     }
 
-    pub fn remove_agent(&mut self, identifier: String) {
-        self.agent_service.remove_agent(&identifier);
+    fn is_queued_registration(&self, identifier: &str) -> bool {
+        self.queued
+            .iter()
+            .filter_map(|(data, _)| data.validator_data.as_ref())
+            .any(|validator| validator.account_id == identifier)
     }
 
-    pub fn add_edge_change(&mut self, from: String, to: String, weight: i8) -> Result<(), ChainError> {
-        let data = EdgeData::new(from.clone(), to, weight);
+    /// Add delegation of stake to the chain, so all peers count delegated stake of the agent the same way. Pending edge
+    /// changes are added first, as delegated stake is derived from edges of the node.
+    pub fn delegate_stake(&mut self, node_name: String, identifier: String, agent_id: String) -> GraphResults {
+        let chain_error = DatabaseError::ChainError;
 
-        let block = Block::new(
-            self.blocks.len(),
-            self.blocks.last().unwrap().hash.clone(),
-            BlockData::new(BlockDataType::EdgeData, Some(data), None),
-            &mut self.wallet,
-            self.agent_service.get_difficulty(&from),
+        self.flush_mempool().map_err(chain_error)?;
+        self.agent_service.validate_delegation(&identifier, &agent_id).map_err(chain_error)?;
+
+        let delegation = DelegationData::new(identifier.clone(), agent_id.clone());
+        self.add_local_block(BlockData::delegation(delegation), 0).map_err(chain_error)?;
+        let stake = self.agent_service.get_edge_count(&identifier);
+
+        let mut result = FxHashMap::default();
+        result.insert(InternalNodeAttribute::ID_ATTRIBUTE.to_string(), identifier);
+        result.insert(InternalNodeAttribute::NAME_ATTRIBUTE.to_string(), node_name);
+        result.insert("agent".to_string(), agent_id);
+        result.insert("stake".to_string(), stake.to_string());
+
+        Ok(vec![result])
+    }
+
+    pub fn export_mnemonic(&self) -> GraphResults {
+        let mut result = FxHashMap::default();
+        result.insert("public_key".to_string(), self.signer.get_public_key());
+        result.insert("mnemonic".to_string(), self.signer.export_mnemonic().map_err(DatabaseError::ChainError)?);
+
+        Ok(vec![result])
+    }
+
+    /// Sign peer id by the local wallet, so peer can join authenticated network whose authority is this wallet. Only
+    /// valid peer id is signed, with network token prefix, so the token can't be used as any other signature.
+    pub fn issue_network_token(&mut self, peer_id: &str) -> GraphResults {
+        let peer = PeerId::from_str(peer_id).map_err(|error| DatabaseError::InvalidQuery(format!("peer id {peer_id} is not valid ({error})")))?;
+
+        let mut result = FxHashMap::default();
+        result.insert("peer_id".to_string(), peer.to_string());
+        result.insert(
+            "token".to_string(),
+            self.signer
+                .sign(&Authentication::token_message(&peer))
+                .map_err(DatabaseError::ChainError)?,
         );
+        result.insert("authority".to_string(), self.signer.get_public_key());
 
-        self.add_new_block(block)
+        Ok(vec![result])
     }
 
-    pub fn replace_chain(&mut self, chain: &Vec<Block>) -> Result<(), ChainError> {
-        self.validate_chain(chain)?;
+    /// Sign role of query client by the local wallet, so client can authenticate with the token until it expires.
+    pub fn issue_client_token(&mut self, role: Role, expires_in: u64) -> GraphResults {
+        let token = ClientToken {
+            role,
+            expires_at: Expiry::now().saturating_add(expires_in),
+        };
 
-        if chain.len() <= self.blocks.len() {
-            return Err(ChainError::ChainSizeIsNotLongerThanLocalChain);
-        }
+        let mut result = FxHashMap::default();
+        result.insert("token".to_string(), token.issue(self.signer.as_mut()).map_err(DatabaseError::ChainError)?);
+        result.insert("role".to_string(), role.to_string());
+        result.insert("expires_at".to_string(), token.expires_at.to_string());
+        result.insert("authority".to_string(), self.signer.get_public_key());
+
+        Ok(vec![result])
+    }
+
+    /// Add edge change to mempool, full batch is added to the chain right away. In proof of work mode, batch is left
+    /// for the mining task instead, as mining can take a while.
+    pub fn add_edge_change(&mut self, from: String, to: String, weight: i8) -> Result<(), ChainError> {
+        self.add_edge_data(EdgeData::new(from, to, weight))
+    }
 
-        self.blocks = chain.clone();
+    /// Add edge change (e.g. with time to live) to the mempool, see `add_edge_change`.
+    pub fn add_edge_data(&mut self, data: EdgeData) -> Result<(), ChainError> {
+        let difficulty = self.agent_service.get_difficulty(&data.from);
+
+        if self.mempool.push(data, difficulty) && !self.consensus.is_proof_of_work() {
+            return self.flush_mempool();
+        }
 
         Ok(())
     }
 
-    pub fn add_new_block(&mut self, block: Block) -> Result<(), ChainError> {
-        let previous_block = self.blocks.last().unwrap();
+    /// Add node change to the chain, with difficulty of node's account. Pending edge changes are added first, so
+    /// changes are replayed by peers in the same order as they were made. Values of secret attributes are kept only in
+    /// the local graph, so they are removed from the change.
+    pub fn add_node_change(&mut self, data: NodeData) -> Result<(), ChainError> {
+        self.flush_mempool()?;
 
-        self.validate_block(&block, previous_block)?;
+        let data = self.without_secrets(data);
+        let difficulty = self.agent_service.get_difficulty(&data.id);
 
-        self.blocks.push(block);
+        self.add_local_block(BlockData::node(data), difficulty)
+    }
+
+    /// Add node changes made by one command (e.g. `add nodes`) to the chain in batched blocks (split by maximum batch
+    /// size), with the highest difficulty of their accounts, instead of one block for each node.
+    pub fn add_node_changes(&mut self, changes: Vec<NodeData>) -> Result<(), ChainError> {
+        self.flush_mempool()?;
+
+        let mut changes: Vec<NodeData> = changes.into_iter().map(|data| self.without_secrets(data)).collect();
+        while !changes.is_empty() {
+            let batch: Vec<NodeData> = changes.drain(..changes.len().min(Mempool::MAX_BATCH_SIZE)).collect();
+            let difficulty = batch
+                .iter()
+                .map(|data| self.agent_service.get_difficulty(&data.id))
+                .max()
+                .unwrap_or_default();
+
+            self.add_local_block(BlockData::node_batch(batch), difficulty)?;
+        }
 
         Ok(())
     }
 
-    fn validate_block(&self, block: &Block, previous_block: &Block) -> Result<(), ChainError> {
-        if block.previous_hash != previous_block.hash {
-            return Err(ChainError::BlockHasWrongPreviousHashValue(block.id));
+    fn without_secrets(&self, mut data: NodeData) -> NodeData {
+        if let Some(secret_attributes) = self.secret_attributes.get(&data.name) {
+            data.attributes.retain(|attribute, _| !secret_attributes.contains(attribute));
         }
 
-        if block.id != previous_block.id + 1 {
-            return Err(ChainError::BlockIsNotNextBlockInSequence(block.id));
+        data
+    }
+
+    /// Add node definition (with conditions of its agent) to the chain, so all peers have the same definitions.
+    pub fn add_schema_change(&mut self, data: SchemaData) -> Result<(), ChainError> {
+        self.flush_mempool()?;
+
+        // Secret attributes are known before the block is added, so they are removed from held node changes too
+        if !data.secret_attributes.is_empty() {
+            self.secret_attributes.insert(data.name.clone(), data.secret_attributes.clone());
         }
 
-        Block::validate_block_hash(block)?;
-        self.validate_signature(block.id, &block.validator, &block.signature, &block.hash)?;
-        self.validate_stake(block.id, &block.validator, block.difficulty)?;
+        self.add_local_block(BlockData::schema(data), 0)
+    }
 
-        Ok(())
+    /// Add sequence and hash of the latest audit log entry to the chain. Pending edge changes are added first, so the
+    /// anchor follows the changes of the audited command.
+    pub fn add_audit_anchor(&mut self, data: AuditData) -> Result<(), ChainError> {
+        self.flush_mempool()?;
+
+        self.add_local_block(BlockData::audit(data), 0)
     }
 
-    fn validate_chain(&self, chain: &[Block]) -> Result<(), ChainError> {
-        if *chain.first().unwrap() != Block::default() {
-            return Err(ChainError::ChainHasInvalidGenesisBlock);
+    /// Latest audit log anchor added by the local validator, including anchors signed by its keys before rotation.
+    pub fn audit_anchor(&self) -> Option<&AuditData> {
+        let mut public_keys = vec![self.signer.get_public_key()];
+
+        for block in self.blocks.iter().rev() {
+            if let Some(rotation) = block
+                .data
+                .key_rotation_data
+                .as_ref()
+                .filter(|rotation| public_keys.contains(&rotation.new_public_key))
+            {
+                public_keys.push(rotation.old_public_key.clone());
+            }
+            if let Some(audit) = block.data.audit_data.as_ref().filter(|_| public_keys.contains(&block.validator)) {
+                return Some(audit);
+            }
         }
 
-        for i in 1..chain.len() {
-            let block = &chain[i];
-            let previous_block = &chain[i - 1];
+        None
+    }
 
-            if previous_block.hash != block.previous_hash {
-                return Err(ChainError::BlockHasWrongPreviousHashValue(block.id));
-            } else if previous_block.id + 1 != block.id {
-                return Err(ChainError::BlockIsNotNextBlockInSequence(block.id));
+    /// Apply definitions, node and edge changes of blocks from given height to the graph, e.g. after blocks were
+    /// received from peer. If local chain was replaced by chain which diverged from it, changes of abandoned blocks are
+    /// rolled back first, and blocks are applied from the divergence point. Changes which can't be applied (e.g. node
+    /// made by local node is already in the graph, or edge of unknown node) are skipped, where node changes of peers
+    /// are reported. Soft deletes and restores use block timestamp, so each peer restores the same nodes. Expiry of
+    /// added nodes and edges is scheduled, where only items of blocks produced by local validator are deleted by it in
+    /// the chain.
+    pub fn replay(&mut self, graph: &mut Graph, from_height: usize) {
+        let abandoned = std::mem::take(&mut self.abandoned);
+        let from_height = match abandoned.first() {
+            Some(block) => {
+                self.rollback(graph, &abandoned);
+                from_height.min(block.id)
+            }
+            None => from_height,
+        };
+
+        let public_key = self.signer.get_public_key();
+        for block in self.blocks.iter().skip(from_height) {
+            let (data, is_local) = (&block.data, block.validator == public_key);
+            if let Some(schema) = &data.schema_data {
+                let _ = graph.create_definition(schema.name.clone(), schema.attributes.clone());
+                if schema.deny_self_loops {
+                    graph.denied_self_loops.insert(schema.name.clone());
+                }
+                if !schema.secret_attributes.is_empty() {
+                    graph
+                        .secret_attributes
+                        .insert(schema.name.clone(), schema.secret_attributes.iter().cloned().collect());
+                }
+            }
+
+            for node in data.nodes() {
+                let result = match node.operation {
+                    NodeOperation::Add => graph
+                        .insert_node(node.name.clone(), Self::node_attributes(&node.id, &node.attributes))
+                        .inspect(|_| {
+                            if let Some(expires_at) = node.expires_at {
+                                graph
+                                    .expiry
+                                    .schedule_at(Expiring::Node(node.name.clone(), node.id.clone()), expires_at, is_local);
+                            }
+                        }),
+                    NodeOperation::Update => graph.update_node(node.name.clone(), Self::node_attributes(&node.id, &node.attributes)),
+                    NodeOperation::Delete => graph.delete_node(node.name.clone(), identifier_attributes(&node.id)),
+                    NodeOperation::SoftDelete => graph.soft_delete_node(node.name.clone(), identifier_attributes(&node.id), block.timestamp),
+                    NodeOperation::Restore => graph.restore_node(node.name.clone(), identifier_attributes(&node.id), block.timestamp),
+                };
+
+                if let (Err(error), false) = (result, is_local) {
+                    eprintln!("Node change of block {} can't be replayed: {error}", block.id);
+                }
+            }
+
+            for edge in data.edge_data.iter().chain(data.edge_batch.iter().flatten()) {
+                Self::apply_edge(graph, &edge.from, &edge.to, edge.weight);
+
+                if let (Some(expires_at), Some(from_name), Some(to_name)) = (edge.expires_at, graph.find_name(&edge.from), graph.find_name(&edge.to))
+                {
+                    let item = Expiring::Connection((from_name, edge.from.clone()), (to_name, edge.to.clone()));
+                    graph.expiry.schedule_at(item, expires_at, is_local);
+                }
+            }
+        }
+    }
+
+    /// Restore nodes and edges changed by abandoned blocks to their state at the divergence point, which is the same in
+    /// both chains. Definitions of abandoned blocks are kept, as nodes could be already added to them.
+    fn rollback(&self, graph: &mut Graph, abandoned: &[Block]) {
+        let state = self.history.snapshot(&self.blocks, abandoned[0].id - 1);
+
+        for node in abandoned.iter().flat_map(|block| block.data.nodes()) {
+            let _ = match state.nodes.get(&node.id) {
+                Some((name, attributes)) => Self::restore_state(graph, name, &node.id, attributes),
+                None => graph.delete_node(node.name.clone(), identifier_attributes(&node.id)),
+            };
+        }
+
+        for block in abandoned {
+            for edge in block.data.edge_data.iter().chain(block.data.edge_batch.iter().flatten()) {
+                let weight = state.edges.get(&edge.from).and_then(|edges| edges.get(&edge.to));
+
+                Self::apply_edge(graph, &edge.from, &edge.to, weight.copied().unwrap_or(0));
+            }
+        }
+    }
+
+    /// Set node to its state from the history, where node soft deleted in that state is changed as visible node first
+    /// and soft deleted again at its original time.
+    fn restore_state(graph: &mut Graph, name: &String, identifier: &String, attributes: &BTreeMap<String, String>) -> GraphResults {
+        let mut attributes = Self::node_attributes(identifier, attributes);
+        let deleted_at = attributes.remove(InternalNodeAttribute::DELETED_ATTRIBUTE);
+
+        let result = match graph.find_by_id(name, identifier) {
+            Ok(node) => {
+                node.attributes.remove(InternalNodeAttribute::DELETED_ATTRIBUTE);
+                graph.update_node(name.clone(), attributes)
+            }
+            Err(_) => graph.insert_node(name.clone(), attributes),
+        };
+
+        match deleted_at {
+            Some(deleted_at) => {
+                result.and_then(|_| graph.soft_delete_node(name.clone(), identifier_attributes(identifier), deleted_at.parse().unwrap_or_default()))
             }
+            None => result,
+        }
+    }
+
+    fn node_attributes(identifier: &str, attributes: &BTreeMap<String, String>) -> FxHashMap<String, String> {
+        let mut node_attributes: FxHashMap<String, String> = attributes.clone().into_iter().collect();
+        node_attributes.insert(InternalNodeAttribute::ID_ATTRIBUTE.to_string(), identifier.to_string());
+        node_attributes
+    }
+
+    /// Set weight of the edge between nodes with given identifiers, edge with zero weight is deleted. Identifiers are
+    /// unique across definitions, so each identifier resolves to the same node on every peer.
+    fn apply_edge(graph: &mut Graph, from_id: &str, to_id: &str, weight: i8) {
+        let (Some(from_name), Some(to_name)) = (graph.find_name(from_id), graph.find_name(to_id)) else {
+            return;
+        };
+        let from = (from_name, identifier_attributes(from_id));
+        let to = (to_name, identifier_attributes(to_id));
+
+        let _ = match weight {
+            0 => graph.delete_edge(from, to),
+            _ => graph
+                .update_edge(from.clone(), to.clone(), weight)
+                .or_else(|_| graph.add_edge(from, to, weight)),
+        };
+    }
+
+    /// Take blocks which were appended to the chain since the last delivery to change stream, and due webhook payloads
+    /// (if they are configured) as job, which is delivered outside of the chain. There is no job if there is nothing to
+    /// deliver.
+    pub fn delivery_job(&mut self) -> Option<DeliveryJob> {
+        let changes = self.changes.as_mut().and_then(|changes| changes.delivery(&self.blocks));
+        let webhooks = self.webhooks.as_mut().and_then(Webhooks::delivery);
+
+        (changes.is_some() || webhooks.is_some()).then_some(DeliveryJob { changes, webhooks })
+    }
+
+    /// Store progress of delivered job, so undelivered blocks and failed payloads are delivered again by the next job.
+    pub fn complete_delivery(&mut self, job: DeliveryJob) -> Result<(), ChainError> {
+        if let (Some(webhooks), Some(delivery)) = (&mut self.webhooks, job.webhooks) {
+            webhooks.complete(delivery);
+        }
+
+        match (&mut self.changes, job.changes) {
+            (Some(changes), Some(delivery)) => changes.complete(delivery).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Add changes queued for the slot of local validator, followed by all pending edge changes to the chain - single
+    /// change is added as edge block, and multiple changes are added as one batch block, with highest difficulty of
+    /// their accounts. Changes are kept pending while local validator is not scheduled to propose the next block. In
+    /// proof of work mode changes are added by mining task instead.
+    pub fn flush_mempool(&mut self) -> Result<(), ChainError> {
+        if self.held.is_some() {
+            self.queue_mempool();
+            return Ok(());
+        }
+        if self.consensus.is_proof_of_work() {
+            return Ok(());
+        }
+
+        self.add_queued_blocks()?;
+
+        if self.mempool.is_empty() || !self.queued.is_empty() || !self.is_scheduled() {
+            return Ok(());
+        }
+
+        let (changes, difficulty) = self.mempool.take();
+
+        self.add_local_block(Self::edge_block_data(changes), difficulty)
+    }
+
+    fn add_queued_blocks(&mut self) -> Result<(), ChainError> {
+        while !self.consensus.is_proof_of_work() && self.is_scheduled() {
+            let Some((data, difficulty)) = self.queued.pop_front() else {
+                break;
+            };
+
+            self.add_local_block(data, difficulty)?;
         }
 
         Ok(())
     }
 
-    fn validate_signature(&self, id: usize, validator: &String, signature: &String, hash: &String) -> Result<(), ChainError> {
-        let public_key = VerifyingKey::from_bytes(
-            hex::decode(validator)
-                .map_err(|_| ChainError::BlockHasWrongValidatorValue(id))?
-                .as_slice()
-                .try_into()
-                .map_err(|_| ChainError::BlockHasWrongValidatorValue(id))?,
-        )
-        .map_err(|_| ChainError::BlockHasWrongValidatorValue(id))?;
+    fn queue_mempool(&mut self) {
+        if !self.mempool.is_empty() {
+            let (changes, difficulty) = self.mempool.take();
+            self.queued.push_back((Self::edge_block_data(changes), difficulty));
+        }
+    }
 
-        Ok(public_key
-            .verify(
-                hash.as_bytes(),
-                &Signature::from_str(signature.as_str()).map_err(|_| ChainError::BlockHasWrongSignatureValue(id))?,
-            )
-            .map_err(|_| ChainError::BlockHasWrongSignatureValue(id))?)
+    /// Hold changes of local validator (in the queue, the same way as while it's not scheduled) until they are either
+    /// released to the chain or discarded, so changes of a transaction are added only if all of its commands succeed.
+    pub fn hold(&mut self) {
+        self.held = Some(Held {
+            mempool: self.mempool.clone(),
+            queued: self.queued.len(),
+            secret_attributes: self.secret_attributes.clone(),
+        });
     }
 
-    fn validate_stake(&self, id: usize, validator: &String, difficulty: usize) -> Result<(), ChainError> {
-        let validator_difficulty = self.agent_service.get_validator_difficulty(validator);
+    /// Add held changes to the chain, as if they were added without holding them.
+    pub fn release(&mut self) -> Result<(), ChainError> {
+        let Some(held) = self.held.take() else {
+            return Ok(());
+        };
 
-        if validator_difficulty < difficulty {
-            return Err(ChainError::BlockHasWrongDifficultyValue(id));
+        for (data, difficulty) in self.queued.split_off(held.queued) {
+            self.add_queued_blocks()?;
+            self.add_local_block(data, difficulty)?;
         }
 
         Ok(())
     }
 
-    pub fn as_graph_result(&self) -> GraphResults{
-        Ok(self.blocks.iter().map(|block| block.as_hash_map()).collect())
+    /// Discard held changes, and restore pending edge changes as they were when changes started to be held.
+    pub fn discard(&mut self) {
+        if let Some(held) = self.held.take() {
+            self.mempool = held.mempool;
+            self.queued.truncate(held.queued);
+            self.secret_attributes = held.secret_attributes;
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::graph::attribute::InternalNodeAttribute;
-    use super::*;
-    use crate::graph::node::Node;
+    /// Take the first queued change, or all pending edge changes, as block which is mined by mining task, in proof of
+    /// work mode only.
+    pub fn mining_job(&mut self) -> Option<MiningJob> {
+        if !self.consensus.is_proof_of_work() || self.mode.is_observer() {
+            return None;
+        }
 
-    #[test]
-    fn should_define_agent() {
-        // Given
-        let mut chain = Chain::default();
+        let (data, difficulty) = match self.queued.pop_front() {
+            Some(queued) => queued,
+            None if !self.mempool.is_empty() => {
+                let (changes, difficulty) = self.mempool.take();
+                (Self::edge_block_data(changes), difficulty)
+            }
+            None => return None,
+        };
+        let zero_bits = self.consensus.required_work(&data.data_type);
+        let block_difficulty = self.local_difficulty(&data.data_type, difficulty);
+        let block = Block::unsigned(
+            self.blocks.len(),
+            self.blocks.last().unwrap().hash.clone(),
+            data,
+            self.signer.get_public_key(),
+            block_difficulty,
+            self.hash_algorithm(),
+        );
 
-        // When
-        chain.define_agent("User".to_string(), FxHashMap::default());
+        Some(MiningJob::new(block, zero_bits, self.hash_algorithm(), difficulty))
+    }
 
-        // Then
-        assert_eq!(chain.agent_service.agents.len(), 1);
+    /// Sign and add block mined by mining task, followed by the reward block. If chain was extended while block was
+    /// mined, its changes are queued again before other changes, for the next block.
+    pub fn add_mined_block(&mut self, job: MiningJob) -> Result<(), ChainError> {
+        if job.block.previous_hash != self.blocks.last().unwrap().hash {
+            self.queued.push_front((job.block.data, job.difficulty));
+            return Ok(());
+        }
+
+        let block = job.block.sign(self.signer.as_mut())?;
+
+        self.add_produced_block(block)
     }
 
-    #[test]
-    fn should_add_or_update_agent() {
-        // Given
-        let mut chain = Chain::default();
-        let mut graph = Graph::default();
+    /// Single change is added as edge block, and multiple changes are added as one batch block.
+    fn edge_block_data(mut changes: Vec<EdgeData>) -> BlockData {
+        if changes.len() == 1 {
+            BlockData::new(BlockDataType::EdgeData, changes.pop(), None)
+        } else {
+            BlockData::batch(changes)
+        }
+    }
 
-        let mut attributes = FxHashMap::default();
-        attributes.insert(InternalNodeAttribute::ID_ATTRIBUTE.to_string(), "identifier".to_string());
-        attributes.insert("key".to_string(), chain.wallet.get_public_key());
-        graph.nodes.insert("identifier:User".to_string(), Node::new(attributes, vec![]));
+    /// Check if local validator can propose the next block now, any validator can propose it in proof of work mode.
+    fn is_scheduled(&self) -> bool {
+        if self.consensus.is_proof_of_work() {
+            return true;
+        }
 
-        chain.agent_service.agents.insert("User".to_string(), FxHashMap::default());
+        let validator_set = self.epochs.validator_set(self.blocks.len());
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
 
-        // When
-        let result = chain.add_or_update_agent(&mut graph, "User".to_string(), "identifier".to_string());
+        self.schedule
+            .is_scheduled(&validator_set, self.blocks.last().unwrap(), &self.signer.get_public_key(), now)
+    }
 
-        // Then
-        assert!(result.is_ok());
-        assert_eq!(chain.agent_service.agents.len(), 1);
-        assert_eq!(chain.blocks.len(), 2);
+    /// Changes of local validator are queued until its slot in the proposer schedule.
+    pub fn has_queued_changes(&self) -> bool {
+        !self.queued.is_empty()
     }
 
-    #[test]
-    fn should_add_edge_change() {
-        // Given
-        let mut chain = Chain::default();
+    /// Sign block produced by local validator, and add it to the chain. Observer never produces blocks. Block which
+    /// must be proposed by scheduled validator is queued (after pending edge changes, so changes keep their order)
+    /// while local validator is not scheduled, and in proof of work mode it's always queued for the mining task, so
+    /// chain isn't locked while it's mined. Any block is queued while changes are held.
+    fn add_local_block(&mut self, data: BlockData, difficulty: usize) -> Result<(), ChainError> {
+        if self.mode.is_observer() {
+            return Err(ChainError::NodeIsObserver);
+        }
 
-        // When
-        let result = chain.add_edge_change("from".to_string(), "to".to_string(), 1);
+        if self.held.is_some()
+            || DifficultyAlgorithm::requires_difficulty(&data.data_type) && (self.consensus.is_proof_of_work() || !self.is_scheduled())
+        {
+            self.queue_mempool();
+            self.queued.push_back((data, difficulty));
 
-        // Then
-        assert!(result.is_ok());
-        assert_eq!(chain.blocks.len(), 2);
-        assert_block(
-            chain.blocks.last().unwrap(),
-            Some(EdgeData::new("from".to_string(), "to".to_string(), 1)),
-            None,
-        );
+            return Ok(());
+        }
+
+        let difficulty = self.local_difficulty(&data.data_type, difficulty);
+        let hash_algorithm = self.hash_algorithm();
+
+        let block = Block::new(
+            self.blocks.len(),
+            self.blocks.last().unwrap().hash.clone(),
+            data,
+            self.signer.as_mut(),
+            difficulty,
+            hash_algorithm,
+        )?;
+
+        self.add_produced_block(block)
     }
 
-    #[test]
-    fn should_replace_chain() {}
+    /// Difficulty of the block produced by local validator with given difficulty of its account.
+    fn local_difficulty(&self, data_type: &BlockDataType, difficulty: usize) -> usize {
+        // Stake of the validator could be changed in the middle of the epoch, while the block can't exceed its stake
+        // at the start of the epoch
+        let validator_set = self.epochs.validator_set(self.blocks.len());
+        let difficulty = difficulty.min(validator_set.get_stake(&self.signer.get_public_key()));
 
-    #[test]
-    fn should_add_new_block() {
-        // Given
-        let mut chain = Chain::default();
-        let previous_block = chain.blocks.last().unwrap().clone();
+        if DifficultyAlgorithm::requires_difficulty(data_type) {
+            difficulty.max(self.required_difficulty())
+        } else {
+            difficulty
+        }
+    }
 
-        // When
-        let result = chain.add_new_block(Block::new(
-            chain.blocks.len(),
-            previous_block.hash.clone(),
-            BlockData::new(
-                BlockDataType::ValidatorData,
-                None,
-                Some(ValidatorData::new("public_key".to_string(), "account_id".to_string())),
-            ),
-            &mut Wallet::default(),
+    /// Add block produced by local validator, followed by the reward block crediting local validator. Both blocks are
+    /// queued for publishing, and local agents which qualified by the block are registered.
+    fn add_produced_block(&mut self, block: Block) -> Result<(), ChainError> {
+        let block_id = block.id;
+
+        self.add_outbound_block(block)?;
+
+        let reward = RewardData::new(self.signer.get_public_key(), block_id, Self::BLOCK_REWARD);
+        let hash_algorithm = self.hash_algorithm();
+        let block = Block::new(
+            self.blocks.len(),
+            self.blocks.last().unwrap().hash.clone(),
+            BlockData::reward(reward),
+            self.signer.as_mut(),
             0,
-        ));
+            hash_algorithm,
+        )?;
 
-        // Then
-        assert!(result.is_ok());
-        assert_eq!(chain.blocks.len(), 2);
-        assert_block(
-            chain.blocks.last().unwrap(),
-            None,
-            Some(ValidatorData::new("public_key".to_string(), "account_id".to_string())),
-        );
+        self.add_outbound_block(block)?;
+
+        self.register_local_agents()
+    }
+
+    fn add_outbound_block(&mut self, block: Block) -> Result<(), ChainError> {
+        self.add_new_block(block.clone())?;
+
+        if self.outbound.len() == Self::MAX_OUTBOUND_BLOCKS {
+            self.outbound.pop_front();
+        }
+        self.outbound.push_back(block);
+
+        Ok(())
+    }
+
+    /// Replace local chain with longer valid chain. Whole chain is validated before anything is changed - links, hashes
+    /// and signatures in parallel, and then each block against the state built by blocks before it. Agents, epochs and
+    /// secret attributes are then taken from the state built from the new chain, unpublished blocks of the abandoned fork
+    /// are dropped, and only blocks after the divergence point are archived.
+    pub fn replace_chain(&mut self, chain: &[Block]) -> Result<(), ChainError> {
+        self.validate_chain(chain)?;
+        self.finality.validate_fork(chain)?;
+
+        if chain.len() <= self.blocks.len() {
+            return Err(ChainError::ChainSizeIsNotLongerThanLocalChain);
+        }
+
+        let rebuilt = self.rebuild(chain, Self::validate_block_state).map_err(|(_, error)| error)?;
+
+        // Blocks of local chain after the divergence point are rolled back from the graph on its next replay
+        let divergence = self.blocks.iter().zip(chain).take_while(|(local, block)| local == block).count();
+        self.abandoned = self.blocks.split_off(divergence);
+        self.blocks = chain.to_vec();
+        self.agent_service = rebuilt.agent_service;
+        self.epochs = rebuilt.epochs;
+        self.secret_attributes = rebuilt.secret_attributes;
+
+        // Blocks produced on the abandoned fork can't be published, while queued and pending changes aren't linked to
+        // any block, so they are added on top of the new chain. Queued registrations of local agents are dropped, as
+        // agents are registered again from accounts of the new chain.
+        self.outbound.retain(|block| block.id < divergence);
+        self.queued.retain(|(data, _)| data.validator_data.is_none());
+
+        for block in &chain[divergence..] {
+            if let Some(Err(error)) = self.archive.as_mut().map(|archive| archive.index(block)) {
+                eprintln!("Chain error: {error}");
+            }
+        }
+
+        if let Some(webhooks) = &mut self.webhooks {
+            let chain_id = chain[0]
+                .data
+                .genesis_data
+                .as_ref()
+                .map(|genesis| genesis.chain_id.as_str())
+                .unwrap_or_default();
+            webhooks.chain_replaced(chain_id, chain, divergence, self.abandoned.len());
+        }
+
+        Ok(())
+    }
+
+    /// Append blocks missing in local chain (e.g. after node was offline), instead of replacing whole chain. Segment
+    /// can overlap with local chain, but it must continue it. Each new block is validated the same way as block
+    /// received from peer, and valid blocks before the first invalid one are kept.
+    pub fn append_segment(&mut self, segment: &[Block]) -> Result<(), ChainError> {
+        for block in segment {
+            match self.blocks.get(block.id) {
+                Some(local_block) if local_block == block => continue,
+                Some(_) => return Err(ChainError::BlockIsNotNextBlockInSequence(block.id)),
+                None => {
+                    self.validate_block(block, self.blocks.last().unwrap())?;
+                    self.apply_block(block);
+                    self.notify_block(block);
+                    self.blocks.push(block.clone());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Id of the first block of given segment which differs from the local chain, if segment continues the local chain
+    /// at its start (so local chain can be replaced from that block).
+    pub fn fork_point(&self, segment: &[Block]) -> Option<usize> {
+        let first = segment.first()?;
+        let previous = self.blocks.get(first.id.checked_sub(1)?)?;
+        if previous.hash != first.previous_hash {
+            return None;
+        }
+
+        let point = segment.iter().find(|block| self.blocks.get(block.id) != Some(*block));
+        Some(point.map_or(first.id + segment.len(), |block| block.id))
+    }
+
+    /// Validate headers which follow the local chain without their data - headers must be linked, in sequence and
+    /// signed by their validators. Hash, stake and data of each block are validated once its data is received.
+    pub fn validate_headers(&self, headers: &[BlockHeader]) -> Result<(), ChainError> {
+        let Some(first) = headers.first() else {
+            return Ok(());
+        };
+
+        let mut previous_header = match first.id.checked_sub(1).and_then(|id| self.blocks.get(id)) {
+            Some(block) => block.header(),
+            None => return Err(ChainError::BlockIsNotNextBlockInSequence(first.id)),
+        };
+
+        for header in headers {
+            if header.previous_hash != previous_header.hash {
+                return Err(ChainError::BlockHasWrongPreviousHashValue(header.id));
+            }
+
+            if header.id != previous_header.id + 1 {
+                return Err(ChainError::BlockIsNotNextBlockInSequence(header.id));
+            }
+
+            Self::validate_signature(header.id, &header.validator, &header.signature, &header.hash)?;
+            previous_header = header.clone();
+        }
+
+        Ok(())
+    }
+
+    pub fn add_new_block(&mut self, block: Block) -> Result<(), ChainError> {
+        let previous_block = self.blocks.last().unwrap();
+
+        if let Err(error) = self.validate_block(&block, previous_block) {
+            self.slash(block)?;
+
+            return Err(error);
+        }
+
+        self.apply_block(&block);
+        self.notify_block(&block);
+        self.blocks.push(block);
+
+        Ok(())
+    }
+
+    /// Queue webhook payloads of block appended to the chain.
+    fn notify_block(&mut self, block: &Block) {
+        let genesis = self.blocks[0].data.genesis_data.as_ref();
+
+        if let Some(webhooks) = &mut self.webhooks {
+            webhooks.block_accepted(genesis.map(|genesis| genesis.chain_id.as_str()).unwrap_or_default(), block);
+        }
+    }
+
+    /// Vote of local validator for the latest checkpoint of the chain, which is created (and counted) once the
+    /// checkpoint block is added, if local validator has stake in its epoch.
+    /// Local validator with stake didn't vote for the latest checkpoint yet, checked before chain is locked for
+    /// writing.
+    pub fn is_vote_due(&self) -> bool {
+        !self.mode.is_observer()
+            && self
+                .finality
+                .next_vote(&self.blocks)
+                .is_some_and(|block| self.epochs.validator_set(block.id).get_stake(&self.signer.get_public_key()) > 0)
+    }
+
+    pub fn checkpoint_vote(&mut self) -> Result<Option<CheckpointVote>, ChainError> {
+        if self.mode.is_observer() {
+            return Ok(None);
+        }
+
+        let Some(block) = self.finality.next_vote(&self.blocks) else {
+            return Ok(self.finality.local_vote().cloned());
+        };
+
+        let validator = self.signer.get_public_key();
+        let validator_set = self.epochs.validator_set(block.id);
+        if validator_set.get_stake(&validator) == 0 {
+            return Ok(self.finality.local_vote().cloned());
+        }
+
+        let signature = self.signer.sign(&CheckpointVote::message(block.id, &block.hash))?;
+        let vote = CheckpointVote::new(block.id, block.hash.clone(), validator, signature);
+        self.finality.set_local_vote(vote.clone());
+        self.add_checkpoint_vote(&vote)?;
+
+        Ok(Some(vote))
+    }
+
+    /// Count vote of validator for checkpoint of the local chain, returns false if vote was already counted or it can't
+    /// be counted (e.g. its checkpoint is not in the local chain). Vote must be signed by validator with stake in the
+    /// epoch of its checkpoint.
+    pub fn add_checkpoint_vote(&mut self, vote: &CheckpointVote) -> Result<bool, ChainError> {
+        let error = ChainError::CheckpointVoteIsNotValid(vote.block_id);
+        if !self.finality.is_checkpoint(vote.block_id) {
+            return Err(error);
+        }
+
+        // Vote for checkpoint which isn't synced yet, or which is on other fork
+        if self.blocks.get(vote.block_id).is_none_or(|block| block.hash != vote.hash) {
+            return Ok(false);
+        }
+
+        let message = CheckpointVote::message(vote.block_id, &vote.hash);
+        Self::validate_signature(vote.block_id, &vote.validator, &vote.signature, &message)
+            .map_err(|_| ChainError::CheckpointVoteSignatureIsNotValid(vote.block_id))?;
+
+        let validator_set = self.epochs.validator_set(vote.block_id);
+        let stake = validator_set.get_stake(&vote.validator);
+        if stake == 0 {
+            return Err(error);
+        }
+
+        Ok(self.finality.add_vote(vote, stake, validator_set.total_stake))
+    }
+
+    /// Apply changes of accepted block to validators, their agents (with agent definitions, node and edge changes) and
+    /// secret attributes of definitions. Agents are applied the same way for local blocks and blocks received from
+    /// peers.
+    fn apply_block(&mut self, block: &Block) {
+        if let Some(SchemaData {
+            name,
+            conditions: Some(conditions),
+            ..
+        }) = &block.data.schema_data
+        {
+            self.agent_service.define_agent(name.clone(), conditions.clone());
+        }
+
+        for node in block.data.nodes() {
+            self.agent_service.apply_node(node);
+        }
+
+        for edge in block.data.edge_data.iter().chain(block.data.edge_batch.iter().flatten()) {
+            self.agent_service.apply_edge(edge);
+        }
+
+        if let Some(validator) = &block.data.validator_data {
+            self.agent_service.registered.insert(validator.account_id.clone());
+        }
+
+        if let Some(Err(error)) = block
+            .data
+            .delegation_data
+            .as_ref()
+            .map(|delegation| self.agent_service.delegate(&delegation.node_id, &delegation.agent_id))
+        {
+            eprintln!("Chain error: {error}");
+        }
+
+        if let Some(schema) = block.data.schema_data.as_ref().filter(|schema| !schema.secret_attributes.is_empty()) {
+            self.secret_attributes.insert(schema.name.clone(), schema.secret_attributes.clone());
+        }
+
+        if let Some(slash) = &block.data.slash_data {
+            self.agent_service.slash(slash.public_key.clone());
+        }
+
+        if let Some(rotation) = &block.data.key_rotation_data {
+            self.agent_service.rotate_key(&rotation.old_public_key, &rotation.new_public_key);
+        }
+
+        self.epochs.snapshot(block, &self.agent_service);
+
+        if let Some(Err(error)) = self.archive.as_mut().map(|archive| archive.index(block)) {
+            eprintln!("Chain error: {error}");
+        }
+    }
+
+    /// Replace signing key of local validator with new one. Key rotation block is signed by old key and published
+    /// to all peers, so validator keeps its accounts and stake.
+    pub fn rotate_key(&mut self) -> GraphResults {
+        let chain_error = DatabaseError::ChainError;
+
+        let mut wallet = self.signer.rotate().map_err(chain_error)?;
+        let old_public_key = self.signer.get_public_key();
+        let new_public_key = wallet.get_public_key();
+
+        let signature = wallet.sign(&old_public_key).map_err(chain_error)?;
+
+        let rotation = KeyRotationData::new(old_public_key.clone(), new_public_key.clone(), signature);
+        self.add_local_block(BlockData::key_rotation(rotation), 0).map_err(chain_error)?;
+        self.signer = wallet;
+        self.signer.store().map_err(chain_error)?;
+
+        let mut result = FxHashMap::default();
+        result.insert("old_public_key".to_string(), old_public_key);
+        result.insert("new_public_key".to_string(), new_public_key);
+
+        Ok(vec![result])
+    }
+
+    /// Record offense of the validator which signed provably invalid block, by adding slash block (with invalid block
+    /// as evidence) which is then published to all peers. Nothing is recorded for other invalid blocks, or by observer.
+    fn slash(&mut self, block: Block) -> Result<(), ChainError> {
+        if self.mode.is_observer() || !self.is_provably_invalid(&block) || self.agent_service.slashed.contains(&block.validator) {
+            return Ok(());
+        }
+
+        let slash = SlashData::new(block.validator.clone(), Box::new(block));
+
+        self.add_local_block(BlockData::slash(slash), 0)
+    }
+
+    /// Block is provably invalid if it has valid signature of its validator, but its hash doesn't match its content.
+    /// Blocks with invalid signature can't be attributed to the validator, as anyone can use validator's public key.
+    fn is_provably_invalid(&self, block: &Block) -> bool {
+        // Block of newer format can't be validated, so it isn't considered as evidence
+        block.version <= Block::FORMAT_VERSION
+            && Block::validate_block_hash(block, self.hash_algorithm()).is_err()
+            && Self::validate_signature(block.id, &block.validator, &block.signature, &block.hash).is_ok()
+    }
+
+    fn validate_block(&self, block: &Block, previous_block: &Block) -> Result<(), ChainError> {
+        if block.previous_hash != previous_block.hash {
+            return Err(ChainError::BlockHasWrongPreviousHashValue(block.id));
+        }
+
+        if block.id != previous_block.id + 1 {
+            return Err(ChainError::BlockIsNotNextBlockInSequence(block.id));
+        }
+
+        Block::validate_format_version(block, previous_block)?;
+        Block::validate_block_hash(block, self.hash_algorithm())?;
+        Self::validate_signature(block.id, &block.validator, &block.signature, &block.hash)?;
+        self.clock.validate(block, previous_block)?;
+        self.validate_block_state(block, previous_block)
+    }
+
+    /// Validate block against state of the chain before it - stake of its validator in the epoch, schedule, difficulty
+    /// and its data. Links, hashes and signatures are validated separately, as they don't depend on the state.
+    fn validate_block_state(&self, block: &Block, previous_block: &Block) -> Result<(), ChainError> {
+        self.validate_stake(block.id, &block.validator, block.difficulty)?;
+        if self.consensus.is_proof_of_work() {
+            self.consensus.validate_work(block)?;
+        } else {
+            self.validate_schedule(block, previous_block)?;
+            self.validate_difficulty(block)?;
+        }
+
+        match block.data.data_type {
+            BlockDataType::EdgeBatch => Self::validate_batch(block)?,
+            BlockDataType::ValidatorData => Self::validate_validator(block)?,
+            BlockDataType::Reward => self.validate_reward(block)?,
+            BlockDataType::SlashData => self.validate_slash(block)?,
+            BlockDataType::KeyRotation => self.validate_key_rotation(block)?,
+            BlockDataType::NodeData => Self::validate_node(block)?,
+            BlockDataType::NodeBatch => Self::validate_node_batch(block)?,
+            BlockDataType::SchemaData => self.validate_schema(block)?,
+            BlockDataType::Delegation => self.validate_delegation(block)?,
+            BlockDataType::Audit => Self::validate_audit(block)?,
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Blocks of the chain are validated in parallel, and signatures are verified in batches. Error of the first
+    /// invalid block is returned, in the same order of validations as if blocks were validated one by one.
+    fn validate_chain(&self, chain: &[Block]) -> Result<(), ChainError> {
+        if chain.first() != self.blocks.first() {
+            return Err(ChainError::ChainHasInvalidGenesisBlock);
+        }
+
+        // Chain holds signer which can't be shared between threads, so only its settings are used
+        let (clock, consensus, hash_algorithm) = (&self.clock, &self.consensus, self.hash_algorithm());
+        let first_error = |validate: &(dyn Fn(&Block, &Block) -> Result<(), ChainError> + Sync)| {
+            chain
+                .par_windows(2)
+                .enumerate()
+                .find_map_first(|(index, pair)| validate(&pair[1], &pair[0]).err().map(|error| (index, error)))
+        };
+
+        let link_error = first_error(&|block, previous_block| {
+            if previous_block.hash != block.previous_hash {
+                return Err(ChainError::BlockHasWrongPreviousHashValue(block.id));
+            } else if previous_block.id + 1 != block.id {
+                return Err(ChainError::BlockIsNotNextBlockInSequence(block.id));
+            }
+
+            Block::validate_format_version(block, previous_block)?;
+            Block::validate_block_hash(block, hash_algorithm)
+        });
+        let signature_error = Self::validate_signatures(&chain[1..]);
+        let timestamp_error = first_error(&|block, previous_block| {
+            clock.validate(block, previous_block)?;
+            consensus.validate_work(block)
+        });
+
+        // Errors of the same block are ordered by validation
+        [link_error, signature_error, timestamp_error]
+            .into_iter()
+            .enumerate()
+            .filter_map(|(order, error)| error.map(|(index, error)| ((index, order), error)))
+            .min_by_key(|(position, _)| *position)
+            .map_or(Ok(()), |(_, error)| Err(error))
+    }
+
+    /// Verify signatures of given blocks in parallel batches, and return index and error of the first block with
+    /// invalid signature. If batch is not valid (or it contains keys of other schemes than ed25519), its blocks are
+    /// verified one by one to find the invalid one.
+    fn validate_signatures(blocks: &[Block]) -> Option<(usize, ChainError)> {
+        blocks
+            .par_chunks(Self::SIGNATURE_BATCH_SIZE)
+            .enumerate()
+            .find_map_first(|(chunk_index, chunk)| {
+                let parsed: Result<(Vec<VerifyingKey>, Vec<Signature>), ChainError> = chunk
+                    .iter()
+                    .map(|block| Self::parse_signature(block.id, &block.validator, &block.signature))
+                    .collect();
+                let messages: Vec<&[u8]> = chunk.iter().map(|block| block.hash.as_bytes()).collect();
+
+                if parsed.is_ok_and(|(keys, signatures)| ed25519_dalek::verify_batch(&messages, &signatures, &keys).is_ok()) {
+                    return None;
+                }
+
+                chunk.iter().enumerate().find_map(|(index, block)| {
+                    Self::validate_signature(block.id, &block.validator, &block.signature, &block.hash)
+                        .err()
+                        .map(|error| (chunk_index * Self::SIGNATURE_BATCH_SIZE + index, error))
+                })
+            })
+    }
+
+    /// Verify signature by the scheme of validator key.
+    fn validate_signature(id: usize, validator: &str, signature: &str, hash: &str) -> Result<(), ChainError> {
+        SignatureAlgorithm::verify(validator, hash.as_bytes(), signature).map_err(|error| match error {
+            SignatureError::InvalidPublicKey => ChainError::BlockHasWrongValidatorValue(id),
+            SignatureError::InvalidSignature => ChainError::BlockHasWrongSignatureValue(id),
+        })
+    }
+
+    /// Parse ed25519 key and signature, which can be verified in batch.
+    fn parse_signature(id: usize, validator: &String, signature: &str) -> Result<(VerifyingKey, Signature), ChainError> {
+        let public_key = VerifyingKey::from_bytes(
+            hex::decode(validator)
+                .map_err(|_| ChainError::BlockHasWrongValidatorValue(id))?
+                .as_slice()
+                .try_into()
+                .map_err(|_| ChainError::BlockHasWrongValidatorValue(id))?,
+        )
+        .map_err(|_| ChainError::BlockHasWrongValidatorValue(id))?;
+        let signature = Signature::from_str(signature).map_err(|_| ChainError::BlockHasWrongSignatureValue(id))?;
+
+        Ok((public_key, signature))
+    }
+
+    /// Difficulty of the block can't exceed stake of its validator at the start of the epoch of the block.
+    fn validate_stake(&self, id: usize, validator: &String, difficulty: usize) -> Result<(), ChainError> {
+        let validator_set = self.epochs.validator_set(id);
+
+        if validator_set.get_stake(validator) < difficulty {
+            return Err(ChainError::BlockHasWrongDifficultyValue(id));
+        }
+
+        Ok(())
+    }
+
+    /// Block must be proposed by its primary validator, or by other validator after timeout. Reward, slash and key
+    /// rotation blocks are validated by their own rules.
+    fn validate_schedule(&self, block: &Block, previous_block: &Block) -> Result<(), ChainError> {
+        if !DifficultyAlgorithm::requires_difficulty(&block.data.data_type) {
+            return Ok(());
+        }
+
+        let validator_set = self.epochs.validator_set(block.id);
+        if !self
+            .schedule
+            .is_scheduled(&validator_set, previous_block, &block.validator, block.timestamp)
+        {
+            return Err(ChainError::BlockProposerIsNotScheduled(block.id));
+        }
+
+        Ok(())
+    }
+
+    fn validate_difficulty(&self, block: &Block) -> Result<(), ChainError> {
+        if DifficultyAlgorithm::requires_difficulty(&block.data.data_type) && block.difficulty < self.required_difficulty() {
+            return Err(ChainError::BlockHasWrongDifficultyValue(block.id));
+        }
+
+        Ok(())
+    }
+
+    /// Id of the chain given by its genesis block.
+    pub fn chain_id(&self) -> Option<&str> {
+        self.blocks[0].data.genesis_data.as_ref().map(|genesis| genesis.chain_id.as_str())
+    }
+
+    /// Hash algorithm of the chain, given by configuration of its genesis block.
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.blocks[0]
+            .data
+            .genesis_data
+            .as_ref()
+            .map_or(HashAlgorithm::default(), |genesis| genesis.hash_algorithm)
+    }
+
+    /// Difficulty required for the next block, recalculated for each block by configured algorithm from stakes at the
+    /// start of the epoch.
+    pub fn required_difficulty(&self) -> usize {
+        let validator_set = self.epochs.validator_set(self.blocks.len());
+
+        self.difficulty
+            .required_difficulty(&self.blocks, validator_set.total_stake, validator_set.max_stake())
+    }
+
+    /// Batch must contain at least one and at most maximum batch size changes, and no other data.
+    fn validate_batch(block: &Block) -> Result<(), ChainError> {
+        let is_valid = block.data.edge_data.is_none()
+            && block.data.validator_data.is_none()
+            && block
+                .data
+                .edge_batch
+                .as_ref()
+                .is_some_and(|changes| (1..=Mempool::MAX_BATCH_SIZE).contains(&changes.len()));
+
+        if !is_valid {
+            return Err(ChainError::BlockHasWrongDataValue(block.id));
+        }
+
+        Ok(())
+    }
+
+    /// Signature scheme of validator registration can't differ from scheme of its public key.
+    fn validate_validator(block: &Block) -> Result<(), ChainError> {
+        let is_valid = block
+            .data
+            .validator_data
+            .as_ref()
+            .is_some_and(|validator| SignatureAlgorithm::of_public_key(&validator.public_key).is_none_or(|scheme| scheme == validator.scheme));
+
+        if !is_valid {
+            return Err(ChainError::BlockHasWrongDataValue(block.id));
+        }
+
+        Ok(())
+    }
+
+    /// Node change must identify its node, and deleted (or soft deleted) node can't have attributes.
+    fn validate_node(block: &Block) -> Result<(), ChainError> {
+        if !block.data.node_data.as_ref().is_some_and(Self::is_valid_node) {
+            return Err(ChainError::BlockHasWrongDataValue(block.id));
+        }
+
+        Ok(())
+    }
+
+    fn validate_audit(block: &Block) -> Result<(), ChainError> {
+        if block.data.audit_data.as_ref().is_none_or(|audit| audit.hash.is_empty()) {
+            return Err(ChainError::BlockHasWrongDataValue(block.id));
+        }
+
+        Ok(())
+    }
+
+    /// Node batch must contain at least one and at most maximum batch size valid node changes, and no single change.
+    fn validate_node_batch(block: &Block) -> Result<(), ChainError> {
+        let is_valid = block.data.node_data.is_none()
+            && block
+                .data
+                .node_batch
+                .as_ref()
+                .is_some_and(|changes| (1..=Mempool::MAX_BATCH_SIZE).contains(&changes.len()) && changes.iter().all(Self::is_valid_node));
+
+        if !is_valid {
+            return Err(ChainError::BlockHasWrongDataValue(block.id));
+        }
+
+        Ok(())
+    }
+
+    fn is_valid_node(node: &NodeData) -> bool {
+        !node.name.is_empty()
+            && !node.id.is_empty()
+            && (!matches!(node.operation, NodeOperation::Delete | NodeOperation::SoftDelete) || node.attributes.is_empty())
+    }
+
+    /// Delegation must be made by node which is not an agent, to registered agent.
+    fn validate_delegation(&self, block: &Block) -> Result<(), ChainError> {
+        let error = ChainError::BlockHasWrongDataValue(block.id);
+        let delegation = block.data.delegation_data.as_ref().ok_or(error.clone())?;
+
+        self.agent_service
+            .validate_delegation(&delegation.node_id, &delegation.agent_id)
+            .map_err(|_| error)
+    }
+
+    /// Definition must have name, and node with the same name can't be defined by earlier block.
+    fn validate_schema(&self, block: &Block) -> Result<(), ChainError> {
+        let error = ChainError::BlockHasWrongDataValue(block.id);
+        let schema = block.data.schema_data.as_ref().ok_or(error.clone())?;
+
+        let is_defined = self
+            .blocks
+            .iter()
+            .filter_map(|block| block.data.schema_data.as_ref())
+            .any(|existing| existing.name == schema.name);
+
+        if schema.name.is_empty() || is_defined {
+            return Err(error);
+        }
+
+        Ok(())
+    }
+
+    /// Reward is valid if it credits the validator of already accepted (non reward) block, is signed by that same
+    /// validator, has the fixed amount and the block was not rewarded before.
+    fn validate_reward(&self, block: &Block) -> Result<(), ChainError> {
+        let error = ChainError::BlockHasWrongRewardValue(block.id);
+        let reward = block.data.reward_data.as_ref().ok_or(error.clone())?;
+        let rewarded_block = self.blocks.get(reward.block_id).ok_or(error.clone())?;
+
+        let is_rewarded = self.blocks[reward.block_id..]
+            .iter()
+            .filter_map(|block| block.data.reward_data.as_ref())
+            .any(|existing| existing.block_id == reward.block_id);
+
+        if is_rewarded
+            || matches!(rewarded_block.data.data_type, BlockDataType::Reward | BlockDataType::RootNode)
+            || rewarded_block.validator != reward.public_key
+            || block.validator != reward.public_key
+            || reward.amount != Self::BLOCK_REWARD
+        {
+            return Err(error);
+        }
+
+        Ok(())
+    }
+
+    /// Slash is valid if its evidence is provably invalid block signed by slashed validator, which wasn't slashed
+    /// before.
+    fn validate_slash(&self, block: &Block) -> Result<(), ChainError> {
+        let error = ChainError::BlockHasWrongSlashValue(block.id);
+        let slash = block.data.slash_data.as_ref().ok_or(error.clone())?;
+
+        if slash.evidence.validator != slash.public_key
+            || !self.is_provably_invalid(&slash.evidence)
+            || self.agent_service.slashed.contains(&slash.public_key)
+        {
+            return Err(error);
+        }
+
+        Ok(())
+    }
+
+    /// Key rotation is valid if it is signed by old key, new key signed old public key, and old key was not slashed.
+    fn validate_key_rotation(&self, block: &Block) -> Result<(), ChainError> {
+        let error = ChainError::BlockHasWrongKeyRotationValue(block.id);
+        let rotation = block.data.key_rotation_data.as_ref().ok_or(error.clone())?;
+
+        if block.validator != rotation.old_public_key
+            || rotation.old_public_key == rotation.new_public_key
+            || self.agent_service.slashed.contains(&rotation.old_public_key)
+            || self.agent_service.rotations.contains_key(&rotation.old_public_key)
+        {
+            return Err(error);
+        }
+
+        Self::validate_signature(block.id, &rotation.new_public_key, &rotation.new_key_signature, &rotation.old_public_key).map_err(|_| error)
+    }
+
+    /// Validate the whole local chain again, block by block from genesis, the same way as blocks received from peers.
+    /// Slashes and key rotations are applied as blocks are validated, so each block is validated with state of the
+    /// validators at its time. Result contains the first invalid block with its error, if there is one.
+    pub fn verify(&self) -> GraphResults {
+        let mut result = FxHashMap::default();
+
+        match self.verify_blocks() {
+            Ok(_) => {
+                result.insert("valid".to_string(), "true".to_string());
+            }
+            Err((block_id, error)) => {
+                result.insert("valid".to_string(), "false".to_string());
+                result.insert("block".to_string(), block_id.to_string());
+                result.insert("code".to_string(), error.code().to_string());
+                result.insert("error".to_string(), error.to_string());
+            }
+        }
+        result.insert("blocks".to_string(), self.blocks.len().to_string());
+
+        Ok(vec![result])
+    }
+
+    fn verify_blocks(&self) -> Result<(), (usize, ChainError)> {
+        self.rebuild(&self.blocks, Self::validate_block).map(|_| ())
+    }
+
+    /// Validate given blocks one by one from genesis block with given validation, and return chain with state (agents,
+    /// epochs and secret attributes) built from a clean state by applying only these blocks. Chain has the same
+    /// settings as this chain, but no signer, archive or other side effects. Error contains id of the first invalid
+    /// block.
+    fn rebuild(&self, blocks: &[Block], validate: fn(&Chain, &Block, &Block) -> Result<(), ChainError>) -> Result<Chain, (usize, ChainError)> {
+        let genesis = &blocks[0];
+        let Some(genesis_data) = genesis.data.genesis_data.as_ref().filter(|data| Block::genesis(data) == *genesis) else {
+            return Err((0, ChainError::ChainHasInvalidGenesisBlock));
+        };
+
+        let mut verifier = Chain {
+            consensus: self.consensus.clone(),
+            difficulty: self.difficulty.clone(),
+            clock: self.clock.clone(),
+            schedule: self.schedule.clone(),
+            ..Chain::new(genesis_data)
+        };
+
+        for block in &blocks[1..] {
+            validate(&verifier, block, verifier.blocks.last().unwrap()).map_err(|error| (block.id, error))?;
+            verifier.apply_block(block);
+            verifier.blocks.push(block.clone());
+        }
+
+        Ok(verifier)
+    }
+
+    /// Balances of validators (by public key) accumulated from reward blocks.
+    pub fn balances(&self) -> FxHashMap<String, u64> {
+        let mut balances = FxHashMap::default();
+
+        // Rewards of rotated keys belong to the latest key of validator
+        for reward in self.blocks.iter().filter_map(|block| block.data.reward_data.as_ref()) {
+            *balances.entry(self.agent_service.resolve_key(&reward.public_key)).or_insert(0) += reward.amount;
+        }
+
+        balances
+    }
+
+    pub fn balances_as_graph_result(&self) -> GraphResults {
+        let mut balances: Vec<(String, u64)> = self.balances().into_iter().collect();
+        balances.sort();
+
+        Ok(balances
+            .into_iter()
+            .map(|(public_key, balance)| {
+                let mut accounts: Vec<&String> = self
+                    .agent_service
+                    .accounts
+                    .iter()
+                    .filter(|(_, account)| account.public_key == public_key)
+                    .map(|(identifier, _)| identifier)
+                    .collect();
+                accounts.sort();
+
+                let mut result = FxHashMap::default();
+                result.insert("public_key".to_string(), public_key);
+                result.insert("balance".to_string(), balance.to_string());
+                result.insert(
+                    "accounts".to_string(),
+                    accounts.iter().map(|account| account.as_str()).collect::<Vec<&str>>().join(","),
+                );
+                result
+            })
+            .collect())
+    }
+
+    /// Blocks produced by each validator (latest key of the validator), with their total difficulty, timestamps of the
+    /// first and the last block and current stake of the validator. Reward blocks are not counted.
+    pub fn validators_as_graph_result(&self) -> GraphResults {
+        // Number of blocks, total difficulty, first and last timestamp by public key
+        let mut validators: BTreeMap<String, (usize, usize, u64, u64)> = BTreeMap::new();
+
+        for block in self
+            .blocks
+            .iter()
+            .filter(|block| !block.validator.is_empty() && block.data.data_type != BlockDataType::Reward)
+        {
+            let (blocks, difficulty, _, last_timestamp) =
+                validators
+                    .entry(self.agent_service.resolve_key(&block.validator))
+                    .or_insert((0, 0, block.timestamp, 0));
+
+            *blocks += 1;
+            *difficulty += block.difficulty;
+            *last_timestamp = block.timestamp;
+        }
+
+        Ok(validators
+            .into_iter()
+            .map(|(public_key, (blocks, difficulty, first_timestamp, last_timestamp))| {
+                let mut result = FxHashMap::default();
+                result.insert("stake".to_string(), self.agent_service.get_validator_difficulty(&public_key).to_string());
+                result.insert("public_key".to_string(), public_key);
+                result.insert("blocks".to_string(), blocks.to_string());
+                result.insert("difficulty".to_string(), difficulty.to_string());
+                result.insert("first_timestamp".to_string(), first_timestamp.to_string());
+                result.insert("last_timestamp".to_string(), last_timestamp.to_string());
+                result
+            })
+            .collect())
+    }
+
+    /// Node with its outgoing edges as they were after block with given height was added to the chain. In archive mode,
+    /// only blocks which changed the node or its edges are read.
+    pub fn node_at(&self, name: String, identifier: String, height: usize) -> GraphResults {
+        if height >= self.blocks.len() {
+            return Err(DatabaseError::ChainError(ChainError::BlockNotFound(height.to_string())));
+        }
+
+        match &self.archive {
+            Some(archive) => archive.node_at(&self.blocks, name, identifier, height),
+            None => self.history.node_at(&self.blocks, name, identifier, height),
+        }
+    }
+
+    /// Weights of the edge between given nodes (name and identifier) through the whole chain. In archive mode, only
+    /// blocks which changed the edge are read.
+    pub fn edge_history(&self, from: (String, String), to: (String, String)) -> GraphResults {
+        match &self.archive {
+            // Archive can have heights of blocks which are not in the chain (e.g. after restart), so they are skipped
+            Some(archive) => History::edge_history(
+                archive.edge_heights(&from.1, &to.1).iter().filter_map(|height| self.blocks.get(*height)),
+                from,
+                to,
+            ),
+            None => History::edge_history(&self.blocks, from, to),
+        }
+    }
+
+    /// Block with given hash or id, with its data decoded.
+    pub fn block_as_graph_result(&self, hash_or_id: &str) -> GraphResults {
+        let block = self
+            .blocks
+            .iter()
+            .find(|block| block.hash == hash_or_id)
+            .or_else(|| hash_or_id.parse().ok().and_then(|id: usize| self.blocks.get(id)))
+            .ok_or(DatabaseError::ChainError(ChainError::BlockNotFound(hash_or_id.to_string())))?;
+
+        Ok(vec![block.as_decoded_hash_map()])
+    }
+
+    /// Blocks from the first to the last height (both inclusive) with their data decoded. Last height is limited to the
+    /// end of the chain.
+    pub fn blocks_as_graph_result(&self, from: usize, to: usize) -> GraphResults {
+        Ok(self.blocks[self.blocks_range(from, to)?].iter().map(Block::as_decoded_hash_map).collect())
+    }
+
+    /// Heights of blocks from the first to the last height (both inclusive), with last height limited to the end of the
+    /// chain.
+    pub fn blocks_range(&self, from: usize, to: usize) -> Result<Range<usize>, DatabaseError> {
+        if from >= self.blocks.len() {
+            return Err(DatabaseError::ChainError(ChainError::BlockNotFound(from.to_string())));
+        }
+        if from > to {
+            return Err(DatabaseError::InvalidQuery(format!("block height {from} is greater than {to}")));
+        }
+
+        Ok(from..to.min(self.blocks.len() - 1) + 1)
+    }
+
+    pub fn as_graph_result(&self) -> GraphResults {
+        Ok(self.blocks.iter().map(|block| block.as_hash_map()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::agent::Account;
+    use crate::chain::genesis::GenesisValidator;
+    use crate::graph::condition::{Condition, Operator};
+    use crate::graph::tombstone::Tombstones;
+    use proptest::prelude::*;
+
+    #[test]
+    fn should_register_local_agent() {
+        // Given
+        let mut chain = Chain::default();
+        let schema = SchemaData::new("User".to_string(), vec!["key".to_string()], Some(vec![]), false, vec![]);
+        chain.add_schema_change(schema).unwrap();
+
+        let mut attributes = BTreeMap::new();
+        attributes.insert("key".to_string(), chain.signer.get_public_key());
+
+        // When
+        let result = chain.add_node_change(NodeData::new(
+            NodeOperation::Add,
+            "User".to_string(),
+            "identifier".to_string(),
+            attributes,
+        ));
+
+        // Then
+        assert!(result.is_ok());
+        assert_eq!(chain.agent_service.agents.len(), 1);
+        assert!(chain.agent_service.registered.contains("identifier"));
+        assert!(chain.blocks.iter().any(|block| block
+            .data
+            .validator_data
+            .as_ref()
+            .is_some_and(|validator| validator.account_id == "identifier")));
+    }
+
+    #[test]
+    fn should_register_agent_of_received_node() {
+        // Given
+        let mut chain = Chain::default();
+        let mut peer = Chain::default();
+        let schema = SchemaData::new("User".to_string(), vec!["key".to_string()], Some(vec![]), false, vec![]);
+        peer.add_schema_change(schema).unwrap();
+
+        let mut attributes = BTreeMap::new();
+        attributes.insert("key".to_string(), peer.signer.get_public_key());
+        peer.add_node_change(NodeData::new(
+            NodeOperation::Add,
+            "User".to_string(),
+            "identifier".to_string(),
+            attributes,
+        ))
+        .unwrap();
+
+        // When
+        let result = chain.append_segment(&peer.blocks);
+
+        // Then
+        assert!(result.is_ok());
+        assert_eq!(
+            chain.agent_service.accounts.get("identifier").unwrap().public_key,
+            peer.signer.get_public_key()
+        );
+        assert!(chain.agent_service.registered.contains("identifier"));
+    }
+
+    #[test]
+    fn should_count_delegated_stake_from_delegation_block() {
+        // Given
+        let mut chain = Chain::default();
+        let mut peer = Chain::default();
+        let public_key = chain.signer.get_public_key();
+        insert_agent(&mut chain, &public_key, 0);
+        insert_agent(&mut peer, &public_key, 0);
+        chain.add_edge_change("user".to_string(), "to".to_string(), 1).unwrap();
+
+        // When
+        let result = chain.delegate_stake("User".to_string(), "user".to_string(), "agent".to_string());
+        peer.append_segment(&chain.blocks).unwrap();
+
+        // Then
+        assert_eq!(result.unwrap()[0].get("stake").unwrap(), "1");
+        assert!(chain.blocks[3].data.data_type == BlockDataType::Delegation);
+        assert_eq!(chain.agent_service.get_difficulty(&"agent".to_string()), 1);
+        assert_eq!(peer.agent_service.get_difficulty(&"agent".to_string()), 1);
+    }
+
+    #[test]
+    fn should_add_edge_change() {
+        // Given
+        let mut chain = Chain::default();
+
+        // When
+        let result = chain.add_edge_change("from".to_string(), "to".to_string(), 1);
+
+        // Then
+        assert!(result.is_ok());
+        assert_eq!(chain.blocks.len(), 3);
+        assert_block(&chain.blocks[1], Some(EdgeData::new("from".to_string(), "to".to_string(), 1)), None);
+    }
+
+    #[test]
+    fn should_replay_node_changes() {
+        // Given
+        let mut chain = Chain::default();
+        let mut graph = Graph::default();
+        graph.create_definition("User".to_string(), vec!["name".to_string()]).unwrap();
+
+        let mut attributes = FxHashMap::default();
+        attributes.insert(InternalNodeAttribute::ID_ATTRIBUTE.to_string(), "identifier".to_string());
+        attributes.insert("name".to_string(), "John".to_string());
+        chain
+            .add_node_change(NodeData::from_attributes(NodeOperation::Add, "User".to_string(), &attributes))
+            .unwrap();
+
+        // When
+        chain.replay(&mut graph, 1);
+
+        // Then
+        assert_eq!(chain.blocks.len(), 3);
+        assert!(chain.blocks[1].data.data_type == BlockDataType::NodeData);
+        assert_eq!(graph.nodes["identifier:User"].attributes.get("name").unwrap(), "John");
+    }
+
+    #[test]
+    fn should_replay_soft_deletes_and_restores_at_block_time() {
+        // Given
+        let mut chain = Chain::default();
+        chain
+            .add_schema_change(SchemaData::new("User".to_string(), vec![], None, false, vec![]))
+            .unwrap();
+        for (operation, identifier) in [
+            (NodeOperation::Add, "a"),
+            (NodeOperation::Add, "b"),
+            (NodeOperation::SoftDelete, "a"),
+            (NodeOperation::SoftDelete, "b"),
+            (NodeOperation::Restore, "a"),
+            (NodeOperation::Restore, "b"),
+        ] {
+            chain
+                .add_node_change(NodeData::from_attributes(
+                    operation,
+                    "User".to_string(),
+                    &identifier_attributes(identifier),
+                ))
+                .unwrap();
+        }
+        for block in chain.blocks.iter_mut() {
+            block.timestamp = match block.data.node_data.as_ref().map(|node| (&node.operation, node.id.as_str())) {
+                Some((NodeOperation::SoftDelete, _)) => 1000,
+                Some((NodeOperation::Restore, "a")) => 1060,
+                Some((NodeOperation::Restore, _)) => 1061,
+                _ => block.timestamp,
+            };
+        }
+        let mut graph = Graph {
+            tombstones: Tombstones::new(60),
+            ..Graph::default()
+        };
+
+        // When
+        chain.replay(&mut graph, 1);
+
+        // Then
+        assert!(!graph.nodes["a:User"].is_deleted());
+        assert_eq!(graph.nodes["b:User"].attributes[InternalNodeAttribute::DELETED_ATTRIBUTE], "1000");
+    }
+
+    #[test]
+    fn should_roll_back_abandoned_blocks_after_reorg() {
+        // Given
+        let mut chain = Chain::default();
+        chain
+            .add_schema_change(SchemaData::new("User".to_string(), vec![], None, false, vec![]))
+            .unwrap();
+        for identifier in ["a", "b", "c"] {
+            chain
+                .add_node_change(NodeData::from_attributes(
+                    NodeOperation::Add,
+                    "User".to_string(),
+                    &identifier_attributes(identifier),
+                ))
+                .unwrap();
+        }
+
+        let mut remote_chain = Chain::default();
+        remote_chain.append_segment(&chain.blocks[1..]).unwrap();
+        remote_chain.add_edge_change("a".to_string(), "c".to_string(), 20).unwrap();
+        remote_chain.add_edge_change("b".to_string(), "c".to_string(), 30).unwrap();
+
+        let mut graph = Graph::default();
+        chain.add_edge_change("a".to_string(), "b".to_string(), 50).unwrap();
+        chain.replay(&mut graph, 1);
+        let height = chain.blocks.len();
+
+        // When
+        chain.replace_chain(&remote_chain.blocks).unwrap();
+        chain.replay(&mut graph, height);
+
+        // Then
+        let edges: Vec<(&str, i8)> = graph.nodes["a:User"]
+            .edges
+            .iter()
+            .map(|edge| (edge.to_node_id.as_str(), edge.weight))
+            .collect();
+        assert_eq!(edges, vec![("c", 20)]);
+        assert_eq!(graph.nodes["b:User"].edges.len(), 1);
+        assert!(chain.outbound.iter().all(|block| remote_chain.blocks.contains(block)));
+    }
+
+    #[test]
+    fn should_replay_schema_changes() {
+        // Given
+        let mut remote_chain = Chain::default();
+        let conditions = vec![Condition::new("premium".to_string(), Operator::Equal, "true".to_string())];
+        let schema = SchemaData::new("User".to_string(), vec!["premium".to_string()], Some(conditions.clone()), false, vec![]);
+        remote_chain.add_schema_change(schema.clone()).unwrap();
+
+        let mut chain = Chain::default();
+        let mut graph = Graph::default();
+
+        // When
+        chain.append_segment(&remote_chain.blocks[1..]).unwrap();
+        chain.replay(&mut graph, 1);
+        let duplicated_result = remote_chain.add_schema_change(schema);
+
+        // Then
+        assert_eq!(graph.definitions.get("User").unwrap(), &vec!["premium".to_string()]);
+        assert_eq!(chain.agent_service.agents.get("User").unwrap(), &conditions);
+        assert!(duplicated_result.is_err());
+    }
+
+    #[test]
+    fn should_reward_validator() {
+        // Given
+        let mut chain = Chain::default();
+
+        // When
+        chain.add_edge_change("from".to_string(), "to".to_string(), 1).unwrap();
+        chain.add_edge_change("from".to_string(), "to".to_string(), 2).unwrap();
+
+        // Then
+        let reward = chain.blocks[2].data.reward_data.clone().unwrap();
+        assert_eq!(reward, RewardData::new(chain.signer.get_public_key(), 1, Chain::BLOCK_REWARD));
+        assert_eq!(chain.balances().get(&chain.signer.get_public_key()), Some(&2));
+    }
+
+    #[test]
+    fn should_reject_duplicated_reward() {
+        // Given
+        let mut chain = Chain::default();
+        chain.add_edge_change("from".to_string(), "to".to_string(), 1).unwrap();
+
+        // When
+        let reward = RewardData::new(chain.signer.get_public_key(), 1, Chain::BLOCK_REWARD);
+        let block = Block::new(
+            chain.blocks.len(),
+            chain.blocks.last().unwrap().hash.clone(),
+            BlockData::reward(reward),
+            chain.signer.as_mut(),
+            0,
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
+        let result = chain.add_new_block(block);
+
+        // Then
+        assert!(result.is_err());
+        assert_eq!(chain.blocks.len(), 3);
+    }
+
+    #[test]
+    fn should_replace_chain() {}
+
+    #[test]
+    fn should_rebuild_agents_from_replaced_chain() {
+        // Given
+        let mut chain = Chain::default();
+        let schema = SchemaData::new("User".to_string(), vec!["key".to_string()], Some(vec![]), false, vec![]);
+        chain.add_schema_change(schema).unwrap();
+
+        let mut remote_chain = Chain::default();
+        remote_chain.append_segment(&chain.blocks[1..]).unwrap();
+        for weight in 1..4 {
+            remote_chain.add_edge_change("from".to_string(), "to".to_string(), weight).unwrap();
+        }
+
+        // Agent of abandoned block
+        let mut attributes = BTreeMap::new();
+        attributes.insert("key".to_string(), chain.signer.get_public_key());
+        chain
+            .add_node_change(NodeData::new(NodeOperation::Add, "User".to_string(), "agent".to_string(), attributes))
+            .unwrap();
+
+        // When
+        let result = chain.replace_chain(&remote_chain.blocks);
+
+        // Then
+        assert!(result.is_ok());
+        assert!(chain.agent_service.accounts.is_empty());
+        assert!(chain.agent_service.registered.is_empty());
+        assert_eq!(chain.agent_service.agents.len(), 1);
+        assert_eq!(chain.abandoned.len(), 4);
+    }
+
+    #[test]
+    fn should_append_segment() {
+        // Given
+        let mut remote_chain = Chain::default();
+        remote_chain.add_edge_change("from".to_string(), "to".to_string(), 1).unwrap();
+        remote_chain.add_edge_change("from".to_string(), "to".to_string(), 2).unwrap();
+
+        let mut chain = Chain::default();
+        chain.append_segment(&remote_chain.blocks[1..3]).unwrap();
+
+        let mut forged = remote_chain.blocks[4].clone();
+        forged.hash = "forged".to_string();
+
+        // When
+        let overlapping_result = chain.append_segment(&remote_chain.blocks[2..4]);
+        let local_length = chain.blocks.len();
+        let result = chain.append_segment(&remote_chain.blocks[4..5]);
+        let forged_result = Chain::default().append_segment(&[remote_chain.blocks[1].clone(), forged]);
+
+        // Then
+        assert!(overlapping_result.is_ok());
+        assert_eq!(local_length, 4);
+        assert!(result.is_ok());
+        assert!(chain.blocks == remote_chain.blocks);
+        assert!(forged_result.is_err());
+    }
+
+    #[test]
+    fn should_validate_headers() {
+        // Given
+        let mut remote_chain = Chain::default();
+        remote_chain.add_edge_change("from".to_string(), "to".to_string(), 1).unwrap();
+        let headers: Vec<BlockHeader> = remote_chain.blocks[1..].iter().map(Block::header).collect();
+
+        let mut forged_headers = headers.clone();
+        forged_headers[1].signature = headers[0].signature.clone();
+
+        // When
+        let chain = Chain::default();
+        let result = chain.validate_headers(&headers);
+        let forged_result = chain.validate_headers(&forged_headers);
+        let unlinked_result = chain.validate_headers(&headers[1..]);
+
+        // Then
+        assert!(result.is_ok());
+        assert_eq!(
+            forged_result.err().unwrap().to_string(),
+            ChainError::BlockHasWrongSignatureValue(2).to_string()
+        );
+        assert!(unlinked_result.is_err());
+    }
+
+    #[test]
+    fn should_add_new_block() {
+        // Given
+        let mut chain = Chain::default();
+        let previous_block = chain.blocks.last().unwrap().clone();
+
+        // When
+        let result = chain.add_new_block(
+            Block::new(
+                chain.blocks.len(),
+                previous_block.hash.clone(),
+                BlockData::new(
+                    BlockDataType::ValidatorData,
+                    None,
+                    Some(ValidatorData::new("public_key".to_string(), "account_id".to_string())),
+                ),
+                &mut Wallet::default(),
+                0,
+                HashAlgorithm::Sha256,
+            )
+            .unwrap(),
+        );
+
+        // Then
+        assert!(result.is_ok());
+        assert_eq!(chain.blocks.len(), 2);
+        assert_block(
+            chain.blocks.last().unwrap(),
+            None,
+            Some(ValidatorData::new("public_key".to_string(), "account_id".to_string())),
+        );
+    }
+
+    #[test]
+    fn should_accept_blocks_signed_by_secp256k1_key() {
+        // Given
+        let wallet = Wallet::generate(SignatureAlgorithm::Secp256k1);
+        let public_key = wallet.get_public_key();
+        let mut remote_chain = Chain {
+            signer: Box::new(wallet),
+            ..Chain::default()
+        };
+        remote_chain.add_edge_change("from".to_string(), "to".to_string(), 1).unwrap();
+        let validator_data = ValidatorData {
+            scheme: SignatureAlgorithm::Ed25519,
+            ..ValidatorData::new(public_key.clone(), "account_id".to_string())
+        };
+        let mut chain = Chain::default();
+
+        // When
+        let result = chain.replace_chain(&remote_chain.blocks);
+        let wrong_scheme = chain.add_new_block(
+            Block::new(
+                chain.blocks.len(),
+                chain.blocks.last().unwrap().hash.clone(),
+                BlockData::new(BlockDataType::ValidatorData, None, Some(validator_data)),
+                &mut Wallet::default(),
+                0,
+                HashAlgorithm::Sha256,
+            )
+            .unwrap(),
+        );
+
+        // Then
+        assert!(result.is_ok());
+        assert_eq!(chain.blocks[1].validator, public_key);
+        assert_eq!(
+            ValidatorData::new(public_key, "account_id".to_string()).scheme,
+            SignatureAlgorithm::Secp256k1
+        );
+        assert!(matches!(wrong_scheme, Err(ChainError::BlockHasWrongDataValue(3))));
+    }
+
+    #[test]
+    fn should_slash_validator_of_invalid_block() {
+        // Given
+        let mut chain = Chain::default();
+        let mut offender = Wallet::default();
+        chain
+            .agent_service
+            .accounts
+            .insert("offender".to_string(), Account::new("User".to_string(), offender.get_public_key(), 2));
+
+        let mut block = Block::new(
+            chain.blocks.len(),
+            chain.blocks.last().unwrap().hash.clone(),
+            BlockData::new(
+                BlockDataType::EdgeData,
+                Some(EdgeData::new("from".to_string(), "to".to_string(), 1)),
+                None,
+            ),
+            &mut offender,
+            0,
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
+        // Tamper with data after block was signed
+        block.data.edge_data.as_mut().unwrap().weight = 100;
+
+        // When
+        let result = chain.add_new_block(block);
+
+        // Then
+        assert!(result.is_err());
+        assert_eq!(chain.blocks.len(), 3); // Genesis, slash and reward block
+        assert!(chain.blocks[1].data.data_type == BlockDataType::SlashData);
+        assert_eq!(chain.agent_service.get_validator_difficulty(&offender.get_public_key()), 0);
+    }
+
+    #[test]
+    fn should_sync_chain_without_signing_blocks_in_observer_mode() {
+        // Given
+        let mut remote_chain = Chain::default();
+        remote_chain.add_edge_change("from".to_string(), "to".to_string(), 1).unwrap();
+        let mut chain = Chain {
+            mode: NodeMode::Observer,
+            ..Chain::default()
+        };
+        let mut offender = Wallet::default();
+        let mut block = Block::new(
+            remote_chain.blocks.len(),
+            remote_chain.blocks.last().unwrap().hash.clone(),
+            BlockData::new(
+                BlockDataType::EdgeData,
+                Some(EdgeData::new("from".to_string(), "to".to_string(), 1)),
+                None,
+            ),
+            &mut offender,
+            0,
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
+        block.data.edge_data.as_mut().unwrap().weight = 100;
+
+        // When
+        let sync_result = chain.replace_chain(&remote_chain.blocks);
+        let invalid_result = chain.add_new_block(block);
+        let change_result = chain.add_edge_change("from".to_string(), "to".to_string(), 1);
+
+        // Then
+        assert!(sync_result.is_ok());
+        assert!(invalid_result.is_err());
+        assert!(matches!(change_result, Err(ChainError::NodeIsObserver)));
+        assert!(chain.blocks == remote_chain.blocks);
+        assert!(chain.checkpoint_vote().unwrap().is_none());
+    }
+
+    #[test]
+    fn should_not_slash_block_with_invalid_signature() {
+        // Given
+        let mut chain = Chain::default();
+        let mut block = Block::new(
+            chain.blocks.len(),
+            chain.blocks.last().unwrap().hash.clone(),
+            BlockData::new(
+                BlockDataType::EdgeData,
+                Some(EdgeData::new("from".to_string(), "to".to_string(), 1)),
+                None,
+            ),
+            &mut Wallet::default(),
+            0,
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
+        block.validator = Wallet::default().get_public_key();
+
+        // When
+        let result = chain.add_new_block(block);
+
+        // Then
+        assert!(result.is_err());
+        assert_eq!(chain.blocks.len(), 1);
+        assert!(chain.agent_service.slashed.is_empty());
+    }
+
+    #[test]
+    fn should_reject_block_below_required_difficulty() {
+        // Given
+        let mut chain = Chain {
+            difficulty: DifficultyAlgorithm::Proportional {
+                target_interval: 10,
+                stake_share: 50,
+            },
+            ..Chain::new(&Genesis {
+                validators: vec![GenesisValidator::new("key".to_string(), 4)],
+                ..Genesis::default()
+            })
+        };
+
+        // When
+        let block = Block::new(
+            chain.blocks.len(),
+            chain.blocks.last().unwrap().hash.clone(),
+            BlockData::new(
+                BlockDataType::EdgeData,
+                Some(EdgeData::new("from".to_string(), "to".to_string(), 1)),
+                None,
+            ),
+            &mut Wallet::default(),
+            0,
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
+        let result = chain.add_new_block(block);
+
+        // Then
+        assert_eq!(chain.required_difficulty(), 2);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn should_batch_edge_changes() {
+        // Given
+        let mut arguments = std::collections::HashMap::new();
+        arguments.insert("batch_size".to_string(), vec!["2".to_string()]);
+        let mut chain = Chain {
+            mempool: Mempool::from_arguments(&arguments).unwrap(),
+            ..Chain::default()
+        };
+
+        // When
+        chain.add_edge_change("from".to_string(), "to".to_string(), 1).unwrap();
+        let blocks = chain.blocks.len();
+        chain.add_edge_change("from".to_string(), "other".to_string(), 2).unwrap();
+
+        // Then
+        assert_eq!(blocks, 1);
+        assert_eq!(chain.blocks.len(), 3); // Genesis, batch and reward block
+        assert!(chain.blocks[1].data.data_type == BlockDataType::EdgeBatch);
+        assert_eq!(chain.blocks[1].data.edge_batch.as_ref().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn should_reject_empty_batch() {
+        // Given
+        let mut chain = Chain::default();
+        let mut wallet = Wallet::default();
+
+        // When
+        let block = Block::new(
+            chain.blocks.len(),
+            chain.blocks.last().unwrap().hash.clone(),
+            BlockData::batch(vec![]),
+            &mut wallet,
+            0,
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
+        let result = chain.add_new_block(block);
+
+        // Then
+        assert!(result.is_err());
+        assert_eq!(chain.blocks.len(), 1);
+    }
+
+    #[test]
+    fn should_reject_chain_with_other_genesis() {
+        // Given
+        let mut chain = Chain::default();
+        let mut other_chain = Chain::new(&Genesis {
+            chain_id: "other".to_string(),
+            ..Genesis::default()
+        });
+        other_chain.add_edge_change("from".to_string(), "to".to_string(), 1).unwrap();
+
+        // When
+        let result = chain.replace_chain(&other_chain.blocks);
+
+        // Then
+        assert!(result.is_err());
+        assert_eq!(chain.blocks.len(), 1);
+    }
+
+    #[test]
+    fn should_rotate_key() {
+        // Given
+        let mut chain = Chain::default();
+        let old_public_key = chain.signer.get_public_key();
+        insert_agent(&mut chain, &old_public_key, 2);
+        chain.add_edge_change("from".to_string(), "to".to_string(), 1).unwrap();
+
+        // When
+        let result = chain.rotate_key().unwrap();
+
+        // Then
+        let new_public_key = chain.signer.get_public_key();
+        assert_eq!(result[0].get("new_public_key").unwrap(), &new_public_key);
+        assert!(chain.blocks[3].data.data_type == BlockDataType::KeyRotation);
+        assert_eq!(chain.agent_service.get_validator_difficulty(&new_public_key), 2);
+        assert_eq!(chain.agent_service.get_validator_difficulty(&old_public_key), 0);
+        assert_eq!(chain.balances().get(&new_public_key), Some(&2));
+    }
+
+    #[test]
+    fn should_summarize_validators() {
+        // Given
+        let mut chain = Chain::default();
+        let public_key = chain.signer.get_public_key();
+        insert_agent(&mut chain, &public_key, 2);
+        chain.add_edge_change("agent".to_string(), "node0".to_string(), 1).unwrap();
+        chain.add_edge_change("agent".to_string(), "node0".to_string(), 2).unwrap();
+
+        // When
+        let result = chain.validators_as_graph_result().unwrap();
+
+        // Then
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].get("public_key").unwrap(), &public_key);
+        assert_eq!(result[0].get("blocks").unwrap(), "2");
+        assert_eq!(result[0].get("difficulty").unwrap(), "4");
+        assert_eq!(result[0].get("stake").unwrap(), "2");
+        assert_eq!(result[0].get("first_timestamp").unwrap(), &chain.blocks[1].timestamp.to_string());
+        assert_eq!(result[0].get("last_timestamp").unwrap(), &chain.blocks[3].timestamp.to_string());
+    }
+
+    #[test]
+    fn should_refuse_fork_behind_finalized_checkpoint() {
+        // Given
+        let (wallet, other_wallet) = (Wallet::default(), Wallet::default());
+        let genesis = Genesis {
+            validators: vec![
+                GenesisValidator::new(wallet.get_public_key(), 1),
+                GenesisValidator::new(other_wallet.get_public_key(), 2),
+            ],
+            ..Genesis::default()
+        };
+        let arguments = std::collections::HashMap::from([("proposer_timeout".to_string(), vec!["0".to_string()])]);
+        let create_chain = |signer: Wallet| Chain {
+            signer: Box::new(signer),
+            finality: Finality::new(2, 67),
+            schedule: ProposerSchedule::from_arguments(&arguments).unwrap(),
+            ..Chain::new(&genesis)
+        };
+        let (mut chain, mut fork) = (create_chain(wallet), create_chain(other_wallet));
+        chain
+            .add_schema_change(SchemaData::new("User".to_string(), vec![], None, false, vec![]))
+            .unwrap();
+        fork.add_schema_change(SchemaData::new("Other".to_string(), vec![], None, false, vec![]))
+            .unwrap();
+        fork.add_schema_change(SchemaData::new("User".to_string(), vec![], None, false, vec![]))
+            .unwrap();
+
+        // When
+        let local_vote = chain.checkpoint_vote().unwrap().unwrap();
+        let pending = chain.finality.finalized().is_none();
+        let hash = chain.blocks[2].hash.clone();
+        let vote = |signer: &mut dyn Signer| {
+            let signature = signer.sign(&CheckpointVote::message(2, &hash)).unwrap();
+            CheckpointVote::new(2, hash.clone(), signer.get_public_key(), signature)
+        };
+        let without_stake = chain.add_checkpoint_vote(&vote(&mut Wallet::default()));
+        let counted = chain.add_checkpoint_vote(&vote(fork.signer.as_mut())).unwrap();
+        let result = chain.replace_chain(&fork.blocks);
+
+        // Then
+        assert_eq!(local_vote.block_id, 2);
+        assert!(pending);
+        assert!(matches!(without_stake, Err(ChainError::CheckpointVoteIsNotValid(2))));
+        assert!(counted);
+        assert_eq!(chain.finality.finalized(), Some((2, &hash)));
+        assert!(matches!(result, Err(ChainError::ChainConflictsWithFinalizedCheckpoint(2))));
+    }
+
+    #[test]
+    fn should_queue_local_changes_until_scheduled() {
+        // Given
+        let genesis = Genesis {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            validators: vec![GenesisValidator::new(Wallet::default().get_public_key(), 1)],
+            ..Genesis::default()
+        };
+        let mut chain = Chain::new(&genesis);
+        let arguments = std::collections::HashMap::from([("proposer_timeout".to_string(), vec!["0".to_string()])]);
+
+        // When
+        chain.add_edge_change("from".to_string(), "to".to_string(), 1).unwrap();
+        chain
+            .add_schema_change(SchemaData::new("User".to_string(), vec![], None, false, vec![]))
+            .unwrap();
+        let queued = (chain.blocks.len(), chain.has_queued_changes());
+        chain.schedule = ProposerSchedule::from_arguments(&arguments).unwrap();
+        chain.flush_mempool().unwrap();
+
+        // Then
+        assert_eq!(queued, (1, true));
+        assert!(!chain.has_queued_changes());
+        let data_types: Vec<&BlockDataType> = chain
+            .blocks
+            .iter()
+            .map(|block| &block.data.data_type)
+            .filter(|data_type| **data_type != BlockDataType::Reward)
+            .collect();
+        assert!(data_types[1..] == [&BlockDataType::EdgeData, &BlockDataType::SchemaData]);
+    }
+
+    #[test]
+    fn should_reject_proposer_timeout_within_clock_drift() {
+        // Given
+        let arguments = std::collections::HashMap::from([
+            ("proposer_timeout".to_string(), vec!["10".to_string()]),
+            ("max_clock_drift".to_string(), vec!["10".to_string()]),
+        ]);
+
+        // When
+        let result = Chain::from_arguments(&Genesis::default(), &arguments);
+
+        // Then
+        assert!(matches!(result, Err(ChainError::InvalidScheduleConfiguration(_))));
+    }
+
+    #[test]
+    fn should_mine_edge_changes() {
+        // Given
+        let consensus = Consensus::ProofOfWork { zero_bits: 8 };
+        let mut chain = Chain {
+            consensus: consensus.clone(),
+            ..Chain::default()
+        };
+        let mut peer_chain = Chain {
+            consensus,
+            ..Chain::default()
+        };
+        chain.add_edge_change("from".to_string(), "to".to_string(), 1).unwrap();
+        chain
+            .add_schema_change(SchemaData::new("Node".to_string(), vec![], None, false, vec![]))
+            .unwrap();
+
+        // When
+        let pending_blocks = chain.blocks.len();
+        let job = chain.mining_job().unwrap().mine();
+        let stale_job = chain.mining_job().unwrap().mine();
+        chain.add_mined_block(job).unwrap();
+        chain.add_mined_block(stale_job).unwrap();
+        let restored = chain.has_queued_changes();
+        let job = chain.mining_job().unwrap().mine();
+        chain.add_mined_block(job).unwrap();
+
+        // Then
+        assert_eq!(pending_blocks, 1);
+        assert!(restored);
+        assert_eq!(chain.blocks.len(), 5);
+        assert!(chain.blocks[1].data.data_type == BlockDataType::EdgeData);
+        assert!(chain.blocks[3].data.data_type == BlockDataType::SchemaData);
+        assert!(chain.blocks[1].hash.starts_with("00"));
+        assert!(chain.blocks[3].hash.starts_with("00"));
+        assert!(!chain.has_queued_changes());
+        assert!(peer_chain.add_new_block(chain.blocks[1].clone()).is_ok());
+    }
+
+    #[test]
+    fn should_reject_block_without_work() {
+        // Given
+        let mut chain = Chain {
+            consensus: Consensus::ProofOfWork { zero_bits: 64 },
+            ..Chain::default()
+        };
+        let data = BlockData::new(
+            BlockDataType::EdgeData,
+            Some(EdgeData::new("from".to_string(), "to".to_string(), 1)),
+            None,
+        );
+        let block = Block::new(1, chain.blocks[0].hash.clone(), data, &mut Wallet::default(), 0, HashAlgorithm::Sha256).unwrap();
+
+        // When
+        let result = chain.add_new_block(block);
+
+        // Then
+        assert!(matches!(result, Err(ChainError::BlockHasInsufficientWork(1))));
+    }
+
+    #[test]
+    fn should_replace_chain_hashed_by_genesis_algorithm() {
+        // Given
+        let genesis = Genesis {
+            hash_algorithm: HashAlgorithm::Blake3,
+            ..Genesis::default()
+        };
+        let mut remote_chain = Chain::new(&genesis);
+        remote_chain.add_edge_change("from".to_string(), "to".to_string(), 1).unwrap();
+        let mut chain = Chain::new(&genesis);
+
+        // When
+        let result = chain.replace_chain(&remote_chain.blocks);
+
+        // Then
+        assert!(result.is_ok());
+        assert_eq!(chain.hash_algorithm(), HashAlgorithm::Blake3);
+        assert!(Chain::default().replace_chain(&remote_chain.blocks).is_err());
+        assert!(Block::validate_block_hash(&remote_chain.blocks[1], HashAlgorithm::Sha256).is_err());
+    }
+
+    #[test]
+    fn should_verify_chain() {
+        // Given
+        let mut chain = Chain::default();
+        chain.add_edge_change("from".to_string(), "to".to_string(), 1).unwrap();
+        chain.add_edge_change("from".to_string(), "to".to_string(), 2).unwrap();
+
+        // When
+        let result = chain.verify().unwrap();
+
+        // Then
+        assert_eq!(result[0].get("valid").unwrap(), "true");
+        assert_eq!(result[0].get("blocks").unwrap(), "5");
+        assert!(!result[0].contains_key("block"));
+    }
+
+    #[test]
+    fn should_verify_chain_with_stake_of_its_epochs() {
+        // Given
+        let mut chain = Chain::new(&Genesis {
+            epoch_length: 2,
+            ..Genesis::default()
+        });
+        let schema = SchemaData::new("User".to_string(), vec!["key".to_string()], Some(vec![]), false, vec![]);
+        chain.add_schema_change(schema).unwrap();
+
+        let mut attributes = BTreeMap::new();
+        attributes.insert("key".to_string(), chain.signer.get_public_key());
+        chain
+            .add_node_change(NodeData::new(NodeOperation::Add, "User".to_string(), "agent".to_string(), attributes))
+            .unwrap();
+        for weight in 1..4 {
+            chain.add_edge_change("agent".to_string(), format!("node{weight}"), weight).unwrap();
+        }
+
+        // Agent doesn't have stake anymore, while earlier blocks were produced with its stake
+        chain
+            .add_node_change(NodeData::new(
+                NodeOperation::Delete,
+                "User".to_string(),
+                "agent".to_string(),
+                BTreeMap::new(),
+            ))
+            .unwrap();
+
+        // When
+        let result = chain.verify().unwrap();
+
+        // Then
+        assert!(chain.blocks.iter().any(|block| block.difficulty > 0));
+        assert_eq!(chain.agent_service.get_difficulty(&"agent".to_string()), 0);
+        assert_eq!(result[0].get("valid").unwrap(), "true");
+    }
+
+    #[test]
+    fn should_report_first_invalid_block() {
+        // Given
+        let mut chain = Chain::default();
+        chain.add_edge_change("from".to_string(), "to".to_string(), 1).unwrap();
+        chain.add_edge_change("from".to_string(), "to".to_string(), 2).unwrap();
+        chain.blocks[3].data.edge_data.as_mut().unwrap().weight = 3;
+
+        // When
+        let result = chain.verify().unwrap();
+
+        // Then
+        assert_eq!(result[0].get("valid").unwrap(), "false");
+        assert_eq!(result[0].get("block").unwrap(), "3");
+        assert_eq!(result[0].get("code").unwrap(), ChainError::BlockHasWrongHashValue(3).code());
+    }
+
+    /// Mutation which makes honestly generated chain invalid, applied to block at given index (genesis excluded).
+    #[derive(Debug, Clone)]
+    enum Mutation {
+        TamperHash(usize),
+        TamperData(usize),
+        TamperPreviousHash(usize),
+        SwapBlocks(usize, usize),
+        ForgeSignature(usize),
+    }
+
+    fn edge_block(id: usize, previous_hash: String, weight: i8, signer: &mut Wallet) -> Block {
+        let edge_data = EdgeData::new("from".to_string(), "to".to_string(), weight);
+
+        Block::new(
+            id,
+            previous_hash,
+            BlockData::new(BlockDataType::EdgeData, Some(edge_data), None),
+            signer,
+            0,
+            HashAlgorithm::Sha256,
+        )
+        .unwrap()
+    }
+
+    fn honest_chain(weights: &[i8]) -> Vec<Block> {
+        let mut signer = Wallet::default();
+        let mut blocks = Chain::default().blocks;
+
+        for weight in weights {
+            let previous_hash = blocks.last().unwrap().hash.clone();
+            blocks.push(edge_block(blocks.len(), previous_hash, *weight, &mut signer));
+        }
+
+        blocks
+    }
+
+    fn mutate(blocks: &mut [Block], mutation: &Mutation) {
+        match *mutation {
+            Mutation::TamperHash(index) => blocks[index].hash = sha256::digest(&blocks[index].hash),
+            Mutation::TamperData(index) => {
+                let edge_data = blocks[index].data.edge_data.as_mut().unwrap();
+                edge_data.weight = edge_data.weight.wrapping_add(1);
+            }
+            Mutation::TamperPreviousHash(index) => blocks[index].previous_hash = sha256::digest(&blocks[index].previous_hash),
+            Mutation::SwapBlocks(first, second) => blocks.swap(first, second),
+            Mutation::ForgeSignature(index) => {
+                // Block is signed by other key, while validator of the block is kept
+                let block = &blocks[index];
+                let forged = edge_block(
+                    block.id,
+                    block.previous_hash.clone(),
+                    block.data.edge_data.as_ref().unwrap().weight,
+                    &mut Wallet::default(),
+                );
+                blocks[index].signature = forged.signature;
+            }
+        }
+    }
+
+    /// Chain with at least two blocks after genesis (so they can be swapped), and mutation of one or two of them.
+    fn mutated_chain() -> impl Strategy<Value = (Vec<i8>, Mutation)> {
+        prop::collection::vec(any::<i8>(), 2..8).prop_flat_map(|weights| {
+            let length = weights.len() + 1;
+            let index = 1..length;
+            let mutation = prop_oneof![
+                index.clone().prop_map(Mutation::TamperHash),
+                index.clone().prop_map(Mutation::TamperData),
+                index.clone().prop_map(Mutation::TamperPreviousHash),
+                index.clone().prop_map(Mutation::ForgeSignature),
+                (index.clone(), index)
+                    .prop_filter("Blocks must be different", |(first, second)| first != second)
+                    .prop_map(|(first, second)| Mutation::SwapBlocks(first, second)),
+            ];
+
+            (Just(weights), mutation)
+        })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn should_accept_honest_chain(weights in prop::collection::vec(any::<i8>(), 1..8)) {
+            // Given
+            let blocks = honest_chain(&weights);
+
+            // When
+            let mut replaced = Chain::default();
+            let replace_result = replaced.replace_chain(&blocks);
+
+            let mut appended = Chain::default();
+            let append_results: Vec<Result<(), ChainError>> = blocks[1..].iter().map(|block| appended.add_new_block(block.clone())).collect();
+
+            // Then
+            prop_assert!(replace_result.is_ok());
+            prop_assert!(append_results.iter().all(Result::is_ok));
+            prop_assert!(replaced.blocks == blocks);
+            prop_assert!(appended.blocks == blocks);
+        }
+
+        #[test]
+        fn should_reject_mutated_chain((weights, mutation) in mutated_chain()) {
+            // Given
+            let mut blocks = honest_chain(&weights);
+            mutate(&mut blocks, &mutation);
+
+            // When
+            let mut replaced = Chain::default();
+            let replace_result = replaced.replace_chain(&blocks);
+
+            let mut appended = Chain::default();
+            let append_results: Vec<Result<(), ChainError>> = blocks[1..].iter().map(|block| appended.add_new_block(block.clone())).collect();
+
+            // Then
+            prop_assert!(replace_result.is_err());
+            prop_assert!(replaced.blocks.len() == 1);
+            prop_assert!(append_results.iter().any(Result::is_err));
+        }
+    }
+
+    #[test]
+    fn should_report_first_invalid_block_of_large_chain() {
+        // Given
+        let blocks = honest_chain(&[1; 600]);
+        let (mut forged, mut tampered) = (blocks.clone(), blocks.clone());
+        mutate(&mut forged, &Mutation::TamperData(500));
+        mutate(&mut forged, &Mutation::ForgeSignature(300));
+        mutate(&mut tampered, &Mutation::ForgeSignature(300));
+        mutate(&mut tampered, &Mutation::TamperData(300));
+
+        // When
+        let honest_result = Chain::default().replace_chain(&blocks);
+        let forged_result = Chain::default().replace_chain(&forged);
+        let tampered_result = Chain::default().replace_chain(&tampered);
+
+        // Then
+        assert!(honest_result.is_ok());
+        assert!(matches!(forged_result, Err(ChainError::BlockHasWrongSignatureValue(300))));
+        assert!(matches!(tampered_result, Err(ChainError::BlockHasWrongHashValue(300))));
+    }
+
+    /// Register agent with given key, whose stake is given by its edges (already registered by validator block).
+    pub(crate) fn insert_agent(chain: &mut Chain, public_key: &str, stake: usize) {
+        let mut attributes = BTreeMap::new();
+        attributes.insert("key".to_string(), public_key.to_string());
+
+        chain.agent_service.define_agent("User".to_string(), vec![]);
+        chain
+            .agent_service
+            .apply_node(&NodeData::new(NodeOperation::Add, "User".to_string(), "agent".to_string(), attributes));
+        for index in 0..stake {
+            chain
+                .agent_service
+                .apply_edge(&EdgeData::new("agent".to_string(), format!("node{index}"), 1));
+        }
+        chain.agent_service.registered.insert("agent".to_string());
+
+        // Agent inserted before the first block has stake in the first epoch
+        if chain.blocks.len() == 1 {
+            chain.epochs.snapshot(&chain.blocks[0], &chain.agent_service);
+        }
     }
 
     fn assert_block(block: &Block, edge_data: Option<EdgeData>, validator_data: Option<ValidatorData>) {
         assert_eq!(block.id, 1);
-        assert_eq!(block.previous_hash, "0000494d137e1631bba301d5acab6e7bb7aa74ce1185d456565ef51d737677b2");
+        assert_eq!(block.previous_hash, Block::default().hash);
 
         assert!(block.timestamp > 0);
         assert!(!block.hash.is_empty());
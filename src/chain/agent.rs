@@ -1,76 +1,282 @@
+use crate::chain::block::{EdgeData, NodeData, NodeOperation};
 use crate::chain::error::ChainError;
-use crate::graph::Graph;
-use rustc_hash::FxHashMap;
-
-// Note: This should be implemented as API call to graph db
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::condition::Condition;
+use crate::graph::GraphResults;
+use derive_more::Constructor;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Agents and their stake, derived only from changes recorded in the chain (definitions, node and edge changes, slashes
+/// and key rotations), so every peer which applied the same blocks has the same agents.
+#[derive(Default)]
 pub struct AgentService {
-    pub(crate) agents: FxHashMap<String, FxHashMap<String, String>>,
-    pub(crate) accounts: FxHashMap<String, (String, usize)>,
+    pub(crate) agents: FxHashMap<String, Vec<Condition>>,
+    pub(crate) accounts: FxHashMap<String, Account>,
+    pub(crate) slashed: FxHashSet<String>,
+    // Identifier of the agent to which node delegated its stake, by identifier of the node
+    delegations: FxHashMap<String, String>,
+    pub(crate) genesis_stake: FxHashMap<String, usize>,
+    pub(crate) rotations: FxHashMap<String, String>,
+    /// Accounts whose validator registration was added to the chain
+    pub(crate) registered: FxHashSet<String>,
+    // Nodes of agent definitions by identifier, as they are recorded in the chain
+    nodes: FxHashMap<String, AgentNode>,
+    // Weights of outgoing edges of each node, by identifier of from and to node
+    edges: FxHashMap<String, FxHashMap<String, i8>>,
 }
 
-impl Default for AgentService {
-    fn default() -> Self {
-        AgentService {
-            agents: FxHashMap::default(),
-            accounts: FxHashMap::default(),
-        }
-    }
+#[derive(Constructor, Clone)]
+pub struct Account {
+    pub node_name: String,
+    pub public_key: String,
+    pub difficulty: usize,
+}
+
+struct AgentNode {
+    name: String,
+    attributes: FxHashMap<String, String>,
+    deleted: bool,
 }
 
 impl AgentService {
-    pub fn define_agent(&mut self, node_name: String, conditions: FxHashMap<String, String>) {
+    pub fn define_agent(&mut self, node_name: String, conditions: Vec<Condition>) {
         self.agents.insert(node_name, conditions);
     }
 
-    pub fn add_or_update_agent(&mut self, graph: &mut Graph, node_name: String, identifier: &String) -> Result<(String, usize), ChainError> {
-        if let Ok(value) = self.validate_agent(graph, node_name, identifier) {
-            self.accounts.insert(identifier.clone(), value.clone());
+    /// Apply node change of the chain. Node of agent definition becomes agent once it meets conditions of the agent,
+    /// while deleted node loses its account, delegation and edges.
+    pub fn apply_node(&mut self, node: &NodeData) {
+        match node.operation {
+            NodeOperation::Add | NodeOperation::Update if self.agents.contains_key(&node.name) => {
+                let agent_node = AgentNode {
+                    name: node.name.clone(),
+                    attributes: node.attributes.clone().into_iter().collect(),
+                    deleted: false,
+                };
+                self.nodes.insert(node.id.clone(), agent_node);
+            }
+            NodeOperation::Delete => {
+                self.nodes.remove(&node.id);
+                self.edges.remove(&node.id);
+                self.delegations.remove(&node.id);
+
+                // Edges to deleted node are deleted with it, without their own changes in the chain
+                let sources: Vec<String> = self
+                    .edges
+                    .iter_mut()
+                    .filter_map(|(from, edges)| edges.remove(&node.id).map(|_| from.clone()))
+                    .collect();
+                for from in sources {
+                    self.evaluate(&from);
+                }
+            }
+            NodeOperation::SoftDelete | NodeOperation::Restore => {
+                if let Some(agent_node) = self.nodes.get_mut(&node.id) {
+                    agent_node.deleted = node.operation == NodeOperation::SoftDelete;
+                }
+            }
+            _ => {}
+        }
+
+        self.evaluate(&node.id);
+    }
+
+    /// Apply edge change of the chain, edge with zero weight is deleted. Agent conditions can depend on graph
+    /// structure, so from node is evaluated again.
+    pub fn apply_edge(&mut self, edge: &EdgeData) {
+        let edges = self.edges.entry(edge.from.clone()).or_default();
+        match edge.weight {
+            0 => edges.remove(&edge.to),
+            weight => edges.insert(edge.to.clone(), weight),
+        };
+
+        self.evaluate(&edge.from);
+    }
+
+    /// Register node as agent if it meets conditions of its agent and it has a key, otherwise its account is removed.
+    /// Agent uses its own stake, so its delegation is not needed anymore.
+    fn evaluate(&mut self, identifier: &str) {
+        let edges = self.edges.get(identifier);
+        let difficulty = edges.map_or(0, FxHashMap::len);
+
+        let account = self.nodes.get(identifier).filter(|node| !node.deleted).and_then(|node| {
+            // Conditions can also depend on graph structure, e.g. $edges or $totalWeight
+            let mut attributes = node.attributes.clone();
+            attributes.insert(InternalNodeAttribute::ID_ATTRIBUTE.to_string(), identifier.to_string());
+            attributes.insert(InternalNodeAttribute::NAME_ATTRIBUTE.to_string(), node.name.clone());
+            attributes.insert(InternalNodeAttribute::EDGE_COUNT_ATTRIBUTE.to_string(), difficulty.to_string());
+            attributes.insert(
+                InternalNodeAttribute::TOTAL_WEIGHT_ATTRIBUTE.to_string(),
+                edges
+                    .map_or(0, |edges| edges.values().map(|weight| *weight as i64).sum::<i64>())
+                    .to_string(),
+            );
+
+            let conditions = self.agents.get(&node.name)?;
+            if !conditions.iter().all(|condition| condition.is_met(&attributes)) {
+                return None;
+            }
+
+            let public_key = node.attributes.get("key")?;
+            Some(Account::new(node.name.clone(), self.resolve_key(public_key), difficulty))
+        });
+
+        match account {
+            Some(account) => {
+                self.accounts.insert(identifier.to_string(), account);
+                self.delegations.remove(identifier);
+            }
+            None => {
+                self.accounts.remove(identifier);
+            }
+        }
+    }
 
-            Ok(value)
-        } else {
-            self.remove_agent(identifier);
+    /// Node which is already an agent can't delegate its stake, and it can be delegated only to registered agent.
+    pub fn validate_delegation(&self, identifier: &String, agent_id: &String) -> Result<(), ChainError> {
+        if self.accounts.contains_key(identifier) {
+            return Err(ChainError::DelegationNotAllowed(identifier.clone()));
+        }
 
-            Err(ChainError::NotQualifiedForAgent(identifier.clone()))
+        if !self.accounts.contains_key(agent_id) {
+            return Err(ChainError::WrongAgentIdentifier(agent_id.clone()));
         }
+
+        Ok(())
     }
 
-    pub fn remove_agent(&mut self, identifier: &String) {
-        self.accounts.remove(identifier);
+    /// Delegate stake of the node which doesn't qualify as agent to registered agent. Stake is derived from the edges
+    /// of the node, same as difficulty of agent. Delegating again replaces previous delegation of the node.
+    pub fn delegate(&mut self, identifier: &String, agent_id: &String) -> Result<usize, ChainError> {
+        self.validate_delegation(identifier, agent_id)?;
+        self.delegations.insert(identifier.clone(), agent_id.clone());
+
+        Ok(self.get_edge_count(identifier))
+    }
+
+    pub fn get_edge_count(&self, identifier: &String) -> usize {
+        self.edges.get(identifier).map_or(0, FxHashMap::len)
+    }
+
+    fn get_delegated_stake(&self, agent_id: &String) -> usize {
+        self.delegations
+            .iter()
+            .filter(|(_, delegated_to)| *delegated_to == agent_id)
+            .map(|(identifier, _)| self.get_edge_count(identifier))
+            .sum()
     }
 
-    fn validate_agent(&self, graph: &mut Graph, node_name: String, identifier: &String) -> Result<(String, usize), ChainError> {
-        let agent = self.agents.get(&node_name).ok_or(ChainError::WrongAgentIdentifier(identifier.clone()))?;
-        let node = graph
-            .find_by_id(&node_name, identifier)
-            .map_err(|_| ChainError::WrongAgentIdentifier(identifier.clone()))?;
+    /// Move accounts and stake of the old key to the new key. Keys of agent nodes are not changed in the graph,
+    /// so rotation is also remembered and applied each time agent is evaluated.
+    pub fn rotate_key(&mut self, old_public_key: &String, new_public_key: &str) {
+        self.rotations.insert(old_public_key.clone(), new_public_key.to_string());
 
-        for (condition, condition_value) in agent {
-            if node.attributes.get(condition) != Some(condition_value) {
-                return Err(ChainError::WrongAgentIdentifier(identifier.clone()));
+        for account in self.accounts.values_mut() {
+            if account.public_key == *old_public_key {
+                account.public_key = new_public_key.to_string();
             }
         }
 
-        let p_key = node.attributes.get("key").ok_or_else(|| ChainError::WrongAgentKey(node_name.clone()))?;
-        Ok((p_key.clone(), node.edges.len()))
+        if let Some(stake) = self.genesis_stake.remove(old_public_key) {
+            self.genesis_stake.insert(new_public_key.to_string(), stake);
+        }
+    }
+
+    /// Latest key of the validator, following all its key rotations.
+    pub fn resolve_key(&self, public_key: &String) -> String {
+        let mut key = public_key;
+
+        while let Some(new_key) = self.rotations.get(key) {
+            key = new_key;
+        }
+
+        key.clone()
+    }
+
+    /// Slashed validator loses its stake, regardless of its edges.
+    pub fn slash(&mut self, public_key: String) {
+        self.slashed.insert(public_key);
     }
 
     pub fn get_difficulty(&self, identifier: &String) -> usize {
-        self.accounts.get(identifier).map_or(0, |(_, difficulty)| *difficulty)
+        self.accounts
+            .get(identifier)
+            .filter(|account| !self.slashed.contains(&account.public_key))
+            .map_or(0, |account| account.difficulty + self.get_delegated_stake(identifier))
     }
 
     pub fn get_validator_difficulty(&self, validator: &String) -> usize {
-        self.accounts
+        if self.slashed.contains(validator) {
+            return 0;
+        }
+
+        let stake: usize = self
+            .accounts
             .iter()
-            .filter(|(_, (p_key, _))| p_key == validator)
-            .map(|(_, (_, difficulty))| *difficulty)
-            .sum()
+            .filter(|(_, account)| account.public_key == *validator)
+            .map(|(identifier, account)| account.difficulty + self.get_delegated_stake(identifier))
+            .sum();
+
+        stake + self.genesis_stake.get(validator).copied().unwrap_or(0)
+    }
+
+    /// Stake of each validator with registered account or genesis stake.
+    pub fn get_validator_stakes(&self) -> FxHashMap<String, usize> {
+        let validators = self.accounts.values().map(|account| &account.public_key).chain(self.genesis_stake.keys());
+
+        validators
+            .map(|validator| (validator.clone(), self.get_validator_difficulty(validator)))
+            .collect()
+    }
+
+    /// Total stake of all validators in the network (as seen by this peer).
+    pub fn get_total_stake(&self) -> usize {
+        let genesis_stake: usize = self
+            .genesis_stake
+            .iter()
+            .filter(|(public_key, _)| !self.slashed.contains(*public_key))
+            .map(|(_, stake)| stake)
+            .sum();
+
+        genesis_stake + self.accounts.keys().map(|identifier| self.get_difficulty(identifier)).sum::<usize>()
+    }
+
+    /// List all registered agent accounts with their public key, difficulty and conditions of their agent definition.
+    pub fn as_graph_result(&self) -> GraphResults {
+        let mut identifiers: Vec<&String> = self.accounts.keys().collect();
+        identifiers.sort();
+
+        Ok(identifiers
+            .into_iter()
+            .map(|identifier| {
+                let account = &self.accounts[identifier];
+                let conditions = self.agents.get(&account.node_name).map_or(vec![], |conditions| conditions.clone());
+
+                let mut result = FxHashMap::default();
+                result.insert(InternalNodeAttribute::ID_ATTRIBUTE.to_string(), identifier.clone());
+                result.insert(InternalNodeAttribute::NAME_ATTRIBUTE.to_string(), account.node_name.clone());
+                result.insert("public_key".to_string(), account.public_key.clone());
+                result.insert("difficulty".to_string(), account.difficulty.to_string());
+                result.insert(
+                    "conditions".to_string(),
+                    conditions
+                        .iter()
+                        .map(|condition| condition.to_string())
+                        .collect::<Vec<String>>()
+                        .join(","),
+                );
+
+                result
+            })
+            .collect())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::graph::attribute::InternalNodeAttribute;
+    use crate::graph::condition::Operator;
+    use std::collections::BTreeMap;
 
     #[test]
     fn should_define_agent() {
@@ -78,98 +284,187 @@ mod tests {
         let mut agent_service = AgentService::default();
 
         // When
-        agent_service.define_agent("User".to_string(), FxHashMap::default());
+        agent_service.define_agent("User".to_string(), vec![]);
 
         // Then
         assert_eq!(agent_service.agents.len(), 1);
     }
 
     #[test]
-    fn should_add_or_update_agent() {
+    fn should_add_agent_from_node_change() {
         // Given
         let mut agent_service = AgentService::default();
-        let mut graph = Graph::default();
-        let identifier = insert_agent(&mut graph);
-
         define_agent(&mut agent_service);
 
         // When
-        let result = agent_service.add_or_update_agent(&mut graph, "User".to_string(), &identifier);
+        agent_service.apply_node(&agent_node(NodeOperation::Add, "1", "true"));
 
         // Then
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), ("1234567890".to_string(), 0));
         assert_eq!(agent_service.agents.len(), 1);
         assert_eq!(agent_service.accounts.len(), 1);
+        assert_eq!(agent_service.accounts.get("1").unwrap().public_key, "1234567890");
     }
 
     #[test]
-    fn should_remove_agent() {
+    fn should_reject_agent_not_meeting_conditions() {
+        // Given
+        let mut agent_service = AgentService::default();
+        agent_service.define_agent(
+            "User".to_string(),
+            vec![Condition::new("key".to_string(), Operator::Greater, "9999999999".to_string())],
+        );
+
+        // When
+        agent_service.apply_node(&agent_node(NodeOperation::Add, "1", "true"));
+
+        // Then
+        assert_eq!(agent_service.accounts.len(), 0);
+    }
+
+    #[test]
+    fn should_ignore_node_without_agent_definition() {
         // Given
         let mut agent_service = AgentService::default();
-        let mut graph = Graph::default();
-        let identifier = insert_agent(&mut graph);
 
+        // When
+        agent_service.apply_node(&agent_node(NodeOperation::Add, "1", "true"));
+
+        // Then
+        assert!(agent_service.accounts.is_empty());
+        assert!(agent_service.nodes.is_empty());
+    }
+
+    #[test]
+    fn should_list_agents() {
+        // Given
+        let mut agent_service = AgentService::default();
         define_agent(&mut agent_service);
-        let _ = agent_service.add_or_update_agent(&mut graph, "User".to_string(), &identifier);
+        agent_service.apply_node(&agent_node(NodeOperation::Add, "1", "true"));
+
+        // When
+        let result = agent_service.as_graph_result().unwrap();
+
+        // Then
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].get(InternalNodeAttribute::ID_ATTRIBUTE).unwrap(), "1");
+        assert_eq!(result[0].get(InternalNodeAttribute::NAME_ATTRIBUTE).unwrap(), "User");
+        assert_eq!(result[0].get("public_key").unwrap(), "1234567890");
+        assert_eq!(result[0].get("difficulty").unwrap(), "0");
+        assert_eq!(result[0].get("conditions").unwrap(), "premium=\"true\"");
+    }
 
-        // Change user to non-premium
-        let mut attributes = FxHashMap::default();
-        attributes.insert(InternalNodeAttribute::ID_ATTRIBUTE.to_string(), identifier.clone());
-        graph.update_node("User".to_string(), attributes).unwrap();
+    #[test]
+    fn should_remove_agent() {
+        // Given
+        let mut agent_service = AgentService::default();
+        define_agent(&mut agent_service);
+        agent_service.apply_node(&agent_node(NodeOperation::Add, "1", "true"));
 
         // When
-        let result = agent_service.add_or_update_agent(&mut graph, "User".to_string(), &identifier);
+        agent_service.apply_node(&agent_node(NodeOperation::Update, "1", "false"));
 
         // Then
-        assert!(result.is_err());
         assert_eq!(agent_service.agents.len(), 1);
         assert_eq!(agent_service.accounts.len(), 0);
     }
 
+    #[test]
+    fn should_remove_soft_deleted_agent_until_restored() {
+        // Given
+        let mut agent_service = AgentService::default();
+        define_agent(&mut agent_service);
+        agent_service.apply_node(&agent_node(NodeOperation::Add, "1", "true"));
+
+        // When
+        agent_service.apply_node(&identifier_node(NodeOperation::SoftDelete, "1"));
+        let soft_deleted = agent_service.accounts.len();
+        agent_service.apply_node(&identifier_node(NodeOperation::Restore, "1"));
+
+        // Then
+        assert_eq!(soft_deleted, 0);
+        assert_eq!(agent_service.accounts.len(), 1);
+    }
+
     #[test]
     fn should_get_difficulty() {
         // Given
         let mut agent_service = AgentService::default();
-        let mut graph = Graph::default();
-        let identifier = insert_agent(&mut graph);
-
-        // Insert edge
-        let mut wrapped_identifier = FxHashMap::default();
-        wrapped_identifier.insert(InternalNodeAttribute::ID_ATTRIBUTE.to_string(), identifier.clone());
-        graph
-            .add_edge(
-                ("User".to_string(), wrapped_identifier.clone()),
-                ("User".to_string(), wrapped_identifier),
-                1,
-            )
-            .unwrap();
+        define_agent(&mut agent_service);
+        agent_service.apply_node(&agent_node(NodeOperation::Add, "1", "true"));
+
+        // When
+        agent_service.apply_edge(&EdgeData::new("1".to_string(), "1".to_string(), 1));
+        agent_service.apply_edge(&EdgeData::new("1".to_string(), "2".to_string(), 1));
+        agent_service.apply_edge(&EdgeData::new("1".to_string(), "2".to_string(), 0));
+
+        // Then
+        assert_eq!(agent_service.get_difficulty(&"1".to_string()), 1);
+    }
 
+    #[test]
+    fn should_remove_edges_of_deleted_node() {
+        // Given
+        let mut agent_service = AgentService::default();
         define_agent(&mut agent_service);
-        let _ = agent_service.add_or_update_agent(&mut graph, "User".to_string(), &identifier);
+        agent_service.apply_node(&agent_node(NodeOperation::Add, "1", "true"));
+        agent_service.apply_edge(&EdgeData::new("1".to_string(), "2".to_string(), 1));
 
         // When
-        let difficulty = agent_service.get_difficulty(&identifier);
+        agent_service.apply_node(&identifier_node(NodeOperation::Delete, "2"));
 
         // Then
-        assert_eq!(difficulty, 1);
+        assert_eq!(agent_service.get_difficulty(&"1".to_string()), 0);
     }
 
-    fn insert_agent(graph: &mut Graph) -> String {
-        let mut attributes = FxHashMap::default();
+    #[test]
+    fn should_delegate_stake() {
+        // Given
+        let mut agent_service = AgentService::default();
+        define_agent(&mut agent_service);
+        agent_service.apply_node(&agent_node(NodeOperation::Add, "1", "true"));
+        agent_service.apply_node(&agent_node(NodeOperation::Add, "2", "false"));
+        agent_service.apply_edge(&EdgeData::new("2".to_string(), "2".to_string(), 1));
+
+        // When
+        let result = agent_service.delegate(&"2".to_string(), &"1".to_string());
 
-        attributes.insert("premium".to_string(), "true".to_string());
+        // Then
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(agent_service.get_difficulty(&"1".to_string()), 1);
+        assert_eq!(agent_service.get_validator_difficulty(&"1234567890".to_string()), 1);
+        assert!(agent_service.delegate(&"1".to_string(), &"1".to_string()).is_err());
+    }
+
+    #[test]
+    fn should_rotate_key() {
+        // Given
+        let mut agent_service = AgentService::default();
+        define_agent(&mut agent_service);
+        agent_service.apply_node(&agent_node(NodeOperation::Add, "1", "true"));
+
+        // When
+        agent_service.rotate_key(&"1234567890".to_string(), "new");
+        agent_service.apply_node(&agent_node(NodeOperation::Update, "1", "true"));
+
+        // Then
+        assert_eq!(agent_service.accounts.get("1").unwrap().public_key, "new");
+    }
+
+    fn agent_node(operation: NodeOperation, identifier: &str, premium: &str) -> NodeData {
+        let mut attributes = BTreeMap::new();
+        attributes.insert("premium".to_string(), premium.to_string());
         attributes.insert("key".to_string(), "1234567890".to_string());
 
-        graph.create_definition("User".to_string(), attributes.keys().cloned().collect()).unwrap();
-        InternalNodeAttribute::get_identifier(&graph.add_node("User".to_string(), attributes).unwrap().first().unwrap())
+        NodeData::new(operation, "User".to_string(), identifier.to_string(), attributes)
     }
 
-    fn define_agent(agent_service: &mut AgentService) {
-        let mut conditions = FxHashMap::default();
+    fn identifier_node(operation: NodeOperation, identifier: &str) -> NodeData {
+        NodeData::new(operation, "User".to_string(), identifier.to_string(), BTreeMap::new())
+    }
 
-        conditions.insert("premium".to_string(), "true".to_string());
+    fn define_agent(agent_service: &mut AgentService) {
+        let conditions = vec![Condition::new("premium".to_string(), Operator::Equal, "true".to_string())];
 
-        agent_service.define_agent("User".to_string(), FxHashMap::default());
+        agent_service.define_agent("User".to_string(), conditions);
     }
 }
@@ -0,0 +1,386 @@
+use crate::chain::block::{Block, NodeOperation};
+use crate::chain::encryption::{LineFile, StorageEncryption};
+use crate::chain::error::ChainError;
+use crate::chain::history::History;
+use crate::graph::error::DatabaseError;
+use crate::graph::GraphResults;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// Nodes and edges changed by one block, stored as one JSON line of the archive file.
+#[derive(Serialize, Deserialize)]
+struct ArchiveEntry {
+    height: usize,
+    hash: String,
+    nodes: Vec<String>,
+    edges: Vec<(String, String)>,
+}
+
+impl ArchiveEntry {
+    fn new(block: &Block) -> ArchiveEntry {
+        let edges = block.data.edge_data.iter().chain(block.data.edge_batch.iter().flatten());
+
+        ArchiveEntry {
+            height: block.id,
+            hash: block.hash.clone(),
+            nodes: block.data.nodes().map(|node| node.id.clone()).collect(),
+            edges: edges.map(|edge| (edge.from.clone(), edge.to.clone())).collect(),
+        }
+    }
+}
+
+/// Index of heights of blocks which changed each node (by identifier) and each edge (by from and to identifier), so
+/// history queries read only blocks of given node or edge (found by binary search) instead of replaying the whole
+/// chain. Index is stored in archive file, one line for each block, and loaded on start, so blocks synced again after
+/// restart are not indexed again. Entries of blocks replaced by fork are discarded. With storage encryption, the file is
+/// encrypted (see `StorageEncryption`).
+pub struct Archive {
+    path: String,
+    file: LineFile,
+    // Hash of each indexed block by its height, starting with genesis block
+    hashes: Vec<String>,
+    nodes: FxHashMap<String, Vec<usize>>,
+    edges: FxHashMap<String, FxHashMap<String, Vec<usize>>>,
+}
+
+impl Archive {
+    /// Open archive file given by `--archive="archive.jsonl"` argument, if it is given.
+    pub fn from_arguments(
+        arguments: &HashMap<String, Vec<String>>,
+        genesis: &Block,
+        encryption: Option<StorageEncryption>,
+    ) -> Result<Option<Archive>, ChainError> {
+        arguments
+            .get("archive")
+            .and_then(|values| values.first())
+            .map(|path| Self::open(path, genesis, encryption))
+            .transpose()
+    }
+
+    /// Load archive file, or create it if it doesn't exist. Archive of other chain (with different genesis block) can't
+    /// be opened.
+    pub fn open(path: &str, genesis: &Block, encryption: Option<StorageEncryption>) -> Result<Archive, ChainError> {
+        let error = |reason: String| ChainError::ArchiveError(path.to_string(), reason);
+
+        let mut archive = Archive {
+            path: path.to_string(),
+            file: LineFile::new(path, encryption),
+            hashes: vec![],
+            nodes: FxHashMap::default(),
+            edges: FxHashMap::default(),
+        };
+
+        if !Path::new(path).exists() {
+            archive.index(genesis)?;
+            return Ok(archive);
+        }
+
+        for line in archive.file.read().map_err(error)? {
+            let entry: ArchiveEntry = serde_json::from_str(&line).map_err(|e| error(e.to_string()))?;
+            if entry.height != archive.hashes.len() {
+                return Err(error(format!("Block {} is not in sequence", entry.height)));
+            }
+
+            archive.insert(entry);
+        }
+
+        if archive.hashes.first() != Some(&genesis.hash) {
+            return Err(error("Archive belongs to other chain".to_string()));
+        }
+
+        Ok(archive)
+    }
+
+    /// Index block added to the chain. Already indexed block is skipped, while entries of replaced block and all blocks
+    /// after it are discarded before the block is indexed.
+    pub(crate) fn index(&mut self, block: &Block) -> Result<(), ChainError> {
+        match self.hashes.get(block.id) {
+            Some(hash) if *hash == block.hash => return Ok(()),
+            Some(_) => self.truncate(block.id)?,
+            None if block.id > self.hashes.len() => {
+                return Err(ChainError::ArchiveError(
+                    self.path.clone(),
+                    format!("Block {} is not in sequence", block.id),
+                ))
+            }
+            None => {}
+        }
+
+        let error = |reason: String| ChainError::ArchiveError(self.path.clone(), reason);
+
+        let entry = ArchiveEntry::new(block);
+        let line = serde_json::to_string(&entry).map_err(|e| error(e.to_string()))?;
+        self.file.append(&[line]).map_err(error)?;
+
+        self.insert(entry);
+
+        Ok(())
+    }
+
+    fn insert(&mut self, entry: ArchiveEntry) {
+        for node in entry.nodes {
+            self.nodes.entry(node).or_default().push(entry.height);
+        }
+
+        for (from, to) in entry.edges {
+            let heights = self.edges.entry(from).or_default().entry(to).or_default();
+            // Batch can change the same edge multiple times
+            if heights.last() != Some(&entry.height) {
+                heights.push(entry.height);
+            }
+        }
+
+        self.hashes.push(entry.hash);
+    }
+
+    /// Discard entries of blocks with given or higher height, from the index and the archive file.
+    fn truncate(&mut self, height: usize) -> Result<(), ChainError> {
+        let error = |reason: String| ChainError::ArchiveError(self.path.clone(), reason);
+
+        self.file.truncate(height).map_err(error)?;
+
+        let truncate = |heights: &mut Vec<usize>| heights.truncate(heights.partition_point(|indexed| *indexed < height));
+        self.nodes.values_mut().for_each(truncate);
+        self.edges.values_mut().flat_map(|edges| edges.values_mut()).for_each(truncate);
+        self.hashes.truncate(height);
+
+        Ok(())
+    }
+
+    /// Heights of blocks which changed the edge between given nodes.
+    pub fn edge_heights(&self, from: &str, to: &str) -> &[usize] {
+        self.edges
+            .get(from)
+            .and_then(|edges| edges.get(to))
+            .map_or(&[], |heights| heights.as_slice())
+    }
+
+    /// Node with given name and identifier together with its outgoing edges, as they were at given block height, read
+    /// only from blocks which changed the node or its edges.
+    pub fn node_at(&self, blocks: &[Block], name: String, identifier: String, height: usize) -> GraphResults {
+        let attributes = self
+            .node_state(blocks, &identifier, height)
+            .filter(|(node_name, _)| *node_name == name)
+            .map(|(_, attributes)| attributes)
+            .ok_or(DatabaseError::NodeNotFound(name.clone(), identifier.clone()))?;
+
+        // Edges are deleted together with the node, so edges changed before the last deletion are skipped
+        let deleted = self.last_deletion(blocks, &identifier, height);
+        let mut edges: Vec<(String, Option<String>, i8)> = self
+            .edges
+            .get(&identifier)
+            .into_iter()
+            .flatten()
+            .filter_map(|(to_id, heights)| {
+                let edge_height = Self::last_height(heights, height).filter(|edge_height| deleted.is_none_or(|deleted| *edge_height > deleted))?;
+                let block = blocks.get(edge_height)?;
+                let weight = block
+                    .data
+                    .edge_data
+                    .iter()
+                    .chain(block.data.edge_batch.iter().flatten())
+                    .rfind(|edge| edge.from == identifier && edge.to == *to_id)?
+                    .weight;
+
+                let to_name = self.node_state(blocks, to_id, height).map(|(to_name, _)| to_name);
+                (weight != 0).then(|| (to_id.clone(), to_name, weight))
+            })
+            .collect();
+        edges.sort_by(|(first, ..), (second, ..)| first.cmp(second));
+
+        Ok(History::node_result(&name, &identifier, attributes, edges))
+    }
+
+    /// Name and attributes of the node at given height, if it exists.
+    fn node_state<'a>(&self, blocks: &'a [Block], identifier: &str, height: usize) -> Option<(String, &'a BTreeMap<String, String>)> {
+        let node_height = Self::last_height(self.nodes.get(identifier)?, height)?;
+        let node = blocks.get(node_height)?.data.nodes().find(|node| node.id == identifier)?;
+
+        (!matches!(node.operation, NodeOperation::Delete | NodeOperation::SoftDelete)).then(|| (node.name.clone(), &node.attributes))
+    }
+
+    fn last_deletion(&self, blocks: &[Block], identifier: &str, height: usize) -> Option<usize> {
+        let heights = self.nodes.get(identifier)?;
+
+        heights[..heights.partition_point(|indexed| *indexed <= height)]
+            .iter()
+            .rev()
+            .find(|indexed| {
+                blocks
+                    .get(**indexed)
+                    .and_then(|block| block.data.nodes().find(|node| node.id == identifier))
+                    .is_some_and(|node| node.operation == NodeOperation::Delete)
+            })
+            .copied()
+    }
+
+    /// The highest of sorted heights which is not higher than given height.
+    fn last_height(heights: &[usize], height: usize) -> Option<usize> {
+        heights
+            .partition_point(|indexed| *indexed <= height)
+            .checked_sub(1)
+            .map(|index| heights[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::block::NodeData;
+    use crate::chain::Chain;
+    use std::env;
+    use std::fs;
+
+    fn node(operation: NodeOperation, id: &str, value: &str) -> NodeData {
+        NodeData::new(
+            operation,
+            "User".to_string(),
+            id.to_string(),
+            BTreeMap::from([("name".to_string(), value.to_string())]),
+        )
+    }
+
+    #[test]
+    fn should_answer_history_queries_from_archive() {
+        // Given
+        let path = env::temp_dir().join("should_answer_history_queries_from_archive.jsonl");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let mut chain = Chain::default();
+        chain.archive = Some(Archive::open(path, &chain.blocks[0], None).unwrap());
+        chain.add_node_change(node(NodeOperation::Add, "1", "John")).unwrap();
+        chain.add_node_change(node(NodeOperation::Add, "2", "Jane")).unwrap();
+        chain.add_edge_change("1".to_string(), "2".to_string(), 5).unwrap();
+        chain.add_node_change(node(NodeOperation::Update, "1", "Johnny")).unwrap();
+        chain.add_edge_change("1".to_string(), "2".to_string(), 7).unwrap();
+        chain
+            .add_node_change(NodeData::new(NodeOperation::Delete, "User".to_string(), "2".to_string(), BTreeMap::new()))
+            .unwrap();
+        let height = chain.blocks.len() - 1;
+
+        // When
+        let mut results = vec![];
+        for height in [4, 6, 8, height] {
+            let archived = chain.node_at("User".to_string(), "1".to_string(), height).unwrap();
+            let replayed = chain.history.node_at(&chain.blocks, "User".to_string(), "1".to_string(), height).unwrap();
+            results.push((archived, replayed));
+        }
+        let history = chain.edge_history(("User".to_string(), "1".to_string()), ("User".to_string(), "2".to_string()));
+        let reopened = Archive::open(path, &chain.blocks[0], None).unwrap();
+
+        // Then
+        for (archived, replayed) in results {
+            assert_eq!(archived, replayed);
+        }
+        assert_eq!(history.unwrap().len(), 2);
+        assert_eq!(reopened.hashes.len(), chain.blocks.len());
+        assert_eq!(reopened.edge_heights("1", "2"), &[5, 9]);
+        assert!(Archive::open(path, &Block::default(), None).is_ok());
+        assert!(Archive::open(path, &chain.blocks[1], None).is_err());
+
+        // Archive can have blocks which are not in the chain after restart
+        chain.blocks.truncate(6);
+        let history = chain.edge_history(("User".to_string(), "1".to_string()), ("User".to_string(), "2".to_string()));
+        assert_eq!(history.unwrap().len(), 1);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_hide_soft_deleted_nodes_as_history() {
+        // Given
+        let path = env::temp_dir().join("should_hide_soft_deleted_nodes_as_history.jsonl");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let mut chain = Chain::default();
+        chain.archive = Some(Archive::open(path, &chain.blocks[0], None).unwrap());
+        chain.add_node_change(node(NodeOperation::Add, "1", "John")).unwrap();
+        chain.add_node_change(node(NodeOperation::Add, "2", "Jane")).unwrap();
+        chain.add_edge_change("1".to_string(), "2".to_string(), 5).unwrap();
+        chain
+            .add_node_change(NodeData::new(
+                NodeOperation::SoftDelete,
+                "User".to_string(),
+                "2".to_string(),
+                BTreeMap::new(),
+            ))
+            .unwrap();
+        let deleted = chain.blocks.len() - 1;
+        chain.add_node_change(node(NodeOperation::Restore, "2", "Jane")).unwrap();
+        let restored = chain.blocks.len() - 1;
+
+        // When
+        let mut results = vec![];
+        for (identifier, height) in [("1", deleted), ("2", deleted), ("1", restored), ("2", restored)] {
+            let archived = chain.node_at("User".to_string(), identifier.to_string(), height);
+            let replayed = chain.history.node_at(&chain.blocks, "User".to_string(), identifier.to_string(), height);
+            results.push((archived, replayed));
+        }
+
+        // Then
+        assert!(!results[0].0.as_ref().unwrap()[1].contains_key("$to"));
+        assert_eq!(results[1].0.as_ref().err().unwrap().code(), "NODE_NOT_FOUND");
+        assert_eq!(results[3].0.as_ref().unwrap()[0]["name"], "Jane");
+        for (archived, replayed) in results {
+            assert_eq!(archived.map_err(|error| error.code()), replayed.map_err(|error| error.code()));
+        }
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_discard_entries_of_replaced_blocks() {
+        // Given
+        let path = env::temp_dir().join("should_discard_entries_of_replaced_blocks.jsonl");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let mut chain = Chain::default();
+        chain.archive = Some(Archive::open(path, &chain.blocks[0], None).unwrap());
+        chain.add_edge_change("1".to_string(), "2".to_string(), 5).unwrap();
+
+        let mut fork = Chain::default();
+        fork.add_edge_change("1".to_string(), "3".to_string(), 5).unwrap();
+        fork.add_edge_change("1".to_string(), "3".to_string(), 6).unwrap();
+
+        // When
+        chain.replace_chain(&fork.blocks).unwrap();
+
+        // Then
+        let archive = chain.archive.as_ref().unwrap();
+        assert!(archive.edge_heights("1", "2").is_empty());
+        assert_eq!(archive.edge_heights("1", "3"), &[1, 3]);
+        assert_eq!(Archive::open(path, &chain.blocks[0], None).unwrap().hashes, archive.hashes);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_encrypt_archive_lines() {
+        // Given
+        let path = env::temp_dir().join("should_encrypt_archive_lines.jsonl");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let encryption = StorageEncryption::from_passphrase("secret");
+
+        let mut chain = Chain::default();
+        chain.archive = Some(Archive::open(path, &chain.blocks[0], Some(encryption.clone())).unwrap());
+        chain.add_edge_change("1".to_string(), "2".to_string(), 5).unwrap();
+
+        // When
+        let reopened = Archive::open(path, &chain.blocks[0], Some(encryption));
+        let other_key = Archive::open(path, &chain.blocks[0], Some(StorageEncryption::from_passphrase("other")));
+
+        // Then
+        assert!(!fs::read_to_string(path).unwrap().contains(&chain.blocks[0].hash));
+        assert_eq!(reopened.unwrap().edge_heights("1", "2"), &[1]);
+        assert!(other_key.is_err());
+        assert!(Archive::open(path, &chain.blocks[0], None).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,10 +1,17 @@
+use crate::chain::encoding::CanonicalEncode;
 use crate::chain::error::ChainError;
-use crate::chain::wallet::Wallet;
+use crate::chain::genesis::Genesis;
+use crate::chain::hasher::HashAlgorithm;
+use crate::chain::scheme::SignatureAlgorithm;
+use crate::chain::signer::Signer;
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::condition::Condition;
 use derive_more::Constructor;
+use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
-use sha256::digest;
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::time::{SystemTime, UNIX_EPOCH};
-use rustc_hash::FxHashMap;
 
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct Block {
@@ -16,13 +23,57 @@ pub struct Block {
     pub validator: String,
     pub signature: String,
     pub difficulty: usize,
+    /// Format in which block is encoded for hashing, legacy blocks (without version) are hashed as JSON.
+    #[serde(default, skip_serializing_if = "Block::is_legacy_format")]
+    pub version: u8,
+    /// Value which is changed while mining the block, until its hash has required number of leading zero bits.
+    #[serde(default, skip_serializing_if = "Block::has_no_nonce")]
+    pub nonce: u64,
 }
 
-#[derive(Serialize, Deserialize, Constructor, Clone, PartialEq)]
+/// Block without its data, which is enough to validate links and signatures of the chain. Block hash covers the data,
+/// so it's validated once data of the block is received.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct BlockHeader {
+    pub id: usize,
+    pub hash: String,
+    pub previous_hash: String,
+    pub timestamp: u64,
+    pub validator: String,
+    pub signature: String,
+    pub difficulty: usize,
+    #[serde(default, skip_serializing_if = "Block::is_legacy_format")]
+    pub version: u8,
+    #[serde(default, skip_serializing_if = "Block::has_no_nonce")]
+    pub nonce: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct BlockData {
     pub data_type: BlockDataType,
     pub edge_data: Option<EdgeData>,
     pub validator_data: Option<ValidatorData>,
+    // Skipped when empty, so hashes of blocks without reward are not changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reward_data: Option<RewardData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slash_data: Option<SlashData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edge_batch: Option<Vec<EdgeData>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub genesis_data: Option<Genesis>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_rotation_data: Option<KeyRotationData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_data: Option<NodeData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_data: Option<SchemaData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delegation_data: Option<DelegationData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_batch: Option<Vec<NodeData>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_data: Option<AuditData>,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq)]
@@ -30,83 +81,474 @@ pub enum BlockDataType {
     EdgeData,
     ValidatorData,
     RootNode,
+    Reward,
+    SlashData,
+    EdgeBatch,
+    KeyRotation,
+    NodeData,
+    SchemaData,
+    Delegation,
+    NodeBatch,
+    Audit,
 }
 
-#[derive(Serialize, Deserialize, Constructor, Clone, PartialEq)]
+/// Change of edge weight (zero for deleted edge), with time at which added edge expires if it has time to live.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct EdgeData {
     pub from: String,
     pub to: String,
     pub weight: i8,
+    /// Seconds since Unix epoch, skipped for edges without time to live so hashes of existing blocks are not changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
 }
 
-#[derive(Serialize, Deserialize, Constructor, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct ValidatorData {
     pub public_key: String,
     pub account_id: String,
+    /// Signature scheme of the public key, skipped for ed25519 keys so hashes of existing blocks are not changed.
+    #[serde(default, skip_serializing_if = "SignatureAlgorithm::is_default")]
+    pub scheme: SignatureAlgorithm,
+}
+
+/// Reward credited to the validator (public key) which produced the block with given id.
+#[derive(Serialize, Deserialize, Constructor, Clone, PartialEq, Debug)]
+pub struct RewardData {
+    pub public_key: String,
+    pub block_id: usize,
+    pub amount: u64,
+}
+
+/// Penalty for the validator (public key) which signed provably invalid block, block itself is kept as evidence.
+#[derive(Serialize, Deserialize, Constructor, Clone, PartialEq)]
+pub struct SlashData {
+    pub public_key: String,
+    pub evidence: Box<Block>,
+}
+
+/// Rotation of validator key - block is signed by old key, while new key signs old public key to prove its ownership.
+#[derive(Serialize, Deserialize, Constructor, Clone, PartialEq)]
+pub struct KeyRotationData {
+    pub old_public_key: String,
+    pub new_public_key: String,
+    pub new_key_signature: String,
+}
+
+/// Delegation of stake of the node (given by its identifier) which doesn't qualify as agent, to the agent with given
+/// identifier.
+#[derive(Serialize, Deserialize, Constructor, Clone, PartialEq)]
+pub struct DelegationData {
+    pub node_id: String,
+    pub agent_id: String,
+}
+
+/// Change of node with given name and identifier, together with attributes set by the change (none for deleted node).
+/// Attributes are ordered, so hash of the block doesn't depend on order in which they were given. Added node with time
+/// to live also has time at which it expires, so each peer removes it at the same time.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct NodeData {
+    pub operation: NodeOperation,
+    pub name: String,
+    pub id: String,
+    pub attributes: BTreeMap<String, String>,
+    /// Seconds since Unix epoch, skipped for nodes without time to live so hashes of existing blocks are not changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+/// Sequence and hash of the latest entry of the audit log of the validator, so entries which were removed or changed
+/// in the audit log file (even with all hashes recomputed) are found by `verify audit`.
+#[derive(Serialize, Deserialize, Constructor, Clone, PartialEq)]
+pub struct AuditData {
+    pub sequence: u64,
+    pub hash: String,
+}
+
+/// Node definition with its attributes, conditions of its agent if node is defined as agent, whether its nodes can't be
+/// connected to themselves, and its secret attributes (whose values are never stored in the chain).
+#[derive(Serialize, Deserialize, Constructor, Clone, PartialEq)]
+pub struct SchemaData {
+    pub name: String,
+    pub attributes: Vec<String>,
+    pub conditions: Option<Vec<Condition>>,
+    #[serde(default, skip_serializing_if = "SchemaData::allows_self_loops")]
+    pub deny_self_loops: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secret_attributes: Vec<String>,
+}
+
+impl SchemaData {
+    fn allows_self_loops(deny_self_loops: &bool) -> bool {
+        !deny_self_loops
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub enum NodeOperation {
+    Add,
+    Update,
+    Delete,
+    SoftDelete,
+    Restore,
+}
+
+impl EdgeData {
+    pub fn new(from: String, to: String, weight: i8) -> EdgeData {
+        EdgeData {
+            from,
+            to,
+            weight,
+            expires_at: None,
+        }
+    }
+
+    pub fn with_expiry(self, expires_at: Option<u64>) -> EdgeData {
+        EdgeData { expires_at, ..self }
+    }
+}
+
+impl NodeData {
+    pub fn new(operation: NodeOperation, name: String, id: String, attributes: BTreeMap<String, String>) -> NodeData {
+        NodeData {
+            operation,
+            name,
+            id,
+            attributes,
+            expires_at: None,
+        }
+    }
+
+    pub fn with_expiry(self, expires_at: Option<u64>) -> NodeData {
+        NodeData { expires_at, ..self }
+    }
+
+    /// Create node change from attributes of the node, which must contain `$id`. Other internal attributes are not
+    /// part of the change, as they are recalculated by each peer.
+    pub fn from_attributes(operation: NodeOperation, name: String, attributes: &FxHashMap<String, String>) -> NodeData {
+        let id = InternalNodeAttribute::get_identifier(attributes);
+        let attributes = match operation {
+            NodeOperation::Delete | NodeOperation::SoftDelete => BTreeMap::new(),
+            _ => attributes
+                .iter()
+                .filter(|(key, _)| !key.starts_with('$'))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        };
+
+        NodeData::new(operation, name, id, attributes)
+    }
+}
+
+impl ValidatorData {
+    /// Create validator registration with scheme recognized from its public key.
+    pub fn new(public_key: String, account_id: String) -> ValidatorData {
+        ValidatorData {
+            scheme: SignatureAlgorithm::of_public_key(&public_key).unwrap_or_default(),
+            public_key,
+            account_id,
+        }
+    }
+}
+
+impl BlockData {
+    pub fn new(data_type: BlockDataType, edge_data: Option<EdgeData>, validator_data: Option<ValidatorData>) -> BlockData {
+        BlockData {
+            data_type,
+            edge_data,
+            validator_data,
+            reward_data: None,
+            slash_data: None,
+            edge_batch: None,
+            genesis_data: None,
+            key_rotation_data: None,
+            node_data: None,
+            schema_data: None,
+            delegation_data: None,
+            node_batch: None,
+            audit_data: None,
+        }
+    }
+
+    pub fn reward(reward_data: RewardData) -> BlockData {
+        BlockData {
+            reward_data: Some(reward_data),
+            ..BlockData::new(BlockDataType::Reward, None, None)
+        }
+    }
+
+    pub fn slash(slash_data: SlashData) -> BlockData {
+        BlockData {
+            slash_data: Some(slash_data),
+            ..BlockData::new(BlockDataType::SlashData, None, None)
+        }
+    }
+
+    pub fn key_rotation(key_rotation_data: KeyRotationData) -> BlockData {
+        BlockData {
+            key_rotation_data: Some(key_rotation_data),
+            ..BlockData::new(BlockDataType::KeyRotation, None, None)
+        }
+    }
+
+    pub fn node(node_data: NodeData) -> BlockData {
+        BlockData {
+            node_data: Some(node_data),
+            ..BlockData::new(BlockDataType::NodeData, None, None)
+        }
+    }
+
+    pub fn schema(schema_data: SchemaData) -> BlockData {
+        BlockData {
+            schema_data: Some(schema_data),
+            ..BlockData::new(BlockDataType::SchemaData, None, None)
+        }
+    }
+
+    pub fn delegation(delegation_data: DelegationData) -> BlockData {
+        BlockData {
+            delegation_data: Some(delegation_data),
+            ..BlockData::new(BlockDataType::Delegation, None, None)
+        }
+    }
+
+    pub fn audit(audit_data: AuditData) -> BlockData {
+        BlockData {
+            audit_data: Some(audit_data),
+            ..BlockData::new(BlockDataType::Audit, None, None)
+        }
+    }
+
+    /// Multiple edge changes in one block.
+    pub fn batch(edge_batch: Vec<EdgeData>) -> BlockData {
+        BlockData {
+            edge_batch: Some(edge_batch),
+            ..BlockData::new(BlockDataType::EdgeBatch, None, None)
+        }
+    }
+
+    /// Multiple node changes (e.g. nodes added by one command) in one block.
+    pub fn node_batch(node_batch: Vec<NodeData>) -> BlockData {
+        BlockData {
+            node_batch: Some(node_batch),
+            ..BlockData::new(BlockDataType::NodeBatch, None, None)
+        }
+    }
+
+    /// Node changes of the block, either single change or batch.
+    pub fn nodes(&self) -> impl Iterator<Item = &NodeData> {
+        self.node_data.iter().chain(self.node_batch.iter().flatten())
+    }
 }
 
 impl Default for Block {
     fn default() -> Self {
-        Block {
+        Block::genesis(&Genesis::default())
+    }
+}
+
+impl Block {
+    /// Format of blocks hashed as JSON, before canonical encoding was introduced.
+    pub const LEGACY_FORMAT_VERSION: u8 = 0;
+    /// Format of blocks hashed by their canonical encoding, before nonce was introduced.
+    pub const CANONICAL_FORMAT_VERSION: u8 = 1;
+    /// Format of new blocks, which are hashed by their canonical encoding including nonce.
+    pub const FORMAT_VERSION: u8 = 2;
+
+    /// Create genesis block, whose hash is derived from genesis configuration.
+    pub fn genesis(genesis: &Genesis) -> Block {
+        let data = BlockData {
+            genesis_data: Some(genesis.clone()),
+            ..BlockData::new(BlockDataType::RootNode, None, None)
+        };
+
+        let mut block = Block {
             id: 0,
-            hash: "0000494d137e1631bba301d5acab6e7bb7aa74ce1185d456565ef51d737677b2".to_string(),
+            hash: "".to_string(),
             previous_hash: "".to_string(),
-            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
-            data: BlockData::new(BlockDataType::RootNode, None, None),
+            timestamp: genesis.timestamp,
+            data,
             validator: "".to_string(),
             signature: "".to_string(),
             difficulty: 0,
-        }
+            version: genesis.format_version,
+            nonce: 0,
+        };
+        block.hash = block.calculate_hash(genesis.hash_algorithm);
+
+        block
     }
-}
 
-impl Block {
-    pub fn new(id: usize, previous_hash: String, data: BlockData, wallet: &mut Wallet, difficulty: usize) -> Block {
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-        let validator = wallet.get_public_key();
-        let hash = Block::calculate_hash(id, timestamp, &previous_hash, &data, &validator, difficulty);
+    pub fn new(
+        id: usize,
+        previous_hash: String,
+        data: BlockData,
+        signer: &mut dyn Signer,
+        difficulty: usize,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Block, ChainError> {
+        Block::unsigned(id, previous_hash, data, signer.get_public_key(), difficulty, hash_algorithm).sign(signer)
+    }
 
-        Block {
+    /// Create block whose hash starts with given number of zero bits, by searching for nonce of the block.
+    pub fn mine(
+        id: usize,
+        previous_hash: String,
+        data: BlockData,
+        signer: &mut dyn Signer,
+        difficulty: usize,
+        zero_bits: u32,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Block, ChainError> {
+        let mut block = Block::unsigned(id, previous_hash, data, signer.get_public_key(), difficulty, hash_algorithm);
+        block.find_nonce(zero_bits, hash_algorithm);
+
+        block.sign(signer)
+    }
+
+    /// Create block of given validator with current timestamp, which is not yet signed.
+    pub(crate) fn unsigned(
+        id: usize,
+        previous_hash: String,
+        data: BlockData,
+        validator: String,
+        difficulty: usize,
+        hash_algorithm: HashAlgorithm,
+    ) -> Block {
+        let mut block = Block {
             id,
-            data,
-            hash: hash.clone(),
+            hash: "".to_string(),
             previous_hash,
-            timestamp,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            data,
             validator,
-            signature: wallet.sign(&hash),
+            signature: "".to_string(),
             difficulty,
+            version: Self::FORMAT_VERSION,
+            nonce: 0,
+        };
+        block.hash = block.calculate_hash(hash_algorithm);
+
+        block
+    }
+
+    /// Increment nonce (and recalculate hash) until hash of the block starts with given number of zero bits.
+    pub(crate) fn find_nonce(&mut self, zero_bits: u32, hash_algorithm: HashAlgorithm) {
+        while !Block::has_work(&self.hash, zero_bits) {
+            self.nonce += 1;
+            self.hash = self.calculate_hash(hash_algorithm);
         }
     }
 
-    pub fn validate_block_hash(block: &Block) -> Result<(), ChainError> {
-        let hash = Block::calculate_hash(
-            block.id,
-            block.timestamp,
-            &block.previous_hash,
-            &block.data,
-            &block.validator,
-            block.difficulty,
-        );
+    pub(crate) fn sign(mut self, signer: &mut dyn Signer) -> Result<Block, ChainError> {
+        self.signature = signer.sign(&self.hash)?;
+
+        Ok(self)
+    }
+
+    /// Check if hash (hex encoded) starts with given number of zero bits.
+    pub fn has_work(hash: &str, zero_bits: u32) -> bool {
+        let Ok(bytes) = hex::decode(hash) else {
+            return zero_bits == 0;
+        };
 
-        if hash != block.hash {
+        let mut leading_zeros = 0;
+        for byte in bytes {
+            leading_zeros += byte.leading_zeros();
+            if byte != 0 {
+                break;
+            }
+        }
+
+        leading_zeros >= zero_bits
+    }
+
+    /// Create block from its header and data received separately.
+    pub fn from_header(header: BlockHeader, data: BlockData) -> Block {
+        Block {
+            id: header.id,
+            hash: header.hash,
+            previous_hash: header.previous_hash,
+            timestamp: header.timestamp,
+            data,
+            validator: header.validator,
+            signature: header.signature,
+            difficulty: header.difficulty,
+            version: header.version,
+            nonce: header.nonce,
+        }
+    }
+
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            id: self.id,
+            hash: self.hash.clone(),
+            previous_hash: self.previous_hash.clone(),
+            timestamp: self.timestamp,
+            validator: self.validator.clone(),
+            signature: self.signature.clone(),
+            difficulty: self.difficulty,
+            version: self.version,
+            nonce: self.nonce,
+        }
+    }
+
+    /// Hash of the block must match its content, hashed by hash algorithm of the chain.
+    pub fn validate_block_hash(block: &Block, hash_algorithm: HashAlgorithm) -> Result<(), ChainError> {
+        if block.calculate_hash(hash_algorithm) != block.hash {
             return Err(ChainError::BlockHasWrongHashValue(block.id));
         }
 
         Ok(())
     }
 
-    fn calculate_hash(id: usize, timestamp: u64, previous_hash: &str, data: &BlockData, validator: &String, difficulty: usize) -> String {
-        digest(
-            serde_json::json!({
-                "id": id,
-                "timestamp": timestamp,
-                "previous_hash": previous_hash,
-                "data": data,
-                "validator": validator,
-                "difficulty": difficulty,
-            })
-            .to_string(),
-        )
+    /// Format version of the block must be known, and it can't be older than format of the previous block.
+    pub fn validate_format_version(block: &Block, previous_block: &Block) -> Result<(), ChainError> {
+        if block.version > Self::FORMAT_VERSION || block.version < previous_block.version {
+            return Err(ChainError::BlockHasUnsupportedFormatVersion(block.id));
+        }
+
+        Ok(())
+    }
+
+    fn is_legacy_format(version: &u8) -> bool {
+        *version == Self::LEGACY_FORMAT_VERSION
+    }
+
+    fn has_no_nonce(nonce: &u64) -> bool {
+        *nonce == 0
+    }
+
+    /// Hash of all block attributes except hash and signature, nonce is covered only by blocks of current format.
+    fn calculate_hash(&self, hash_algorithm: HashAlgorithm) -> String {
+        if self.version == Self::LEGACY_FORMAT_VERSION {
+            return hash_algorithm.digest(
+                serde_json::json!({
+                    "id": self.id,
+                    "timestamp": self.timestamp,
+                    "previous_hash": self.previous_hash,
+                    "data": self.data,
+                    "validator": self.validator,
+                    "difficulty": self.difficulty,
+                })
+                .to_string()
+                .as_bytes(),
+            );
+        }
+
+        let mut buffer = vec![];
+        self.version.encode(&mut buffer);
+        self.id.encode(&mut buffer);
+        self.timestamp.encode(&mut buffer);
+        self.previous_hash.encode(&mut buffer);
+        self.data.encode(&mut buffer);
+        self.validator.encode(&mut buffer);
+        self.difficulty.encode(&mut buffer);
+        if self.version > Self::CANONICAL_FORMAT_VERSION {
+            self.nonce.encode(&mut buffer);
+        }
+
+        hash_algorithm.digest(buffer.as_slice())
     }
 
     pub fn as_hash_map(&self) -> FxHashMap<String, String> {
@@ -120,14 +562,47 @@ impl Block {
         map.insert("validator".to_string(), self.validator.clone());
         map.insert("signature".to_string(), self.signature.clone());
         map.insert("difficulty".to_string(), self.difficulty.to_string());
+        map.insert("version".to_string(), self.version.to_string());
+        map.insert("nonce".to_string(), self.nonce.to_string());
+
+        map
+    }
+
+    /// Block with its data decoded into separate attributes (e.g. `data.edge_data.weight` or `data.edge_batch.0.from`),
+    /// instead of JSON encoded data attribute.
+    pub fn as_decoded_hash_map(&self) -> FxHashMap<String, String> {
+        let mut map = self.as_hash_map();
+        map.remove("data");
+
+        flatten("data".to_string(), serde_json::to_value(&self.data).unwrap(), &mut map);
 
         map
     }
 }
 
+/// Add each value nested in given value under its path, with keys of objects and indexes of arrays separated by dot.
+/// Missing values are skipped.
+fn flatten(path: String, value: Value, map: &mut FxHashMap<String, String>) {
+    match value {
+        Value::Null => {}
+        Value::Object(object) => object.into_iter().for_each(|(key, value)| flatten(format!("{path}.{key}"), value, map)),
+        Value::Array(array) => array
+            .into_iter()
+            .enumerate()
+            .for_each(|(index, value)| flatten(format!("{path}.{index}"), value, map)),
+        Value::String(value) => {
+            map.insert(path, value);
+        }
+        value => {
+            map.insert(path, value.to_string());
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chain::wallet::Wallet;
 
     #[test]
     fn should_calculate_hash() {
@@ -137,20 +612,193 @@ mod tests {
             None,
             Some(ValidatorData::new("public_key".to_string(), "account_id".to_string())),
         );
-        let block = Block::new(1, "previous_hash".to_string(), block_data, &mut Wallet::default(), 0);
+        let block = Block::new(
+            1,
+            "previous_hash".to_string(),
+            block_data,
+            &mut Wallet::default(),
+            0,
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
 
         // When
-        let hash = Block::calculate_hash(
-            block.id,
-            block.timestamp,
-            &block.previous_hash,
-            &block.data,
-            &block.validator,
-            block.difficulty,
-        );
+        let hash = block.calculate_hash(HashAlgorithm::Sha256);
 
         // Then
         assert_eq!(block.hash, hash);
-        assert!(Block::validate_block_hash(&block).is_ok());
+        assert!(Block::validate_block_hash(&block, HashAlgorithm::Sha256).is_ok());
+    }
+
+    #[test]
+    fn should_mine_block() {
+        // Given
+        let block_data = BlockData::new(BlockDataType::EdgeData, None, None);
+
+        // When
+        let block = Block::mine(
+            1,
+            "previous_hash".to_string(),
+            block_data,
+            &mut Wallet::default(),
+            0,
+            8,
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
+        let tampered_block = Block {
+            nonce: block.nonce + 1,
+            ..block.clone()
+        };
+
+        // Then
+        assert!(block.hash.starts_with("00"));
+        assert!(Block::validate_block_hash(&block, HashAlgorithm::Sha256).is_ok());
+        assert!(Block::validate_block_hash(&tampered_block, HashAlgorithm::Sha256).is_err());
+        assert!(Block::has_work("0fff", 4));
+        assert!(!Block::has_work("0fff", 5));
+        assert!(!Block::has_work("invalid", 1));
+    }
+
+    #[test]
+    fn should_derive_genesis_hash_from_configuration() {
+        // Given
+        let genesis = Genesis {
+            chain_id: "other".to_string(),
+            ..Genesis::default()
+        };
+
+        // When
+        let (default_block, other_block) = (Block::default(), Block::genesis(&genesis));
+
+        // Then
+        assert!(default_block == Block::default());
+        assert_ne!(default_block.hash, other_block.hash);
+        assert!(Block::validate_block_hash(&other_block, HashAlgorithm::Sha256).is_ok());
+    }
+
+    #[test]
+    fn should_hash_blocks_with_hash_algorithm_of_genesis() {
+        // Given
+        let genesis = Genesis {
+            hash_algorithm: HashAlgorithm::Blake3,
+            ..Genesis::default()
+        };
+        let block_data = BlockData::new(BlockDataType::EdgeData, None, None);
+
+        // When
+        let genesis_block = Block::genesis(&genesis);
+        let block = Block::new(
+            1,
+            genesis_block.hash.clone(),
+            block_data,
+            &mut Wallet::default(),
+            0,
+            HashAlgorithm::Blake3,
+        )
+        .unwrap();
+
+        // Then
+        assert_ne!(genesis_block.hash, Block::default().hash);
+        assert!(Block::validate_block_hash(&genesis_block, HashAlgorithm::Blake3).is_ok());
+        assert!(Block::validate_block_hash(&block, HashAlgorithm::Blake3).is_ok());
+        assert!(Block::validate_block_hash(&block, HashAlgorithm::Sha256).is_err());
+        assert!(serde_json::to_string(&genesis).unwrap().contains("\"hash_algorithm\":\"blake3\""));
+        assert!(!serde_json::to_string(&Genesis::default()).unwrap().contains("hash_algorithm"));
+    }
+
+    #[test]
+    fn should_keep_hash_of_legacy_genesis_block() {
+        // Given
+        let genesis = Genesis {
+            format_version: Block::LEGACY_FORMAT_VERSION,
+            ..Genesis::default()
+        };
+
+        // When
+        let block = Block::genesis(&genesis);
+
+        // Then
+        assert_eq!(block.hash, "bf57853712e33986f697daf410f00a01ecb2a96560c80f281cf202cff91dcbf4");
+        assert!(!serde_json::to_string(&block).unwrap().contains("version"));
+        assert_ne!(block.hash, Block::default().hash);
+    }
+
+    #[test]
+    fn should_validate_format_version() {
+        // Given
+        let legacy_block = Block::genesis(&Genesis {
+            format_version: Block::LEGACY_FORMAT_VERSION,
+            ..Genesis::default()
+        });
+        let block = Block::new(
+            1,
+            legacy_block.hash.clone(),
+            BlockData::new(BlockDataType::EdgeData, None, None),
+            &mut Wallet::default(),
+            0,
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
+        let downgraded_block = Block {
+            version: Block::LEGACY_FORMAT_VERSION,
+            ..block.clone()
+        };
+        let newer_block = Block {
+            version: Block::FORMAT_VERSION + 1,
+            ..block.clone()
+        };
+
+        // When
+        let upgraded = Block::validate_format_version(&block, &legacy_block);
+        let downgraded = Block::validate_format_version(&downgraded_block, &block);
+        let newer = Block::validate_format_version(&newer_block, &block);
+
+        // Then
+        assert!(upgraded.is_ok());
+        assert!(downgraded.is_err());
+        assert!(newer.is_err());
+    }
+
+    #[test]
+    fn should_not_serialize_empty_reward() {
+        // Given
+        let block_data = BlockData::new(BlockDataType::EdgeData, None, None);
+
+        // When
+        let json = serde_json::to_string(&block_data).unwrap();
+
+        // Then
+        assert!(!json.contains("reward_data"));
+        assert!(serde_json::from_str::<BlockData>(&json).unwrap() == block_data);
+    }
+
+    #[test]
+    fn should_decode_block_data() {
+        // Given
+        let edges = vec![
+            EdgeData::new("from".to_string(), "to".to_string(), 5),
+            EdgeData::new("to".to_string(), "from".to_string(), -5),
+        ];
+        let block = Block::new(
+            1,
+            "previous_hash".to_string(),
+            BlockData::batch(edges),
+            &mut Wallet::default(),
+            0,
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
+
+        // When
+        let map = block.as_decoded_hash_map();
+
+        // Then
+        assert!(!map.contains_key("data"));
+        assert!(!map.contains_key("data.edge_data"));
+        assert_eq!(map.get("id").unwrap(), "1");
+        assert_eq!(map.get("data.data_type").unwrap(), "EdgeBatch");
+        assert_eq!(map.get("data.edge_batch.0.from").unwrap(), "from");
+        assert_eq!(map.get("data.edge_batch.1.weight").unwrap(), "-5");
     }
 }
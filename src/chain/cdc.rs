@@ -0,0 +1,449 @@
+use crate::chain::block::Block;
+use crate::chain::encryption::{LineFile, StorageEncryption};
+use crate::chain::error::ChainError;
+use crate::chain::http;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+/// Destination of change events, which accepts each batch as a whole or fails, so failed batch is delivered again.
+pub trait ChangeSink: Send + Sync {
+    fn deliver(&mut self, events: &[Value]) -> Result<(), String>;
+}
+
+/// Events appended to file as JSON lines (encrypted as archive, if storage encryption is configured).
+pub struct FileSink {
+    file: LineFile,
+}
+
+/// Events written as JSON lines to Unix socket, connection is kept open and made again after failure.
+pub struct UnixSink {
+    path: String,
+    stream: Option<UnixStream>,
+}
+
+/// Events produced to Kafka topic through Kafka REST API (e.g. Confluent REST Proxy or Redpanda HTTP Proxy), with
+/// block height as record key.
+pub struct KafkaSink {
+    host: String,
+    path: String,
+}
+
+impl ChangeSink for FileSink {
+    fn deliver(&mut self, events: &[Value]) -> Result<(), String> {
+        let lines: Vec<String> = events.iter().map(Value::to_string).collect();
+
+        self.file.append(&lines)
+    }
+}
+
+impl ChangeSink for UnixSink {
+    fn deliver(&mut self, events: &[Value]) -> Result<(), String> {
+        let lines: String = events.iter().map(|event| format!("{event}\n")).collect();
+
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => self.stream.insert(UnixStream::connect(&self.path).map_err(|error| error.to_string())?),
+        };
+
+        stream.write_all(lines.as_bytes()).map_err(|error| {
+            self.stream = None;
+            error.to_string()
+        })
+    }
+}
+
+impl ChangeSink for KafkaSink {
+    fn deliver(&mut self, events: &[Value]) -> Result<(), String> {
+        let records: Vec<Value> = events
+            .iter()
+            .map(|event| json!({ "key": event["offset"].to_string(), "value": event }))
+            .collect();
+        let body = json!({ "records": records }).to_string();
+
+        let content_type = ("Content-Type", "application/vnd.kafka.json.v2+json".to_string());
+        http::post(&self.host, &self.path, &[content_type], &body).map_err(|error| format!("Records were not accepted: {error}"))
+    }
+}
+
+/// Offset of the next block to deliver, together with hash of the last delivered block.
+#[derive(Serialize, Deserialize, Default, Clone)]
+struct Offset {
+    offset: usize,
+    hash: Option<String>,
+}
+
+/// Change data capture - each block appended to the chain is delivered to the sink as block event followed by events of
+/// graph mutations (definitions, nodes and edges) of the block, e.g.
+/// `{"chain_id":"weighted-graph","offset":5,"index":1,"block_hash":"...","type":"node","change":{...}}`. Block height
+/// is the offset of its events, which is stored after each delivered block, so delivery resumes from it after restart.
+/// Block can be delivered again (if node stops before offset is stored, or if delivered blocks are replaced by fork,
+/// which restarts delivery from genesis block), so consumers deduplicate events by offset and index.
+pub struct ChangeStream {
+    // Sink is taken by delivery while its blocks are being delivered
+    sink: Option<Box<dyn ChangeSink>>,
+    name: String,
+    offsets: Option<String>,
+    offset: Offset,
+}
+
+/// Events of blocks after the stored offset, taken from the change stream together with its sink, so they can be
+/// delivered (e.g. in blocking task) without holding the chain.
+pub struct ChangeDelivery {
+    sink: Box<dyn ChangeSink>,
+    name: String,
+    offsets: Option<String>,
+    blocks: Vec<(Offset, Vec<Value>)>,
+    delivered: Option<Offset>,
+    count: usize,
+    error: Option<ChainError>,
+}
+
+impl ChangeDelivery {
+    /// Deliver events of each block to the sink and store its offset. Delivery stops on the first failed block.
+    pub fn deliver(mut self) -> ChangeDelivery {
+        for (offset, events) in &self.blocks {
+            let delivered = self
+                .sink
+                .deliver(events)
+                .map_err(|error| ChainError::ChangeStreamError(self.name.clone(), error))
+                .and_then(|_| ChangeStream::store(self.offsets.as_deref(), offset));
+
+            if let Err(error) = delivered {
+                self.error = Some(error);
+                break;
+            }
+            self.delivered = Some(offset.clone());
+            self.count += 1;
+        }
+
+        self
+    }
+}
+
+impl ChangeStream {
+    /// Blocks delivered by one delivery, so progress is stored regularly while the whole chain is synced.
+    const MAX_BLOCKS: usize = 64;
+
+    /// Create change stream from command line arguments, if sink is given: `--cdc-sink="file:changes.jsonl"`,
+    /// `--cdc-sink="unix:/run/cdc.sock"` or `--cdc-sink="kafka:127.0.0.1:8082/topics/graph"`, with offset stored in
+    /// `--cdc-offsets="cdc.offset"` file (without it, all blocks are delivered again after restart). File sink is
+    /// encrypted with given storage encryption.
+    pub fn from_arguments(
+        arguments: &HashMap<String, Vec<String>>,
+        encryption: Option<StorageEncryption>,
+    ) -> Result<Option<ChangeStream>, ChainError> {
+        let argument = |name: &str| arguments.get(name).and_then(|values| values.first());
+
+        let Some(sink) = argument("cdc-sink") else {
+            return Ok(None);
+        };
+
+        Self::new(sink, argument("cdc-offsets").map(String::as_str), encryption).map(Some)
+    }
+
+    pub fn new(sink: &str, offsets: Option<&str>, encryption: Option<StorageEncryption>) -> Result<ChangeStream, ChainError> {
+        let (kind, target) = sink
+            .split_once(':')
+            .ok_or(ChainError::InvalidChangeStreamConfiguration(format!("cdc-sink={sink}")))?;
+
+        let destination: Box<dyn ChangeSink> = match kind {
+            "file" => Box::new(FileSink {
+                file: LineFile::new(target, encryption),
+            }),
+            "unix" => Box::new(UnixSink {
+                path: target.to_string(),
+                stream: None,
+            }),
+            "kafka" => {
+                let (host, topic) = target.split_once('/').ok_or(ChainError::InvalidChangeStreamConfiguration(format!(
+                    "cdc-sink={sink} (topic path is required)"
+                )))?;
+                Box::new(KafkaSink {
+                    host: host.to_string(),
+                    path: format!("/{topic}"),
+                })
+            }
+            _ => return Err(ChainError::InvalidChangeStreamConfiguration(format!("cdc-sink={sink}"))),
+        };
+
+        let offset = match offsets {
+            Some(path) if Path::new(path).exists() => fs::read_to_string(path)
+                .map_err(|error| error.to_string())
+                .and_then(|content| serde_json::from_str(&content).map_err(|error| error.to_string()))
+                .map_err(|error| ChainError::ChangeStreamError(path.to_string(), error))?,
+            _ => Offset::default(),
+        };
+
+        Ok(ChangeStream {
+            sink: Some(destination),
+            name: sink.to_string(),
+            offsets: offsets.map(str::to_string),
+            offset,
+        })
+    }
+
+    /// Offset of the next block to deliver.
+    pub fn offset(&self) -> usize {
+        self.offset.offset
+    }
+
+    /// Deliver blocks after the stored offset, and return number of delivered blocks. Delivery waits until the chain
+    /// is synced to the offset, and it stops on the first failed block, which is delivered again on next call.
+    pub fn capture(&mut self, blocks: &[Block]) -> Result<usize, ChainError> {
+        match self.delivery(blocks) {
+            Some(delivery) => self.complete(delivery.deliver()),
+            None => Ok(0),
+        }
+    }
+
+    /// Take events of blocks after the stored offset for delivery, or none if there is nothing to deliver (or previous
+    /// delivery isn't completed yet). Delivery waits until the chain is synced to the offset.
+    pub fn delivery(&mut self, blocks: &[Block]) -> Option<ChangeDelivery> {
+        let previous = self.offset.offset.checked_sub(1).map(|height| blocks.get(height));
+        match previous {
+            // Chain is not synced to the offset yet
+            Some(None) => return None,
+            Some(Some(block)) if self.offset.hash.as_ref() != Some(&block.hash) => self.offset = Offset::default(),
+            _ => {}
+        }
+
+        let end = blocks.len().min(self.offset.offset + Self::MAX_BLOCKS);
+        if self.offset.offset >= end {
+            return None;
+        }
+
+        let chain_id = blocks
+            .first()
+            .and_then(|block| block.data.genesis_data.as_ref())
+            .map(|genesis| genesis.chain_id.clone())
+            .unwrap_or_default();
+
+        Some(ChangeDelivery {
+            sink: self.sink.take()?,
+            name: self.name.clone(),
+            offsets: self.offsets.clone(),
+            blocks: blocks[self.offset.offset..end]
+                .iter()
+                .map(|block| {
+                    let offset = Offset {
+                        offset: block.id + 1,
+                        hash: Some(block.hash.clone()),
+                    };
+                    (offset, Self::events(&chain_id, block))
+                })
+                .collect(),
+            delivered: None,
+            count: 0,
+            error: None,
+        })
+    }
+
+    /// Return sink of the delivery to the change stream, and move the offset after delivered blocks. Returns number of
+    /// delivered blocks, or error of the first failed block.
+    pub fn complete(&mut self, delivery: ChangeDelivery) -> Result<usize, ChainError> {
+        self.sink = Some(delivery.sink);
+        if let Some(offset) = delivery.delivered {
+            self.offset = offset;
+        }
+
+        match delivery.error {
+            Some(error) => Err(error),
+            None => Ok(delivery.count),
+        }
+    }
+
+    fn events(chain_id: &str, block: &Block) -> Vec<Value> {
+        let data = &block.data;
+        let mut changes = vec![(
+            "block",
+            json!({
+                "previous_hash": block.previous_hash,
+                "timestamp": block.timestamp,
+                "validator": block.validator,
+                "data_type": data.data_type,
+            }),
+        )];
+
+        if let Some(schema) = &data.schema_data {
+            changes.push(("definition", json!(schema)));
+        }
+        for node in data.nodes() {
+            changes.push(("node", json!(node)));
+        }
+        for edge in data.edge_data.iter().chain(data.edge_batch.iter().flatten()) {
+            changes.push(("edge", json!(edge)));
+        }
+
+        changes
+            .into_iter()
+            .enumerate()
+            .map(|(index, (kind, change))| {
+                json!({
+                    "chain_id": chain_id,
+                    "offset": block.id,
+                    "index": index,
+                    "block_hash": block.hash,
+                    "type": kind,
+                    "change": change,
+                })
+            })
+            .collect()
+    }
+
+    fn store(offsets: Option<&str>, offset: &Offset) -> Result<(), ChainError> {
+        let Some(path) = offsets else {
+            return Ok(());
+        };
+
+        serde_json::to_string(offset)
+            .map_err(|error| error.to_string())
+            .and_then(|content| fs::write(path, content).map_err(|error| error.to_string()))
+            .map_err(|error| ChainError::ChangeStreamError(path.to_string(), error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::block::{NodeData, NodeOperation};
+    use crate::chain::Chain;
+    use std::collections::BTreeMap;
+    use std::env;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn should_deliver_changes_from_stored_offset() {
+        // Given
+        let sink = env::temp_dir().join("should_deliver_changes_from_stored_offset.jsonl");
+        let offsets = env::temp_dir().join("should_deliver_changes_from_stored_offset.offset");
+        let (sink, offsets) = (sink.to_str().unwrap(), offsets.to_str().unwrap());
+        let _ = fs::remove_file(sink);
+        let _ = fs::remove_file(offsets);
+
+        let mut chain = Chain::default();
+        let node = NodeData::new(
+            NodeOperation::Add,
+            "User".to_string(),
+            "1".to_string(),
+            BTreeMap::from([("name".to_string(), "John".to_string())]),
+        );
+        chain.add_node_change(node).unwrap();
+        let mut stream = ChangeStream::new(&format!("file:{sink}"), Some(offsets), None).unwrap();
+
+        let mut fork = Chain::default();
+        for weight in 1..=5 {
+            fork.add_edge_change("1".to_string(), "3".to_string(), weight).unwrap();
+        }
+
+        // When
+        let delivered = stream.capture(&chain.blocks).unwrap();
+        let height = chain.blocks.len();
+        chain.add_edge_change("1".to_string(), "2".to_string(), 5).unwrap();
+        let reopen = || ChangeStream::new(&format!("file:{sink}"), Some(offsets), None).unwrap();
+        let resumed = reopen().capture(&chain.blocks).unwrap();
+        let unsynced = reopen().capture(&chain.blocks[..1]).unwrap();
+        let forked = reopen().capture(&fork.blocks).unwrap();
+
+        // Then
+        let events: Vec<Value> = fs::read_to_string(sink)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(delivered, height);
+        assert_eq!(resumed, chain.blocks.len() - height);
+        assert_eq!(unsynced, 0);
+        assert_eq!(forked, fork.blocks.len());
+        assert!(events
+            .iter()
+            .any(|event| event["type"] == "node" && event["change"]["attributes"]["name"] == "John"));
+        assert!(events.iter().any(|event| event["type"] == "edge" && event["change"]["weight"] == 5));
+        assert_eq!(
+            events.iter().filter(|event| event["type"] == "block").count(),
+            chain.blocks.len() + fork.blocks.len()
+        );
+        assert!(ChangeStream::new("ftp:changes", None, None).is_err());
+
+        fs::remove_file(sink).unwrap();
+        fs::remove_file(offsets).unwrap();
+    }
+
+    #[test]
+    fn should_deliver_changes_outside_of_chain() {
+        // Given
+        let sink = env::temp_dir().join("should_deliver_changes_outside_of_chain.jsonl");
+        let sink = sink.to_str().unwrap();
+        let _ = fs::remove_file(sink);
+
+        let mut chain = Chain {
+            changes: Some(ChangeStream::new(&format!("file:{sink}"), None, None).unwrap()),
+            ..Chain::default()
+        };
+        chain.add_edge_change("1".to_string(), "2".to_string(), 5).unwrap();
+
+        // When
+        let job = chain.delivery_job().unwrap();
+        let concurrent = chain.delivery_job();
+        let undelivered = fs::metadata(sink).is_ok();
+        chain.complete_delivery(job.deliver()).unwrap();
+        let completed = chain.delivery_job();
+
+        // Then
+        assert!(concurrent.is_none());
+        assert!(!undelivered);
+        assert!(completed.is_none());
+        assert_eq!(chain.changes.as_ref().unwrap().offset(), chain.blocks.len());
+        assert_eq!(
+            fs::read_to_string(sink)
+                .unwrap()
+                .lines()
+                .filter(|line| line.contains("\"type\":\"block\""))
+                .count(),
+            chain.blocks.len()
+        );
+
+        fs::remove_file(sink).unwrap();
+    }
+
+    #[test]
+    fn should_produce_changes_to_kafka_rest_endpoint() {
+        // Given
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let responses = ["HTTP/1.1 500 Internal Server Error\r\n\r\n", "HTTP/1.1 200 OK\r\n\r\n"];
+            responses.map(|response| {
+                let (mut connection, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                let mut buffer = [0; 4096];
+                while !request.ends_with("]}") {
+                    let length = connection.read(&mut buffer).unwrap();
+                    request.push_str(&String::from_utf8_lossy(&buffer[..length]));
+                }
+                connection.write_all(response.as_bytes()).unwrap();
+                request
+            })
+        });
+        let mut stream = ChangeStream::new(&format!("kafka:{address}/topics/graph"), None, None).unwrap();
+        let chain = Chain::default();
+
+        // When
+        let failed = stream.capture(&chain.blocks);
+        let delivered = stream.capture(&chain.blocks).unwrap();
+
+        // Then
+        let requests = server.join().unwrap();
+        assert_eq!(failed.err().unwrap().code(), "CHANGE_STREAM_ERROR");
+        assert_eq!(delivered, 1);
+        assert_eq!(stream.offset(), 1);
+        assert!(requests[1].starts_with("POST /topics/graph HTTP/1.1"));
+        assert!(requests[1].contains("\"records\":[{\"key\":\"0\""));
+    }
+}
@@ -0,0 +1,101 @@
+use crate::chain::block::Block;
+use crate::chain::error::ChainError;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Tolerance of block timestamps to clock skew between peers. Block can't be older than its previous block, and it
+/// can't be ahead of local clock by more than maximum drift (in seconds).
+#[derive(Clone, PartialEq, Debug)]
+pub struct ClockTolerance {
+    max_drift: u64,
+}
+
+impl Default for ClockTolerance {
+    fn default() -> Self {
+        ClockTolerance {
+            max_drift: Self::DEFAULT_MAX_DRIFT,
+        }
+    }
+}
+
+impl ClockTolerance {
+    const DEFAULT_MAX_DRIFT: u64 = 15;
+
+    /// Create tolerance from command line arguments: `-max_clock_drift="15"` (in seconds).
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<ClockTolerance, ChainError> {
+        let max_drift = arguments
+            .get("max_clock_drift")
+            .and_then(|values| values.first())
+            .map_or(Ok(Self::DEFAULT_MAX_DRIFT), |value| {
+                value
+                    .parse()
+                    .map_err(|_| ChainError::InvalidClockConfiguration(format!("max_clock_drift={value}")))
+            })?;
+
+        Ok(ClockTolerance { max_drift })
+    }
+
+    pub fn max_drift(&self) -> u64 {
+        self.max_drift
+    }
+
+    pub fn validate(&self, block: &Block, previous_block: &Block) -> Result<(), ChainError> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        if block.timestamp < previous_block.timestamp || block.timestamp > now + self.max_drift {
+            return Err(ChainError::BlockHasInvalidTimestamp(block.id));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::block::{BlockData, BlockDataType};
+    use crate::chain::hasher::HashAlgorithm;
+    use crate::chain::wallet::Wallet;
+
+    fn block(id: usize, timestamp: u64) -> Block {
+        let data = BlockData::new(BlockDataType::EdgeData, None, None);
+        let mut block = Block::new(id, "previous_hash".to_string(), data, &mut Wallet::default(), 0, HashAlgorithm::Sha256).unwrap();
+        block.timestamp = timestamp;
+        block
+    }
+
+    #[test]
+    fn should_validate_timestamp() {
+        // Given
+        let tolerance = ClockTolerance::default();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let previous_block = block(1, now - 10);
+
+        // When
+        let valid = tolerance.validate(&block(2, now), &previous_block);
+        let same_second = tolerance.validate(&block(2, now - 10), &previous_block);
+        let before_previous = tolerance.validate(&block(2, now - 11), &previous_block);
+        let in_future = tolerance.validate(&block(2, now + 60), &previous_block);
+
+        // Then
+        assert!(valid.is_ok());
+        assert!(same_second.is_ok());
+        assert_eq!(before_previous.unwrap_err().code(), "BLOCK_HAS_INVALID_TIMESTAMP");
+        assert_eq!(in_future.unwrap_err().code(), "BLOCK_HAS_INVALID_TIMESTAMP");
+    }
+
+    #[test]
+    fn should_parse_arguments() {
+        // Given
+        let valid = HashMap::from([("max_clock_drift".to_string(), vec!["60".to_string()])]);
+        let invalid = HashMap::from([("max_clock_drift".to_string(), vec!["-1".to_string()])]);
+
+        // When
+        let tolerance = ClockTolerance::from_arguments(&valid).unwrap();
+
+        // Then
+        assert_eq!(tolerance.max_drift, 60);
+        assert_eq!(ClockTolerance::from_arguments(&HashMap::new()).unwrap(), ClockTolerance::default());
+        assert!(ClockTolerance::from_arguments(&invalid).is_err());
+    }
+}
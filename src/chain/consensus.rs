@@ -0,0 +1,102 @@
+use crate::chain::block::{Block, BlockDataType};
+use crate::chain::difficulty::DifficultyAlgorithm;
+use crate::chain::error::ChainError;
+use std::collections::HashMap;
+
+/// Consensus used by the chain to decide which validator can add the next block.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub enum Consensus {
+    /// Blocks are proposed by scheduled validators, with difficulty limited by their stake.
+    #[default]
+    ProofOfStake,
+    /// Any validator can add the block, once it finds nonce for which hash of the block starts with given number of
+    /// zero bits. Reward, slash and key rotation blocks don't require work, as they are validated by their own rules.
+    ProofOfWork { zero_bits: u32 },
+}
+
+impl Consensus {
+    const DEFAULT_ZERO_BITS: u32 = 16;
+    const MAX_ZERO_BITS: u32 = 64;
+
+    /// Create consensus from command line arguments: `-consensus="pow" -zero_bits="16"` (`pos` by default).
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<Consensus, ChainError> {
+        let argument = |name: &str| arguments.get(name).and_then(|values| values.first());
+
+        match argument("consensus").map(|value| value.as_str()) {
+            None | Some("pos") => Ok(Consensus::ProofOfStake),
+            Some("pow") => {
+                let zero_bits = argument("zero_bits").map_or(Ok(Self::DEFAULT_ZERO_BITS), |value| {
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|zero_bits| (1..=Self::MAX_ZERO_BITS).contains(zero_bits))
+                        .ok_or(ChainError::InvalidConsensusConfiguration(format!("zero_bits={value}")))
+                })?;
+
+                Ok(Consensus::ProofOfWork { zero_bits })
+            }
+            Some(value) => Err(ChainError::InvalidConsensusConfiguration(format!("consensus={value}"))),
+        }
+    }
+
+    pub fn is_proof_of_work(&self) -> bool {
+        matches!(self, Consensus::ProofOfWork { .. })
+    }
+
+    /// Number of zero bits required at the start of hash of the block with given type.
+    pub fn required_work(&self, data_type: &BlockDataType) -> u32 {
+        match self {
+            Consensus::ProofOfWork { zero_bits } if DifficultyAlgorithm::requires_difficulty(data_type) => *zero_bits,
+            _ => 0,
+        }
+    }
+
+    pub fn validate_work(&self, block: &Block) -> Result<(), ChainError> {
+        if !Block::has_work(&block.hash, self.required_work(&block.data.data_type)) {
+            return Err(ChainError::BlockHasInsufficientWork(block.id));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_create_consensus_from_arguments() {
+        // Given
+        let arguments = |values: &[(&str, &str)]| -> HashMap<String, Vec<String>> {
+            values.iter().map(|(name, value)| (name.to_string(), vec![value.to_string()])).collect()
+        };
+
+        // When
+        let default = Consensus::from_arguments(&arguments(&[]));
+        let proof_of_work = Consensus::from_arguments(&arguments(&[("consensus", "pow"), ("zero_bits", "8")]));
+        let invalid_zero_bits = Consensus::from_arguments(&arguments(&[("consensus", "pow"), ("zero_bits", "0")]));
+        let invalid_consensus = Consensus::from_arguments(&arguments(&[("consensus", "other")]));
+
+        // Then
+        assert_eq!(default.unwrap(), Consensus::ProofOfStake);
+        assert_eq!(proof_of_work.unwrap(), Consensus::ProofOfWork { zero_bits: 8 });
+        assert!(invalid_zero_bits.is_err());
+        assert!(invalid_consensus.is_err());
+    }
+
+    #[test]
+    fn should_require_work_only_for_produced_blocks() {
+        // Given
+        let consensus = Consensus::ProofOfWork { zero_bits: 8 };
+
+        // When
+        let edge_work = consensus.required_work(&BlockDataType::EdgeData);
+        let reward_work = consensus.required_work(&BlockDataType::Reward);
+        let stake_work = Consensus::ProofOfStake.required_work(&BlockDataType::EdgeData);
+
+        // Then
+        assert_eq!(edge_work, 8);
+        assert_eq!(reward_work, 0);
+        assert_eq!(stake_work, 0);
+    }
+}
@@ -0,0 +1,20 @@
+use crate::chain::cdc::ChangeDelivery;
+use crate::chain::webhook::WebhookDelivery;
+
+/// Work of the chain which talks to external systems (blocks for change stream and webhook payloads), taken from the
+/// chain so it can be done in blocking task, without holding the chain while slow sink or endpoint answers. Job is
+/// returned to the chain once it's done, so its progress is stored.
+pub struct DeliveryJob {
+    pub(crate) changes: Option<ChangeDelivery>,
+    pub(crate) webhooks: Option<WebhookDelivery>,
+}
+
+impl DeliveryJob {
+    /// Deliver all work of the job, which can take a while if external systems are slow or unreachable.
+    pub fn deliver(mut self) -> DeliveryJob {
+        self.changes = self.changes.map(ChangeDelivery::deliver);
+        self.webhooks = self.webhooks.map(WebhookDelivery::send);
+
+        self
+    }
+}
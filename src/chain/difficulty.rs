@@ -0,0 +1,161 @@
+use crate::chain::block::{Block, BlockDataType};
+use crate::chain::error::ChainError;
+use std::collections::HashMap;
+
+/// Algorithm used to calculate difficulty required for the next block in the chain.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub enum DifficultyAlgorithm {
+    /// No difficulty is required, block only can't have bigger difficulty than stake of its validator.
+    #[default]
+    Fixed,
+    /// Difficulty is given percentage of total network stake, increased when blocks are produced faster than target
+    /// interval (in seconds) and decreased when they are produced slower. It can't be bigger than the largest stake of
+    /// a single validator, as no validator could produce the block otherwise.
+    Proportional { target_interval: u64, stake_share: usize },
+}
+
+impl DifficultyAlgorithm {
+    const DEFAULT_TARGET_INTERVAL: u64 = 10;
+    const DEFAULT_STAKE_SHARE: usize = 50;
+
+    /// Create algorithm from command line arguments, e.g.
+    /// `-difficulty="proportional" -target_interval="10" -stake_share="50"`.
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<DifficultyAlgorithm, ChainError> {
+        let argument = |name: &str| arguments.get(name).and_then(|values| values.first());
+
+        match argument("difficulty").map(|value| value.as_str()) {
+            None | Some("fixed") => Ok(DifficultyAlgorithm::Fixed),
+            Some("proportional") => {
+                let target_interval = argument("target_interval").map_or(Ok(Self::DEFAULT_TARGET_INTERVAL), |value| {
+                    value
+                        .parse()
+                        .map_err(|_| ChainError::InvalidDifficultyConfiguration(format!("target_interval={value}")))
+                })?;
+                let stake_share = argument("stake_share").map_or(Ok(Self::DEFAULT_STAKE_SHARE), |value| {
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|share| *share <= 100)
+                        .ok_or(ChainError::InvalidDifficultyConfiguration(format!("stake_share={value}")))
+                })?;
+
+                Ok(DifficultyAlgorithm::Proportional {
+                    target_interval,
+                    stake_share,
+                })
+            }
+            Some(value) => Err(ChainError::InvalidDifficultyConfiguration(format!("difficulty={value}"))),
+        }
+    }
+
+    /// Difficulty required for the block following given blocks, with total stake and the largest stake of validators.
+    /// Interval is measured between last two blocks produced by validators, as reward and slash blocks are added right
+    /// after the block which caused them.
+    pub fn required_difficulty(&self, blocks: &[Block], total_stake: usize, max_stake: usize) -> usize {
+        match self {
+            DifficultyAlgorithm::Fixed => 0,
+            DifficultyAlgorithm::Proportional {
+                target_interval,
+                stake_share,
+            } => {
+                let base = (total_stake * stake_share).div_ceil(100);
+
+                let mut produced = blocks.iter().rev().filter(|block| Self::requires_difficulty(&block.data.data_type));
+                let interval = match (produced.next(), produced.next()) {
+                    (Some(last), Some(previous)) => last.timestamp.saturating_sub(previous.timestamp).max(1),
+                    _ => return base.min(max_stake),
+                };
+
+                ((base as u64 * target_interval).div_ceil(interval) as usize).min(max_stake)
+            }
+        }
+    }
+
+    /// Reward, slash and key rotation blocks are validated by their own rules, so they don't need required difficulty.
+    pub fn requires_difficulty(data_type: &BlockDataType) -> bool {
+        matches!(
+            data_type,
+            BlockDataType::EdgeData
+                | BlockDataType::EdgeBatch
+                | BlockDataType::ValidatorData
+                | BlockDataType::NodeData
+                | BlockDataType::NodeBatch
+                | BlockDataType::SchemaData
+                | BlockDataType::Delegation
+                | BlockDataType::Audit
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::block::BlockData;
+
+    #[test]
+    fn should_increase_difficulty_for_fast_blocks() {
+        // Given
+        let algorithm = DifficultyAlgorithm::Proportional {
+            target_interval: 10,
+            stake_share: 50,
+        };
+        let blocks = vec![create_block(100), create_block(105)];
+
+        // When
+        let difficulty = (
+            algorithm.required_difficulty(&blocks[..1], 10, 10),
+            algorithm.required_difficulty(&blocks, 10, 10),
+            algorithm.required_difficulty(&[create_block(100), create_block(200)], 10, 10),
+        );
+
+        // Then
+        assert_eq!(difficulty, (5, 10, 1));
+    }
+
+    #[test]
+    fn should_limit_difficulty_to_largest_stake() {
+        // Given
+        let algorithm = DifficultyAlgorithm::Proportional {
+            target_interval: 10,
+            stake_share: 50,
+        };
+        let blocks = vec![create_block(100), create_block(105)];
+
+        // When
+        let difficulty = (
+            algorithm.required_difficulty(&blocks[..1], 10, 3),
+            algorithm.required_difficulty(&blocks, 10, 4),
+        );
+
+        // Then
+        assert_eq!(difficulty, (3, 4));
+    }
+
+    #[test]
+    fn should_parse_arguments() {
+        // Given
+        let mut arguments = HashMap::new();
+        arguments.insert("difficulty".to_string(), vec!["proportional".to_string()]);
+        arguments.insert("target_interval".to_string(), vec!["30".to_string()]);
+
+        // When
+        let algorithm = DifficultyAlgorithm::from_arguments(&arguments);
+
+        // Then
+        assert_eq!(
+            algorithm.unwrap(),
+            DifficultyAlgorithm::Proportional {
+                target_interval: 30,
+                stake_share: 50
+            }
+        );
+    }
+
+    fn create_block(timestamp: u64) -> Block {
+        Block {
+            timestamp,
+            data: BlockData::new(BlockDataType::EdgeData, None, None),
+            ..Block::default()
+        }
+    }
+}
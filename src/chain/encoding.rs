@@ -0,0 +1,380 @@
+use crate::chain::block::{
+    AuditData, Block, BlockData, BlockDataType, DelegationData, EdgeData, KeyRotationData, NodeData, NodeOperation, RewardData, SchemaData,
+    SlashData, ValidatorData,
+};
+use crate::chain::genesis::{Genesis, GenesisValidator};
+use crate::chain::hasher::HashAlgorithm;
+use crate::chain::scheme::SignatureAlgorithm;
+use crate::graph::condition::{Condition, Operator};
+use std::collections::BTreeMap;
+
+/// Canonical binary encoding used for hashing blocks, which doesn't depend on serialization library or platform.
+/// Integers are encoded as little-endian with fixed width (`usize` as 64 bits), strings, lists and maps are prefixed
+/// by their length, missing values by zero byte (present ones by one byte), enums by fixed tag and structs as their
+/// fields in fixed order. Tags of existing enum variants and order of existing fields must never be changed, as it
+/// would change hashes of existing blocks.
+pub trait CanonicalEncode {
+    fn encode(&self, buffer: &mut Vec<u8>);
+
+    fn to_canonical_bytes(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        self.encode(&mut buffer);
+        buffer
+    }
+}
+
+impl CanonicalEncode for u8 {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.push(*self);
+    }
+}
+
+impl CanonicalEncode for i8 {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl CanonicalEncode for u64 {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl CanonicalEncode for usize {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        (*self as u64).encode(buffer);
+    }
+}
+
+impl CanonicalEncode for str {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.len().encode(buffer);
+        buffer.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl CanonicalEncode for String {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.as_str().encode(buffer);
+    }
+}
+
+impl<T: CanonicalEncode> CanonicalEncode for Option<T> {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        match self {
+            None => 0u8.encode(buffer),
+            Some(value) => {
+                1u8.encode(buffer);
+                value.encode(buffer);
+            }
+        }
+    }
+}
+
+impl<T: CanonicalEncode> CanonicalEncode for Vec<T> {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.len().encode(buffer);
+        self.iter().for_each(|item| item.encode(buffer));
+    }
+}
+
+impl<T: CanonicalEncode> CanonicalEncode for Box<T> {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.as_ref().encode(buffer);
+    }
+}
+
+impl<K: CanonicalEncode, V: CanonicalEncode> CanonicalEncode for BTreeMap<K, V> {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.len().encode(buffer);
+        self.iter().for_each(|(key, value)| {
+            key.encode(buffer);
+            value.encode(buffer);
+        });
+    }
+}
+
+impl CanonicalEncode for Block {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.version.encode(buffer);
+        self.id.encode(buffer);
+        self.hash.encode(buffer);
+        self.previous_hash.encode(buffer);
+        self.timestamp.encode(buffer);
+        self.data.encode(buffer);
+        self.validator.encode(buffer);
+        self.signature.encode(buffer);
+        self.difficulty.encode(buffer);
+        if self.version > Block::CANONICAL_FORMAT_VERSION {
+            self.nonce.encode(buffer);
+        }
+    }
+}
+
+impl CanonicalEncode for BlockData {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.data_type.encode(buffer);
+        self.edge_data.encode(buffer);
+        self.validator_data.encode(buffer);
+        self.reward_data.encode(buffer);
+        self.slash_data.encode(buffer);
+        self.edge_batch.encode(buffer);
+        self.genesis_data.encode(buffer);
+        self.key_rotation_data.encode(buffer);
+        self.node_data.encode(buffer);
+        self.schema_data.encode(buffer);
+        // Encoded only if present, so hashes of blocks created before delegations were stored are not changed
+        if let Some(delegation) = &self.delegation_data {
+            1u8.encode(buffer);
+            delegation.encode(buffer);
+        }
+        // Encoded only if present (after its own marker), so hashes of blocks created before node batches are not changed
+        if let Some(nodes) = &self.node_batch {
+            2u8.encode(buffer);
+            nodes.encode(buffer);
+        }
+        // Audit anchors are encoded the same way, after their own marker
+        if let Some(audit) = &self.audit_data {
+            3u8.encode(buffer);
+            audit.encode(buffer);
+        }
+    }
+}
+
+impl CanonicalEncode for BlockDataType {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            BlockDataType::EdgeData => 0,
+            BlockDataType::ValidatorData => 1,
+            BlockDataType::RootNode => 2,
+            BlockDataType::Reward => 3,
+            BlockDataType::SlashData => 4,
+            BlockDataType::EdgeBatch => 5,
+            BlockDataType::KeyRotation => 6,
+            BlockDataType::NodeData => 7,
+            BlockDataType::SchemaData => 8,
+            BlockDataType::Delegation => 9,
+            BlockDataType::NodeBatch => 10,
+            BlockDataType::Audit => 11,
+        };
+        tag.encode(buffer);
+    }
+}
+
+impl CanonicalEncode for EdgeData {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.from.encode(buffer);
+        self.to.encode(buffer);
+        self.weight.encode(buffer);
+        if let Some(expires_at) = self.expires_at {
+            expires_at.encode(buffer);
+        }
+    }
+}
+
+impl CanonicalEncode for ValidatorData {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.public_key.encode(buffer);
+        self.account_id.encode(buffer);
+        if !self.scheme.is_default() {
+            self.scheme.encode(buffer);
+        }
+    }
+}
+
+impl CanonicalEncode for SignatureAlgorithm {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            SignatureAlgorithm::Ed25519 => 0,
+            SignatureAlgorithm::Secp256k1 => 1,
+        };
+        tag.encode(buffer);
+    }
+}
+
+impl CanonicalEncode for RewardData {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.public_key.encode(buffer);
+        self.block_id.encode(buffer);
+        self.amount.encode(buffer);
+    }
+}
+
+impl CanonicalEncode for SlashData {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.public_key.encode(buffer);
+        self.evidence.encode(buffer);
+    }
+}
+
+impl CanonicalEncode for KeyRotationData {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.old_public_key.encode(buffer);
+        self.new_public_key.encode(buffer);
+        self.new_key_signature.encode(buffer);
+    }
+}
+
+impl CanonicalEncode for DelegationData {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.node_id.encode(buffer);
+        self.agent_id.encode(buffer);
+    }
+}
+
+impl CanonicalEncode for AuditData {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.sequence.encode(buffer);
+        self.hash.encode(buffer);
+    }
+}
+
+impl CanonicalEncode for NodeData {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.operation.encode(buffer);
+        self.name.encode(buffer);
+        self.id.encode(buffer);
+        self.attributes.encode(buffer);
+        if let Some(expires_at) = self.expires_at {
+            expires_at.encode(buffer);
+        }
+    }
+}
+
+impl CanonicalEncode for NodeOperation {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            NodeOperation::Add => 0,
+            NodeOperation::Update => 1,
+            NodeOperation::Delete => 2,
+            NodeOperation::SoftDelete => 3,
+            NodeOperation::Restore => 4,
+        };
+        tag.encode(buffer);
+    }
+}
+
+impl CanonicalEncode for SchemaData {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.name.encode(buffer);
+        self.attributes.encode(buffer);
+        self.conditions.encode(buffer);
+        // Policy is encoded only if self loops are denied, so hashes of definitions created before it are not changed
+        if self.deny_self_loops {
+            1u8.encode(buffer);
+        }
+        if !self.secret_attributes.is_empty() {
+            2u8.encode(buffer);
+            self.secret_attributes.encode(buffer);
+        }
+    }
+}
+
+impl CanonicalEncode for Condition {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.attribute.encode(buffer);
+        self.operator.encode(buffer);
+        self.value.encode(buffer);
+    }
+}
+
+impl CanonicalEncode for Operator {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            Operator::Equal => 0,
+            Operator::NotEqual => 1,
+            Operator::Greater => 2,
+            Operator::GreaterOrEqual => 3,
+            Operator::Less => 4,
+            Operator::LessOrEqual => 5,
+            Operator::Matches => 6,
+        };
+        tag.encode(buffer);
+    }
+}
+
+impl CanonicalEncode for Genesis {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.chain_id.encode(buffer);
+        self.timestamp.encode(buffer);
+        self.validators.encode(buffer);
+        self.definitions.encode(buffer);
+        self.format_version.encode(buffer);
+        if !self.hash_algorithm.is_default() {
+            self.hash_algorithm.encode(buffer);
+        }
+        // Marker doesn't collide with tag of non-default hash algorithm
+        if !Genesis::is_default_epoch_length(&self.epoch_length) {
+            2u8.encode(buffer);
+            self.epoch_length.encode(buffer);
+        }
+        if !Genesis::is_default_retention(&self.soft_delete_retention) {
+            3u8.encode(buffer);
+            self.soft_delete_retention.encode(buffer);
+        }
+    }
+}
+
+impl CanonicalEncode for HashAlgorithm {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            HashAlgorithm::Sha256 => 0,
+            HashAlgorithm::Blake3 => 1,
+        };
+        tag.encode(buffer);
+    }
+}
+
+impl CanonicalEncode for GenesisValidator {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        self.public_key.encode(buffer);
+        self.stake.encode(buffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_encode_with_fixed_layout() {
+        // Given
+        let edge = EdgeData::new("a".to_string(), "bc".to_string(), -1);
+
+        // When
+        let bytes = Some(edge).to_canonical_bytes();
+
+        // Then
+        assert_eq!(bytes, vec![1, 1, 0, 0, 0, 0, 0, 0, 0, b'a', 2, 0, 0, 0, 0, 0, 0, 0, b'b', b'c', 0xff]);
+    }
+
+    #[test]
+    fn should_not_depend_on_insertion_order() {
+        // Given
+        let attributes = [("name", "John"), ("age", "30"), ("city", "Zagreb")].map(|(key, value)| (key.to_string(), value.to_string()));
+        let node = |attributes: Vec<(String, String)>| {
+            NodeData::new(NodeOperation::Add, "User".to_string(), "1".to_string(), attributes.into_iter().collect())
+        };
+
+        // When
+        let forward = node(attributes.to_vec()).to_canonical_bytes();
+        let backward = node(attributes.iter().rev().cloned().collect()).to_canonical_bytes();
+
+        // Then
+        assert_eq!(forward, backward);
+    }
+
+    #[test]
+    fn should_distinguish_missing_and_empty_values() {
+        // Given
+        let missing = BlockData::new(BlockDataType::EdgeBatch, None, None);
+        let empty = BlockData::batch(vec![]);
+
+        // When
+        let (missing, empty) = (missing.to_canonical_bytes(), empty.to_canonical_bytes());
+
+        // Then
+        assert_ne!(missing, empty);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::chain::error::ChainError;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// Encryption of files stored by the node (archive, audit log, change stream file, slow query log and exported data), as
+/// node attributes often contain personal data. Key of each file is derived from storage key (or passphrase) and random
+/// salt of the file, stored in header line of the file, so the same passphrase gives different keys on each node and
+/// keys can't be precomputed. Wallet key file is always encrypted with its own passphrase (see `Wallet`).
+#[derive(Clone)]
+pub struct StorageEncryption {
+    key: StorageKey,
+}
+
+#[derive(Clone)]
+enum StorageKey {
+    Key([u8; 32]),
+    Passphrase(String),
+}
+
+/// Encryption of lines of one file. Each line is encrypted separately with AES-256-GCM, random nonce and index of the
+/// line as associated data, and stored as base64 of nonce followed by ciphertext, so files can still be appended line
+/// by line, while reordered or removed lines (except the last ones) can't be decrypted.
+pub struct FileEncryption {
+    cipher: Aes256Gcm,
+    // Index of the next line, starting after the header
+    line: u64,
+}
+
+/// File of lines, encrypted if storage encryption is given. Encryption of the file is opened when the file is read or
+/// first appended to, and the file is created with its header if it doesn't exist.
+pub struct LineFile {
+    path: String,
+    encryption: Option<StorageEncryption>,
+    file: Option<FileEncryption>,
+}
+
+impl StorageEncryption {
+    const KEY_DERIVATION_ROUNDS: u32 = 100_000;
+    const SALT_LENGTH: usize = 16;
+    /// Prefix of the header line, followed by hex encoded salt of the file
+    const HEADER_PREFIX: &'static str = "salt:";
+    const KEY_VARIABLE: &'static str = "STORAGE_KEY";
+    const PASSPHRASE_VARIABLE: &'static str = "STORAGE_PASSPHRASE";
+
+    /// Create encryption from key given by STORAGE_KEY environment variable (hex encoded 32 bytes, e.g. provided by key
+    /// management service), or from passphrase given by `--storage-passphrase` argument or STORAGE_PASSPHRASE
+    /// environment variable. Without key or passphrase, files are not encrypted.
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<Option<StorageEncryption>, ChainError> {
+        if let Ok(key) = env::var(Self::KEY_VARIABLE) {
+            return Self::from_key(&key).map(Some);
+        }
+
+        let passphrase = arguments
+            .get("storage-passphrase")
+            .and_then(|values| values.first())
+            .cloned()
+            .or_else(|| env::var(Self::PASSPHRASE_VARIABLE).ok());
+
+        Ok(passphrase.map(|passphrase| Self::from_passphrase(&passphrase)))
+    }
+
+    pub fn from_passphrase(passphrase: &str) -> StorageEncryption {
+        StorageEncryption {
+            key: StorageKey::Passphrase(passphrase.to_string()),
+        }
+    }
+
+    pub fn from_key(key: &str) -> Result<StorageEncryption, ChainError> {
+        let key: [u8; 32] = hex::decode(key.trim())
+            .ok()
+            .and_then(|key| key.try_into().ok())
+            .ok_or(ChainError::InvalidEncryptionConfiguration(format!(
+                "{} (32 hex encoded bytes are required)",
+                Self::KEY_VARIABLE
+            )))?;
+
+        Ok(StorageEncryption { key: StorageKey::Key(key) })
+    }
+
+    /// Encryption of new file with random salt, together with header line, which is the first line of the file.
+    pub fn create(&self) -> (FileEncryption, String) {
+        let mut salt = [0u8; Self::SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+
+        (self.with_salt(&salt), format!("{}{}", Self::HEADER_PREFIX, hex::encode(salt)))
+    }
+
+    /// Encryption of existing file with salt from its header line, which fails if file isn't encrypted.
+    pub fn open(&self, header: &str) -> Result<FileEncryption, String> {
+        let salt = header
+            .trim()
+            .strip_prefix(Self::HEADER_PREFIX)
+            .and_then(|salt| hex::decode(salt).ok())
+            .filter(|salt| salt.len() == Self::SALT_LENGTH)
+            .ok_or("File is not encrypted (header with salt is missing)".to_string())?;
+
+        Ok(self.with_salt(&salt))
+    }
+
+    fn with_salt(&self, salt: &[u8]) -> FileEncryption {
+        let mut key = [0u8; 32];
+        match &self.key {
+            // Given key is already random, so it is only bound to the salt
+            StorageKey::Key(storage_key) => pbkdf2::pbkdf2_hmac::<Sha256>(storage_key, salt, 1, &mut key),
+            StorageKey::Passphrase(passphrase) => pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, Self::KEY_DERIVATION_ROUNDS, &mut key),
+        }
+
+        FileEncryption {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+            line: 0,
+        }
+    }
+}
+
+impl FileEncryption {
+    const NONCE_LENGTH: usize = 12;
+
+    /// Encrypt the next line of the file.
+    pub fn encrypt(&mut self, line: &str) -> String {
+        let mut nonce = [0u8; Self::NONCE_LENGTH];
+        OsRng.fill_bytes(&mut nonce);
+
+        let payload = Payload {
+            msg: line.as_bytes(),
+            aad: &self.line.to_be_bytes(),
+        };
+        let mut encrypted = nonce.to_vec();
+        encrypted.extend(
+            self.cipher
+                .encrypt(Nonce::from_slice(&nonce), payload)
+                .expect("Encryption of line failed"),
+        );
+        self.line += 1;
+
+        STANDARD.encode(encrypted)
+    }
+
+    /// Decrypt the next line of the file, which fails if line was encrypted by other key, it was changed or it was
+    /// encrypted as other line of the file.
+    pub fn decrypt(&mut self, line: &str) -> Result<String, String> {
+        let encrypted = STANDARD.decode(line.trim()).map_err(|_| "Line is not encrypted".to_string())?;
+        if encrypted.len() < Self::NONCE_LENGTH {
+            return Err("Line is not encrypted".to_string());
+        }
+
+        let (nonce, ciphertext) = encrypted.split_at(Self::NONCE_LENGTH);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &self.line.to_be_bytes(),
+        };
+        let line = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| format!("Line {} can't be decrypted (wrong key, or corrupted or reordered file)", self.line + 1))?;
+        self.line += 1;
+
+        String::from_utf8(line).map_err(|error| error.to_string())
+    }
+}
+
+impl LineFile {
+    pub fn new(path: &str, encryption: Option<StorageEncryption>) -> LineFile {
+        LineFile {
+            path: path.to_string(),
+            encryption,
+            file: None,
+        }
+    }
+
+    /// Read all lines of the file (without header), or no lines if the file doesn't exist.
+    pub fn read(&mut self) -> Result<Vec<String>, String> {
+        let content = self.content()?;
+        let mut lines = content.lines();
+
+        let Some(encryption) = &self.encryption else {
+            return Ok(lines.map(str::to_string).collect());
+        };
+        let Some(header) = lines.next() else {
+            return Ok(vec![]);
+        };
+
+        let mut file = encryption.open(header)?;
+        let lines = lines.map(|line| file.decrypt(line)).collect::<Result<Vec<String>, String>>()?;
+        self.file = Some(file);
+
+        Ok(lines)
+    }
+
+    /// Append lines to the file, creating it (with header) if it doesn't exist.
+    pub fn append(&mut self, lines: &[String]) -> Result<(), String> {
+        let mut content = String::new();
+        if let (Some(encryption), None) = (&self.encryption, &self.file) {
+            let existing = self.content()?;
+            let mut existing = existing.lines();
+
+            self.file = Some(match existing.next() {
+                Some(header) => {
+                    let mut file = encryption.open(header)?;
+                    file.line = existing.count() as u64;
+                    file
+                }
+                None => {
+                    let (file, header) = encryption.create();
+                    content.push_str(&header);
+                    content.push('\n');
+                    file
+                }
+            });
+        }
+
+        for line in lines {
+            match &mut self.file {
+                Some(file) => content.push_str(&file.encrypt(line)),
+                None => content.push_str(line),
+            }
+            content.push('\n');
+        }
+
+        let written = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(content.as_bytes()));
+        if let Err(error) = written {
+            // Lines which weren't written are encrypted again, with the same indexes
+            self.file = None;
+            return Err(error.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Keep only given number of lines of the file (without header).
+    pub fn truncate(&mut self, count: usize) -> Result<(), String> {
+        let header = self.encryption.is_some() as usize;
+        let content: String = self.content()?.lines().take(count + header).map(|line| format!("{line}\n")).collect();
+        fs::write(&self.path, content).map_err(|error| error.to_string())?;
+
+        if let Some(file) = &mut self.file {
+            file.line = file.line.min(count as u64);
+        }
+        Ok(())
+    }
+
+    fn content(&self) -> Result<String, String> {
+        if !Path::new(&self.path).exists() {
+            return Ok(String::new());
+        }
+
+        fs::read_to_string(&self.path).map_err(|error| error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_encrypt_and_decrypt_lines() {
+        // Given
+        let encryption = StorageEncryption::from_passphrase("secret");
+        let (mut file, header) = encryption.create();
+        let lines = [r#"{"$id":"1","name":"John"}"#, r#"{"$id":"2","name":"Jane"}"#];
+
+        // When
+        let encrypted: Vec<String> = lines.iter().map(|line| file.encrypt(line)).collect();
+        let mut reopened = StorageEncryption::from_passphrase("secret").open(&header).unwrap();
+        let decrypted: Vec<String> = encrypted.iter().map(|line| reopened.decrypt(line).unwrap()).collect();
+        let (_, other_header) = encryption.create();
+
+        // Then
+        assert_eq!(decrypted, lines);
+        assert!(!encrypted[0].contains("John"));
+        assert_ne!(header, other_header);
+        assert!(encryption.open(&header).unwrap().decrypt(&encrypted[1]).is_err());
+        assert!(encryption.open(&other_header).unwrap().decrypt(&encrypted[0]).is_err());
+        assert!(StorageEncryption::from_passphrase("other")
+            .open(&header)
+            .unwrap()
+            .decrypt(&encrypted[0])
+            .is_err());
+        assert!(encryption.open(&header).unwrap().decrypt(lines[0]).is_err());
+        assert!(encryption.open(lines[0]).is_err());
+        let key = StorageEncryption::from_key(&"ab".repeat(32)).unwrap();
+        assert!(key.open(&header).unwrap().decrypt(&encrypted[0]).is_err());
+        assert!(StorageEncryption::from_key("abcd").is_err());
+    }
+
+    #[test]
+    fn should_append_read_and_truncate_encrypted_file() {
+        // Given
+        let path = std::env::temp_dir().join("should_append_read_and_truncate_encrypted_file.jsonl");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let encryption = || Some(StorageEncryption::from_passphrase("secret"));
+        let lines = |count: usize| (0..count).map(|line| format!("line {line}")).collect::<Vec<String>>();
+        let mut file = LineFile::new(path, encryption());
+
+        // When
+        file.append(&lines(2)).unwrap();
+        LineFile::new(path, encryption()).append(&lines(3)[2..]).unwrap();
+        let appended = file.read().unwrap();
+        file.truncate(1).unwrap();
+        file.append(&lines(2)[1..]).unwrap();
+        let truncated = LineFile::new(path, encryption()).read().unwrap();
+        let content = fs::read_to_string(path).unwrap();
+        let removed: String = content
+            .lines()
+            .take(1)
+            .chain(content.lines().skip(2))
+            .map(|line| format!("{line}\n"))
+            .collect();
+        fs::write(path, removed).unwrap();
+        let tampered = LineFile::new(path, encryption()).read();
+
+        // Then
+        assert_eq!(appended, lines(3));
+        assert_eq!(truncated, lines(2));
+        assert!(!content.contains("line"));
+        assert!(tampered.is_err());
+        assert_eq!(LineFile::new(path, None).read().unwrap().len(), 2);
+        fs::remove_file(path).unwrap();
+    }
+}
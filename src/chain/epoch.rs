@@ -0,0 +1,140 @@
+use crate::chain::agent::AgentService;
+use crate::chain::block::Block;
+use rustc_hash::FxHashMap;
+use std::collections::BTreeMap;
+
+/// Stakes of validators (by public key) at the start of the epoch, together with total stake of the network.
+#[derive(Clone, Default)]
+pub struct ValidatorSet {
+    stakes: FxHashMap<String, usize>,
+    pub total_stake: usize,
+}
+
+impl ValidatorSet {
+    pub(crate) fn snapshot(agent_service: &AgentService) -> ValidatorSet {
+        ValidatorSet {
+            stakes: agent_service.get_validator_stakes(),
+            total_stake: agent_service.get_total_stake(),
+        }
+    }
+
+    pub fn get_stake(&self, validator: &String) -> usize {
+        self.stakes.get(validator).copied().unwrap_or(0)
+    }
+
+    /// The largest stake of a single validator.
+    pub fn max_stake(&self) -> usize {
+        self.stakes.values().copied().max().unwrap_or(0)
+    }
+
+    pub fn stakes(&self) -> impl Iterator<Item = (&String, usize)> {
+        self.stakes.iter().map(|(validator, stake)| (validator, *stake))
+    }
+}
+
+/// Chain is divided into epochs of number of blocks given by genesis configuration. Validator set is snapshotted once
+/// the last block before the epoch is applied, and stakes of all blocks in the epoch are validated against it, so stake
+/// changes in the middle of the epoch (e.g. while chain is being synced) don't change validity of its blocks. Snapshot
+/// is derived only from the blocks before the epoch, so every peer has the same validator set.
+pub struct Epochs {
+    length: usize,
+    validator_sets: BTreeMap<usize, ValidatorSet>,
+}
+
+impl Default for Epochs {
+    fn default() -> Self {
+        Epochs::new(Self::DEFAULT_LENGTH)
+    }
+}
+
+impl Epochs {
+    pub const DEFAULT_LENGTH: usize = 100;
+
+    pub fn new(length: usize) -> Epochs {
+        Epochs {
+            length,
+            validator_sets: BTreeMap::new(),
+        }
+    }
+
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Snapshot validator set of the next epoch if given block is the last block of its epoch, and of the first epoch
+    /// for genesis block.
+    pub(crate) fn snapshot(&mut self, block: &Block, agent_service: &AgentService) {
+        if block.id == 0 {
+            self.validator_sets.insert(0, ValidatorSet::snapshot(agent_service));
+        }
+
+        if (block.id + 1).is_multiple_of(self.length) {
+            self.validator_sets
+                .insert((block.id + 1) / self.length, ValidatorSet::snapshot(agent_service));
+        }
+    }
+
+    /// Validator set of the epoch of the block with given id, or of the latest snapshotted epoch before it.
+    pub(crate) fn validator_set(&self, block_id: usize) -> ValidatorSet {
+        self.validator_sets
+            .range(..=block_id / self.length)
+            .next_back()
+            .map(|(_, validator_set)| validator_set.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::genesis::Genesis;
+    use crate::chain::tests::insert_agent;
+    use crate::chain::Chain;
+
+    #[test]
+    fn should_keep_stakes_until_next_epoch() {
+        // Given
+        let mut chain = Chain::new(&Genesis {
+            epoch_length: 4,
+            ..Genesis::default()
+        });
+        let public_key = chain.signer.get_public_key();
+        insert_agent(&mut chain, &public_key, 2);
+
+        // When
+        let first = chain.epochs.validator_set(1);
+        chain.add_edge_change("agent".to_string(), "node0".to_string(), 1).unwrap();
+        insert_agent(&mut chain, &public_key, 5);
+        let same_epoch = chain.epochs.validator_set(3);
+        chain.add_edge_change("agent".to_string(), "node0".to_string(), 1).unwrap();
+        let next_epoch = chain.epochs.validator_set(5);
+
+        // Then
+        assert_eq!(first.get_stake(&public_key), 2);
+        assert_eq!(same_epoch.get_stake(&public_key), 2);
+        assert_eq!(chain.blocks[3].difficulty, 2);
+        assert_eq!(next_epoch.get_stake(&public_key), 5);
+        assert_eq!(next_epoch.total_stake, 5);
+    }
+
+    #[test]
+    fn should_snapshot_validator_set_at_epoch_boundary() {
+        // Given
+        let mut epochs = Epochs::new(2);
+        let mut agent_service = AgentService::default();
+        let blocks: Vec<Block> = (0..4).map(|id| Block { id, ..Block::default() }).collect();
+
+        // When
+        epochs.snapshot(&blocks[0], &agent_service);
+        agent_service.genesis_stake.insert("validator".to_string(), 3);
+        epochs.snapshot(&blocks[1], &agent_service);
+        agent_service.genesis_stake.insert("validator".to_string(), 5);
+        epochs.snapshot(&blocks[2], &agent_service);
+
+        // Then
+        assert_eq!(epochs.validator_set(1).total_stake, 0);
+        assert_eq!(epochs.validator_set(2).get_stake(&"validator".to_string()), 3);
+        assert_eq!(epochs.validator_set(3).total_stake, 3);
+        assert_eq!(epochs.validator_set(7).total_stake, 3);
+    }
+}
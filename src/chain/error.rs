@@ -1,49 +1,181 @@
+use serde::{Deserialize, Serialize};
 use std::fmt::{Debug, Display, Formatter};
 
-#[derive(Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub enum ChainError {
+    ArchiveError(String, String),
+    BlockHasInsufficientWork(usize),
+    BlockHasInvalidTimestamp(usize),
+    BlockHasUnsupportedFormatVersion(usize),
+    BlockHasWrongDataValue(usize),
     BlockHasWrongDifficultyValue(usize),
     BlockHasWrongHashValue(usize),
+    BlockHasWrongKeyRotationValue(usize),
     BlockHasWrongPreviousHashValue(usize),
+    BlockHasWrongRewardValue(usize),
     BlockHasWrongSignatureValue(usize),
+    BlockHasWrongSlashValue(usize),
     BlockHasWrongValidatorValue(usize),
     BlockIsNotNextBlockInSequence(usize),
+    BlockNotFound(String),
+    BlockProposerIsNotScheduled(usize),
+    ChainConflictsWithFinalizedCheckpoint(usize),
     ChainHasInvalidGenesisBlock,
     ChainSizeIsNotLongerThanLocalChain,
+    ChangeStreamError(String, String),
+    CheckpointVoteIsNotValid(usize),
+    CheckpointVoteSignatureIsNotValid(usize),
+    DelegationNotAllowed(String),
+    InvalidBatchConfiguration(String),
+    InvalidChangeStreamConfiguration(String),
+    InvalidClockConfiguration(String),
+    InvalidConsensusConfiguration(String),
+    InvalidDifficultyConfiguration(String),
+    InvalidEncryptionConfiguration(String),
+    InvalidFinalityConfiguration(String),
+    InvalidGenesisConfiguration(String, String),
+    InvalidMnemonic(String),
+    InvalidModeConfiguration(String),
+    InvalidScheduleConfiguration(String),
+    InvalidSignatureScheme(String),
+    InvalidWebhookConfiguration(String, String),
+    NodeIsObserver,
     NotQualifiedForAgent(String),
+    SignerError(String),
+    WalletError(String, String),
+    WebhookError(String, String),
     WrongAgentIdentifier(String),
     WrongAgentKey(String),
 }
 
 fn error_message(error: &ChainError, f: &mut Formatter<'_>) -> std::fmt::Result {
     match error {
+        ChainError::ArchiveError(path, error) => {
+            write!(f, "Archive {path} failed: {error}")
+        }
+        ChainError::BlockHasInsufficientWork(block_id) => {
+            write!(f, "Block {block_id} has insufficient proof of work")
+        }
+        ChainError::BlockHasInvalidTimestamp(block_id) => {
+            write!(f, "Block {block_id} has invalid timestamp")
+        }
+        ChainError::BlockHasUnsupportedFormatVersion(block_id) => {
+            write!(f, "Block {block_id} has unsupported format version")
+        }
+        ChainError::BlockHasWrongDataValue(block_id) => {
+            write!(f, "Block {block_id} has invalid data")
+        }
         ChainError::BlockHasWrongDifficultyValue(block_id) => {
             write!(f, "Block {block_id} has invalid difficulty")
         }
         ChainError::BlockHasWrongHashValue(block_id) => {
             write!(f, "Block {block_id} has invalid hash")
         }
+        ChainError::BlockHasWrongKeyRotationValue(block_id) => {
+            write!(f, "Block {block_id} has invalid key rotation")
+        }
         ChainError::BlockHasWrongPreviousHashValue(block_id) => {
             write!(f, "Block {block_id} has invalid previous hash")
         }
+        ChainError::BlockHasWrongRewardValue(block_id) => {
+            write!(f, "Block {block_id} has invalid reward")
+        }
         ChainError::BlockHasWrongSignatureValue(block_id) => {
             write!(f, "Block {block_id} has invalid signature")
         }
+        ChainError::BlockHasWrongSlashValue(block_id) => {
+            write!(f, "Block {block_id} has invalid slash")
+        }
         ChainError::BlockHasWrongValidatorValue(block_id) => {
             write!(f, "Block {block_id} has invalid validator")
         }
         ChainError::BlockIsNotNextBlockInSequence(block_id) => {
             write!(f, "Block {block_id} is not the next block in the sequence")
         }
+        ChainError::BlockNotFound(block) => {
+            write!(f, "Block {block} was not found")
+        }
+        ChainError::BlockProposerIsNotScheduled(block_id) => {
+            write!(f, "Validator of block {block_id} is not scheduled to propose it")
+        }
+        ChainError::ChainConflictsWithFinalizedCheckpoint(block_id) => {
+            write!(f, "Chain does not contain finalized checkpoint block {block_id}")
+        }
         ChainError::ChainHasInvalidGenesisBlock => {
             write!(f, "Chain has invalid genesis block")
         }
         ChainError::ChainSizeIsNotLongerThanLocalChain => {
             write!(f, "Chain size is not longer than local chain")
         }
+        ChainError::ChangeStreamError(sink, error) => {
+            write!(f, "Change stream to {sink} failed: {error}")
+        }
+        ChainError::CheckpointVoteIsNotValid(block_id) => {
+            write!(f, "Vote for checkpoint block {block_id} is not valid")
+        }
+        ChainError::CheckpointVoteSignatureIsNotValid(block_id) => {
+            write!(f, "Vote for checkpoint block {block_id} has invalid signature")
+        }
+        ChainError::DelegationNotAllowed(identifier) => {
+            write!(
+                f,
+                "Item with id {identifier} does not exist or is already an agent, so it can't delegate stake"
+            )
+        }
+        ChainError::InvalidBatchConfiguration(argument) => {
+            write!(f, "Batch configuration {argument} is not valid")
+        }
+        ChainError::InvalidChangeStreamConfiguration(argument) => {
+            write!(f, "Change stream configuration {argument} is not valid")
+        }
+        ChainError::InvalidClockConfiguration(argument) => {
+            write!(f, "Clock configuration {argument} is not valid")
+        }
+        ChainError::InvalidConsensusConfiguration(argument) => {
+            write!(f, "Consensus configuration {argument} is not valid")
+        }
+        ChainError::InvalidDifficultyConfiguration(argument) => {
+            write!(f, "Difficulty configuration {argument} is not valid")
+        }
+        ChainError::InvalidEncryptionConfiguration(argument) => {
+            write!(f, "Encryption configuration {argument} is not valid")
+        }
+        ChainError::InvalidFinalityConfiguration(argument) => {
+            write!(f, "Finality configuration {argument} is not valid")
+        }
+        ChainError::InvalidGenesisConfiguration(path, error) => {
+            write!(f, "Genesis configuration {path} is not valid: {error}")
+        }
+        ChainError::InvalidMnemonic(error) => {
+            write!(f, "Mnemonic is not valid: {error}")
+        }
+        ChainError::InvalidModeConfiguration(argument) => {
+            write!(f, "Mode configuration {argument} is not valid")
+        }
+        ChainError::InvalidScheduleConfiguration(argument) => {
+            write!(f, "Proposer schedule configuration {argument} is not valid")
+        }
+        ChainError::InvalidSignatureScheme(name) => {
+            write!(f, "Signature scheme {name} is not supported")
+        }
+        ChainError::InvalidWebhookConfiguration(path, error) => {
+            write!(f, "Webhook configuration {path} is not valid: {error}")
+        }
+        ChainError::NodeIsObserver => {
+            write!(f, "Node is in observer mode, so it can't produce blocks")
+        }
         ChainError::NotQualifiedForAgent(identifier) => {
             write!(f, "Item with id {identifier} is not qualified to be an agent")
         }
+        ChainError::SignerError(error) => {
+            write!(f, "Signer failed: {error}")
+        }
+        ChainError::WalletError(path, error) => {
+            write!(f, "Wallet key file {path} can't be used: {error}")
+        }
+        ChainError::WebhookError(url, error) => {
+            write!(f, "Webhook {url} failed: {error}")
+        }
         ChainError::WrongAgentIdentifier(identifier) => {
             write!(f, "Agent with identifier {identifier} does not exist or is not valid")
         }
@@ -53,6 +185,57 @@ fn error_message(error: &ChainError, f: &mut Formatter<'_>) -> std::fmt::Result
     }
 }
 
+impl ChainError {
+    /// Stable machine-readable error code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ChainError::ArchiveError(..) => "ARCHIVE_ERROR",
+            ChainError::BlockHasInsufficientWork(..) => "BLOCK_HAS_INSUFFICIENT_WORK",
+            ChainError::BlockHasInvalidTimestamp(..) => "BLOCK_HAS_INVALID_TIMESTAMP",
+            ChainError::BlockHasUnsupportedFormatVersion(..) => "BLOCK_HAS_UNSUPPORTED_FORMAT_VERSION",
+            ChainError::BlockHasWrongDataValue(..) => "BLOCK_HAS_WRONG_DATA_VALUE",
+            ChainError::BlockHasWrongDifficultyValue(..) => "BLOCK_HAS_WRONG_DIFFICULTY_VALUE",
+            ChainError::BlockHasWrongHashValue(..) => "BLOCK_HAS_WRONG_HASH_VALUE",
+            ChainError::BlockHasWrongKeyRotationValue(..) => "BLOCK_HAS_WRONG_KEY_ROTATION_VALUE",
+            ChainError::BlockHasWrongPreviousHashValue(..) => "BLOCK_HAS_WRONG_PREVIOUS_HASH_VALUE",
+            ChainError::BlockHasWrongRewardValue(..) => "BLOCK_HAS_WRONG_REWARD_VALUE",
+            ChainError::BlockHasWrongSignatureValue(..) => "BLOCK_HAS_WRONG_SIGNATURE_VALUE",
+            ChainError::BlockHasWrongSlashValue(..) => "BLOCK_HAS_WRONG_SLASH_VALUE",
+            ChainError::BlockHasWrongValidatorValue(..) => "BLOCK_HAS_WRONG_VALIDATOR_VALUE",
+            ChainError::BlockIsNotNextBlockInSequence(..) => "BLOCK_IS_NOT_NEXT_BLOCK_IN_SEQUENCE",
+            ChainError::BlockNotFound(..) => "BLOCK_NOT_FOUND",
+            ChainError::BlockProposerIsNotScheduled(..) => "BLOCK_PROPOSER_IS_NOT_SCHEDULED",
+            ChainError::ChainConflictsWithFinalizedCheckpoint(..) => "CHAIN_CONFLICTS_WITH_FINALIZED_CHECKPOINT",
+            ChainError::ChainHasInvalidGenesisBlock => "CHAIN_HAS_INVALID_GENESIS_BLOCK",
+            ChainError::ChainSizeIsNotLongerThanLocalChain => "CHAIN_SIZE_IS_NOT_LONGER_THAN_LOCAL_CHAIN",
+            ChainError::ChangeStreamError(..) => "CHANGE_STREAM_ERROR",
+            ChainError::CheckpointVoteIsNotValid(..) => "CHECKPOINT_VOTE_IS_NOT_VALID",
+            ChainError::CheckpointVoteSignatureIsNotValid(..) => "CHECKPOINT_VOTE_SIGNATURE_IS_NOT_VALID",
+            ChainError::DelegationNotAllowed(..) => "DELEGATION_NOT_ALLOWED",
+            ChainError::InvalidBatchConfiguration(..) => "INVALID_BATCH_CONFIGURATION",
+            ChainError::InvalidChangeStreamConfiguration(..) => "INVALID_CHANGE_STREAM_CONFIGURATION",
+            ChainError::InvalidClockConfiguration(..) => "INVALID_CLOCK_CONFIGURATION",
+            ChainError::InvalidConsensusConfiguration(..) => "INVALID_CONSENSUS_CONFIGURATION",
+            ChainError::InvalidDifficultyConfiguration(..) => "INVALID_DIFFICULTY_CONFIGURATION",
+            ChainError::InvalidEncryptionConfiguration(..) => "INVALID_ENCRYPTION_CONFIGURATION",
+            ChainError::InvalidFinalityConfiguration(..) => "INVALID_FINALITY_CONFIGURATION",
+            ChainError::InvalidGenesisConfiguration(..) => "INVALID_GENESIS_CONFIGURATION",
+            ChainError::InvalidMnemonic(..) => "INVALID_MNEMONIC",
+            ChainError::InvalidModeConfiguration(..) => "INVALID_MODE_CONFIGURATION",
+            ChainError::InvalidScheduleConfiguration(..) => "INVALID_SCHEDULE_CONFIGURATION",
+            ChainError::InvalidSignatureScheme(..) => "INVALID_SIGNATURE_SCHEME",
+            ChainError::InvalidWebhookConfiguration(..) => "INVALID_WEBHOOK_CONFIGURATION",
+            ChainError::NodeIsObserver => "NODE_IS_OBSERVER",
+            ChainError::NotQualifiedForAgent(..) => "NOT_QUALIFIED_FOR_AGENT",
+            ChainError::SignerError(..) => "SIGNER_ERROR",
+            ChainError::WalletError(..) => "WALLET_ERROR",
+            ChainError::WebhookError(..) => "WEBHOOK_ERROR",
+            ChainError::WrongAgentIdentifier(..) => "WRONG_AGENT_IDENTIFIER",
+            ChainError::WrongAgentKey(..) => "WRONG_AGENT_KEY",
+        }
+    }
+}
+
 impl Display for ChainError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         error_message(self, f)
@@ -0,0 +1,204 @@
+use crate::chain::block::Block;
+use crate::chain::error::ChainError;
+use derive_more::Constructor;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Signature of validator over the hash of checkpoint block, gossiped to all peers.
+#[derive(Serialize, Deserialize, Constructor, Clone, PartialEq, Debug)]
+pub struct CheckpointVote {
+    pub block_id: usize,
+    pub hash: String,
+    pub validator: String,
+    pub signature: String,
+}
+
+impl CheckpointVote {
+    /// Message signed by the vote, which differs from block hash so vote can't be replayed as block signature.
+    pub fn message(block_id: usize, hash: &str) -> String {
+        format!("checkpoint/{block_id}/{hash}")
+    }
+}
+
+/// Votes for one checkpoint, with stake of each validator which voted for it.
+struct Checkpoint {
+    block_id: usize,
+    hash: String,
+    votes: FxHashMap<String, usize>,
+}
+
+/// Every given number of blocks, validators vote for the checkpoint block. Once validators with given share (percent)
+/// of total stake voted for the checkpoint, it becomes final - chain can't be replaced by fork which doesn't contain
+/// it, which protects the chain against long-range rewrites.
+pub struct Finality {
+    interval: usize,
+    quorum: usize,
+    pending: Option<Checkpoint>,
+    finalized: Option<(usize, String)>,
+    // Vote of local validator, kept so it can be published again
+    local_vote: Option<CheckpointVote>,
+}
+
+impl Default for Finality {
+    fn default() -> Self {
+        Finality::new(Self::DEFAULT_INTERVAL, Self::DEFAULT_QUORUM)
+    }
+}
+
+impl Finality {
+    const DEFAULT_INTERVAL: usize = 100;
+    const DEFAULT_QUORUM: usize = 67;
+
+    pub fn new(interval: usize, quorum: usize) -> Finality {
+        Finality {
+            interval,
+            quorum,
+            pending: None,
+            finalized: None,
+            local_vote: None,
+        }
+    }
+
+    /// Create finality from command line arguments: `-checkpoint_interval="100" -finality_quorum="67"` (interval in
+    /// blocks, quorum in percent of total stake).
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<Finality, ChainError> {
+        let argument = |name: &str| arguments.get(name).and_then(|values| values.first());
+
+        let interval = argument("checkpoint_interval").map_or(Ok(Self::DEFAULT_INTERVAL), |value| {
+            value
+                .parse()
+                .ok()
+                .filter(|interval| *interval > 0)
+                .ok_or(ChainError::InvalidFinalityConfiguration(format!("checkpoint_interval={value}")))
+        })?;
+        let quorum = argument("finality_quorum").map_or(Ok(Self::DEFAULT_QUORUM), |value| {
+            value
+                .parse()
+                .ok()
+                .filter(|quorum| (1..=100).contains(quorum))
+                .ok_or(ChainError::InvalidFinalityConfiguration(format!("finality_quorum={value}")))
+        })?;
+
+        Ok(Finality::new(interval, quorum))
+    }
+
+    pub fn is_checkpoint(&self, block_id: usize) -> bool {
+        block_id > 0 && block_id.is_multiple_of(self.interval)
+    }
+
+    /// Id and hash of the latest final checkpoint.
+    pub fn finalized(&self) -> Option<(usize, &String)> {
+        self.finalized.as_ref().map(|(block_id, hash)| (*block_id, hash))
+    }
+
+    /// Latest checkpoint of given blocks which isn't final yet and local validator didn't vote for it.
+    pub(crate) fn next_vote<'a>(&self, blocks: &'a [Block]) -> Option<&'a Block> {
+        let block_id = (blocks.len() - 1) / self.interval * self.interval;
+        let block = &blocks[block_id];
+
+        let is_voted = self
+            .local_vote
+            .as_ref()
+            .is_some_and(|vote| vote.block_id == block_id && vote.hash == block.hash);
+        let is_finalized = self.finalized.as_ref().is_some_and(|(finalized_id, _)| *finalized_id >= block_id);
+
+        (self.is_checkpoint(block_id) && !is_voted && !is_finalized).then_some(block)
+    }
+
+    pub fn local_vote(&self) -> Option<&CheckpointVote> {
+        self.local_vote.as_ref()
+    }
+
+    pub(crate) fn set_local_vote(&mut self, vote: CheckpointVote) {
+        self.local_vote = Some(vote);
+    }
+
+    /// Count vote (with stake of its validator) for checkpoint of the local chain, returns false if vote was already
+    /// counted or its checkpoint is older than the latest one. Votes of older checkpoints are discarded.
+    pub(crate) fn add_vote(&mut self, vote: &CheckpointVote, stake: usize, total_stake: usize) -> bool {
+        if self.finalized.as_ref().is_some_and(|(block_id, _)| *block_id >= vote.block_id)
+            || self.pending.as_ref().is_some_and(|checkpoint| checkpoint.block_id > vote.block_id)
+        {
+            return false;
+        }
+
+        // Checkpoint block could be replaced by fork, so its older votes are discarded
+        let checkpoint = match &mut self.pending {
+            Some(checkpoint) if checkpoint.block_id == vote.block_id && checkpoint.hash == vote.hash => checkpoint,
+            pending => pending.insert(Checkpoint {
+                block_id: vote.block_id,
+                hash: vote.hash.clone(),
+                votes: FxHashMap::default(),
+            }),
+        };
+
+        if checkpoint.votes.insert(vote.validator.clone(), stake).is_some() {
+            return false;
+        }
+
+        let voted_stake: usize = checkpoint.votes.values().sum();
+        if voted_stake * 100 >= total_stake * self.quorum {
+            self.finalized = Some((checkpoint.block_id, checkpoint.hash.clone()));
+            self.pending = None;
+        }
+
+        true
+    }
+
+    /// Chain which replaces the local chain must contain the latest final checkpoint.
+    pub fn validate_fork(&self, chain: &[Block]) -> Result<(), ChainError> {
+        match &self.finalized {
+            Some((block_id, hash)) if chain.get(*block_id).is_none_or(|block| block.hash != *hash) => {
+                Err(ChainError::ChainConflictsWithFinalizedCheckpoint(*block_id))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vote(block_id: usize, hash: &str, validator: &str) -> CheckpointVote {
+        CheckpointVote::new(block_id, hash.to_string(), validator.to_string(), "signature".to_string())
+    }
+
+    #[test]
+    fn should_finalize_checkpoint_with_quorum_of_stake() {
+        // Given
+        let mut finality = Finality::new(10, 67);
+
+        // When
+        let first = finality.add_vote(&vote(10, "hash", "first"), 3, 10);
+        let repeated = finality.add_vote(&vote(10, "hash", "first"), 3, 10);
+        let second = finality.add_vote(&vote(10, "hash", "second"), 3, 10);
+        let pending = finality.finalized().is_none();
+        finality.add_vote(&vote(10, "hash", "third"), 1, 10);
+
+        // Then
+        assert!(first);
+        assert!(!repeated);
+        assert!(second);
+        assert!(pending);
+        assert_eq!(finality.finalized(), Some((10, &"hash".to_string())));
+        assert!(!finality.add_vote(&vote(10, "hash", "fourth"), 3, 10));
+    }
+
+    #[test]
+    fn should_discard_votes_of_replaced_checkpoint() {
+        // Given
+        let mut finality = Finality::new(10, 50);
+        finality.add_vote(&vote(10, "replaced", "first"), 4, 10);
+
+        // When
+        finality.add_vote(&vote(10, "hash", "second"), 4, 10);
+        let older = finality.add_vote(&vote(0, "genesis", "third"), 4, 10);
+        finality.add_vote(&vote(10, "hash", "third"), 1, 10);
+
+        // Then
+        assert!(!older);
+        assert_eq!(finality.finalized(), Some((10, &"hash".to_string())));
+    }
+}
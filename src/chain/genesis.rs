@@ -0,0 +1,164 @@
+use crate::chain::block::Block;
+use crate::chain::epoch::Epochs;
+use crate::chain::error::ChainError;
+use crate::chain::hasher::HashAlgorithm;
+use crate::graph::error::DatabaseError;
+use crate::graph::tombstone::Tombstones;
+use crate::graph::Graph;
+use derive_more::Constructor;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+
+/// Configuration of the network from which genesis block is created. As genesis hash is derived from the whole
+/// configuration, peers with different configuration can't sync their chains.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Genesis {
+    pub chain_id: String,
+    pub timestamp: u64,
+    #[serde(default)]
+    pub validators: Vec<GenesisValidator>,
+    #[serde(default)]
+    pub definitions: BTreeMap<String, Vec<String>>,
+    /// Format version of the genesis block. Configurations without it keep legacy format, so hash of their genesis
+    /// block isn't changed.
+    #[serde(default, skip_serializing_if = "Genesis::is_legacy_format")]
+    pub format_version: u8,
+    /// Hash algorithm of the chain, skipped when default so hash of existing genesis blocks isn't changed.
+    #[serde(default, skip_serializing_if = "HashAlgorithm::is_default")]
+    pub hash_algorithm: HashAlgorithm,
+    /// Number of blocks in each epoch, skipped when default so hash of existing genesis blocks isn't changed.
+    #[serde(default = "Genesis::default_epoch_length", skip_serializing_if = "Genesis::is_default_epoch_length")]
+    pub epoch_length: usize,
+    /// Seconds for which soft deleted node can be restored, skipped when default so hash of existing genesis blocks
+    /// isn't changed.
+    #[serde(default = "Genesis::default_retention", skip_serializing_if = "Genesis::is_default_retention")]
+    pub soft_delete_retention: u64,
+}
+
+/// Validator with initial stake, which can approve blocks before any agent is registered.
+#[derive(Serialize, Deserialize, Constructor, Clone, PartialEq, Debug)]
+pub struct GenesisValidator {
+    pub public_key: String,
+    pub stake: usize,
+}
+
+impl Default for Genesis {
+    fn default() -> Self {
+        Genesis {
+            chain_id: "weighted-graph".to_string(),
+            timestamp: 0,
+            validators: vec![],
+            definitions: BTreeMap::new(),
+            format_version: Block::FORMAT_VERSION,
+            hash_algorithm: HashAlgorithm::default(),
+            epoch_length: Epochs::DEFAULT_LENGTH,
+            soft_delete_retention: Tombstones::DEFAULT_RETENTION,
+        }
+    }
+}
+
+impl Genesis {
+    fn is_legacy_format(version: &u8) -> bool {
+        *version == Block::LEGACY_FORMAT_VERSION
+    }
+
+    fn default_epoch_length() -> usize {
+        Epochs::DEFAULT_LENGTH
+    }
+
+    pub(crate) fn is_default_epoch_length(length: &usize) -> bool {
+        *length == Epochs::DEFAULT_LENGTH
+    }
+
+    fn default_retention() -> u64 {
+        Tombstones::DEFAULT_RETENTION
+    }
+
+    pub(crate) fn is_default_retention(retention: &u64) -> bool {
+        *retention == Tombstones::DEFAULT_RETENTION
+    }
+
+    /// Load genesis configuration from JSON file given by `-genesis="genesis.json"` argument, or use default one.
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<Genesis, ChainError> {
+        match arguments.get("genesis").and_then(|values| values.first()) {
+            None => Ok(Genesis::default()),
+            Some(path) => Self::load(path),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Genesis, ChainError> {
+        let content = fs::read_to_string(path).map_err(|error| ChainError::InvalidGenesisConfiguration(path.to_string(), error.to_string()))?;
+
+        let genesis: Genesis =
+            serde_json::from_str(&content).map_err(|error| ChainError::InvalidGenesisConfiguration(path.to_string(), error.to_string()))?;
+
+        if genesis.epoch_length == 0 {
+            return Err(ChainError::InvalidGenesisConfiguration(
+                path.to_string(),
+                "epoch_length must be at least 1".to_string(),
+            ));
+        }
+
+        Ok(genesis)
+    }
+
+    /// Create initial node definitions in the graph.
+    pub fn create_definitions(&self, graph: &mut Graph) -> Result<(), DatabaseError> {
+        for (name, attributes) in &self.definitions {
+            graph.create_definition(name.clone(), attributes.clone())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn should_load_genesis() {
+        // Given
+        let path = env::temp_dir().join("should_load_genesis.json");
+        fs::write(
+            &path,
+            r#"{"chain_id":"test","timestamp":1700000000,"validators":[{"public_key":"key","stake":2}],"definitions":{"User":["name"]},"soft_delete_retention":3600}"#,
+        )
+        .unwrap();
+
+        let mut arguments = HashMap::new();
+        arguments.insert("genesis".to_string(), vec![path.to_str().unwrap().to_string()]);
+
+        // When
+        let genesis = Genesis::from_arguments(&arguments).unwrap();
+
+        // Then
+        assert_eq!(genesis.chain_id, "test");
+        assert_eq!(genesis.validators, vec![GenesisValidator::new("key".to_string(), 2)]);
+        assert_eq!(genesis.soft_delete_retention, 3600);
+        assert_eq!(Genesis::default().soft_delete_retention, Tombstones::DEFAULT_RETENTION);
+
+        let mut graph = Graph::default();
+        genesis.create_definitions(&mut graph).unwrap();
+        assert_eq!(graph.definitions.get("User").unwrap(), &vec!["name".to_string()]);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_reject_empty_epochs() {
+        // Given
+        let path = env::temp_dir().join("should_reject_empty_epochs.json");
+        fs::write(&path, r#"{"chain_id":"test","timestamp":1700000000,"epoch_length":0}"#).unwrap();
+
+        // When
+        let result = Genesis::load(path.to_str().unwrap());
+
+        // Then
+        assert!(result.is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+}
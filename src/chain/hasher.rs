@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+
+/// Hash function used to calculate hashes of the blocks, with hex encoded result.
+pub trait Hasher: Send + Sync {
+    fn digest(&self, data: &[u8]) -> String;
+}
+
+pub struct Sha256Hasher;
+
+pub struct Blake3Hasher;
+
+impl Hasher for Sha256Hasher {
+    fn digest(&self, data: &[u8]) -> String {
+        sha256::digest(data)
+    }
+}
+
+impl Hasher for Blake3Hasher {
+    fn digest(&self, data: &[u8]) -> String {
+        blake3::hash(data).to_hex().to_string()
+    }
+}
+
+/// Hash algorithm of the chain, selected by genesis configuration (`"hash_algorithm": "blake3"`), so chains with
+/// different algorithm have different genesis block.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn hasher(&self) -> &'static dyn Hasher {
+        match self {
+            HashAlgorithm::Sha256 => &Sha256Hasher,
+            HashAlgorithm::Blake3 => &Blake3Hasher,
+        }
+    }
+
+    pub fn digest(&self, data: &[u8]) -> String {
+        self.hasher().digest(data)
+    }
+
+    pub(crate) fn is_default(&self) -> bool {
+        *self == HashAlgorithm::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_digest_with_selected_algorithm() {
+        // Given
+        let data = b"abc";
+
+        // When
+        let sha256 = HashAlgorithm::Sha256.digest(data);
+        let blake3 = HashAlgorithm::Blake3.digest(data);
+
+        // Then
+        assert_eq!(sha256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(blake3, "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85");
+        assert_eq!(serde_json::to_string(&HashAlgorithm::Blake3).unwrap(), "\"blake3\"");
+    }
+}
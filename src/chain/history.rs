@@ -0,0 +1,208 @@
+use crate::chain::block::{Block, EdgeData, NodeOperation};
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::error::DatabaseError;
+use crate::graph::GraphResults;
+use rustc_hash::FxHashMap;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Nodes (name and attributes by identifier) and edges (weight by from and to identifier) recorded in the chain up to
+/// and including block with given height. Soft deleted node is kept with `$deleted` attribute (block timestamp), so it
+/// can be restored, but as in archive, it is not found at heights where it is soft deleted.
+#[derive(Clone, Default)]
+pub(crate) struct Snapshot {
+    height: usize,
+    hash: String,
+    pub(crate) nodes: FxHashMap<String, (String, BTreeMap<String, String>)>,
+    pub(crate) edges: FxHashMap<String, BTreeMap<String, i8>>,
+}
+
+impl Snapshot {
+    fn apply(&mut self, block: &Block) {
+        for node in block.data.nodes() {
+            match node.operation {
+                NodeOperation::Add | NodeOperation::Update | NodeOperation::Restore => {
+                    self.nodes.insert(node.id.clone(), (node.name.clone(), node.attributes.clone()));
+                }
+                NodeOperation::Delete => {
+                    self.nodes.remove(&node.id);
+                    self.edges.remove(&node.id);
+                }
+                NodeOperation::SoftDelete => {
+                    if let Some((_, attributes)) = self.nodes.get_mut(&node.id) {
+                        attributes.insert(InternalNodeAttribute::DELETED_ATTRIBUTE.to_string(), block.timestamp.to_string());
+                    }
+                }
+            }
+        }
+
+        for edge in block.data.edge_data.iter().chain(block.data.edge_batch.iter().flatten()) {
+            self.apply_edge(edge);
+        }
+
+        self.height = block.id;
+        self.hash = block.hash.clone();
+    }
+
+    /// Deleted edges are recorded with zero weight.
+    /// Name and attributes of the node which is not soft deleted.
+    fn visible_node(&self, identifier: &str) -> Option<&(String, BTreeMap<String, String>)> {
+        self.nodes
+            .get(identifier)
+            .filter(|(_, attributes)| !attributes.contains_key(InternalNodeAttribute::DELETED_ATTRIBUTE))
+    }
+
+    fn apply_edge(&mut self, edge: &EdgeData) {
+        let edges = self.edges.entry(edge.from.clone()).or_default();
+
+        if edge.weight == 0 {
+            edges.remove(&edge.to);
+        } else {
+            edges.insert(edge.to.clone(), edge.weight);
+        }
+    }
+}
+
+/// History of nodes and edges reconstructed from the chain. State is replayed from the nearest snapshot before the
+/// requested height, and snapshots are memoized every `SNAPSHOT_INTERVAL` blocks while replaying. Snapshots of blocks
+/// which were replaced (e.g. by longer chain from peer) are discarded.
+#[derive(Default)]
+pub struct History {
+    snapshots: Mutex<Vec<Snapshot>>,
+}
+
+impl History {
+    const SNAPSHOT_INTERVAL: usize = 100;
+
+    /// Node with given name and identifier together with its outgoing edges, as they were at given block height.
+    pub fn node_at(&self, blocks: &[Block], name: String, identifier: String, height: usize) -> GraphResults {
+        let snapshot = self.snapshot(blocks, height);
+
+        let (_, attributes) = snapshot
+            .visible_node(&identifier)
+            .filter(|(node_name, _)| *node_name == name)
+            .ok_or(DatabaseError::NodeNotFound(name.clone(), identifier.clone()))?;
+        let edges = snapshot
+            .edges
+            .get(&identifier)
+            .into_iter()
+            .flatten()
+            .map(|(to_id, weight)| (to_id.clone(), snapshot.visible_node(to_id).map(|(to_name, _)| to_name.clone()), *weight))
+            .collect();
+
+        Ok(Self::node_result(&name, &identifier, attributes, edges))
+    }
+
+    /// Node attributes in the first row, followed by its outgoing edges (identifier and name of the node to which edge
+    /// points, with edge weight) in the next rows.
+    pub(crate) fn node_result(
+        name: &str,
+        identifier: &str,
+        attributes: &BTreeMap<String, String>,
+        edges: Vec<(String, Option<String>, i8)>,
+    ) -> Vec<FxHashMap<String, String>> {
+        let mut node: FxHashMap<String, String> = attributes.clone().into_iter().collect();
+        node.insert(InternalNodeAttribute::ID_ATTRIBUTE.to_string(), identifier.to_string());
+        node.insert(InternalNodeAttribute::NAME_ATTRIBUTE.to_string(), name.to_string());
+        node.insert(InternalNodeAttribute::EDGE_COUNT_ATTRIBUTE.to_string(), edges.len().to_string());
+
+        let mut result = vec![node];
+        for (to_id, to_name, weight) in edges {
+            let mut edge = FxHashMap::default();
+            edge.insert(InternalNodeAttribute::FROM_ATTRIBUTE.to_string(), name.to_string());
+            edge.insert(InternalNodeAttribute::FROM_ID_ATTRIBUTE.to_string(), identifier.to_string());
+            // Node could be deleted in the meantime, so its name is not known
+            if let Some(to_name) = to_name {
+                edge.insert(InternalNodeAttribute::TO_ATTRIBUTE.to_string(), to_name);
+            }
+            edge.insert(InternalNodeAttribute::TO_ID_ATTRIBUTE.to_string(), to_id);
+            edge.insert(InternalNodeAttribute::WEIGHT_ATTRIBUTE.to_string(), weight.to_string());
+
+            result.push(edge);
+        }
+
+        result
+    }
+
+    /// Each weight of the edge between given nodes stored in given blocks, with block which stored it (its id,
+    /// timestamp and validator). Deleted edge has zero weight.
+    pub fn edge_history<'a>(
+        blocks: impl IntoIterator<Item = &'a Block>,
+        (from_name, from_id): (String, String),
+        (to_name, to_id): (String, String),
+    ) -> GraphResults {
+        let mut result = vec![];
+
+        for block in blocks {
+            let edges = block.data.edge_data.iter().chain(block.data.edge_batch.iter().flatten());
+
+            for edge in edges.filter(|edge| edge.from == from_id && edge.to == to_id) {
+                let mut row = FxHashMap::default();
+                row.insert(InternalNodeAttribute::FROM_ATTRIBUTE.to_string(), from_name.clone());
+                row.insert(InternalNodeAttribute::FROM_ID_ATTRIBUTE.to_string(), from_id.clone());
+                row.insert(InternalNodeAttribute::TO_ATTRIBUTE.to_string(), to_name.clone());
+                row.insert(InternalNodeAttribute::TO_ID_ATTRIBUTE.to_string(), to_id.clone());
+                row.insert(InternalNodeAttribute::WEIGHT_ATTRIBUTE.to_string(), edge.weight.to_string());
+                row.insert("block".to_string(), block.id.to_string());
+                row.insert("timestamp".to_string(), block.timestamp.to_string());
+                row.insert("validator".to_string(), block.validator.clone());
+
+                result.push(row);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Replay blocks up to given height (which must exist) from the nearest valid snapshot.
+    pub(crate) fn snapshot(&self, blocks: &[Block], height: usize) -> Snapshot {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        snapshots.retain(|snapshot| blocks.get(snapshot.height).is_some_and(|block| block.hash == snapshot.hash));
+
+        let mut state = snapshots
+            .iter()
+            .filter(|snapshot| snapshot.height <= height)
+            .max_by_key(|snapshot| snapshot.height)
+            .cloned()
+            .unwrap_or_default();
+
+        let start = if state.hash.is_empty() { 0 } else { state.height + 1 };
+        for block in &blocks[start..=height] {
+            state.apply(block);
+
+            if block.id % Self::SNAPSHOT_INTERVAL == 0 && !snapshots.iter().any(|snapshot| snapshot.height == block.id) {
+                snapshots.push(state.clone());
+            }
+        }
+
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::Chain;
+
+    #[test]
+    fn should_memoize_and_discard_snapshots() {
+        // Given
+        let mut chain = Chain::default();
+        for weight in 1..=60 {
+            chain.add_edge_change("from".to_string(), "to".to_string(), weight).unwrap();
+        }
+        let history = History::default();
+
+        // When
+        history.snapshot(&chain.blocks, 110);
+        let memoized = history.snapshots.lock().unwrap().len();
+
+        chain.blocks.truncate(50);
+        let snapshot = history.snapshot(&chain.blocks, 49);
+
+        // Then
+        assert_eq!(memoized, 2);
+        assert_eq!(history.snapshots.lock().unwrap().len(), 1);
+        assert_eq!(snapshot.edges["from"]["to"], 25);
+    }
+}
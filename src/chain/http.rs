@@ -0,0 +1,42 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Time to connect, send request and read response status, so unreachable endpoint doesn't block the chain.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Send POST request with given headers and body to `host:port`, and check that response has success (2xx) status.
+/// Response body is not read, as endpoints (change stream and webhooks) only acknowledge the request.
+pub(crate) fn post(host: &str, path: &str, headers: &[(&str, String)], body: &str) -> Result<(), String> {
+    let headers: String = headers.iter().map(|(name, value)| format!("{name}: {value}\r\n")).collect();
+    let request = format!(
+        "POST {path} HTTP/1.1\r\nHost: {host}\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+
+    let address = host
+        .to_socket_addrs()
+        .map_err(|error| error.to_string())?
+        .next()
+        .ok_or(format!("Host {host} has no address"))?;
+    let mut stream = TcpStream::connect_timeout(&address, TIMEOUT).map_err(|error| error.to_string())?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(|error| error.to_string())?;
+    stream.set_write_timeout(Some(TIMEOUT)).map_err(|error| error.to_string())?;
+    stream.write_all(request.as_bytes()).map_err(|error| error.to_string())?;
+
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status).map_err(|error| error.to_string())?;
+
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(format!("Request was not accepted ({})", status.trim())),
+    }
+}
+
+/// Split `http://host:port/path` URL to host and path (only plain HTTP is supported).
+pub(crate) fn parse_url(url: &str) -> Option<(String, String)> {
+    let address = url.strip_prefix("http://")?;
+    let (host, path) = address.split_once('/').map_or((address, ""), |(host, path)| (host, path));
+
+    (!host.is_empty()).then(|| (host.to_string(), format!("/{path}")))
+}
@@ -0,0 +1,121 @@
+use crate::chain::block::EdgeData;
+use crate::chain::error::ChainError;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Pending edge changes which are not yet added to the chain. Changes are added in one block when there are enough
+/// of them, or when interval since the first pending change has passed.
+#[derive(Clone)]
+pub struct Mempool {
+    changes: Vec<(EdgeData, usize)>,
+    batch_size: usize,
+    interval: Option<Duration>,
+    first_change: Option<Instant>,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Mempool {
+            changes: vec![],
+            batch_size: 1,
+            interval: None,
+            first_change: None,
+        }
+    }
+}
+
+impl Mempool {
+    /// Maximum number of changes in one block.
+    pub const MAX_BATCH_SIZE: usize = 1000;
+
+    /// Create mempool from command line arguments: `-batch_size="100" -batch_interval="5"` (interval in seconds).
+    /// If only interval is set, changes are batched until the interval has passed or maximum batch size is reached.
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<Mempool, ChainError> {
+        let argument = |name: &str| arguments.get(name).and_then(|values| values.first());
+
+        let interval = argument("batch_interval")
+            .map(|value| {
+                value
+                    .parse()
+                    .map(Duration::from_secs)
+                    .map_err(|_| ChainError::InvalidBatchConfiguration(format!("batch_interval={value}")))
+            })
+            .transpose()?;
+
+        let default_size = if interval.is_some() { Self::MAX_BATCH_SIZE } else { 1 };
+        let batch_size = argument("batch_size").map_or(Ok(default_size), |value| {
+            value
+                .parse()
+                .ok()
+                .filter(|size| (1..=Self::MAX_BATCH_SIZE).contains(size))
+                .ok_or(ChainError::InvalidBatchConfiguration(format!("batch_size={value}")))
+        })?;
+
+        Ok(Mempool {
+            batch_size,
+            interval,
+            ..Mempool::default()
+        })
+    }
+
+    /// Add change with difficulty of its account, returns true if batch is full.
+    pub fn push(&mut self, change: EdgeData, difficulty: usize) -> bool {
+        self.first_change.get_or_insert_with(Instant::now);
+        self.changes.push((change, difficulty));
+
+        self.is_full()
+    }
+
+    pub fn is_due(&self) -> bool {
+        match (self.interval, self.first_change) {
+            (Some(interval), Some(first_change)) => first_change.elapsed() >= interval,
+            _ => false,
+        }
+    }
+
+    /// Full batch is added to the chain right away, unless it was kept pending (e.g. because local validator was not
+    /// scheduled to propose the block).
+    pub fn is_full(&self) -> bool {
+        self.changes.len() >= self.batch_size
+    }
+
+    /// Take all pending changes, together with highest difficulty of their accounts.
+    pub fn take(&mut self) -> (Vec<EdgeData>, usize) {
+        self.first_change = None;
+
+        let difficulty = self.changes.iter().map(|(_, difficulty)| *difficulty).max().unwrap_or(0);
+        (self.changes.drain(..).map(|(change, _)| change).collect(), difficulty)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_batch_changes() {
+        // Given
+        let mut arguments = HashMap::new();
+        arguments.insert("batch_size".to_string(), vec!["2".to_string()]);
+        let mut mempool = Mempool::from_arguments(&arguments).unwrap();
+
+        // When
+        let is_full = (
+            mempool.push(EdgeData::new("1".to_string(), "2".to_string(), 10), 1),
+            mempool.push(EdgeData::new("1".to_string(), "3".to_string(), 20), 3),
+        );
+
+        // Then
+        assert_eq!(is_full, (false, true));
+        assert!(!mempool.is_due());
+
+        let (changes, difficulty) = mempool.take();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(difficulty, 3);
+        assert!(mempool.is_empty());
+    }
+}
@@ -0,0 +1,30 @@
+use crate::chain::block::Block;
+use crate::chain::hasher::HashAlgorithm;
+
+/// Block produced by local validator which is mined outside of the chain (e.g. in blocking task), so chain isn't locked
+/// while nonce of the block is searched. Block is signed once it's added to the chain.
+pub struct MiningJob {
+    pub(crate) block: Block,
+    zero_bits: u32,
+    hash_algorithm: HashAlgorithm,
+    // Data of the block (with difficulty of its accounts) is queued again if chain was extended while block was mined
+    pub(crate) difficulty: usize,
+}
+
+impl MiningJob {
+    pub(crate) fn new(block: Block, zero_bits: u32, hash_algorithm: HashAlgorithm, difficulty: usize) -> MiningJob {
+        MiningJob {
+            block,
+            zero_bits,
+            hash_algorithm,
+            difficulty,
+        }
+    }
+
+    /// Search for nonce of the block, which can take a while depending on required number of zero bits.
+    pub fn mine(mut self) -> MiningJob {
+        self.block.find_nonce(self.zero_bits, self.hash_algorithm);
+
+        self
+    }
+}
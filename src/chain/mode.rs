@@ -0,0 +1,28 @@
+use crate::chain::error::ChainError;
+use std::collections::HashMap;
+
+/// Role of the node in the network.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum NodeMode {
+    /// Node produces blocks with pending changes, votes for checkpoints and slashes offending validators.
+    #[default]
+    Validator,
+    /// Node only syncs and validates the chain and answers read-only queries (e.g. for dashboards), so it never changes
+    /// the graph, registers as agent or signs blocks.
+    Observer,
+}
+
+impl NodeMode {
+    /// Create mode from command line arguments: `--mode="observer"` (`validator` by default).
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<NodeMode, ChainError> {
+        match arguments.get("mode").and_then(|values| values.first()).map(|value| value.as_str()) {
+            None | Some("validator") => Ok(NodeMode::Validator),
+            Some("observer") => Ok(NodeMode::Observer),
+            Some(value) => Err(ChainError::InvalidModeConfiguration(format!("mode={value}"))),
+        }
+    }
+
+    pub fn is_observer(&self) -> bool {
+        *self == NodeMode::Observer
+    }
+}
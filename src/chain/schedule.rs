@@ -0,0 +1,136 @@
+use crate::chain::block::Block;
+use crate::chain::epoch::ValidatorSet;
+use crate::chain::error::ChainError;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Schedule of block proposers - for each height, one validator (primary) is selected from validators with stake,
+/// with probability proportional to its stake and seeded by hash of the previous block, so all peers select the same
+/// one. Other validators can propose the block only when timeout (in seconds) since the previous block has passed, e.g.
+/// when primary validator is offline.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ProposerSchedule {
+    timeout: u64,
+}
+
+impl Default for ProposerSchedule {
+    fn default() -> Self {
+        ProposerSchedule {
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+impl ProposerSchedule {
+    const DEFAULT_TIMEOUT: u64 = 30;
+
+    /// Create schedule from command line arguments: `-proposer_timeout="30"` (in seconds).
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<ProposerSchedule, ChainError> {
+        let timeout = arguments
+            .get("proposer_timeout")
+            .and_then(|values| values.first())
+            .map_or(Ok(Self::DEFAULT_TIMEOUT), |value| {
+                value
+                    .parse()
+                    .map_err(|_| ChainError::InvalidScheduleConfiguration(format!("proposer_timeout={value}")))
+            })?;
+
+        Ok(ProposerSchedule { timeout })
+    }
+
+    pub fn timeout(&self) -> u64 {
+        self.timeout
+    }
+
+    /// Primary proposer of the block following given block, or `None` if no validator has stake (anyone can propose).
+    pub fn primary(validator_set: &ValidatorSet, previous_block: &Block) -> Option<String> {
+        let mut validators: Vec<(&String, usize)> = validator_set.stakes().filter(|(_, stake)| *stake > 0).collect();
+        validators.sort();
+
+        let total_stake: usize = validators.iter().map(|(_, stake)| stake).sum();
+        if total_stake == 0 {
+            return None;
+        }
+
+        let seed = Sha256::digest(previous_block.hash.as_bytes());
+        let mut position = (u64::from_le_bytes(seed[..8].try_into().unwrap()) % total_stake as u64) as usize;
+
+        validators.into_iter().find_map(|(validator, stake)| match position < stake {
+            true => Some(validator.clone()),
+            false => {
+                position -= stake;
+                None
+            }
+        })
+    }
+
+    /// Check if validator can propose block with given timestamp after given block.
+    pub fn is_scheduled(&self, validator_set: &ValidatorSet, previous_block: &Block, validator: &String, timestamp: u64) -> bool {
+        timestamp >= previous_block.timestamp + self.timeout
+            || Self::primary(validator_set, previous_block).is_none_or(|primary| primary == *validator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::agent::AgentService;
+
+    fn validator_set(stakes: &[(&str, usize)]) -> ValidatorSet {
+        let mut agent_service = AgentService::default();
+        for (validator, stake) in stakes {
+            agent_service.genesis_stake.insert(validator.to_string(), *stake);
+        }
+
+        ValidatorSet::snapshot(&agent_service)
+    }
+
+    #[test]
+    fn should_select_primary_by_stake() {
+        // Given
+        let validator_set = validator_set(&[("first", 1), ("second", 3), ("without_stake", 0)]);
+        let blocks: Vec<Block> = (0..400)
+            .map(|id| Block {
+                hash: id.to_string(),
+                ..Block::default()
+            })
+            .collect();
+
+        // When
+        let primaries: Vec<String> = blocks
+            .iter()
+            .map(|block| ProposerSchedule::primary(&validator_set, block).unwrap())
+            .collect();
+
+        // Then
+        let count = |validator: &str| primaries.iter().filter(|primary| *primary == validator).count();
+        assert_eq!(count("first") + count("second"), 400);
+        assert!(count("second") > 2 * count("first"));
+        assert_eq!(
+            ProposerSchedule::primary(&validator_set, &blocks[7]),
+            ProposerSchedule::primary(&validator_set, &blocks[7])
+        );
+    }
+
+    #[test]
+    fn should_allow_other_validators_after_timeout() {
+        // Given
+        let schedule = ProposerSchedule::default();
+        let validator_set = validator_set(&[("first", 1), ("second", 1)]);
+        let previous_block = Block::default();
+        let primary = ProposerSchedule::primary(&validator_set, &previous_block).unwrap();
+        let other = if primary == "first" { "second" } else { "first" }.to_string();
+
+        // When
+        let primary_in_time = schedule.is_scheduled(&validator_set, &previous_block, &primary, previous_block.timestamp);
+        let other_in_time = schedule.is_scheduled(&validator_set, &previous_block, &other, previous_block.timestamp + 1);
+        let other_after_timeout = schedule.is_scheduled(&validator_set, &previous_block, &other, previous_block.timestamp + 30);
+        let without_validators = schedule.is_scheduled(&ValidatorSet::default(), &previous_block, &other, previous_block.timestamp);
+
+        // Then
+        assert!(primary_in_time);
+        assert!(!other_in_time);
+        assert!(other_after_timeout);
+        assert!(without_validators);
+    }
+}
@@ -0,0 +1,159 @@
+use ed25519_dalek::Verifier;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum SignatureError {
+    InvalidPublicKey,
+    InvalidSignature,
+}
+
+/// Scheme by which validators sign blocks, with 32 byte secret keys (so any key can be backed up as mnemonic).
+pub trait SignatureScheme: Send + Sync {
+    fn generate(&self) -> [u8; 32];
+
+    /// Public key of given secret key, or none if secret key isn't valid for the scheme.
+    fn public_key(&self, secret_key: &[u8; 32]) -> Option<Vec<u8>>;
+
+    fn sign(&self, secret_key: &[u8; 32], message: &[u8]) -> Vec<u8>;
+
+    fn verify(&self, public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), SignatureError>;
+}
+
+pub struct Ed25519Scheme;
+
+/// ECDSA over secp256k1 curve (used by e.g. Ethereum keys), with compressed public key and compact signature.
+pub struct Secp256k1Scheme;
+
+impl SignatureScheme for Ed25519Scheme {
+    fn generate(&self) -> [u8; 32] {
+        ed25519_dalek::SigningKey::generate(&mut OsRng).to_bytes()
+    }
+
+    fn public_key(&self, secret_key: &[u8; 32]) -> Option<Vec<u8>> {
+        Some(ed25519_dalek::SigningKey::from_bytes(secret_key).verifying_key().to_bytes().to_vec())
+    }
+
+    fn sign(&self, secret_key: &[u8; 32], message: &[u8]) -> Vec<u8> {
+        ed25519_dalek::Signer::sign(&ed25519_dalek::SigningKey::from_bytes(secret_key), message).to_vec()
+    }
+
+    fn verify(&self, public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), SignatureError> {
+        let public_key = public_key
+            .try_into()
+            .ok()
+            .and_then(|bytes| ed25519_dalek::VerifyingKey::from_bytes(bytes).ok())
+            .ok_or(SignatureError::InvalidPublicKey)?;
+        let signature = ed25519_dalek::Signature::from_slice(signature).map_err(|_| SignatureError::InvalidSignature)?;
+
+        public_key.verify(message, &signature).map_err(|_| SignatureError::InvalidSignature)
+    }
+}
+
+impl SignatureScheme for Secp256k1Scheme {
+    fn generate(&self) -> [u8; 32] {
+        k256::ecdsa::SigningKey::random(&mut OsRng).to_bytes().into()
+    }
+
+    fn public_key(&self, secret_key: &[u8; 32]) -> Option<Vec<u8>> {
+        let signing_key = k256::ecdsa::SigningKey::from_slice(secret_key).ok()?;
+
+        Some(signing_key.verifying_key().to_sec1_bytes().to_vec())
+    }
+
+    fn sign(&self, secret_key: &[u8; 32], message: &[u8]) -> Vec<u8> {
+        let signing_key = k256::ecdsa::SigningKey::from_slice(secret_key).expect("Secret key is valid secp256k1 key");
+        let signature: k256::ecdsa::Signature = k256::ecdsa::signature::Signer::sign(&signing_key, message);
+
+        signature.to_vec()
+    }
+
+    fn verify(&self, public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), SignatureError> {
+        let public_key = k256::ecdsa::VerifyingKey::from_sec1_bytes(public_key).map_err(|_| SignatureError::InvalidPublicKey)?;
+        let signature = k256::ecdsa::Signature::from_slice(signature).map_err(|_| SignatureError::InvalidSignature)?;
+
+        k256::ecdsa::signature::Verifier::verify(&public_key, message, &signature).map_err(|_| SignatureError::InvalidSignature)
+    }
+}
+
+/// Signature scheme of the validator key. Scheme is recognized by length of the public key (32 bytes for ed25519, 33
+/// bytes for compressed secp256k1 key), so blocks can be validated without looking up validator registration.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureAlgorithm {
+    #[default]
+    Ed25519,
+    Secp256k1,
+}
+
+impl SignatureAlgorithm {
+    const ED25519_KEY_LENGTH: usize = 32;
+    const SECP256K1_KEY_LENGTH: usize = 33;
+
+    pub fn scheme(&self) -> &'static dyn SignatureScheme {
+        match self {
+            SignatureAlgorithm::Ed25519 => &Ed25519Scheme,
+            SignatureAlgorithm::Secp256k1 => &Secp256k1Scheme,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SignatureAlgorithm> {
+        match name {
+            "ed25519" => Some(SignatureAlgorithm::Ed25519),
+            "secp256k1" => Some(SignatureAlgorithm::Secp256k1),
+            _ => None,
+        }
+    }
+
+    /// Scheme of hex encoded public key, or none if public key isn't valid hex of known length.
+    pub fn of_public_key(public_key: &str) -> Option<SignatureAlgorithm> {
+        match hex::decode(public_key).ok()?.len() {
+            Self::ED25519_KEY_LENGTH => Some(SignatureAlgorithm::Ed25519),
+            Self::SECP256K1_KEY_LENGTH => Some(SignatureAlgorithm::Secp256k1),
+            _ => None,
+        }
+    }
+
+    /// Verify signature (hex encoded) of message by public key (hex encoded) in its scheme.
+    pub fn verify(public_key: &str, message: &[u8], signature: &str) -> Result<(), SignatureError> {
+        let scheme = Self::of_public_key(public_key).ok_or(SignatureError::InvalidPublicKey)?;
+        let public_key = hex::decode(public_key).map_err(|_| SignatureError::InvalidPublicKey)?;
+        let signature = hex::decode(signature).map_err(|_| SignatureError::InvalidSignature)?;
+
+        scheme.scheme().verify(&public_key, message, &signature)
+    }
+
+    pub(crate) fn is_default(&self) -> bool {
+        *self == SignatureAlgorithm::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_sign_and_verify_with_each_scheme() {
+        for algorithm in [SignatureAlgorithm::Ed25519, SignatureAlgorithm::Secp256k1] {
+            // Given
+            let scheme = algorithm.scheme();
+            let secret_key = scheme.generate();
+            let public_key = hex::encode(scheme.public_key(&secret_key).unwrap());
+
+            // When
+            let signature = hex::encode(scheme.sign(&secret_key, b"message"));
+
+            // Then
+            assert_eq!(SignatureAlgorithm::of_public_key(&public_key), Some(algorithm));
+            assert!(SignatureAlgorithm::verify(&public_key, b"message", &signature).is_ok());
+            assert_eq!(
+                SignatureAlgorithm::verify(&public_key, b"other", &signature),
+                Err(SignatureError::InvalidSignature)
+            );
+            assert_eq!(
+                SignatureAlgorithm::verify("key", b"message", &signature),
+                Err(SignatureError::InvalidPublicKey)
+            );
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use crate::chain::error::ChainError;
+use crate::chain::scheme::SignatureAlgorithm;
+use crate::chain::wallet::Wallet;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+/// Signer of the blocks produced by local validator - either in-process wallet or external signer (e.g. HSM).
+pub trait Signer: Send + Sync {
+    fn get_public_key(&self) -> String;
+
+    fn sign(&mut self, message: &str) -> Result<String, ChainError>;
+
+    fn export_mnemonic(&self) -> Result<String, ChainError> {
+        Err(ChainError::SignerError("Mnemonic can't be exported from external signer".to_string()))
+    }
+
+    fn rotate(&self) -> Result<Box<dyn Signer>, ChainError> {
+        Err(ChainError::SignerError("Key of external signer can't be rotated".to_string()))
+    }
+
+    /// Persist current key of the signer, after rotation block signed by it was added to the chain.
+    fn store(&self) -> Result<(), ChainError> {
+        Ok(())
+    }
+}
+
+impl Signer for Wallet {
+    fn get_public_key(&self) -> String {
+        Wallet::get_public_key(self)
+    }
+
+    fn sign(&mut self, message: &str) -> Result<String, ChainError> {
+        Ok(Wallet::sign(self, &message.to_string()))
+    }
+
+    fn export_mnemonic(&self) -> Result<String, ChainError> {
+        Ok(Wallet::export_mnemonic(self))
+    }
+
+    fn rotate(&self) -> Result<Box<dyn Signer>, ChainError> {
+        Wallet::rotate(self).map(|wallet| Box::new(wallet) as Box<dyn Signer>)
+    }
+
+    fn store(&self) -> Result<(), ChainError> {
+        Wallet::store(self)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+enum SignerRequest<'a> {
+    PublicKey,
+    Sign { message: &'a str },
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct SignerResponse {
+    public_key: Option<String>,
+    signature: Option<String>,
+    error: Option<String>,
+}
+
+/// Signer running as separate process, reachable by Unix socket. Each request is sent as one JSON line on new
+/// connection, e.g. `{"method":"sign","message":"..."}`, and signer answers with one JSON line: `{"signature":"..."}`
+/// (`{"public_key":"..."}` for public key request) or `{"error":"..."}`. Public key is fetched once, on connect, and
+/// every returned signature is verified by it, so faulty signer can't produce blocks with invalid signature.
+pub struct RemoteSigner {
+    path: String,
+    public_key: String,
+}
+
+impl RemoteSigner {
+    /// Maximal time of writing request to the signer and reading its response, so hung signer doesn't block the chain.
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Connect to remote signer given by `--remote-signer="/run/signer.sock"` argument, if it is given.
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<Option<RemoteSigner>, ChainError> {
+        arguments
+            .get("remote-signer")
+            .and_then(|values| values.first())
+            .map(|path| Self::connect(path))
+            .transpose()
+    }
+
+    pub fn connect(path: &str) -> Result<RemoteSigner, ChainError> {
+        let public_key = Self::request(path, &SignerRequest::PublicKey)?
+            .public_key
+            .ok_or(ChainError::SignerError("Signer did not return public key".to_string()))?;
+
+        if SignatureAlgorithm::of_public_key(&public_key).is_none() {
+            return Err(ChainError::SignerError("Signer returned invalid public key".to_string()));
+        }
+
+        Ok(RemoteSigner {
+            path: path.to_string(),
+            public_key,
+        })
+    }
+
+    fn request(path: &str, request: &SignerRequest) -> Result<SignerResponse, ChainError> {
+        let error = |reason: String| ChainError::SignerError(format!("{path}: {reason}"));
+
+        let mut stream = UnixStream::connect(path).map_err(|e| error(e.to_string()))?;
+        stream.set_read_timeout(Some(Self::TIMEOUT)).map_err(|e| error(e.to_string()))?;
+        stream.set_write_timeout(Some(Self::TIMEOUT)).map_err(|e| error(e.to_string()))?;
+        let mut line = serde_json::to_string(request).map_err(|e| error(e.to_string()))?;
+        line.push('\n');
+        stream.write_all(line.as_bytes()).map_err(|e| error(e.to_string()))?;
+
+        let mut response = String::new();
+        BufReader::new(stream).read_line(&mut response).map_err(|e| error(e.to_string()))?;
+
+        let response: SignerResponse = serde_json::from_str(&response).map_err(|e| error(e.to_string()))?;
+
+        match response.error {
+            Some(reason) => Err(error(reason)),
+            None => Ok(response),
+        }
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn get_public_key(&self) -> String {
+        self.public_key.clone()
+    }
+
+    fn sign(&mut self, message: &str) -> Result<String, ChainError> {
+        let signature = Self::request(&self.path, &SignerRequest::Sign { message })?
+            .signature
+            .ok_or(ChainError::SignerError("Signer did not return signature".to_string()))?;
+
+        SignatureAlgorithm::verify(&self.public_key, message.as_bytes(), &signature)
+            .map_err(|_| ChainError::SignerError("Signer returned invalid signature".to_string()))?;
+
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    #[test]
+    fn should_sign_with_remote_signer() {
+        // Given
+        let path = env::temp_dir().join("should_sign_with_remote_signer.sock");
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let mut wallet = Wallet::default();
+        let public_key = wallet.get_public_key();
+        let signature = wallet.sign(&"hash".to_string());
+
+        let server = thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut request = String::new();
+                BufReader::new(&stream).read_line(&mut request).unwrap();
+
+                let response = if request.contains("public_key") {
+                    serde_json::json!({ "public_key": wallet.get_public_key() })
+                } else {
+                    serde_json::json!({ "signature": wallet.sign(&"hash".to_string()) })
+                };
+                stream.write_all(format!("{response}\n").as_bytes()).unwrap();
+            }
+        });
+
+        // When
+        let mut signer = RemoteSigner::connect(path.to_str().unwrap()).unwrap();
+        let result = signer.sign("hash");
+
+        // Then
+        assert_eq!(signer.get_public_key(), public_key);
+        assert_eq!(result.unwrap(), signature);
+        assert!(signer.export_mnemonic().is_err());
+
+        server.join().unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_reject_invalid_signature_of_remote_signer() {
+        // Given
+        let path = env::temp_dir().join("should_reject_invalid_signature_of_remote_signer.sock");
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let mut wallet = Wallet::default();
+        let public_key = wallet.get_public_key();
+
+        let server = thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut request = String::new();
+                BufReader::new(&stream).read_line(&mut request).unwrap();
+
+                let response = if request.contains("public_key") {
+                    serde_json::json!({ "public_key": public_key })
+                } else {
+                    serde_json::json!({ "signature": wallet.sign(&"other".to_string()) })
+                };
+                stream.write_all(format!("{response}\n").as_bytes()).unwrap();
+            }
+        });
+
+        // When
+        let mut signer = RemoteSigner::connect(path.to_str().unwrap()).unwrap();
+        let result = signer.sign("hash");
+
+        // Then
+        assert!(matches!(result, Err(ChainError::SignerError(reason)) if reason == "Signer returned invalid signature"));
+
+        server.join().unwrap();
+        fs::remove_file(path).unwrap();
+    }
+}
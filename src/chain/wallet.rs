@@ -1,32 +1,246 @@
-use ed25519_dalek::{Signer, SigningKey};
+use crate::chain::error::ChainError;
+use crate::chain::scheme::SignatureAlgorithm;
+use bip39::Mnemonic;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
 use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
 
 pub struct Wallet {
-    signing_key: SigningKey,
+    secret_key: [u8; 32],
+    scheme: SignatureAlgorithm,
+    // Path and passphrase of key file from which wallet was loaded
+    key_file: Option<(String, String)>,
 }
 
 impl Default for Wallet {
     fn default() -> Self {
-        Wallet {
-            signing_key: SigningKey::generate(&mut OsRng),
-        }
+        Wallet::generate(SignatureAlgorithm::default())
     }
 }
 
+/// Signing key encrypted with key derived from passphrase, as stored on disk.
+#[derive(Serialize, Deserialize)]
+struct KeyFile {
+    public_key: String,
+    salt: String,
+    nonce: String,
+    encrypted_key: String,
+    // Skipped for ed25519 keys, so key files created before other schemes were supported are not changed
+    #[serde(default, skip_serializing_if = "SignatureAlgorithm::is_default")]
+    scheme: SignatureAlgorithm,
+}
+
 impl Wallet {
+    const KEY_DERIVATION_ROUNDS: u32 = 100_000;
+    const PASSPHRASE_VARIABLE: &'static str = "WALLET_PASSPHRASE";
+
+    /// Load wallet from key file given by `--key-file="wallet.json"` argument. Passphrase is read from
+    /// `--key-passphrase` argument or WALLET_PASSPHRASE environment variable. Wallet can also be recovered from
+    /// mnemonic given by `--recover-from-mnemonic="..."` argument, and stored to the new key file if it is given.
+    /// Signature scheme of new or recovered wallet is given by `--key-scheme="secp256k1"` argument (ed25519 by
+    /// default), while loaded wallet keeps scheme of its key file. Without key file or mnemonic, no wallet is loaded.
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<Option<Wallet>, ChainError> {
+        let argument = |name: &str| arguments.get(name).and_then(|values| values.first()).cloned();
+
+        let scheme = argument("key-scheme").map_or(Ok(SignatureAlgorithm::default()), |name| {
+            SignatureAlgorithm::from_name(&name).ok_or(ChainError::InvalidSignatureScheme(name))
+        })?;
+        let recovered = argument("recover-from-mnemonic")
+            .map(|mnemonic| Self::from_mnemonic(&mnemonic, scheme))
+            .transpose()?;
+
+        let Some(path) = argument("key-file") else {
+            return Ok(recovered);
+        };
+
+        let passphrase = argument("key-passphrase")
+            .or_else(|| env::var(Self::PASSPHRASE_VARIABLE).ok())
+            .ok_or(ChainError::WalletError(
+                path.clone(),
+                format!("Passphrase is required (--key-passphrase or {})", Self::PASSPHRASE_VARIABLE),
+            ))?;
+
+        let mut wallet = match recovered {
+            Some(wallet) => wallet.save(&path, &passphrase).map(|_| wallet),
+            None => Self::load_or_create(&path, &passphrase, scheme),
+        }?;
+
+        wallet.key_file = Some((path, passphrase));
+        Ok(Some(wallet))
+    }
+
+    /// Load wallet from key file encrypted with given passphrase, or create new wallet and store it to the file if
+    /// file doesn't exist, so validator keeps its identity (and stake) between restarts.
+    pub fn load_or_create(path: &str, passphrase: &str, scheme: SignatureAlgorithm) -> Result<Wallet, ChainError> {
+        let error = |reason: String| ChainError::WalletError(path.to_string(), reason);
+
+        if Path::new(path).exists() {
+            let content = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+            let key_file: KeyFile = serde_json::from_str(&content).map_err(|e| error(e.to_string()))?;
+
+            return Self::decrypt(&key_file, passphrase).ok_or(error("Wrong passphrase or corrupted key file".to_string()));
+        }
+
+        let wallet = Wallet::generate(scheme);
+        wallet.save(path, passphrase)?;
+
+        Ok(wallet)
+    }
+
+    /// Create wallet with new key of given signature scheme.
+    pub fn generate(scheme: SignatureAlgorithm) -> Wallet {
+        Wallet {
+            secret_key: scheme.scheme().generate(),
+            scheme,
+            key_file: None,
+        }
+    }
+
+    /// Wallet with given secret key, or none if secret key isn't valid for given scheme.
+    fn from_secret_key(secret_key: [u8; 32], scheme: SignatureAlgorithm) -> Option<Wallet> {
+        scheme.scheme().public_key(&secret_key)?;
+
+        Some(Wallet {
+            secret_key,
+            scheme,
+            key_file: None,
+        })
+    }
+
+    /// Store wallet to the new key file, existing key file is never overwritten. Only owner can read the key file.
+    fn save(&self, path: &str, passphrase: &str) -> Result<(), ChainError> {
+        let error = |reason: String| ChainError::WalletError(path.to_string(), reason);
+
+        let key_file = serde_json::to_string_pretty(&self.encrypt(passphrase)).map_err(|e| error(e.to_string()))?;
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let mut file = options.open(path).map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => error("Key file already exists".to_string()),
+            _ => error(e.to_string()),
+        })?;
+        file.write_all(key_file.as_bytes()).map_err(|e| error(e.to_string()))
+    }
+
+    /// Create wallet with new signing key of the same scheme, which uses key file of this wallet (if any). New key
+    /// isn't written to the key file until it is stored.
+    pub fn rotate(&self) -> Result<Wallet, ChainError> {
+        let mut wallet = Wallet::generate(self.scheme);
+        wallet.key_file = self.key_file.clone();
+
+        Ok(wallet)
+    }
+
+    /// Replace key file of the wallet with its current key, while previous key file is kept with .old extension. Does
+    /// nothing if wallet wasn't loaded from key file.
+    pub fn store(&self) -> Result<(), ChainError> {
+        let Some((path, passphrase)) = &self.key_file else {
+            return Ok(());
+        };
+
+        let old_path = format!("{path}.old");
+        fs::rename(path, &old_path).map_err(|error| ChainError::WalletError(path.clone(), error.to_string()))?;
+
+        self.save(path, passphrase).inspect_err(|_| {
+            let _ = fs::rename(&old_path, path);
+        })
+    }
+
+    /// Recover wallet of given signature scheme from BIP39 mnemonic (24 words) exported by `wallet export mnemonic`
+    /// command.
+    pub fn from_mnemonic(mnemonic: &str, scheme: SignatureAlgorithm) -> Result<Wallet, ChainError> {
+        let entropy = Mnemonic::parse(mnemonic)
+            .map_err(|error| ChainError::InvalidMnemonic(error.to_string()))?
+            .to_entropy();
+
+        let key = entropy
+            .as_slice()
+            .try_into()
+            .map_err(|_| ChainError::InvalidMnemonic(format!("Mnemonic must have 24 words, but it has {}", mnemonic.split_whitespace().count())))?;
+
+        Self::from_secret_key(key, scheme).ok_or(ChainError::InvalidMnemonic(format!("Mnemonic is not valid {scheme:?} key")))
+    }
+
+    /// Signing key encoded as BIP39 mnemonic, so it can be backed up in human-friendly form.
+    pub fn export_mnemonic(&self) -> String {
+        Mnemonic::from_entropy(&self.secret_key)
+            .expect("Signing key is valid mnemonic entropy")
+            .to_string()
+    }
+
+    fn encrypt(&self, passphrase: &str) -> KeyFile {
+        let mut salt = [0u8; 16];
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let encrypted_key = Self::cipher(passphrase, &salt)
+            .encrypt(Nonce::from_slice(&nonce), self.secret_key.as_slice())
+            .expect("Encryption of signing key failed");
+
+        KeyFile {
+            public_key: self.get_public_key(),
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            encrypted_key: hex::encode(encrypted_key),
+            scheme: self.scheme,
+        }
+    }
+
+    fn decrypt(key_file: &KeyFile, passphrase: &str) -> Option<Wallet> {
+        let salt = hex::decode(&key_file.salt).ok()?;
+        let nonce = hex::decode(&key_file.nonce).ok()?;
+        let encrypted_key = hex::decode(&key_file.encrypted_key).ok()?;
+
+        if nonce.len() != 12 {
+            return None;
+        }
+
+        let key = Self::cipher(passphrase, &salt)
+            .decrypt(Nonce::from_slice(&nonce), encrypted_key.as_slice())
+            .ok()?;
+
+        let wallet = Self::from_secret_key(key.as_slice().try_into().ok()?, key_file.scheme)?;
+
+        (wallet.get_public_key() == key_file.public_key).then_some(wallet)
+    }
+
+    fn cipher(passphrase: &str, salt: &[u8]) -> ChaCha20Poly1305 {
+        let mut key = [0u8; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, Self::KEY_DERIVATION_ROUNDS, &mut key);
+
+        ChaCha20Poly1305::new(Key::from_slice(&key))
+    }
+
     pub(crate) fn get_public_key(&self) -> String {
-        hex::encode(self.signing_key.verifying_key().to_bytes())
+        hex::encode(
+            self.scheme
+                .scheme()
+                .public_key(&self.secret_key)
+                .expect("Secret key is valid for its scheme"),
+        )
     }
 
     pub fn sign(&mut self, hash: &String) -> String {
-        hex::encode(self.signing_key.sign(hash.as_bytes()).to_vec())
+        hex::encode(self.scheme.scheme().sign(&self.secret_key, hash.as_bytes()))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+    use ed25519_dalek::{Signature, SigningKey, Verifier, VerifyingKey};
     use std::str::FromStr;
 
     #[test]
@@ -39,9 +253,9 @@ mod tests {
         let signature = wallet.sign(&message);
 
         // Then
-        assert_eq!(wallet.get_public_key(), hex::encode(wallet.signing_key.verifying_key().to_bytes()));
-        assert!(wallet
-            .signing_key
+        let signing_key = SigningKey::from_bytes(&wallet.secret_key);
+        assert_eq!(wallet.get_public_key(), hex::encode(signing_key.verifying_key().to_bytes()));
+        assert!(signing_key
             .verify(message.as_bytes(), &Signature::from_str(signature.as_str()).unwrap())
             .is_ok());
 
@@ -50,4 +264,79 @@ mod tests {
             .verify(message.as_bytes(), &Signature::from_str(signature.as_str()).unwrap())
             .is_ok());
     }
+
+    #[test]
+    fn should_load_or_create_wallet() {
+        // Given
+        let path = env::temp_dir().join("should_load_or_create_wallet.json");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        // When
+        let created = Wallet::load_or_create(path, "passphrase", SignatureAlgorithm::Secp256k1).unwrap();
+        let loaded = Wallet::load_or_create(path, "passphrase", SignatureAlgorithm::Ed25519).unwrap();
+
+        // Then
+        assert_eq!(created.get_public_key(), loaded.get_public_key());
+        assert_eq!(loaded.scheme, SignatureAlgorithm::Secp256k1);
+        assert!(!fs::read_to_string(path).unwrap().contains(&hex::encode(created.secret_key)));
+        assert!(Wallet::load_or_create(path, "wrong", SignatureAlgorithm::Secp256k1).is_err());
+        #[cfg(unix)]
+        assert_eq!(
+            std::os::unix::fs::PermissionsExt::mode(&fs::metadata(path).unwrap().permissions()) & 0o777,
+            0o600
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_store_rotated_wallet() {
+        // Given
+        let path = env::temp_dir().join("should_store_rotated_wallet.json");
+        let path = path.to_str().unwrap();
+        let old_path = format!("{path}.old");
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(&old_path);
+        let mut wallet = Wallet::load_or_create(path, "passphrase", SignatureAlgorithm::Ed25519).unwrap();
+        wallet.key_file = Some((path.to_string(), "passphrase".to_string()));
+
+        // When
+        let rotated = wallet.rotate().unwrap();
+        let before_store = Wallet::load_or_create(path, "passphrase", SignatureAlgorithm::Ed25519).unwrap();
+        rotated.store().unwrap();
+
+        // Then
+        assert_eq!(before_store.get_public_key(), wallet.get_public_key());
+        assert_eq!(
+            Wallet::load_or_create(path, "passphrase", SignatureAlgorithm::Ed25519)
+                .unwrap()
+                .get_public_key(),
+            rotated.get_public_key()
+        );
+        assert_eq!(
+            Wallet::load_or_create(&old_path, "passphrase", SignatureAlgorithm::Ed25519)
+                .unwrap()
+                .get_public_key(),
+            wallet.get_public_key()
+        );
+
+        fs::remove_file(path).unwrap();
+        fs::remove_file(&old_path).unwrap();
+    }
+
+    #[test]
+    fn should_recover_wallet_from_mnemonic() {
+        // Given
+        let wallet = Wallet::default();
+        let mnemonic = wallet.export_mnemonic();
+
+        // When
+        let recovered = Wallet::from_mnemonic(&mnemonic, SignatureAlgorithm::Ed25519).unwrap();
+
+        // Then
+        assert_eq!(mnemonic.split_whitespace().count(), 24);
+        assert_eq!(recovered.get_public_key(), wallet.get_public_key());
+        assert!(Wallet::from_mnemonic("abandon abandon abandon", SignatureAlgorithm::Ed25519).is_err());
+    }
 }
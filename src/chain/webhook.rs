@@ -0,0 +1,451 @@
+use crate::chain::block::{Block, NodeData};
+use crate::chain::error::ChainError;
+use crate::chain::http;
+use crate::graph::condition::Condition;
+use hmac::{Hmac, Mac};
+use rustc_hash::FxHashSet;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Event of the chain, or of the graph recorded in the chain, which webhook can subscribe to.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    BlockAccepted,
+    ChainReplaced,
+    NodeChanged,
+}
+
+impl WebhookEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::BlockAccepted => "block_accepted",
+            WebhookEvent::ChainReplaced => "chain_replaced",
+            WebhookEvent::NodeChanged => "node_changed",
+        }
+    }
+}
+
+/// Node definition whose changes are sent to webhook, optionally only if changed node meets all conditions.
+#[derive(Deserialize)]
+struct NodeFilter {
+    name: String,
+    #[serde(default)]
+    conditions: Vec<Condition>,
+}
+
+impl NodeFilter {
+    fn matches(&self, node: &NodeData) -> bool {
+        let attributes = node.attributes.iter().map(|(name, value)| (name.clone(), value.clone())).collect();
+
+        node.name == self.name && self.conditions.iter().all(|condition| condition.is_met(&attributes))
+    }
+}
+
+#[derive(Deserialize)]
+struct Webhook {
+    url: String,
+    events: Vec<WebhookEvent>,
+    #[serde(default)]
+    node: Option<NodeFilter>,
+    #[serde(default)]
+    secret: Option<String>,
+}
+
+impl Webhook {
+    fn accepts(&self, event: WebhookEvent, node: Option<&NodeData>) -> bool {
+        self.events.contains(&event)
+            && match (&self.node, node) {
+                (Some(filter), Some(node)) => filter.matches(node),
+                _ => true,
+            }
+    }
+
+    /// Send payload as JSON, signed by HMAC-SHA256 with secret of the webhook (if it is given), so receiver can check
+    /// that payload was sent by this node.
+    fn send(&self, event: WebhookEvent, payload: &str) -> Result<(), String> {
+        let (host, path) = http::parse_url(&self.url).ok_or(format!("URL {} is not supported", self.url))?;
+
+        let mut headers = vec![
+            ("Content-Type", "application/json".to_string()),
+            ("X-Webhook-Event", event.name().to_string()),
+        ];
+        if let Some(secret) = &self.secret {
+            headers.push(("X-Webhook-Signature", format!("sha256={}", Webhooks::signature(secret, payload))));
+        }
+
+        http::post(&host, &path, &headers, payload)
+    }
+}
+
+/// Payload waiting to be sent to webhook, after given time.
+struct Delivery {
+    hook: usize,
+    event: WebhookEvent,
+    payload: String,
+    attempts: u32,
+    retry_at: Instant,
+}
+
+/// HTTP webhooks, called when block is accepted to the chain, when the chain is replaced by longer chain, or when node
+/// matching filter of the webhook is changed by accepted block. Payloads are queued when the event happens, and due
+/// payloads are taken on each tick of the node and sent by blocking task, so slow endpoint doesn't slow down the chain.
+/// Failed payload is sent again with exponential backoff, and it is dropped after the last attempt (or when too many
+/// payloads are queued), so payloads can arrive out of order - each payload contains height of its block.
+pub struct Webhooks {
+    hooks: Arc<Vec<Webhook>>,
+    pending: VecDeque<Delivery>,
+    // Due payloads are taken by one delivery at a time
+    sending: bool,
+}
+
+/// Due payloads taken from the queue together with their webhooks, so they can be sent (e.g. in blocking task) without
+/// holding the chain.
+pub struct WebhookDelivery {
+    hooks: Arc<Vec<Webhook>>,
+    due: Vec<Delivery>,
+    // Result of each sent payload, payloads of webhook which failed before are not sent
+    results: Vec<Option<Result<(), String>>>,
+}
+
+impl WebhookDelivery {
+    /// Send due payloads. After webhook fails, its other payloads are not sent by this delivery.
+    pub fn send(mut self) -> WebhookDelivery {
+        let mut failed = FxHashSet::default();
+
+        self.results = self
+            .due
+            .iter()
+            .map(|delivery| {
+                if failed.contains(&delivery.hook) {
+                    return None;
+                }
+
+                let result = self.hooks[delivery.hook].send(delivery.event, &delivery.payload);
+                if result.is_err() {
+                    failed.insert(delivery.hook);
+                }
+                Some(result)
+            })
+            .collect();
+
+        self
+    }
+}
+
+impl Webhooks {
+    const MAX_ATTEMPTS: u32 = 8;
+    /// Payloads sent by one delivery, so endpoint which is down doesn't postpone payloads of other webhooks too long.
+    const MAX_DELIVERIES: usize = 16;
+    const MAX_PENDING: usize = 4096;
+    const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+    /// Load webhooks given by `--webhooks="webhooks.json"` argument, if it is given.
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<Option<Webhooks>, ChainError> {
+        arguments
+            .get("webhooks")
+            .and_then(|values| values.first())
+            .map(|path| Self::open(path))
+            .transpose()
+    }
+
+    /// Load webhooks from JSON file, e.g.
+    /// `[{"url":"http://127.0.0.1:9000/users","events":["node_changed"],"node":{"name":"User"},"secret":"..."}]`.
+    pub fn open(path: &str) -> Result<Webhooks, ChainError> {
+        let error = |reason: String| ChainError::InvalidWebhookConfiguration(path.to_string(), reason);
+
+        let content = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        let hooks: Vec<Webhook> = serde_json::from_str(&content).map_err(|e| error(e.to_string()))?;
+
+        if let Some(hook) = hooks.iter().find(|hook| http::parse_url(&hook.url).is_none()) {
+            return Err(error(format!("URL {} is not supported", hook.url)));
+        }
+
+        Ok(Webhooks {
+            hooks: Arc::new(hooks),
+            pending: VecDeque::new(),
+            sending: false,
+        })
+    }
+
+    /// Queue payloads of block accepted to the chain, and of node changed by it.
+    pub fn block_accepted(&mut self, chain_id: &str, block: &Block) {
+        let payload = json!({
+            "event": WebhookEvent::BlockAccepted.name(),
+            "chain_id": chain_id,
+            "height": block.id,
+            "hash": block.hash,
+            "validator": block.validator,
+            "timestamp": block.timestamp,
+            "data_type": block.data.data_type,
+        });
+        self.queue(WebhookEvent::BlockAccepted, payload, None);
+
+        for node in block.data.nodes() {
+            let payload = json!({
+                "event": WebhookEvent::NodeChanged.name(),
+                "chain_id": chain_id,
+                "height": block.id,
+                "hash": block.hash,
+                "change": node,
+            });
+            self.queue(WebhookEvent::NodeChanged, payload, Some(node));
+        }
+    }
+
+    /// Queue payload of replaced chain, followed by payloads of its blocks after the divergence point.
+    pub fn chain_replaced(&mut self, chain_id: &str, blocks: &[Block], divergence: usize, abandoned: usize) {
+        let payload = json!({
+            "event": WebhookEvent::ChainReplaced.name(),
+            "chain_id": chain_id,
+            "divergence": divergence,
+            "abandoned": abandoned,
+            "height": blocks.len() - 1,
+            "hash": blocks.last().map(|block| &block.hash),
+        });
+        self.queue(WebhookEvent::ChainReplaced, payload, None);
+
+        for block in &blocks[divergence..] {
+            self.block_accepted(chain_id, block);
+        }
+    }
+
+    fn queue(&mut self, event: WebhookEvent, payload: Value, node: Option<&NodeData>) {
+        let payload = payload.to_string();
+
+        for (hook, webhook) in self.hooks.iter().enumerate() {
+            if !webhook.accepts(event, node) {
+                continue;
+            }
+
+            if self.pending.len() == Self::MAX_PENDING {
+                let dropped = self.pending.pop_front().unwrap();
+                let error = ChainError::WebhookError(self.hooks[dropped.hook].url.clone(), "too many payloads are queued".to_string());
+                eprintln!("Chain error: {error}");
+            }
+
+            self.pending.push_back(Delivery {
+                hook,
+                event,
+                payload: payload.clone(),
+                attempts: 0,
+                retry_at: Instant::now(),
+            });
+        }
+    }
+
+    /// Send queued payloads whose time has come, and return number of sent payloads. After webhook fails, its other
+    /// payloads are not sent until the next call.
+    pub fn deliver(&mut self) -> usize {
+        match self.delivery() {
+            Some(delivery) => self.complete(delivery.send()),
+            None => 0,
+        }
+    }
+
+    /// Take queued payloads whose time has come for delivery, or none if no payload is due (or previous delivery isn't
+    /// completed yet).
+    pub fn delivery(&mut self) -> Option<WebhookDelivery> {
+        if self.sending {
+            return None;
+        }
+
+        let now = Instant::now();
+        let mut due = vec![];
+        let mut remaining = VecDeque::new();
+        for delivery in self.pending.drain(..) {
+            if delivery.retry_at <= now && due.len() < Self::MAX_DELIVERIES {
+                due.push(delivery);
+            } else {
+                remaining.push_back(delivery);
+            }
+        }
+        self.pending = remaining;
+
+        if due.is_empty() {
+            return None;
+        }
+
+        self.sending = true;
+        Some(WebhookDelivery {
+            hooks: self.hooks.clone(),
+            due,
+            results: vec![],
+        })
+    }
+
+    /// Queue failed (and not sent) payloads of the delivery again, before payloads queued in the meantime, and return
+    /// number of sent payloads.
+    pub fn complete(&mut self, delivery: WebhookDelivery) -> usize {
+        self.sending = false;
+
+        let now = Instant::now();
+        let mut delivered = 0;
+        let mut remaining = VecDeque::new();
+        for (index, mut payload) in delivery.due.into_iter().enumerate() {
+            match delivery.results.get(index).cloned().flatten() {
+                Some(Ok(())) => delivered += 1,
+                None => remaining.push_back(payload),
+                Some(Err(error)) => {
+                    payload.attempts += 1;
+
+                    if payload.attempts == Self::MAX_ATTEMPTS {
+                        let reason = format!("{error}, payload is dropped after {} attempts", Self::MAX_ATTEMPTS);
+                        eprintln!("Chain error: {}", ChainError::WebhookError(self.hooks[payload.hook].url.clone(), reason));
+                    } else {
+                        payload.retry_at = now + Self::RETRY_DELAY * 2u32.pow(payload.attempts - 1);
+                        remaining.push_back(payload);
+                    }
+                }
+            }
+        }
+
+        remaining.append(&mut self.pending);
+        self.pending = remaining;
+        delivered
+    }
+
+    /// Hex encoded HMAC-SHA256 of the payload.
+    pub fn signature(secret: &str, payload: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts key of any size");
+        mac.update(payload.as_bytes());
+
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::block::NodeOperation;
+    use crate::chain::Chain;
+    use std::collections::BTreeMap;
+    use std::env;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn node(id: &str, name: &str) -> NodeData {
+        NodeData::new(
+            NodeOperation::Add,
+            "User".to_string(),
+            id.to_string(),
+            BTreeMap::from([("name".to_string(), name.to_string())]),
+        )
+    }
+
+    /// Request is complete once its whole body (by content length) is received.
+    fn is_complete(request: &str) -> bool {
+        request.split_once("\r\n\r\n").is_some_and(|(head, body)| {
+            head.lines()
+                .find_map(|line| line.strip_prefix("Content-Length: "))
+                .is_some_and(|length| body.len() >= length.parse().unwrap())
+        })
+    }
+
+    fn webhooks(name: &str, hooks: Value) -> Webhooks {
+        let path = env::temp_dir().join(format!("{name}.json"));
+        fs::write(&path, hooks.to_string()).unwrap();
+
+        let webhooks = Webhooks::open(path.to_str().unwrap()).unwrap();
+        fs::remove_file(path).unwrap();
+        webhooks
+    }
+
+    #[test]
+    fn should_send_signed_payload_of_matching_node_again_after_failure() {
+        // Given
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let responses = ["HTTP/1.1 503 Service Unavailable\r\n\r\n", "HTTP/1.1 204 No Content\r\n\r\n"];
+            responses.map(|response| {
+                let (mut connection, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                let mut buffer = [0; 4096];
+                while !is_complete(&request) {
+                    let length = connection.read(&mut buffer).unwrap();
+                    request.push_str(&String::from_utf8_lossy(&buffer[..length]));
+                }
+                connection.write_all(response.as_bytes()).unwrap();
+                request
+            })
+        });
+
+        let hooks = json!([{
+            "url": format!("http://{address}/users"),
+            "events": ["node_changed"],
+            "node": {"name": "User", "conditions": [{"attribute": "name", "operator": "Equal", "value": "John"}]},
+            "secret": "secret",
+        }]);
+        let mut chain = Chain {
+            webhooks: Some(webhooks("should_send_signed_payload_of_matching_node_again_after_failure", hooks)),
+            ..Chain::default()
+        };
+        chain.add_node_change(node("1", "Jane")).unwrap();
+        chain.add_node_change(node("2", "John")).unwrap();
+
+        // When
+        let job = chain.delivery_job().unwrap();
+        let concurrent = chain.delivery_job();
+        chain.complete_delivery(job.deliver()).unwrap();
+        let postponed = chain.webhooks.as_mut().unwrap().deliver();
+        chain.webhooks.as_mut().unwrap().pending[0].retry_at = Instant::now();
+        let delivered = chain.webhooks.as_mut().unwrap().deliver();
+
+        // Then
+        let requests = server.join().unwrap();
+        let (head, payload) = requests[1].split_once("\r\n\r\n").unwrap();
+        let payload: Value = serde_json::from_str(payload).unwrap();
+        assert!(concurrent.is_none());
+        assert_eq!((postponed, delivered), (0, 1));
+        assert!(head.starts_with("POST /users HTTP/1.1"));
+        assert!(head.contains("X-Webhook-Event: node_changed"));
+        assert!(head.contains(&format!(
+            "X-Webhook-Signature: sha256={}",
+            Webhooks::signature("secret", &payload.to_string())
+        )));
+        assert_eq!(payload["change"]["id"], "2");
+        assert_eq!(requests[0], requests[1]);
+        assert!(chain.webhooks.as_ref().unwrap().pending.is_empty());
+    }
+
+    #[test]
+    fn should_queue_payloads_of_replaced_chain() {
+        // Given
+        let mut chain = Chain::default();
+        chain.add_edge_change("1".to_string(), "2".to_string(), 5).unwrap();
+        chain.webhooks = Some(webhooks(
+            "should_queue_payloads_of_replaced_chain",
+            json!([{"url": "http://127.0.0.1:9/chain", "events": ["chain_replaced", "block_accepted"]}]),
+        ));
+
+        let mut fork = Chain::default();
+        for weight in 1..=3 {
+            fork.add_edge_change("1".to_string(), "3".to_string(), weight).unwrap();
+        }
+
+        // When
+        chain.replace_chain(&fork.blocks).unwrap();
+
+        // Then
+        let payloads: Vec<Value> = chain
+            .webhooks
+            .as_ref()
+            .unwrap()
+            .pending
+            .iter()
+            .map(|delivery| serde_json::from_str(&delivery.payload).unwrap())
+            .collect();
+        assert_eq!(payloads[0]["event"], "chain_replaced");
+        assert_eq!(payloads[0]["divergence"], 1);
+        assert_eq!(payloads[0]["abandoned"], 2);
+        assert_eq!(payloads.len(), fork.blocks.len());
+        assert!(payloads[1..].iter().all(|payload| payload["event"] == "block_accepted"));
+    }
+}
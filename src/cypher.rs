@@ -0,0 +1,344 @@
+extern crate peg;
+
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::condition::{Condition as AttributeCondition, Operator};
+use crate::graph::error::DatabaseError;
+use crate::graph::node::Node;
+use crate::graph::{Graph, GraphResults};
+use peg::error::ParseError;
+use peg::str::LineCol;
+use rustc_hash::FxHashMap;
+use std::slice;
+
+peg::parser! {
+    grammar cypher_parser() for str {
+        pub rule query() -> CypherQuery = _ keyword("MATCH") _ start:node() hops:hop()* _ conditions:conditions()? _ keyword("RETURN") _ returns:variable() ++ (_ "," _) _ {
+            CypherQuery { start, hops, conditions: conditions.unwrap_or_default(), returns }
+        }
+
+        rule hop() -> (Option<String>, NodePattern) = _ relationship:relationship() _ node:node() { (relationship, node) }
+
+        rule node() -> NodePattern = "(" _ variable:variable()? _ label:(":" _ label:name() { label })? _ properties:properties()? _ ")" {
+            NodePattern { variable, label, properties: properties.unwrap_or_default() }
+        }
+
+        rule relationship() -> Option<String> = "-[" _ variable:variable()? _ "]->" { variable }
+
+        rule properties() -> FxHashMap<String, String> = "{" _ properties:property() ** (_ "," _) _ "}" { properties.into_iter().collect() }
+
+        rule property() -> (String, String) = name:name() _ ":" _ value:value() { (Cypher::attribute_name(&name), value) }
+
+        rule conditions() -> Vec<Condition> = keyword("WHERE") _ conditions:condition() ++ (_ keyword("AND") _) { conditions }
+
+        rule condition() -> Condition = variable:variable() "." property:name() _ operator:operator() _ value:value() {
+            Condition { variable, condition: AttributeCondition::new(Cypher::attribute_name(&property), operator, value) }
+        }
+
+        rule operator() -> Operator
+            = "<>" { Operator::NotEqual }
+            / ">=" { Operator::GreaterOrEqual }
+            / "<=" { Operator::LessOrEqual }
+            / "=~" { Operator::Matches }
+            / "=" { Operator::Equal }
+            / ">" { Operator::Greater }
+            / "<" { Operator::Less }
+
+        rule value() -> String
+            = "\"" value:$([^'"']*) "\"" { value.to_string() }
+            / value:$("-"? ['0'..='9']+ ("." ['0'..='9']+)?) { value.to_string() }
+
+        rule keyword(expected: &'static str) = word:$(['a'..='z' | 'A'..='Z']+) {?
+            if word.eq_ignore_ascii_case(expected) { Ok(()) } else { Err(expected) }
+        }
+
+        rule variable() -> String = name()
+
+        rule name() -> String = name:$(['a'..='z' | 'A'..='Z' | '_' | '$'] ['a'..='z' | 'A'..='Z' | '0'..='9' | '_']*) { name.to_string() }
+
+        rule _ = [' ' | '\t' | '\r' | '\n']*
+    }
+}
+
+pub struct NodePattern {
+    variable: Option<String>,
+    label: Option<String>,
+    properties: FxHashMap<String, String>,
+}
+
+pub struct Condition {
+    variable: String,
+    condition: AttributeCondition,
+}
+
+pub struct CypherQuery {
+    start: NodePattern,
+    hops: Vec<(Option<String>, NodePattern)>,
+    conditions: Vec<Condition>,
+    returns: Vec<String>,
+}
+
+enum Variable {
+    Node(usize),
+    Relationship(usize),
+}
+
+/// Matched path - node keys and weights of edges between them.
+#[derive(Clone)]
+struct Path {
+    nodes: Vec<String>,
+    weights: Vec<i8>,
+}
+
+pub struct Cypher;
+
+impl Cypher {
+    /// Check if command should be handled by Cypher front-end.
+    pub fn is_cypher(command: &str) -> bool {
+        command.trim_start().get(..5).is_some_and(|keyword| keyword.eq_ignore_ascii_case("MATCH"))
+    }
+
+    /// Execute Cypher query
+    ///
+    /// Only subset of Cypher is supported, e.g.
+    /// `MATCH (a:Label {id: "..."})-[r]->(b:Label) WHERE r.weight > 50 RETURN a, r, b`. First node must be matched by
+    /// label and id (as graph can only be searched by id), while other nodes can be matched by optional label and
+    /// properties. All conditions in WHERE clause are joined with AND. If only one variable is returned, its attributes
+    /// are returned as is, otherwise attributes are prefixed with variable name. Without access to secrets, secret
+    /// attributes are removed from results and can't be matched or used in conditions.
+    pub fn execute(graph: &Graph, command: &str, secrets: bool) -> Result<GraphResults, ParseError<LineCol>> {
+        cypher_parser::query(command).map(|query| query.execute(graph, secrets))
+    }
+
+    /// Cypher `id` property is mapped to internal identifier attribute.
+    fn attribute_name(name: &str) -> String {
+        match name {
+            "id" => InternalNodeAttribute::ID_ATTRIBUTE.to_string(),
+            _ => name.to_string(),
+        }
+    }
+}
+
+impl NodePattern {
+    fn matches(&self, node: &Node) -> bool {
+        self.label
+            .as_ref()
+            .is_none_or(|label| node.attributes.get(InternalNodeAttribute::NAME_ATTRIBUTE) == Some(label))
+            && self.properties.iter().all(|(name, value)| node.attributes.get(name) == Some(value))
+    }
+}
+
+impl CypherQuery {
+    fn execute(&self, graph: &Graph, secrets: bool) -> GraphResults {
+        let variables = self.variables();
+
+        if !secrets {
+            // Nodes matched without label could be of any node, so secret attribute of any node is refused
+            let secret = |attribute: &String| graph.secret_attributes.values().any(|attributes| attributes.contains(attribute));
+            let properties = std::iter::once(&self.start)
+                .chain(self.hops.iter().map(|(_, node)| node))
+                .flat_map(|node| node.properties.keys());
+            let conditions = self.conditions.iter().map(|condition| &condition.condition.attribute);
+            if let Some(attribute) = properties.chain(conditions).find(|attribute| secret(attribute)) {
+                return Err(DatabaseError::PermissionDenied(format!(
+                    "secret attribute {attribute} can be used only by admin"
+                )));
+            }
+        }
+
+        for variable in self.returns.iter().chain(self.conditions.iter().map(|condition| &condition.variable)) {
+            if !variables.contains_key(variable) {
+                return Err(DatabaseError::InvalidQuery(format!("Variable {variable} is not defined")));
+            }
+        }
+
+        let mut paths = self.start_path(graph)?;
+
+        for (_, pattern) in &self.hops {
+            paths = paths
+                .into_iter()
+                .flat_map(|path| {
+                    graph.nodes[path.nodes.last().unwrap()]
+                        .edges
+                        .iter()
+                        .map(|edge| (format!("{}:{}", edge.to_node_id, edge.to_node), edge.weight))
+                        .filter(|(key, _)| graph.nodes.get(key).is_some_and(|node| pattern.matches(node)))
+                        .map(|(key, weight)| {
+                            let mut path = path.clone();
+                            path.nodes.push(key);
+                            path.weights.push(weight);
+                            path
+                        })
+                        .collect::<Vec<Path>>()
+                })
+                .collect();
+        }
+
+        Ok(paths
+            .iter()
+            .filter(|path| {
+                self.conditions.iter().all(|condition| {
+                    condition
+                        .condition
+                        .is_met(&Self::attributes(graph, path, &variables[&condition.variable]))
+                })
+            })
+            .map(|path| {
+                let mut result = FxHashMap::default();
+
+                for variable in &self.returns {
+                    let mut attributes = Self::attributes(graph, path, &variables[variable]);
+                    if !secrets {
+                        graph.redact(slice::from_mut(&mut attributes));
+                    }
+
+                    for (key, value) in attributes {
+                        let key = if self.returns.len() == 1 { key } else { format!("{variable}.{key}") };
+                        result.insert(key, value);
+                    }
+                }
+
+                result
+            })
+            .collect())
+    }
+
+    fn start_path(&self, graph: &Graph) -> Result<Vec<Path>, DatabaseError> {
+        let label = self
+            .start
+            .label
+            .clone()
+            .ok_or(DatabaseError::InvalidQuery("First node must have label".to_string()))?;
+        let identifier = self
+            .start
+            .properties
+            .get(InternalNodeAttribute::ID_ATTRIBUTE)
+            .ok_or(DatabaseError::InvalidQuery("First node must be matched by id".to_string()))?;
+
+        let key = format!("{identifier}:{label}");
+        let node = graph.nodes.get(&key).ok_or(DatabaseError::NodeNotFound(label, identifier.clone()))?;
+
+        if !self.start.matches(node) {
+            return Ok(vec![]);
+        }
+
+        Ok(vec![Path {
+            nodes: vec![key],
+            weights: vec![],
+        }])
+    }
+
+    fn variables(&self) -> FxHashMap<String, Variable> {
+        let mut variables = FxHashMap::default();
+
+        if let Some(variable) = &self.start.variable {
+            variables.insert(variable.clone(), Variable::Node(0));
+        }
+
+        for (index, (relationship, node)) in self.hops.iter().enumerate() {
+            if let Some(variable) = relationship {
+                variables.insert(variable.clone(), Variable::Relationship(index));
+            }
+            if let Some(variable) = &node.variable {
+                variables.insert(variable.clone(), Variable::Node(index + 1));
+            }
+        }
+
+        variables
+    }
+
+    /// Attributes of the node or relationship bound to the variable in the given path.
+    fn attributes(graph: &Graph, path: &Path, variable: &Variable) -> FxHashMap<String, String> {
+        match variable {
+            Variable::Node(index) => graph.nodes[&path.nodes[*index]].attributes.clone(),
+            Variable::Relationship(index) => {
+                let name = |key: &String| {
+                    graph.nodes[key]
+                        .attributes
+                        .get(InternalNodeAttribute::NAME_ATTRIBUTE)
+                        .cloned()
+                        .unwrap_or_default()
+                };
+
+                let mut attributes = FxHashMap::default();
+                attributes.insert(InternalNodeAttribute::FROM_ATTRIBUTE.to_string(), name(&path.nodes[*index]));
+                attributes.insert(InternalNodeAttribute::TO_ATTRIBUTE.to_string(), name(&path.nodes[*index + 1]));
+                attributes.insert(InternalNodeAttribute::WEIGHT_ATTRIBUTE.to_string(), path.weights[*index].to_string());
+                // Allow referencing weight without $ sign in conditions, e.g. r.weight > 50
+                attributes.insert("weight".to_string(), path.weights[*index].to_string());
+                attributes
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::edge::Edge;
+
+    #[test]
+    fn should_match_connected_nodes() {
+        // Given
+        let graph = create_graph();
+        let query = "MATCH (a:Person {id: \"1\"})-[r]->(b:Playlist) WHERE r.weight > 50 RETURN b";
+
+        // When
+        let result = Cypher::execute(&graph, query, true).unwrap().unwrap();
+
+        // Then
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].get("name").unwrap(), "Party mix");
+    }
+
+    #[test]
+    fn should_return_multiple_variables() {
+        // Given
+        let graph = create_graph();
+        let query = "match (a:Person {id: \"1\"})-[r]->(b {name: \"Chill\"}) return a, r";
+
+        // When
+        let result = Cypher::execute(&graph, query, true).unwrap().unwrap();
+
+        // Then
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].get("a.name").unwrap(), "John");
+        assert_eq!(result[0].get("r.$weight").unwrap(), "20");
+    }
+
+    #[test]
+    fn should_reject_undefined_variable() {
+        // Given
+        let graph = create_graph();
+        let query = "MATCH (a:Person {id: \"1\"}) RETURN b";
+
+        // When
+        let result = Cypher::execute(&graph, query, true).unwrap();
+
+        // Then
+        assert!(result.is_err());
+    }
+
+    fn create_graph() -> Graph {
+        let mut graph = Graph::default();
+
+        insert_node(&mut graph, "1", "Person", "John", vec![("2", 70), ("3", 20)]);
+        insert_node(&mut graph, "2", "Playlist", "Party mix", vec![]);
+        insert_node(&mut graph, "3", "Playlist", "Chill", vec![]);
+
+        graph
+    }
+
+    fn insert_node(graph: &mut Graph, identifier: &str, label: &str, name: &str, edges: Vec<(&str, i8)>) {
+        let mut attributes = FxHashMap::default();
+        attributes.insert(InternalNodeAttribute::ID_ATTRIBUTE.to_string(), identifier.to_string());
+        attributes.insert(InternalNodeAttribute::NAME_ATTRIBUTE.to_string(), label.to_string());
+        attributes.insert("name".to_string(), name.to_string());
+
+        let edges = edges
+            .into_iter()
+            .map(|(to, weight)| Edge::new("Playlist".to_string(), to.to_string(), weight))
+            .collect();
+
+        graph.nodes.insert(format!("{identifier}:{label}"), Node::new(attributes, edges));
+    }
+}
@@ -0,0 +1,564 @@
+use crate::access::Role;
+use crate::audit::AuditLog;
+use crate::chain::Chain;
+use crate::graph::error::DatabaseError;
+use crate::graph::expiry::Expiring;
+use crate::graph::{Graph, GraphResults, GraphRows};
+use crate::import::identifier_attributes;
+use crate::query_processor::{Access, QueryProcessor};
+use crate::stats::{Measurement, QueryStats};
+use peg::error::ParseError;
+use peg::str::LineCol;
+use rustc_hash::FxHashMap;
+use std::io;
+use std::io::Write;
+use std::ops::Range;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+/// Graph and chain shared between query clients. Read-only commands are executed under shared lock, so they can run in
+/// parallel, while other commands have exclusive access. Locks are always taken in the same order: graph, then chain.
+#[derive(Default)]
+pub struct Database {
+    pub graph: RwLock<Graph>,
+    pub chain: RwLock<Chain>,
+    pub stats: QueryStats,
+    pub audit: AuditLog,
+}
+
+impl Database {
+    pub fn new(graph: Graph, chain: Chain) -> Database {
+        Database {
+            graph: RwLock::new(graph),
+            chain: RwLock::new(chain),
+            stats: QueryStats::default(),
+            audit: AuditLog::default(),
+        }
+    }
+
+    /// Execute command with shared access if it is read-only, otherwise with exclusive access.
+    pub fn execute(&self, command: &str) -> Result<GraphResults, ParseError<LineCol>> {
+        self.execute_as(Some(Role::Admin), command)
+    }
+
+    pub fn execute_as(&self, role: Option<Role>, command: &str) -> Result<GraphResults, ParseError<LineCol>> {
+        self.execute_from(AuditLog::LOCAL_SOURCE, role, command)
+    }
+
+    /// Execute command if client with given role is allowed to execute it. Commands of readers are always executed
+    /// with shared access, so they can't change graph or chain. Observer executes only read-only commands, regardless
+    /// of the role of the client. Successful commands with exclusive access are recorded to the audit log with given
+    /// source.
+    pub fn execute_from(&self, source: &str, role: Option<Role>, command: &str) -> Result<GraphResults, ParseError<LineCol>> {
+        if let Err(error) = Role::authorize(role, command) {
+            return Ok(Err(error));
+        }
+        // Audit log is available to admins of observer too
+        if let Some(result) = self.audit.execute(role, command, &self.chain) {
+            return Ok(result);
+        }
+
+        let role = if self.chain.read().unwrap().mode.is_observer() {
+            if Role::required(command) > Role::Reader {
+                return Ok(Err(DatabaseError::PermissionDenied("node is in observer mode".to_string())));
+            }
+            role.min(Some(Role::Reader))
+        } else {
+            role
+        };
+
+        if let Some(result) = self.stats.execute(command) {
+            return Ok(result);
+        }
+
+        {
+            let graph = self.graph.read().unwrap();
+            let chain = self.chain.read().unwrap();
+
+            let (started, visited) = (Instant::now(), graph.nodes.visited());
+            let mut access = Access::shared(&graph, &chain).with_role(role);
+            let result = QueryProcessor::parse_with_access(&mut access, command);
+            if result.is_ok() || role < Some(Role::Writer) {
+                let visited = graph.nodes.visited().saturating_sub(visited);
+                self.stats
+                    .record(command, Measurement::new(started, access.executed_at(), &result, visited));
+                return Self::redact(&graph, role, result);
+            }
+        }
+
+        let mut graph = self.graph.write().unwrap();
+        let mut chain = self.chain.write().unwrap();
+
+        // Command is masked before it's executed, so it isn't executed if it can't be audited
+        let masked = match graph.mask(command) {
+            Ok(masked) => masked,
+            Err(error) => return Ok(Err(error)),
+        };
+
+        // Only the exclusive attempt is measured, as parsing with shared access failed before any execution
+        let (started, visited) = (Instant::now(), graph.nodes.visited());
+        let mut access = Access::exclusive(&mut graph, &mut chain).with_role(role);
+        let result = QueryProcessor::parse_with_access(&mut access, command);
+        let executed_at = access.executed_at();
+
+        let visited = graph.nodes.visited().saturating_sub(visited);
+        self.stats.record(command, Measurement::new(started, executed_at, &result, visited));
+        if let Ok(Ok(results)) = &result {
+            self.audit.record(&mut chain, source, role, &masked, results);
+        }
+        Self::redact(&graph, role, result)
+    }
+
+    /// Execute changes of a transaction, so either all of them are applied, or none of them. Changes are executed under
+    /// savepoint of the graph while changes of the chain are held, and the first failed change rolls back the graph and
+    /// discards held changes. Changes are audited once all of them succeed. Result contains rows of all changes.
+    pub fn execute_transaction(&self, source: &str, role: Option<Role>, commands: &[String]) -> GraphResults {
+        for command in commands {
+            Role::authorize(role, command)?;
+        }
+        if self.chain.read().unwrap().mode.is_observer() {
+            return Err(DatabaseError::PermissionDenied("node is in observer mode".to_string()));
+        }
+
+        let mut graph = self.graph.write().unwrap();
+        let mut chain = self.chain.write().unwrap();
+
+        let masked: Vec<String> = commands.iter().map(|command| graph.mask(command)).collect::<Result<_, _>>()?;
+
+        let savepoint = graph.savepoint();
+        chain.hold();
+        let executed = match Self::execute_all(&mut graph, &mut chain, role, commands) {
+            Ok(executed) => executed,
+            Err(error) => {
+                graph.rollback(savepoint);
+                chain.discard();
+                return Err(error);
+            }
+        };
+        graph.release(savepoint);
+        chain.release().map_err(DatabaseError::ChainError)?;
+
+        let mut rows = vec![];
+        for (command, results) in masked.iter().zip(executed) {
+            self.audit.record(&mut chain, source, role, command, &results);
+            rows.extend(results);
+        }
+        if role < Some(Role::Admin) {
+            graph.redact(&mut rows);
+        }
+        Ok(rows)
+    }
+
+    fn execute_all(
+        graph: &mut Graph,
+        chain: &mut Chain,
+        role: Option<Role>,
+        commands: &[String],
+    ) -> Result<Vec<Vec<FxHashMap<String, String>>>, DatabaseError> {
+        commands
+            .iter()
+            .map(
+                |command| match QueryProcessor::parse_with_access(&mut Access::exclusive(graph, chain).with_role(role), command) {
+                    Ok(result) => result,
+                    Err(error) => Err(DatabaseError::InvalidQuery(format!("command {command} is not valid ({error})"))),
+                },
+            )
+            .collect()
+    }
+
+    /// Execute command the same way as `execute_from`, but return its rows one by one. Blocks of the chain (rows of
+    /// `fetch connection chain` and `fetch blocks`) are read from the chain as they are consumed, so whole chain is
+    /// never collected, and chain lock isn't held while rows are written.
+    pub fn stream_from(self: &Arc<Self>, source: &str, role: Option<Role>, command: &str) -> Result<GraphRows, ParseError<LineCol>> {
+        let heights = {
+            let graph = self.graph.read().unwrap();
+            let chain = self.chain.read().unwrap();
+            QueryProcessor::block_heights(&mut Access::shared(&graph, &chain), command)
+        };
+
+        match heights {
+            Some(Ok((heights, decoded))) if Role::authorize(role, command).is_ok() => Ok(Ok(Box::new(BlockRows {
+                database: self.clone(),
+                heights,
+                decoded,
+            }))),
+            _ => QueryProcessor::rows(self.execute_from(source, role, command)),
+        }
+    }
+
+    /// Secret attributes are returned only to admins.
+    fn redact(graph: &Graph, role: Option<Role>, mut result: Result<GraphResults, ParseError<LineCol>>) -> Result<GraphResults, ParseError<LineCol>> {
+        if let Ok(Ok(results)) = &mut result {
+            if role < Some(Role::Admin) {
+                graph.redact(results);
+            }
+        }
+
+        result
+    }
+
+    /// Delete nodes and connections whose time to live has passed, and purge soft deleted nodes whose retention has
+    /// passed. Deletes of items added by local node are executed as commands, so they are stored in the chain and fire
+    /// triggers as any other delete. Items replicated from peers are deleted only from the local graph, as their
+    /// deletes are stored in the chain by peers which added them. Returns number of deleted items.
+    pub fn expire(&self) -> usize {
+        {
+            let graph = self.graph.read().unwrap();
+            if graph.expiry.is_empty() && graph.tombstones.is_empty() {
+                return 0;
+            }
+        }
+
+        let mut graph = self.graph.write().unwrap();
+        let mut chain = self.chain.write().unwrap();
+
+        let (mut expired, mut deleted) = (vec![], 0);
+        for (item, is_local) in graph.expiry.take_expired() {
+            let result = match item {
+                item if is_local => {
+                    expired.push(item);
+                    continue;
+                }
+                Expiring::Node(name, identifier) => graph.delete_node(name, identifier_attributes(&identifier)),
+                Expiring::Connection((from_name, from_id), (to_name, to_id)) => {
+                    graph.delete_edge((from_name, identifier_attributes(&from_id)), (to_name, identifier_attributes(&to_id)))
+                }
+            };
+            deleted += usize::from(result.is_ok());
+        }
+        if chain.mode.is_observer() {
+            return deleted;
+        }
+
+        let escape = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"");
+        expired.extend(graph.tombstones.take_purged());
+        expired
+            .into_iter()
+            .map(|item| match item {
+                Expiring::Node(name, identifier) => format!("delete node {name}($id=\"{}\")", escape(&identifier)),
+                Expiring::Connection((from_name, from_id), (to_name, to_id)) => format!(
+                    "delete connection from {from_name}($id=\"{}\") to {to_name}($id=\"{}\")",
+                    escape(&from_id),
+                    escape(&to_id)
+                ),
+            })
+            .filter(|command| matches!(QueryProcessor::parse_command(&mut graph, &mut chain, command), Ok(Ok(_))))
+            .count()
+            + deleted
+    }
+
+    pub fn handle_request(&self, line: &str) -> String {
+        QueryProcessor::handle_request(|command| self.execute(command), line)
+    }
+
+    pub fn stream_request(self: &Arc<Self>, line: &str, output: &mut impl Write) -> io::Result<()> {
+        QueryProcessor::stream_request(
+            |command| self.stream_from(AuditLog::LOCAL_SOURCE, Some(Role::Admin), command),
+            line,
+            output,
+        )
+    }
+}
+
+/// Blocks of the chain read one by one, each under its own shared lock.
+struct BlockRows {
+    database: Arc<Database>,
+    heights: Range<usize>,
+    decoded: bool,
+}
+
+impl Iterator for BlockRows {
+    type Item = FxHashMap<String, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let height = self.heights.next()?;
+        let chain = self.database.chain.read().unwrap();
+        let block = chain.blocks.get(height)?;
+
+        Some(if self.decoded {
+            block.as_decoded_hash_map()
+        } else {
+            block.as_hash_map()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::mode::NodeMode;
+    use std::thread;
+
+    #[test]
+    fn should_execute_read_only_commands_in_parallel() {
+        // Given
+        let database = Database::default();
+        database.execute("define node User(name)").unwrap().unwrap();
+        let read_lock = database.graph.read().unwrap();
+
+        // When
+        let result = thread::scope(|scope| scope.spawn(|| database.execute("fetch agents")).join().unwrap());
+        let shared_result =
+            QueryProcessor::parse_with_access(&mut Access::shared(&read_lock, &database.chain.read().unwrap()), "define node Song(name)");
+
+        // Then
+        assert!(result.unwrap().is_ok());
+        assert!(shared_result.is_err());
+        assert!(!read_lock.definitions.contains_key("Song"));
+    }
+
+    #[test]
+    fn should_delete_expired_nodes_and_connections() {
+        // Given
+        let database = Database::default();
+        database.execute("define node User(name)").unwrap().unwrap();
+        database.execute("add node User($id=\"kept\", name=\"John\")").unwrap().unwrap();
+        database
+            .execute("add node User($id=\"session\", name=\"Jane\") with ttl 0")
+            .unwrap()
+            .unwrap();
+        database.execute("add node User($id=\"later\") with ttl 3600").unwrap().unwrap();
+        database
+            .execute("add connection from User($id=\"kept\") to User($id=\"later\") with weight 5 with ttl 0")
+            .unwrap()
+            .unwrap();
+
+        // When
+        let expired = database.expire();
+
+        // Then
+        let graph = database.graph.read().unwrap();
+        assert_eq!(expired, 2);
+        assert!(graph.get_by_id(&"User".to_string(), &"session".to_string()).is_err());
+        assert!(graph.get_by_id(&"User".to_string(), &"later".to_string()).is_ok());
+        assert!(graph.get_by_id(&"User".to_string(), &"kept".to_string()).unwrap().edges.is_empty());
+        assert!(!graph.expiry.is_empty());
+    }
+
+    #[test]
+    fn should_delete_expired_items_replicated_from_peer_only_locally() {
+        // Given
+        let origin = Database::default();
+        origin.execute("define node User(name)").unwrap().unwrap();
+        origin.execute("add node User($id=\"kept\", name=\"John\")").unwrap().unwrap();
+        origin.execute("add node User($id=\"session\") with ttl 0").unwrap().unwrap();
+        origin.execute("add node User($id=\"later\") with ttl 3600").unwrap().unwrap();
+        origin
+            .execute("add connection from User($id=\"kept\") to User($id=\"later\") with weight 5 with ttl 0")
+            .unwrap()
+            .unwrap();
+        let (mut graph, mut chain) = (Graph::default(), Chain::default());
+        chain.blocks = origin.chain.read().unwrap().blocks.clone();
+        chain.replay(&mut graph, 1);
+        let height = chain.blocks.len();
+        let peer = Database::new(graph, chain);
+
+        // When
+        let expired = peer.expire();
+
+        // Then
+        let graph = peer.graph.read().unwrap();
+        assert_eq!(expired, 2);
+        assert!(graph.get_by_id(&"User".to_string(), &"session".to_string()).is_err());
+        assert!(graph.get_by_id(&"User".to_string(), &"later".to_string()).is_ok());
+        assert!(graph.get_by_id(&"User".to_string(), &"kept".to_string()).unwrap().edges.is_empty());
+        assert!(!graph.expiry.is_empty());
+        assert_eq!(peer.chain.read().unwrap().blocks.len(), height);
+    }
+
+    #[test]
+    fn should_purge_soft_deleted_nodes_after_retention() {
+        // Given
+        let mut graph = Graph::default();
+        graph.tombstones.retention = 0;
+        let database = Database::new(graph, Chain::default());
+        database.execute("define node User(name)").unwrap().unwrap();
+        database.execute("add node User($id=\"1\", name=\"John\")").unwrap().unwrap();
+        database.execute("delete node User($id=\"1\") soft").unwrap().unwrap();
+
+        // When
+        let purged = database.expire();
+
+        // Then
+        let graph = database.graph.read().unwrap();
+        assert_eq!(purged, 1);
+        assert!(graph.get_by_id(&"User".to_string(), &"1".to_string()).is_err());
+        assert!(graph.tombstones.is_empty());
+    }
+
+    #[test]
+    fn should_execute_only_read_only_commands_in_observer_mode() {
+        // Given
+        let database = Database::default();
+        database.execute("define node User(name)").unwrap().unwrap();
+        database.chain.write().unwrap().mode = NodeMode::Observer;
+
+        // When
+        let write_result = database.execute("add node User(name=\"John\")").unwrap();
+        let define_result = database.execute("define node Song(name)").unwrap();
+        let read_result = database.execute("fetch agents").unwrap();
+
+        // Then
+        assert!(matches!(write_result, Err(DatabaseError::PermissionDenied(_))));
+        assert!(define_result.is_err());
+        assert!(read_result.is_ok());
+        assert!(!database.graph.read().unwrap().definitions.contains_key("Song"));
+    }
+
+    #[test]
+    fn should_apply_all_changes_of_transaction_or_none() {
+        // Given
+        let database = Database::default();
+        database.execute("define node User(name)").unwrap().unwrap();
+        let length = database.chain.read().unwrap().blocks.len();
+        let commands = |last: &str| vec![r#"add node User($id="1", name="John")"#.to_string(), last.to_string()];
+
+        // When
+        let failed = database.execute_transaction("test", Some(Role::Writer), &commands(r#"add node Song(name="Intro")"#));
+        let failed_length = database.chain.read().unwrap().blocks.len();
+        let committed = database
+            .execute_transaction("test", Some(Role::Writer), &commands(r#"add node User($id="2", name="Jane")"#))
+            .unwrap();
+
+        // Then
+        let graph = database.graph.read().unwrap();
+        let chain = database.chain.read().unwrap();
+        assert_eq!(failed.err().unwrap().code(), "NODE_NOT_DEFINED");
+        assert_eq!(failed_length, length);
+        assert!(!chain.has_queued_changes());
+        assert_eq!(committed.len(), 2);
+        assert!(graph.get_by_id(&"User".to_string(), &"1".to_string()).is_ok());
+        assert!(chain.blocks.len() > length);
+    }
+
+    #[test]
+    fn should_check_triggered_commands_with_role_of_client() {
+        // Given
+        let database = Database::default();
+        database.execute("define node Account(name, email secret)").unwrap().unwrap();
+        database.execute("define node Person(name)").unwrap().unwrap();
+        database
+            .execute("add node Account($id=\"a\", name=\"Main\", email=\"main@example.com\")")
+            .unwrap()
+            .unwrap();
+        database
+            .execute("create trigger on add node Person do delete node Account($id=\"a\", email=\"main@example.com\")")
+            .unwrap()
+            .unwrap();
+
+        // When
+        let writer = database
+            .execute_as(Some(Role::Writer), "add node Person($id=\"1\", name=\"John\")")
+            .unwrap();
+        let admin = database.execute("add node Person($id=\"2\", name=\"Jane\")").unwrap();
+
+        // Then
+        let graph = database.graph.read().unwrap();
+        assert_eq!(writer.err().unwrap().code(), "PERMISSION_DENIED");
+        assert!(graph.get_by_id(&"Person".to_string(), &"1".to_string()).is_err());
+        assert!(admin.is_ok());
+        assert!(graph.get_by_id(&"Account".to_string(), &"a".to_string()).is_err());
+    }
+
+    #[test]
+    fn should_redact_secret_attributes() {
+        // Given
+        let database = Database::default();
+        database.execute("define node User(name, email secret)").unwrap().unwrap();
+        database.execute("define node Team(name)").unwrap().unwrap();
+        database
+            .execute("add node User($id=\"1\", name=\"John\", email=\"john@example.com\")")
+            .unwrap()
+            .unwrap();
+        database.execute("add node Team($id=\"2\", name=\"Core\")").unwrap().unwrap();
+        database
+            .execute("add connection from Team($id=\"2\") to User($id=\"1\") with weight 5")
+            .unwrap()
+            .unwrap();
+        database
+            .execute("update node User($id=\"1\") set (email=\"john@example.org\")")
+            .unwrap()
+            .unwrap();
+
+        // When
+        let admin = database.execute("fetch node User($id=\"1\")").unwrap().unwrap();
+        let reader = database.execute_as(Some(Role::Reader), "fetch node User($id=\"1\")").unwrap().unwrap();
+        let joined = database
+            .execute_as(Some(Role::Reader), "fetch node Team($id=\"2\") join User($weight > \"4\")")
+            .unwrap()
+            .unwrap();
+        let updated = database
+            .execute_as(
+                Some(Role::Writer),
+                "update node User($id=\"1\") set (name=\"Johnny\", email=\"johnny@example.com\")",
+            )
+            .unwrap()
+            .unwrap();
+        let filtered = database.execute_as(Some(Role::Reader), "fetch node User where (email~\"^j\")").unwrap();
+        let selected = database
+            .execute_as(Some(Role::Writer), "delete node User($id=\"1\", email=\"johnny@example.com\")")
+            .unwrap();
+        let matched = database
+            .execute_as(Some(Role::Reader), "MATCH (t:Team {id: \"2\"})-[r]->(u:User) RETURN t, u")
+            .unwrap()
+            .unwrap();
+        let audit = database.execute("fetch audit").unwrap().unwrap();
+
+        // Then
+        let chain = database.chain.read().unwrap();
+        let (mut peer, mut peer_graph) = (Chain::default(), Graph::default());
+        peer.blocks = chain.blocks.clone();
+        peer.replay(&mut peer_graph, 1);
+        assert_eq!(admin[0]["email"], "john@example.org");
+        assert!(!reader[0].contains_key("email"));
+        assert_eq!(reader[0]["name"], "John");
+        assert!(!joined[0].contains_key("User.email"));
+        assert_eq!(joined[0]["User.name"], "John");
+        assert!(!updated[0].contains_key("email") && !updated[0].contains_key("old.email"));
+        assert_eq!(filtered.err().unwrap().code(), "PERMISSION_DENIED");
+        assert_eq!(selected.err().unwrap().code(), "PERMISSION_DENIED");
+        assert!(!matched[0].contains_key("u.email"));
+        assert_eq!(matched[0]["u.name"], "Johnny");
+        assert!(audit.iter().all(|entry| !entry["command"].contains("@example")));
+        assert!(audit.last().unwrap()["command"].contains("email=\"***\""));
+        assert!(audit.last().unwrap()["command"].contains("name=\"Johnny\""));
+        assert!(chain
+            .blocks
+            .iter()
+            .flat_map(|block| block.data.nodes())
+            .all(|node| !node.attributes.contains_key("email")));
+        assert!(chain
+            .blocks
+            .iter()
+            .filter_map(|block| block.data.schema_data.as_ref())
+            .any(|schema| schema.secret_attributes == vec!["email".to_string()]));
+        assert!(peer_graph.secret_attributes["User"].contains("email"));
+        assert!(!peer_graph
+            .get_by_id(&"User".to_string(), &"1".to_string())
+            .unwrap()
+            .attributes
+            .contains_key("email"));
+    }
+
+    #[test]
+    fn should_stream_blocks_without_holding_chain_lock() {
+        // Given
+        let database = Arc::new(Database::default());
+        database.execute("define node User(name)").unwrap().unwrap();
+        let length = database.chain.read().unwrap().blocks.len();
+
+        // When
+        let mut rows = database
+            .stream_from(AuditLog::LOCAL_SOURCE, Some(Role::Reader), "fetch blocks from 0 to 100")
+            .unwrap()
+            .unwrap();
+        let first = rows.next().unwrap();
+        database.execute("define node Song(name)").unwrap().unwrap();
+        let rest: Vec<_> = rows.collect();
+
+        // Then
+        assert_eq!(first["id"], "0");
+        assert_eq!(rest.len(), length - 1);
+        assert!(database.chain.read().unwrap().blocks.len() > length);
+    }
+}
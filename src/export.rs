@@ -0,0 +1,81 @@
+use crate::analytics::Projection;
+use crate::chain::encryption::StorageEncryption;
+use crate::graph::error::DatabaseError;
+use crate::graph::{Graph, GraphResults};
+use rustc_hash::FxHashMap;
+use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+
+mod dot;
+mod graphml;
+mod ndjson;
+
+pub enum ExportFormat {
+    Dot,
+    GraphML,
+}
+
+pub struct Export;
+
+impl Export {
+    /// Export graph to file
+    ///
+    /// Whole graph is written to the file at given path in the given format. Nodes are written with all their
+    /// attributes (including internal ones) and edges with their weight, except soft deleted nodes and their edges.
+    /// Result contains number of exported items.
+    /// With storage encryption, each line of the file is encrypted (the same way as `export_data`).
+    pub fn export_graph(graph: &Graph, format: ExportFormat, path: &str, encryption: Option<&StorageEncryption>) -> GraphResults {
+        let projection = Projection::new(graph);
+
+        let (name, mut content) = match format {
+            ExportFormat::Dot => ("dot", dot::serialize(graph, &projection)),
+            ExportFormat::GraphML => ("graphml", graphml::serialize(graph, &projection)),
+        };
+        if let Some(encryption) = encryption {
+            let (mut file, header) = encryption.create();
+            content = content
+                .lines()
+                .fold(format!("{header}\n"), |content, line| content + &file.encrypt(line) + "\n");
+        }
+
+        fs::write(path, content).map_err(|error| DatabaseError::FileError(path.to_string(), error.to_string()))?;
+
+        let mut result = FxHashMap::default();
+        result.insert("format".to_string(), name.to_string());
+        result.insert("path".to_string(), path.to_string());
+        result.insert("nodes".to_string(), projection.keys.len().to_string());
+        result.insert("edges".to_string(), projection.edges.len().to_string());
+
+        Ok(vec![result])
+    }
+
+    /// Export data to file
+    ///
+    /// Node definitions, nodes and edges are written to the file at given path as JSON Lines (one item per line,
+    /// including internal attributes), so they can be imported to another database with `import data` command. Soft
+    /// deleted nodes and their edges are not exported.
+    /// With storage encryption, each line is encrypted, so file can be imported only with the same key.
+    pub fn export_data(graph: &Graph, path: &str, encryption: Option<&StorageEncryption>) -> GraphResults {
+        let projection = Projection::new(graph);
+        let file = File::create(path).map_err(|error| DatabaseError::FileError(path.to_string(), error.to_string()))?;
+
+        let edges = ndjson::write(graph, &projection, &mut BufWriter::new(file), encryption)
+            .map_err(|error| DatabaseError::FileError(path.to_string(), error.to_string()))?;
+
+        let mut result = FxHashMap::default();
+        result.insert("path".to_string(), path.to_string());
+        result.insert("definitions".to_string(), graph.definitions.len().to_string());
+        result.insert("nodes".to_string(), projection.keys.len().to_string());
+        result.insert("edges".to_string(), edges.to_string());
+
+        Ok(vec![result])
+    }
+
+    /// Node attributes sorted by name, so exported files are deterministic.
+    fn sorted_attributes<'a>(graph: &'a Graph, key: &String) -> Vec<(&'a String, &'a String)> {
+        let mut attributes: Vec<(&String, &String)> = graph.nodes[key].attributes.iter().collect();
+        attributes.sort();
+        attributes
+    }
+}
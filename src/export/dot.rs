@@ -0,0 +1,70 @@
+use crate::analytics::Projection;
+use crate::export::Export;
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::Graph;
+
+/// Serialize graph to Graphviz DOT format. Node attributes are written as DOT attributes (node is labeled by its
+/// name attribute), and edges are labeled with their weight.
+pub fn serialize(graph: &Graph, projection: &Projection) -> String {
+    let mut content = String::from("digraph G {\n");
+
+    for key in &projection.keys {
+        let attributes = Export::sorted_attributes(graph, key);
+        let label = graph.nodes[key].attributes.get(InternalNodeAttribute::NAME_ATTRIBUTE).unwrap_or(key);
+
+        let mut line = format!("    \"{}\" [label=\"{}\"", escape(key), escape(label));
+        for (name, value) in attributes {
+            line.push_str(&format!(", \"{}\"=\"{}\"", escape(name), escape(value)));
+        }
+        line.push_str("];\n");
+
+        content.push_str(&line);
+    }
+
+    for (from, to, weight) in &projection.edges {
+        content.push_str(&format!(
+            "    \"{}\" -> \"{}\" [weight={weight}, label=\"{weight}\"];\n",
+            escape(&projection.keys[*from]),
+            escape(&projection.keys[*to])
+        ));
+    }
+
+    content.push_str("}\n");
+
+    content
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::node::Node;
+    use rustc_hash::FxHashMap;
+
+    #[test]
+    fn should_serialize_graph() {
+        // Given
+        let mut graph = Graph::default();
+        let mut attributes = FxHashMap::default();
+        attributes.insert(InternalNodeAttribute::NAME_ATTRIBUTE.to_string(), "Playlist".to_string());
+        attributes.insert("name".to_string(), "\"Party\" mix".to_string());
+        graph.nodes.insert("1:Playlist".to_string(), Node::new(attributes, vec![]));
+
+        let projection = Projection {
+            keys: vec!["1:Playlist".to_string()],
+            edges: vec![(0, 0, -20.0)],
+        };
+
+        // When
+        let content = serialize(&graph, &projection);
+
+        // Then
+        assert!(content.starts_with("digraph G {\n"));
+        assert!(content.contains("\"1:Playlist\" [label=\"Playlist\", \"$name\"=\"Playlist\", \"name\"=\"\\\"Party\\\" mix\"];"));
+        assert!(content.contains("\"1:Playlist\" -> \"1:Playlist\" [weight=-20, label=\"-20\"];"));
+        assert!(content.ends_with("}\n"));
+    }
+}
@@ -0,0 +1,84 @@
+use crate::analytics::Projection;
+use crate::export::Export;
+use crate::graph::Graph;
+use std::collections::BTreeSet;
+
+/// Serialize graph to GraphML. Each node attribute is declared as GraphML key, and edge weight is exported as
+/// integer `weight` key, so it can be used directly by tools like Gephi.
+pub fn serialize(graph: &Graph, projection: &Projection) -> String {
+    let attribute_names: BTreeSet<&String> = projection.keys.iter().flat_map(|key| graph.nodes[key].attributes.keys()).collect();
+
+    let mut content = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    content.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+
+    for name in &attribute_names {
+        content.push_str(&format!(
+            "  <key id=\"{}\" for=\"node\" attr.name=\"{}\" attr.type=\"string\"/>\n",
+            escape(name),
+            escape(name)
+        ));
+    }
+    content.push_str("  <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"int\"/>\n");
+    content.push_str("  <graph id=\"G\" edgedefault=\"directed\">\n");
+
+    for key in &projection.keys {
+        content.push_str(&format!("    <node id=\"{}\">\n", escape(key)));
+
+        for (name, value) in Export::sorted_attributes(graph, key) {
+            content.push_str(&format!("      <data key=\"{}\">{}</data>\n", escape(name), escape(value)));
+        }
+
+        content.push_str("    </node>\n");
+    }
+
+    for (from, to, weight) in &projection.edges {
+        content.push_str(&format!(
+            "    <edge source=\"{}\" target=\"{}\">\n      <data key=\"weight\">{weight}</data>\n    </edge>\n",
+            escape(&projection.keys[*from]),
+            escape(&projection.keys[*to])
+        ));
+    }
+
+    content.push_str("  </graph>\n</graphml>\n");
+
+    content
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::node::Node;
+    use rustc_hash::FxHashMap;
+
+    #[test]
+    fn should_serialize_graph() {
+        // Given
+        let mut graph = Graph::default();
+        let mut attributes = FxHashMap::default();
+        attributes.insert("name".to_string(), "Rock & Roll".to_string());
+        graph.nodes.insert("1:Playlist".to_string(), Node::new(attributes, vec![]));
+
+        let projection = Projection {
+            keys: vec!["1:Playlist".to_string()],
+            edges: vec![(0, 0, 50.0)],
+        };
+
+        // When
+        let content = serialize(&graph, &projection);
+
+        // Then
+        assert!(content.contains("<key id=\"name\" for=\"node\" attr.name=\"name\" attr.type=\"string\"/>"));
+        assert!(content.contains("<data key=\"name\">Rock &amp; Roll</data>"));
+        assert!(content.contains("<edge source=\"1:Playlist\" target=\"1:Playlist\">"));
+        assert!(content.contains("<data key=\"weight\">50</data>"));
+    }
+}
@@ -0,0 +1,123 @@
+use crate::analytics::Projection;
+use crate::chain::encryption::{FileEncryption, StorageEncryption};
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::Graph;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::io;
+use std::io::Write;
+
+/// Write graph as JSON Lines. Node definitions are written first (secret attributes marked as `secret` instead of `*`,
+/// and with `$self_loops` set to `deny` if they are denied), followed by nodes (with all internal attributes) and
+/// edges, so file can be imported line by line. Only nodes of the projection are written, together with edges
+/// between them. With storage encryption, the file starts with header of its encryption, followed by encrypted lines.
+/// Returns number of written edges.
+pub fn write(graph: &Graph, projection: &Projection, writer: &mut impl Write, encryption: Option<&StorageEncryption>) -> io::Result<usize> {
+    let mut encryption = match encryption {
+        Some(encryption) => {
+            let (file, header) = encryption.create();
+            writeln!(writer, "{header}")?;
+            Some(file)
+        }
+        None => None,
+    };
+    let mut definitions: Vec<(&String, &Vec<String>)> = graph.definitions.iter().collect();
+    definitions.sort();
+
+    for (name, attributes) in definitions {
+        let mut line: FxHashMap<&str, &str> = attributes
+            .iter()
+            .map(|attribute| (attribute.as_str(), if graph.is_secret(name, attribute) { "secret" } else { "*" }))
+            .collect();
+        line.insert(InternalNodeAttribute::DEFINITION_ATTRIBUTE, name);
+        if graph.denied_self_loops.contains(name) {
+            line.insert(InternalNodeAttribute::SELF_LOOPS_ATTRIBUTE, "deny");
+        }
+
+        write_line(writer, &line, &mut encryption)?;
+    }
+
+    for key in &projection.keys {
+        write_line(writer, &graph.nodes[key].attributes, &mut encryption)?;
+    }
+
+    let keys: FxHashSet<&String> = projection.keys.iter().collect();
+    let mut edges = 0;
+    for key in &projection.keys {
+        let node = &graph.nodes[key];
+
+        for edge in node
+            .edges
+            .iter()
+            .filter(|edge| keys.contains(&format!("{}:{}", edge.to_node_id, edge.to_node)))
+        {
+            let mut line = FxHashMap::default();
+            line.insert(
+                InternalNodeAttribute::FROM_ATTRIBUTE,
+                node.attributes[InternalNodeAttribute::NAME_ATTRIBUTE].clone(),
+            );
+            line.insert(
+                InternalNodeAttribute::FROM_ID_ATTRIBUTE,
+                node.attributes[InternalNodeAttribute::ID_ATTRIBUTE].clone(),
+            );
+            line.insert(InternalNodeAttribute::TO_ATTRIBUTE, edge.to_node.clone());
+            line.insert(InternalNodeAttribute::TO_ID_ATTRIBUTE, edge.to_node_id.clone());
+            line.insert(InternalNodeAttribute::WEIGHT_ATTRIBUTE, edge.weight.to_string());
+
+            write_line(writer, &line, &mut encryption)?;
+            edges += 1;
+        }
+    }
+
+    writer.flush()?;
+    Ok(edges)
+}
+
+fn write_line(writer: &mut impl Write, line: &impl serde::Serialize, encryption: &mut Option<FileEncryption>) -> io::Result<()> {
+    match encryption {
+        Some(encryption) => writer.write_all(encryption.encrypt(&serde_json::to_string(line)?).as_bytes())?,
+        None => serde_json::to_writer(&mut *writer, line)?,
+    }
+    writer.write_all(b"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::edge::Edge;
+    use crate::graph::node::Node;
+
+    #[test]
+    fn should_write_graph() {
+        // Given
+        let mut graph = Graph::default();
+        graph.definitions.insert("User".to_string(), vec!["name".to_string()]);
+
+        let mut attributes = FxHashMap::default();
+        attributes.insert(InternalNodeAttribute::ID_ATTRIBUTE.to_string(), "1".to_string());
+        attributes.insert(InternalNodeAttribute::NAME_ATTRIBUTE.to_string(), "User".to_string());
+        graph.nodes.insert(
+            "1:User".to_string(),
+            Node::new(attributes, vec![Edge::new("User".to_string(), "1".to_string(), 20)]),
+        );
+
+        let projection = Projection::new(&graph);
+        let mut output = vec![];
+
+        // When
+        write(&graph, &projection, &mut output, None).unwrap();
+
+        // Then
+        let lines: Vec<FxHashMap<String, String>> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].get(InternalNodeAttribute::DEFINITION_ATTRIBUTE).unwrap(), "User");
+        assert_eq!(lines[0].get("name").unwrap(), "*");
+        assert_eq!(lines[1].get(InternalNodeAttribute::ID_ATTRIBUTE).unwrap(), "1");
+        assert_eq!(lines[2].get(InternalNodeAttribute::TO_ID_ATTRIBUTE).unwrap(), "1");
+        assert_eq!(lines[2].get(InternalNodeAttribute::WEIGHT_ATTRIBUTE).unwrap(), "20");
+    }
+}
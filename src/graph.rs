@@ -1,31 +1,66 @@
 use crate::graph::attribute::InternalNodeAttribute;
+use condition::{Condition, Operator};
 use edge::Edge;
 use error::DatabaseError;
+use expiry::{Expiring, Expiry};
+use generator::IdGenerator;
 use node::Node;
-use rustc_hash::FxHashMap;
+use nodes::Nodes;
+use regex::{Captures, Regex};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::{BTreeMap, VecDeque};
+use std::mem;
+use std::sync::LazyLock;
 use std::vec;
+use tombstone::Tombstones;
+use trigger::{Trigger, TriggerTarget};
+use view::View;
 
 pub mod attribute;
-mod edge;
-pub(crate) mod error;
-mod generator;
+pub mod condition;
+pub(crate) mod edge;
+pub mod error;
+pub mod expiry;
+pub mod generator;
+pub mod index;
 pub(crate) mod node;
+pub(crate) mod nodes;
+pub mod tombstone;
+pub mod trigger;
+pub mod view;
 
+/// Quoted value compared with an attribute in the command (e.g. `email="john@example.com"`), with the attribute and
+/// everything before the value captured, so only the value is masked.
+static ASSIGNMENT: LazyLock<Result<Regex, regex::Error>> =
+    LazyLock::new(|| Regex::new(r#"((?:^|[^a-zA-Z0-9$*])([a-zA-Z0-9*]+)\s*(?:!=|>=|<=|=|>|<|~)\s*)"(?:\\.|[^"\\])*""#));
+
+#[derive(Default, Clone)]
 pub struct Graph {
     pub definitions: FxHashMap<String, Vec<String>>,
-    pub nodes: FxHashMap<String, Node>,
+    pub nodes: Nodes,
+    pub triggers: Vec<Trigger>,
+    pub views: BTreeMap<String, View>,
+    pub ids: IdGenerator,
+    pub expiry: Expiry,
+    pub tombstones: Tombstones,
+    /// Definitions whose nodes can't be connected to themselves
+    pub denied_self_loops: FxHashSet<String>,
+    /// Secret attributes of each definition, which are omitted from results of clients without admin role
+    pub secret_attributes: FxHashMap<String, FxHashSet<String>>,
 }
 
-impl Default for Graph {
-    fn default() -> Self {
-        Graph {
-            definitions: FxHashMap::default(),
-            nodes: FxHashMap::default(),
-        }
-    }
+pub type GraphResults = Result<Vec<FxHashMap<String, String>>, DatabaseError>;
+
+/// Result rows produced one by one, so large results (e.g. whole chain) can be streamed without collecting them.
+pub type GraphRows = Result<Box<dyn Iterator<Item = FxHashMap<String, String>>>, DatabaseError>;
+
+/// Generated identifiers of the graph before changes which can be undone by `Graph::rollback`.
+pub struct Savepoint {
+    ids: IdGenerator,
 }
 
-pub type GraphResults = Result<Vec<FxHashMap<String, String>>, DatabaseError>;
+/// Connection given by name and attributes of from and to node, and its weight.
+pub type Connection = ((String, FxHashMap<String, String>), (String, FxHashMap<String, String>), i8);
 
 impl Graph {
     /// Fetch node
@@ -34,29 +69,230 @@ impl Graph {
     /// If node is found but joins does not meet given query, empty result is returned.
     /// This behaviour is currently ok, as we can only fetch nodes by id. Fetching by attributes
     /// would require adding searchable index tree.
-    pub fn search(&mut self, name: String, attributes: FxHashMap<String, String>, joins: Vec<(String, i8)>) -> GraphResults {
-        let node = self.fetch_node(&name, &attributes)?.clone();
+    pub fn search(&self, name: String, attributes: FxHashMap<String, String>, joins: Vec<(String, i8)>) -> GraphResults {
+        let node = self.get_visible(&name, &InternalNodeAttribute::require_identifier(&attributes)?)?;
+
+        Ok(self.join(node, &joins)?.into_iter().collect())
+    }
+
+    /// Fetch node by its identifier only, when its name is not known. Nodes of different definitions could share the
+    /// identifier (e.g. when they are imported or replicated from peers), so all visible nodes with given identifier are
+    /// returned, sorted by their name.
+    pub fn search_by_id(&self, identifier: &str, joins: Vec<(String, i8)>) -> GraphResults {
+        let mut names = self.nodes.names(identifier).to_vec();
+        names.sort();
+
+        let mut results = Vec::new();
+        for name in names {
+            let node = self.get_by_id(&name, &identifier.to_string())?;
+            if !node.is_deleted() {
+                results.extend(self.join(node, &joins)?);
+            }
+        }
+
+        Ok(results)
+    }
 
-        let mut result = node.attributes;
+    /// Fetch subgraph
+    ///
+    /// Fetch node with given identifier and all nodes reachable from it over at most given number of outgoing edges,
+    /// together with the traversed edges, so neighborhood of the node can be rendered at once. Nodes are returned in
+    /// order they were reached, followed by edges, where each row has $type attribute set to `node` or `edge`. Soft
+    /// deleted nodes (and edges to them) are skipped.
+    pub fn subgraph(&self, name: String, attributes: FxHashMap<String, String>, depth: usize) -> GraphResults {
+        let identifier = InternalNodeAttribute::require_identifier(&attributes)?;
+        self.get_visible(&name, &identifier)?;
+
+        let mut visited = FxHashSet::from_iter([format!("{identifier}:{name}")]);
+        let mut queue = VecDeque::from([(name, identifier, 0)]);
+        let (mut nodes, mut edges) = (Vec::new(), Vec::new());
+
+        while let Some((name, identifier, distance)) = queue.pop_front() {
+            let node = self.get_by_id(&name, &identifier)?;
+            let mut result = node.attributes.clone();
+            result.insert(InternalNodeAttribute::TYPE_ATTRIBUTE.to_string(), "node".to_string());
+            nodes.push(result);
+
+            if distance == depth {
+                continue;
+            }
+
+            for edge in &node.edges {
+                if self.get_visible(&edge.to_node, &edge.to_node_id).is_err() {
+                    continue;
+                }
+
+                let mut result = FxHashMap::default();
+                result.insert(InternalNodeAttribute::TYPE_ATTRIBUTE.to_string(), "edge".to_string());
+                result.insert(InternalNodeAttribute::FROM_ATTRIBUTE.to_string(), name.clone());
+                result.insert(InternalNodeAttribute::FROM_ID_ATTRIBUTE.to_string(), identifier.clone());
+                result.insert(InternalNodeAttribute::TO_ATTRIBUTE.to_string(), edge.to_node.clone());
+                result.insert(InternalNodeAttribute::TO_ID_ATTRIBUTE.to_string(), edge.to_node_id.clone());
+                result.insert(InternalNodeAttribute::WEIGHT_ATTRIBUTE.to_string(), edge.weight.to_string());
+                edges.push(result);
+
+                if visited.insert(format!("{}:{}", edge.to_node_id, edge.to_node)) {
+                    queue.push_back((edge.to_node.clone(), edge.to_node_id.clone(), distance + 1));
+                }
+            }
+        }
+
+        nodes.extend(edges);
+        Ok(nodes)
+    }
+
+    /// Fetch nodes of given definition whose attributes meet all conditions, e.g. `age>"18"` or `name~"^Jan"`, where
+    /// numbers are compared as numbers. Only the shard of the definition is scanned, node is looked up directly if the
+    /// identifier is given, and only nodes in the range are read if range condition is on indexed attribute.
+    pub fn filter(&self, name: String, conditions: Vec<Condition>, joins: Vec<(String, i8)>) -> GraphResults {
+        if !self.definitions.contains_key(&name) {
+            return Err(DatabaseError::NodeNotDefined(name));
+        }
+        if let Some(condition) = conditions.iter().find(|condition| !condition.is_valid()) {
+            return Err(DatabaseError::InvalidQuery(format!("{condition} is not valid regular expression")));
+        }
+
+        let identifier = conditions
+            .iter()
+            .find(|condition| condition.attribute == InternalNodeAttribute::ID_ATTRIBUTE && condition.operator == Operator::Equal);
+        let indexed = conditions.iter().find_map(|condition| self.nodes.indexed(&name, condition));
+        let nodes: Vec<&Node> = match (identifier, indexed) {
+            (Some(condition), _) => self.get_visible(&name, &condition.value).into_iter().collect(),
+            (None, Some(keys)) => keys.into_iter().filter_map(|key| self.nodes.get(key)).collect(),
+            (None, None) => self.nodes.shard(&name).into_iter().flat_map(|shard| shard.values()).collect(),
+        };
+
+        let mut results = Vec::new();
+        for node in nodes
+            .into_iter()
+            .filter(|node| !node.is_deleted() && conditions.iter().all(|condition| condition.is_met(&node.attributes)))
+        {
+            results.extend(self.join(node, &joins)?);
+        }
+        results.sort_by(|first, second| {
+            first
+                .get(InternalNodeAttribute::ID_ATTRIBUTE)
+                .cmp(&second.get(InternalNodeAttribute::ID_ATTRIBUTE))
+        });
+
+        Ok(results)
+    }
+
+    /// Identifier of the node whose key attributes are equal to given attributes, or `None` if there is no such node.
+    /// Key must identify at most one node, so upsert doesn't pick one of the duplicates by chance.
+    pub fn find_by_key(&self, name: &str, attributes: &FxHashMap<String, String>, key: &[&str]) -> Result<Option<String>, DatabaseError> {
+        if !self.definitions.contains_key(name) {
+            return Err(DatabaseError::NodeNotDefined(name.to_string()));
+        }
+        if key.is_empty() {
+            return Err(DatabaseError::InvalidQuery("key must contain at least one attribute".to_string()));
+        }
+
+        let mut conditions = Vec::new();
+        for attribute in key {
+            let value = attributes
+                .get(*attribute)
+                .ok_or(DatabaseError::InvalidQuery(format!("key attribute {attribute} is missing")))?;
+            conditions.push(Condition::new(attribute.to_string(), Operator::Equal, value.clone()));
+        }
+
+        let mut identifiers = self
+            .nodes
+            .shard(name)
+            .into_iter()
+            .flat_map(|shard| shard.values())
+            .filter(|node| !node.is_deleted())
+            .filter(|node| {
+                conditions
+                    .iter()
+                    .all(|condition| node.attributes.get(&condition.attribute) == Some(&condition.value))
+            })
+            .map(|node| InternalNodeAttribute::get_identifier(&node.attributes));
+
+        match (identifiers.next(), identifiers.next()) {
+            (Some(_), Some(_)) => Err(DatabaseError::InvalidQuery(format!("more than one {name} node matches the key"))),
+            (identifier, _) => Ok(identifier),
+        }
+    }
+
+    /// Attributes of the node together with attributes of joined nodes (prefixed by their name), or `None` if the node
+    /// has no edge to joined node with at least given weight, or joined node is soft deleted.
+    fn join(&self, node: &Node, joins: &[(String, i8)]) -> Result<Option<FxHashMap<String, String>>, DatabaseError> {
+        let mut result = node.attributes.clone();
 
         // Collect edges
-        for (join, weight) in &joins {
+        for (join, weight) in joins {
             let edge = node.edges.iter().find(|edge| edge.to_node == *join);
 
             if edge.is_none() || edge.unwrap().weight < *weight {
-                return Ok(vec![]);
+                return Ok(None);
             }
 
             let edge = edge.unwrap();
-            self.find_by_id(&edge.to_node, &edge.to_node_id)?
-                .attributes
-                .iter()
-                .for_each(|(key, value)| {
-                    result.insert(format!("{}.{key}", edge.to_node), value.clone());
-                });
+            let joined = self.get_by_id(&edge.to_node, &edge.to_node_id)?;
+            if joined.is_deleted() {
+                return Ok(None);
+            }
+
+            joined.attributes.iter().for_each(|(key, value)| {
+                result.insert(format!("{}.{key}", edge.to_node), value.clone());
+            });
         }
 
-        Ok(vec![result])
+        Ok(Some(result))
+    }
+
+    /// Remove secret attributes from given results, including secret attributes of joined nodes (e.g. `Address.street`)
+    /// and their previous values (e.g. `old.email`).
+    pub fn redact(&self, results: &mut [FxHashMap<String, String>]) {
+        if self.secret_attributes.is_empty() {
+            return;
+        }
+
+        for result in results {
+            let name = result.get(InternalNodeAttribute::NAME_ATTRIBUTE).cloned();
+            result.retain(|key, _| {
+                let key = key.strip_prefix(InternalNodeAttribute::PREVIOUS_PREFIX).unwrap_or(key);
+                let (name, attribute) = match key.split_once('.') {
+                    Some((name, attribute)) => (Some(name), attribute),
+                    None => (name.as_deref(), key),
+                };
+
+                !name.is_some_and(|name| self.is_secret(name, attribute))
+            });
+        }
+    }
+
+    pub fn is_secret(&self, name: &str, attribute: &str) -> bool {
+        self.secret_attributes
+            .get(name)
+            .is_some_and(|secret_attributes| secret_attributes.contains(attribute))
+    }
+
+    /// Replace quoted values of secret attributes in the command with `***` (e.g. `email="***"`), so the command can be
+    /// stored without them. Node of the attribute isn't known from the command text, so secret attribute of any node is
+    /// masked.
+    pub fn mask(&self, command: &str) -> Result<String, DatabaseError> {
+        if self.secret_attributes.is_empty() {
+            return Ok(command.to_string());
+        }
+
+        let assignment = ASSIGNMENT
+            .as_ref()
+            .map_err(|error| DatabaseError::InvalidQuery(format!("secret attributes of command {command} can't be masked ({error})")))?;
+        let masked = assignment.replace_all(command, |captures: &Captures| {
+            if self
+                .secret_attributes
+                .values()
+                .any(|secret_attributes| secret_attributes.contains(&captures[2]))
+            {
+                format!(r#"{}"***""#, &captures[1])
+            } else {
+                captures[0].to_string()
+            }
+        });
+
+        Ok(masked.into_owned())
     }
 
     /// Create node definition
@@ -67,24 +303,228 @@ impl Graph {
             return Err(DatabaseError::NodeAlreadyExists(name));
         }
 
+        for attribute in attributes.iter().filter(|attribute| attribute.starts_with('*')) {
+            self.nodes.create_index(&name, attribute);
+        }
         self.definitions.insert(name, attributes.clone());
 
         self.return_definition(attributes)
     }
 
+    /// Create trigger
+    ///
+    /// This method will add trigger executed after each change of its target. Target nodes must be defined.
+    pub fn create_trigger(&mut self, trigger: Trigger) -> GraphResults {
+        let names = match &trigger.target {
+            TriggerTarget::Node(name) => vec![name],
+            TriggerTarget::Connection(from, to) => vec![from, to],
+        };
+
+        if let Some(name) = names.into_iter().find(|name| !self.definitions.contains_key(*name)) {
+            return Err(DatabaseError::NodeNotDefined(name.clone()));
+        }
+
+        self.triggers.push(trigger);
+
+        Ok(vec![self.return_trigger(self.triggers.len() - 1)])
+    }
+
+    /// Fetch all triggers, identified by their position.
+    pub fn fetch_triggers(&self) -> GraphResults {
+        Ok((0..self.triggers.len()).map(|index| self.return_trigger(index)).collect())
+    }
+
+    /// Drop trigger
+    ///
+    /// This method will remove trigger with given identifier, so identifiers of triggers created after it are shifted.
+    pub fn drop_trigger(&mut self, identifier: usize) -> GraphResults {
+        if identifier >= self.triggers.len() {
+            return Err(DatabaseError::TriggerNotFound(identifier.to_string()));
+        }
+
+        let result = self.return_trigger(identifier);
+        self.triggers.remove(identifier);
+
+        Ok(vec![result])
+    }
+
+    /// Create view
+    ///
+    /// This method will create materialized view of fetch query over all nodes with given name and attributes. Rows of
+    /// the view are computed once, and updated by each change of the graph which affects them.
+    pub fn create_view(&mut self, view: String, name: String, attributes: FxHashMap<String, String>, joins: Vec<(String, i8)>) -> GraphResults {
+        if self.views.contains_key(&view) {
+            return Err(DatabaseError::ViewAlreadyExists(view));
+        }
+
+        let mut names = std::iter::once(&name).chain(joins.iter().map(|(join, _)| join));
+        if let Some(undefined) = names.find(|name| !self.definitions.contains_key(*name)) {
+            return Err(DatabaseError::NodeNotDefined(undefined.clone()));
+        }
+
+        let materialized = View::new(self, name, attributes, joins);
+        let rows = materialized.rows().count();
+        self.views.insert(view.clone(), materialized);
+
+        let mut result = FxHashMap::default();
+        result.insert("view".to_string(), view);
+        result.insert("rows".to_string(), rows.to_string());
+        Ok(vec![result])
+    }
+
+    /// Fetch rows of the view, without traversing the graph.
+    pub fn fetch_view(&self, view: &str) -> GraphResults {
+        let view = self.views.get(view).ok_or(DatabaseError::ViewNotFound(view.to_string()))?;
+
+        Ok(view.rows().cloned().collect())
+    }
+
+    pub fn drop_view(&mut self, view: &str) -> GraphResults {
+        self.views.remove(view).ok_or(DatabaseError::ViewNotFound(view.to_string()))?;
+
+        let mut result = FxHashMap::default();
+        result.insert("view".to_string(), view.to_string());
+        Ok(vec![result])
+    }
+
+    /// Update range indexes and views after node with given identifier and name (or its outgoing edges) was changed.
+    fn refresh(&mut self, identifier: &str, name: &str) {
+        let key = format!("{identifier}:{name}");
+        self.nodes.reindex(&key);
+        if self.views.is_empty() {
+            return;
+        }
+
+        let mut views = mem::take(&mut self.views);
+        for view in views.values_mut() {
+            view.refresh(self, &key);
+        }
+        self.views = views;
+    }
+
+    /// Start recording changes of nodes (with their edges) and their expiry, so they can be undone by `rollback`. Only
+    /// changed nodes are recorded, so the graph isn't copied. Savepoints can be nested (e.g. triggers checked within a
+    /// transaction), and `rollback` undoes changes since the last one, while `release` keeps them.
+    pub fn savepoint(&mut self) -> Savepoint {
+        self.nodes.record();
+        self.expiry.record();
+        self.tombstones.record();
+        Savepoint { ids: self.ids.clone() }
+    }
+
+    /// Undo changes of nodes since given savepoint and refresh indexes and views of changed nodes.
+    pub fn rollback(&mut self, savepoint: Savepoint) {
+        self.ids = savepoint.ids;
+        self.expiry.rollback();
+        self.tombstones.rollback();
+
+        for key in self.nodes.rollback() {
+            let (identifier, name) = key.rsplit_once(':').unwrap_or(("", &key));
+            self.refresh(identifier, name);
+        }
+    }
+
+    /// Keep changes since given savepoint, they can still be undone by rollback of the outer savepoint.
+    pub fn release(&mut self, _savepoint: Savepoint) {
+        self.nodes.release();
+        self.expiry.release();
+        self.tombstones.release();
+    }
+
     /// Add node to the graph
     ///
     /// This method will add named node with given attributes to the graph database.
-    /// Method will also check if attributes are valid and does not contain any internal attribute.
+    /// Method will also check if attributes are valid and does not contain any internal attribute except $id. If $id
+    /// is given, node with same identifier (of any definition) must not exist, otherwise identifier is generated.
     pub fn add_node(&mut self, name: String, mut attributes: FxHashMap<String, String>) -> GraphResults {
+        let identifier = attributes.remove(InternalNodeAttribute::ID_ATTRIBUTE);
         self.validate_attributes(&name, &attributes, vec![])?;
 
-        let identifier = generator::IdGenerator::generate();
+        let identifier = match identifier {
+            Some(identifier) if identifier.is_empty() => {
+                return Err(DatabaseError::InvalidQuery("identifier must not be empty".to_string()));
+            }
+            Some(identifier) => {
+                self.require_unused_identifier(&identifier)?;
+                identifier
+            }
+            None => self.ids.generate(),
+        };
         attributes.insert(InternalNodeAttribute::ID_ATTRIBUTE.to_string(), identifier.clone());
         attributes.insert(InternalNodeAttribute::NAME_ATTRIBUTE.to_string(), name.clone());
         attributes.insert(InternalNodeAttribute::EDGE_COUNT_ATTRIBUTE.to_string(), "0".to_string());
 
         self.nodes.insert(format!("{identifier}:{name}"), Node::new(attributes.clone(), vec![]));
+        self.refresh(&identifier, &name);
+
+        Ok(vec![attributes])
+    }
+
+    /// Add multiple nodes of the same definition
+    ///
+    /// All nodes are validated and identifiers of nodes without `$id` are generated before any of them is added, so
+    /// either all nodes are added, or none of them is added and the error of the first invalid node is returned.
+    pub fn add_nodes(&mut self, name: String, mut nodes: Vec<FxHashMap<String, String>>) -> GraphResults {
+        let mut identifiers = FxHashSet::default();
+        for attributes in &nodes {
+            let mut attributes = attributes.clone();
+            let identifier = attributes.remove(InternalNodeAttribute::ID_ATTRIBUTE);
+            self.validate_attributes(&name, &attributes, vec![])?;
+
+            match identifier {
+                Some(identifier) if identifier.is_empty() => {
+                    return Err(DatabaseError::InvalidQuery("identifier must not be empty".to_string()));
+                }
+                Some(identifier) if !identifiers.insert(identifier.clone()) => {
+                    return Err(DatabaseError::IdentifierAlreadyExists(name, identifier));
+                }
+                Some(identifier) => self.require_unused_identifier(&identifier)?,
+                None => {}
+            }
+        }
+
+        // Generated identifier must not be given to a later node of the batch, or used by existing node
+        for attributes in nodes
+            .iter_mut()
+            .filter(|attributes| !attributes.contains_key(InternalNodeAttribute::ID_ATTRIBUTE))
+        {
+            let identifier = loop {
+                let identifier = self.ids.generate();
+                if !identifiers.contains(&identifier) && self.find_name(&identifier).is_none() {
+                    break identifier;
+                }
+            };
+            identifiers.insert(identifier.clone());
+            attributes.insert(InternalNodeAttribute::ID_ATTRIBUTE.to_string(), identifier);
+        }
+
+        let mut results = Vec::with_capacity(nodes.len());
+        for attributes in nodes {
+            results.extend(self.add_node(name.clone(), attributes)?);
+        }
+
+        Ok(results)
+    }
+
+    /// Insert node with existing identifier
+    ///
+    /// This method will insert node with identifier given in $id attribute, e.g. when node is imported from another
+    /// database. Internal attributes $name and $edges are recalculated, and node with same identifier (of any
+    /// definition) must not exist.
+    pub fn insert_node(&mut self, name: String, mut attributes: FxHashMap<String, String>) -> GraphResults {
+        attributes.remove(InternalNodeAttribute::NAME_ATTRIBUTE);
+        attributes.remove(InternalNodeAttribute::EDGE_COUNT_ATTRIBUTE);
+
+        self.validate_attributes(&name, &attributes, vec![InternalNodeAttribute::ID_ATTRIBUTE])?;
+
+        let identifier = InternalNodeAttribute::get_identifier(&attributes);
+        self.require_unused_identifier(&identifier)?;
+
+        attributes.insert(InternalNodeAttribute::NAME_ATTRIBUTE.to_string(), name.clone());
+        attributes.insert(InternalNodeAttribute::EDGE_COUNT_ATTRIBUTE.to_string(), "0".to_string());
+
+        self.nodes.insert(format!("{identifier}:{name}"), Node::new(attributes.clone(), vec![]));
+        self.refresh(&identifier, &name);
 
         Ok(vec![attributes])
     }
@@ -100,12 +540,32 @@ impl Graph {
         let node = self.fetch_node(&name, &attributes)?;
 
         // New attributes map already contains $id, so only other internal variables are required to append
-        attributes.insert(InternalNodeAttribute::NAME_ATTRIBUTE.to_string(), name);
+        attributes.insert(InternalNodeAttribute::NAME_ATTRIBUTE.to_string(), name.clone());
         attributes.insert(InternalNodeAttribute::EDGE_COUNT_ATTRIBUTE.to_string(), node.edges.len().to_string());
 
-        node.attributes = attributes.clone();
+        let previous = mem::replace(&mut node.attributes, attributes.clone());
+        self.refresh(&InternalNodeAttribute::get_identifier(&attributes), &name);
 
-        Ok(vec![attributes])
+        Ok(vec![Self::with_previous(attributes, previous)])
+    }
+
+    /// Set given attributes of existing node
+    ///
+    /// This method will change only given attributes, while other attributes of the node are kept. In the list of the
+    /// attributes, internal attribute $id must be present so specific node is found.
+    pub fn merge_node(&mut self, name: String, attributes: FxHashMap<String, String>) -> GraphResults {
+        self.validate_attributes(&name, &attributes, vec![InternalNodeAttribute::ID_ATTRIBUTE])?;
+
+        let node = self.fetch_node(&name, &attributes)?;
+        let mut merged: FxHashMap<String, String> = node
+            .attributes
+            .iter()
+            .filter(|(key, _)| !key.starts_with('$'))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        merged.extend(attributes);
+
+        self.update_node(name, merged)
     }
 
     /// Delete existing node from the graph
@@ -118,12 +578,62 @@ impl Graph {
 
         let identifier = InternalNodeAttribute::get_identifier(&attributes);
 
-        Ok(vec![
-            self.nodes
-                .remove(format!("{identifier}:{name}").as_str())
-                .ok_or(DatabaseError::NodeNotFound(name.clone(), identifier.clone()))?
-                .attributes,
-        ])
+        let node = self
+            .nodes
+            .remove(format!("{identifier}:{name}").as_str())
+            .ok_or(DatabaseError::NodeNotFound(name.clone(), identifier.clone()))?;
+        self.refresh(&identifier, &name);
+        self.expiry.cancel(&Expiring::Node(name.clone(), identifier.clone()));
+        self.tombstones.cancel(name, identifier);
+
+        Ok(vec![node.attributes])
+    }
+
+    /// Soft delete existing node
+    ///
+    /// This method will mark existing node with $deleted attribute (given time of deletion in seconds since Unix epoch,
+    /// e.g. timestamp of the replayed block), so it is not fetched or joined, and it can't be changed, until it is
+    /// restored. In the list of the attributes, internal attribute $id must be present so specific node is deleted.
+    pub fn soft_delete_node(&mut self, name: String, attributes: FxHashMap<String, String>, deleted_at: u64) -> GraphResults {
+        self.validate_attributes(&name, &attributes, vec![InternalNodeAttribute::ID_ATTRIBUTE])?;
+
+        let node = self.fetch_node(&name, &attributes)?;
+        node.attributes
+            .insert(InternalNodeAttribute::DELETED_ATTRIBUTE.to_string(), deleted_at.to_string());
+
+        let result = node.attributes.clone();
+        self.refresh(&InternalNodeAttribute::get_identifier(&attributes), &name);
+
+        Ok(vec![result])
+    }
+
+    /// Restore soft deleted node
+    ///
+    /// This method will remove $deleted attribute of the node, if retention since its deletion has not passed at given
+    /// time of restore (e.g. timestamp of the replayed block). In the list of the attributes, internal attribute $id
+    /// must be present so specific node is restored.
+    pub fn restore_node(&mut self, name: String, attributes: FxHashMap<String, String>, restored_at: u64) -> GraphResults {
+        self.validate_attributes(&name, &attributes, vec![InternalNodeAttribute::ID_ATTRIBUTE])?;
+
+        let identifier = InternalNodeAttribute::get_identifier(&attributes);
+        let deleted_at = self
+            .get_by_id(&name, &identifier)?
+            .attributes
+            .get(InternalNodeAttribute::DELETED_ATTRIBUTE)
+            .ok_or(DatabaseError::InvalidQuery(format!(
+                "node {name} with identifier {identifier} is not deleted"
+            )))?;
+        if !self.tombstones.is_restorable(deleted_at.parse().unwrap_or_default(), restored_at) {
+            return Err(DatabaseError::NodeNotFound(name, identifier));
+        }
+
+        let node = self.find_by_id(&name, &identifier)?;
+        node.attributes.remove(InternalNodeAttribute::DELETED_ATTRIBUTE);
+        let result = node.attributes.clone();
+        self.refresh(&identifier, &name);
+        self.tombstones.cancel(name, identifier);
+
+        Ok(vec![result])
     }
 
     /// Connect two nodes with given weight
@@ -137,6 +647,7 @@ impl Graph {
         weight: i8,
     ) -> GraphResults {
         self.validate_edge((&from_name, &from_atr), (&to_name, &to_atr))?;
+        self.validate_self_loop((&from_name, &from_atr), (&to_name, &to_atr))?;
 
         let node = self.fetch_node(&from_name, &from_atr)?;
         let edge = Edge::new(to_name.clone(), InternalNodeAttribute::get_identifier(&to_atr), weight);
@@ -149,9 +660,39 @@ impl Graph {
         node.attributes
             .insert(InternalNodeAttribute::EDGE_COUNT_ATTRIBUTE.to_string(), node.edges.len().to_string());
 
+        self.refresh(&InternalNodeAttribute::get_identifier(&from_atr), &from_name);
+
         self.return_edge(from_name, to_name, weight)
     }
 
+    /// Connect multiple pairs of nodes
+    ///
+    /// All connections are validated before any of them is added, so either all connections are added, or none of them
+    /// is added and the error of the first invalid connection is returned.
+    pub fn add_edges(&mut self, edges: Vec<Connection>) -> GraphResults {
+        let mut connected = FxHashSet::default();
+        for ((from_name, from_atr), (to_name, to_atr), _) in &edges {
+            self.validate_edge((from_name, from_atr), (to_name, to_atr))?;
+            self.validate_self_loop((from_name, from_atr), (to_name, to_atr))?;
+
+            let (from_id, to_id) = (
+                InternalNodeAttribute::get_identifier(from_atr),
+                InternalNodeAttribute::get_identifier(to_atr),
+            );
+            let node = self.get_by_id(from_name, &from_id)?;
+            if node.edges.iter().any(|edge| edge.to_node_id == to_id) || !connected.insert((from_id, to_id)) {
+                return Err(DatabaseError::EdgeAlreadyExists(from_name.clone(), to_name.clone()));
+            }
+        }
+
+        let mut results = Vec::with_capacity(edges.len());
+        for (from, to, weight) in edges {
+            results.extend(self.add_edge(from, to, weight)?);
+        }
+
+        Ok(results)
+    }
+
     /// Update connection between two nodes
     ///
     /// This method will update weight of edge (connection) between two nodes (from/to name/identifier).
@@ -161,8 +702,33 @@ impl Graph {
         (from_name, from_atr): (String, FxHashMap<String, String>),
         (to_name, to_atr): (String, FxHashMap<String, String>),
         weight: i8,
+    ) -> GraphResults {
+        self.change_edge((from_name, from_atr), (to_name, to_atr), |_| weight)
+    }
+
+    /// Increment weight of connection between two nodes
+    ///
+    /// This method will add given amount (negative to decrement) to weight of existing edge, where resulting weight is
+    /// clamped to the valid range of weights instead of failing. Result contains the resulting weight.
+    pub fn increment_edge(
+        &mut self,
+        (from_name, from_atr): (String, FxHashMap<String, String>),
+        (to_name, to_atr): (String, FxHashMap<String, String>),
+        amount: i64,
+    ) -> GraphResults {
+        self.change_edge((from_name, from_atr), (to_name, to_atr), |weight| {
+            (weight as i64).saturating_add(amount).clamp(i8::MIN as i64, i8::MAX as i64) as i8
+        })
+    }
+
+    fn change_edge(
+        &mut self,
+        (from_name, from_atr): (String, FxHashMap<String, String>),
+        (to_name, to_atr): (String, FxHashMap<String, String>),
+        change: impl FnOnce(i8) -> i8,
     ) -> GraphResults {
         self.validate_edge((&from_name, &from_atr), (&to_name, &to_atr))?;
+        self.validate_self_loop((&from_name, &from_atr), (&to_name, &to_atr))?;
 
         let node = self.fetch_node(&from_name, &from_atr)?;
 
@@ -173,9 +739,15 @@ impl Graph {
             .find(|edge| edge.to_node_id == *to_id)
             .ok_or(DatabaseError::EdgeNotFound(from_name.clone(), to_name.clone()))?;
 
-        edge.weight = weight;
+        let previous = edge.weight;
+        edge.weight = change(previous);
+        let weight = edge.weight;
 
-        self.return_edge(from_name, to_name, weight)
+        self.refresh(&InternalNodeAttribute::get_identifier(&from_atr), &from_name);
+
+        let mut result = self.return_edge(from_name, to_name, weight)?;
+        let previous = FxHashMap::from_iter([(InternalNodeAttribute::WEIGHT_ATTRIBUTE.to_string(), previous.to_string())]);
+        Ok(vec![Self::with_previous(result.remove(0), previous)])
     }
 
     /// Delete connection between two nodes
@@ -205,6 +777,11 @@ impl Graph {
         node.attributes
             .insert(InternalNodeAttribute::EDGE_COUNT_ATTRIBUTE.to_string(), node.edges.len().to_string());
 
+        let from_id = InternalNodeAttribute::get_identifier(&from_atr);
+        self.refresh(&from_id, &from_name);
+        self.expiry
+            .cancel(&Expiring::Connection((from_name.clone(), from_id), (to_name.clone(), to_id)));
+
         self.return_edge(from_name, to_name, weight)
     }
 
@@ -218,6 +795,17 @@ impl Graph {
         Ok(vec![result])
     }
 
+    fn return_trigger(&self, identifier: usize) -> FxHashMap<String, String> {
+        let trigger = &self.triggers[identifier];
+
+        let mut result = FxHashMap::default();
+        result.insert(InternalNodeAttribute::ID_ATTRIBUTE.to_string(), identifier.to_string());
+        result.insert("operation".to_string(), trigger.operation.to_string());
+        result.insert("target".to_string(), trigger.target.to_string());
+        result.insert("command".to_string(), trigger.command.clone());
+        result
+    }
+
     fn return_edge(&mut self, from: String, to: String, weight: i8) -> GraphResults {
         let mut edge_attributes = FxHashMap::default();
 
@@ -228,6 +816,16 @@ impl Graph {
         Ok(vec![edge_attributes])
     }
 
+    /// Attributes after the change together with attributes before the change (prefixed by `old.`), so clients can
+    /// compute the difference without fetching the node first.
+    fn with_previous(mut attributes: FxHashMap<String, String>, previous: FxHashMap<String, String>) -> FxHashMap<String, String> {
+        for (key, value) in previous {
+            attributes.insert(format!("{}{key}", InternalNodeAttribute::PREVIOUS_PREFIX), value);
+        }
+
+        attributes
+    }
+
     /// Check if attributes are same as defined in node definition.
     /// Also, check if only allowed internal attributes are present in attributes map.
     fn validate_attributes(
@@ -238,10 +836,9 @@ impl Graph {
     ) -> Result<(), DatabaseError> {
         let allowed_attributes = self.definitions.get(node_name).ok_or(DatabaseError::NodeNotDefined(node_name.clone()))?;
 
-        for (key, _) in check {
-            if key.starts_with('$') && !internal_attributes.contains(&key.as_str()) {
-                return Err(DatabaseError::AttributeNotAllowed(key.clone()));
-            } else if !key.starts_with('$') && !allowed_attributes.contains(key) {
+        for key in check.keys() {
+            if (key.starts_with('$') && !internal_attributes.contains(&key.as_str())) || (!key.starts_with('$') && !allowed_attributes.contains(key))
+            {
                 return Err(DatabaseError::AttributeNotAllowed(key.clone()));
             }
         }
@@ -267,11 +864,30 @@ impl Graph {
         Ok(())
     }
 
-    /// This method will find node and return mut reference.
+    /// Check if edge doesn't connect node to itself, if its definition denies self loops. Edge attributes must be
+    /// already validated.
+    fn validate_self_loop(
+        &self,
+        (from_name, from_atr): (&String, &FxHashMap<String, String>),
+        (to_name, to_atr): (&String, &FxHashMap<String, String>),
+    ) -> Result<(), DatabaseError> {
+        let identifier = InternalNodeAttribute::get_identifier(from_atr);
+
+        if from_name == to_name && self.denied_self_loops.contains(from_name) && identifier == InternalNodeAttribute::get_identifier(to_atr) {
+            return Err(DatabaseError::SelfLoopNotAllowed(from_name.clone(), identifier));
+        }
+
+        Ok(())
+    }
+
+    /// This method will find node which is not soft deleted and return mut reference.
     fn fetch_node(&mut self, name: &String, attributes: &FxHashMap<String, String>) -> Result<&mut Node, DatabaseError> {
         let identifier = InternalNodeAttribute::get_identifier(attributes);
 
-        self.find_by_id(name, &identifier)
+        match self.find_by_id(name, &identifier)? {
+            node if node.is_deleted() => Err(DatabaseError::NodeNotFound(name.clone(), identifier)),
+            node => Ok(node),
+        }
     }
 
     pub fn find_by_id(&mut self, name: &String, identifier: &String) -> Result<&mut Node, DatabaseError> {
@@ -279,6 +895,34 @@ impl Graph {
             .get_mut(format!("{identifier}:{name}").as_str())
             .ok_or(DatabaseError::NodeNotFound(name.clone(), identifier.clone()))
     }
+
+    /// Check that no node of any definition has given identifier, as blocks of the chain (e.g. edge changes) reference
+    /// nodes only by their identifiers, so peers find the same node when they apply them.
+    fn require_unused_identifier(&self, identifier: &str) -> Result<(), DatabaseError> {
+        match self.find_name(identifier) {
+            Some(name) => Err(DatabaseError::IdentifierAlreadyExists(name, identifier.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Name of the node with given identifier, found in the index of identifiers.
+    pub fn find_name(&self, identifier: &str) -> Option<String> {
+        self.nodes.names(identifier).first().cloned()
+    }
+
+    pub fn get_by_id(&self, name: &String, identifier: &String) -> Result<&Node, DatabaseError> {
+        self.nodes
+            .get(format!("{identifier}:{name}").as_str())
+            .ok_or(DatabaseError::NodeNotFound(name.clone(), identifier.clone()))
+    }
+
+    /// Node which is not soft deleted.
+    pub fn get_visible(&self, name: &String, identifier: &String) -> Result<&Node, DatabaseError> {
+        match self.get_by_id(name, identifier)? {
+            node if node.is_deleted() => Err(DatabaseError::NodeNotFound(name.clone(), identifier.clone())),
+            node => Ok(node),
+        }
+    }
 }
 
 // There are no test cases for this module as it is tested though query processor integration test cases.
@@ -1,16 +1,38 @@
+use crate::graph::error::DatabaseError;
 use rustc_hash::FxHashMap;
 
 pub struct InternalNodeAttribute;
 
 impl InternalNodeAttribute {
+    pub const CENTRALITY_ATTRIBUTE: &'static str = "$centrality";
+    pub const COMMUNITY_ATTRIBUTE: &'static str = "$community";
+    pub const DEFINITION_ATTRIBUTE: &'static str = "$definition";
+    pub const DELETED_ATTRIBUTE: &'static str = "$deleted";
     pub const EDGE_COUNT_ATTRIBUTE: &'static str = "$edges";
     pub const FROM_ATTRIBUTE: &'static str = "$from";
+    pub const FROM_ID_ATTRIBUTE: &'static str = "$from_id";
     pub const ID_ATTRIBUTE: &'static str = "$id";
     pub const NAME_ATTRIBUTE: &'static str = "$name";
+    pub const ORDER_ATTRIBUTE: &'static str = "$order";
+    pub const SELF_LOOPS_ATTRIBUTE: &'static str = "$self_loops";
+    pub const TOTAL_WEIGHT_ATTRIBUTE: &'static str = "$totalWeight";
     pub const TO_ATTRIBUTE: &'static str = "$to";
+    pub const TO_ID_ATTRIBUTE: &'static str = "$to_id";
+    pub const TYPE_ATTRIBUTE: &'static str = "$type";
     pub const WEIGHT_ATTRIBUTE: &'static str = "$weight";
+    /// Prefix of attributes which contain value before the change, e.g. `old.name` in result of update.
+    pub const PREVIOUS_PREFIX: &'static str = "old.";
 
+    /// Identifier of node whose attributes are already validated to contain `$id`.
     pub fn get_identifier(attributes: &FxHashMap<String, String>) -> String {
         attributes.get(Self::ID_ATTRIBUTE).unwrap().clone()
     }
+
+    /// Identifier of node given in query, where `$id` attribute could be missing.
+    pub fn require_identifier(attributes: &FxHashMap<String, String>) -> Result<String, DatabaseError> {
+        attributes
+            .get(Self::ID_ATTRIBUTE)
+            .cloned()
+            .ok_or(DatabaseError::AttributeIsRequired(Self::ID_ATTRIBUTE.to_string()))
+    }
 }
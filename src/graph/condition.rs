@@ -0,0 +1,218 @@
+use regex::Regex;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::sync::OnceLock;
+
+/// Comparison operator used in query and agent conditions.
+///
+/// If both values are numbers, they are compared as numbers, otherwise they are compared as strings.
+/// Matches operator treats expected value as regular expression.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum Operator {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Matches,
+}
+
+/// Expected value of `~` operator. Expression which only anchors literal text to the start (e.g. `^Jan`) is matched as
+/// prefix, without regular expression engine.
+#[derive(Clone, Debug)]
+enum Pattern {
+    Prefix(String),
+    Regex(Regex),
+    Invalid,
+}
+
+/// Condition which attribute of the node must meet, e.g. followers > 100. Expected value of `~` operator is compiled
+/// once, when the condition is created (or first checked, if it was deserialized), so it isn't compiled for each node.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Condition {
+    pub attribute: String,
+    pub operator: Operator,
+    pub value: String,
+    #[serde(skip)]
+    pattern: OnceLock<Pattern>,
+}
+
+impl PartialEq for Condition {
+    fn eq(&self, other: &Self) -> bool {
+        self.attribute == other.attribute && self.operator == other.operator && self.value == other.value
+    }
+}
+
+impl Condition {
+    pub fn new(attribute: String, operator: Operator, value: String) -> Condition {
+        let condition = Condition {
+            attribute,
+            operator,
+            value,
+            pattern: OnceLock::new(),
+        };
+
+        if condition.operator == Operator::Matches {
+            condition.pattern();
+        }
+        condition
+    }
+
+    /// Check if condition is met for given attributes. If attribute is missing, condition is not met.
+    pub fn is_met(&self, attributes: &FxHashMap<String, String>) -> bool {
+        attributes.get(&self.attribute).is_some_and(|actual| match self.operator {
+            Operator::Matches => self.pattern().is_match(actual),
+            _ => self.operator.compare(actual, &self.value),
+        })
+    }
+
+    /// Check if expected value can be compared, i.e. it is valid regular expression for `~` operator.
+    pub fn is_valid(&self) -> bool {
+        self.operator != Operator::Matches || !matches!(self.pattern(), Pattern::Invalid)
+    }
+
+    fn pattern(&self) -> &Pattern {
+        self.pattern.get_or_init(|| Pattern::compile(&self.value))
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(formatter, "{}{}\"{}\"", self.attribute, self.operator, self.value)
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            Operator::Equal => "=",
+            Operator::NotEqual => "!=",
+            Operator::Greater => ">",
+            Operator::GreaterOrEqual => ">=",
+            Operator::Less => "<",
+            Operator::LessOrEqual => "<=",
+            Operator::Matches => "~",
+        };
+
+        write!(formatter, "{symbol}")
+    }
+}
+
+impl Operator {
+    /// Compare given values. Expected value of `~` operator is compiled on each call, so conditions which are checked
+    /// for many nodes should be checked by `Condition`, which compiles it once.
+    pub fn compare(&self, actual: &str, expected: &str) -> bool {
+        if *self == Operator::Matches {
+            return Pattern::compile(expected).is_match(actual);
+        }
+
+        let ordering = match (actual.parse::<f64>(), expected.parse::<f64>()) {
+            (Ok(actual), Ok(expected)) => actual.partial_cmp(&expected),
+            _ => Some(actual.cmp(expected)),
+        };
+
+        match ordering {
+            None => false,
+            Some(ordering) => match self {
+                Operator::Equal => ordering.is_eq(),
+                Operator::NotEqual => ordering.is_ne(),
+                Operator::Greater => ordering.is_gt(),
+                Operator::GreaterOrEqual => ordering.is_ge(),
+                Operator::Less => ordering.is_lt(),
+                Operator::LessOrEqual => ordering.is_le(),
+                Operator::Matches => false,
+            },
+        }
+    }
+}
+
+impl Pattern {
+    fn compile(expression: &str) -> Pattern {
+        let literal = expression.strip_prefix('^').filter(|prefix| regex::escape(prefix) == *prefix);
+        match (literal, Regex::new(expression)) {
+            (Some(prefix), _) => Pattern::Prefix(prefix.to_string()),
+            (None, Ok(regex)) => Pattern::Regex(regex),
+            (None, Err(_)) => Pattern::Invalid,
+        }
+    }
+
+    fn is_match(&self, actual: &str) -> bool {
+        match self {
+            Pattern::Prefix(prefix) => actual.starts_with(prefix.as_str()),
+            Pattern::Regex(regex) => regex.is_match(actual),
+            Pattern::Invalid => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_compare_numbers() {
+        // Given
+        let (small, big) = ("20", "100");
+
+        // When
+        let result = (Operator::Greater.compare(big, small), Operator::LessOrEqual.compare("-5", "-5.0"));
+
+        // Then
+        assert_eq!(result, (true, true));
+    }
+
+    #[test]
+    fn should_match_regex() {
+        // Given
+        let condition = Condition::new("name".to_string(), Operator::Matches, "^Jan".to_string());
+        let mut attributes = FxHashMap::default();
+        attributes.insert("name".to_string(), "Janne".to_string());
+
+        // When
+        let result = (condition.is_met(&attributes), condition.is_met(&FxHashMap::default()));
+
+        // Then
+        assert_eq!(result, (true, false));
+    }
+
+    #[test]
+    fn should_match_prefix_and_compile_pattern_once() {
+        // Given
+        let condition = |expression: &str| Condition::new("name".to_string(), Operator::Matches, expression.to_string());
+        let (prefix, regex, invalid) = (condition("^Jan"), condition("^Ja(n|ck)$"), condition("^Jan("));
+        let attributes = |name: &str| FxHashMap::from_iter([("name".to_string(), name.to_string())]);
+
+        // When
+        let result = (
+            prefix.is_met(&attributes("Janne")),
+            regex.is_met(&attributes("Jack")),
+            regex.is_met(&attributes("Janne")),
+            invalid.is_valid(),
+        );
+
+        // Then
+        assert!(matches!(prefix.pattern.get(), Some(Pattern::Prefix(_))));
+        assert!(matches!(regex.pattern.get(), Some(Pattern::Regex(_))));
+        assert!(matches!(invalid.pattern.get(), Some(Pattern::Invalid)));
+        assert!(condition("Jan").pattern.get().is_some());
+        assert!(Condition::new("name".to_string(), Operator::Equal, "Jan".to_string())
+            .pattern
+            .get()
+            .is_none());
+        assert_eq!(result, (true, true, false, false));
+    }
+
+    #[test]
+    fn should_compare_strings() {
+        // Given
+        let (first, second) = ("Anna", "John");
+
+        // When
+        let result = (Operator::Less.compare(first, second), Operator::Equal.compare(first, second));
+
+        // Then
+        assert_eq!(result, (true, false));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::chain::error::ChainError;
 use serde::{Deserialize, Serialize};
 use std::fmt::{Debug, Display, Formatter};
 
@@ -5,11 +6,36 @@ use std::fmt::{Debug, Display, Formatter};
 pub enum DatabaseError {
     AttributeNotAllowed(String),
     AttributeIsRequired(String),
+    ChainError(ChainError),
+    CycleDetected(String, String),
+    DatabaseAlreadyExists(String),
+    DatabaseNotFound(String),
     EdgeAlreadyExists(String, String),
     EdgeNotFound(String, String),
+    FileError(String, String),
+    IdentifierAlreadyExists(String, String),
+    InvalidAnalyticsConfiguration(String),
+    InvalidAuditLog(String, String),
+    InvalidIdStrategy(String),
+    InvalidQuery(String),
+    InvalidRateLimit(String),
+    InvalidSlowQueryConfiguration(String),
+    InvalidTimeout(String),
+    InvalidWeight(String),
     NodeAlreadyExists(String),
     NodeNotDefined(String),
     NodeNotFound(String, String),
+    PeerError(String),
+    PermissionDenied(String),
+    RateLimitExceeded(String),
+    SelfLoopNotAllowed(String, String),
+    SessionClosed(String),
+    SessionNotFound(String),
+    TransactionAlreadyStarted(String),
+    TransactionNotStarted(String),
+    TriggerNotFound(String),
+    ViewAlreadyExists(String),
+    ViewNotFound(String),
 }
 
 fn error_message(error: &DatabaseError, formatter: &mut Formatter<'_>) -> std::fmt::Result {
@@ -23,12 +49,62 @@ fn error_message(error: &DatabaseError, formatter: &mut Formatter<'_>) -> std::f
         DatabaseError::AttributeIsRequired(name) => {
             write!(formatter, "Attribute {name} is required.")
         }
+        DatabaseError::ChainError(error) => {
+            write!(formatter, "{error}.")
+        }
+        DatabaseError::CycleDetected(name, cycle) => {
+            write!(formatter, "Nodes {name} are not acyclic, found cycle {cycle}.")
+        }
+        DatabaseError::DatabaseAlreadyExists(name) => {
+            write!(formatter, "Database {name} already exists.")
+        }
+        DatabaseError::DatabaseNotFound(name) => {
+            write!(formatter, "Database {name} was not found.")
+        }
         DatabaseError::EdgeAlreadyExists(from, to) => {
             write!(formatter, "Edge from node {from} to node {to} already exists.")
         }
         DatabaseError::EdgeNotFound(from, to) => {
             write!(formatter, "Edge from node {from} to node {to} was not found.")
         }
+        DatabaseError::FileError(path, error) => {
+            write!(formatter, "Error while accessing file {path}: {error}.")
+        }
+        DatabaseError::IdentifierAlreadyExists(name, identifier) => {
+            write!(formatter, "Node with given name {name} and identifier {identifier} already exists.")
+        }
+        DatabaseError::InvalidAnalyticsConfiguration(value) => {
+            write!(formatter, "Analytics configuration {value} is not valid.")
+        }
+        DatabaseError::InvalidAuditLog(path, reason) => {
+            write!(formatter, "Audit log {path} is not valid: {reason}.")
+        }
+        DatabaseError::InvalidIdStrategy(value) => {
+            write!(
+                formatter,
+                "Identifier strategy {value} is not valid. Use random, uuid7 or snowflake:<node id>."
+            )
+        }
+        DatabaseError::InvalidQuery(reason) => {
+            write!(formatter, "Query is not valid: {reason}.")
+        }
+        DatabaseError::InvalidRateLimit(value) => {
+            write!(formatter, "Rate limit {value} is not valid. Use positive number of events per second.")
+        }
+        DatabaseError::InvalidSlowQueryConfiguration(value) => {
+            write!(formatter, "Slow query configuration {value} is not valid.")
+        }
+        DatabaseError::InvalidTimeout(value) => {
+            write!(formatter, "Session idle timeout {value} is not valid. Use number of seconds.")
+        }
+        DatabaseError::InvalidWeight(weight) => {
+            write!(
+                formatter,
+                "Weight {weight} is not valid. Weight must be whole number from {} to {}.",
+                i8::MIN,
+                i8::MAX
+            )
+        }
         DatabaseError::NodeAlreadyExists(name) => {
             write!(formatter, "Node definition for name {name} already exists.")
         }
@@ -41,6 +117,79 @@ fn error_message(error: &DatabaseError, formatter: &mut Formatter<'_>) -> std::f
         DatabaseError::NodeNotFound(name, identifier) => {
             write!(formatter, "Node with given name {name} and identifier {identifier} was not found.")
         }
+        DatabaseError::PeerError(error) => {
+            write!(formatter, "Peer operation failed: {error}.")
+        }
+        DatabaseError::PermissionDenied(reason) => {
+            write!(formatter, "Permission denied: {reason}.")
+        }
+        DatabaseError::RateLimitExceeded(reason) => {
+            write!(formatter, "Rate limit was exceeded: {reason}.")
+        }
+        DatabaseError::SelfLoopNotAllowed(name, identifier) => {
+            write!(formatter, "Node {name} with identifier {identifier} can't be connected to itself.")
+        }
+        DatabaseError::SessionClosed(reason) => {
+            write!(formatter, "Session was closed: {reason}.")
+        }
+        DatabaseError::SessionNotFound(identifier) => {
+            write!(formatter, "Session {identifier} was not found.")
+        }
+        DatabaseError::TransactionAlreadyStarted(session) => {
+            write!(formatter, "Transaction of session {session} was already started.")
+        }
+        DatabaseError::TransactionNotStarted(session) => {
+            write!(formatter, "Transaction of session {session} was not started.")
+        }
+        DatabaseError::TriggerNotFound(identifier) => {
+            write!(formatter, "Trigger {identifier} was not found.")
+        }
+        DatabaseError::ViewAlreadyExists(name) => {
+            write!(formatter, "View {name} already exists.")
+        }
+        DatabaseError::ViewNotFound(name) => {
+            write!(formatter, "View {name} was not found.")
+        }
+    }
+}
+
+impl DatabaseError {
+    /// Stable machine-readable error code, chain errors keep their own code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            DatabaseError::AttributeNotAllowed(..) => "ATTRIBUTE_NOT_ALLOWED",
+            DatabaseError::AttributeIsRequired(..) => "ATTRIBUTE_IS_REQUIRED",
+            DatabaseError::ChainError(error) => error.code(),
+            DatabaseError::CycleDetected(..) => "CYCLE_DETECTED",
+            DatabaseError::DatabaseAlreadyExists(..) => "DATABASE_ALREADY_EXISTS",
+            DatabaseError::DatabaseNotFound(..) => "DATABASE_NOT_FOUND",
+            DatabaseError::EdgeAlreadyExists(..) => "EDGE_ALREADY_EXISTS",
+            DatabaseError::EdgeNotFound(..) => "EDGE_NOT_FOUND",
+            DatabaseError::FileError(..) => "FILE_ERROR",
+            DatabaseError::IdentifierAlreadyExists(..) => "IDENTIFIER_ALREADY_EXISTS",
+            DatabaseError::InvalidAnalyticsConfiguration(..) => "INVALID_ANALYTICS_CONFIGURATION",
+            DatabaseError::InvalidAuditLog(..) => "INVALID_AUDIT_LOG",
+            DatabaseError::InvalidIdStrategy(..) => "INVALID_ID_STRATEGY",
+            DatabaseError::InvalidQuery(..) => "INVALID_QUERY",
+            DatabaseError::InvalidRateLimit(..) => "INVALID_RATE_LIMIT",
+            DatabaseError::InvalidSlowQueryConfiguration(..) => "INVALID_SLOW_QUERY_CONFIGURATION",
+            DatabaseError::InvalidTimeout(..) => "INVALID_TIMEOUT",
+            DatabaseError::InvalidWeight(..) => "INVALID_WEIGHT",
+            DatabaseError::NodeAlreadyExists(..) => "NODE_ALREADY_EXISTS",
+            DatabaseError::NodeNotDefined(..) => "NODE_NOT_DEFINED",
+            DatabaseError::NodeNotFound(..) => "NODE_NOT_FOUND",
+            DatabaseError::PeerError(..) => "PEER_ERROR",
+            DatabaseError::PermissionDenied(..) => "PERMISSION_DENIED",
+            DatabaseError::RateLimitExceeded(..) => "RATE_LIMIT_EXCEEDED",
+            DatabaseError::SelfLoopNotAllowed(..) => "SELF_LOOP_NOT_ALLOWED",
+            DatabaseError::SessionClosed(..) => "SESSION_CLOSED",
+            DatabaseError::SessionNotFound(..) => "SESSION_NOT_FOUND",
+            DatabaseError::TransactionAlreadyStarted(..) => "TRANSACTION_ALREADY_STARTED",
+            DatabaseError::TransactionNotStarted(..) => "TRANSACTION_NOT_STARTED",
+            DatabaseError::TriggerNotFound(..) => "TRIGGER_NOT_FOUND",
+            DatabaseError::ViewAlreadyExists(..) => "VIEW_ALREADY_EXISTS",
+            DatabaseError::ViewNotFound(..) => "VIEW_NOT_FOUND",
+        }
     }
 }
 
@@ -0,0 +1,110 @@
+use rustc_hash::FxHashMap;
+use std::collections::BTreeSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Node or connection which is removed after its time to live.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub enum Expiring {
+    Node(String, String),
+    Connection((String, String), (String, String)),
+}
+
+/// Time (in seconds since Unix epoch) at which each expiring node or connection is removed, and whether it was added by
+/// local node. Expiry is stored in the chain with the added item, so each peer removes it at the same time, while only
+/// the node where the item was added stores its removal in the chain as any other delete.
+#[derive(Clone, Default)]
+pub struct Expiry {
+    queue: BTreeSet<(u64, Expiring)>,
+    expires_at: FxHashMap<Expiring, (u64, bool)>,
+    /// Expiry of items before their first change since the last savepoint (`None` if item wasn't expiring)
+    journal: Vec<FxHashMap<Expiring, Option<(u64, bool)>>>,
+}
+
+impl Expiry {
+    /// Schedule removal of item added by local node after given time to live (in seconds).
+    pub fn schedule(&mut self, item: Expiring, ttl: u64) {
+        self.schedule_at(item, Self::now().saturating_add(ttl), true);
+    }
+
+    /// Schedule removal of item at given time, e.g. of item replayed from the chain.
+    pub fn schedule_at(&mut self, item: Expiring, expires_at: u64, is_local: bool) {
+        self.cancel(&item);
+
+        self.queue.insert((expires_at, item.clone()));
+        self.expires_at.insert(item, (expires_at, is_local));
+    }
+
+    /// Stop tracking item which was deleted before it expired, so item added later with the same identifier is kept.
+    pub fn cancel(&mut self, item: &Expiring) {
+        self.remember(item);
+        if let Some((expires_at, _)) = self.expires_at.remove(item) {
+            self.queue.remove(&(expires_at, item.clone()));
+        }
+    }
+
+    /// Take all items whose time to live has passed, with whether they were added by local node.
+    pub fn take_expired(&mut self) -> Vec<(Expiring, bool)> {
+        let now = Self::now();
+        let mut expired = vec![];
+
+        while let Some((expires_at, _)) = self.queue.first() {
+            if *expires_at > now {
+                break;
+            }
+
+            let (_, item) = self.queue.pop_first().unwrap();
+            self.remember(&item);
+            let is_local = self.expires_at.remove(&item).is_some_and(|(_, is_local)| is_local);
+            expired.push((item, is_local));
+        }
+
+        expired
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Start recording expiry of items before it is changed, so changes can be undone by `rollback`.
+    pub fn record(&mut self) {
+        self.journal.push(FxHashMap::default());
+    }
+
+    /// Restore expiry of items changed since the last recording started.
+    pub fn rollback(&mut self) {
+        for (item, previous) in self.journal.pop().unwrap_or_default() {
+            if let Some((expires_at, _)) = self.expires_at.remove(&item) {
+                self.queue.remove(&(expires_at, item.clone()));
+            }
+
+            if let Some((expires_at, is_local)) = previous {
+                self.queue.insert((expires_at, item.clone()));
+                self.expires_at.insert(item, (expires_at, is_local));
+            }
+        }
+    }
+
+    /// Keep expiry changed since the last recording started, so it's undone only by rollback of the outer recording.
+    pub fn release(&mut self) {
+        let journal = self.journal.pop().unwrap_or_default();
+
+        if let Some(outer) = self.journal.last_mut() {
+            for (item, previous) in journal {
+                outer.entry(item).or_insert(previous);
+            }
+        }
+    }
+
+    fn remember(&mut self, item: &Expiring) {
+        if let Some(journal) = self.journal.last_mut() {
+            if !journal.contains_key(item) {
+                journal.insert(item.clone(), self.expires_at.get(item).copied());
+            }
+        }
+    }
+
+    /// Seconds since Unix epoch.
+    pub fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+    }
+}
@@ -1,4 +1,8 @@
+use crate::graph::error::DatabaseError;
 use nanoid::nanoid;
+use rand::Rng;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 const ALPHABET: [char; 62] = [
     'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', 'A', 'B', 'C',
@@ -6,10 +10,117 @@ const ALPHABET: [char; 62] = [
     '6', '7', '8', '9',
 ];
 
-pub struct IdGenerator;
+/// Strategy of generating identifiers of added nodes, which don't have identifier given by the client.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum IdStrategy {
+    /// Random 16 characters long alphanumeric identifier
+    #[default]
+    Random,
+    /// UUID version 7, ordered by creation time
+    UuidV7,
+    /// 64-bit number composed of milliseconds since 2020-01-01, node id (0 to 1023) and sequence, so nodes of the
+    /// network generate unique identifiers without coordination
+    Snowflake(u16),
+}
+
+#[derive(Clone, Default)]
+pub struct IdGenerator {
+    pub strategy: IdStrategy,
+    // Timestamp and sequence of the last snowflake identifier
+    last: (u64, u64),
+}
 
 impl IdGenerator {
-    pub fn generate() -> String {
-        nanoid!(16, &ALPHABET)
+    const SNOWFLAKE_EPOCH: u64 = 1_577_836_800_000;
+    const MAX_NODE_ID: u16 = 1023;
+    const MAX_SEQUENCE: u64 = 4095;
+
+    pub fn new(strategy: IdStrategy) -> IdGenerator {
+        IdGenerator { strategy, last: (0, 0) }
+    }
+
+    /// Create generator from `--id-strategy` argument, which is `random` (default), `uuid7` or `snowflake:<node id>`.
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<IdGenerator, DatabaseError> {
+        let Some(value) = arguments.get("id-strategy").and_then(|values| values.first()) else {
+            return Ok(IdGenerator::default());
+        };
+
+        let strategy = match value.split_once(':') {
+            None if value == "random" => IdStrategy::Random,
+            None if value == "uuid7" => IdStrategy::UuidV7,
+            Some(("snowflake", node)) => match node.parse() {
+                Ok(node) if node <= Self::MAX_NODE_ID => IdStrategy::Snowflake(node),
+                _ => return Err(DatabaseError::InvalidIdStrategy(value.clone())),
+            },
+            _ => return Err(DatabaseError::InvalidIdStrategy(value.clone())),
+        };
+
+        Ok(IdGenerator::new(strategy))
+    }
+
+    pub fn generate(&mut self) -> String {
+        match self.strategy {
+            IdStrategy::Random => nanoid!(16, &ALPHABET),
+            IdStrategy::UuidV7 => Self::uuid_v7(),
+            IdStrategy::Snowflake(node) => self.snowflake(node).to_string(),
+        }
+    }
+
+    fn uuid_v7() -> String {
+        let mut bytes: [u8; 16] = rand::thread_rng().gen();
+        bytes[..6].copy_from_slice(&Self::now().to_be_bytes()[2..]);
+        bytes[6] = (bytes[6] & 0x0f) | 0x70;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+        let hex = hex::encode(bytes);
+        format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+    }
+
+    /// If sequence of the current millisecond is exhausted, or clock moved backwards, the last timestamp is used, so
+    /// identifiers are never repeated.
+    fn snowflake(&mut self, node: u16) -> u64 {
+        let now = Self::now().saturating_sub(Self::SNOWFLAKE_EPOCH);
+        let (last, sequence) = self.last;
+
+        self.last = if now > last {
+            (now, 0)
+        } else if sequence < Self::MAX_SEQUENCE {
+            (last, sequence + 1)
+        } else {
+            (last + 1, 0)
+        };
+
+        (self.last.0 << 22) | ((node as u64) << 12) | self.last.1
+    }
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_hash::FxHashSet;
+
+    #[test]
+    fn should_generate_identifiers_by_strategy() {
+        // Given
+        let arguments = |value: &str| HashMap::from([("id-strategy".to_string(), vec![value.to_string()])]);
+        let mut uuid = IdGenerator::from_arguments(&arguments("uuid7")).unwrap();
+        let mut snowflake = IdGenerator::from_arguments(&arguments("snowflake:7")).unwrap();
+
+        // When
+        let uuids: Vec<String> = (0..2).map(|_| uuid.generate()).collect();
+        let snowflakes: FxHashSet<String> = (0..10_000).map(|_| snowflake.generate()).collect();
+
+        // Then
+        assert_eq!(uuids[0].len(), 36);
+        assert_eq!(&uuids[0][14..15], "7");
+        assert_ne!(uuids[0], uuids[1]);
+        assert_eq!(snowflakes.len(), 10_000);
+        assert!(snowflakes.iter().all(|id| (id.parse::<u64>().unwrap() >> 12) & 1023 == 7));
+        assert!(IdGenerator::from_arguments(&arguments("snowflake:1024")).is_err());
+        assert!(IdGenerator::from_arguments(&arguments("sequential")).is_err());
     }
 }
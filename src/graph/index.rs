@@ -0,0 +1,150 @@
+use crate::graph::condition::{Condition, Operator};
+use rustc_hash::FxHashMap;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::ops::Bound::{Included, Unbounded};
+
+/// Number ordered by its total order, so it can be kept in B-Tree. Negative zero is stored as zero, as they are equal
+/// when compared by conditions.
+#[derive(Clone, Copy, Debug)]
+struct Number(f64);
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Number {}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Number {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Number {
+    fn parse(value: &str) -> Option<Number> {
+        value
+            .parse::<f64>()
+            .ok()
+            .filter(|number| !number.is_nan())
+            .map(|number| Number(number + 0.0))
+    }
+}
+
+/// Values of one attribute of nodes of one definition (defined with `*`, e.g. `*age`), ordered by value, so range
+/// conditions read only nodes in the range instead of the whole shard. Condition compares numbers as numbers and other
+/// values as text, so numbers are ordered as numbers, other values (words) as text, and all values also as text.
+#[derive(Clone, Default)]
+pub struct RangeIndex {
+    values: FxHashMap<String, String>,
+    numbers: BTreeSet<(Number, String)>,
+    words: BTreeSet<(String, String)>,
+    texts: BTreeSet<(String, String)>,
+}
+
+impl RangeIndex {
+    /// Index value of the node with given key, replacing its previous value (node without value is removed).
+    pub fn insert(&mut self, key: &str, value: Option<&String>) {
+        if let Some(previous) = self.values.remove(key) {
+            match Number::parse(&previous) {
+                Some(number) => self.numbers.remove(&(number, key.to_string())),
+                None => self.words.remove(&(previous.clone(), key.to_string())),
+            };
+            self.texts.remove(&(previous, key.to_string()));
+        }
+
+        if let Some(value) = value {
+            match Number::parse(value) {
+                Some(number) => self.numbers.insert((number, key.to_string())),
+                None => self.words.insert((value.clone(), key.to_string())),
+            };
+            self.texts.insert((value.clone(), key.to_string()));
+            self.values.insert(key.to_string(), value.clone());
+        }
+    }
+
+    /// Keys of nodes which meet given condition, or `None` if the condition isn't a range (`!=` or `~`). Number is
+    /// compared with numbers as number and with words as text, while text is compared with all values as text.
+    pub fn candidates(&self, condition: &Condition) -> Option<BTreeSet<&String>> {
+        if matches!(condition.operator, Operator::NotEqual | Operator::Matches) {
+            return None;
+        }
+
+        let (operator, expected) = (condition.operator.clone(), condition.value.clone());
+        let keys = match Number::parse(&condition.value) {
+            Some(number) => Self::range(&self.numbers, operator.clone(), number)
+                .chain(Self::range(&self.words, operator, expected))
+                .collect(),
+            None => Self::range(&self.texts, operator, expected).collect(),
+        };
+
+        Some(keys)
+    }
+
+    fn range<T: Ord + Clone>(values: &BTreeSet<(T, String)>, operator: Operator, expected: T) -> impl Iterator<Item = &String> {
+        let from = match operator {
+            Operator::Equal | Operator::Greater | Operator::GreaterOrEqual => Included((expected.clone(), String::new())),
+            _ => Unbounded,
+        };
+        let skipped = (operator == Operator::Greater).then(|| expected.clone());
+
+        values
+            .range((from, Unbounded))
+            .skip_while(move |(value, _)| skipped.as_ref() == Some(value))
+            .take_while(move |(value, _)| match operator {
+                Operator::Less => *value < expected,
+                Operator::Equal | Operator::LessOrEqual => *value <= expected,
+                _ => true,
+            })
+            .map(|(_, key)| key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_find_candidates_in_range() {
+        // Given
+        let mut index = RangeIndex::default();
+        for (key, age) in [
+            ("1:User", "9"),
+            ("2:User", "18"),
+            ("3:User", "-0"),
+            ("4:User", "unknown"),
+            ("5:User", "100"),
+        ] {
+            index.insert(key, Some(&age.to_string()));
+        }
+        index.insert("5:User", Some(&"42".to_string()));
+        let candidates = |operator: Operator, value: &str| {
+            let condition = Condition::new("*age".to_string(), operator, value.to_string());
+            index
+                .candidates(&condition)
+                .map(|keys| keys.into_iter().cloned().collect::<Vec<String>>())
+        };
+
+        // When
+        let adults = candidates(Operator::GreaterOrEqual, "18").unwrap();
+        let children = candidates(Operator::Less, "18").unwrap();
+        let zero = candidates(Operator::Equal, "0").unwrap();
+        let older = candidates(Operator::Greater, "42").unwrap();
+        let other = candidates(Operator::NotEqual, "18");
+
+        // Then
+        assert_eq!(adults, vec!["2:User", "4:User", "5:User"]);
+        assert_eq!(children, vec!["1:User", "3:User"]);
+        assert_eq!(zero, vec!["3:User"]);
+        assert_eq!(older, vec!["4:User"]);
+        assert!(other.is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::graph::attribute::InternalNodeAttribute;
 use crate::graph::Edge;
 use derive_more::Constructor;
 use rustc_hash::FxHashMap;
@@ -7,3 +8,10 @@ pub struct Node {
     pub attributes: FxHashMap<String, String>,
     pub edges: Vec<Edge>,
 }
+
+impl Node {
+    /// Soft deleted node is kept until it is purged, but it is not visible to fetches and joins.
+    pub fn is_deleted(&self) -> bool {
+        self.attributes.contains_key(InternalNodeAttribute::DELETED_ATTRIBUTE)
+    }
+}
@@ -0,0 +1,264 @@
+use crate::graph::condition::Condition;
+use crate::graph::index::RangeIndex;
+use crate::graph::node::Node;
+use rustc_hash::FxHashMap;
+use std::collections::BTreeSet;
+use std::ops::Index;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Nodes of the graph, partitioned in one shard per node definition. Nodes are addressed by `identifier:name` key,
+/// where name selects the shard, so analytics can iterate nodes of single definition without touching the others.
+/// Shards aren't locked separately - all of them are behind the lock of the graph, because every write also changes the
+/// chain under its exclusive lock, so writes of different definitions couldn't run in parallel anyway. Nodes read by
+/// queries are counted, so query statistics can show how many nodes each query visited (count is shared by queries
+/// running in parallel). Names of nodes are also indexed by identifier, so node can be found when only its identifier
+/// is known. Attributes defined with `*` (e.g. `*age`) are kept in range index of their shard.
+#[derive(Default)]
+pub struct Nodes {
+    shards: FxHashMap<String, FxHashMap<String, Node>>,
+    names: FxHashMap<String, Vec<String>>,
+    visited: AtomicUsize,
+    indexes: FxHashMap<String, FxHashMap<String, RangeIndex>>,
+    /// Nodes as they were before their first change since the last savepoint (`None` if node didn't exist)
+    journal: Vec<FxHashMap<String, Option<Node>>>,
+}
+
+impl Clone for Nodes {
+    fn clone(&self) -> Self {
+        Nodes {
+            shards: self.shards.clone(),
+            names: self.names.clone(),
+            visited: AtomicUsize::new(self.visited()),
+            indexes: self.indexes.clone(),
+            journal: self.journal.clone(),
+        }
+    }
+}
+
+impl Nodes {
+    /// Get nodes with given definition name.
+    pub fn shard(&self, name: &str) -> Option<&FxHashMap<String, Node>> {
+        let shard = self.shards.get(name);
+        self.visit(shard.map_or(0, |shard| shard.len()));
+        shard
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Node> {
+        self.visit(1);
+        self.shards.get(Self::shard_name(key))?.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Node> {
+        self.visit(1);
+        self.remember(key);
+        self.shards.get_mut(Self::shard_name(key))?.get_mut(key)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.shards.get(Self::shard_name(key)).is_some_and(|shard| shard.contains_key(key))
+    }
+
+    pub fn insert(&mut self, key: String, node: Node) -> Option<Node> {
+        self.remember(&key);
+        let (identifier, name) = Self::split(&key);
+        let (identifier, name) = (identifier.to_string(), name.to_string());
+
+        let previous = self.shards.entry(name.clone()).or_default().insert(key.clone(), node);
+        if previous.is_none() {
+            self.names.entry(identifier).or_default().push(name);
+        }
+        self.reindex(&key);
+
+        previous
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Node> {
+        self.remember(key);
+        let (identifier, name) = Self::split(key);
+        let shard = self.shards.get_mut(name)?;
+        let node = shard.remove(key);
+
+        if shard.is_empty() {
+            self.shards.remove(name);
+        }
+
+        if node.is_some() {
+            if let Some(names) = self.names.get_mut(identifier) {
+                names.retain(|existing| existing != name);
+                if names.is_empty() {
+                    self.names.remove(identifier);
+                }
+            }
+        }
+        self.reindex(key);
+
+        node
+    }
+
+    /// Names of the nodes with given identifier (identifier is unique across definitions, so there is at most one).
+    pub fn names(&self, identifier: &str) -> &[String] {
+        self.names.get(identifier).map_or(&[], |names| names.as_slice())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Node)> {
+        self.shards.values().flatten().inspect(|_| self.visit(1))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Node> {
+        self.iter().map(|(_, node)| node)
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.values().map(|shard| shard.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.is_empty()
+    }
+
+    /// Create range index of given attribute of nodes with given definition name.
+    pub fn create_index(&mut self, name: &str, attribute: &str) {
+        let mut index = RangeIndex::default();
+        for (key, node) in self.shards.get(name).into_iter().flatten() {
+            index.insert(key, node.attributes.get(attribute));
+        }
+
+        self.indexes.entry(name.to_string()).or_default().insert(attribute.to_string(), index);
+    }
+
+    /// Update range indexes after node with given key was changed (nodes changed by `get_mut` must be reindexed).
+    pub fn reindex(&mut self, key: &str) {
+        let name = Self::shard_name(key);
+        let Some(indexes) = self.indexes.get_mut(name) else {
+            return;
+        };
+
+        let node = self.shards.get(name).and_then(|shard| shard.get(key));
+        for (attribute, index) in indexes {
+            index.insert(key, node.and_then(|node| node.attributes.get(attribute)));
+        }
+    }
+
+    /// Keys of nodes with given definition name which may meet given condition, found in range index of its attribute,
+    /// or `None` if the attribute isn't indexed or the condition isn't a range.
+    pub fn indexed(&self, name: &str, condition: &Condition) -> Option<BTreeSet<&String>> {
+        self.indexes.get(name)?.get(&condition.attribute)?.candidates(condition)
+    }
+
+    /// Start recording nodes before they are changed, so changes can be undone by `rollback` without copying all nodes.
+    pub fn record(&mut self) {
+        self.journal.push(FxHashMap::default());
+    }
+
+    /// Restore nodes changed since the last recording started, and return their keys.
+    pub fn rollback(&mut self) -> Vec<String> {
+        let journal = self.journal.pop().unwrap_or_default();
+
+        journal
+            .into_iter()
+            .map(|(key, previous)| {
+                match (
+                    previous,
+                    self.shards.get_mut(Self::shard_name(&key)).and_then(|shard| shard.get_mut(&key)),
+                ) {
+                    (Some(previous), Some(node)) => *node = previous,
+                    (Some(previous), None) => _ = self.insert(key.clone(), previous),
+                    (None, _) => _ = self.remove(&key),
+                }
+                key
+            })
+            .collect()
+    }
+
+    /// Keep nodes changed since the last recording started, so they are undone only by rollback of the outer recording.
+    pub fn release(&mut self) {
+        let journal = self.journal.pop().unwrap_or_default();
+
+        if let Some(outer) = self.journal.last_mut() {
+            for (key, previous) in journal {
+                outer.entry(key).or_insert(previous);
+            }
+        }
+    }
+
+    /// Number of nodes read since the graph was created.
+    pub fn visited(&self) -> usize {
+        self.visited.load(Ordering::Relaxed)
+    }
+
+    fn visit(&self, count: usize) {
+        self.visited.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn remember(&mut self, key: &str) {
+        if let Some(journal) = self.journal.last_mut() {
+            if !journal.contains_key(key) {
+                let node = self.shards.get(Self::shard_name(key)).and_then(|shard| shard.get(key));
+                journal.insert(key.to_string(), node.cloned());
+            }
+        }
+    }
+
+    fn shard_name(key: &str) -> &str {
+        Self::split(key).1
+    }
+
+    fn split(key: &str) -> (&str, &str) {
+        key.rsplit_once(':').unwrap_or(("", key))
+    }
+}
+
+impl Index<&str> for Nodes {
+    type Output = Node;
+
+    fn index(&self, key: &str) -> &Node {
+        self.get(key).expect("Node not found")
+    }
+}
+
+impl Index<&String> for Nodes {
+    type Output = Node;
+
+    fn index(&self, key: &String) -> &Node {
+        &self[key.as_str()]
+    }
+}
+
+impl<'a> IntoIterator for &'a Nodes {
+    type Item = (&'a String, &'a Node);
+    type IntoIter = Box<dyn Iterator<Item = (&'a String, &'a Node)> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_partition_nodes_by_name() {
+        // Given
+        let mut nodes = Nodes::default();
+
+        // When
+        nodes.insert("1:User".to_string(), Node::new(FxHashMap::default(), vec![]));
+        nodes.insert("2:User".to_string(), Node::new(FxHashMap::default(), vec![]));
+        nodes.insert("1:Song".to_string(), Node::new(FxHashMap::default(), vec![]));
+        nodes.remove("1:Song");
+
+        // Then
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes.shard("User").unwrap().len(), 2);
+        assert!(nodes.shard("Song").is_none());
+        assert!(nodes.contains_key("2:User"));
+        assert!(!nodes.contains_key("2:Song"));
+        assert_eq!(nodes.names("1"), ["User"]);
+        assert!(nodes.names("3").is_empty());
+    }
+}
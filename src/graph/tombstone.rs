@@ -0,0 +1,87 @@
+use crate::graph::expiry::{Expiring, Expiry};
+
+/// Soft deleted nodes, which can be restored until retention passes, after which they are purged (deleted from the
+/// graph and the chain as any other node). Unlike expiry, purge is kept only by the node where the node was soft
+/// deleted.
+#[derive(Clone)]
+pub struct Tombstones {
+    /// Seconds for which soft deleted node can be restored
+    pub retention: u64,
+    purge: Expiry,
+}
+
+impl Default for Tombstones {
+    fn default() -> Self {
+        Tombstones::new(Self::DEFAULT_RETENTION)
+    }
+}
+
+impl Tombstones {
+    pub const DEFAULT_RETENTION: u64 = 7 * 24 * 60 * 60;
+
+    /// Create tombstones with retention from genesis configuration, as all peers must restore nodes the same way.
+    pub fn new(retention: u64) -> Tombstones {
+        Tombstones {
+            retention,
+            purge: Expiry::default(),
+        }
+    }
+
+    pub fn schedule(&mut self, name: String, identifier: String) {
+        self.purge.schedule(Expiring::Node(name, identifier), self.retention);
+    }
+
+    pub fn cancel(&mut self, name: String, identifier: String) {
+        self.purge.cancel(&Expiring::Node(name, identifier));
+    }
+
+    /// Check if node soft deleted at given time can still be restored at given time (in seconds since Unix epoch).
+    pub fn is_restorable(&self, deleted_at: u64, restored_at: u64) -> bool {
+        deleted_at.saturating_add(self.retention) >= restored_at
+    }
+
+    /// Start recording purge of soft deleted nodes before it is changed, so changes can be undone by `rollback`.
+    pub fn record(&mut self) {
+        self.purge.record();
+    }
+
+    /// Restore purge of soft deleted nodes changed since recording started.
+    pub fn rollback(&mut self) {
+        self.purge.rollback();
+    }
+
+    /// Keep purge of soft deleted nodes changed since recording started.
+    pub fn release(&mut self) {
+        self.purge.release();
+    }
+
+    /// Take all soft deleted nodes whose retention has passed.
+    pub fn take_purged(&mut self) -> Vec<Expiring> {
+        self.purge.take_expired().into_iter().map(|(item, _)| item).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.purge.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_create_tombstones_with_retention() {
+        // Given
+        let mut tombstones = Tombstones::new(0);
+
+        // When
+        tombstones.schedule("User".to_string(), "1".to_string());
+
+        // Then
+        assert_eq!(Tombstones::default().retention, 604_800);
+        assert!(tombstones.is_restorable(100, 100));
+        assert!(!tombstones.is_restorable(99, 100));
+        assert_eq!(tombstones.take_purged(), vec![Expiring::Node("User".to_string(), "1".to_string())]);
+        assert!(tombstones.is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::Graph;
+use regex::{Captures, Regex};
+use rustc_hash::FxHashMap;
+use std::fmt::{Display, Formatter};
+
+/// Change of node or connection which fires the trigger.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TriggerOperation {
+    Add,
+    Update,
+    Delete,
+}
+
+/// Node (by definition name) or connection (by from and to definition name) whose change fires the trigger.
+#[derive(Clone, PartialEq, Debug)]
+pub enum TriggerTarget {
+    Node(String),
+    Connection(String, String),
+}
+
+/// Command executed after each change of given operation and target, e.g. `update node Playlist(...) set (...)` after
+/// each `add connection Person->Playlist`. Command can contain placeholders, which are replaced by attributes of
+/// changed node (`{node.name}`), changed connection (`{connection.$weight}`) or from and to node of changed connection
+/// (`{to.$id}`), including previous values of updated node or connection (`{node.old.name}`). Number can be added to
+/// numeric attribute (`{to.followers+1}`), where missing attribute counts as 0.
+#[derive(Clone, Debug)]
+pub struct Trigger {
+    pub operation: TriggerOperation,
+    pub target: TriggerTarget,
+    pub command: String,
+}
+
+/// Change of the graph, made by node or connection command.
+pub struct TriggerEvent {
+    operation: TriggerOperation,
+    target: TriggerTarget,
+    // Identifiers of from and to node of changed connection
+    identifiers: Option<(String, String)>,
+}
+
+impl TriggerEvent {
+    pub fn node(operation: TriggerOperation, name: &str) -> TriggerEvent {
+        TriggerEvent {
+            operation,
+            target: TriggerTarget::Node(name.to_string()),
+            identifiers: None,
+        }
+    }
+
+    pub fn connection(
+        operation: TriggerOperation,
+        (from_name, from_attributes): (&str, &FxHashMap<String, String>),
+        (to_name, to_attributes): (&str, &FxHashMap<String, String>),
+    ) -> TriggerEvent {
+        let identifier = |attributes: &FxHashMap<String, String>| attributes.get(InternalNodeAttribute::ID_ATTRIBUTE).cloned().unwrap_or_default();
+
+        TriggerEvent {
+            operation,
+            target: TriggerTarget::Connection(from_name.to_string(), to_name.to_string()),
+            identifiers: Some((identifier(from_attributes), identifier(to_attributes))),
+        }
+    }
+
+    /// Attributes available to placeholders, by their scope. Changed node or connection is given as returned by its
+    /// command, while from and to node are read from the graph after the change.
+    fn scopes(&self, graph: &Graph, changed: &FxHashMap<String, String>) -> FxHashMap<&'static str, FxHashMap<String, String>> {
+        let mut scopes = FxHashMap::default();
+
+        match (&self.target, &self.identifiers) {
+            (TriggerTarget::Connection(from_name, to_name), Some((from_id, to_id))) => {
+                let attributes =
+                    |name: &String, identifier: &String| graph.get_by_id(name, identifier).map(|node| node.attributes.clone()).unwrap_or_default();
+
+                scopes.insert("connection", changed.clone());
+                scopes.insert("from", attributes(from_name, from_id));
+                scopes.insert("to", attributes(to_name, to_id));
+            }
+            _ => {
+                scopes.insert("node", changed.clone());
+            }
+        }
+
+        scopes
+    }
+}
+
+impl Trigger {
+    pub fn fires_on(&self, event: &TriggerEvent) -> bool {
+        self.operation == event.operation && self.target == event.target
+    }
+
+    /// Command of the trigger with placeholders replaced by attributes of given event. Values are escaped, so they
+    /// can be used inside quoted attribute values.
+    pub fn command_for(&self, graph: &Graph, event: &TriggerEvent, changed: &FxHashMap<String, String>) -> String {
+        let scopes = event.scopes(graph, changed);
+        let placeholder = Regex::new(r"\{(node|connection|from|to)\.([a-zA-Z0-9$*.]+)(?:([+-])([0-9]+))?\}").unwrap();
+
+        placeholder
+            .replace_all(&self.command, |captures: &Captures| {
+                let value = scopes.get(&captures[1]).and_then(|attributes| attributes.get(&captures[2]));
+
+                let value = match (captures.get(3), captures.get(4)) {
+                    (Some(sign), Some(amount)) => {
+                        let current: i64 = value.and_then(|value| value.parse().ok()).unwrap_or_default();
+                        let amount: i64 = amount.as_str().parse().unwrap_or_default();
+
+                        if sign.as_str() == "+" {
+                            current.saturating_add(amount)
+                        } else {
+                            current.saturating_sub(amount)
+                        }
+                        .to_string()
+                    }
+                    _ => value.cloned().unwrap_or_default(),
+                };
+
+                value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+            })
+            .to_string()
+    }
+}
+
+impl Display for TriggerOperation {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TriggerOperation::Add => write!(formatter, "add"),
+            TriggerOperation::Update => write!(formatter, "update"),
+            TriggerOperation::Delete => write!(formatter, "delete"),
+        }
+    }
+}
+
+impl Display for TriggerTarget {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TriggerTarget::Node(name) => write!(formatter, "node {name}"),
+            TriggerTarget::Connection(from, to) => write!(formatter, "connection {from}->{to}"),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use crate::graph::node::Node;
+use crate::graph::Graph;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::BTreeMap;
+
+/// Materialized result of fetch query over all nodes of one definition which have given attributes, e.g. all Users
+/// joined with User they are connected to with weight of at least 50. Result row of each node is kept, and only rows
+/// affected by a change are computed again - row of changed node (or node whose edge was changed), and rows of nodes
+/// joined with changed node, as their rows contain its attributes.
+#[derive(Clone)]
+pub struct View {
+    pub node: String,
+    pub attributes: FxHashMap<String, String>,
+    pub joins: Vec<(String, i8)>,
+    // Result row of each node by its identifier, sorted so view is fetched in stable order
+    rows: BTreeMap<String, FxHashMap<String, String>>,
+    // Identifiers of nodes whose rows depend on joined node, by key of joined node, and the other way around
+    dependents: FxHashMap<String, FxHashSet<String>>,
+    joined: FxHashMap<String, Vec<String>>,
+}
+
+impl View {
+    pub fn new(graph: &Graph, node: String, attributes: FxHashMap<String, String>, joins: Vec<(String, i8)>) -> View {
+        let mut view = View {
+            node,
+            attributes,
+            joins,
+            rows: BTreeMap::new(),
+            dependents: FxHashMap::default(),
+            joined: FxHashMap::default(),
+        };
+
+        let keys: Vec<String> = graph
+            .nodes
+            .shard(&view.node)
+            .into_iter()
+            .flat_map(|shard| shard.keys().cloned())
+            .collect();
+        for key in keys {
+            view.refresh(graph, &key);
+        }
+
+        view
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &FxHashMap<String, String>> {
+        self.rows.values()
+    }
+
+    /// Compute again rows affected by change of node with given key (`identifier:name`), or of its outgoing edges.
+    pub fn refresh(&mut self, graph: &Graph, key: &str) {
+        let mut identifiers = self.dependents.get(key).cloned().unwrap_or_default();
+        if let Some((identifier, name)) = key.rsplit_once(':') {
+            if *name == self.node {
+                identifiers.insert(identifier.to_string());
+            }
+        }
+
+        for identifier in identifiers {
+            self.compute(graph, identifier);
+        }
+    }
+
+    fn compute(&mut self, graph: &Graph, identifier: String) {
+        self.rows.remove(&identifier);
+        for key in self.joined.remove(&identifier).unwrap_or_default() {
+            if let Some(dependents) = self.dependents.get_mut(&key) {
+                dependents.remove(&identifier);
+                if dependents.is_empty() {
+                    self.dependents.remove(&key);
+                }
+            }
+        }
+
+        let Ok(node) = graph.get_visible(&self.node, &identifier) else {
+            return;
+        };
+
+        if !self.attributes.iter().all(|(name, value)| node.attributes.get(name) == Some(value)) {
+            return;
+        }
+
+        // Joined nodes are tracked even if the row is not complete, e.g. if joined node is missing
+        let keys = self.joined_keys(node);
+        for key in &keys {
+            self.dependents.entry(key.clone()).or_default().insert(identifier.clone());
+        }
+        self.joined.insert(identifier.clone(), keys);
+
+        if let Ok(Some(row)) = graph.join(node, &self.joins) {
+            self.rows.insert(identifier, row);
+        }
+    }
+
+    fn joined_keys(&self, node: &Node) -> Vec<String> {
+        self.joins
+            .iter()
+            .filter_map(|(join, _)| node.edges.iter().find(|edge| edge.to_node == *join))
+            .map(|edge| format!("{}:{}", edge.to_node_id, edge.to_node))
+            .collect()
+    }
+}
@@ -0,0 +1,100 @@
+use crate::chain::block::{NodeData, NodeOperation};
+use crate::chain::Chain;
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::error::DatabaseError;
+use crate::graph::GraphResults;
+use rustc_hash::FxHashMap;
+
+pub mod csv;
+pub mod ndjson;
+
+/// Summary of the import - number of imported and failed rows, together with error for each failed row.
+pub struct ImportReport {
+    path: String,
+    imported: usize,
+    errors: Vec<(usize, String)>,
+}
+
+impl ImportReport {
+    /// Number of rows after which progress is reported and chain changes are emitted.
+    pub const BATCH_SIZE: usize = 1000;
+
+    pub fn new(path: &str) -> ImportReport {
+        ImportReport {
+            path: path.to_string(),
+            imported: 0,
+            errors: vec![],
+        }
+    }
+
+    pub fn imported(&mut self) {
+        self.imported += 1;
+    }
+
+    pub fn failed(&mut self, line: usize, error: String) {
+        self.errors.push((line, error));
+    }
+
+    pub fn report_progress(&self) {
+        eprintln!(
+            "IMPORT :: {} :: {} rows imported, {} rows failed",
+            self.path,
+            self.imported,
+            self.errors.len()
+        );
+    }
+
+    /// First result row contains summary of the import, and each next row contains line number and error of failed row.
+    pub fn as_graph_result(&self) -> GraphResults {
+        let mut summary = FxHashMap::default();
+        summary.insert("path".to_string(), self.path.clone());
+        summary.insert("imported".to_string(), self.imported.to_string());
+        summary.insert("failed".to_string(), self.errors.len().to_string());
+
+        let mut result = vec![summary];
+        for (line, error) in &self.errors {
+            let mut row = FxHashMap::default();
+            row.insert("line".to_string(), line.to_string());
+            row.insert("error".to_string(), error.clone());
+
+            result.push(row);
+        }
+
+        Ok(result)
+    }
+}
+
+/// Wrap node identifier into attributes map, as expected by graph edge methods.
+pub fn identifier_attributes(identifier: &str) -> FxHashMap<String, String> {
+    let mut attributes = FxHashMap::default();
+    attributes.insert(InternalNodeAttribute::ID_ATTRIBUTE.to_string(), identifier.to_string());
+    attributes
+}
+
+pub fn file_error(path: &str, error: impl ToString) -> DatabaseError {
+    DatabaseError::FileError(path.to_string(), error.to_string())
+}
+
+/// Edge change waiting to be stored in the chain - from identifier, to identifier and weight.
+pub type EdgeChange = (String, String, i8);
+
+/// Store all imported edge changes in the chain, agents whose edges were changed are re-evaluated once they are
+/// applied.
+pub fn add_edge_changes(chain: &mut Chain, changes: &mut Vec<EdgeChange>) {
+    for (from_id, to_id, weight) in changes.drain(..) {
+        if let Err(error) = chain.add_edge_change(from_id, to_id, weight) {
+            eprintln!("Chain error: {error}");
+        }
+    }
+}
+
+/// Imported nodes are not stored in the chain, except nodes of agent definitions, as agents are derived from the chain.
+pub fn add_agent_node_change(chain: &mut Chain, name: &str, attributes: &FxHashMap<String, String>) {
+    if !chain.agent_service.agents.contains_key(name) {
+        return;
+    }
+
+    if let Err(error) = chain.add_node_change(NodeData::from_attributes(NodeOperation::Add, name.to_string(), attributes)) {
+        eprintln!("Chain error: {error}");
+    }
+}
@@ -0,0 +1,189 @@
+use crate::chain::Chain;
+use crate::graph::error::DatabaseError;
+use crate::graph::{Graph, GraphResults};
+use crate::import::{add_agent_node_change, add_edge_changes, file_error, identifier_attributes, ImportReport};
+use csv::{Reader, StringRecord};
+use rustc_hash::FxHashMap;
+use std::fs::File;
+
+pub struct CsvImport;
+
+impl CsvImport {
+    /// Columns required in the CSV file with edges.
+    const EDGE_COLUMNS: [&'static str; 5] = ["from", "from_id", "to", "to_id", "weight"];
+
+    /// Import nodes from CSV file
+    ///
+    /// First row of the file must contain attribute names, which are validated against node definition before
+    /// any row is imported. Each next row is inserted as a new node (and registered as agent if it qualifies).
+    /// Rows which can't be parsed or inserted are skipped and reported in the result.
+    pub fn import_nodes(graph: &mut Graph, chain: &mut Chain, name: &str, path: &str) -> GraphResults {
+        let (mut reader, headers) = Self::open(path)?;
+
+        let definition = graph.definitions.get(name).ok_or(DatabaseError::NodeNotDefined(name.to_string()))?;
+        if let Some(header) = headers.iter().find(|header| !definition.iter().any(|attribute| attribute == header)) {
+            return Err(DatabaseError::AttributeNotAllowed(header.to_string()));
+        }
+
+        let mut report = ImportReport::new(path);
+
+        for (index, record) in reader.records().enumerate() {
+            // Header is in the first line
+            let line = index + 2;
+
+            let inserted = record.map_err(|error| error.to_string()).and_then(|record| {
+                let attributes: FxHashMap<String, String> = headers.iter().map(String::from).zip(record.iter().map(String::from)).collect();
+
+                graph.add_node(name.to_string(), attributes).map_err(|error| error.to_string())
+            });
+
+            match inserted {
+                Ok(result) => {
+                    add_agent_node_change(chain, name, result.first().unwrap());
+
+                    report.imported();
+                }
+                Err(error) => report.failed(line, error),
+            }
+
+            if line % ImportReport::BATCH_SIZE == 0 {
+                report.report_progress();
+            }
+        }
+
+        report.report_progress();
+        report.as_graph_result()
+    }
+
+    /// Import edges from CSV file
+    ///
+    /// File must contain columns from, from_id, to, to_id and weight, where from/to are node names and from_id/to_id
+    /// node identifiers. Edges are inserted in batches, and after each batch chain changes are created for inserted
+    /// edges. Rows which can't be parsed or inserted are skipped and reported in the result.
+    pub fn import_edges(graph: &mut Graph, chain: &mut Chain, path: &str) -> GraphResults {
+        let (mut reader, headers) = Self::open(path)?;
+
+        let mut positions = vec![];
+        for column in Self::EDGE_COLUMNS {
+            positions.push(
+                headers
+                    .iter()
+                    .position(|header| header == column)
+                    .ok_or(DatabaseError::AttributeIsRequired(column.to_string()))?,
+            );
+        }
+
+        let mut report = ImportReport::new(path);
+        let mut changes = vec![];
+
+        for (index, record) in reader.records().enumerate() {
+            // Header is in the first line
+            let line = index + 2;
+
+            let inserted = record.map_err(|error| error.to_string()).and_then(|record| {
+                let (from, from_id, to, to_id, weight) = Self::parse_edge(&record, &positions)?;
+
+                graph
+                    .add_edge(
+                        (from.clone(), identifier_attributes(&from_id)),
+                        (to, identifier_attributes(&to_id)),
+                        weight,
+                    )
+                    .map(|_| (from_id, to_id, weight))
+                    .map_err(|error| error.to_string())
+            });
+
+            match inserted {
+                Ok(change) => {
+                    changes.push(change);
+                    report.imported();
+                }
+                Err(error) => report.failed(line, error),
+            }
+
+            if line % ImportReport::BATCH_SIZE == 0 {
+                add_edge_changes(chain, &mut changes);
+                report.report_progress();
+            }
+        }
+
+        add_edge_changes(chain, &mut changes);
+        report.report_progress();
+        report.as_graph_result()
+    }
+
+    fn open(path: &str) -> Result<(Reader<File>, StringRecord), DatabaseError> {
+        let mut reader = Reader::from_path(path).map_err(|error| file_error(path, error))?;
+        let headers = reader.headers().map_err(|error| file_error(path, error))?.clone();
+
+        Ok((reader, headers))
+    }
+
+    fn parse_edge(record: &StringRecord, positions: &[usize]) -> Result<(String, String, String, String, i8), String> {
+        let column = |index: usize| record.get(positions[index]).unwrap_or_default().to_string();
+
+        let weight = column(4);
+        let weight = weight.parse().map_err(|error| format!("Weight {weight} is not valid: {error}"))?;
+
+        Ok((column(0), column(1), column(2), column(3), weight))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::attribute::InternalNodeAttribute;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn should_import_nodes() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        graph.create_definition("Person".to_string(), vec!["name".to_string()]).unwrap();
+
+        let path = env::temp_dir().join("should_import_nodes.csv");
+        fs::write(&path, "name\nJohn\nJanne\nToo,many\n").unwrap();
+
+        // When
+        let result = CsvImport::import_nodes(&mut graph, &mut chain, "Person", path.to_str().unwrap()).unwrap();
+
+        // Then
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].get("imported").unwrap(), "2");
+        assert_eq!(result[0].get("failed").unwrap(), "1");
+        assert_eq!(result[1].get("line").unwrap(), "4");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_import_edges() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        graph.create_definition("Person".to_string(), vec![]).unwrap();
+        let from = InternalNodeAttribute::get_identifier(graph.add_node("Person".to_string(), FxHashMap::default()).unwrap().first().unwrap());
+        let to = InternalNodeAttribute::get_identifier(graph.add_node("Person".to_string(), FxHashMap::default()).unwrap().first().unwrap());
+
+        let path = env::temp_dir().join("should_import_edges.csv");
+        fs::write(
+            &path,
+            format!("from,from_id,to,to_id,weight\nPerson,{from},Person,{to},50\nPerson,{from},Person,{to},invalid\n"),
+        )
+        .unwrap();
+
+        // When
+        let result = CsvImport::import_edges(&mut graph, &mut chain, path.to_str().unwrap()).unwrap();
+
+        // Then
+        assert_eq!(result[0].get("imported").unwrap(), "1");
+        assert_eq!(result[0].get("failed").unwrap(), "1");
+        assert_eq!(graph.nodes.get(&format!("{from}:Person")).unwrap().edges.len(), 1);
+        assert_eq!(chain.blocks.len(), 3);
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+use crate::chain::block::SchemaData;
+use crate::chain::Chain;
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::{Graph, GraphResults};
+use crate::import::{add_agent_node_change, add_edge_changes, file_error, identifier_attributes, EdgeChange, ImportReport};
+use rustc_hash::FxHashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+pub struct NdjsonImport;
+
+impl NdjsonImport {
+    /// Import data from JSON Lines file
+    ///
+    /// Each line contains either node definition ($definition attribute, with `secret` marker of secret attributes and
+    /// `$self_loops` policy), node (with its $name and $id) or
+    /// edge ($from, $from_id, $to, $to_id and $weight attributes), as written by `export data` command.
+    /// Definitions which already exist are skipped, and nodes keep their original identifiers.
+    /// Lines which can't be parsed or inserted are skipped and reported in the result. With storage encryption, the file
+    /// must start with header of its encryption, and each line is decrypted first.
+    pub fn import_data(graph: &mut Graph, chain: &mut Chain, path: &str) -> GraphResults {
+        let file = File::open(path).map_err(|error| file_error(path, error))?;
+
+        let mut report = ImportReport::new(path);
+        let mut changes = vec![];
+        let mut lines = BufReader::new(file).lines().enumerate();
+
+        // Encrypted file starts with header of its encryption
+        let mut encryption = match chain.encryption.clone() {
+            Some(encryption) => {
+                let header = lines.next().map(|(_, line)| line).transpose().map_err(|error| file_error(path, error))?;
+                Some(
+                    encryption
+                        .open(header.as_deref().unwrap_or_default())
+                        .map_err(|error| file_error(path, error))?,
+                )
+            }
+            None => None,
+        };
+
+        for (index, line) in lines {
+            let line_number = index + 1;
+
+            let line = match line {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => line,
+                Err(error) => return Err(file_error(path, error)),
+            };
+
+            let line = match &mut encryption {
+                Some(encryption) => encryption.decrypt(&line),
+                None => Ok(line),
+            };
+
+            match line
+                .and_then(|line| serde_json::from_str::<FxHashMap<String, String>>(&line).map_err(|error| error.to_string()))
+                .and_then(|item| Self::import_item(graph, chain, item, &mut changes))
+            {
+                Ok(_) => report.imported(),
+                Err(error) => report.failed(line_number, error),
+            }
+
+            if line_number % ImportReport::BATCH_SIZE == 0 {
+                add_edge_changes(chain, &mut changes);
+                report.report_progress();
+            }
+        }
+
+        add_edge_changes(chain, &mut changes);
+        report.report_progress();
+        report.as_graph_result()
+    }
+
+    fn import_item(graph: &mut Graph, chain: &mut Chain, mut item: FxHashMap<String, String>, changes: &mut Vec<EdgeChange>) -> Result<(), String> {
+        let attribute = |item: &mut FxHashMap<String, String>, name: &str| item.remove(name).ok_or(format!("Attribute {name} is required."));
+
+        if let Some(name) = item.remove(InternalNodeAttribute::DEFINITION_ATTRIBUTE) {
+            if !graph.definitions.contains_key(&name) {
+                let deny_self_loops = item
+                    .remove(InternalNodeAttribute::SELF_LOOPS_ATTRIBUTE)
+                    .is_some_and(|policy| policy == "deny");
+                let mut secret_attributes: Vec<String> = item
+                    .iter()
+                    .filter(|(_, marker)| *marker == "secret")
+                    .map(|(attribute, _)| attribute.clone())
+                    .collect();
+                secret_attributes.sort();
+
+                graph
+                    .create_definition(name.clone(), item.into_keys().collect())
+                    .map_err(|error| error.to_string())?;
+                if deny_self_loops {
+                    graph.denied_self_loops.insert(name.clone());
+                }
+                if !secret_attributes.is_empty() {
+                    graph.secret_attributes.insert(name.clone(), secret_attributes.iter().cloned().collect());
+                }
+
+                // Policies are added to the chain, so peers enforce them and values of secret attributes stay local
+                if deny_self_loops || !secret_attributes.is_empty() {
+                    let attributes = graph.definitions[&name].clone();
+                    let schema = SchemaData::new(name, attributes, None, deny_self_loops, secret_attributes);
+                    chain.add_schema_change(schema).map_err(|error| error.to_string())?;
+                }
+            }
+        } else if item.contains_key(InternalNodeAttribute::FROM_ATTRIBUTE) {
+            let from = attribute(&mut item, InternalNodeAttribute::FROM_ATTRIBUTE)?;
+            let from_id = attribute(&mut item, InternalNodeAttribute::FROM_ID_ATTRIBUTE)?;
+            let to = attribute(&mut item, InternalNodeAttribute::TO_ATTRIBUTE)?;
+            let to_id = attribute(&mut item, InternalNodeAttribute::TO_ID_ATTRIBUTE)?;
+            let weight = attribute(&mut item, InternalNodeAttribute::WEIGHT_ATTRIBUTE)?;
+            let weight: i8 = weight.parse().map_err(|error| format!("Weight {weight} is not valid: {error}"))?;
+
+            graph
+                .add_edge(
+                    (from.clone(), identifier_attributes(&from_id)),
+                    (to, identifier_attributes(&to_id)),
+                    weight,
+                )
+                .map_err(|error| error.to_string())?;
+
+            changes.push((from_id, to_id, weight));
+        } else {
+            let name = attribute(&mut item, InternalNodeAttribute::NAME_ATTRIBUTE)?;
+            let result = graph.insert_node(name.clone(), item).map_err(|error| error.to_string())?;
+
+            add_agent_node_change(chain, &name, result.first().unwrap());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::Export;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn should_import_data() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+
+        let path = env::temp_dir().join("should_import_data.ndjson");
+        fs::write(
+            &path,
+            [
+                r#"{"$definition":"User","name":"*"}"#,
+                r#"{"$name":"User","$id":"1","$edges":"1","name":"John"}"#,
+                r#"{"$name":"User","$id":"2","$edges":"0","name":"Janne"}"#,
+                r#"{"$from":"User","$from_id":"1","$to":"User","$to_id":"2","$weight":"70"}"#,
+                r#"{"$name":"User","$id":"3","age":"30"}"#,
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+
+        // When
+        let result = NdjsonImport::import_data(&mut graph, &mut chain, path.to_str().unwrap()).unwrap();
+
+        // Then
+        assert_eq!(result[0].get("imported").unwrap(), "4");
+        assert_eq!(result[0].get("failed").unwrap(), "1");
+        assert_eq!(result[1].get("line").unwrap(), "5");
+
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.nodes.get("1:User").unwrap().edges.len(), 1);
+        assert_eq!(graph.nodes.get("1:User").unwrap().attributes.get("name").unwrap(), "John");
+        assert_eq!(chain.blocks.len(), 3);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_import_exported_secret_attributes_and_self_loops_policy() {
+        // Given
+        let mut exported = Graph::default();
+        exported
+            .create_definition("User".to_string(), vec!["name".to_string(), "email".to_string()])
+            .unwrap();
+        exported
+            .secret_attributes
+            .insert("User".to_string(), ["email".to_string()].into_iter().collect());
+        exported.denied_self_loops.insert("User".to_string());
+
+        let path = env::temp_dir().join("should_import_exported_secret_attributes_and_self_loops_policy.ndjson");
+        Export::export_data(&exported, path.to_str().unwrap(), None).unwrap();
+
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+
+        // When
+        let result = NdjsonImport::import_data(&mut graph, &mut chain, path.to_str().unwrap()).unwrap();
+
+        // Then
+        let schema = chain.blocks.iter().find_map(|block| block.data.schema_data.as_ref()).unwrap();
+        assert_eq!(result[0].get("imported").unwrap(), "1");
+        assert!(graph.is_secret("User", "email"));
+        assert!(!graph.is_secret("User", "name"));
+        assert!(graph.denied_self_loops.contains("User"));
+        assert_eq!(schema.secret_attributes, vec!["email".to_string()]);
+        assert!(schema.deny_self_loops);
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,21 @@
+pub mod access;
+pub mod analytics;
+pub mod audit;
+pub mod bootstrap;
+pub mod chain;
+#[cfg(feature = "cypher")]
+pub mod cypher;
+pub mod database;
+pub mod export;
+pub mod graph;
+pub mod import;
+pub mod namespace;
+mod parser;
+pub mod protocol;
+pub mod query_processor;
+pub mod rate;
+pub mod repl;
+pub mod server;
+pub mod session;
+pub mod stats;
+pub mod ui;
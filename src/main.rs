@@ -1,56 +1,200 @@
-use crate::bootstrap::Bootstrap;
-use crate::chain::Chain;
-use crate::graph::Graph;
-use crate::protocol::Protocol;
-use query_processor::QueryProcessor;
-use tokio::{io, io::AsyncBufReadExt, select};
-
-mod bootstrap;
-mod chain;
-mod graph;
-mod protocol;
-mod query_processor;
+use std::env;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::{select, task, time};
+use weighted_graph::access::{AccessControl, Role};
+use weighted_graph::analytics::ThreadPool;
+use weighted_graph::audit::AuditLog;
+use weighted_graph::bootstrap::Bootstrap;
+use weighted_graph::chain::delivery::DeliveryJob;
+use weighted_graph::chain::genesis::Genesis;
+use weighted_graph::chain::mine::MiningJob;
+use weighted_graph::chain::Chain;
+use weighted_graph::database::Database;
+use weighted_graph::graph::generator::IdGenerator;
+use weighted_graph::graph::tombstone::Tombstones;
+use weighted_graph::graph::Graph;
+use weighted_graph::namespace::Namespaces;
+use weighted_graph::protocol::authentication::Authentication;
+use weighted_graph::protocol::command::{Compression, SyncMode, WireFormat};
+use weighted_graph::protocol::network::NetworkConfig;
+use weighted_graph::protocol::Protocol;
+use weighted_graph::query_processor::QueryProcessor;
+use weighted_graph::rate::RateLimit;
+use weighted_graph::repl::Repl;
+use weighted_graph::server::QueryServer;
+use weighted_graph::session::Sessions;
+use weighted_graph::stats::QueryStats;
+use weighted_graph::ui::UiServer;
 
 #[tokio::main]
 async fn main() {
     let mut graph = Graph::default();
-    let mut chain = Chain::default();
 
-    let mut protocol = Protocol::init().map_err(|error| eprintln!("{error}")).unwrap();
+    let (_, arguments) = argmap::parse(env::args());
+    let genesis = Genesis::from_arguments(&arguments).map_err(|error| eprintln!("{error}")).unwrap();
+    graph.ids = IdGenerator::from_arguments(&arguments).map_err(|error| eprintln!("{error}")).unwrap();
+    graph.tombstones = Tombstones::new(genesis.soft_delete_retention);
+    if let Err(error) = genesis.create_definitions(&mut graph) {
+        eprintln!("{error}");
+    }
 
-    let mut input = io::BufReader::new(io::stdin()).lines();
+    let mut chain = Chain::from_arguments(&genesis, &arguments).map_err(|error| eprintln!("{error}")).unwrap();
 
-    // Initialization for testing
-    if let Err(error) = Bootstrap::init(&mut graph, &mut chain) {
-        eprintln!("{error}");
+    ThreadPool::init(&arguments).map_err(|error| eprintln!("{error}")).unwrap();
+
+    let wire_format = WireFormat::from_arguments(&arguments).map_err(|error| eprintln!("{error}")).unwrap();
+    let network_config = NetworkConfig::from_arguments(&arguments).map_err(|error| eprintln!("{error}")).unwrap();
+    let mut protocol = Protocol::init(&network_config, wire_format, &genesis.chain_id)
+        .map_err(|error| eprintln!("{error}"))
+        .unwrap();
+    protocol.sync_mode = SyncMode::from_arguments(&arguments).map_err(|error| eprintln!("{error}")).unwrap();
+    protocol.compression = Compression::from_arguments(&arguments).map_err(|error| eprintln!("{error}")).unwrap();
+    protocol.authentication = Authentication::from_arguments(&arguments).map_err(|error| eprintln!("{error}")).unwrap();
+    protocol.block_rate = RateLimit::from_arguments(&arguments, "peer-block-rate")
+        .map_err(|error| eprintln!("{error}"))
+        .unwrap();
+
+    let mut mempool_timer = time::interval(Duration::from_secs(1));
+    // In proof of work mode, pending changes are mined in blocking task, so chain isn't locked while mining
+    let mut mining: Option<(Arc<Database>, task::JoinHandle<MiningJob>)> = None;
+    let mut deliveries: JoinSet<(Arc<Database>, DeliveryJob)> = JoinSet::new();
+
+    // Initialization for testing, observer only syncs the chain from peers
+    if !chain.mode.is_observer() {
+        if let Err(error) = Bootstrap::init(&mut graph, &mut chain) {
+            eprintln!("{error}");
+        }
+    }
+
+    let audit = AuditLog::from_arguments(&arguments, chain.encryption.clone())
+        .map_err(|error| eprintln!("{error}"))
+        .unwrap();
+    let database = Database {
+        stats: QueryStats::from_arguments(&arguments, chain.encryption.clone())
+            .map_err(|error| eprintln!("{error}"))
+            .unwrap(),
+        audit,
+        ..Database::new(graph, chain)
+    };
+    let database = Arc::new(database);
+    let access = AccessControl::from_arguments(&arguments)
+        .map_err(|error| eprintln!("{error}"))
+        .unwrap()
+        .map(Arc::new);
+    let console_role = AccessControl::console_role(access.as_deref());
+    let sessions = Arc::new(Sessions::from_arguments(&arguments).map_err(|error| eprintln!("{error}")).unwrap());
+    // Local input uses the database selected by `use` command, while query server clients select database of their
+    // session
+    let mut namespaces = Namespaces::new(database.clone(), genesis, arguments.clone());
+    if let Some(server) = QueryServer::from_arguments(&arguments)
+        .await
+        .map_err(|error| eprintln!("{error}"))
+        .unwrap()
+    {
+        server
+            .with_access(access.clone())
+            .with_sessions(sessions.clone())
+            .spawn(namespaces.shared());
+    }
+    if let Some(server) = UiServer::from_arguments(&arguments).await.map_err(|error| eprintln!("{error}")).unwrap() {
+        server.with_access(access).spawn(database.clone());
     }
 
+    // Rows of large results are written as separate lines with `--stream` argument
+    let stream = arguments.contains_key("stream");
+    let definitions = Arc::new(Mutex::new(database.graph.read().unwrap().definitions.clone()));
+    let mut input = Repl::spawn(definitions.clone());
+
     loop {
         select! {
-            Ok(Some(line)) = input.next_line() => {
-                match QueryProcessor::parse_command(&mut graph, &mut chain, &line) {
-                    Err(error) => eprintln!("{error}"),
-                    Ok(result) => match result {
-                        Ok(items) => {
-                                match serde_json::to_string(&items) {
-                                    Ok(json) => println!("{json}"),
-                                    Err(error) => eprintln!("{error}"),
-                                }
-                            }
-                        Err(error) => eprintln!("{error}"),
+            Some(line) = input.recv() => {
+                // Peer commands are executed by protocol and namespace commands by namespaces, so they are available only on
+                // local input
+                let mut parse = |command: &str| match Role::authorize(Some(console_role), command) {
+                    Err(error) => Ok(Err(error)),
+                    Ok(_) => match protocol.execute(command) {
+                        Some(result) => QueryProcessor::rows(Ok(result)),
+                        None => match namespaces.execute(command, &mut protocol) {
+                            Some(result) => QueryProcessor::rows(Ok(result)),
+                            None => match sessions.execute(Some(console_role), command) {
+                                Some(result) => QueryProcessor::rows(Ok(result)),
+                                None => namespaces.current().stream_from(AuditLog::CONSOLE_SOURCE, Some(console_role), command),
+                            },
+                        },
+                    },
+                };
+
+                if stream {
+                    if let Err(error) = QueryProcessor::stream_request(parse, &line, &mut io::stdout().lock()) {
+                        eprintln!("{error}");
                     }
+                } else {
+                    println!("{}", QueryProcessor::handle_request(|command| parse(command).map(|rows| rows.map(Iterator::collect)), &line));
                 }
+                definitions.lock().unwrap().clone_from(&namespaces.current().graph.read().unwrap().definitions);
             },
             event = protocol.fetch_network_event() => {
-                match protocol.handle_network_event(&mut chain, event) {
+                let database = namespaces.by_chain_id(protocol.chain_of(&event));
+                let mut graph = database.graph.write().unwrap();
+                let mut chain = database.chain.write().unwrap();
+                match protocol.handle_network_event(&mut graph, &mut chain, event) {
                     Err(error) => eprintln!("{error}"),
                     Ok(message) =>if message != "NOP" { println!("{message}") },
                 }
             },
+            // Changes made by query server clients are also published on each tick
+            _ = mempool_timer.tick() => {
+                // Peers whose connection dropped are dialed again with exponential backoff
+                if let Err(error) = protocol.reconnect() {
+                    eprintln!("{error}");
+                }
+                for database in namespaces.databases() {
+                    // Nodes and connections whose time to live has passed are deleted before pending changes are published
+                    database.expire();
+
+                    let mut chain = database.chain.write().unwrap();
+                    if chain.mempool.is_due() || chain.mempool.is_full() || chain.has_queued_changes() {
+                        if chain.consensus.is_proof_of_work() {
+                            // Blocks are mined for one database at a time
+                            if mining.is_none() {
+                                mining = chain.mining_job().map(|job| (database.clone(), task::spawn_blocking(|| job.mine())));
+                            }
+                        } else if let Err(error) = chain.flush_mempool() {
+                            eprintln!("Chain error: {error}");
+                        }
+                    }
+                    // Blocks are delivered to change stream and payloads sent to webhooks by blocking task, and failed ones
+                    // are delivered again by the next job
+                    if let Some(job) = chain.delivery_job() {
+                        let database = database.clone();
+                        deliveries.spawn_blocking(move || (database, job.deliver()));
+                    }
+                }
+            },
+            Some(Ok((database, job))) = deliveries.join_next(), if !deliveries.is_empty() => {
+                if let Err(error) = database.chain.write().unwrap().complete_delivery(job) {
+                    eprintln!("Chain error: {error}");
+                }
+            },
+            Some((database, job)) = async { let (database, job) = mining.as_mut()?; Some((database.clone(), job.await.ok()?)) }, if mining.is_some() => {
+                mining = None;
+                if let Err(error) = database.chain.write().unwrap().add_mined_block(job) {
+                    eprintln!("Chain error: {error}");
+                }
+            },
         }
 
-        if let Err(error) = protocol.publish_changes(&chain) {
-            eprintln!("{error}");
+        for database in namespaces.databases() {
+            // Chain is locked for writing only when there is something to publish
+            if !protocol.has_changes(&database.chain.read().unwrap()) {
+                continue;
+            }
+            if let Err(error) = protocol.publish_changes(&mut database.chain.write().unwrap()) {
+                eprintln!("{error}");
+            }
         }
     }
 }
@@ -0,0 +1,232 @@
+extern crate peg;
+
+use crate::audit::AuditLog;
+use crate::chain::genesis::Genesis;
+use crate::chain::Chain;
+use crate::database::Database;
+use crate::graph::error::DatabaseError;
+use crate::graph::generator::IdGenerator;
+use crate::graph::tombstone::Tombstones;
+use crate::graph::{Graph, GraphResults};
+use crate::parser::command_parser;
+use crate::protocol::Protocol;
+use crate::stats::QueryStats;
+use rustc_hash::FxHashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+enum NamespaceCommand {
+    Create(String),
+    Use(String),
+    Fetch,
+}
+
+command_parser! {
+    grammar namespace_parser() for str {
+        pub rule command() -> NamespaceCommand = command:(create_database() / use_database() / fetch_databases()) _ ";"? _ { command }
+
+        rule create_database() -> NamespaceCommand = _ keyword("create") _ keyword("database") _ name:name() {
+            NamespaceCommand::Create(name.to_string())
+        }
+
+        rule use_database() -> NamespaceCommand = _ keyword("use") _ name:name() {
+            NamespaceCommand::Use(name.to_string())
+        }
+
+        rule fetch_databases() -> NamespaceCommand = _ keyword("fetch") _ keyword("databases") {
+            NamespaceCommand::Fetch
+        }
+
+        rule name() -> &'input str = $(['a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-']+)
+    }
+}
+
+/// Databases by their names, shared with query server, so its clients can use databases created on local input.
+pub type Databases = Arc<RwLock<BTreeMap<String, Arc<Database>>>>;
+
+/// Databases hosted by one node, each with its own graph and chain. Chain of created database is configured by the same
+/// genesis and arguments as chain of the default database, but its chain id is derived from chain id of the default
+/// database (e.g. `weighted-graph/staging`), so it is synced over its own topics, and its storage files are derived
+/// from storage files of the default database. Commands of local input are executed on the database selected by
+/// `use <name>` (`create database <name>` and `fetch databases` manage the databases), while query server clients
+/// select database of their session (see `Sessions`).
+pub struct Namespaces {
+    databases: Databases,
+    current: String,
+    genesis: Genesis,
+    arguments: HashMap<String, Vec<String>>,
+}
+
+impl Namespaces {
+    pub const DEFAULT: &'static str = "default";
+    /// Arguments with storage files, which are separate for each database.
+    const STORAGE_ARGUMENTS: [&'static str; 3] = ["archive", "audit-log", "cdc-offsets"];
+
+    pub fn new(database: Arc<Database>, genesis: Genesis, arguments: HashMap<String, Vec<String>>) -> Namespaces {
+        Namespaces {
+            databases: Arc::new(RwLock::new(BTreeMap::from([(Self::DEFAULT.to_string(), database)]))),
+            current: Self::DEFAULT.to_string(),
+            genesis,
+            arguments,
+        }
+    }
+
+    /// Database selected by the last `use` command.
+    pub fn current(&self) -> Arc<Database> {
+        self.databases.read().unwrap()[&self.current].clone()
+    }
+
+    pub fn databases(&self) -> Vec<Arc<Database>> {
+        self.databases.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn shared(&self) -> Databases {
+        self.databases.clone()
+    }
+
+    /// Database whose chain has given id, or the default database if chain id is not given (or not known).
+    pub fn by_chain_id(&self, chain_id: Option<&str>) -> Arc<Database> {
+        let databases = self.databases.read().unwrap();
+        chain_id
+            .and_then(|chain_id| {
+                databases
+                    .values()
+                    .find(|database| database.chain.read().unwrap().chain_id() == Some(chain_id))
+            })
+            .unwrap_or(&databases[Self::DEFAULT])
+            .clone()
+    }
+
+    /// Execute namespace command, or return `None` if given command is not namespace command. Protocol joins the chain
+    /// of created database.
+    pub fn execute(&mut self, command: &str, protocol: &mut Protocol) -> Option<GraphResults> {
+        let command = namespace_parser::command(command).ok()?;
+
+        Some(match command {
+            NamespaceCommand::Create(name) => self.create(name, protocol),
+            NamespaceCommand::Use(name) => self.select(name),
+            NamespaceCommand::Fetch => Ok(self.fetch()),
+        })
+    }
+
+    fn create(&mut self, name: String, protocol: &mut Protocol) -> GraphResults {
+        if self.databases.read().unwrap().contains_key(&name) {
+            return Err(DatabaseError::DatabaseAlreadyExists(name));
+        }
+
+        let genesis = Genesis {
+            chain_id: format!("{}/{name}", self.genesis.chain_id),
+            ..self.genesis.clone()
+        };
+        let mut arguments = self.arguments.clone();
+        for argument in Self::STORAGE_ARGUMENTS {
+            if let Some(path) = arguments.get_mut(argument).and_then(|values| values.first_mut()) {
+                *path = Self::storage_path(path, &name);
+            }
+        }
+
+        let mut graph = Graph {
+            ids: IdGenerator::from_arguments(&arguments)?,
+            tombstones: Tombstones::new(genesis.soft_delete_retention),
+            ..Graph::default()
+        };
+        genesis.create_definitions(&mut graph)?;
+        let chain = Chain::from_arguments(&genesis, &arguments).map_err(DatabaseError::ChainError)?;
+        protocol
+            .join(&genesis.chain_id)
+            .map_err(|error| DatabaseError::PeerError(error.to_string()))?;
+
+        let database = Database {
+            stats: QueryStats::from_arguments(&arguments, chain.encryption.clone())?,
+            audit: AuditLog::from_arguments(&arguments, chain.encryption.clone())?,
+            ..Database::new(graph, chain)
+        };
+        self.databases.write().unwrap().insert(name.clone(), Arc::new(database));
+
+        let mut result = FxHashMap::default();
+        result.insert("database".to_string(), name);
+        result.insert("chain_id".to_string(), genesis.chain_id);
+        Ok(vec![result])
+    }
+
+    fn select(&mut self, name: String) -> GraphResults {
+        if !self.databases.read().unwrap().contains_key(&name) {
+            return Err(DatabaseError::DatabaseNotFound(name));
+        }
+
+        self.current.clone_from(&name);
+
+        let mut result = FxHashMap::default();
+        result.insert("database".to_string(), name);
+        Ok(vec![result])
+    }
+
+    fn fetch(&self) -> Vec<FxHashMap<String, String>> {
+        self.databases
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, database)| {
+                let chain = database.chain.read().unwrap();
+
+                let mut result = FxHashMap::default();
+                result.insert("database".to_string(), name.clone());
+                result.insert("chain_id".to_string(), chain.chain_id().unwrap_or_default().to_string());
+                result.insert("blocks".to_string(), chain.blocks.len().to_string());
+                result.insert("current".to_string(), (*name == self.current).to_string());
+                result
+            })
+            .collect()
+    }
+
+    /// Storage file of database with given name, derived from storage file of the default database (e.g.
+    /// `archive.staging.jsonl` for `archive.jsonl`).
+    pub fn storage_path(path: &str, name: &str) -> String {
+        let path = Path::new(path);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+        let file_name = match path.extension() {
+            Some(extension) => format!("{stem}.{name}.{}", extension.to_string_lossy()),
+            None => format!("{stem}.{name}"),
+        };
+        path.with_file_name(file_name).to_string_lossy().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn should_create_and_use_databases() {
+        // Given
+        let genesis = Genesis::default();
+        let mut protocol = Protocol::in_memory(rand::random(), &genesis.chain_id).unwrap();
+        let mut namespaces = Namespaces::new(Arc::new(Database::default()), genesis, HashMap::new());
+        namespaces.current().execute("define node User(name)").unwrap().unwrap();
+
+        // When
+        let created = namespaces.execute("create database staging;", &mut protocol).unwrap().unwrap();
+        let duplicate = namespaces.execute("create database staging", &mut protocol).unwrap();
+        let missing = namespaces.execute("use production", &mut protocol).unwrap();
+        namespaces.execute("use staging", &mut protocol).unwrap().unwrap();
+        let fetched = namespaces.execute("fetch databases", &mut protocol).unwrap().unwrap();
+
+        // Then
+        assert_eq!(created[0]["chain_id"], "weighted-graph/staging");
+        assert_eq!(duplicate.err().unwrap().code(), "DATABASE_ALREADY_EXISTS");
+        assert_eq!(missing.err().unwrap().code(), "DATABASE_NOT_FOUND");
+        assert_eq!(fetched.len(), 2);
+        assert!(namespaces.current().graph.read().unwrap().definitions.is_empty());
+        assert!(Arc::ptr_eq(
+            &namespaces.by_chain_id(Some("weighted-graph/staging")),
+            &namespaces.current()
+        ));
+        assert!(!Arc::ptr_eq(&namespaces.by_chain_id(None), &namespaces.current()));
+        assert_eq!(namespaces.shared().read().unwrap().len(), 2);
+        assert!(protocol.join("weighted-graph/staging").is_err());
+        assert!(namespaces.execute("fetch nodes", &mut protocol).is_none());
+        assert_eq!(Namespaces::storage_path("data/archive.jsonl", "staging"), "data/archive.staging.jsonl");
+    }
+}
@@ -0,0 +1,26 @@
+/// Grammar of commands, with rules shared by all command parsers: case-insensitive keywords, whitespace (`_`) and
+/// comments (`--` or `//` until the end of the line).
+macro_rules! command_parser {
+    // Dollar sign is passed as token, so shared rules can use slices (`$(...)`) of the grammar
+    (@grammar ($dollar:tt) grammar $name:ident($($arguments:tt)*) for str { $($rules:tt)* }) => {
+        peg::parser! {
+            grammar $name($($arguments)*) for str {
+                $($rules)*
+
+                // Keywords are case-insensitive, and must be whole words
+                rule keyword(expected: &'static str) = word:$dollar(['a'..='z' | 'A'..='Z']+) {?
+                    if word.eq_ignore_ascii_case(expected) { Ok(()) } else { Err(expected) }
+                }
+
+                rule _ = ([' ' | '\t' | '\r' | '\n'] / comment())*
+
+                rule comment() = ("--" / "//") [^'\n']*
+            }
+        }
+    };
+    ($($grammar:tt)*) => {
+        command_parser!(@grammar ($) $($grammar)*);
+    };
+}
+
+pub(crate) use command_parser;
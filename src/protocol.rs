@@ -1,49 +1,274 @@
-use crate::chain::block::Block;
+use crate::access::Role;
+use crate::chain::block::{Block, BlockHeader};
+use crate::chain::error::ChainError;
+use crate::chain::finality::CheckpointVote;
 use crate::chain::Chain;
-use crate::protocol::command::{ChainRequest, ChainResponse};
+use crate::graph::{Graph, GraphResults};
+use crate::protocol::authentication::Authentication;
+use crate::protocol::command::{
+    BodiesResponse, ChainRequest, ChainResponse, Compression, HeadersResponse, QueryRequest, QueryResponse, SyncContent, SyncMode, WireFormat,
+};
 use crate::protocol::error::ProtocolError;
-use crate::protocol::network::{Network, NetworkEvent};
+use crate::protocol::network::{Network, NetworkConfig, NetworkEvent};
+use crate::protocol::peer::PeerCommand;
+use crate::protocol::retry::Backoff;
+use crate::protocol::seen::SeenBlocks;
+use crate::query_processor::{Access, QueryProcessor};
+use crate::rate::{RateLimit, TokenBucket};
 use libp2p::futures::stream::SelectNextSome;
 use libp2p::futures::StreamExt;
-use libp2p::gossipsub::IdentTopic;
+use libp2p::gossipsub::{IdentTopic, MessageAcceptance, MessageId, PublishError, TopicHash};
+use libp2p::swarm::dial_opts::DialOpts;
 use libp2p::swarm::SwarmEvent;
-use libp2p::{gossipsub, mdns, Swarm};
+use libp2p::{dcutr, gossipsub, identify, mdns, relay, request_response, Multiaddr, PeerId, Swarm};
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+use serde_json::Value;
+use std::time::{Duration, Instant};
 
-mod command;
-mod error;
-mod network;
+pub mod authentication;
+pub mod command;
+pub mod error;
+pub mod network;
+mod peer;
+mod retry;
+mod seen;
+#[cfg(test)]
+mod simulation;
 
+/// Topics and synchronization state of one chain the node is subscribed to.
+struct Subscription {
+    chain_id: String,
+    block_topic: IdentTopic,
+    sync_topic: IdentTopic,
+    vote_topic: IdentTopic,
+    pending_blocks: FxHashMap<usize, Block>,
+    seen_blocks: SeenBlocks,
+    last_request: Option<(u64, usize, Instant)>,
+    headers: Vec<BlockHeader>,
+    /// Blocks of peer's chain after the fork point, collected until they form chain longer than the local one
+    fork: Vec<Block>,
+    published_vote: Option<CheckpointVote>,
+    /// Publishing is retried with backoff after it fails (e.g. when no peers are connected)
+    publish_backoff: Backoff,
+}
+
+/// Node can be subscribed to multiple chains (one for each hosted database) over the same network. Subscription of the
+/// chain whose events are handled (or changes published) is active, while other subscriptions are kept by chain id.
 pub struct Protocol {
     network: Swarm<Network>,
-    topic: IdentTopic,
-    chain_count: usize,
+    subscription: Subscription,
+    subscriptions: FxHashMap<String, Subscription>,
+    wire_format: WireFormat,
+    pub sync_mode: SyncMode,
+    pub compression: Compression,
+    pub authentication: Option<Authentication>,
+    /// Limit of blocks received from each peer, so flooding peer can't block the event loop
+    pub block_rate: Option<RateLimit>,
+    /// Token bucket of each peer with number of its blocks which were dropped as they exceeded the limit
+    block_limits: FxHashMap<PeerId, (TokenBucket, u64)>,
+    /// Addresses of peers dialed by the local node, which are dialed again when their connection drops
+    known_peers: FxHashMap<PeerId, Multiaddr>,
+    reconnects: FxHashMap<PeerId, (Multiaddr, Backoff)>,
 }
 
 impl Protocol {
-    const COMMAND_TOPIC: &'static str = "command";
+    /// Blocks are kept until missing blocks are received only this far past the local chain, so peer can't fill pending
+    /// blocks with far-future ids
+    const PENDING_BLOCKS_WINDOW: usize = 256;
+    const MAX_RATE_LIMITED_PEERS: usize = 1024;
+    const MAX_RECONNECT_ATTEMPTS: u32 = 10;
+    const MAX_PUBLISH_DELAY: Duration = Duration::from_secs(60);
+    const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(300);
+    const RETRY_DELAY: Duration = Duration::from_secs(1);
+    const MAX_SEEN_BLOCKS: usize = 1024;
+    const MAX_SYNC_BLOCKS: usize = 64;
+    const MAX_SYNC_HEADERS: usize = 512;
+    const MAX_FORK_BLOCKS: usize = 1024;
+    const SYNC_TIMEOUT: Duration = Duration::from_secs(10);
 
-    pub fn init() -> Result<Protocol, ProtocolError> {
-        let mut network = Network::init().map_err(|error| ProtocolError::NetworkError(error.to_string()))?;
-        let topic = IdentTopic::new(Self::COMMAND_TOPIC);
+    /// Start network and subscribe to topics of chain with given id (from genesis configuration), so independent
+    /// deployments in the same network don't receive blocks of each other.
+    pub fn init(config: &NetworkConfig, wire_format: WireFormat, chain_id: &str) -> Result<Protocol, ProtocolError> {
+        let network = Network::init(config).map_err(|error| ProtocolError::NetworkError(error.to_string()))?;
+
+        Self::subscribe(network, wire_format, chain_id)
+    }
+
+    /// Protocol of node listening on in-memory address, used to simulate nodes in tests.
+    #[cfg(test)]
+    pub(crate) fn in_memory(address: u64, chain_id: &str) -> Result<Protocol, ProtocolError> {
+        let network = Network::in_memory(address).map_err(|error| ProtocolError::NetworkError(error.to_string()))?;
+
+        Self::subscribe(network, WireFormat::default(), chain_id)
+    }
 
-        network
-            .behaviour_mut()
-            .channel
-            .subscribe(&topic)
-            .map_err(|error| ProtocolError::NetworkError(error.to_string()))?;
+    fn subscribe(mut network: Swarm<Network>, wire_format: WireFormat, chain_id: &str) -> Result<Protocol, ProtocolError> {
+        let subscription = Self::subscribe_chain(&mut network, chain_id)?;
 
         Ok(Protocol {
             network,
-            topic,
-            chain_count: 0,
+            subscription,
+            subscriptions: FxHashMap::default(),
+            wire_format,
+            sync_mode: SyncMode::default(),
+            compression: Compression::default(),
+            authentication: None,
+            block_rate: None,
+            block_limits: FxHashMap::default(),
+            known_peers: FxHashMap::default(),
+            reconnects: FxHashMap::default(),
+        })
+    }
+
+    fn subscribe_chain(network: &mut Swarm<Network>, chain_id: &str) -> Result<Subscription, ProtocolError> {
+        let block_topic = IdentTopic::new(format!("chain/{chain_id}/blocks"));
+        let sync_topic = IdentTopic::new(format!("chain/{chain_id}/sync"));
+        let vote_topic = IdentTopic::new(format!("chain/{chain_id}/votes"));
+
+        for topic in [&block_topic, &sync_topic, &vote_topic] {
+            let channel = &mut network.behaviour_mut().channel;
+
+            channel.subscribe(topic).map_err(|error| ProtocolError::NetworkError(error.to_string()))?;
+            channel
+                .set_topic_params(topic.clone(), Network::topic_score())
+                .map_err(|error| ProtocolError::NetworkError(error.to_string()))?;
+        }
+
+        Ok(Subscription {
+            chain_id: chain_id.to_string(),
+            block_topic,
+            sync_topic,
+            vote_topic,
+            pending_blocks: FxHashMap::default(),
+            seen_blocks: SeenBlocks::new(Self::MAX_SEEN_BLOCKS),
+            last_request: None,
+            headers: vec![],
+            fork: vec![],
+            published_vote: None,
+            publish_backoff: Backoff::new(Self::RETRY_DELAY, Self::MAX_PUBLISH_DELAY),
         })
     }
 
+    /// Subscribe to topics of another chain with given id, e.g. of newly created database.
+    pub fn join(&mut self, chain_id: &str) -> Result<(), ProtocolError> {
+        if self.chain_ids().any(|joined| joined == chain_id) {
+            return Err(ProtocolError::NetworkError(format!("Chain {chain_id} is already joined")));
+        }
+
+        let subscription = Self::subscribe_chain(&mut self.network, chain_id)?;
+        self.subscriptions.insert(chain_id.to_string(), subscription);
+
+        Ok(())
+    }
+
+    fn chain_ids(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.subscription.chain_id.as_str()).chain(self.subscriptions.keys().map(String::as_str))
+    }
+
+    /// Id of the chain to which network event belongs, or `None` if event doesn't belong to any chain (e.g. peer
+    /// discovery), so it can be handled with any chain.
+    pub fn chain_of(&self, event: &SwarmEvent<NetworkEvent>) -> Option<&str> {
+        let topic = match event {
+            SwarmEvent::Behaviour(NetworkEvent::Channel(gossipsub::Event::Message { message, .. })) => &message.topic,
+            SwarmEvent::Behaviour(NetworkEvent::Channel(gossipsub::Event::Subscribed { topic, .. })) => topic,
+            _ => return None,
+        };
+
+        std::iter::once(&self.subscription)
+            .chain(self.subscriptions.values())
+            .find(|subscription| {
+                [&subscription.block_topic, &subscription.sync_topic, &subscription.vote_topic]
+                    .iter()
+                    .any(|t| t.hash() == *topic)
+            })
+            .map(|subscription| subscription.chain_id.as_str())
+    }
+
+    /// Make subscription of given chain active, chain without known id (or not joined) keeps the active subscription.
+    fn activate(&mut self, chain: &Chain) {
+        let Some(chain_id) = chain.chain_id() else {
+            return;
+        };
+
+        if let Some(subscription) = self.subscriptions.remove(chain_id) {
+            let previous = std::mem::replace(&mut self.subscription, subscription);
+            self.subscriptions.insert(previous.chain_id.clone(), previous);
+        }
+    }
+
+    /// Execute peer management command, or return `None` if given command is not peer command. Banned peer is
+    /// forgotten, so it isn't dialed again when its connection is closed.
+    pub fn execute(&mut self, command: &str) -> Option<GraphResults> {
+        let rate_limited = self.block_limits.iter().map(|(peer, (_, dropped))| (*peer, *dropped)).collect();
+        let result = PeerCommand::execute(&mut self.network, &rate_limited, command);
+
+        let banned = result.iter().flatten().flatten().filter(|row| row.contains_key("banned"));
+        for peer in banned.filter_map(|row| row["peer_id"].parse::<PeerId>().ok()) {
+            self.known_peers.remove(&peer);
+            self.reconnects.remove(&peer);
+        }
+
+        result
+    }
+
+    /// Dial peers whose connection dropped and whose backoff delay has passed. Peers which are still not reachable
+    /// after `MAX_RECONNECT_ATTEMPTS` are forgotten, until they connect again.
+    pub fn reconnect(&mut self) -> Result<(), ProtocolError> {
+        let due: Vec<PeerId> = self
+            .reconnects
+            .iter()
+            .filter(|(_, (_, backoff))| backoff.is_due())
+            .map(|(peer, _)| *peer)
+            .collect();
+
+        let mut errors = vec![];
+        for peer in due {
+            if self.network.is_connected(&peer) {
+                self.reconnects.remove(&peer);
+                continue;
+            }
+
+            let (address, backoff) = self.reconnects.get_mut(&peer).unwrap();
+            if backoff.failures() >= Self::MAX_RECONNECT_ATTEMPTS {
+                self.reconnects.remove(&peer);
+                self.known_peers.remove(&peer);
+                errors.push(format!("Peer {peer} is not reachable, reconnecting stopped"));
+                continue;
+            }
+
+            backoff.fail();
+            let dial = DialOpts::peer_id(peer).addresses(vec![address.clone()]).build();
+            if let Err(error) = self.network.dial(dial) {
+                errors.push(format!("Peer {peer} can't be dialed ({error})"));
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(ProtocolError::NetworkError(errors.join(", "))),
+        }
+    }
+
     pub fn fetch_network_event(&mut self) -> SelectNextSome<'_, Swarm<Network>> {
         self.network.select_next_some()
     }
 
-    pub fn handle_network_event(&mut self, chain: &mut Chain, event: SwarmEvent<NetworkEvent>) -> Result<String, ProtocolError> {
+    /// Handle network event, and apply node changes of blocks added to the chain by it to the graph.
+    pub fn handle_network_event(&mut self, graph: &mut Graph, chain: &mut Chain, event: SwarmEvent<NetworkEvent>) -> Result<String, ProtocolError> {
+        self.activate(chain);
+        if let SwarmEvent::Behaviour(NetworkEvent::Queries(event)) = event {
+            return self.handle_query(graph, chain, event);
+        }
+
+        let height = chain.blocks.len();
+        let result = self.handle_event(chain, event);
+        chain.replay(graph, height);
+
+        result
+    }
+
+    fn handle_event(&mut self, chain: &mut Chain, event: SwarmEvent<NetworkEvent>) -> Result<String, ProtocolError> {
         match event {
             SwarmEvent::Behaviour(NetworkEvent::AddressResolver(mdns::Event::Discovered(list))) => {
                 for (peer_id, _multiaddr) in list {
@@ -55,36 +280,81 @@ impl Protocol {
                     self.network.behaviour_mut().channel.remove_explicit_peer(&peer_id);
                 }
             }
-            SwarmEvent::Behaviour(NetworkEvent::Channel(gossipsub::Event::Message { message, .. })) => {
-                // Received whole chain from peer - usually on startup
-                if let Ok(remote_chain) = serde_json::from_slice::<ChainResponse>(&message.data) {
-                    if *self.network.local_peer_id() == remote_chain.to_peer {
-                        chain
-                            .replace_chain(&remote_chain.chain)
-                            .map_err(|error| ProtocolError::ChainError(error))?;
-
-                        return Ok(format!(
-                            "Chain replaced with new chain {}",
-                            serde_json::to_string(&remote_chain.chain).unwrap()
-                        ));
-                    }
+            SwarmEvent::Behaviour(NetworkEvent::Channel(gossipsub::Event::Message {
+                propagation_source,
+                message_id,
+                message,
+            })) => {
+                // Messages relayed by peers which are not part of authenticated network are rejected, while relayed
+                // messages created by such peers are ignored
+                if !self.is_authorized(&propagation_source) {
+                    self.report_message(&message_id, &propagation_source, MessageAcceptance::Reject);
+                    return self.deny(propagation_source);
+                }
+                if message.source.is_some_and(|source| !self.is_authorized(&source)) {
+                    self.report_message(&message_id, &propagation_source, MessageAcceptance::Ignore);
+                    return Ok("NOP".to_string());
+                }
+                // Dropped blocks are not propagated, and they are requested again by sync if they are needed
+                if message.topic == self.subscription.block_topic.hash() && !self.take_block_token(&propagation_source) {
+                    self.report_message(&message_id, &propagation_source, MessageAcceptance::Ignore);
+                    return Err(ProtocolError::RateLimitExceeded(propagation_source.to_string()));
                 }
-                // Got request from peer for chain - usually on peer startup
-                else if let Ok(chain_request) = serde_json::from_slice::<ChainRequest>(&message.data) {
-                    if *self.network.local_peer_id() == chain_request.from_peer {
-                        self.publish_chain(chain)?;
 
-                        return Ok(format!("Chain published to peer {}", chain_request.from_peer));
+                let (acceptance, result) = self.handle_message(chain, &propagation_source, &message.topic, &message.data);
+                self.report_message(&message_id, &propagation_source, acceptance);
+
+                return result;
+            }
+            SwarmEvent::Behaviour(NetworkEvent::RelayClient(relay::client::Event::ReservationReqAccepted { relay_peer_id, .. })) => {
+                return Ok(format!("Reservation accepted by relay {relay_peer_id}"));
+            }
+            SwarmEvent::Behaviour(NetworkEvent::HolePunching(dcutr::Event { remote_peer_id, result })) => {
+                return match result {
+                    Ok(_) => Ok(format!("Direct connection established with peer {remote_peer_id}")),
+                    Err(error) => Err(ProtocolError::NetworkError(format!(
+                        "Hole punching with peer {remote_peer_id} failed ({error})"
+                    ))),
+                };
+            }
+            SwarmEvent::Behaviour(NetworkEvent::Identify(identify::Event::Received { peer_id, info, .. })) => {
+                if let Some(authentication) = &mut self.authentication {
+                    if !authentication.authorize(&peer_id, &info.agent_version) {
+                        return self.deny(peer_id);
                     }
                 }
-                // Received new block
-                else if let Ok(block) = serde_json::from_slice::<Block>(&message.data) {
-                    if chain.add_new_block(block.clone()).is_ok() {
-                        // Relaying block
-                        self.publish_block(&block)?;
-
-                        return Ok(format!("Block added to chain {}", serde_json::to_string(&block).unwrap()));
+            }
+            // Missing blocks are requested from newly subscribed peer - usually on startup or after reconnect
+            SwarmEvent::Behaviour(NetworkEvent::Channel(gossipsub::Event::Subscribed { peer_id, topic }))
+                if topic == self.subscription.sync_topic.hash() && self.is_authorized(&peer_id) =>
+            {
+                self.subscription.publish_backoff.reset();
+                return self.request_chain(chain, peer_id);
+            }
+            SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                if endpoint.is_dialer() {
+                    self.known_peers.insert(peer_id, endpoint.get_remote_address().clone());
+                }
+                if self.reconnects.remove(&peer_id).is_some() {
+                    // Sync requests sent before disconnect won't be answered, so chains are requested again as soon as
+                    // peer subscribes
+                    for subscription in std::iter::once(&mut self.subscription).chain(self.subscriptions.values_mut()) {
+                        subscription.last_request = None;
                     }
+                    return Ok(format!("Reconnected to peer {peer_id}"));
+                }
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id, num_established: 0, ..
+            } => {
+                if let Some(address) = self.known_peers.get(&peer_id).filter(|_| self.is_authorized(&peer_id)) {
+                    let mut backoff = Backoff::new(Self::RETRY_DELAY, Self::MAX_RECONNECT_DELAY);
+                    let delay = backoff.fail();
+                    self.reconnects.insert(peer_id, (address.clone(), backoff));
+                    return Ok(format!(
+                        "Connection to peer {peer_id} was closed, reconnecting in {} seconds",
+                        delay.as_secs()
+                    ));
                 }
             }
             _ => {}
@@ -93,44 +363,620 @@ impl Protocol {
         Ok("NOP".to_string())
     }
 
-    pub fn chain_contains_changes(&self, chain: &Chain) -> bool {
-        chain.blocks.len() > self.chain_count
+    /// Answer read-only command forwarded by peer, or return results of command forwarded to peer as JSON lines - one
+    /// line for each row, followed by `{"end":true,"rows":2,"peer_id":"...","request_id":"..."}` terminator record.
+    fn handle_query(
+        &mut self,
+        graph: &Graph,
+        chain: &Chain,
+        event: request_response::Event<QueryRequest, QueryResponse>,
+    ) -> Result<String, ProtocolError> {
+        match event {
+            request_response::Event::Message {
+                peer,
+                message: request_response::Message::Request { request, channel, .. },
+            } => {
+                if !self.is_authorized(&peer) {
+                    return self.deny(peer);
+                }
+
+                // Peer can run only fetch commands, so it can't e.g. export data to the disk of this node
+                let is_fetch = request
+                    .command
+                    .split_whitespace()
+                    .next()
+                    .is_some_and(|word| word.eq_ignore_ascii_case("fetch"));
+                let response = match Role::required(&request.command) {
+                    _ if !is_fetch => QueryResponse {
+                        rows: vec![],
+                        error: Some((
+                            "PERMISSION_DENIED".to_string(),
+                            "Only fetch commands can be forwarded by peer".to_string(),
+                        )),
+                    },
+                    // Peers are not admins of this node, so they can't read or select nodes by secret attributes
+                    Role::Reader => match QueryProcessor::parse_with_access(&mut Access::shared(graph, chain).without_secrets(), &request.command) {
+                        Ok(Ok(mut rows)) => {
+                            graph.redact(&mut rows);
+                            QueryResponse { rows, error: None }
+                        }
+                        Ok(Err(error)) => QueryResponse {
+                            rows: vec![],
+                            error: Some((error.code().to_string(), error.to_string())),
+                        },
+                        Err(error) => QueryResponse {
+                            rows: vec![],
+                            error: Some(("PARSE_ERROR".to_string(), error.to_string())),
+                        },
+                    },
+                    required => QueryResponse {
+                        rows: vec![],
+                        error: Some((
+                            "PERMISSION_DENIED".to_string(),
+                            format!("Command forwarded by peer requires {required} role"),
+                        )),
+                    },
+                };
+
+                self.network
+                    .behaviour_mut()
+                    .queries
+                    .send_response(channel, response)
+                    .map_err(|_| ProtocolError::NetworkError(format!("Query of peer {peer} can't be answered")))?;
+                Ok("NOP".to_string())
+            }
+            request_response::Event::Message {
+                peer,
+                message: request_response::Message::Response { request_id, response },
+            } => {
+                if let Some((code, message)) = response.error {
+                    let mut error = QueryProcessor::error_response(&code, &message);
+                    error["peer_id"] = Value::String(peer.to_string());
+                    error["request_id"] = Value::String(request_id.to_string());
+                    return Ok(error.to_string());
+                }
+
+                let mut lines: Vec<String> = response
+                    .rows
+                    .iter()
+                    .map(|row| serde_json::to_string(row).map_err(|error| ProtocolError::ParseError(error.to_string())))
+                    .collect::<Result<_, _>>()?;
+                lines.push(
+                    serde_json::json!({ "end": true, "rows": response.rows.len(), "peer_id": peer.to_string(), "request_id": request_id.to_string() })
+                        .to_string(),
+                );
+
+                Ok(lines.join("\n"))
+            }
+            request_response::Event::OutboundFailure { peer, request_id, error } => Err(ProtocolError::NetworkError(format!(
+                "Query {request_id} forwarded to peer {peer} failed ({error})"
+            ))),
+            _ => Ok("NOP".to_string()),
+        }
+    }
+
+    /// Check if peer is part of the network, all peers are authorized if network is not authenticated.
+    fn is_authorized(&self, peer: &PeerId) -> bool {
+        self.authentication
+            .as_ref()
+            .is_none_or(|authentication| authentication.is_authorized(peer))
     }
 
-    pub fn publish_changes(&mut self, chain: &Chain) -> Result<(), ProtocolError> {
-        if self.chain_contains_changes(chain) {
-            self.publish_block(&chain.blocks.last().unwrap())?;
+    /// Take token of the peer for received block, or return false if the peer exceeded block rate limit. Buckets of
+    /// disconnected peers are removed once there are too many of them.
+    fn take_block_token(&mut self, peer: &PeerId) -> bool {
+        let Some(limit) = self.block_rate else {
+            return true;
+        };
 
-            self.chain_count = chain.blocks.len();
+        if !self.block_limits.contains_key(peer) && self.block_limits.len() >= Self::MAX_RATE_LIMITED_PEERS {
+            let connected: Vec<PeerId> = self.network.connected_peers().cloned().collect();
+            self.block_limits.retain(|peer, _| connected.contains(peer));
         }
 
-        Ok(())
+        let (bucket, dropped) = self.block_limits.entry(*peer).or_insert_with(|| (limit.bucket(), 0));
+        if bucket.try_take() {
+            return true;
+        }
+
+        *dropped += 1;
+        false
     }
 
-    fn publish_block(&mut self, block: &Block) -> Result<(), ProtocolError> {
-        let topic = &self.topic;
+    /// Disconnect peer which is not part of authenticated network.
+    fn deny(&mut self, peer: PeerId) -> Result<String, ProtocolError> {
+        self.known_peers.remove(&peer);
+        self.network.behaviour_mut().channel.remove_explicit_peer(&peer);
+        let _ = self.network.disconnect_peer_id(peer);
+
+        Err(ProtocolError::NetworkError(format!("Peer {peer} is not authorized")))
+    }
+
+    /// Handle message received from peer. Malformed and invalid messages are rejected, so they are not propagated
+    /// further and their peers are down-scored. Valid messages which can't be applied (e.g. old blocks) are ignored.
+    fn handle_message(
+        &mut self,
+        chain: &mut Chain,
+        source: &PeerId,
+        topic: &TopicHash,
+        data: &[u8],
+    ) -> (MessageAcceptance, Result<String, ProtocolError>) {
+        if *topic != self.subscription.block_topic.hash()
+            && *topic != self.subscription.sync_topic.hash()
+            && *topic != self.subscription.vote_topic.hash()
+        {
+            return (
+                MessageAcceptance::Reject,
+                Err(ProtocolError::NetworkError(format!("Message from foreign topic {topic}"))),
+            );
+        }
+
+        let Ok(data) = Compression::decompress(data) else {
+            return Self::malformed_message();
+        };
+
+        if *topic == self.subscription.sync_topic.hash() {
+            return self.handle_sync_message(chain, source, &data);
+        }
+
+        if *topic == self.subscription.vote_topic.hash() {
+            return match WireFormat::decode::<CheckpointVote>(&data) {
+                Ok(vote) => Self::receive_vote(chain, vote),
+                Err(_) => Self::malformed_message(),
+            };
+        }
+
+        // Received new block
+        match WireFormat::decode::<Block>(&data) {
+            Ok(block) => self.receive_block(chain, source, block),
+            Err(_) => Self::malformed_message(),
+        }
+    }
+
+    fn handle_sync_message(&mut self, chain: &mut Chain, source: &PeerId, data: &[u8]) -> (MessageAcceptance, Result<String, ProtocolError>) {
+        let nop = (MessageAcceptance::Accept, Ok("NOP".to_string()));
+
+        // Received requested blocks from peer - whole chain is replaced, while suffix of chain is appended
+        if let Ok(segment) = WireFormat::decode::<ChainResponse>(data) {
+            return match self.is_response(&segment.to_peer, segment.request_id) {
+                true => self.receive_segment(chain, source, segment),
+                false => nop,
+            };
+        }
+
+        if let Ok(response) = WireFormat::decode::<HeadersResponse>(data) {
+            return match self.is_response(&response.to_peer, response.request_id) {
+                true => self.receive_headers(chain, source, response),
+                false => nop,
+            };
+        }
+
+        if let Ok(response) = WireFormat::decode::<BodiesResponse>(data) {
+            return match self.is_response(&response.to_peer, response.request_id) {
+                true => self.receive_bodies(chain, source, response),
+                false => nop,
+            };
+        }
+
+        // Got request from peer for blocks - usually on peer startup
+        if let Ok(chain_request) = WireFormat::decode::<ChainRequest>(data) {
+            if chain_request.from_peer != *self.network.local_peer_id() {
+                return nop;
+            }
+
+            let result = self.publish_segment(chain, &chain_request).map(|_| {
+                format!(
+                    "Chain published to peer {} from height {}",
+                    chain_request.to_peer, chain_request.from_height
+                )
+            });
+            return (MessageAcceptance::Accept, result);
+        }
+
+        Self::malformed_message()
+    }
+
+    /// Check if response is addressed to the local node and answers its last request.
+    fn is_response(&self, to_peer: &PeerId, request_id: u64) -> bool {
+        *self.network.local_peer_id() == *to_peer && self.subscription.last_request.is_some_and(|(id, ..)| id == request_id)
+    }
+
+    fn receive_segment(&mut self, chain: &mut Chain, source: &PeerId, segment: ChainResponse) -> (MessageAcceptance, Result<String, ProtocolError>) {
+        self.subscription.last_request = None;
+
+        // Peer has more blocks than fit into one segment
+        let is_full = segment.chain.len() == Self::MAX_SYNC_BLOCKS;
+        // Collected blocks of the fork are kept only while segments continuing them are received
+        let continues_fork = matches!(
+            (self.subscription.fork.last(), segment.chain.first()),
+            (Some(last), Some(first)) if first.id == last.id + 1
+        );
+        if !continues_fork {
+            self.subscription.fork.clear();
+        }
+
+        let result = match segment.chain.first() {
+            None => return (MessageAcceptance::Accept, Ok("NOP".to_string())),
+            Some(block) if block.id == 0 => chain.replace_chain(&segment.chain),
+            Some(_) if continues_fork => return self.receive_fork(chain, source, segment.chain, is_full),
+            Some(_) => chain.append_segment(&segment.chain),
+        };
+        match result {
+            Err(ChainError::BlockHasWrongPreviousHashValue(_) | ChainError::BlockIsNotNextBlockInSequence(_)) => {
+                self.receive_fork(chain, source, segment.chain, is_full)
+            }
+            Err(error) => (Self::acceptance(&error), Err(ProtocolError::ChainError(error))),
+            Ok(_) => (MessageAcceptance::Accept, self.complete_sync(chain, source, is_full)),
+        }
+    }
+
+    /// Recover from fork of the peer's chain. If blocks don't continue the local chain, blocks before them are
+    /// requested until the fork point is found. Blocks after the fork point are then collected, and local chain is
+    /// replaced from the fork point once they form longer chain.
+    fn receive_fork(
+        &mut self,
+        chain: &mut Chain,
+        source: &PeerId,
+        blocks: Vec<Block>,
+        is_full: bool,
+    ) -> (MessageAcceptance, Result<String, ProtocolError>) {
+        let blocks = [std::mem::take(&mut self.subscription.fork), blocks].concat();
+        let first_id = blocks[0].id;
+
+        let Some(fork_point) = chain.fork_point(&blocks) else {
+            // Chains differ already after the genesis block
+            if first_id <= 1 {
+                return (MessageAcceptance::Ignore, Ok("NOP".to_string()));
+            }
+
+            let from_height = first_id.saturating_sub(Self::MAX_SYNC_BLOCKS).max(1);
+            return (MessageAcceptance::Ignore, self.request_blocks(*source, from_height, SyncContent::Blocks));
+        };
+
+        let fork = &blocks[fork_point - first_id..];
+        if fork_point + fork.len() > chain.blocks.len() {
+            let candidate = [&chain.blocks[..fork_point], fork].concat();
+            return match chain.replace_chain(&candidate) {
+                Err(error) => (Self::acceptance(&error), Err(ProtocolError::ChainError(error))),
+                Ok(_) => (MessageAcceptance::Accept, self.complete_sync(chain, source, is_full)),
+            };
+        }
 
-        let block = serde_json::to_string(block).map_err(|error| ProtocolError::ParseError(error.to_string()))?;
+        // Peer's chain is not longer than the local one
+        if !is_full || fork.len() >= Self::MAX_FORK_BLOCKS {
+            return (MessageAcceptance::Ignore, Ok("NOP".to_string()));
+        }
+
+        let from_height = fork_point + fork.len();
+        self.subscription.fork = fork.to_vec();
+        (MessageAcceptance::Ignore, self.request_blocks(*source, from_height, SyncContent::Blocks))
+    }
+
+    /// Validate headers received from peer, and request data of their blocks.
+    fn receive_headers(
+        &mut self,
+        chain: &mut Chain,
+        source: &PeerId,
+        response: HeadersResponse,
+    ) -> (MessageAcceptance, Result<String, ProtocolError>) {
+        self.subscription.last_request = None;
+
+        if response.headers.is_empty() {
+            return (MessageAcceptance::Accept, Ok("NOP".to_string()));
+        }
+        match chain.validate_headers(&response.headers) {
+            // Blocks before the headers are requested, so the fork point can be found
+            Err(ChainError::BlockHasWrongPreviousHashValue(_) | ChainError::BlockIsNotNextBlockInSequence(_)) => {
+                let from_height = response.headers[0].id.saturating_sub(Self::MAX_SYNC_BLOCKS).max(1);
+                return (MessageAcceptance::Ignore, self.request_blocks(*source, from_height, SyncContent::Blocks));
+            }
+            Err(error) => return (Self::acceptance(&error), Err(ProtocolError::ChainError(error))),
+            Ok(_) => {}
+        }
+
+        self.subscription.headers = response.headers;
+        (MessageAcceptance::Accept, self.request_chain(chain, *source))
+    }
+
+    /// Create blocks from received data and their validated headers, and append them to the chain.
+    fn receive_bodies(&mut self, chain: &mut Chain, source: &PeerId, response: BodiesResponse) -> (MessageAcceptance, Result<String, ProtocolError>) {
+        self.subscription.last_request = None;
+
+        // Headers were replaced or chain was changed by other blocks in the meantime
+        if self.subscription.headers.first().is_none_or(|header| header.id != response.from_height) || response.bodies.is_empty() {
+            return (MessageAcceptance::Ignore, Ok("NOP".to_string()));
+        }
 
-        self.network
-            .behaviour_mut()
-            .channel
-            .publish(topic.clone(), block.as_bytes())
-            .map_err(|error| ProtocolError::PublishingError(error.to_string()))?;
+        let count = response.bodies.len().min(self.subscription.headers.len());
+        let blocks: Vec<Block> = self
+            .subscription
+            .headers
+            .drain(..count)
+            .zip(response.bodies)
+            .map(|(header, data)| Block::from_header(header, data))
+            .collect();
+
+        if let Err(error) = chain.append_segment(&blocks) {
+            self.subscription.headers.clear();
+            return (Self::acceptance(&error), Err(ProtocolError::ChainError(error)));
+        }
+
+        // Remaining data, or headers after the received ones, are requested until peer has no more blocks
+        (MessageAcceptance::Accept, self.complete_sync(chain, source, true))
+    }
+
+    /// Add pending blocks which follow synced chain, and request next blocks from peer if it has more of them.
+    fn complete_sync(&mut self, chain: &mut Chain, source: &PeerId, has_more: bool) -> Result<String, ProtocolError> {
+        let mut messages = vec![format!("Chain synced to height {}", chain.blocks.len())];
+        self.add_pending_blocks(chain, &mut messages)?;
+
+        if has_more {
+            messages.push(self.request_chain(chain, *source)?);
+        }
+
+        Ok(messages.join("\n"))
+    }
+
+    fn malformed_message() -> (MessageAcceptance, Result<String, ProtocolError>) {
+        (
+            MessageAcceptance::Reject,
+            Err(ProtocolError::ParseError("Message from peer is malformed".to_string())),
+        )
+    }
+
+    /// Add received block to the chain and relay it to peers. Blocks can be received out of order (e.g. block before
+    /// its reward block), so blocks after the next one are kept until missing blocks are received, and missing blocks
+    /// are requested from the peer which relayed the block (only if they are within `PENDING_BLOCKS_WINDOW` blocks from
+    /// the local chain). Blocks which were already handled, or which are not after the local chain, are ignored without
+    /// validation.
+    fn receive_block(&mut self, chain: &mut Chain, source: &PeerId, block: Block) -> (MessageAcceptance, Result<String, ProtocolError>) {
+        if block.id < chain.blocks.len() || self.subscription.seen_blocks.contains(&block.hash) {
+            return (MessageAcceptance::Ignore, Ok("NOP".to_string()));
+        }
+
+        if block.id > chain.blocks.len() {
+            if block.id - chain.blocks.len() <= Self::PENDING_BLOCKS_WINDOW {
+                self.subscription.pending_blocks.insert(block.id, block);
+            }
+
+            return (MessageAcceptance::Ignore, self.request_chain(chain, *source));
+        }
+
+        // Invalid blocks are not marked as seen, as forged block could have hash of the real block which is received later
+        if let Err(error) = chain.add_new_block(block.clone()) {
+            return (Self::acceptance(&error), Ok("NOP".to_string()));
+        }
+        self.subscription.seen_blocks.insert(block.hash.clone());
+
+        // Relaying block
+        let mut messages = vec![format!("Block added to chain {}", serde_json::to_string(&block).unwrap())];
+        let result = self
+            .publish_block(&block)
+            .and_then(|_| self.add_pending_blocks(chain, &mut messages))
+            .map(|_| messages.join("\n"));
+
+        (MessageAcceptance::Accept, result)
+    }
+
+    /// Count checkpoint vote received from peer, votes which were already counted (or can't be counted yet) are not
+    /// relayed further. Only votes with invalid signature are rejected, as honest validators could vote for checkpoint
+    /// (or with stake) which isn't known locally yet.
+    fn receive_vote(chain: &mut Chain, vote: CheckpointVote) -> (MessageAcceptance, Result<String, ProtocolError>) {
+        match chain.add_checkpoint_vote(&vote) {
+            Err(error @ ChainError::CheckpointVoteSignatureIsNotValid(_)) => (MessageAcceptance::Reject, Err(ProtocolError::ChainError(error))),
+            Err(error) => (MessageAcceptance::Ignore, Err(ProtocolError::ChainError(error))),
+            Ok(false) => (MessageAcceptance::Ignore, Ok("NOP".to_string())),
+            Ok(true) => match chain.finality.finalized() {
+                Some((block_id, _)) if block_id == vote.block_id => (MessageAcceptance::Accept, Ok(format!("Checkpoint {block_id} finalized"))),
+                _ => (MessageAcceptance::Accept, Ok("NOP".to_string())),
+            },
+        }
+    }
+
+    /// Add pending blocks which follow the local chain and relay them, pending blocks could be sent by other peers so
+    /// they are not reported.
+    fn add_pending_blocks(&mut self, chain: &mut Chain, messages: &mut Vec<String>) -> Result<(), ProtocolError> {
+        while let Some(block) = self.subscription.pending_blocks.remove(&chain.blocks.len()) {
+            if chain.add_new_block(block.clone()).is_err() {
+                break;
+            }
+
+            self.publish_block(&block)?;
+            messages.push(format!("Block added to chain {}", serde_json::to_string(&block).unwrap()));
+        }
+
+        self.subscription.pending_blocks.retain(|id, _| *id >= chain.blocks.len());
 
         Ok(())
     }
 
-    fn publish_chain(&mut self, chain: &Chain) -> Result<(), ProtocolError> {
-        let topic = &self.topic;
-        let blockchain = serde_json::to_string(&chain.blocks).map_err(|error| ProtocolError::ParseError(error.to_string()))?;
+    /// Request blocks after the local chain from given peer. Blocks of the same height are requested only once, unless
+    /// peer didn't respond in time. In headers mode, data of blocks is requested if their headers were already
+    /// received.
+    fn request_chain(&mut self, chain: &Chain, peer: PeerId) -> Result<String, ProtocolError> {
+        let from_height = chain.blocks.len();
+
+        // Headers which don't follow the local chain anymore are requested again
+        if self.subscription.headers.first().is_some_and(|header| header.id != from_height) {
+            self.subscription.headers.clear();
+        }
+
+        let content = match (self.sync_mode, self.subscription.headers.is_empty()) {
+            (SyncMode::Full, _) => SyncContent::Blocks,
+            (SyncMode::Headers, true) => SyncContent::Headers,
+            (SyncMode::Headers, false) => SyncContent::Bodies,
+        };
+
+        self.request_blocks(peer, from_height, content)
+    }
+
+    /// Request content of peer's chain from given height, unless it was already requested and peer can still respond.
+    fn request_blocks(&mut self, peer: PeerId, from_height: usize, content: SyncContent) -> Result<String, ProtocolError> {
+        if self
+            .subscription
+            .last_request
+            .is_some_and(|(_, height, requested_at)| height == from_height && requested_at.elapsed() < Self::SYNC_TIMEOUT)
+        {
+            return Ok("NOP".to_string());
+        }
+
+        let request = ChainRequest {
+            from_peer: peer,
+            to_peer: *self.network.local_peer_id(),
+            from_height,
+            content,
+            request_id: rand::random(),
+        };
+        self.publish_sync_message(&request)?;
+        self.subscription.last_request = Some((request.request_id, from_height, Instant::now()));
+
+        Ok(format!("Chain {content:?} requested from peer {peer} from height {from_height}"))
+    }
+
+    /// Only blocks whose content is invalid on its own (e.g. wrong hash or signature) are rejected. All other errors
+    /// depend on state of the local chain (e.g. fork, relay of old block, or stake not synced yet), so they could be
+    /// caused by honest peers.
+    fn acceptance(error: &ChainError) -> MessageAcceptance {
+        match error {
+            ChainError::BlockHasInsufficientWork(_)
+            | ChainError::BlockHasUnsupportedFormatVersion(_)
+            | ChainError::BlockHasWrongHashValue(_)
+            | ChainError::BlockHasWrongSignatureValue(_)
+            | ChainError::ChainHasInvalidGenesisBlock => MessageAcceptance::Reject,
+            _ => MessageAcceptance::Ignore,
+        }
+    }
+
+    /// Report validation result of message to peer scoring, and ban peer whose score dropped below ban threshold.
+    fn report_message(&mut self, message_id: &MessageId, source: &PeerId, acceptance: MessageAcceptance) {
+        let channel = &mut self.network.behaviour_mut().channel;
+        // Message is not in cache anymore if validation took too long, so it's not propagated anyway
+        let _ = channel.report_message_validation_result(message_id, source, acceptance);
+
+        if channel.peer_score(source).is_some_and(|score| score < Network::BAN_THRESHOLD) {
+            eprintln!("Peer {source} banned for sending invalid messages");
+            self.known_peers.remove(source);
+            self.network.behaviour_mut().ban(*source);
+        }
+    }
+
+    /// Check if there are queued blocks or vote of local validator to publish, so chain is locked for writing only when
+    /// publishing is needed.
+    pub fn has_changes(&self, chain: &Chain) -> bool {
+        let subscription = chain
+            .chain_id()
+            .and_then(|chain_id| self.subscriptions.get(chain_id))
+            .unwrap_or(&self.subscription);
+
+        !chain.outbound.is_empty() || chain.is_vote_due() || chain.finality.local_vote() != subscription.published_vote.as_ref()
+    }
+
+    /// Publish queued blocks produced by local validator, and vote of local validator for the latest checkpoint. After
+    /// publishing fails, it's retried with exponential backoff (or as soon as peer subscribes to the chain).
+    pub fn publish_changes(&mut self, chain: &mut Chain) -> Result<(), ProtocolError> {
+        self.activate(chain);
+        if !self.subscription.publish_backoff.is_due() {
+            return Ok(());
+        }
+
+        match self.publish_queued(chain) {
+            Err(ProtocolError::PublishingError(error)) => {
+                let delay = self.subscription.publish_backoff.fail();
+                Err(ProtocolError::PublishingError(format!(
+                    "{error}, retrying in {} seconds",
+                    delay.as_secs()
+                )))
+            }
+            result => {
+                self.subscription.publish_backoff.reset();
+                result
+            }
+        }
+    }
+
+    fn publish_queued(&mut self, chain: &mut Chain) -> Result<(), ProtocolError> {
+        // Blocks are removed from the queue only when they are published, so they are published again after failure
+        // (e.g. when no peers are connected yet)
+        while let Some(block) = chain.outbound.front() {
+            // Blocks abandoned by fork are not valid anymore
+            if chain.blocks.get(block.id) == Some(block) {
+                self.publish_block(block)?;
+            }
+            chain.outbound.pop_front();
+        }
 
-        self.network
-            .behaviour_mut()
-            .channel
-            .publish(topic.clone(), blockchain.as_bytes())
-            .map_err(|error| ProtocolError::PublishingError(error.to_string()))?;
+        let vote = chain.checkpoint_vote().map_err(ProtocolError::ChainError)?;
+        if let Some(vote) = vote.filter(|vote| self.subscription.published_vote.as_ref() != Some(vote)) {
+            let message = self.encode(&vote)?;
+            match self
+                .network
+                .behaviour_mut()
+                .channel
+                .publish(self.subscription.vote_topic.clone(), message)
+            {
+                Ok(_) | Err(PublishError::Duplicate) => self.subscription.published_vote = Some(vote),
+                Err(error) => return Err(ProtocolError::PublishingError(error.to_string())),
+            }
+        }
 
         Ok(())
     }
+
+    fn publish_block(&mut self, block: &Block) -> Result<(), ProtocolError> {
+        let topic = &self.subscription.block_topic;
+
+        let block = self.encode(block)?;
+
+        match self.network.behaviour_mut().channel.publish(topic.clone(), block) {
+            // Block received from peer is already known to the network
+            Ok(_) | Err(PublishError::Duplicate) => Ok(()),
+            Err(error) => Err(ProtocolError::PublishingError(error.to_string())),
+        }
+    }
+
+    /// Publish requested content of the local chain starting at given height, at most `MAX_SYNC_BLOCKS` blocks (or
+    /// `MAX_SYNC_HEADERS` headers) per segment.
+    fn publish_segment(&mut self, chain: &Chain, request: &ChainRequest) -> Result<(), ProtocolError> {
+        let limit = match request.content {
+            SyncContent::Headers => Self::MAX_SYNC_HEADERS,
+            _ => Self::MAX_SYNC_BLOCKS,
+        };
+        let from_height = request.from_height.min(chain.blocks.len());
+        let blocks = &chain.blocks[from_height..chain.blocks.len().min(from_height + limit)];
+        let (to_peer, request_id) = (request.to_peer, request.request_id);
+
+        match request.content {
+            SyncContent::Blocks => self.publish_sync_message(&ChainResponse {
+                chain: blocks.to_vec(),
+                candidates: vec![],
+                to_peer,
+                request_id,
+            }),
+            SyncContent::Headers => self.publish_sync_message(&HeadersResponse {
+                headers: blocks.iter().map(Block::header).collect(),
+                to_peer,
+                request_id,
+            }),
+            SyncContent::Bodies => self.publish_sync_message(&BodiesResponse {
+                from_height,
+                bodies: blocks.iter().map(|block| block.data.clone()).collect(),
+                to_peer,
+                request_id,
+            }),
+        }
+    }
+
+    fn publish_sync_message<T: Serialize>(&mut self, message: &T) -> Result<(), ProtocolError> {
+        let topic = &self.subscription.sync_topic;
+        let message = self.encode(message)?;
+
+        match self.network.behaviour_mut().channel.publish(topic.clone(), message) {
+            Ok(_) | Err(PublishError::Duplicate) => Ok(()),
+            Err(error) => Err(ProtocolError::PublishingError(error.to_string())),
+        }
+    }
+
+    fn encode<T: Serialize>(&self, message: &T) -> Result<Vec<u8>, ProtocolError> {
+        self.compression.compress(self.wire_format.encode(message)?)
+    }
 }
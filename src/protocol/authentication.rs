@@ -0,0 +1,123 @@
+use crate::protocol::error::ProtocolError;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use libp2p::PeerId;
+use rustc_hash::FxHashSet;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Authenticated mode of private network - only peers from allowlist (`--allowed-peers="<peer_id>,<peer_id>"`), or
+/// peers with token issued by network authority (`--network-authority="<public_key>"`) are accepted. Token is signature
+/// of prefixed peer id by authority wallet (issued by `wallet issue token <peer_id>` command), and peer presents it in
+/// identify protocol (`--network-token="<token>"`). Other peers are disconnected and their messages are rejected.
+pub struct Authentication {
+    allowed_peers: FxHashSet<PeerId>,
+    authority: Option<VerifyingKey>,
+    authorized_peers: FxHashSet<PeerId>,
+}
+
+impl Authentication {
+    pub const TOKEN_PREFIX: &'static str = "weighted-graph/token/";
+    /// Signed message is prefixed, so signature of the authority for other purpose (e.g. client token) isn't a token
+    const MESSAGE_PREFIX: &'static str = "weighted-graph/network-token/";
+
+    pub fn new(allowed_peers: FxHashSet<PeerId>, authority: Option<VerifyingKey>) -> Authentication {
+        Authentication {
+            allowed_peers,
+            authority,
+            authorized_peers: FxHashSet::default(),
+        }
+    }
+
+    /// Create authentication from command line arguments, or return `None` if network is not authenticated.
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<Option<Authentication>, ProtocolError> {
+        let argument = |name: &str| arguments.get(name).and_then(|values| values.first());
+
+        let allowed_peers = match argument("allowed-peers") {
+            Some(peers) => peers
+                .split(',')
+                .map(|peer| PeerId::from_str(peer.trim()).map_err(|_| ProtocolError::ConfigurationError(format!("allowed-peers={peer}"))))
+                .collect::<Result<FxHashSet<PeerId>, ProtocolError>>()?,
+            None => FxHashSet::default(),
+        };
+
+        let authority = argument("network-authority")
+            .map(|public_key| {
+                hex::decode(public_key)
+                    .ok()
+                    .and_then(|key| <[u8; 32]>::try_from(key).ok())
+                    .and_then(|key| VerifyingKey::from_bytes(&key).ok())
+                    .ok_or(ProtocolError::ConfigurationError(format!("network-authority={public_key}")))
+            })
+            .transpose()?;
+
+        match allowed_peers.is_empty() && authority.is_none() {
+            true => Ok(None),
+            false => Ok(Some(Authentication::new(allowed_peers, authority))),
+        }
+    }
+
+    /// Agent version announced by identify protocol, which carries token of the local node.
+    pub fn agent_version(token: &str) -> String {
+        format!("{}{token}", Self::TOKEN_PREFIX)
+    }
+
+    /// Message which is signed by authority as token of given peer.
+    pub fn token_message(peer: &PeerId) -> String {
+        format!("{}{peer}", Self::MESSAGE_PREFIX)
+    }
+
+    pub fn is_authorized(&self, peer: &PeerId) -> bool {
+        self.allowed_peers.contains(peer) || self.authorized_peers.contains(peer)
+    }
+
+    /// Authorize peer by token from agent version it announced, and return whether peer is authorized.
+    pub fn authorize(&mut self, peer: &PeerId, agent_version: &str) -> bool {
+        let is_valid = self.authority.as_ref().is_some_and(|authority| {
+            agent_version
+                .strip_prefix(Self::TOKEN_PREFIX)
+                .and_then(|token| Signature::from_str(token).ok())
+                .is_some_and(|signature| authority.verify(Self::token_message(peer).as_bytes(), &signature).is_ok())
+        });
+
+        if is_valid {
+            self.authorized_peers.insert(*peer);
+        }
+
+        self.is_authorized(peer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::wallet::Wallet;
+
+    #[test]
+    fn should_authorize_peers_by_allowlist_and_token() {
+        // Given
+        let mut authority = Wallet::default();
+        let (allowed, with_token, unknown) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let token = authority.sign(&Authentication::token_message(&with_token));
+        let unprefixed = authority.sign(&with_token.to_string());
+
+        let mut arguments = HashMap::new();
+        arguments.insert("allowed-peers".to_string(), vec![allowed.to_string()]);
+        arguments.insert("network-authority".to_string(), vec![authority.get_public_key()]);
+        let mut authentication = Authentication::from_arguments(&arguments).unwrap().unwrap();
+
+        // When
+        let is_unknown_authorized = authentication.authorize(&unknown, &Authentication::agent_version(&token));
+        let is_unprefixed_authorized =
+            Authentication::new(FxHashSet::default(), authentication.authority).authorize(&with_token, &Authentication::agent_version(&unprefixed));
+        let is_authorized = authentication.authorize(&with_token, &Authentication::agent_version(&token));
+
+        // Then
+        assert!(authentication.is_authorized(&allowed));
+        assert!(is_authorized);
+        assert!(authentication.is_authorized(&with_token));
+        assert!(!is_unknown_authorized);
+        assert!(!is_unprefixed_authorized);
+        assert!(!authentication.authorize(&unknown, "rust-libp2p/0.45.1"));
+        assert!(Authentication::from_arguments(&HashMap::new()).unwrap().is_none());
+    }
+}
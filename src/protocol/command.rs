@@ -1,10 +1,34 @@
-use crate::chain::block::Block;
+use crate::chain::block::{Block, BlockData, BlockHeader};
+use crate::protocol::error::ProtocolError;
 use libp2p::PeerId;
+use rustc_hash::FxHashMap;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Read;
 
+/// Request for blocks of peer's chain starting at given height, so peer which was offline fetches only the missing
+/// suffix of the chain (or whole chain from height 0). Headers and data of blocks can be requested separately.
+/// Request id is random and repeated in response, so repeated requests (and their responses) are not dropped as
+/// duplicate messages, and responses to older requests can be recognized.
 #[derive(Serialize, Deserialize)]
 pub struct ChainRequest {
     pub from_peer: PeerId,
+    pub to_peer: PeerId,
+    pub from_height: usize,
+    #[serde(default)]
+    pub content: SyncContent,
+    #[serde(default)]
+    pub request_id: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum SyncContent {
+    #[default]
+    Blocks,
+    Headers,
+    Bodies,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -12,4 +36,202 @@ pub struct ChainResponse {
     pub chain: Vec<Block>,
     pub candidates: Vec<Block>,
     pub to_peer: PeerId,
+    #[serde(default)]
+    pub request_id: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HeadersResponse {
+    pub headers: Vec<BlockHeader>,
+    pub to_peer: PeerId,
+    #[serde(default)]
+    pub request_id: u64,
+}
+
+/// Data of blocks starting at given height, whose headers were already received.
+#[derive(Serialize, Deserialize)]
+pub struct BodiesResponse {
+    pub from_height: usize,
+    pub bodies: Vec<BlockData>,
+    pub to_peer: PeerId,
+    #[serde(default)]
+    pub request_id: u64,
+}
+
+/// Read-only command forwarded to peer (`fetch ... on peer <peer_id>`), e.g. when peers hold different partitions of
+/// the graph. Queries are sent directly to the peer by request-response protocol, instead of being published to topic.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QueryRequest {
+    pub command: String,
+}
+
+/// Result rows of forwarded command, or code and message of its error.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QueryResponse {
+    pub rows: Vec<FxHashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<(String, String)>,
+}
+
+/// Synchronization of missing blocks - full blocks are requested by default, while with headers mode
+/// (`--sync-mode="headers"`) headers are requested and validated first, and data of blocks is fetched in batches.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum SyncMode {
+    #[default]
+    Full,
+    Headers,
+}
+
+impl SyncMode {
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<SyncMode, ProtocolError> {
+        match arguments.get("sync-mode").and_then(|values| values.first()).map(|value| value.as_str()) {
+            None | Some("full") => Ok(SyncMode::Full),
+            Some("headers") => Ok(SyncMode::Headers),
+            Some(value) => Err(ProtocolError::ConfigurationError(format!("sync-mode={value}"))),
+        }
+    }
+}
+
+/// Encoding of messages published to peers. Binary messages start with format version byte followed by MessagePack
+/// data (with field names, so optional block fields can still be skipped), while JSON messages start with `{` or `[`.
+/// Both formats are always accepted, so peers using JSON (`--wire-format="json"`) can still sync with the others.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum WireFormat {
+    #[default]
+    Binary,
+    Json,
+}
+
+impl WireFormat {
+    pub const BINARY_FORMAT_VERSION: u8 = 1;
+
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<WireFormat, ProtocolError> {
+        match arguments.get("wire-format").and_then(|values| values.first()).map(|value| value.as_str()) {
+            None | Some("binary") => Ok(WireFormat::Binary),
+            Some("json") => Ok(WireFormat::Json),
+            Some(value) => Err(ProtocolError::ConfigurationError(format!("wire-format={value}"))),
+        }
+    }
+
+    pub fn encode<T: Serialize>(&self, message: &T) -> Result<Vec<u8>, ProtocolError> {
+        match self {
+            WireFormat::Binary => {
+                let mut data = vec![Self::BINARY_FORMAT_VERSION];
+                rmp_serde::encode::write_named(&mut data, message).map_err(|error| ProtocolError::ParseError(error.to_string()))?;
+
+                Ok(data)
+            }
+            WireFormat::Json => serde_json::to_vec(message).map_err(|error| ProtocolError::ParseError(error.to_string())),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, ProtocolError> {
+        match data.split_first() {
+            Some((&Self::BINARY_FORMAT_VERSION, message)) => {
+                rmp_serde::from_slice(message).map_err(|error| ProtocolError::ParseError(error.to_string()))
+            }
+            _ => serde_json::from_slice(data).map_err(|error| ProtocolError::ParseError(error.to_string())),
+        }
+    }
+}
+
+/// Compression of large messages (`--compression="none"` to disable it). Compressed messages start with compression
+/// flag byte followed by zstd data of encoded message, so peers always accept both compressed and plain messages.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Compression {
+    None,
+    #[default]
+    Zstd,
+}
+
+impl Compression {
+    pub const COMPRESSED_FORMAT_FLAG: u8 = 0x80;
+    /// Smaller messages (e.g. single edge block) are not worth compressing
+    pub const COMPRESSION_THRESHOLD: usize = 1024;
+    pub const MAX_DECOMPRESSED_SIZE: u64 = 16 * 1024 * 1024;
+
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<Compression, ProtocolError> {
+        match arguments.get("compression").and_then(|values| values.first()).map(|value| value.as_str()) {
+            None | Some("zstd") => Ok(Compression::Zstd),
+            Some("none") => Ok(Compression::None),
+            Some(value) => Err(ProtocolError::ConfigurationError(format!("compression={value}"))),
+        }
+    }
+
+    /// Compress encoded message if it's large enough and compression makes it smaller.
+    pub fn compress(&self, data: Vec<u8>) -> Result<Vec<u8>, ProtocolError> {
+        if *self == Compression::None || data.len() < Self::COMPRESSION_THRESHOLD {
+            return Ok(data);
+        }
+
+        let mut compressed = vec![Self::COMPRESSED_FORMAT_FLAG];
+        zstd::stream::copy_encode(data.as_slice(), &mut compressed, 0).map_err(|error| ProtocolError::ParseError(error.to_string()))?;
+
+        match compressed.len() < data.len() {
+            true => Ok(compressed),
+            false => Ok(data),
+        }
+    }
+
+    /// Decompress message if it's compressed, decompressed size is limited so small message can't exhaust memory.
+    pub fn decompress(data: &[u8]) -> Result<Cow<'_, [u8]>, ProtocolError> {
+        let Some((&Self::COMPRESSED_FORMAT_FLAG, compressed)) = data.split_first() else {
+            return Ok(Cow::Borrowed(data));
+        };
+
+        let mut message = vec![];
+        zstd::stream::read::Decoder::new(compressed)
+            .and_then(|decoder| decoder.take(Self::MAX_DECOMPRESSED_SIZE + 1).read_to_end(&mut message))
+            .map_err(|error| ProtocolError::ParseError(error.to_string()))?;
+
+        if message.len() as u64 > Self::MAX_DECOMPRESSED_SIZE {
+            return Err(ProtocolError::ParseError("Decompressed message is too large".to_string()));
+        }
+
+        Ok(Cow::Owned(message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_decode_binary_and_json_messages() {
+        // Given
+        let block = Block::default();
+
+        // When
+        let binary = WireFormat::Binary.encode(&block).unwrap();
+        let json = WireFormat::Json.encode(&block).unwrap();
+
+        // Then
+        assert_eq!(binary[0], WireFormat::BINARY_FORMAT_VERSION);
+        assert!(binary.len() < json.len());
+        assert_eq!(WireFormat::decode::<Block>(&binary).unwrap().hash, block.hash);
+        assert_eq!(WireFormat::decode::<Block>(&json).unwrap().hash, block.hash);
+        assert!(WireFormat::decode::<ChainRequest>(&binary).is_err());
+    }
+
+    #[test]
+    fn should_compress_large_messages() {
+        // Given
+        let blocks = vec![Block::default(); 20];
+        let block = WireFormat::Binary.encode(&Block::default()).unwrap();
+        let chain = WireFormat::Binary.encode(&blocks).unwrap();
+
+        // When
+        let compressed_block = Compression::Zstd.compress(block.clone()).unwrap();
+        let compressed_chain = Compression::Zstd.compress(chain.clone()).unwrap();
+        let plain_chain = Compression::None.compress(chain.clone()).unwrap();
+
+        // Then
+        assert_eq!(compressed_block, block);
+        assert_eq!(compressed_chain[0], Compression::COMPRESSED_FORMAT_FLAG);
+        assert!(compressed_chain.len() < chain.len());
+        assert_eq!(plain_chain, chain);
+        assert_eq!(Compression::decompress(&compressed_chain).unwrap(), chain);
+        assert_eq!(Compression::decompress(&block).unwrap(), block);
+        assert!(Compression::decompress(&[Compression::COMPRESSED_FORMAT_FLAG, 1, 2, 3]).is_err());
+    }
 }
@@ -1,11 +1,15 @@
 use crate::chain::error::ChainError;
-use std::fmt::Display;
+use std::fmt::{Debug, Display};
 
+#[allow(clippy::enum_variant_names)]
 pub enum ProtocolError {
     NetworkError(String),
     PublishingError(String),
     ParseError(String),
     ChainError(ChainError),
+    ConfigurationError(String),
+    IdentityError(String, String),
+    RateLimitExceeded(String),
 }
 
 impl Display for ProtocolError {
@@ -23,6 +27,21 @@ impl Display for ProtocolError {
             ProtocolError::ChainError(error) => {
                 write!(formatter, "There was an error with the chain: {error}")
             }
+            ProtocolError::ConfigurationError(value) => {
+                write!(formatter, "Protocol configuration {value} is not valid")
+            }
+            ProtocolError::IdentityError(path, error) => {
+                write!(formatter, "Network identity file {path} can't be used: {error}")
+            }
+            ProtocolError::RateLimitExceeded(peer) => {
+                write!(formatter, "Peer {peer} exceeded block rate limit, its block was dropped")
+            }
         }
     }
 }
+
+impl Debug for ProtocolError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, formatter)
+    }
+}
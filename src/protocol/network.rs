@@ -1,43 +1,356 @@
-use libp2p::{gossipsub, mdns, noise, swarm::NetworkBehaviour, tcp, yamux, Swarm, SwarmBuilder};
+use crate::protocol::authentication::Authentication;
+use crate::protocol::command::{QueryRequest, QueryResponse};
+use crate::protocol::error::ProtocolError;
+use libp2p::allow_block_list::{Behaviour as BlockList, BlockedPeers};
+use libp2p::core::transport::OptionalTransport;
+use libp2p::gossipsub::{PeerScoreParams, PeerScoreThresholds, TopicScoreParams};
+use libp2p::identity::Keypair;
+use libp2p::multiaddr::Protocol;
+use libp2p::request_response::ProtocolSupport;
+use libp2p::swarm::behaviour::toggle::Toggle;
+use libp2p::{
+    dcutr, gossipsub, identify, mdns, noise, quic, relay, request_response, swarm::NetworkBehaviour, tcp, yamux, Multiaddr, PeerId, StreamProtocol,
+    Swarm, SwarmBuilder,
+};
+use std::collections::HashMap;
 use std::error::Error;
+use std::fs;
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::option::Option;
+use std::path::Path;
 use std::time::Duration;
 use tokio::io;
 
 #[derive(NetworkBehaviour)]
 pub struct Network {
     pub channel: gossipsub::Behaviour,
-    pub address_resolver: mdns::tokio::Behaviour,
+    pub address_resolver: Toggle<mdns::tokio::Behaviour>,
+    pub banned_peers: BlockList<BlockedPeers>,
+    pub identify: identify::Behaviour,
+    pub relay_client: relay::client::Behaviour,
+    pub hole_punching: dcutr::Behaviour,
+    pub queries: request_response::json::Behaviour<QueryRequest, QueryResponse>,
+}
+
+/// Addresses and transports of the node: `--listen="/ip4/0.0.0.0/tcp/4001,/ip6/::/tcp/4001"` for listen addresses,
+/// `--quic` to enable QUIC transport and `--external="/ip4/203.0.113.1/tcp/4001"` for addresses announced to peers
+/// (e.g. port forwarded on firewall). By default node listens on random TCP port (and random QUIC port with `--quic`).
+/// Node behind NAT can also listen through relay servers (`--relay="/ip4/198.51.100.1/tcp/4001/p2p/<peer_id>"`).
+/// Network identity (peer id) is kept in `--identity-file="identity.key"`, or in identity.key file next to wallet key
+/// file, otherwise new identity is used on each start. Token for authenticated network is given by `--network-token`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct NetworkConfig {
+    pub listen_addresses: Vec<Multiaddr>,
+    pub quic: bool,
+    pub external_addresses: Vec<Multiaddr>,
+    pub relay_addresses: Vec<Multiaddr>,
+    pub identity_file: Option<String>,
+    pub token: Option<String>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            listen_addresses: vec![Multiaddr::empty().with(Protocol::Ip4([0, 0, 0, 0].into())).with(Protocol::Tcp(0))],
+            quic: false,
+            external_addresses: vec![],
+            relay_addresses: vec![],
+            identity_file: None,
+            token: None,
+        }
+    }
+}
+
+impl NetworkConfig {
+    const IDENTITY_FILE: &'static str = "identity.key";
+
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<NetworkConfig, ProtocolError> {
+        let quic = arguments.contains_key("quic");
+        let mut config = NetworkConfig {
+            quic,
+            ..NetworkConfig::default()
+        };
+
+        if let Some(addresses) = Self::addresses(arguments, "listen")? {
+            config.listen_addresses = addresses;
+        } else if quic {
+            config.listen_addresses.push(
+                Multiaddr::empty()
+                    .with(Protocol::Ip4([0, 0, 0, 0].into()))
+                    .with(Protocol::Udp(0))
+                    .with(Protocol::QuicV1),
+            );
+        }
+        config.external_addresses = Self::addresses(arguments, "external")?.unwrap_or_default();
+        config.relay_addresses = Self::addresses(arguments, "relay")?.unwrap_or_default();
+
+        let argument = |name: &str| arguments.get(name).and_then(|values| values.first());
+        config.identity_file = argument("identity-file")
+            .cloned()
+            .or_else(|| argument("key-file").map(|key_file| Path::new(key_file).with_file_name(Self::IDENTITY_FILE).to_string_lossy().to_string()));
+
+        if !quic
+            && config
+                .listen_addresses
+                .iter()
+                .any(|address| address.iter().any(|protocol| protocol == Protocol::QuicV1))
+        {
+            return Err(ProtocolError::ConfigurationError("listen (QUIC address requires --quic)".to_string()));
+        }
+
+        // Relay reservation is made with known relay peer
+        if let Some(address) = config
+            .relay_addresses
+            .iter()
+            .find(|address| !matches!(address.iter().last(), Some(Protocol::P2p(_))))
+        {
+            return Err(ProtocolError::ConfigurationError(format!(
+                "relay={address} (address must end with relay peer id)"
+            )));
+        }
+
+        Ok(config)
+    }
+
+    fn addresses(arguments: &HashMap<String, Vec<String>>, key: &str) -> Result<Option<Vec<Multiaddr>>, ProtocolError> {
+        let Some(value) = arguments.get(key).and_then(|values| values.first()) else {
+            return Ok(None);
+        };
+
+        value
+            .split(',')
+            .map(|address| {
+                address
+                    .trim()
+                    .parse()
+                    .map_err(|_| ProtocolError::ConfigurationError(format!("{key}={address}")))
+            })
+            .collect::<Result<Vec<Multiaddr>, ProtocolError>>()
+            .map(Some)
+    }
 }
 
 impl Network {
-    pub fn init() -> Result<Swarm<Network>, Box<dyn Error>> {
-        let mut swarm = SwarmBuilder::with_new_identity()
+    /// Peers with lower score are banned - with topic score, third invalid message in short time is enough.
+    pub const BAN_THRESHOLD: f64 = -80.0;
+    pub const IDENTIFY_PROTOCOL: &'static str = "/weighted-graph/1.0.0";
+    pub const QUERY_PROTOCOL: StreamProtocol = StreamProtocol::new("/weighted-graph/query/1.0.0");
+
+    pub fn init(config: &NetworkConfig) -> Result<Swarm<Network>, Box<dyn Error>> {
+        let identity = match &config.identity_file {
+            Some(path) => Self::load_or_create_identity(path).map_err(|error| error.to_string())?,
+            None => Keypair::generate_ed25519(),
+        };
+
+        let mut swarm = SwarmBuilder::with_existing_identity(identity)
             .with_tokio()
             .with_tcp(tcp::Config::default(), noise::Config::new, yamux::Config::default)?
-            .with_behaviour(|key| {
-                let gossip_config = gossipsub::ConfigBuilder::default()
-                    .heartbeat_interval(Duration::from_secs(10))
-                    .validation_mode(gossipsub::ValidationMode::Strict)
-                    .message_id_fn(|message| {
-                        let mut hasher = DefaultHasher::new();
-                        message.data.hash(&mut hasher);
-                        gossipsub::MessageId::from(hasher.finish().to_string())
-                    })
-                    .build()
-                    .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
-
-                Ok(Network {
-                    channel: gossipsub::Behaviour::new(gossipsub::MessageAuthenticity::Signed(key.clone()), gossip_config)?,
-                    address_resolver: mdns::tokio::Behaviour::new(mdns::Config::default(), key.public().to_peer_id())?,
-                })
+            .with_other_transport(|key| match config.quic {
+                true => OptionalTransport::some(quic::tokio::Transport::new(quic::Config::new(key))),
+                false => OptionalTransport::none(),
+            })?
+            .with_relay_client(noise::Config::new, yamux::Config::default)?
+            .with_behaviour(|key, relay_client| Self::behaviour(key, relay_client, true, config.token.as_deref()))?
+            .with_swarm_config(|config| config.with_idle_connection_timeout(Duration::from_secs(60)))
+            .build();
+
+        for address in &config.listen_addresses {
+            swarm.listen_on(address.clone())?;
+        }
+        // Peers behind NAT are reachable through relay, until direct connection is made by hole punching
+        for address in &config.relay_addresses {
+            swarm.listen_on(address.clone().with(Protocol::P2pCircuit))?;
+        }
+        // External addresses are announced to peers by identify protocol
+        for address in &config.external_addresses {
+            swarm.add_external_address(address.clone());
+        }
+
+        Ok(swarm)
+    }
+
+    /// Load network identity from file, or create new identity and store it to the file if file doesn't exist, so node
+    /// keeps its peer id between restarts.
+    pub fn load_or_create_identity(path: &str) -> Result<Keypair, ProtocolError> {
+        let error = |reason: String| ProtocolError::IdentityError(path.to_string(), reason);
+
+        if Path::new(path).exists() {
+            let content = fs::read(path).map_err(|e| error(e.to_string()))?;
+            return Keypair::from_protobuf_encoding(&content).map_err(|e| error(e.to_string()));
+        }
+
+        let identity = Keypair::generate_ed25519();
+        let content = identity.to_protobuf_encoding().map_err(|e| error(e.to_string()))?;
+        Self::write_private_file(path, &content).map_err(|e| error(e.to_string()))?;
+
+        Ok(identity)
+    }
+
+    /// Identity file contains private key, so only owner can read it.
+    fn write_private_file(path: &str, content: &[u8]) -> io::Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        std::io::Write::write_all(&mut options.open(path)?, content)
+    }
+
+    /// Network listening on in-memory address, without peer discovery (peers are dialed explicitly). Used to simulate
+    /// multiple nodes in one process.
+    #[cfg(test)]
+    pub fn in_memory(address: u64) -> Result<Swarm<Network>, Box<dyn Error>> {
+        use libp2p::core::transport::{MemoryTransport, Transport};
+        use libp2p::core::upgrade::Version;
+
+        let mut swarm = SwarmBuilder::with_new_identity()
+            .with_tokio()
+            .with_other_transport(|key| {
+                Ok::<_, Box<dyn Error + Send + Sync>>(
+                    MemoryTransport::default()
+                        .upgrade(Version::V1)
+                        .authenticate(noise::Config::new(key)?)
+                        .multiplex(yamux::Config::default()),
+                )
             })?
+            .with_relay_client(noise::Config::new, yamux::Config::default)?
+            .with_behaviour(|key, relay_client| Self::behaviour(key, relay_client, false, None))?
             .with_swarm_config(|config| config.with_idle_connection_timeout(Duration::from_secs(60)))
             .build();
 
-        swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
+        swarm.listen_on(Protocol::Memory(address).into())?;
 
         Ok(swarm)
     }
+
+    /// Score of the command topic, where peers are down-scored only for invalid messages. As blocks are produced
+    /// rarely, peers aren't penalized for delivering too few messages, and they don't collect positive score (for time
+    /// in mesh or delivered messages) which would cover their later invalid messages.
+    pub fn topic_score() -> TopicScoreParams {
+        TopicScoreParams {
+            topic_weight: 1.0,
+            time_in_mesh_weight: 0.0,
+            first_message_deliveries_weight: 0.0,
+            mesh_message_deliveries_weight: 0.0,
+            mesh_failure_penalty_weight: 0.0,
+            invalid_message_deliveries_weight: -10.0,
+            invalid_message_deliveries_decay: 0.9,
+            ..TopicScoreParams::default()
+        }
+    }
+
+    /// Ban peer, which disconnects it and denies its new connections until restart.
+    pub fn ban(&mut self, peer_id: PeerId) {
+        self.banned_peers.block_peer(peer_id);
+        self.channel.blacklist_peer(&peer_id);
+        self.channel.remove_explicit_peer(&peer_id);
+    }
+
+    fn score_thresholds() -> PeerScoreThresholds {
+        PeerScoreThresholds {
+            graylist_threshold: Self::BAN_THRESHOLD,
+            ..PeerScoreThresholds::default()
+        }
+    }
+
+    fn behaviour(
+        key: &Keypair,
+        relay_client: relay::client::Behaviour,
+        discovery: bool,
+        token: Option<&str>,
+    ) -> Result<Network, Box<dyn Error + Send + Sync>> {
+        let gossip_config = gossipsub::ConfigBuilder::default()
+            .heartbeat_interval(Duration::from_secs(10))
+            .validation_mode(gossipsub::ValidationMode::Strict)
+            // Messages are propagated only after they are validated by application
+            .validate_messages()
+            .message_id_fn(|message| {
+                let mut hasher = DefaultHasher::new();
+                message.data.hash(&mut hasher);
+                gossipsub::MessageId::from(hasher.finish().to_string())
+            })
+            .build()
+            .map_err(io::Error::other)?;
+
+        let address_resolver = match discovery {
+            true => Some(mdns::tokio::Behaviour::new(mdns::Config::default(), key.public().to_peer_id())?),
+            false => None,
+        };
+
+        // Token of authenticated network is presented to peers as agent version
+        let mut identify_config = identify::Config::new(Self::IDENTIFY_PROTOCOL.to_string(), key.public());
+        if let Some(token) = token {
+            identify_config = identify_config.with_agent_version(Authentication::agent_version(token));
+        }
+
+        let mut channel = gossipsub::Behaviour::new(gossipsub::MessageAuthenticity::Signed(key.clone()), gossip_config)?;
+        channel.with_peer_score(PeerScoreParams::default(), Self::score_thresholds())?;
+
+        Ok(Network {
+            channel,
+            address_resolver: Toggle::from(address_resolver),
+            banned_peers: BlockList::default(),
+            identify: identify::Behaviour::new(identify_config),
+            relay_client,
+            hole_punching: dcutr::Behaviour::new(key.public().to_peer_id()),
+            queries: request_response::json::Behaviour::new([(Self::QUERY_PROTOCOL, ProtocolSupport::Full)], request_response::Config::default()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn should_load_or_create_identity() {
+        // Given
+        let path = env::temp_dir().join("should_load_or_create_identity.key");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        // When
+        let created = Network::load_or_create_identity(path).unwrap();
+        let loaded = Network::load_or_create_identity(path).unwrap();
+
+        // Then
+        assert_eq!(created.public().to_peer_id(), loaded.public().to_peer_id());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_create_network_config_from_arguments() {
+        // Given
+        let mut arguments = HashMap::new();
+        arguments.insert("listen".to_string(), vec!["/ip4/0.0.0.0/tcp/4001, /ip6/::/udp/4001/quic-v1".to_string()]);
+        arguments.insert("external".to_string(), vec!["/ip4/203.0.113.1/tcp/4001".to_string()]);
+        arguments.insert("quic".to_string(), vec![]);
+        arguments.insert(
+            "relay".to_string(),
+            vec!["/ip4/198.51.100.1/tcp/4001/p2p/12D3KooWD3eckifWpRn9wQpMG9R9hX3sD158z7EqHWmweQAJU5SA".to_string()],
+        );
+
+        // When
+        let config = NetworkConfig::from_arguments(&arguments).unwrap();
+        arguments.remove("quic");
+        let without_quic = NetworkConfig::from_arguments(&arguments);
+        arguments.insert("quic".to_string(), vec![]);
+        arguments.insert("relay".to_string(), vec!["/ip4/198.51.100.1/tcp/4001".to_string()]);
+        let without_relay_peer = NetworkConfig::from_arguments(&arguments);
+
+        // Then
+        assert_eq!(config.listen_addresses.len(), 2);
+        assert_eq!(config.external_addresses[0].to_string(), "/ip4/203.0.113.1/tcp/4001");
+        assert!(config.quic);
+        assert_eq!(config.relay_addresses.len(), 1);
+        assert!(config.identity_file.is_none());
+        assert!(without_quic.is_err());
+        assert!(without_relay_peer.is_err());
+        assert_eq!(
+            NetworkConfig::from_arguments(&HashMap::new()).unwrap().listen_addresses[0].to_string(),
+            "/ip4/0.0.0.0/tcp/0"
+        );
+    }
 }
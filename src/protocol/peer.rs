@@ -0,0 +1,79 @@
+extern crate peg;
+
+use crate::graph::GraphResults;
+use crate::parser::command_parser;
+use crate::protocol::network::Network;
+use libp2p::{PeerId, Swarm};
+use rustc_hash::FxHashMap;
+
+command_parser! {
+    grammar peer_parser(network: &mut Swarm<Network>, rate_limited: &FxHashMap<PeerId, u64>) for str {
+        use crate::graph::error::DatabaseError;
+        use crate::protocol::command::QueryRequest;
+        use libp2p::Multiaddr;
+        use std::str::FromStr;
+
+        pub rule command() -> GraphResults = result:(remote_fetch() / fetch_peers() / connect_peer() / ban_peer()) _ ";"? _ { result }
+
+        // Results are received later, as network event of the protocol
+        rule remote_fetch() -> GraphResults = _ command:$(keyword("fetch") (!on_peer() [_])+) on_peer() peer_id:value() {
+            let peer = PeerId::from_str(peer_id).map_err(|error| DatabaseError::InvalidQuery(format!("peer id {peer_id} is not valid ({error})")))?;
+            let request_id = network.behaviour_mut().queries.send_request(&peer, QueryRequest { command: command.to_string() });
+
+            let mut result = FxHashMap::default();
+            result.insert("peer_id".to_string(), peer_id.to_string());
+            result.insert("request_id".to_string(), request_id.to_string());
+            Ok(vec![result])
+        }
+
+        rule on_peer() = [' ' | '\t' | '\r' | '\n']+ keyword("on") _ keyword("peer") _
+
+        rule fetch_peers() -> GraphResults = _ keyword("fetch") _ keyword("peers") {
+            let channel = &network.behaviour().channel;
+            let subscribed: Vec<&PeerId> = channel.all_peers().filter(|(_, topics)| !topics.is_empty()).map(|(peer_id, _)| peer_id).collect();
+
+            Ok(network.connected_peers().map(|peer_id| {
+                let mut result = FxHashMap::default();
+                result.insert("peer_id".to_string(), peer_id.to_string());
+                result.insert("subscribed".to_string(), subscribed.contains(&peer_id).to_string());
+                result.insert("rate_limited".to_string(), rate_limited.get(peer_id).copied().unwrap_or_default().to_string());
+                result
+            }).collect())
+        }
+
+        rule connect_peer() -> GraphResults = _ keyword("peer") _ keyword("connect") _ address:value() {
+            let multiaddr = Multiaddr::from_str(address).map_err(|error| DatabaseError::InvalidQuery(format!("address {address} is not valid ({error})")))?;
+            network.dial(multiaddr).map_err(|error| DatabaseError::PeerError(error.to_string()))?;
+
+            let mut result = FxHashMap::default();
+            result.insert("address".to_string(), address.to_string());
+            Ok(vec![result])
+        }
+
+        rule ban_peer() -> GraphResults = _ keyword("peer") _ keyword("ban") _ peer_id:value() {
+            let peer = PeerId::from_str(peer_id).map_err(|error| DatabaseError::InvalidQuery(format!("peer id {peer_id} is not valid ({error})")))?;
+
+            network.behaviour_mut().ban(peer);
+
+            let mut result = FxHashMap::default();
+            result.insert("peer_id".to_string(), peer_id.to_string());
+            result.insert("banned".to_string(), "true".to_string());
+            Ok(vec![result])
+        }
+
+        rule value() -> &'input str = $([^' ' | '\t' | '\r' | '\n' | ';']+)
+    }
+}
+
+/// Commands for managing peers of the local node (`fetch peers`, `peer connect <multiaddr>` and `peer ban <peer_id>`),
+/// which are executed by protocol instead of database. Fetch commands can be forwarded to peer by `on peer <peer_id>`
+/// suffix (e.g. `fetch node User($id="...") on peer <peer_id>`).
+pub struct PeerCommand;
+
+impl PeerCommand {
+    /// Execute peer command, or return `None` if given command is not peer command. Peers are fetched with number of
+    /// their blocks which were dropped by rate limit.
+    pub fn execute(network: &mut Swarm<Network>, rate_limited: &FxHashMap<PeerId, u64>, command: &str) -> Option<GraphResults> {
+        peer_parser::command(command, network, rate_limited).ok()
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::{Duration, Instant};
+
+/// Exponential backoff of retried operation (e.g. publishing or dialing peer), where delay after each failed attempt is
+/// doubled up to the maximum delay.
+pub(crate) struct Backoff {
+    initial_delay: Duration,
+    max_delay: Duration,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl Backoff {
+    pub fn new(initial_delay: Duration, max_delay: Duration) -> Backoff {
+        Backoff {
+            initial_delay,
+            max_delay,
+            failures: 0,
+            retry_at: None,
+        }
+    }
+
+    /// Check if operation can be attempted, which is always true until it fails.
+    pub fn is_due(&self) -> bool {
+        self.is_due_at(Instant::now())
+    }
+
+    fn is_due_at(&self, now: Instant) -> bool {
+        self.retry_at.is_none_or(|retry_at| now >= retry_at)
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Record failed attempt, and return delay after which operation is attempted again.
+    pub fn fail(&mut self) -> Duration {
+        self.fail_at(Instant::now())
+    }
+
+    fn fail_at(&mut self, now: Instant) -> Duration {
+        let delay = self.initial_delay.saturating_mul(2u32.saturating_pow(self.failures)).min(self.max_delay);
+        self.failures += 1;
+        self.retry_at = Some(now + delay);
+
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.failures = 0;
+        self.retry_at = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_double_delay_after_each_failure() {
+        // Given
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+        let now = Instant::now();
+
+        // When
+        let delays: Vec<Duration> = (0..4).map(|_| backoff.fail_at(now)).collect();
+        let (waiting, due) = (backoff.is_due_at(now), backoff.is_due_at(now + Duration::from_secs(5)));
+        backoff.reset();
+
+        // Then
+        assert_eq!(delays, [1, 2, 4, 5].map(Duration::from_secs));
+        assert!(!waiting);
+        assert!(due);
+        assert!(backoff.is_due_at(now));
+        assert_eq!(backoff.failures(), 0);
+    }
+}
@@ -0,0 +1,91 @@
+use rustc_hash::FxHashMap;
+use std::collections::VecDeque;
+
+/// Hashes of recently seen blocks, so blocks relayed back by peers are not validated again. When cache is full, the
+/// least recently seen hash is evicted.
+pub(crate) struct SeenBlocks {
+    capacity: usize,
+    // Hash with the tick when it was seen, older entries of hashes which were seen again are skipped on eviction
+    order: VecDeque<(String, u64)>,
+    last_seen: FxHashMap<String, u64>,
+    tick: u64,
+}
+
+impl SeenBlocks {
+    pub(crate) fn new(capacity: usize) -> SeenBlocks {
+        SeenBlocks {
+            capacity,
+            order: VecDeque::new(),
+            last_seen: FxHashMap::default(),
+            tick: 0,
+        }
+    }
+
+    /// Check if hash was seen, and mark it as the most recently seen one.
+    pub(crate) fn contains(&mut self, hash: &str) -> bool {
+        let seen = self.last_seen.contains_key(hash);
+        if seen {
+            self.insert(hash.to_string());
+        }
+
+        seen
+    }
+
+    pub(crate) fn insert(&mut self, hash: String) {
+        self.tick += 1;
+        self.last_seen.insert(hash.clone(), self.tick);
+        self.order.push_back((hash, self.tick));
+
+        while self.last_seen.len() > self.capacity {
+            if let Some((hash, tick)) = self.order.pop_front() {
+                if self.last_seen.get(&hash) == Some(&tick) {
+                    self.last_seen.remove(&hash);
+                }
+            }
+        }
+
+        // Hashes which are seen often would grow the queue with stale entries
+        if self.order.len() > 2 * self.capacity {
+            let last_seen = &self.last_seen;
+            self.order.retain(|(hash, tick)| last_seen.get(hash) == Some(tick));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_evict_least_recently_seen_hash() {
+        // Given
+        let mut seen = SeenBlocks::new(2);
+        seen.insert("first".to_string());
+        seen.insert("second".to_string());
+
+        // When
+        let first_seen = seen.contains("first");
+        seen.insert("third".to_string());
+
+        // Then
+        assert!(first_seen);
+        assert!(seen.contains("first"));
+        assert!(seen.contains("third"));
+        assert!(!seen.contains("second"));
+    }
+
+    #[test]
+    fn should_not_grow_with_repeated_hashes() {
+        // Given
+        let mut seen = SeenBlocks::new(2);
+
+        // When
+        for _ in 0..100 {
+            seen.insert("hash".to_string());
+        }
+
+        // Then
+        assert!(seen.order.len() <= 4);
+        assert_eq!(seen.last_seen.len(), 1);
+    }
+}
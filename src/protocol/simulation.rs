@@ -0,0 +1,613 @@
+use crate::chain::genesis::Genesis;
+use crate::chain::Chain;
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::{Graph, GraphResults};
+use crate::protocol::authentication::Authentication;
+use crate::protocol::command::{SyncMode, WireFormat};
+use crate::protocol::error::ProtocolError;
+use crate::protocol::network::Network;
+use crate::protocol::Protocol;
+use crate::query_processor::QueryProcessor;
+use crate::rate::RateLimit;
+use libp2p::futures::future;
+use libp2p::multiaddr::Protocol as Address;
+use libp2p::Multiaddr;
+use rustc_hash::FxHashSet;
+use std::time::Duration;
+use tokio::time;
+
+/// Node of simulated network, with its own graph and chain.
+struct Node {
+    protocol: Protocol,
+    graph: Graph,
+    chain: Chain,
+    address: u64,
+    // Messages of handled network events, e.g. results of forwarded queries
+    messages: Vec<String>,
+}
+
+/// In-process simulation of multiple nodes, connected to each other by in-memory transport. Network events of all
+/// nodes are handled the same way as in main loop, until expected state of the simulated network is reached.
+struct Simulation {
+    nodes: Vec<Node>,
+}
+
+impl Simulation {
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Start given number of nodes, connect each of them to all other nodes and wait until they are subscribed to
+    /// all topics of each other.
+    async fn start(count: usize) -> Simulation {
+        Self::start_chains(&vec![Genesis::default().chain_id; count]).await
+    }
+
+    /// Start node for each of given chain ids, nodes are connected to each other regardless of their chain.
+    async fn start_chains(chain_ids: &[String]) -> Simulation {
+        let count = chain_ids.len();
+        let nodes = chain_ids
+            .iter()
+            .map(|chain_id| {
+                // Memory addresses are shared by whole process, so random address is used for each node
+                let address = rand::random();
+                let network = Network::in_memory(address).expect("SIMULATION :: Failed to start node");
+
+                Node {
+                    protocol: Protocol::subscribe(network, WireFormat::default(), chain_id).expect("SIMULATION :: Failed to start node"),
+                    graph: Graph::default(),
+                    chain: Chain::new(&Genesis {
+                        chain_id: chain_id.clone(),
+                        ..Genesis::default()
+                    }),
+                    address,
+                    messages: vec![],
+                }
+            })
+            .collect();
+
+        let mut simulation = Simulation { nodes };
+        let addresses: Vec<u64> = simulation.nodes.iter().map(|node| node.address).collect();
+
+        for (index, node) in simulation.nodes.iter_mut().enumerate() {
+            for address in &addresses[..index] {
+                node.protocol
+                    .network
+                    .dial(Multiaddr::from(Address::Memory(*address)))
+                    .expect("SIMULATION :: Failed to dial node");
+            }
+        }
+
+        simulation
+            .run_until(|nodes| nodes.iter().all(|node| Self::is_subscribed(node, count - 1)))
+            .await;
+
+        simulation
+    }
+
+    /// Execute command on the node with given index.
+    fn execute(&mut self, index: usize, command: &str) -> GraphResults {
+        let node = &mut self.nodes[index];
+
+        QueryProcessor::parse_command(&mut node.graph, &mut node.chain, command).expect("SIMULATION :: Failed to parse command")
+    }
+
+    /// Handle network events until all nodes have the same chain.
+    async fn run_until_converged(&mut self) {
+        self.run_until(Self::is_converged).await
+    }
+
+    /// Handle network events until all nodes have the same chain and their sync requests were answered.
+    async fn run_until_synced(&mut self) {
+        self.run_until(|nodes| Self::is_converged(nodes) && nodes.iter().all(|node| node.protocol.subscription.last_request.is_none()))
+            .await
+    }
+
+    /// Handle network events of all nodes until condition is met, changes of each node are published after each
+    /// handled event. Panics if condition is not met in time.
+    async fn run_until(&mut self, condition: impl Fn(&[Node]) -> bool) {
+        let deadline = time::Instant::now() + Self::TIMEOUT;
+
+        loop {
+            for node in &mut self.nodes {
+                // Publishing fails until node is subscribed by peers, changes are published again later
+                let _ = node.protocol.publish_changes(&mut node.chain);
+                let _ = node.protocol.reconnect();
+            }
+
+            if condition(&self.nodes) {
+                return;
+            }
+
+            let events = self.nodes.iter_mut().map(|node| node.protocol.fetch_network_event());
+            let (event, index, _) = time::timeout_at(deadline, future::select_all(events))
+                .await
+                .expect("SIMULATION :: Condition was not met in time");
+
+            let node = &mut self.nodes[index];
+            match node.protocol.handle_network_event(&mut node.graph, &mut node.chain, event) {
+                Err(error) => eprintln!("SIMULATION :: Node {index} :: {error}"),
+                Ok(message) if message != "NOP" => node.messages.push(message),
+                Ok(_) => {}
+            }
+        }
+    }
+
+    /// Check if given number of peers is subscribed to the node, and peers of the same chain are subscribed to all its
+    /// topics (peers of other chains are subscribed only to their own topics).
+    fn is_subscribed(node: &Node, count: usize) -> bool {
+        let topics = [
+            node.protocol.subscription.block_topic.hash(),
+            node.protocol.subscription.sync_topic.hash(),
+            node.protocol.subscription.vote_topic.hash(),
+        ];
+        let channel = &node.protocol.network.behaviour().channel;
+
+        let peers: Vec<_> = channel.all_peers().filter(|(_, peer_topics)| !peer_topics.is_empty()).collect();
+        let chain_peers = peers
+            .iter()
+            .filter(|(_, peer_topics)| peer_topics.iter().any(|topic| topics.contains(topic)));
+
+        peers.len() == count
+            && chain_peers
+                .clone()
+                .all(|(_, peer_topics)| topics.iter().all(|topic| peer_topics.contains(&topic)))
+    }
+
+    fn is_converged(nodes: &[Node]) -> bool {
+        nodes.windows(2).all(|pair| pair[0].chain.blocks == pair[1].chain.blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::block::{Block, SchemaData};
+    use crate::chain::error::ChainError;
+    use crate::chain::finality::{CheckpointVote, Finality};
+    use crate::chain::wallet::Wallet;
+    use crate::protocol::command::QueryRequest;
+    use libp2p::gossipsub::MessageAcceptance;
+
+    /// Connect two new users on the node with given index.
+    fn connect_users(simulation: &mut Simulation, index: usize, weight: i8) {
+        let mut add_user = || InternalNodeAttribute::get_identifier(&simulation.execute(index, "add node User(name=\"John\")").unwrap()[0]);
+        let from = add_user();
+        let to = add_user();
+
+        simulation
+            .execute(
+                index,
+                &format!("add connection from User($id=\"{from}\") to User($id=\"{to}\") with weight {weight}"),
+            )
+            .unwrap();
+    }
+
+    /// Start given number of nodes, with users defined on the first node and synced to the others.
+    async fn start(count: usize) -> Simulation {
+        let mut simulation = Simulation::start(count).await;
+        simulation.execute(0, "define node User(name)").unwrap();
+        simulation.run_until_converged().await;
+
+        simulation
+    }
+
+    #[tokio::test]
+    async fn should_propagate_blocks_to_all_nodes() {
+        // Given
+        let mut simulation = start(3).await;
+
+        // When
+        connect_users(&mut simulation, 0, 50);
+        simulation.run_until_converged().await;
+
+        // Then
+        for node in &simulation.nodes {
+            assert_eq!(node.chain.blocks.len(), 9); // Genesis, definition, two nodes, edge and their reward blocks
+            assert!(node.graph.definitions.contains_key("User"));
+            assert_eq!(node.chain.blocks[7].data.edge_data.as_ref().unwrap().weight, 50);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_drop_blocks_of_peer_exceeding_rate_limit() {
+        // Given
+        let mut simulation = start(2).await;
+        simulation.nodes[1].protocol.block_rate = Some(RateLimit { rate: 0.001 });
+
+        // When
+        connect_users(&mut simulation, 0, 5);
+        simulation
+            .run_until(|nodes| nodes[1].protocol.block_limits.values().any(|(_, dropped)| *dropped > 0))
+            .await;
+
+        // Then
+        let peers = simulation.nodes[1].protocol.execute("fetch peers").unwrap().unwrap();
+        assert_ne!(peers[0]["rate_limited"], "0");
+        assert!(simulation.nodes[1].chain.blocks.len() < simulation.nodes[0].chain.blocks.len());
+    }
+
+    #[tokio::test]
+    async fn should_keep_unpublished_blocks_queued() {
+        // Given
+        let mut protocol = Protocol::in_memory(rand::random(), &Genesis::default().chain_id).unwrap();
+        let mut chain = Chain::default();
+        QueryProcessor::parse_command(&mut Graph::default(), &mut chain, "define node User(name)")
+            .unwrap()
+            .unwrap();
+
+        // When
+        let result = protocol.publish_changes(&mut chain);
+
+        // Then
+        assert!(matches!(result, Err(ProtocolError::PublishingError(_))));
+        assert_eq!(chain.outbound.len(), 2); // Definition and its reward block
+        assert!(chain.outbound[0] == chain.blocks[1]);
+        assert!(!protocol.subscription.publish_backoff.is_due());
+    }
+
+    #[tokio::test]
+    async fn should_reconnect_and_resync_dropped_peer() {
+        // Given
+        let mut simulation = start(2).await;
+        let peer = *simulation.nodes[0].protocol.network.local_peer_id();
+        let _ = simulation.nodes[1].protocol.network.disconnect_peer_id(peer);
+        simulation.run_until(|nodes| nodes[1].protocol.reconnects.contains_key(&peer)).await;
+
+        // When
+        connect_users(&mut simulation, 0, 10);
+        time::sleep(Protocol::RETRY_DELAY + Duration::from_millis(100)).await;
+        simulation.run_until_converged().await;
+
+        // Then
+        assert!(simulation.nodes[1].protocol.reconnects.is_empty());
+        assert!(simulation.nodes[1].messages.iter().any(|message| message.starts_with("Reconnected")));
+        assert_eq!(simulation.nodes[1].chain.blocks.len(), 9);
+    }
+
+    #[tokio::test]
+    async fn should_replicate_node_changes() {
+        // Given
+        let mut simulation = start(2).await;
+        let user = InternalNodeAttribute::get_identifier(&simulation.execute(0, "add node User(name=\"John\")").unwrap()[0]);
+        let deleted = InternalNodeAttribute::get_identifier(&simulation.execute(0, "add node User(name=\"Janne\")").unwrap()[0]);
+
+        // When
+        simulation
+            .execute(0, &format!("update node User($id=\"{user}\") set (name=\"Johnny\")"))
+            .unwrap();
+        simulation.execute(0, &format!("delete node User($id=\"{deleted}\")")).unwrap();
+        simulation.run_until_converged().await;
+
+        // Then
+        let graph = &simulation.nodes[1].graph;
+        assert_eq!(graph.nodes.len(), 1);
+        assert_eq!(
+            graph.get_by_id(&"User".to_string(), &user).unwrap().attributes.get("name").unwrap(),
+            "Johnny"
+        );
+    }
+
+    #[tokio::test]
+    async fn should_converge_after_changes_on_every_node() {
+        // Given
+        let mut simulation = start(4).await;
+
+        // When
+        for round in 0..2 {
+            for index in 0..4 {
+                connect_users(&mut simulation, index, round * 4 + index as i8);
+                simulation.run_until_converged().await;
+            }
+        }
+
+        // Then
+        let chain = &simulation.nodes[0].chain;
+        assert_eq!(chain.blocks.len(), 3 + 6 * 4 * 2);
+        assert!(Simulation::is_converged(&simulation.nodes));
+        assert_eq!(chain.balances().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn should_manage_peers() {
+        // Given
+        let mut simulation = start(2).await;
+        let peer_id = simulation.nodes[1].protocol.network.local_peer_id().to_string();
+
+        // When
+        let peers = simulation.nodes[0].protocol.execute("fetch peers").unwrap().unwrap();
+        let invalid = simulation.nodes[0].protocol.execute("peer connect invalid").unwrap();
+        let banned = simulation.nodes[0].protocol.execute(&format!("peer ban {peer_id};")).unwrap().unwrap();
+        simulation
+            .run_until(|nodes| nodes[0].protocol.network.connected_peers().count() == 0)
+            .await;
+
+        // Then
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0]["peer_id"], peer_id);
+        assert_eq!(peers[0]["subscribed"], "true");
+        assert_eq!(invalid.err().unwrap().code(), "INVALID_QUERY");
+        assert_eq!(banned[0]["banned"], "true");
+        assert!(simulation.nodes[0].protocol.execute("fetch nodes").is_none());
+        assert!(simulation.nodes[0].protocol.execute("fetch peers").unwrap().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_ban_peer_sending_invalid_messages() {
+        // Given
+        let mut simulation = start(2).await;
+        let offender = simulation.nodes[1].protocol.network.local_peer_id().to_owned();
+
+        // When
+        for message in ["invalid", "invalid block", "invalid chain"] {
+            let protocol = &mut simulation.nodes[1].protocol;
+            protocol
+                .network
+                .behaviour_mut()
+                .channel
+                .publish(protocol.subscription.block_topic.clone(), message.as_bytes())
+                .unwrap();
+        }
+        simulation
+            .run_until(|nodes| nodes[0].protocol.network.connected_peers().count() == 0)
+            .await;
+
+        // Then
+        let channel = &simulation.nodes[0].protocol.network.behaviour().channel;
+        assert!(channel.peer_score(&offender).unwrap() < Network::BAN_THRESHOLD);
+    }
+
+    #[tokio::test]
+    async fn should_sync_missing_blocks_of_offline_node() {
+        // Given
+        let mut simulation = start(3).await;
+        connect_users(&mut simulation, 0, 50);
+        simulation.run_until_synced().await;
+
+        // Node was offline while first change was made
+        simulation.nodes[2].chain.blocks.truncate(1);
+
+        // When
+        connect_users(&mut simulation, 0, 20);
+        simulation.run_until_converged().await;
+
+        // Then
+        assert_eq!(simulation.nodes[2].chain.blocks.len(), 15);
+        assert_eq!(simulation.nodes[2].chain.blocks[13].data.edge_data.as_ref().unwrap().weight, 20);
+    }
+
+    #[tokio::test]
+    async fn should_resync_from_fork_point() {
+        // Given
+        let mut simulation = start(2).await;
+        simulation.run_until_synced().await;
+
+        // Nodes were partitioned while they made different changes
+        for (index, count) in [(0, 1), (1, 2)] {
+            for _ in 0..count {
+                simulation.execute(index, "add node User(name=\"Partitioned\")").unwrap();
+            }
+            simulation.nodes[index].chain.outbound.clear();
+        }
+        let fork = simulation.nodes[1].chain.blocks[3].clone();
+
+        // When
+        connect_users(&mut simulation, 1, 20);
+        simulation.run_until_converged().await;
+
+        // Then
+        assert_eq!(simulation.nodes[0].chain.blocks.len(), 13);
+        assert!(simulation.nodes[0].chain.blocks[3] == fork);
+        assert!(simulation.nodes[0].protocol.subscription.fork.is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_sync_headers_first() {
+        // Given
+        let mut simulation = start(2).await;
+        simulation.nodes[1].protocol.sync_mode = SyncMode::Headers;
+        connect_users(&mut simulation, 0, 50);
+        simulation.run_until_synced().await;
+
+        // Node was offline while first change was made
+        simulation.nodes[1].chain.blocks.truncate(1);
+
+        // When
+        connect_users(&mut simulation, 0, 20);
+        simulation.run_until_converged().await;
+
+        // Then
+        assert_eq!(simulation.nodes[1].chain.blocks.len(), 15);
+        assert!(simulation.nodes[1].protocol.subscription.headers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_deny_peers_outside_of_allowlist() {
+        // Given
+        let mut simulation = start(3).await;
+        let allowed = *simulation.nodes[1].protocol.network.local_peer_id();
+        let denied = *simulation.nodes[2].protocol.network.local_peer_id();
+        simulation.nodes[0].protocol.authentication = Some(Authentication::new(FxHashSet::from_iter([allowed]), None));
+
+        // When
+        connect_users(&mut simulation, 2, 50);
+        simulation
+            .run_until(|nodes| nodes[1].chain.blocks.len() == 9 && !nodes[0].protocol.network.is_connected(&denied))
+            .await;
+
+        // Then
+        assert_eq!(simulation.nodes[0].chain.blocks.len(), 3);
+        assert!(simulation.nodes[0].protocol.network.is_connected(&allowed));
+    }
+
+    #[tokio::test]
+    async fn should_isolate_chains_with_different_id() {
+        // Given
+        let chain_ids = ["first".to_string(), "first".to_string(), "second".to_string()];
+        let mut simulation = Simulation::start_chains(&chain_ids).await;
+        simulation.execute(0, "define node User(name)").unwrap();
+        simulation.execute(2, "define node User(name)").unwrap();
+        simulation.run_until(|nodes| nodes[1].graph.definitions.contains_key("User")).await;
+
+        // When
+        connect_users(&mut simulation, 0, 50);
+        connect_users(&mut simulation, 2, 20);
+        simulation
+            .run_until(|nodes| nodes[1].chain.blocks.len() == 9 && nodes[2].chain.blocks.len() == 9)
+            .await;
+
+        // Then
+        assert!(simulation.nodes[0].chain.blocks == simulation.nodes[1].chain.blocks);
+        assert_eq!(simulation.nodes[2].chain.blocks[7].data.edge_data.as_ref().unwrap().weight, 20);
+    }
+
+    #[tokio::test]
+    async fn should_forward_fetch_command_to_peer() {
+        // Given
+        let chain_ids = ["first".to_string(), "second".to_string()];
+        let mut simulation = Simulation::start_chains(&chain_ids).await;
+        simulation.execute(1, "define node User(name)").unwrap();
+        let user = InternalNodeAttribute::get_identifier(&simulation.execute(1, "add node User(name=\"John\")").unwrap()[0]);
+        let fetch = format!("fetch node User($id=\"{user}\")");
+        let peer_id = simulation.nodes[1].protocol.network.local_peer_id().to_string();
+
+        // When
+        let local = simulation.nodes[0].protocol.execute(&fetch);
+        let request = simulation.nodes[0]
+            .protocol
+            .execute(&format!("{fetch} on peer {peer_id};"))
+            .unwrap()
+            .unwrap();
+        let unknown = simulation.nodes[0]
+            .protocol
+            .execute(&format!("fetch peers on peer {peer_id}"))
+            .unwrap()
+            .unwrap();
+        simulation.run_until(|nodes| nodes[0].messages.len() == 2).await;
+
+        // Then
+        // Responses can be received in any order
+        let response = |request_id: &str| {
+            let request_id = format!("\"request_id\":\"{request_id}\"");
+            simulation.nodes[0].messages.iter().find(|message| message.contains(&request_id)).unwrap()
+        };
+        let lines: Vec<&str> = response(&request[0]["request_id"]).lines().collect();
+        assert!(local.is_none());
+        assert_eq!(request[0]["peer_id"], peer_id);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("\"name\":\"John\""));
+        assert!(lines[1].contains("\"end\":true"));
+        assert!(response(&unknown[0]["request_id"]).contains("PARSE_ERROR"));
+        assert!(simulation.nodes[0].graph.nodes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_answer_only_fetch_commands_of_peer() {
+        // Given
+        let mut simulation = Simulation::start(2).await;
+        let peer_id = *simulation.nodes[1].protocol.network.local_peer_id();
+
+        // When
+        let request_id = simulation.nodes[0].protocol.network.behaviour_mut().queries.send_request(
+            &peer_id,
+            QueryRequest {
+                command: "verify chain".to_string(),
+            },
+        );
+        let request_id = format!("\"request_id\":\"{request_id}\"");
+        simulation
+            .run_until(|nodes| nodes[0].messages.iter().any(|message| message.contains(&request_id)))
+            .await;
+
+        // Then
+        let response = simulation.nodes[0].messages.iter().find(|message| message.contains(&request_id)).unwrap();
+        assert!(response.contains("PERMISSION_DENIED"));
+    }
+
+    #[test]
+    fn should_reject_only_votes_with_invalid_signature() {
+        // Given
+        let mut chain = Chain::default();
+        chain.finality = Finality::new(1, 67);
+        chain
+            .add_schema_change(SchemaData::new("User".to_string(), vec![], None, false, vec![]))
+            .unwrap();
+        let hash = chain.blocks[1].hash.clone();
+        let mut wallet = Wallet::default();
+        let signature = wallet.sign(&CheckpointVote::message(1, &hash));
+
+        // When
+        let (without_stake, _) = Protocol::receive_vote(&mut chain, CheckpointVote::new(1, hash.clone(), wallet.get_public_key(), signature));
+        let (unknown, _) = Protocol::receive_vote(&mut chain, CheckpointVote::new(7, hash.clone(), wallet.get_public_key(), String::new()));
+        let (forged, _) = Protocol::receive_vote(&mut chain, CheckpointVote::new(1, hash, wallet.get_public_key(), "forged".to_string()));
+
+        // Then
+        assert!(matches!(without_stake, MessageAcceptance::Ignore));
+        assert!(matches!(unknown, MessageAcceptance::Ignore));
+        assert!(matches!(forged, MessageAcceptance::Reject));
+    }
+
+    #[test]
+    fn should_ignore_blocks_invalid_for_local_chain() {
+        // Given
+        let local_errors = [
+            ChainError::BlockHasWrongPreviousHashValue(1),
+            ChainError::BlockHasInvalidTimestamp(1),
+            ChainError::BlockProposerIsNotScheduled(1),
+            ChainError::BlockHasWrongDifficultyValue(1),
+            ChainError::ChainConflictsWithFinalizedCheckpoint(1),
+        ];
+
+        // When
+        let acceptances: Vec<MessageAcceptance> = local_errors.iter().map(Protocol::acceptance).collect();
+        let forged = Protocol::acceptance(&ChainError::BlockHasWrongSignatureValue(1));
+
+        // Then
+        assert!(acceptances.iter().all(|acceptance| matches!(acceptance, MessageAcceptance::Ignore)));
+        assert!(matches!(forged, MessageAcceptance::Reject));
+    }
+
+    #[tokio::test]
+    async fn should_accept_block_after_its_forged_copy() {
+        // Given
+        let mut simulation = Simulation::start(1).await;
+        let mut producer = Chain::default();
+        producer
+            .add_schema_change(SchemaData::new("User".to_string(), vec![], None, false, vec![]))
+            .unwrap();
+        let block = producer.blocks[1].clone();
+        let forged = Block {
+            signature: "forged".to_string(),
+            ..block.clone()
+        };
+        let node = &mut simulation.nodes[0];
+        let source = *node.protocol.network.local_peer_id();
+
+        // When
+        let (forged, _) = node.protocol.receive_block(&mut node.chain, &source, forged);
+        let (real, _) = node.protocol.receive_block(&mut node.chain, &source, block);
+
+        // Then
+        assert!(matches!(forged, MessageAcceptance::Reject));
+        assert!(matches!(real, MessageAcceptance::Accept));
+        assert_eq!(node.chain.blocks.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn should_keep_only_pending_blocks_near_chain_tip() {
+        // Given
+        let mut simulation = Simulation::start(1).await;
+        let node = &mut simulation.nodes[0];
+        let source = *node.protocol.network.local_peer_id();
+        let block = |id: usize| Block { id, ..Block::default() };
+
+        // When
+        let _ = node.protocol.receive_block(&mut node.chain, &source, block(2));
+        let _ = node
+            .protocol
+            .receive_block(&mut node.chain, &source, block(Protocol::PENDING_BLOCKS_WINDOW + 2));
+
+        // Then
+        let pending = &node.protocol.subscription.pending_blocks;
+        assert!(pending.contains_key(&2));
+        assert!(!pending.contains_key(&(Protocol::PENDING_BLOCKS_WINDOW + 2)));
+    }
+}
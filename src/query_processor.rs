@@ -1,305 +1,2288 @@
 extern crate peg;
 
+use crate::access::Role;
+use crate::analytics::centrality::{Centrality, CentralityType};
+use crate::analytics::community::Community;
+use crate::analytics::order::Order;
+use crate::analytics::tree::SpanningTree;
+use crate::chain::block::{NodeData, NodeOperation};
 use crate::chain::Chain;
+#[cfg(feature = "cypher")]
+use crate::cypher::Cypher;
+use crate::export::{Export, ExportFormat};
+use crate::graph::attribute::InternalNodeAttribute;
+use crate::graph::condition::Condition;
+use crate::graph::error::DatabaseError;
+use crate::graph::expiry::Expiring;
+use crate::graph::trigger::{TriggerEvent, TriggerOperation};
 use crate::graph::Graph;
-use crate::graph::GraphResults;
+use crate::graph::{GraphResults, GraphRows};
+use crate::import::csv::CsvImport;
+use crate::import::ndjson::NdjsonImport;
+use crate::parser::command_parser;
 use peg::error::ParseError;
 use peg::str::LineCol;
 use rustc_hash::FxHashMap;
-
-peg::parser! {
-    grammar query_parser(graph: &mut Graph, chain: &mut Chain) for str {
+use serde::Deserialize;
+use serde_json::Value;
+use std::cell::Cell;
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::ops::Range;
+use std::time::Instant;
+
+command_parser! {
+    grammar query_parser(access: &mut Access) for str {
+        use crate::chain::block::{EdgeData, NodeData, NodeOperation, SchemaData};
         use crate::graph::attribute::InternalNodeAttribute;
+        use crate::graph::condition::{Condition, Operator};
+        use crate::graph::error::DatabaseError;
+        use crate::graph::expiry::{Expiring, Expiry};
+        use crate::graph::trigger::{Trigger, TriggerEvent, TriggerOperation, TriggerTarget};
+        use crate::graph::Connection;
+        use crate::import::{add_edge_changes, EdgeChange};
+
+        pub rule command() -> GraphResults = result:(define_node() / add_nodes() / add_edges() / add_node() / update_node() / replace_node() / ambiguous_update() / soft_delete_node() / delete_node() / restore_node() / add_edge() / update_edge() / increment_edge() / delete_edge() / upsert_node() / fetch_node_at() / fetch_node() / fetch_node_by_id() / filter_nodes() / fetch_subgraph() / fetch_order() / fetch_edge_history() / fetch_connection() / fetch_block() / fetch_blocks() / verify_chain() / fetch_agents() / fetch_balances() / fetch_validators() / delegate_stake() / export_mnemonic() / issue_network_token() / create_token() / rotate_key() / compute_communities() / compute_centrality() / compute_spanning_tree() / export_graph() / import_nodes() / import_edges() / export_data() / import_data() / create_trigger() / fetch_triggers() / drop_trigger() / create_view() / fetch_view() / drop_view()) _ ";"? _ { result }
+
+        rule define_node() -> GraphResults = exclusive() _ keyword("define") _ keyword("node") _ name:name() _ attributes:attribute_definitions() _ self_loops:self_loops()? _ conditions:agent()? {
+            let (graph, chain) = access.write();
+            let secret_attributes: Vec<String> = attributes.iter().filter(|(_, secret)| *secret).map(|(attribute, _)| attribute.to_string()).collect();
+            let attributes: Vec<String> = attributes.iter().map(|(attribute, _)| attribute.to_string()).collect();
+            let result = graph.create_definition(name.to_string(), attributes.clone());
+            let deny_self_loops = self_loops == Some(false);
+
+            if result.is_ok() {
+                if deny_self_loops {
+                    graph.denied_self_loops.insert(name.to_string());
+                }
+                if !secret_attributes.is_empty() {
+                    graph.secret_attributes.insert(name.to_string(), secret_attributes.iter().cloned().collect());
+                }
+
+                let schema = SchemaData::new(name.to_string(), attributes, conditions, deny_self_loops, secret_attributes);
+                if let Err(error) = chain.add_schema_change(schema) {
+                    eprintln!("Chain error: {error}");
+                }
+            }
+
+            result
+        }
+
+        rule fetch_node() -> GraphResults = _ keyword("fetch") _ keyword("node") _ name:name() _ attributes:attributes() _ joins:joins() {
+            access.check_secrets(name, attributes.keys())?;
+            access.graph().search(name.to_string(), attributes, joins?)
+        }
+
+        rule fetch_node_by_id() -> GraphResults = _ keyword("fetch") _ keyword("node") _ attributes:attributes() _ joins:joins() {
+            access.graph().search_by_id(&InternalNodeAttribute::require_identifier(&attributes)?, joins?)
+        }
+
+        rule fetch_subgraph() -> GraphResults = _ keyword("fetch") _ keyword("subgraph") _ keyword("around") _ name:name() _ attributes:attributes() _ keyword("depth") _ depth:depth() {
+            access.check_secrets(name, attributes.keys())?;
+            access.graph().subgraph(name.to_string(), attributes, depth?)
+        }
+
+        rule filter_nodes() -> GraphResults = _ keyword("fetch") _ keyword("node") _ name:name() _ keyword("where") _ "(" _ conditions:condition() ** (_ "," _) _ ")" _ joins:joins() {
+            access.check_secrets(name, conditions.iter().map(|condition| &condition.attribute))?;
+            access.graph().filter(name.to_string(), conditions, joins?)
+        }
+
+        rule fetch_node_at() -> GraphResults = _ keyword("fetch") _ keyword("node") _ name:name() _ attributes:attributes() _ keyword("at") _ keyword("block") _ height:height() {
+            access.check_secrets(name, attributes.keys())?;
+            access.chain().node_at(name.to_string(), InternalNodeAttribute::require_identifier(&attributes)?, height?)
+        }
+
+        rule fetch_edge_history() -> GraphResults = _ keyword("fetch") _ keyword("history") _ keyword("from") _ from_name:name() _ from_attributes:attributes() _ keyword("to") _ to_name:name() _ to_attributes:attributes() {
+            access.check_secrets(from_name, from_attributes.keys())?;
+            access.check_secrets(to_name, to_attributes.keys())?;
+            let from_id = InternalNodeAttribute::require_identifier(&from_attributes)?;
+            let to_id = InternalNodeAttribute::require_identifier(&to_attributes)?;
+
+            access.chain().edge_history((from_name.to_string(), from_id), (to_name.to_string(), to_id))
+        }
+
+        rule fetch_connection() -> GraphResults = _ keyword("fetch") _ keyword("connection") _ keyword("chain") {
+            access.chain().as_graph_result()
+        }
+
+        rule fetch_block() -> GraphResults = _ keyword("fetch") _ keyword("block") _ hash_or_id:$(['0'..='9' | 'a'..='f']+) {
+            access.chain().block_as_graph_result(hash_or_id)
+        }
+
+        rule fetch_blocks() -> GraphResults = _ keyword("fetch") _ keyword("blocks") _ keyword("from") _ from:height() _ keyword("to") _ to:height() {
+            access.chain().blocks_as_graph_result(from?, to?)
+        }
+
+        rule verify_chain() -> GraphResults = _ keyword("verify") _ keyword("chain") {
+            access.chain().verify()
+        }
+
+        rule fetch_agents() -> GraphResults = _ keyword("fetch") _ keyword("agents") {
+            access.chain().agent_service.as_graph_result()
+        }
+
+        rule fetch_balances() -> GraphResults = _ keyword("fetch") _ keyword("balances") {
+            access.chain().balances_as_graph_result()
+        }
+
+        rule fetch_validators() -> GraphResults = _ keyword("fetch") _ keyword("validators") {
+            access.chain().validators_as_graph_result()
+        }
+
+        rule delegate_stake() -> GraphResults = exclusive() _ keyword("delegate") _ keyword("stake") _ keyword("from") _ from_name:name() _ from_attributes:attributes() _ keyword("to") _ to_name:name() _ to_attributes:attributes() {
+            let (graph, chain) = access.write();
+            let from_id = InternalNodeAttribute::require_identifier(&from_attributes)?;
+            let to_id = InternalNodeAttribute::require_identifier(&to_attributes)?;
+
+            graph.find_by_id(&to_name.to_string(), &to_id)?;
+            chain.delegate_stake(from_name.to_string(), from_id, to_id)
+        }
+
+        rule export_mnemonic() -> GraphResults = _ keyword("wallet") _ keyword("export") _ keyword("mnemonic") {
+            access.chain().export_mnemonic()
+        }
+
+        rule issue_network_token() -> GraphResults = exclusive() _ keyword("wallet") _ keyword("issue") _ keyword("token") _ peer_id:path() {
+            let (_, chain) = access.write();
+            chain.issue_network_token(&peer_id)
+        }
+
+        rule create_token() -> GraphResults = exclusive() _ keyword("create") _ keyword("token") _ keyword("for") _ keyword("role") _ role:role() _ keyword("expires") _ expires_in:duration() {
+            let (_, chain) = access.write();
+            chain.issue_client_token(role?, expires_in?)
+        }
+
+        rule rotate_key() -> GraphResults = exclusive() _ keyword("rotate") _ keyword("key") {
+            let (_, chain) = access.write();
+            chain.rotate_key()
+        }
+
+        rule compute_communities() -> GraphResults = _ keyword("compute") _ keyword("communities") _ name:shard()? {
+            Community::detect(access.graph(), name)
+        }
+
+        rule compute_centrality() -> GraphResults = _ keyword("compute") _ keyword("centrality") _ centrality_type:centrality_type() _ name:shard()? {
+            Centrality::compute(access.graph(), centrality_type, name)
+        }
+
+        rule compute_spanning_tree() -> GraphResults = _ keyword("compute") _ keyword("mst") _ maximum:(keyword("maximum") _)? name:shard()? {
+            SpanningTree::compute(access.graph(), maximum.is_some(), name)
+        }
+
+        rule fetch_order() -> GraphResults = _ keyword("fetch") _ keyword("order") _ name:name() {
+            Order::sort(access.graph(), name)
+        }
+
+        rule shard() -> &'input str = keyword("for") _ name:name() { name }
+
+        rule centrality_type() -> CentralityType
+            = keyword("degree") { CentralityType::Degree }
+            / keyword("weighted") { CentralityType::WeightedDegree }
+            / keyword("betweenness") { CentralityType::Betweenness }
+
+        rule export_graph() -> GraphResults = _ keyword("export") _ keyword("graph") _ format:export_format() _ path:path() {
+            Export::export_graph(access.graph(), format, &path, access.chain().encryption.as_ref())
+        }
+
+        rule export_format() -> ExportFormat
+            = keyword("graphml") { ExportFormat::GraphML }
+            / keyword("dot") { ExportFormat::Dot }
+
+        rule import_nodes() -> GraphResults = exclusive() _ keyword("import") _ keyword("nodes") _ path:path() _ keyword("as") _ name:name() {
+            let (graph, chain) = access.write();
+            CsvImport::import_nodes(graph, chain, name, &path)
+        }
+
+        rule import_edges() -> GraphResults = exclusive() _ keyword("import") _ keyword("edges") _ path:path() {
+            let (graph, chain) = access.write();
+            CsvImport::import_edges(graph, chain, &path)
+        }
+
+        rule export_data() -> GraphResults = _ keyword("export") _ keyword("data") _ path:path() {
+            Export::export_data(access.graph(), &path, access.chain().encryption.as_ref())
+        }
+
+        rule import_data() -> GraphResults = exclusive() _ keyword("import") _ keyword("data") _ path:path() {
+            let (graph, chain) = access.write();
+            NdjsonImport::import_data(graph, chain, &path)
+        }
+
+        rule add_node() -> GraphResults = exclusive() _ keyword("add") _ keyword("node") _ name:name() _ attributes:attributes()? _ ttl:ttl()? {
+            let expires_at = ttl.transpose()?.map(|ttl| Expiry::now().saturating_add(ttl));
+            QueryProcessor::add_node(access, name, attributes, expires_at)
+        }
+
+        // Triggers fire once for each added node
+        rule add_nodes() -> GraphResults = exclusive() _ keyword("add") _ keyword("nodes") _ name:name() _ "[" _ nodes:attributes() ** (_ "," _) _ "]" {
+            let events = nodes.iter().map(|_| TriggerEvent::node(TriggerOperation::Add, name)).collect();
+
+            QueryProcessor::with_all_triggers(access, events, |graph, chain| {
+                let result = graph.add_nodes(name.to_string(), nodes.clone())?;
+
+                let changes = result.iter().map(|attributes| NodeData::from_attributes(NodeOperation::Add, name.to_string(), attributes)).collect();
+                if let Err(error) = chain.add_node_changes(changes) {
+                    eprintln!("Chain error: {error}");
+                }
+
+                Ok(result)
+            })
+        }
+
+        // Edge changes are collected in the mempool, so they are stored in the chain as batched blocks. Triggers fire once
+        // for each added connection
+        rule add_edges() -> GraphResults = exclusive() _ keyword("add") _ keyword("connections") _ "[" _ edges:connection() ** (_ "," _) _ "]" {
+            let edges = edges.into_iter().collect::<Result<Vec<Connection>, DatabaseError>>()?;
+            for ((from_name, from_attributes), (to_name, to_attributes), _) in &edges {
+                access.check_secrets(from_name, from_attributes.keys())?;
+                access.check_secrets(to_name, to_attributes.keys())?;
+            }
+            let changes: Vec<EdgeChange> = edges
+                .iter()
+                .map(|((_, from_attributes), (_, to_attributes), weight)| {
+                    (InternalNodeAttribute::get_identifier(from_attributes), InternalNodeAttribute::get_identifier(to_attributes), *weight)
+                })
+                .collect();
+            let events = edges
+                .iter()
+                .map(|((from_name, from_attributes), (to_name, to_attributes), _)| {
+                    TriggerEvent::connection(TriggerOperation::Add, (from_name, from_attributes), (to_name, to_attributes))
+                })
+                .collect();
+
+            QueryProcessor::with_all_triggers(access, events, |graph, chain| {
+                let result = graph.add_edges(edges.clone())?;
+                add_edge_changes(chain, &mut changes.clone());
+
+                Ok(result)
+            })
+        }
+
+        rule add_edge() -> GraphResults = exclusive() _ keyword("add") _ keyword("connection") _ keyword("from") _ from_name:name() _ from_attributes:attributes() _ keyword("to") _ to_name:name() _ to_attributes:attributes() _ keyword("with") _ keyword("weight") _ weight:weight() _ ttl:ttl()? {
+            access.check_secrets(from_name, from_attributes.keys())?;
+            access.check_secrets(to_name, to_attributes.keys())?;
+            let (weight, expires_at) = (weight?, ttl.transpose()?.map(|ttl| Expiry::now().saturating_add(ttl)));
+
+            QueryProcessor::with_triggers(access, TriggerEvent::connection(TriggerOperation::Add, (from_name, &from_attributes), (to_name, &to_attributes)), |graph, chain| {
+                let result = graph.add_edge((from_name.to_string(), from_attributes.clone()), (to_name.to_string(), to_attributes.clone()), weight);
+
+                if result.is_ok() {
+                    let (from_id, to_id) = (InternalNodeAttribute::get_identifier(&from_attributes), InternalNodeAttribute::get_identifier(&to_attributes));
+                    if let Some(expires_at) = expires_at {
+                        let item = Expiring::Connection((from_name.to_string(), from_id.clone()), (to_name.to_string(), to_id.clone()));
+                        graph.expiry.schedule_at(item, expires_at, true);
+                    }
+
+                    if let Err(error) = chain.add_edge_data(EdgeData::new(from_id, to_id, weight).with_expiry(expires_at)) {
+                        eprintln!("Chain error: {error}");
+                    }
+                }
+
+                result
+            })
+        }
+
+        rule update_node() -> GraphResults = exclusive() _ keyword("update") _ keyword("node") _ name:name() _ node:attributes() _ keyword("set") _ attributes:attributes() _ conditions:precondition()? {
+            access.check_secrets(name, node.keys().chain(conditions.iter().flatten().map(|condition| &condition.attribute)))?;
+            QueryProcessor::update_node_if(access, name, (node, attributes), true, conditions.unwrap_or_default())
+        }
+
+        rule replace_node() -> GraphResults = exclusive() _ keyword("replace") _ keyword("node") _ name:name() _ node:attributes() _ keyword("with") _ attributes:attributes() _ conditions:precondition()? {
+            access.check_secrets(name, node.keys().chain(conditions.iter().flatten().map(|condition| &condition.attribute)))?;
+            QueryProcessor::update_node_if(access, name, (node, attributes), false, conditions.unwrap_or_default())
+        }
+
+        // Update without set or with is ambiguous, as it's not clear if attributes which are not given should be kept
+        rule ambiguous_update() -> GraphResults = _ keyword("update") _ keyword("node") _ name:name() _ attributes:attributes() {
+            Err(DatabaseError::InvalidQuery(format!("use update node {name}($id=\"...\") set (...) or replace node {name}($id=\"...\") with (...)")))
+        }
+
+        rule upsert_node() -> GraphResults = exclusive() _ keyword("upsert") _ keyword("node") _ name:name() _ attributes:attributes() _ keyword("on") _ "(" _ key:attribute_name() ** (_ "," _) _ ")" {
+            access.check_secrets(name, &key)?;
+            QueryProcessor::upsert_node(access, name, attributes, key)
+        }
+
+        rule update_edge() -> GraphResults = exclusive() _ keyword("update") _ keyword("connection") _ keyword("from") _ from_name:name() _ from_attributes:attributes() _ keyword("to") _ to_name:name() _ to_attributes:attributes() _ keyword("with") _ keyword("weight") _ weight:weight()  {
+            access.check_secrets(from_name, from_attributes.keys())?;
+            access.check_secrets(to_name, to_attributes.keys())?;
+            let weight = weight?;
+
+            QueryProcessor::with_triggers(access, TriggerEvent::connection(TriggerOperation::Update, (from_name, &from_attributes), (to_name, &to_attributes)), |graph, chain| {
+                let result = graph.update_edge((from_name.to_string(), from_attributes.clone()), (to_name.to_string(), to_attributes.clone()), weight);
+
+                if result.is_ok() {
+                    if let Err(error) = chain.add_edge_change(InternalNodeAttribute::get_identifier(&from_attributes),InternalNodeAttribute::get_identifier(&to_attributes), weight) {
+                        eprintln!("Chain error: {error}");
+                    }
+                }
+
+                result
+            })
+        }
+
+        // Weight is read and changed under exclusive access, so concurrent increments are not lost
+        rule increment_edge() -> GraphResults = exclusive() _ sign:increment() _ keyword("connection") _ keyword("from") _ from_name:name() _ from_attributes:attributes() _ keyword("to") _ to_name:name() _ to_attributes:attributes() _ keyword("by") _ amount:amount() {
+            access.check_secrets(from_name, from_attributes.keys())?;
+            access.check_secrets(to_name, to_attributes.keys())?;
+            let amount = sign * amount?;
+
+            QueryProcessor::with_triggers(access, TriggerEvent::connection(TriggerOperation::Update, (from_name, &from_attributes), (to_name, &to_attributes)), |graph, chain| {
+                let result = graph.increment_edge((from_name.to_string(), from_attributes.clone()), (to_name.to_string(), to_attributes.clone()), amount);
+
+                if let Ok(items) = &result {
+                    let weight = items[0][InternalNodeAttribute::WEIGHT_ATTRIBUTE].parse().unwrap_or_default();
+                    if let Err(error) = chain.add_edge_change(InternalNodeAttribute::get_identifier(&from_attributes),InternalNodeAttribute::get_identifier(&to_attributes), weight) {
+                        eprintln!("Chain error: {error}");
+                    }
+                }
+
+                result
+            })
+        }
+
+        rule delete_node() -> GraphResults = exclusive() _ keyword("delete") _ keyword("node") _ name:name() _ attributes:attributes() {
+            access.check_secrets(name, attributes.keys())?;
+            QueryProcessor::with_triggers(access, TriggerEvent::node(TriggerOperation::Delete, name), |graph, chain| {
+                let result = graph.delete_node(name.to_string(), attributes.clone());
+
+                if result.is_ok() {
+                    if let Err(error) = chain.add_node_change(NodeData::from_attributes(NodeOperation::Delete, name.to_string(), &attributes)) {
+                        eprintln!("Chain error: {error}");
+                    }
+                }
+
+                result
+            })
+        }
+
+        rule soft_delete_node() -> GraphResults = exclusive() _ keyword("delete") _ keyword("node") _ name:name() _ attributes:attributes() _ keyword("soft") {
+            access.check_secrets(name, attributes.keys())?;
+            QueryProcessor::with_triggers(access, TriggerEvent::node(TriggerOperation::Delete, name), |graph, chain| {
+                let result = graph.soft_delete_node(name.to_string(), attributes.clone(), Expiry::now());
+
+                if result.is_ok() {
+                    if let Err(error) = chain.add_node_change(NodeData::from_attributes(NodeOperation::SoftDelete, name.to_string(), &attributes)) {
+                        eprintln!("Chain error: {error}");
+                    }
+
+                    graph.tombstones.schedule(name.to_string(), InternalNodeAttribute::get_identifier(&attributes));
+                }
+
+                result
+            })
+        }
+
+        rule restore_node() -> GraphResults = exclusive() _ keyword("restore") _ keyword("node") _ name:name() _ attributes:attributes() {
+            access.check_secrets(name, attributes.keys())?;
+            let (graph, chain) = access.write();
+            let result = graph.restore_node(name.to_string(), attributes, Expiry::now())?;
+
+            if let Err(error) = chain.add_node_change(NodeData::from_attributes(NodeOperation::Restore, name.to_string(), &result[0])) {
+                eprintln!("Chain error: {error}");
+            }
+
+            Ok(result)
+        }
+
+        rule delete_edge() -> GraphResults = exclusive() _ keyword("delete") _ keyword("connection") _ keyword("from") _ from_name:name() _ from_attributes:attributes() _ keyword("to") _ to_name:name() _ to_attributes:attributes() {
+            access.check_secrets(from_name, from_attributes.keys())?;
+            access.check_secrets(to_name, to_attributes.keys())?;
+            QueryProcessor::with_triggers(access, TriggerEvent::connection(TriggerOperation::Delete, (from_name, &from_attributes), (to_name, &to_attributes)), |graph, chain| {
+                let result = graph.delete_edge((from_name.to_string(), from_attributes.clone()), (to_name.to_string(), to_attributes.clone()));
+
+                if result.is_ok() {
+                    if let Err(error) = chain.add_edge_change(InternalNodeAttribute::get_identifier(&from_attributes),InternalNodeAttribute::get_identifier(&to_attributes), 0) {
+                        eprintln!("Chain error: {error}");
+                    }
+                }
+
+                result
+            })
+        }
+
+        // Triggers execute only node and connection commands, so they can be verified under savepoint of the graph
+        rule create_trigger() -> GraphResults = exclusive() _ keyword("create") _ keyword("trigger") _ keyword("on") _ operation:trigger_operation() _ target:trigger_target() _ keyword("do") _ command:$((keyword("add") / keyword("update") / keyword("replace") / keyword("delete")) [_]+) {
+            let (graph, _) = access.write();
+            graph.create_trigger(Trigger { operation, target, command: command.trim().to_string() })
+        }
+
+        rule fetch_triggers() -> GraphResults = _ keyword("fetch") _ keyword("triggers") {
+            access.graph().fetch_triggers()
+        }
+
+        rule drop_trigger() -> GraphResults = exclusive() _ keyword("drop") _ keyword("trigger") _ identifier:$(['0'..='9']+) {
+            let (graph, _) = access.write();
+            graph.drop_trigger(identifier.parse().map_err(|_| DatabaseError::TriggerNotFound(identifier.to_string()))?)
+        }
+
+        rule create_view() -> GraphResults = exclusive() _ keyword("create") _ keyword("view") _ view:name() _ keyword("as") _ keyword("fetch") _ keyword("node") _ name:name() _ attributes:attributes() _ joins:joins() {
+            let (graph, _) = access.write();
+            graph.create_view(view.to_string(), name.to_string(), attributes, joins?)
+        }
+
+        rule fetch_view() -> GraphResults = _ keyword("fetch") _ keyword("view") _ view:name() {
+            access.graph().fetch_view(view)
+        }
+
+        rule drop_view() -> GraphResults = exclusive() _ keyword("drop") _ keyword("view") _ view:name() {
+            let (graph, _) = access.write();
+            graph.drop_view(view)
+        }
+
+        rule trigger_operation() -> TriggerOperation
+            = keyword("add") { TriggerOperation::Add }
+            / keyword("update") { TriggerOperation::Update }
+            / keyword("delete") { TriggerOperation::Delete }
+
+        rule trigger_target() -> TriggerTarget
+            = keyword("node") _ name:name() { TriggerTarget::Node(name.to_string()) }
+            / keyword("connection") _ from:name() _ "->" _ to:name() { TriggerTarget::Connection(from.to_string(), to.to_string()) }
+
+        rule precondition() -> Vec<Condition> = keyword("if") _ "(" _ conditions:condition() ** (_ "," _) _ ")" { conditions }
+
+        rule connection() -> Result<Connection, DatabaseError> = keyword("from") _ from_name:name() _ from_attributes:attributes() _ keyword("to") _ to_name:name() _ to_attributes:attributes() _ keyword("with") _ keyword("weight") _ weight:weight() {
+            Ok(((from_name.to_string(), from_attributes), (to_name.to_string(), to_attributes), weight?))
+        }
+
+        // Self loops are allowed by default
+        rule self_loops() -> bool = allow:(keyword("allow") { true } / keyword("deny") { false }) _ keyword("self") _ keyword("loops") { allow }
+
+        rule agent() -> Vec<Condition> = _ keyword("with") _ keyword("agent") _ "(" _ conditions:condition() ** (_ "," _) _ ")" { conditions }
+
+        rule condition() -> Condition = name:attribute_name() _ operator:operator() _ value:attribute_value() {
+            Condition::new(name.to_string(), operator, value)
+        }
+
+        rule operator() -> Operator
+            = "!=" { Operator::NotEqual }
+            / ">=" { Operator::GreaterOrEqual }
+            / "<=" { Operator::LessOrEqual }
+            / "=" { Operator::Equal }
+            / ">" { Operator::Greater }
+            / "<" { Operator::Less }
+            / "~" { Operator::Matches }
+
+        rule joins() -> Result<Vec<(String, i8)>, DatabaseError> = joins:join() ** _ { joins.into_iter().collect() }
+
+        rule join() -> Result<(String, i8), DatabaseError> = _ keyword("join") _ name:name() _ "(" _ "$weight" _ ">" _ "\"" weight:weight() "\"" _ ")" { Ok((name.to_string(), weight?)) }
+
+        rule attributes() -> FxHashMap<String, String> = "(" _ attributes:attribute() ** (_ "," _) _ ")" {
+            attributes.into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect::<FxHashMap<String, String>>()
+        }
+
+        rule attribute() -> (&'input str, String) = name:attribute_name() _ "=" _ value:attribute_value() { (name, value) }
+
+        rule attribute_name() -> &'input str = $(['a'..='z' | 'A'..='Z' | '0'..='9' | '$' | '*']+)
+
+        rule attribute_value() -> String = "\"" parts:value_part()* "\"" { parts.concat() }
+
+        // Quote, backslash and new line are escaped by backslash, other escapes (e.g. in regex) are kept as they are
+        rule value_part() -> &'input str
+            = "\\\"" { "\"" }
+            / "\\\\" { "\\" }
+            / "\\n" { "\n" }
+            / $("\\"? [^'"' | '\\'])
+
+        rule attribute_definitions() -> Vec<(&'input str, bool)> = "(" _ names:attribute_definition() ** (_ "," _) _ ")" { names }
+
+        // Secret attribute is marked with `secret` after its name, e.g. `email secret`
+        rule attribute_definition() -> (&'input str, bool) = name:$(['a'..='z' | 'A'..='Z' | '0'..='9' | '*']+) secret:(_ keyword("secret"))? {
+            (name, secret.is_some())
+        }
+
+        rule path() -> String = attribute_value() / path:$([^' ' | '"']+) { path.to_string() }
+
+        rule name() -> &'input str = $(['a'..='z' | 'A'..='Z']+)
+
+        // Weight is parsed as any signed number, so out of range weight is reported instead of failed parsing
+        rule weight() -> Result<i8, DatabaseError> = n:$("-"? ['0'..='9']+) { n.parse().map_err(|_| DatabaseError::InvalidWeight(n.to_string())) }
+
+        rule ttl() -> Result<u64, DatabaseError> = keyword("with") _ keyword("ttl") _ n:$(['0'..='9']+) {
+            n.parse().map_err(|_| DatabaseError::InvalidQuery(format!("time to live {n} is too large")))
+        }
+
+        rule role() -> Result<Role, DatabaseError> = role:name() {
+            Role::from_name(role).ok_or(DatabaseError::InvalidQuery(format!("role {role} is not known, use reader, writer or admin")))
+        }
+
+        // Duration in seconds, given with unit (e.g. `90s`, `15m`, `12h` or `30d`)
+        rule duration() -> Result<u64, DatabaseError> = n:$(['0'..='9']+) unit:$(['s' | 'm' | 'h' | 'd']) {
+            let unit = match unit {
+                "s" => 1,
+                "m" => 60,
+                "h" => 60 * 60,
+                _ => 24 * 60 * 60,
+            };
+            n.parse::<u64>()
+                .ok()
+                .and_then(|n| n.checked_mul(unit))
+                .ok_or(DatabaseError::InvalidQuery(format!("duration {n} is too large")))
+        }
+
+        rule increment() -> i64 = keyword("increment") { 1 } / keyword("decrement") { -1 }
+
+        rule amount() -> Result<i64, DatabaseError> = n:$(['0'..='9']+) { n.parse().map_err(|_| DatabaseError::InvalidWeight(n.to_string())) }
+
+        rule depth() -> Result<usize, DatabaseError> = n:$(['0'..='9']+) { n.parse().map_err(|_| DatabaseError::InvalidQuery(format!("depth {n} is too large"))) }
+
+        // Commands whose rows are blocks of the chain, which can be read from the chain one by one when result is streamed
+        pub rule block_heights() -> Result<(Range<usize>, bool), DatabaseError> = result:(
+            _ keyword("fetch") _ keyword("connection") _ keyword("chain") { Ok((0..access.chain().blocks.len(), false)) } /
+            _ keyword("fetch") _ keyword("blocks") _ keyword("from") _ from:height() _ keyword("to") _ to:height() { Ok((access.chain().blocks_range(from?, to?)?, true)) }
+        ) _ ";"? _ { result }
+
+        rule height() -> Result<usize, DatabaseError> = n:$(['0'..='9']+) { n.parse().map_err(|_| DatabaseError::InvalidQuery(format!("block height {n} is too large"))) }
+
+        // Commands which change graph or chain can't be parsed with shared access
+        rule exclusive() = {?
+            if access.is_exclusive() { Ok(()) } else { Err("exclusive access") }
+        }
+    }
+}
+
+/// Access to graph and chain given to the parser. Commands which change them can be parsed only with exclusive access.
+/// Access also records when the parsed command first used graph or chain, which ends parsing of the command and starts
+/// its execution (both happen in the same pass of the parser).
+pub struct Access<'a> {
+    target: Target<'a>,
+    executed_at: Cell<Option<Instant>>,
+    /// Role of the client, which is also checked for commands executed by triggers
+    role: Option<Role>,
+    secrets: bool,
+}
+
+enum Target<'a> {
+    Shared(&'a Graph, &'a Chain),
+    Exclusive(&'a mut Graph, &'a mut Chain),
+}
+
+impl<'a> Access<'a> {
+    pub fn shared(graph: &'a Graph, chain: &'a Chain) -> Access<'a> {
+        Access {
+            target: Target::Shared(graph, chain),
+            executed_at: Cell::new(None),
+            role: Some(Role::Admin),
+            secrets: true,
+        }
+    }
 
-        pub rule command() -> GraphResults = define_node() / add_node() / update_node() / delete_node() / add_edge() / update_edge() / delete_edge() / fetch_node() / fetch_connection()
+    pub fn exclusive(graph: &'a mut Graph, chain: &'a mut Chain) -> Access<'a> {
+        Access {
+            target: Target::Exclusive(graph, chain),
+            executed_at: Cell::new(None),
+            role: Some(Role::Admin),
+            secrets: true,
+        }
+    }
+
+    /// Access of client which can't read secret attributes, so it also can't select nodes by them (e.g. with
+    /// `where (email~"^a")`), as selected nodes would reveal their values.
+    pub fn without_secrets(mut self) -> Access<'a> {
+        self.secrets = false;
+        self
+    }
+
+    /// Access of client with given role, where only admin can read secret attributes.
+    pub fn with_role(self, role: Option<Role>) -> Access<'a> {
+        let access = Access { role, ..self };
+
+        if role < Some(Role::Admin) {
+            access.without_secrets()
+        } else {
+            access
+        }
+    }
+
+    pub fn graph(&self) -> &Graph {
+        self.execute();
+
+        match &self.target {
+            Target::Shared(graph, _) => graph,
+            Target::Exclusive(graph, _) => graph,
+        }
+    }
+
+    pub fn chain(&self) -> &Chain {
+        self.execute();
+
+        match &self.target {
+            Target::Shared(_, chain) => chain,
+            Target::Exclusive(_, chain) => chain,
+        }
+    }
+
+    pub fn is_exclusive(&self) -> bool {
+        matches!(self.target, Target::Exclusive(..))
+    }
+
+    pub fn has_secrets(&self) -> bool {
+        self.secrets
+    }
+
+    /// Check that the node isn't selected by its secret attributes, unless access can read them.
+    fn check_secrets<S: AsRef<str>>(&self, name: &str, attributes: impl IntoIterator<Item = S>) -> Result<(), DatabaseError> {
+        let graph: &Graph = match &self.target {
+            Target::Shared(graph, _) => graph,
+            Target::Exclusive(graph, _) => graph,
+        };
+
+        match attributes
+            .into_iter()
+            .find(|attribute| !self.secrets && graph.is_secret(name, attribute.as_ref()))
+        {
+            Some(attribute) => Err(DatabaseError::PermissionDenied(format!(
+                "secret attribute {} of {name} can be used only by admin",
+                attribute.as_ref()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Time when execution of the parsed command started, if the command was parsed.
+    pub fn executed_at(&self) -> Option<Instant> {
+        self.executed_at.get()
+    }
+
+    fn write(&mut self) -> (&mut Graph, &mut Chain) {
+        self.execute();
+
+        match &mut self.target {
+            Target::Shared(..) => unreachable!("Exclusive access is checked by grammar"),
+            Target::Exclusive(graph, chain) => (graph, chain),
+        }
+    }
+
+    fn execute(&self) {
+        if self.executed_at.get().is_none() {
+            self.executed_at.set(Some(Instant::now()));
+        }
+    }
+}
+
+pub struct QueryProcessor;
+
+impl QueryProcessor {
+    pub fn parse_command(graph: &mut Graph, chain: &mut Chain, command: &str) -> Result<GraphResults, ParseError<LineCol>> {
+        Self::parse_with_access(&mut Access::exclusive(graph, chain), command)
+    }
+
+    /// Parse command with given access. With shared access, parsing of commands which change graph or chain fails.
+    pub fn parse_with_access(access: &mut Access, command: &str) -> Result<GraphResults, ParseError<LineCol>> {
+        #[cfg(feature = "cypher")]
+        if Cypher::is_cypher(command) {
+            return Cypher::execute(access.graph(), command, access.has_secrets());
+        }
+
+        query_parser::command(command, access)
+    }
+
+    fn add_node(access: &mut Access, name: &str, attributes: Option<FxHashMap<String, String>>, expires_at: Option<u64>) -> GraphResults {
+        QueryProcessor::with_triggers(access, TriggerEvent::node(TriggerOperation::Add, name), |graph, chain| {
+            let result = graph.add_node(name.to_string(), attributes.clone().unwrap_or_default());
+
+            if let Ok(items) = &result {
+                let change = NodeData::from_attributes(NodeOperation::Add, name.to_string(), items.first().unwrap()).with_expiry(expires_at);
+                if let Some(expires_at) = expires_at {
+                    graph
+                        .expiry
+                        .schedule_at(Expiring::Node(name.to_string(), change.id.clone()), expires_at, true);
+                }
+
+                if let Err(error) = chain.add_node_change(change) {
+                    eprintln!("Chain error: {error}");
+                }
+            }
+
+            result
+        })
+    }
 
-        rule define_node() -> GraphResults = _ "define" _ "node" _ name:name() _ attributes:attribute_definitions() _ conditions:agent()? {
-            let result = graph.create_definition(name.to_string(), attributes.iter().map(|attribute| attribute.to_string()).collect());
+    /// Set given attributes of the node (or replace all its attributes if `merge` is false). Change is recorded to the
+    /// chain with all resulting attributes, so peers replay it the same way regardless of how it was made.
+    fn update_node(access: &mut Access, name: &str, attributes: FxHashMap<String, String>, merge: bool) -> GraphResults {
+        QueryProcessor::with_triggers(access, TriggerEvent::node(TriggerOperation::Update, name), |graph, chain| {
+            let result = if merge {
+                graph.merge_node(name.to_string(), attributes.clone())
+            } else {
+                graph.update_node(name.to_string(), attributes.clone())
+            };
 
-            if result.is_ok() && conditions.is_some() {
-                chain.define_agent(name.to_string(), conditions.unwrap())
+            if result.is_ok() {
+                // Result also contains previous values, so the change is recorded from the updated node
+                let identifier = InternalNodeAttribute::get_identifier(&attributes);
+                let updated = graph.get_by_id(&name.to_string(), &identifier)?.attributes.clone();
+                if let Err(error) = chain.add_node_change(NodeData::from_attributes(NodeOperation::Update, name.to_string(), &updated)) {
+                    eprintln!("Chain error: {error}");
+                }
             }
 
-            result
+            result
+        })
+    }
+
+    /// Update node (given by its identifier) only if its current attributes meet all conditions, so client can detect
+    /// that the node was changed since it was fetched. If conditions are given, result contains `$applied` attribute,
+    /// and if the update was not applied, it contains current attributes of the node.
+    fn update_node_if(
+        access: &mut Access,
+        name: &str,
+        (node, mut attributes): (FxHashMap<String, String>, FxHashMap<String, String>),
+        merge: bool,
+        conditions: Vec<Condition>,
+    ) -> GraphResults {
+        let identifier = InternalNodeAttribute::require_identifier(&node)?;
+        attributes.insert(InternalNodeAttribute::ID_ATTRIBUTE.to_string(), identifier.clone());
+        if conditions.is_empty() {
+            return Self::update_node(access, name, attributes, merge);
+        }
+
+        let node = access.graph().get_visible(&name.to_string(), &identifier)?;
+        let (mut result, applied) = if conditions.iter().all(|condition| condition.is_met(&node.attributes)) {
+            (Self::update_node(access, name, attributes, merge)?, true)
+        } else {
+            (vec![node.attributes.clone()], false)
+        };
+
+        if let Some(row) = result.first_mut() {
+            row.insert("$applied".to_string(), applied.to_string());
+        }
+        Ok(result)
+    }
+
+    /// Set attributes of the node whose key attributes are equal to given attributes, or add new node if there is no
+    /// such node. Result contains `$action` attribute with value `updated` or `created`.
+    fn upsert_node(access: &mut Access, name: &str, mut attributes: FxHashMap<String, String>, key: Vec<&str>) -> GraphResults {
+        let (mut result, action) = match access.graph().find_by_key(name, &attributes, &key)? {
+            Some(identifier) => {
+                attributes.insert(InternalNodeAttribute::ID_ATTRIBUTE.to_string(), identifier);
+                (Self::update_node(access, name, attributes, true)?, "updated")
+            }
+            None => (Self::add_node(access, name, Some(attributes), None)?, "created"),
+        };
+
+        if let Some(row) = result.first_mut() {
+            row.insert("$action".to_string(), action.to_string());
+        }
+        Ok(result)
+    }
+
+    /// Execute node or connection change together with triggers fired by it. Change and triggered commands are first
+    /// executed with scratch chain and rolled back (only changed nodes are recorded, so the graph isn't copied), and
+    /// only if all of them succeed, they are executed again and recorded to the chain, so failed trigger leaves graph
+    /// and chain unchanged. Commands executed by triggers don't fire other triggers.
+    fn with_triggers(access: &mut Access, event: TriggerEvent, change: impl Fn(&mut Graph, &mut Chain) -> GraphResults) -> GraphResults {
+        Self::with_all_triggers(access, vec![event], change)
+    }
+
+    /// Execute change of multiple nodes or connections the same way as `with_triggers`, where given events match rows
+    /// of the change result in their order (e.g. one event for each node added by `add nodes`).
+    fn with_all_triggers(access: &mut Access, events: Vec<TriggerEvent>, change: impl Fn(&mut Graph, &mut Chain) -> GraphResults) -> GraphResults {
+        let client = (access.role, access.secrets);
+        let (graph, chain) = access.write();
+        if !graph.triggers.iter().any(|trigger| events.iter().any(|event| trigger.fires_on(event))) {
+            return change(graph, chain);
+        }
+
+        let savepoint = graph.savepoint();
+        let checked = Self::execute_triggered(graph, &mut Chain::default(), client, &events, &change);
+        graph.rollback(savepoint);
+
+        checked?;
+        Self::execute_triggered(graph, chain, client, &events, &change)
+    }
+
+    /// Execute change and commands of triggers which fire on it, with the same role and access to secret attributes as
+    /// the client which made the change.
+    fn execute_triggered(
+        graph: &mut Graph,
+        chain: &mut Chain,
+        (role, secrets): (Option<Role>, bool),
+        events: &[TriggerEvent],
+        change: &impl Fn(&mut Graph, &mut Chain) -> GraphResults,
+    ) -> GraphResults {
+        let result = change(graph, chain)?;
+
+        let triggers = mem::take(&mut graph.triggers);
+        let triggered = events.iter().zip(&result).try_for_each(|(event, changed)| {
+            triggers.iter().filter(|trigger| trigger.fires_on(event)).try_for_each(|trigger| {
+                let command = trigger.command_for(graph, event, changed);
+                Role::authorize(role, &command)?;
+
+                let mut access = Access {
+                    role,
+                    secrets,
+                    ..Access::exclusive(graph, chain)
+                };
+                match Self::parse_with_access(&mut access, &command) {
+                    Ok(result) => result.map(|_| ()),
+                    Err(error) => Err(DatabaseError::InvalidQuery(format!("triggered command {command} is not valid ({error})"))),
+                }
+            })
+        });
+        graph.triggers = triggers;
+
+        triggered.map(|_| result)
+    }
+
+    /// Execute command (by given parser) with optional request id - given either as prefix (`#42 add node ...`) or in
+    /// JSON envelope (`{"id":42,"command":"add node ..."}`), and format its response as single JSON line. Id is echoed
+    /// in the response (`{"id":42,"result":[...]}`), so clients can correlate responses with their requests. Errors
+    /// are returned as `{"error":{"code":"NODE_NOT_FOUND","message":"..."}}`, so clients can handle them
+    /// programmatically.
+    pub fn handle_request(parse: impl FnOnce(&str) -> Result<GraphResults, ParseError<LineCol>>, line: &str) -> String {
+        let (id, command) = match Self::parse_request(line) {
+            Ok(request) => request,
+            Err(error) => return error.to_string(),
+        };
+
+        match (Self::response(parse, &command), id) {
+            (response, None) => response.to_string(),
+            (Value::Object(error), id) => Self::with_id(Value::Object(error), id).to_string(),
+            (result, Some(id)) => serde_json::json!({ "id": id, "result": result }).to_string(),
+        }
+    }
+
+    /// Execute request the same way as `handle_request`, but write each result row as soon as it is produced, as
+    /// separate JSON line, followed by `{"end":true,"rows":2}` terminator record (with request id, if it is given).
+    /// Large results (e.g. whole chain) are therefore never collected or serialized into one line. Errors are written
+    /// as one line.
+    pub fn stream_request(parse: impl FnOnce(&str) -> Result<GraphRows, ParseError<LineCol>>, line: &str, output: &mut impl Write) -> io::Result<()> {
+        let (id, command) = match Self::parse_request(line) {
+            Ok(request) => request,
+            Err(error) => return writeln!(output, "{error}"),
+        };
+
+        let rows = match parse(&command) {
+            Err(error) => return writeln!(output, "{}", Self::with_id(Self::error_response("PARSE_ERROR", &error.to_string()), id)),
+            Ok(Err(error)) => return writeln!(output, "{}", Self::with_id(Self::error_response(error.code(), &error.to_string()), id)),
+            Ok(Ok(rows)) => rows,
+        };
+
+        let mut count = 0;
+        for row in rows {
+            serde_json::to_writer(&mut *output, &row)?;
+            writeln!(output)?;
+            count += 1;
+        }
+
+        writeln!(output, "{}", Self::with_id(serde_json::json!({ "end": true, "rows": count }), id))
+    }
+
+    /// Heights of blocks which are rows of given command (`fetch connection chain` or `fetch blocks`), and whether
+    /// their data is decoded, or none if rows of the command aren't blocks of the chain.
+    pub fn block_heights(access: &mut Access, command: &str) -> Option<Result<(Range<usize>, bool), DatabaseError>> {
+        query_parser::block_heights(command, access).ok()
+    }
+
+    /// Rows of already collected results.
+    pub fn rows(result: Result<GraphResults, ParseError<LineCol>>) -> Result<GraphRows, ParseError<LineCol>> {
+        result.map(|result| result.map(|rows| Box::new(rows.into_iter()) as Box<dyn Iterator<Item = _>>))
+    }
+
+    /// Split request line to optional request id and command.
+    fn parse_request(line: &str) -> Result<(Option<Value>, String), Value> {
+        let line = line.trim_start();
+
+        if line.starts_with('{') {
+            serde_json::from_str::<RequestEnvelope>(line)
+                .map(|envelope| (Some(envelope.id), envelope.command))
+                .map_err(|error| Self::error_response("PARSE_ERROR", &error.to_string()))
+        } else if let Some(line) = line.strip_prefix('#') {
+            let (id, command) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            Ok((Some(Value::String(id.to_string())), command.to_string()))
+        } else {
+            Ok((None, line.to_string()))
+        }
+    }
+
+    fn with_id(response: Value, id: Option<Value>) -> Value {
+        match (response, id) {
+            (Value::Object(mut response), Some(id)) => {
+                response.insert("id".to_string(), id);
+                Value::Object(response)
+            }
+            (response, _) => response,
+        }
+    }
+
+    fn response(parse: impl FnOnce(&str) -> Result<GraphResults, ParseError<LineCol>>, command: &str) -> Value {
+        match parse(command) {
+            Err(error) => Self::error_response("PARSE_ERROR", &error.to_string()),
+            Ok(Err(error)) => Self::error_response(error.code(), &error.to_string()),
+            Ok(Ok(items)) => serde_json::to_value(&items).unwrap_or_else(|error| Self::error_response("SERIALIZATION_ERROR", &error.to_string())),
+        }
+    }
+
+    pub fn error_response(code: &str, message: &str) -> Value {
+        serde_json::json!({ "error": { "code": code, "message": message } })
+    }
+}
+
+#[derive(Deserialize)]
+struct RequestEnvelope {
+    id: Value,
+    command: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access::ClientToken;
+    use crate::chain::block::{Block, BlockDataType};
+    use crate::chain::encryption::StorageEncryption;
+    use crate::chain::error::ChainError;
+    use crate::database::Database;
+    use crate::graph::attribute::InternalNodeAttribute;
+    use crate::graph::condition::{Condition, Operator};
+    use crate::graph::error::DatabaseError;
+    use crate::graph::expiry::Expiry;
+    use crate::graph::generator::{IdGenerator, IdStrategy};
+    use std::sync::Arc;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn should_echo_request_id() {
+        // Given
+        let database = Database::default();
+
+        // When
+        let responses = [
+            database.handle_request("#42 define node User(name)"),
+            database.handle_request(r#"{"id":7,"command":"fetch node User($id=\"1\")"}"#),
+        ];
+
+        // Then
+        let responses: Vec<Value> = responses.iter().map(|response| serde_json::from_str(response).unwrap()).collect();
+        assert_eq!(responses[0]["id"], "42");
+        assert_eq!(responses[0]["result"][0]["name"], "*");
+        assert_eq!(responses[1]["id"], 7);
+        assert_eq!(responses[1]["error"]["code"], "NODE_NOT_FOUND");
+    }
+
+    #[test]
+    fn should_stream_result_rows() {
+        // Given
+        let database = Arc::new(Database::default());
+        let mut output = vec![];
+
+        // When
+        database.stream_request("#1 fetch connection chain", &mut output).unwrap();
+        database.stream_request("fetch node User($id=\"1\")", &mut output).unwrap();
+
+        // Then
+        let lines: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["id"], "0");
+        assert_eq!(lines[1], serde_json::json!({ "id": "1", "end": true, "rows": 1 }));
+        assert_eq!(lines[2]["error"]["code"], "NODE_NOT_FOUND");
+    }
+
+    #[test]
+    fn should_return_error_response() {
+        // Given
+        let database = Database::default();
+
+        // When
+        let responses = [
+            database.handle_request("fetch node User($id=\"1\")"),
+            database.handle_request("fetch everything"),
+        ];
+
+        // Then
+        let responses: Vec<Value> = responses.iter().map(|response| serde_json::from_str(response).unwrap()).collect();
+        assert_eq!(responses[0]["error"]["code"], "NODE_NOT_FOUND");
+        assert!(responses[0]["error"]["message"].as_str().unwrap().contains("User"));
+        assert_eq!(responses[1]["error"]["code"], "PARSE_ERROR");
+    }
+
+    #[test]
+    fn should_fetch_node() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+
+        let from = insert_new_node(&mut graph, "From");
+        let to = insert_new_node(&mut graph, "To");
+
+        insert_new_edge(&mut graph, from.clone(), to.clone(), 50);
+
+        let cmd = format!("fetch node From($id=\"{from}\") join To($weight>\"0\")");
+
+        // When
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, cmd.as_str());
+
+        // Then
+        assert_graph_result(
+            result,
+            vec![
+                ("$name", "From"),
+                ("$id", from.as_str()),
+                ("$edges", "1"),
+                ("To.$id", to.as_str()),
+                ("To.$name", "To"),
+                ("To.$edges", "0"),
+            ],
+        );
+    }
+
+    #[test]
+    fn should_fetch_node_at_block() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+
+        let commands = ["define node User(name)", "add node User(name=\"John\")", "add node User(name=\"Janne\")"];
+        let users: Vec<String> = commands
+            .iter()
+            .map(|command| QueryProcessor::parse_command(&mut graph, &mut chain, command).unwrap().unwrap())
+            .skip(1)
+            .map(|result| InternalNodeAttribute::get_identifier(result.first().unwrap()))
+            .collect();
+        let (from, to) = (&users[0], &users[1]);
+
+        let changes = [
+            format!("add connection from User($id=\"{from}\") to User($id=\"{to}\") with weight 50"),
+            format!("update connection from User($id=\"{from}\") to User($id=\"{to}\") with weight 20"),
+            format!("update node User($id=\"{from}\") set (name=\"Johnny\")"),
+        ];
+        for change in &changes {
+            QueryProcessor::parse_command(&mut graph, &mut chain, change).unwrap().unwrap();
+        }
+
+        // When
+        let before_edge = QueryProcessor::parse_command(&mut graph, &mut chain, &format!("fetch node User($id=\"{from}\") at block 6"));
+        let after_edge = QueryProcessor::parse_command(&mut graph, &mut chain, &format!("fetch node User($id=\"{from}\") at block 8"))
+            .unwrap()
+            .unwrap();
+        let missing = QueryProcessor::parse_command(&mut graph, &mut chain, &format!("fetch node User($id=\"{from}\") at block 100")).unwrap();
+
+        // Then
+        assert_graph_result(
+            before_edge,
+            vec![
+                (InternalNodeAttribute::ID_ATTRIBUTE, from.as_str()),
+                (InternalNodeAttribute::NAME_ATTRIBUTE, "User"),
+                (InternalNodeAttribute::EDGE_COUNT_ATTRIBUTE, "0"),
+                ("name", "John"),
+            ],
+        );
+        assert_eq!(after_edge.len(), 2);
+        assert_eq!(after_edge[0].get(InternalNodeAttribute::EDGE_COUNT_ATTRIBUTE).unwrap(), "1");
+        assert_eq!(after_edge[1].get(InternalNodeAttribute::TO_ID_ATTRIBUTE).unwrap(), to);
+        assert_eq!(after_edge[1].get(InternalNodeAttribute::WEIGHT_ATTRIBUTE).unwrap(), "50");
+        assert_eq!(missing.err().unwrap().code(), "BLOCK_NOT_FOUND");
+    }
+
+    #[test]
+    fn should_fetch_edge_history() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let from_id = insert_new_node(&mut graph, "From");
+        let to_id = insert_new_node(&mut graph, "To");
+
+        let edge = |command: &str| format!("{command} connection from From($id=\"{from_id}\") to To($id=\"{to_id}\")");
+        let changes = [edge("add") + " with weight 50", edge("update") + " with weight 20", edge("delete")];
+        for change in &changes {
+            QueryProcessor::parse_command(&mut graph, &mut chain, change).unwrap().unwrap();
+        }
+
+        // When
+        let result = QueryProcessor::parse_command(
+            &mut graph,
+            &mut chain,
+            &format!("fetch history from From($id=\"{from_id}\") to To($id=\"{to_id}\")"),
+        );
+
+        // Then
+        let items = result.unwrap().unwrap();
+        let weights: Vec<&str> = items
+            .iter()
+            .map(|item| item.get(InternalNodeAttribute::WEIGHT_ATTRIBUTE).unwrap().as_str())
+            .collect();
+        assert_eq!(weights, vec!["50", "20", "0"]);
+        assert_eq!(items[0].get("block").unwrap(), "1");
+        assert_eq!(items[0].get("validator").unwrap(), &chain.signer.get_public_key());
+        assert_eq!(items[0].get(InternalNodeAttribute::TO_ATTRIBUTE).unwrap(), "To");
+    }
+
+    #[test]
+    fn should_fetch_blocks() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        chain.add_edge_change("from".to_string(), "to".to_string(), 50).unwrap();
+        let hash = chain.blocks[1].hash.clone();
+
+        // When
+        let by_id = QueryProcessor::parse_command(&mut graph, &mut chain, "fetch block 1").unwrap().unwrap();
+        let by_hash = QueryProcessor::parse_command(&mut graph, &mut chain, &format!("fetch block {hash}"))
+            .unwrap()
+            .unwrap();
+        let range = QueryProcessor::parse_command(&mut graph, &mut chain, "fetch blocks from 1 to 10")
+            .unwrap()
+            .unwrap();
+        let missing = QueryProcessor::parse_command(&mut graph, &mut chain, "fetch block 10").unwrap();
+
+        // Then
+        assert_eq!(by_id, by_hash);
+        assert_eq!(by_id[0].get("hash").unwrap(), &hash);
+        assert_eq!(by_id[0].get("data.edge_data.weight").unwrap(), "50");
+        assert_eq!(range.len(), 2);
+        assert_eq!(range[1].get("data.data_type").unwrap(), "Reward");
+        assert!(matches!(missing, Err(DatabaseError::ChainError(ChainError::BlockNotFound(_)))));
+    }
+
+    #[test]
+    fn should_add_node_definition() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let cmd = "define node Person(name,premium) with agent (premium=\"true\")";
+
+        // When
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, cmd);
+
+        // Then
+        assert_graph_result(result, vec![("name", "*"), ("premium", "*")]);
+
+        assert!(graph.nodes.is_empty());
+        assert_eq!(graph.definitions.len(), 1);
+        assert!(graph.definitions.contains_key("Person"));
+
+        let conditions = graph.definitions.get("Person").unwrap();
+        assert_eq!(*conditions, vec!["name", "premium"]);
+
+        assert_eq!(chain.agent_service.agents.len(), 1);
+    }
+
+    #[test]
+    fn should_add_node_definition_with_agent_operators() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let cmd = "define node Person(name,followers) with agent (followers>\"100\",name~\"^J\",name!=\"John\")";
+
+        // When
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, cmd);
+
+        // Then
+        assert_graph_result(result, vec![("name", "*"), ("followers", "*")]);
+
+        let conditions = chain.agent_service.agents.get("Person").unwrap();
+        assert_eq!(
+            *conditions,
+            vec![
+                Condition::new("followers".to_string(), Operator::Greater, "100".to_string()),
+                Condition::new("name".to_string(), Operator::Matches, "^J".to_string()),
+                Condition::new("name".to_string(), Operator::NotEqual, "John".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_register_agent_after_adding_edge() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let playlist = insert_new_node(&mut graph, "Playlist");
+
+        let commands = [
+            "define node User(key) with agent ($edges>\"0\",$totalWeight>=\"50\")".to_string(),
+            format!("add node User(key=\"{}\")", chain.signer.get_public_key()),
+        ];
+        let user = commands
+            .iter()
+            .map(|command| QueryProcessor::parse_command(&mut graph, &mut chain, command).unwrap().unwrap())
+            .last()
+            .map(|result| InternalNodeAttribute::get_identifier(result.first().unwrap()))
+            .unwrap();
+
+        assert!(chain.agent_service.accounts.is_empty());
+
+        let cmd = format!("add connection from User($id=\"{user}\") to Playlist($id=\"{playlist}\") with weight 50");
+
+        // When
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, cmd.as_str());
+
+        // Then
+        assert!(result.unwrap().is_ok());
+        assert_eq!(chain.agent_service.get_difficulty(&user), 1);
+        assert_eq!(chain.blocks.len(), 9); // Genesis, definition, node, edge, validator and their reward blocks
+    }
+
+    #[test]
+    fn should_delegate_stake() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+
+        let commands = [
+            "define node User(key,premium) with agent (premium=\"true\")".to_string(),
+            format!("add node User(key=\"{}\",premium=\"true\")", chain.signer.get_public_key()),
+            "add node User(premium=\"false\")".to_string(),
+        ];
+        let users: Vec<String> = commands
+            .iter()
+            .map(|command| QueryProcessor::parse_command(&mut graph, &mut chain, command).unwrap().unwrap())
+            .skip(1)
+            .map(|result| InternalNodeAttribute::get_identifier(result.first().unwrap()))
+            .collect();
+        let (agent, user) = (&users[0], &users[1]);
+        let edge = format!("add connection from User($id=\"{user}\") to User($id=\"{agent}\") with weight 10");
+        assert!(QueryProcessor::parse_command(&mut graph, &mut chain, edge.as_str()).unwrap().is_ok());
+
+        let cmd = format!("delegate stake from User($id=\"{user}\") to User($id=\"{agent}\")");
+
+        // When
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, cmd.as_str());
+
+        // Then
+        assert_graph_result(result, vec![("$id", user), ("$name", "User"), ("agent", agent), ("stake", "1")]);
+        assert_eq!(chain.agent_service.get_difficulty(agent), 1);
+    }
+
+    #[test]
+    fn should_issue_network_token() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let peer_id = "12D3KooWD3eckifWpRn9wQpMG9R9hX3sD158z7EqHWmweQAJU5SA";
+
+        // When
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, &format!("wallet issue token {peer_id}"));
+        let forged = QueryProcessor::parse_command(&mut graph, &mut chain, "wallet issue token weighted-graph/client-token/admin/99999999999");
+
+        // Then
+        assert_graph_result(
+            result,
+            vec![("peer_id", peer_id), ("token", "_"), ("authority", &chain.signer.get_public_key())],
+        );
+        assert!(!matches!(forged, Ok(Ok(_))));
+    }
+
+    #[test]
+    fn should_create_client_token() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let authority = chain.signer.get_public_key();
+
+        // When
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, "create token for role writer expires 30d").unwrap();
+        let unknown_role = QueryProcessor::parse_command(&mut graph, &mut chain, "create token for role owner expires 1h").unwrap();
+
+        // Then
+        let result = result.unwrap();
+        let token = ClientToken::verify(&result[0]["token"], &authority).unwrap();
+        assert_eq!(token.role, Role::Writer);
+        assert_eq!(result[0]["expires_at"], token.expires_at.to_string());
+        assert!(token.expires_at >= Expiry::now() + 30 * 24 * 60 * 60 - 1);
+        assert_eq!(result[0]["authority"], authority);
+        assert_eq!(unknown_role.err().unwrap().code(), "INVALID_QUERY");
+    }
+
+    #[test]
+    fn should_export_mnemonic() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+
+        // When
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, "wallet export mnemonic");
+
+        // Then
+        assert_graph_result(result, vec![("public_key", &chain.signer.get_public_key()), ("mnemonic", "_")]);
+    }
+
+    #[test]
+    fn should_fetch_balances() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        chain.add_edge_change("User".to_string(), "Playlist".to_string(), 50).unwrap();
+
+        // When
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, "fetch balances");
+
+        // Then
+        let result = result.unwrap().unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].get("public_key").unwrap(), &chain.signer.get_public_key());
+        assert_eq!(result[0].get("balance").unwrap(), "1");
+    }
+
+    #[test]
+    fn should_add_node() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        graph
+            .create_definition("Person".to_string(), vec!["name".to_string()])
+            .expect("Inserting definition failed");
+
+        let command = "add node Person(name=\"Janne\")";
+
+        // When
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, command);
+
+        // Then
+        assert_graph_result(
+            result,
+            vec![
+                (InternalNodeAttribute::ID_ATTRIBUTE, "_"),
+                (InternalNodeAttribute::NAME_ATTRIBUTE, "Person"),
+                ("name", "Janne"),
+                (InternalNodeAttribute::EDGE_COUNT_ATTRIBUTE, "0"),
+            ],
+        );
+        assert_eq!(graph.nodes.len(), 1);
+    }
+
+    #[test]
+    fn should_parse_case_insensitive_keywords_and_whitespace() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let define_command = "\tDEFINE  Node\tPerson( name , premium ) ";
+        let add_command = "Add NODE   Person(name = \"Janne\",\tpremium=\"false\")\t";
+
+        // When
+        let define_result = QueryProcessor::parse_command(&mut graph, &mut chain, define_command);
+        let add_result = QueryProcessor::parse_command(&mut graph, &mut chain, add_command);
+
+        // Then
+        assert_graph_result(define_result, vec![("name", "*"), ("premium", "*")]);
+        assert_graph_result(
+            add_result,
+            vec![
+                (InternalNodeAttribute::ID_ATTRIBUTE, "_"),
+                (InternalNodeAttribute::NAME_ATTRIBUTE, "Person"),
+                ("name", "Janne"),
+                ("premium", "false"),
+                (InternalNodeAttribute::EDGE_COUNT_ATTRIBUTE, "0"),
+            ],
+        );
+        assert!(QueryProcessor::parse_command(&mut graph, &mut chain, "addnode Person(name=\"John\")").is_err());
+    }
+
+    #[test]
+    fn should_add_node_with_escaped_value() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        graph
+            .create_definition("Person".to_string(), vec!["name".to_string()])
+            .expect("Inserting definition failed");
+
+        let command = r#"add node Person(name="Janne \"J\" \\ Doe\nJr. \d")"#;
+
+        // When
+        let response = QueryProcessor::handle_request(|command| QueryProcessor::parse_command(&mut graph, &mut chain, command), command);
+
+        // Then
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response[0]["name"], "Janne \"J\" \\ Doe\nJr. \\d");
+        assert!(response.to_string().contains(r#"Janne \"J\" \\ Doe\nJr. \\d"#));
+    }
+
+    #[test]
+    fn should_update_node() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let identifier = insert_new_node_with_attributes(&mut graph, "Person", vec!["name"]);
+
+        let command = format!("update node Person($id=\"{}\") set (name=\"Janne\")", identifier);
+
+        // When
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, command.as_str());
+
+        // Then
+        assert_graph_result(
+            result,
+            vec![
+                (InternalNodeAttribute::ID_ATTRIBUTE, identifier.as_str()),
+                (InternalNodeAttribute::NAME_ATTRIBUTE, "Person"),
+                ("name", "Janne"),
+                (InternalNodeAttribute::EDGE_COUNT_ATTRIBUTE, "0"),
+                ("old.$id", identifier.as_str()),
+                ("old.$name", "Person"),
+                ("old.$edges", "0"),
+            ],
+        );
+        assert_eq!(graph.nodes.len(), 1);
+    }
+
+    #[test]
+    fn should_add_node_with_given_identifier() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        graph.create_definition("Person".to_string(), vec!["name".to_string()]).unwrap();
+        let mut execute = |cmd: &str| QueryProcessor::parse_command(&mut graph, &mut chain, cmd).unwrap();
+
+        // When
+        let added = execute("add node Person($id=\"user-42\", name=\"Janne\")").unwrap();
+        let duplicate = execute("add node Person($id=\"user-42\", name=\"John\")");
+        let fetched = execute("fetch node Person($id=\"user-42\")").unwrap();
+
+        // Then
+        assert_eq!(added[0]["$id"], "user-42");
+        assert_eq!(duplicate.err().unwrap().code(), "IDENTIFIER_ALREADY_EXISTS");
+        assert_eq!(fetched[0]["name"], "Janne");
+    }
+
+    #[test]
+    fn should_fetch_node_by_identifier_only() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let person_id = insert_new_node_with_attributes(&mut graph, "Person", vec!["name"]);
+        let playlist_id = insert_new_node(&mut graph, "Playlist");
+        insert_new_node(&mut graph, "Song");
+        let mut execute = |cmd: &str| QueryProcessor::parse_command(&mut graph, &mut chain, cmd).unwrap();
+        execute(&format!(
+            "add connection from Person($id=\"{person_id}\") to Playlist($id=\"{playlist_id}\") with weight 60"
+        ))
+        .unwrap();
+
+        // When
+        let duplicate = execute(&format!("add node Song($id=\"{person_id}\")"));
+        let joined = execute(&format!("fetch node ($id=\"{person_id}\") join Playlist($weight>\"50\")")).unwrap();
+        let fetched = execute(&format!("fetch node ($id=\"{person_id}\")")).unwrap();
+        let missing = execute("fetch node ($id=\"missing\")").unwrap();
+
+        // Then
+        assert_eq!(duplicate.err().unwrap().code(), "IDENTIFIER_ALREADY_EXISTS");
+        assert_eq!(joined.len(), 1);
+        assert_eq!(joined[0]["Playlist.$id"], playlist_id);
+        assert_eq!(fetched.len(), 1);
+        assert_eq!(fetched[0]["$name"], "Person");
+        assert!(missing.is_empty());
+    }
+
+    #[test]
+    fn should_add_nodes_and_connections_atomically() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        graph.create_definition("Person".to_string(), vec!["name".to_string()]).unwrap();
+        let mut execute = |cmd: &str| QueryProcessor::parse_command(&mut graph, &mut chain, cmd).unwrap();
+
+        // When
+        let duplicate = execute("add nodes Person [($id=\"a\", name=\"A\"), ($id=\"a\", name=\"B\")]");
+        let nodes = execute("add nodes Person [($id=\"a\", name=\"A\"), ($id=\"b\", name=\"B\"), (name=\"C\")]").unwrap();
+        let invalid = execute("add connections [from Person($id=\"a\") to Person($id=\"b\") with weight 10, from Person($id=\"x\") to Person($id=\"a\") with weight 20]");
+        let edges = execute("add connections [from Person($id=\"a\") to Person($id=\"b\") with weight 10, from Person($id=\"b\") to Person($id=\"a\") with weight 20]").unwrap();
+        let fetched = execute("fetch node Person($id=\"a\") join Person($weight>\"5\")").unwrap();
+
+        // Then
+        assert_eq!(duplicate.err().unwrap().code(), "IDENTIFIER_ALREADY_EXISTS");
+        assert_eq!(nodes.len(), 3);
+        let batches: Vec<&Block> = chain
+            .blocks
+            .iter()
+            .filter(|block| block.data.data_type == BlockDataType::NodeBatch)
+            .collect();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].data.nodes().count(), 3);
+        assert_eq!(invalid.err().unwrap().code(), "NODE_NOT_FOUND");
+        assert_eq!(edges.len(), 2);
+        assert_eq!(fetched[0]["Person.name"], "B");
+    }
+
+    #[test]
+    fn should_not_generate_identifier_given_to_later_node_of_batch() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        graph.ids = IdGenerator::new(IdStrategy::Snowflake(1));
+        graph.create_definition("Person".to_string(), vec!["name".to_string()]).unwrap();
+
+        // Identifiers which are generated in the next second are given to later nodes of the batch
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64 - 1_577_836_800_000;
+        let given: Vec<String> = (now..now + 1000).map(|millis| ((millis << 22) | (1 << 12)).to_string()).collect();
+        let nodes: Vec<String> = given.iter().map(|identifier| format!("($id=\"{identifier}\")")).collect();
+        let cmd = format!("add nodes Person [(name=\"Generated\"), {}]", nodes.join(", "));
+
+        // When
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, &cmd).unwrap().unwrap();
+
+        // Then
+        assert_eq!(result.len(), 1001);
+        assert!(!given.contains(&result[0]["$id"]));
+        assert_eq!(graph.nodes.len(), 1001);
+    }
+
+    #[test]
+    fn should_set_or_replace_node_attributes() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        graph
+            .create_definition("Person".to_string(), vec!["name".to_string(), "city".to_string()])
+            .unwrap();
+        let mut execute = |cmd: &str| QueryProcessor::parse_command(&mut graph, &mut chain, cmd).unwrap();
+        let identifier = execute("add node Person(name=\"Janne\", city=\"Oslo\")").unwrap()[0]["$id"].clone();
+
+        // When
+        let set = execute(&format!("update node Person($id=\"{identifier}\") set (city=\"Bergen\")")).unwrap();
+        let replaced = execute(&format!("replace node Person($id=\"{identifier}\") with (name=\"Jan\")")).unwrap();
+        let ambiguous = execute(&format!("update node Person($id=\"{identifier}\", name=\"Janne\")"));
+
+        // Then
+        assert_eq!((set[0]["name"].as_str(), set[0]["city"].as_str()), ("Janne", "Bergen"));
+        assert_eq!(replaced[0]["name"], "Jan");
+        assert!(!replaced[0].contains_key("city"));
+        assert_eq!((replaced[0]["old.name"].as_str(), replaced[0]["old.city"].as_str()), ("Janne", "Bergen"));
+        assert_eq!(ambiguous.err().unwrap().code(), "INVALID_QUERY");
+    }
+
+    #[test]
+    fn should_delete_node() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let identifier = insert_new_node(&mut graph, "Person");
+
+        let command = format!("delete node Person($id=\"{}\")", identifier);
+
+        // When
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, command.as_str());
+
+        // Then
+        assert_graph_result(
+            result,
+            vec![
+                (InternalNodeAttribute::ID_ATTRIBUTE, identifier.as_str()),
+                (InternalNodeAttribute::NAME_ATTRIBUTE, "Person"),
+                (InternalNodeAttribute::EDGE_COUNT_ATTRIBUTE, "0"),
+            ],
+        );
+        assert!(graph.nodes.is_empty());
+    }
+
+    #[test]
+    fn should_add_edge() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let from_id = insert_new_node(&mut graph, "From");
+        let to_id = insert_new_node(&mut graph, "To");
+
+        let cmd = format!("add connection from From($id=\"{}\") to To($id=\"{}\") with weight 50", from_id, to_id);
+
+        // When
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, cmd.as_str());
+
+        // Then
+        assert_graph_result(
+            result,
+            vec![
+                (InternalNodeAttribute::FROM_ATTRIBUTE, "From"),
+                (InternalNodeAttribute::TO_ATTRIBUTE, "To"),
+                (InternalNodeAttribute::WEIGHT_ATTRIBUTE, "50"),
+            ],
+        );
+        assert_edge(&graph, from_id, to_id, 50);
+    }
+
+    #[test]
+    fn should_return_error_when_identifier_is_missing() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        insert_new_node_with_attributes(&mut graph, "User", vec!["name"]);
+
+        // When
+        let fetch_result = QueryProcessor::parse_command(&mut graph, &mut chain, "fetch node User(name=\"John\")").unwrap();
+        let delegate_result = QueryProcessor::parse_command(&mut graph, &mut chain, "delegate stake from User() to User()").unwrap();
+
+        // Then
+        assert_eq!(fetch_result.err().unwrap().code(), "ATTRIBUTE_IS_REQUIRED");
+        assert_eq!(delegate_result.err().unwrap().code(), "ATTRIBUTE_IS_REQUIRED");
+    }
+
+    #[test]
+    fn should_validate_edge_weight() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let from_id = insert_new_node(&mut graph, "From");
+        let to_id = insert_new_node(&mut graph, "To");
+
+        let cmd = |weight: &str| format!("add connection from From($id=\"{from_id}\") to To($id=\"{to_id}\") with weight {weight}");
+
+        // When
+        let invalid_result = QueryProcessor::parse_command(&mut graph, &mut chain, cmd("200").as_str()).unwrap();
+        let negative_result = QueryProcessor::parse_command(&mut graph, &mut chain, cmd("-5").as_str());
+
+        // Then
+        assert_eq!(invalid_result.err().unwrap().code(), "INVALID_WEIGHT");
+        assert_graph_result(
+            negative_result,
+            vec![
+                (InternalNodeAttribute::FROM_ATTRIBUTE, "From"),
+                (InternalNodeAttribute::TO_ATTRIBUTE, "To"),
+                (InternalNodeAttribute::WEIGHT_ATTRIBUTE, "-5"),
+            ],
+        );
+        assert_edge(&graph, from_id, to_id, -5);
+    }
+
+    #[test]
+    fn should_update_edge() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let from_id = insert_new_node(&mut graph, "From");
+        let to_id = insert_new_node(&mut graph, "To");
+
+        insert_new_edge(&mut graph, from_id.clone(), to_id.clone(), 50);
+
+        let cmd = format!("update connection from From($id=\"{}\") to To($id=\"{}\") with weight 80", from_id, to_id);
+
+        // When
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, cmd.as_str());
+
+        // Then
+        assert_graph_result(
+            result,
+            vec![
+                (InternalNodeAttribute::FROM_ATTRIBUTE, "From"),
+                (InternalNodeAttribute::TO_ATTRIBUTE, "To"),
+                (InternalNodeAttribute::WEIGHT_ATTRIBUTE, "80"),
+                ("old.$weight", "50"),
+            ],
+        );
+        assert_edge(&graph, from_id, to_id, 80);
+    }
+
+    #[test]
+    fn should_increment_edge_within_weight_range() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let from_id = insert_new_node(&mut graph, "From");
+        let to_id = insert_new_node(&mut graph, "To");
+
+        insert_new_edge(&mut graph, from_id.clone(), to_id.clone(), 50);
+
+        let connection = format!("connection from From($id=\"{from_id}\") to To($id=\"{to_id}\")");
+
+        // When
+        let incremented = QueryProcessor::parse_command(&mut graph, &mut chain, &format!("increment {connection} by 5"));
+        let clamped = QueryProcessor::parse_command(&mut graph, &mut chain, &format!("decrement {connection} by 300"));
+
+        // Then
+        assert_graph_result(
+            incremented,
+            vec![
+                (InternalNodeAttribute::FROM_ATTRIBUTE, "From"),
+                (InternalNodeAttribute::TO_ATTRIBUTE, "To"),
+                (InternalNodeAttribute::WEIGHT_ATTRIBUTE, "55"),
+                ("old.$weight", "50"),
+            ],
+        );
+        assert_eq!(clamped.unwrap().unwrap()[0][InternalNodeAttribute::WEIGHT_ATTRIBUTE], "-128");
+        assert_edge(&graph, from_id, to_id, i8::MIN);
+    }
+
+    #[test]
+    fn should_delete_edge() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let from_id = insert_new_node(&mut graph, "From");
+        let to_id = insert_new_node(&mut graph, "To");
+
+        insert_new_edge(&mut graph, from_id.clone(), to_id.clone(), 50);
+
+        let cmd = format!("delete connection from From($id=\"{}\") to To($id=\"{}\")", from_id, to_id);
+
+        // When
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, cmd.as_str());
+
+        // Then
+        assert_graph_result(
+            result,
+            vec![
+                (InternalNodeAttribute::FROM_ATTRIBUTE, "From"),
+                (InternalNodeAttribute::TO_ATTRIBUTE, "To"),
+                (InternalNodeAttribute::WEIGHT_ATTRIBUTE, "50"),
+            ],
+        );
+
+        for node in graph.nodes.values() {
+            assert!(node.edges.is_empty());
         }
+    }
 
-        rule fetch_node() -> GraphResults = _ "fetch" _ "node" _ name:name() _ attributes:attributes() _ joins:joins() {
-            graph.search(name.to_string(), attributes, joins)
-        }
+    #[test]
+    fn should_execute_trigger_after_change() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let person_id = insert_new_node_with_attributes(&mut graph, "Person", vec!["name"]);
+        let playlist_id = insert_new_node_with_attributes(&mut graph, "Playlist", vec!["name", "followers"]);
 
-        rule fetch_connection() -> GraphResults = _ "fetch" _ "connection" _ "chain" {
-            chain.as_graph_result()
-        }
+        let trigger =
+            "create trigger on add connection Person->Playlist do update node Playlist($id=\"{to.$id}\") set (followers=\"{to.followers+1}\")";
+        let cmd = format!("add connection from Person($id=\"{person_id}\") to Playlist($id=\"{playlist_id}\") with weight 1");
 
-        rule add_node() -> GraphResults = _ "add" _ "node" _ name:name() _ attributes:attributes()? {
-            let result = graph.add_node(name.to_string(), attributes.clone().unwrap_or_else(FxHashMap::default));
+        // When
+        let created = QueryProcessor::parse_command(&mut graph, &mut chain, trigger).unwrap().unwrap();
+        QueryProcessor::parse_command(&mut graph, &mut chain, &cmd).unwrap().unwrap();
+        let triggers = QueryProcessor::parse_command(&mut graph, &mut chain, "fetch triggers").unwrap().unwrap();
 
-            // Attributes are required for agent registration
-            if result.is_ok() && attributes.is_some() {
-                chain.add_or_update_agent(graph, name.to_string(), InternalNodeAttribute::get_identifier(result.clone().unwrap().first().unwrap()));
-            }
+        // Then
+        assert_eq!(created[0]["target"], "connection Person->Playlist");
+        assert_eq!(triggers.len(), 1);
+        assert_eq!(
+            graph.get_by_id(&"Playlist".to_string(), &playlist_id).unwrap().attributes["followers"],
+            "1"
+        );
+        assert_eq!(graph.get_by_id(&"Person".to_string(), &person_id).unwrap().edges.len(), 1);
+    }
 
-            result
-        }
+    #[test]
+    fn should_execute_triggers_for_each_added_node_and_connection() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let mut execute = |cmd: &str| QueryProcessor::parse_command(&mut graph, &mut chain, cmd).unwrap();
+        execute("define node Person(name)").unwrap();
+        execute("define node Playlist(name, followers, added)").unwrap();
+        execute("add node Playlist($id=\"p\", name=\"Rock\")").unwrap();
+        execute("create trigger on add node Person do update node Playlist($id=\"p\") set (added=\"{node.name}\")").unwrap();
+        execute("create trigger on add connection Person->Playlist do update node Playlist($id=\"{to.$id}\") set (followers=\"{to.followers+1}\")")
+            .unwrap();
 
-        rule add_edge() -> GraphResults = _ "add" _ "connection" _ "from" _ from_name:name() _ from_attributes:attributes() _ "to" _ to_name:name() _ to_attributes:attributes() _ "with" _ "weight" _ weight:weight()  {
-            let result = graph.add_edge((from_name.to_string(), from_attributes.clone()), (to_name.to_string(), to_attributes.clone()), weight);
+        // When
+        execute("add nodes Person [($id=\"1\", name=\"John\"), ($id=\"2\", name=\"Jane\")]").unwrap();
+        execute("add connections [from Person($id=\"1\") to Playlist($id=\"p\") with weight 1, from Person($id=\"2\") to Playlist($id=\"p\") with weight 2]").unwrap();
 
-            if result.is_ok() {
-                  if let Err(error) = chain.add_edge_change(InternalNodeAttribute::get_identifier(&from_attributes),InternalNodeAttribute::get_identifier(&to_attributes), weight) {
-                    eprintln!("Chain error: {error}");
-                }
-            }
+        // Then
+        let playlist = graph.get_by_id(&"Playlist".to_string(), &"p".to_string()).unwrap();
+        assert_eq!(playlist.attributes["added"], "Jane");
+        assert_eq!(playlist.attributes["followers"], "2");
+    }
 
-            result
-        }
+    #[test]
+    fn should_not_change_graph_when_trigger_fails() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let person_id = insert_new_node_with_attributes(&mut graph, "Person", vec!["name"]);
 
-        rule update_node() -> GraphResults = _ "update" _ "node" _ name:name() _ attributes:attributes() {
-            let result = graph.update_node(name.to_string(), attributes.clone());
+        let trigger = "create trigger on update node Person do add node Unknown(name=\"{node.name}\")";
+        let cmd = format!("update node Person($id=\"{person_id}\") set (name=\"John\")");
+        QueryProcessor::parse_command(&mut graph, &mut chain, trigger).unwrap().unwrap();
+        let blocks = chain.blocks.len();
 
-            // Handle case where user does not meet conditions anymore
-            if result.is_ok() {
-               chain.add_or_update_agent(graph, name.to_string(), InternalNodeAttribute::get_identifier(&attributes));
-            }
+        // When
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, &cmd).unwrap();
+        let dropped = QueryProcessor::parse_command(&mut graph, &mut chain, "drop trigger 0").unwrap();
+        let missing = QueryProcessor::parse_command(&mut graph, &mut chain, "drop trigger 0").unwrap();
 
-            result
-        }
+        // Then
+        assert_eq!(result.err().unwrap().code(), "NODE_NOT_DEFINED");
+        assert!(!graph
+            .get_by_id(&"Person".to_string(), &person_id)
+            .unwrap()
+            .attributes
+            .contains_key("name"));
+        assert_eq!(chain.blocks.len(), blocks);
+        assert!(dropped.is_ok());
+        assert_eq!(missing.err().unwrap().code(), "TRIGGER_NOT_FOUND");
+        assert!(QueryProcessor::parse_command(&mut graph, &mut chain, &cmd).unwrap().is_ok());
+    }
 
-        rule update_edge() -> GraphResults = _ "update" _ "connection" _ "from" _ from_name:name() _ from_attributes:attributes() _ "to" _ to_name:name() _ to_attributes:attributes() _ "with" _ "weight" _ weight:weight()  {
-            let result = graph.update_edge((from_name.to_string(), from_attributes.clone()), (to_name.to_string(), to_attributes.clone()), weight);
+    #[test]
+    fn should_rollback_changes_since_savepoint() {
+        // Given
+        let mut graph = Graph::default();
+        let person_id = insert_new_node_with_attributes(&mut graph, "Person", vec!["name"]);
+        let other_id = graph.add_node("Person".to_string(), FxHashMap::default()).unwrap()[0]["$id"].clone();
+        let by_id = |identifier: &String| FxHashMap::from_iter([("$id".to_string(), identifier.clone())]);
+        graph
+            .add_edge(("Person".to_string(), by_id(&person_id)), ("Person".to_string(), by_id(&other_id)), 5)
+            .unwrap();
+        let person = |name: &str| FxHashMap::from_iter([("name".to_string(), name.to_string())]);
 
-            if result.is_ok() {
-                if let Err(error) = chain.add_edge_change(InternalNodeAttribute::get_identifier(&from_attributes),InternalNodeAttribute::get_identifier(&to_attributes), weight) {
-                    eprintln!("Chain error: {error}");
-                }
-            }
+        // When
+        let savepoint = graph.savepoint();
+        let added = graph.add_node("Person".to_string(), person("Jane")).unwrap()[0]["$id"].clone();
+        let nested = graph.savepoint();
+        graph.delete_node("Person".to_string(), by_id(&added)).unwrap();
+        graph.rollback(nested);
+        let kept = graph.get_by_id(&"Person".to_string(), &added).is_ok();
+        let mut updated = person("John");
+        updated.insert("$id".to_string(), person_id.clone());
+        graph.update_node("Person".to_string(), updated).unwrap();
+        graph.delete_node("Person".to_string(), by_id(&other_id)).unwrap();
+        graph.rollback(savepoint);
 
-            result
-        }
+        // Then
+        let person = graph.get_by_id(&"Person".to_string(), &person_id).unwrap();
+        assert!(!person.attributes.contains_key("name"));
+        assert_eq!(person.edges.len(), 1);
+        assert!(graph.get_by_id(&"Person".to_string(), &other_id).is_ok());
+        assert!(graph.get_by_id(&"Person".to_string(), &added).is_err());
+        assert!(kept);
+        assert_eq!(graph.nodes.len(), 2);
+    }
 
-        rule delete_node() -> GraphResults = _ "delete" _ "node" _ name:name() _ attributes:attributes() {
-            let result = graph.delete_node(name.to_string(), attributes.clone());
+    #[test]
+    fn should_maintain_view_after_changes() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        graph.create_definition("User".to_string(), vec!["name".to_string()]).unwrap();
+        let mut add_user = |name: &str| {
+            let attributes = FxHashMap::from_iter([("name".to_string(), name.to_string())]);
+            InternalNodeAttribute::get_identifier(&graph.add_node("User".to_string(), attributes).unwrap()[0])
+        };
+        let (john, jane, bob) = (add_user("John"), add_user("Jane"), add_user("Bob"));
+
+        let mut execute = |cmd: String| QueryProcessor::parse_command(&mut graph, &mut chain, &cmd).unwrap();
+        let connect =
+            |from: &str, to: &str, weight: i8| format!("add connection from User($id=\"{from}\") to User($id=\"{to}\") with weight {weight}");
+        execute(connect(&john, &jane, 60)).unwrap();
+        execute(connect(&bob, &jane, 40)).unwrap();
 
-            if result.is_ok() {
-                chain.remove_agent(InternalNodeAttribute::get_identifier(&attributes));
-            }
+        // When
+        let created = execute("create view PremiumFriends as fetch node User() join User($weight>\"50\")".to_string()).unwrap();
+        execute(format!(
+            "update connection from User($id=\"{bob}\") to User($id=\"{jane}\") with weight 70"
+        ))
+        .unwrap();
+        execute(format!("update node User($id=\"{jane}\") set (name=\"Janet\")")).unwrap();
+        let updated = execute("fetch view PremiumFriends".to_string()).unwrap();
+        execute(format!("delete connection from User($id=\"{john}\") to User($id=\"{jane}\")")).unwrap();
+        let deleted = execute("fetch view PremiumFriends".to_string()).unwrap();
+        let dropped = execute("drop view PremiumFriends".to_string());
+        let missing = execute("fetch view PremiumFriends".to_string());
 
-            result
+        // Then
+        assert_eq!(created[0]["rows"], "1");
+        assert_eq!(updated.len(), 2);
+        assert!(updated.iter().all(|row| row["User.name"] == "Janet"));
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0]["$id"], bob);
+        assert!(dropped.is_ok());
+        assert_eq!(missing.err().unwrap().code(), "VIEW_NOT_FOUND");
+    }
+
+    #[test]
+    fn should_filter_nodes_by_numeric_range() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        graph
+            .create_definition("User".to_string(), vec!["name".to_string(), "age".to_string()])
+            .unwrap();
+        for (name, age) in [("John", "9"), ("Jane", "18"), ("Bob", "42"), ("Anna", "100")] {
+            let attributes = FxHashMap::from_iter([("name".to_string(), name.to_string()), ("age".to_string(), age.to_string())]);
+            graph.add_node("User".to_string(), attributes).unwrap();
         }
+        let mut execute = |cmd: &str| QueryProcessor::parse_command(&mut graph, &mut chain, cmd).unwrap();
 
-        rule delete_edge() -> GraphResults = _ "delete" _ "connection" _ "from" _ from_name:name() _ from_attributes:attributes() _ "to" _ to_name:name() _ to_attributes:attributes() {
-            let result = graph.delete_edge((from_name.to_string(), from_attributes.clone()), (to_name.to_string(), to_attributes.clone()));
+        // When
+        let adults = execute("fetch node User where (age>=\"18\", age<\"100\")").unwrap();
+        let named = execute("fetch node User where (name!=\"Bob\", age>\"9.5\")").unwrap();
+        let undefined = execute("fetch node Song where (age>\"1\")");
 
-            if result.is_ok() {
-                if let Err(error) = chain.add_edge_change(InternalNodeAttribute::get_identifier(&from_attributes),InternalNodeAttribute::get_identifier(&to_attributes), 0) {
-                    eprintln!("Chain error: {error}");
-                }
-            }
+        // Then
+        let mut names: Vec<&str> = adults.iter().map(|row| row["name"].as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["Bob", "Jane"]);
+        assert_eq!(named.len(), 2);
+        assert_eq!(undefined.err().unwrap().code(), "NODE_NOT_DEFINED");
+    }
 
-            result
+    #[test]
+    fn should_filter_nodes_by_indexed_range() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        graph
+            .create_definition("User".to_string(), vec!["name".to_string(), "*age".to_string()])
+            .unwrap();
+        let mut identifiers = vec![];
+        for (name, age) in [("John", "9"), ("Jane", "18"), ("Bob", "42"), ("Anna", "100")] {
+            let attributes = FxHashMap::from_iter([("name".to_string(), name.to_string()), ("*age".to_string(), age.to_string())]);
+            identifiers.push(graph.add_node("User".to_string(), attributes).unwrap()[0]["$id"].clone());
         }
+        let update = format!("update node User($id=\"{}\") set (*age=\"120\")", identifiers[1]);
+        QueryProcessor::parse_command(&mut graph, &mut chain, &update).unwrap().unwrap();
+        let visited = graph.nodes.visited();
 
-        rule agent() -> FxHashMap<String, String> = _ "with" _ "agent" _ conditions:attributes() { conditions }
-
-        rule joins() -> Vec<(String, i8)> = joins:join() ** _ { joins }
-
-        rule join() -> (String, i8) = _ "join" _ name:name() _ "($weight>\"" weight:weight() "\")" { (name.to_string(), weight) }
+        // When
+        let adults = QueryProcessor::parse_command(&mut graph, &mut chain, "fetch node User where (*age>=\"18\", *age<\"100\")")
+            .unwrap()
+            .unwrap();
 
-        rule attributes() -> FxHashMap<String, String> = "(" attributes:attribute() ** "," ")" {
-            attributes.iter()
-                .map(|(k, v)| (k.to_string(), v.to_string()))
-                .collect::<FxHashMap<String, String>>()
-        }
+        // Then
+        assert_eq!(adults.len(), 1);
+        assert_eq!(adults[0]["name"], "Bob");
+        assert!(graph.nodes.visited() - visited < 4);
+    }
 
-        rule attribute() -> (&'input str, &'input str) = name:attribute_name() "=" value:attribute_value() { (name, value) }
+    #[test]
+    fn should_upsert_node_by_key() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        graph
+            .create_definition("Person".to_string(), vec!["name".to_string(), "city".to_string()])
+            .unwrap();
+        let mut execute = |cmd: &str| QueryProcessor::parse_command(&mut graph, &mut chain, cmd).unwrap();
 
-        rule attribute_name() -> &'input str = $(['a'..='z' | 'A'..='Z' | '0'..='9' | '$' | '*']+)
+        // When
+        let created = execute("upsert node Person(name=\"Janne\", city=\"Oslo\") on (name)").unwrap();
+        let updated = execute("upsert node Person(name=\"Janne\", city=\"Bergen\") on (name)").unwrap();
+        let missing_key = execute("upsert node Person(name=\"Janne\") on (city)");
+        execute("add node Person(name=\"Janne\", city=\"Oslo\")").unwrap();
+        let ambiguous = execute("upsert node Person(name=\"Janne\") on (name)");
+        let people = execute("fetch node Person where (city=\"Bergen\")").unwrap();
 
-        rule attribute_value() -> &'input str = "\"" value:__ "\"" { value }
+        // Then
+        assert_eq!(created[0]["$action"], "created");
+        assert_eq!(updated[0]["$action"], "updated");
+        assert_eq!(updated[0]["$id"], created[0]["$id"]);
+        assert_eq!(missing_key.err().unwrap().code(), "INVALID_QUERY");
+        assert_eq!(ambiguous.err().unwrap().code(), "INVALID_QUERY");
+        assert_eq!(people.len(), 1);
+    }
 
-        rule attribute_definitions() -> Vec<&'input str> = "(" names:attribute_definition() ** "," ")" { names }
+    #[test]
+    fn should_update_node_only_if_precondition_holds() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        graph
+            .create_definition("Person".to_string(), vec!["name".to_string(), "premium".to_string()])
+            .unwrap();
+        let mut execute = |cmd: &str| QueryProcessor::parse_command(&mut graph, &mut chain, cmd).unwrap();
+        let person = execute("add node Person(name=\"Janne\", premium=\"false\")").unwrap();
+        let update = format!(
+            "update node Person($id=\"{}\") set (premium=\"true\") if (premium=\"false\")",
+            person[0]["$id"]
+        );
 
-        rule attribute_definition() -> &'input str = $(['a'..='z' | 'A'..='Z' | '0'..='9' | '*']+)
+        // When
+        let applied = execute(&update).unwrap();
+        let rejected = execute(&update).unwrap();
+        execute(&format!("delete node Person($id=\"{}\") soft", person[0]["$id"])).unwrap();
+        let deleted = execute(&update);
 
-        rule name() -> &'input str = $(['a'..='z' | 'A'..='Z']+)
+        // Then
+        assert_eq!(applied[0]["$applied"], "true");
+        assert_eq!(applied[0]["premium"], "true");
+        assert_eq!(rejected[0]["$applied"], "false");
+        assert_eq!(rejected[0]["premium"], "true");
+        assert_eq!(deleted.err().unwrap().code(), "NODE_NOT_FOUND");
+    }
 
-        rule weight() -> i8 = n:$(['0'..='9']+) { n.parse().unwrap() }
+    #[test]
+    fn should_deny_self_loops_by_definition_policy() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let mut execute = |cmd: &str| QueryProcessor::parse_command(&mut graph, &mut chain, cmd).unwrap();
+        execute("define node Person(name) deny self loops").unwrap();
+        execute("define node Song(name) allow self loops").unwrap();
+        execute("add node Person($id=\"1\", name=\"Janne\")").unwrap();
+        execute("add node Person($id=\"2\", name=\"Ola\")").unwrap();
+        execute("add node Song($id=\"3\", name=\"Intro\")").unwrap();
 
-        rule __ -> &'input str = $([^'"']*)
+        // When
+        let self_loop = execute("add connection from Person($id=\"1\") to Person($id=\"1\") with weight 5");
+        let bulk_self_loop = execute(
+            "add connections [from Person($id=\"2\") to Person($id=\"1\") with weight 5, from Person($id=\"2\") to Person($id=\"2\") with weight 5]",
+        );
+        let connection = execute("add connection from Person($id=\"1\") to Person($id=\"2\") with weight 5");
+        let allowed = execute("add connection from Song($id=\"3\") to Song($id=\"3\") with weight 5");
+        let other_definition = execute("add connection from Person($id=\"1\") to Song($id=\"3\") with weight 5");
 
-        rule _ -> &'input str = $([' ']*)
+        // Then
+        assert_eq!(self_loop.err().unwrap().code(), "SELF_LOOP_NOT_ALLOWED");
+        assert_eq!(bulk_self_loop.err().unwrap().code(), "SELF_LOOP_NOT_ALLOWED");
+        assert!(connection.is_ok());
+        assert!(allowed.is_ok());
+        assert!(other_definition.is_ok());
+        assert!(graph.denied_self_loops.contains("Person"));
+        assert!(chain
+            .blocks
+            .iter()
+            .any(|block| block.data.schema_data.as_ref().is_some_and(|schema| schema.deny_self_loops)));
     }
-}
 
-pub struct QueryProcessor;
+    #[test]
+    fn should_fetch_subgraph_within_depth() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let mut execute = |cmd: &str| QueryProcessor::parse_command(&mut graph, &mut chain, cmd).unwrap();
+        execute("define node Person(name)").unwrap();
+        for identifier in ["1", "2", "3", "4"] {
+            execute(&format!("add node Person($id=\"{identifier}\")")).unwrap();
+        }
+        execute("add connection from Person($id=\"1\") to Person($id=\"2\") with weight 10").unwrap();
+        execute("add connection from Person($id=\"2\") to Person($id=\"1\") with weight 20").unwrap();
+        execute("add connection from Person($id=\"2\") to Person($id=\"3\") with weight 30").unwrap();
+        execute("add connection from Person($id=\"3\") to Person($id=\"4\") with weight 40").unwrap();
 
-impl QueryProcessor {
-    pub fn parse_command(mut graph: &mut Graph, mut chain: &mut Chain, command: &str) -> Result<GraphResults, ParseError<LineCol>> {
-        query_parser::command(command, &mut graph, &mut chain)
-    }
-}
+        // When
+        let subgraph = execute("fetch subgraph around Person($id=\"1\") depth 2").unwrap();
+        let single = execute("fetch subgraph around Person($id=\"1\") depth 0").unwrap();
+        let missing = execute("fetch subgraph around Person($id=\"5\") depth 1");
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::graph::attribute::InternalNodeAttribute;
+        // Then
+        let nodes: Vec<&str> = subgraph
+            .iter()
+            .filter(|row| row["$type"] == "node")
+            .map(|row| row["$id"].as_str())
+            .collect();
+        let edges: Vec<(&str, &str, &str)> = subgraph
+            .iter()
+            .filter(|row| row["$type"] == "edge")
+            .map(|row| (row["$from_id"].as_str(), row["$to_id"].as_str(), row["$weight"].as_str()))
+            .collect();
+        assert_eq!(nodes, vec!["1", "2", "3"]);
+        assert_eq!(edges, vec![("1", "2", "10"), ("2", "1", "20"), ("2", "3", "30")]);
+        assert_eq!(single.len(), 1);
+        assert_eq!(missing.err().unwrap().code(), "NODE_NOT_FOUND");
+    }
 
     #[test]
-    fn should_fetch_node() {
+    fn should_soft_delete_and_restore_node() {
         // Given
         let mut graph = Graph::default();
         let mut chain = Chain::default();
+        graph.create_definition("Person".to_string(), vec!["name".to_string()]).unwrap();
+        let mut execute = |cmd: &str| QueryProcessor::parse_command(&mut graph, &mut chain, cmd).unwrap();
+        execute("add node Person($id=\"1\", name=\"Janne\")").unwrap();
+        execute("add node Person($id=\"2\", name=\"Ola\")").unwrap();
+        execute("add connection from Person($id=\"2\") to Person($id=\"1\") with weight 5").unwrap();
 
-        let from = insert_new_node(&mut graph, "From");
-        let to = insert_new_node(&mut graph, "To");
+        // When
+        let deleted = execute("delete node Person($id=\"1\") soft").unwrap();
+        let fetched = execute("fetch node Person($id=\"1\")");
+        let filtered = execute("fetch node Person where (name~\"a\")").unwrap();
+        let joined = execute("fetch node Person($id=\"2\") join Person($weight>\"0\")").unwrap();
+        let updated = execute("update node Person($id=\"1\") set (name=\"Jane\")");
+        let restored = execute("restore node Person($id=\"1\")").unwrap();
+        let restored_again = execute("restore node Person($id=\"1\")");
+        let joined_after_restore = execute("fetch node Person($id=\"2\") join Person($weight>\"0\")").unwrap();
 
-        insert_new_edge(&mut graph, from.clone(), to.clone(), 50);
+        // Then
+        assert!(deleted[0].contains_key("$deleted"));
+        assert_eq!(fetched.err().unwrap().code(), "NODE_NOT_FOUND");
+        assert_eq!(filtered.len(), 1);
+        assert!(joined.is_empty());
+        assert_eq!(updated.err().unwrap().code(), "NODE_NOT_FOUND");
+        assert!(!restored[0].contains_key("$deleted"));
+        assert_eq!(restored_again.err().unwrap().code(), "INVALID_QUERY");
+        assert_eq!(joined_after_restore[0]["Person.name"], "Janne");
+    }
 
-        let cmd = format!("fetch node From($id=\"{from}\") join To($weight>\"0\")");
+    #[test]
+    fn should_skip_soft_deleted_nodes_in_analytics_and_export() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let path = std::env::temp_dir().join("should_skip_soft_deleted_nodes_in_analytics_and_export.ndjson");
+        let mut execute = |cmd: &str| QueryProcessor::parse_command(&mut graph, &mut chain, cmd).unwrap();
+        execute("define node Task(name)").unwrap();
+        for identifier in ["a", "b", "c"] {
+            execute(&format!("add node Task($id=\"{identifier}\")")).unwrap();
+        }
+        execute("add connection from Task($id=\"a\") to Task($id=\"b\") with weight 10").unwrap();
+        execute("add connection from Task($id=\"b\") to Task($id=\"c\") with weight 20").unwrap();
+        execute("delete node Task($id=\"c\") soft").unwrap();
 
         // When
-        let result = query_parser::command(cmd.as_str(), &mut graph, &mut chain);
+        let communities = execute("compute communities").unwrap();
+        let centrality = execute("compute centrality weighted").unwrap();
+        let tree = execute("compute mst").unwrap();
+        let order = execute("fetch order Task").unwrap();
+        let exported = execute(&format!("export data \"{}\"", path.display())).unwrap();
 
         // Then
-        assert_graph_result(
-            result,
-            vec![
-                ("$name", "From"),
-                ("$id", from.as_str()),
-                ("$edges", "1"),
-                ("To.$id", to.as_str()),
-                ("To.$name", "To"),
-                ("To.$edges", "0"),
-            ],
-        );
+        let content = std::fs::read_to_string(&path).unwrap();
+        let identifiers = |rows: &Vec<FxHashMap<String, String>>| rows.iter().all(|row| row.get("$id").is_none_or(|id| id != "c"));
+        assert_eq!(communities.len(), 2);
+        assert_eq!(centrality.len(), 2);
+        assert_eq!(tree.len(), 1);
+        assert_eq!(order.len(), 2);
+        assert!(identifiers(&communities) && identifiers(&centrality) && identifiers(&order));
+        assert_eq!(exported[0]["nodes"], "2");
+        assert_eq!(exported[0]["edges"], "1");
+        assert!(!content.contains("\"c\""));
+
+        std::fs::remove_file(path).unwrap();
     }
 
     #[test]
-    fn should_add_node_definition() {
+    fn should_fetch_topological_order() {
         // Given
         let mut graph = Graph::default();
         let mut chain = Chain::default();
-        let cmd = "define node Person(name,premium) with agent (premium=\"true\")";
+        let mut execute = |cmd: &str| QueryProcessor::parse_command(&mut graph, &mut chain, cmd).unwrap();
+        execute("define node Task(name)").unwrap();
+        for identifier in ["build", "deploy", "test"] {
+            execute(&format!("add node Task($id=\"{identifier}\", name=\"{identifier}\")")).unwrap();
+        }
+        execute("add connection from Task($id=\"build\") to Task($id=\"test\") with weight 1").unwrap();
+        execute("add connection from Task($id=\"test\") to Task($id=\"deploy\") with weight 1").unwrap();
 
         // When
-        let result = query_parser::command(cmd, &mut graph, &mut chain);
+        let order = execute("fetch order Task").unwrap();
+        execute("add connection from Task($id=\"deploy\") to Task($id=\"build\") with weight 1").unwrap();
+        let cycle = execute("fetch order Task");
 
         // Then
-        assert_graph_result(result, vec![("name", "*"), ("premium", "*")]);
+        let identifiers: Vec<&str> = order.iter().map(|row| row["$id"].as_str()).collect();
+        assert_eq!(identifiers, vec!["build", "test", "deploy"]);
+        assert_eq!(order[2]["$order"], "2");
+        assert_eq!(
+            cycle.err().unwrap().to_string(),
+            "Nodes Task are not acyclic, found cycle build -> test -> deploy -> build."
+        );
+    }
 
-        assert!(graph.nodes.is_empty());
-        assert_eq!(graph.definitions.len(), 1);
-        assert!(graph.definitions.contains_key("Person"));
+    #[test]
+    fn should_compute_spanning_tree() {
+        // Given
+        let mut graph = Graph::default();
+        let mut chain = Chain::default();
+        let mut execute = |cmd: &str| QueryProcessor::parse_command(&mut graph, &mut chain, cmd).unwrap();
+        execute("define node City(name)").unwrap();
+        for identifier in ["a", "b", "c"] {
+            execute(&format!("add node City($id=\"{identifier}\")")).unwrap();
+        }
+        execute("add connection from City($id=\"a\") to City($id=\"b\") with weight 10").unwrap();
+        execute("add connection from City($id=\"b\") to City($id=\"c\") with weight 20").unwrap();
+        execute("add connection from City($id=\"c\") to City($id=\"a\") with weight 30").unwrap();
 
-        let conditions = graph.definitions.get("Person").unwrap();
-        assert_eq!(*conditions, vec!["name", "premium"]);
+        // When
+        let minimum = execute("compute mst").unwrap();
+        let maximum = execute("compute mst maximum for City").unwrap();
 
-        assert_eq!(chain.agent_service.agents.len(), 1);
+        // Then
+        let weights = |tree: &Vec<FxHashMap<String, String>>| tree.iter().map(|edge| edge["$weight"].clone()).collect::<Vec<String>>();
+        assert_eq!(weights(&minimum), vec!["10", "20"]);
+        assert_eq!(weights(&maximum), vec!["30", "20"]);
+        assert_eq!(maximum[0]["$from_id"], "c");
+        assert_eq!(maximum[0]["$to"], "City");
     }
 
     #[test]
-    fn should_add_node() {
+    fn should_compute_communities() {
         // Given
         let mut graph = Graph::default();
         let mut chain = Chain::default();
-        graph
-            .create_definition("Person".to_string(), vec!["name".to_string()])
-            .expect("Inserting definition failed");
+        let from_id = insert_new_node(&mut graph, "From");
+        let to_id = insert_new_node(&mut graph, "To");
 
-        let command = "add node Person(name=\"Janne\")";
+        insert_new_edge(&mut graph, from_id.clone(), to_id.clone(), 50);
 
         // When
-        let result = query_parser::command(command, &mut graph, &mut chain);
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, "compute communities");
 
         // Then
-        assert_graph_result(
-            result,
-            vec![
-                (InternalNodeAttribute::ID_ATTRIBUTE, "_"),
-                (InternalNodeAttribute::NAME_ATTRIBUTE, "Person"),
-                ("name", "Janne"),
-                (InternalNodeAttribute::EDGE_COUNT_ATTRIBUTE, "0"),
-            ],
+        let items = result.unwrap().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[0].get(InternalNodeAttribute::COMMUNITY_ATTRIBUTE),
+            items[1].get(InternalNodeAttribute::COMMUNITY_ATTRIBUTE)
         );
-        assert_eq!(graph.nodes.len(), 1);
     }
 
     #[test]
-    fn should_update_node() {
+    fn should_compute_centrality() {
         // Given
         let mut graph = Graph::default();
         let mut chain = Chain::default();
-        let identifier = insert_new_node_with_attributes(&mut graph, "Person", vec!["name"]);
+        let from_id = insert_new_node(&mut graph, "From");
+        let to_id = insert_new_node(&mut graph, "To");
 
-        let command = format!("update node Person($id=\"{}\",name=\"Janne\")", identifier);
+        insert_new_edge(&mut graph, from_id.clone(), to_id.clone(), 50);
 
         // When
-        let result = query_parser::command(command.as_str(), &mut graph, &mut chain);
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, "compute centrality weighted");
 
         // Then
-        assert_graph_result(
-            result,
-            vec![
-                (InternalNodeAttribute::ID_ATTRIBUTE, identifier.as_str()),
-                (InternalNodeAttribute::NAME_ATTRIBUTE, "Person"),
-                ("name", "Janne"),
-                (InternalNodeAttribute::EDGE_COUNT_ATTRIBUTE, "0"),
-            ],
-        );
-        assert_eq!(graph.nodes.len(), 1);
+        let items = result.unwrap().unwrap();
+        assert_eq!(items.len(), 2);
+        items
+            .iter()
+            .for_each(|item| assert_eq!(item.get(InternalNodeAttribute::CENTRALITY_ATTRIBUTE).unwrap(), "50"));
     }
 
     #[test]
-    fn should_delete_node() {
+    fn should_compute_centrality_for_single_node_name() {
         // Given
         let mut graph = Graph::default();
         let mut chain = Chain::default();
-        let identifier = insert_new_node(&mut graph, "Person");
+        let from_id = insert_new_node(&mut graph, "From");
+        let to_id = insert_new_node(&mut graph, "To");
 
-        let command = format!("delete node Person($id=\"{}\")", identifier);
+        insert_new_edge(&mut graph, from_id.clone(), to_id.clone(), 50);
 
         // When
-        let result = query_parser::command(command.as_str(), &mut graph, &mut chain);
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, "compute centrality weighted for To");
 
         // Then
         assert_graph_result(
             result,
             vec![
-                (InternalNodeAttribute::ID_ATTRIBUTE, identifier.as_str()),
-                (InternalNodeAttribute::NAME_ATTRIBUTE, "Person"),
-                (InternalNodeAttribute::EDGE_COUNT_ATTRIBUTE, "0"),
+                (InternalNodeAttribute::ID_ATTRIBUTE, to_id.as_str()),
+                (InternalNodeAttribute::NAME_ATTRIBUTE, "To"),
+                (InternalNodeAttribute::CENTRALITY_ATTRIBUTE, "0"),
             ],
         );
-        assert!(graph.nodes.is_empty());
     }
 
     #[test]
-    fn should_add_edge() {
+    fn should_export_graph() {
         // Given
         let mut graph = Graph::default();
         let mut chain = Chain::default();
         let from_id = insert_new_node(&mut graph, "From");
         let to_id = insert_new_node(&mut graph, "To");
 
-        let cmd = format!("add connection from From($id=\"{}\") to To($id=\"{}\") with weight 50", from_id, to_id);
+        insert_new_edge(&mut graph, from_id.clone(), to_id.clone(), 50);
+
+        let path = std::env::temp_dir().join("should_export_graph.dot");
+        let cmd = format!("export graph dot \"{}\"", path.display());
 
         // When
-        let result = query_parser::command(cmd.as_str(), &mut graph, &mut chain);
+        let result = QueryProcessor::parse_command(&mut graph, &mut chain, cmd.as_str());
 
         // Then
         assert_graph_result(
             result,
-            vec![
-                (InternalNodeAttribute::FROM_ATTRIBUTE, "From"),
-                (InternalNodeAttribute::TO_ATTRIBUTE, "To"),
-                (InternalNodeAttribute::WEIGHT_ATTRIBUTE, "50"),
-            ],
+            vec![("format", "dot"), ("path", path.to_str().unwrap()), ("nodes", "2"), ("edges", "1")],
         );
-        assert_edge(&mut graph, from_id, to_id, 50);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains(format!("\"{from_id}:From\" -> \"{to_id}:To\" [weight=50, label=\"50\"];").as_str()));
+
+        std::fs::remove_file(path).unwrap();
     }
 
     #[test]
-    fn should_update_edge() {
+    fn should_export_and_import_data() {
         // Given
         let mut graph = Graph::default();
         let mut chain = Chain::default();
@@ -308,51 +2291,69 @@ mod tests {
 
         insert_new_edge(&mut graph, from_id.clone(), to_id.clone(), 50);
 
-        let cmd = format!("update connection from From($id=\"{}\") to To($id=\"{}\") with weight 80", from_id, to_id);
+        let path = std::env::temp_dir().join("should_export_and_import_data.ndjson");
+        let export = format!("export data \"{}\"", path.display());
+        let import = format!("import data \"{}\"", path.display());
+
+        let mut imported_graph = Graph::default();
+        let mut imported_chain = Chain::default();
 
         // When
-        let result = query_parser::command(cmd.as_str(), &mut graph, &mut chain);
+        let export_result = QueryProcessor::parse_command(&mut graph, &mut chain, export.as_str());
+        let import_result = QueryProcessor::parse_command(&mut imported_graph, &mut imported_chain, import.as_str());
 
         // Then
         assert_graph_result(
-            result,
-            vec![
-                (InternalNodeAttribute::FROM_ATTRIBUTE, "From"),
-                (InternalNodeAttribute::TO_ATTRIBUTE, "To"),
-                (InternalNodeAttribute::WEIGHT_ATTRIBUTE, "80"),
-            ],
+            export_result,
+            vec![("path", path.to_str().unwrap()), ("definitions", "2"), ("nodes", "2"), ("edges", "1")],
         );
-        assert_edge(&mut graph, from_id, to_id, 80);
+        assert_graph_result(import_result, vec![("path", path.to_str().unwrap()), ("imported", "5"), ("failed", "0")]);
+
+        assert_eq!(imported_graph.definitions.len(), 2);
+        assert_edge(&imported_graph, from_id, to_id, 50);
+
+        std::fs::remove_file(path).unwrap();
     }
 
     #[test]
-    fn should_delete_edge() {
+    fn should_export_and_import_encrypted_data() {
         // Given
         let mut graph = Graph::default();
         let mut chain = Chain::default();
+        chain.encryption = Some(StorageEncryption::from_passphrase("secret"));
         let from_id = insert_new_node(&mut graph, "From");
         let to_id = insert_new_node(&mut graph, "To");
 
         insert_new_edge(&mut graph, from_id.clone(), to_id.clone(), 50);
 
-        let cmd = format!("delete connection from From($id=\"{}\") to To($id=\"{}\")", from_id, to_id);
+        let path = std::env::temp_dir().join("should_export_and_import_encrypted_data.ndjson");
+        let export = format!("export data \"{}\"", path.display());
+        let import = format!("import data \"{}\"", path.display());
+        let graph_path = std::env::temp_dir().join("should_export_and_import_encrypted_data.dot");
+
+        let mut imported_graph = Graph::default();
+        let mut imported_chain = Chain::default();
+        imported_chain.encryption = Some(StorageEncryption::from_passphrase("secret"));
+        let mut plain_chain = Chain::default();
 
         // When
-        let result = query_parser::command(cmd.as_str(), &mut graph, &mut chain);
+        QueryProcessor::parse_command(&mut graph, &mut chain, export.as_str()).unwrap().unwrap();
+        let import_result = QueryProcessor::parse_command(&mut imported_graph, &mut imported_chain, import.as_str());
+        let plain_result = QueryProcessor::parse_command(&mut Graph::default(), &mut plain_chain, import.as_str());
+        QueryProcessor::parse_command(&mut graph, &mut chain, &format!("export graph dot \"{}\"", graph_path.display()))
+            .unwrap()
+            .unwrap();
 
         // Then
-        assert_graph_result(
-            result,
-            vec![
-                (InternalNodeAttribute::FROM_ATTRIBUTE, "From"),
-                (InternalNodeAttribute::TO_ATTRIBUTE, "To"),
-                (InternalNodeAttribute::WEIGHT_ATTRIBUTE, "50"),
-            ],
-        );
-
-        for (_, node) in &graph.nodes {
-            assert!(node.edges.is_empty());
-        }
+        assert!(!std::fs::read_to_string(&path).unwrap().contains(&from_id));
+        assert!(!std::fs::read_to_string(&graph_path).unwrap().contains(&from_id));
+        assert_graph_result(import_result, vec![("path", path.to_str().unwrap()), ("imported", "5"), ("failed", "0")]);
+        assert_edge(&imported_graph, from_id, to_id, 50);
+        // Header of encryption isn't valid line of plain file
+        assert_eq!(plain_result.unwrap().unwrap()[0]["failed"], "6");
+
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(graph_path).unwrap();
     }
 
     fn insert_new_node(graph: &mut Graph, name: &str) -> String {
@@ -422,7 +2423,7 @@ mod tests {
             } else if *id == format!("{to_id}:To") {
                 assert!(node.edges.is_empty())
             } else {
-                assert!(false)
+                panic!("Unexpected node {id}")
             }
         }
     }
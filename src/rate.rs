@@ -0,0 +1,94 @@
+use crate::graph::error::DatabaseError;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Number of events allowed per second, e.g. blocks received from one peer or commands of one query client. Bursts of
+/// up to one second of events are allowed, so events which come in short bursts aren't rejected.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RateLimit {
+    pub rate: f64,
+}
+
+impl RateLimit {
+    /// Rate limit given by argument with given name (e.g. `--peer-block-rate="20"`), or `None` if it's not given.
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>, name: &str) -> Result<Option<RateLimit>, DatabaseError> {
+        let Some(value) = arguments.get(name).and_then(|values| values.first()) else {
+            return Ok(None);
+        };
+
+        match value.parse::<f64>() {
+            Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(Some(RateLimit { rate })),
+            _ => Err(DatabaseError::InvalidRateLimit(format!("{name}={value}"))),
+        }
+    }
+
+    pub fn bucket(&self) -> TokenBucket {
+        TokenBucket {
+            limit: *self,
+            tokens: self.burst(),
+            refilled_at: Instant::now(),
+        }
+    }
+
+    fn burst(&self) -> f64 {
+        self.rate.max(1.0)
+    }
+}
+
+/// Token bucket of one source of events, which is refilled with the rate of its limit.
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    /// Take token for the event, or return false if the source exceeded its rate limit.
+    pub fn try_take(&mut self) -> bool {
+        self.try_take_at(Instant::now())
+    }
+
+    fn try_take_at(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.burst());
+        self.refilled_at = now;
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn should_limit_rate_of_events() {
+        // Given
+        let arguments = HashMap::from([("peer-block-rate".to_string(), vec!["2".to_string()])]);
+        let limit = RateLimit::from_arguments(&arguments, "peer-block-rate").unwrap().unwrap();
+        let mut bucket = limit.bucket();
+        let now = Instant::now();
+
+        // When
+        let burst = (0..3).map(|_| bucket.try_take_at(now)).collect::<Vec<bool>>();
+        let refilled = bucket.try_take_at(now + Duration::from_millis(500));
+        let exhausted = bucket.try_take_at(now + Duration::from_millis(600));
+
+        // Then
+        assert_eq!(burst, vec![true, true, false]);
+        assert!(refilled);
+        assert!(!exhausted);
+        assert_eq!(RateLimit::from_arguments(&HashMap::new(), "peer-block-rate").unwrap(), None);
+        assert!(RateLimit::from_arguments(
+            &HashMap::from([("peer-block-rate".to_string(), vec!["0".to_string()])]),
+            "peer-block-rate"
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,335 @@
+use rustc_hash::FxHashMap;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{Context, Editor, Helper, Highlighter, Hinter, Validator};
+use std::io::IsTerminal;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tokio::io::AsyncBufReadExt;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+/// Node definitions (name with its attributes) shared with REPL for completion.
+pub type Definitions = Arc<Mutex<FxHashMap<String, Vec<String>>>>;
+
+/// Command keywords of query language (including Cypher subset), used for completion.
+const KEYWORDS: [&str; 95] = [
+    "add",
+    "agent",
+    "agents",
+    "allow",
+    "around",
+    "as",
+    "at",
+    "audit",
+    "balances",
+    "ban",
+    "betweenness",
+    "by",
+    "block",
+    "blocks",
+    "centrality",
+    "chain",
+    "close",
+    "communities",
+    "compute",
+    "connect",
+    "connection",
+    "connections",
+    "create",
+    "data",
+    "database",
+    "databases",
+    "define",
+    "decrement",
+    "degree",
+    "delegate",
+    "delete",
+    "deny",
+    "depth",
+    "do",
+    "dot",
+    "drop",
+    "edges",
+    "expires",
+    "export",
+    "fetch",
+    "for",
+    "from",
+    "graph",
+    "graphml",
+    "history",
+    "if",
+    "import",
+    "increment",
+    "issue",
+    "join",
+    "key",
+    "loops",
+    "maximum",
+    "mnemonic",
+    "mst",
+    "node",
+    "nodes",
+    "on",
+    "order",
+    "peer",
+    "peers",
+    "query",
+    "replace",
+    "restore",
+    "role",
+    "rotate",
+    "secret",
+    "self",
+    "session",
+    "sessions",
+    "set",
+    "soft",
+    "stake",
+    "stats",
+    "subgraph",
+    "to",
+    "token",
+    "trigger",
+    "triggers",
+    "ttl",
+    "update",
+    "upsert",
+    "use",
+    "validators",
+    "verify",
+    "view",
+    "wallet",
+    "weight",
+    "weighted",
+    "where",
+    "with",
+    "MATCH",
+    "WHERE",
+    "AND",
+    "RETURN",
+];
+
+#[derive(Helper, Hinter, Highlighter, Validator)]
+struct ReplHelper {
+    definitions: Definitions,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    /// Attributes of node are completed inside its brackets, e.g. `User(na` completes to `User(name`, while
+    /// keywords and node names are completed everywhere else.
+    fn complete(&self, line: &str, position: usize, _: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..position];
+        let start = line
+            .rfind(|character: char| character.is_whitespace() || "(),".contains(character))
+            .map_or(0, |index| index + 1);
+        let word = &line[start..];
+
+        let definitions = self.definitions.lock().unwrap();
+        let candidates: Vec<String> = match Self::open_node(line) {
+            Some(name) => definitions.get(name).cloned().unwrap_or_default(),
+            None => KEYWORDS
+                .iter()
+                .map(|keyword| keyword.to_string())
+                .chain(definitions.keys().cloned())
+                .collect(),
+        };
+
+        let mut candidates: Vec<Pair> = candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect();
+        candidates.sort_by(|first, second| first.display.cmp(&second.display));
+
+        Ok((start, candidates))
+    }
+}
+
+impl ReplHelper {
+    /// Name of the node whose brackets are not closed yet.
+    fn open_node(line: &str) -> Option<&str> {
+        let open = line.rfind('(')?;
+
+        if line[open..].contains(')') {
+            return None;
+        }
+
+        line[..open].split_whitespace().last()
+    }
+}
+
+/// Lines read from input, until statement is terminated by `;`. Comments (`--` or `//` until the end of line) are
+/// removed, while JSON envelopes are complete statements on their own.
+#[derive(Default)]
+pub struct Statements {
+    statement: String,
+}
+
+impl Statements {
+    /// Add line to current statement, returns statements which are terminated in it.
+    pub fn push(&mut self, line: &str) -> Vec<String> {
+        if self.is_empty() && line.trim_start().starts_with('{') {
+            return vec![line.to_string()];
+        }
+
+        let mut statements = vec![];
+        let mut characters = line.chars().peekable();
+        let mut is_quoted = false;
+
+        while let Some(character) = characters.next() {
+            match character {
+                '\\' if is_quoted => {
+                    self.statement.push(character);
+                    self.statement.extend(characters.next());
+                    continue;
+                }
+                '"' => is_quoted = !is_quoted,
+                ';' if !is_quoted => {
+                    statements.push(self.statement.trim().to_string());
+                    self.statement.clear();
+                    continue;
+                }
+                '-' | '/' if !is_quoted && characters.peek() == Some(&character) => break,
+                _ => {}
+            }
+
+            self.statement.push(character);
+        }
+
+        if !self.is_empty() {
+            self.statement.push('\n');
+        }
+
+        statements.retain(|statement| !statement.is_empty());
+        statements
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.statement.trim().is_empty()
+    }
+}
+
+pub struct Repl;
+
+impl Repl {
+    const HISTORY_FILE: &'static str = ".weighted_graph_history";
+
+    /// Read statements from stdin. If stdin is terminal, interactive REPL with command history and completion is
+    /// started, otherwise lines are read as they come. Channel is closed when input ends.
+    pub fn spawn(definitions: Definitions) -> UnboundedReceiver<String> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        if std::io::stdin().is_terminal() {
+            thread::spawn(move || {
+                if let Err(error) = Self::run(definitions, &sender) {
+                    eprintln!("REPL :: {error}");
+                }
+            });
+        } else {
+            tokio::spawn(async move {
+                let mut input = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+                let mut statements = Statements::default();
+
+                while let Ok(Some(line)) = input.next_line().await {
+                    for statement in statements.push(&line) {
+                        if sender.send(statement).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+
+        receiver
+    }
+
+    fn run(definitions: Definitions, sender: &mpsc::UnboundedSender<String>) -> rustyline::Result<()> {
+        let mut editor: Editor<ReplHelper, DefaultHistory> = Editor::new()?;
+        editor.set_helper(Some(ReplHelper { definitions }));
+        // History doesn't exist on first start
+        let _ = editor.load_history(Self::HISTORY_FILE);
+
+        let mut statements = Statements::default();
+
+        loop {
+            let prompt = if statements.is_empty() { "> " } else { "... " };
+
+            match editor.readline(prompt) {
+                Ok(line) => {
+                    if !line.trim().is_empty() {
+                        editor.add_history_entry(line.as_str())?;
+                    }
+
+                    for statement in statements.push(&line) {
+                        if sender.send(statement).is_err() {
+                            return editor.save_history(Self::HISTORY_FILE);
+                        }
+                    }
+                }
+                Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+                Err(error) => return Err(error),
+            }
+        }
+
+        editor.save_history(Self::HISTORY_FILE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustyline::history::MemHistory;
+
+    #[test]
+    fn should_complete_keywords_and_attributes() {
+        // Given
+        let mut definitions = FxHashMap::default();
+        definitions.insert("User".to_string(), vec!["name".to_string(), "premium".to_string()]);
+        let helper = ReplHelper {
+            definitions: Arc::new(Mutex::new(definitions)),
+        };
+        let history = MemHistory::new();
+        let context = Context::new(&history);
+
+        // When
+        let (_, keywords) = helper.complete("fetch no", 8, &context).unwrap();
+        let (start, attributes) = helper.complete("add node User(name=\"John\",pr", 28, &context).unwrap();
+        let (_, names) = helper.complete("fetch node Us", 13, &context).unwrap();
+
+        // Then
+        assert_eq!(
+            keywords.iter().map(|pair| pair.display.as_str()).collect::<Vec<&str>>(),
+            vec!["node", "nodes"]
+        );
+        assert_eq!(start, 26);
+        assert_eq!(attributes[0].replacement, "premium");
+        assert_eq!(names[0].replacement, "User");
+    }
+
+    #[test]
+    fn should_split_statements() {
+        // Given
+        let mut statements = Statements::default();
+
+        // When
+        let first = statements.push("-- Users");
+        let second = statements.push("add node User(name=\"John; \\\"Doe\\\"\") // first user");
+        let third = statements.push("  ; fetch agents; fetch");
+        let fourth = statements.push("balances;");
+
+        // Then
+        assert!(first.is_empty());
+        assert!(second.is_empty());
+        assert_eq!(third, vec!["add node User(name=\"John; \\\"Doe\\\"\")", "fetch agents"]);
+        assert_eq!(fourth, vec!["fetch\nbalances"]);
+        assert!(statements.is_empty());
+    }
+}
@@ -0,0 +1,333 @@
+use crate::access::{AccessControl, Role};
+use crate::graph::error::DatabaseError;
+use crate::namespace::{Databases, Namespaces};
+use crate::query_processor::QueryProcessor;
+use crate::repl::Statements;
+use crate::session::Sessions;
+use std::collections::HashMap;
+use std::future;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+use tokio::{select, task, time};
+
+/// TCP frontend for query clients. Each client sends statements the same way as on standard input (terminated by `;`),
+/// and receives one JSON line for each of them. Statements are executed on blocking threads, so read-only commands of
+/// different clients run in parallel. With access file, each client has its own role (see `AccessControl`). Each
+/// connection has its session (see `Sessions`) with its selected database and pending transaction, which is closed
+/// when client is idle longer than idle timeout, and whose commands over the rate limit are rejected.
+pub struct QueryServer {
+    listener: TcpListener,
+    access: Option<Arc<AccessControl>>,
+    sessions: Arc<Sessions>,
+}
+
+impl QueryServer {
+    /// Listen on address given by `--query-address="127.0.0.1:7878"` argument, if it is given.
+    pub async fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> io::Result<Option<QueryServer>> {
+        match arguments.get("query-address").and_then(|values| values.first()) {
+            None => Ok(None),
+            Some(address) => Ok(Some(QueryServer {
+                listener: TcpListener::bind(address).await?,
+                access: None,
+                sessions: Arc::default(),
+            })),
+        }
+    }
+
+    pub fn with_access(mut self, access: Option<Arc<AccessControl>>) -> QueryServer {
+        self.access = access;
+        self
+    }
+
+    pub fn with_sessions(mut self, sessions: Arc<Sessions>) -> QueryServer {
+        self.sessions = sessions;
+        self
+    }
+
+    pub fn spawn(self, databases: Databases) {
+        tokio::spawn(async move {
+            loop {
+                match self.listener.accept().await {
+                    Ok((stream, address)) => {
+                        let databases = databases.clone();
+                        let access = self.access.clone();
+                        let sessions = self.sessions.clone();
+                        tokio::spawn(async move {
+                            let (session, closed) = sessions.open(address, AccessControl::default_role(access.as_deref()));
+                            let result = Self::handle_client(stream, address, databases, access, &sessions, session, closed).await;
+                            sessions.end(session);
+
+                            if let Err(error) = result {
+                                eprintln!("Query client {address} :: {error}");
+                            }
+                        });
+                    }
+                    Err(error) => eprintln!("Query server :: {error}"),
+                }
+            }
+        });
+    }
+
+    async fn handle_client(
+        stream: TcpStream,
+        address: SocketAddr,
+        databases: Databases,
+        access: Option<Arc<AccessControl>>,
+        sessions: &Arc<Sessions>,
+        session: u64,
+        closed: Arc<Notify>,
+    ) -> io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut statements = Statements::default();
+        let mut role = AccessControl::default_role(access.as_deref());
+        let mut bucket = sessions.command_rate.map(|limit| limit.bucket());
+
+        loop {
+            let received = select! {
+                line = lines.next_line() => match line? {
+                    Some(line) => Ok(line),
+                    None => return Ok(()),
+                },
+                _ = Self::idle(sessions.idle_timeout) => {
+                    Err(format!("client {address} was idle for {} seconds", sessions.idle_timeout.unwrap_or_default().as_secs()))
+                },
+                _ = closed.notified() => Err(format!("client {address} was disconnected by admin")),
+            };
+            let line = match received {
+                Ok(line) => line,
+                Err(reason) => {
+                    let error = DatabaseError::SessionClosed(reason);
+                    let mut response = QueryProcessor::error_response(error.code(), &error.to_string()).to_string();
+                    response.push('\n');
+                    return writer.write_all(response.as_bytes()).await;
+                }
+            };
+
+            for statement in statements.push(&line) {
+                // Rejected statement gets error response, so client can match it to its request and retry it later
+                if bucket.as_mut().is_some_and(|bucket| !bucket.try_take()) {
+                    let rate = sessions.command_rate.map(|limit| limit.rate).unwrap_or_default();
+                    let error = DatabaseError::RateLimitExceeded(format!("client {address} exceeded {rate} commands per second"));
+                    eprintln!("Query client {address} :: {error}");
+                    sessions.limit(session);
+
+                    let mut response = QueryProcessor::handle_request(|_| Ok(Err(error)), &statement);
+                    response.push('\n');
+                    writer.write_all(response.as_bytes()).await?;
+                    continue;
+                }
+
+                let (databases, access, sessions) = (databases.clone(), access.clone(), sessions.clone());
+                let (mut response, client_role) = task::spawn_blocking(move || {
+                    // Tokens are signed by wallet of the node, which is shared by all its databases
+                    let database = databases.read().unwrap()[Namespaces::DEFAULT].clone();
+                    let mut client = None;
+                    let response = QueryProcessor::handle_request(
+                        |command| match access
+                            .as_ref()
+                            .and_then(|access| access.authenticate(command, || database.chain.read().unwrap().signer.get_public_key()))
+                        {
+                            // Client keeps its role for following statements
+                            Some(Ok((client_role, result))) => {
+                                role = Some(client_role);
+                                client = result
+                                    .as_ref()
+                                    .ok()
+                                    .and_then(|rows| rows.first())
+                                    .and_then(|row| row.get("client"))
+                                    .cloned();
+                                Ok(result)
+                            }
+                            Some(Err(error)) => Ok(Err(error)),
+                            None if Role::is_console_only(command) => Ok(Err(DatabaseError::PermissionDenied(
+                                "command is available only on local console".to_string(),
+                            ))),
+                            None => sessions.execute_from(session, &format!("query {address}"), role, command, &databases),
+                        },
+                        &statement,
+                    );
+                    sessions.record(session, role, client);
+                    (response, role)
+                })
+                .await
+                .map_err(io::Error::other)?;
+                role = client_role;
+
+                response.push('\n');
+                writer.write_all(response.as_bytes()).await?;
+            }
+        }
+    }
+
+    /// Wait until idle timeout passes, or forever if idle sessions are kept open.
+    async fn idle(timeout: Option<Duration>) {
+        match timeout {
+            Some(timeout) => time::sleep(timeout).await,
+            None => future::pending().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::genesis::Genesis;
+    use crate::database::Database;
+
+    fn databases(database: Database) -> Databases {
+        Namespaces::new(Arc::new(database), Genesis::default(), HashMap::new()).shared()
+    }
+
+    #[tokio::test]
+    async fn should_execute_statements_from_client() {
+        // Given
+        let mut arguments = HashMap::new();
+        arguments.insert("query-address".to_string(), vec!["127.0.0.1:0".to_string()]);
+        let access = serde_json::from_str(r#"{"default_role":"admin"}"#).unwrap();
+        let server = QueryServer::from_arguments(&arguments)
+            .await
+            .unwrap()
+            .unwrap()
+            .with_access(Some(Arc::new(access)));
+        let address = server.listener.local_addr().unwrap();
+        server.spawn(databases(Database::default()));
+
+        // When
+        let stream = TcpStream::connect(address).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        writer
+            .write_all(b"define node User(name);\n#2 fetch\nagents;\nwallet export mnemonic;\n")
+            .await
+            .unwrap();
+        let mut lines = BufReader::new(reader).lines();
+
+        // Then
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), r#"[{"name":"*"}]"#);
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), r#"{"id":"2","result":[]}"#);
+        assert!(lines.next_line().await.unwrap().unwrap().contains("PERMISSION_DENIED"));
+    }
+
+    #[tokio::test]
+    async fn should_enforce_role_of_client() {
+        // Given
+        let mut arguments = HashMap::new();
+        arguments.insert("query-address".to_string(), vec!["127.0.0.1:0".to_string()]);
+        let access = serde_json::from_str(r#"{"default_role":"reader","clients":[{"name":"ingest","token":"secret","role":"writer"}]}"#).unwrap();
+        let server = QueryServer::from_arguments(&arguments)
+            .await
+            .unwrap()
+            .unwrap()
+            .with_access(Some(Arc::new(access)));
+        let address = server.listener.local_addr().unwrap();
+
+        let database = Database::default();
+        database.execute("define node User(name)").unwrap().unwrap();
+        server.spawn(databases(database));
+
+        // When
+        let stream = TcpStream::connect(address).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        writer
+            .write_all(b"add node User(name=\"John\");\nauthenticate secret;\nadd node User(name=\"John\");\ndefine node Song(name);\n")
+            .await
+            .unwrap();
+        let mut lines = BufReader::new(reader).lines();
+
+        // Then
+        assert!(lines.next_line().await.unwrap().unwrap().contains("PERMISSION_DENIED"));
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), r#"[{"client":"ingest","role":"writer"}]"#);
+        assert!(lines.next_line().await.unwrap().unwrap().contains("John"));
+        assert!(lines.next_line().await.unwrap().unwrap().contains("PERMISSION_DENIED"));
+    }
+
+    #[tokio::test]
+    async fn should_close_idle_and_disconnected_sessions() {
+        // Given
+        let mut arguments = HashMap::new();
+        arguments.insert("query-address".to_string(), vec!["127.0.0.1:0".to_string()]);
+        let mut sessions = Sessions::default();
+        sessions.idle_timeout = Some(Duration::from_millis(300));
+        let sessions = Arc::new(sessions);
+        let access = serde_json::from_str(r#"{"default_role":"admin"}"#).unwrap();
+        let server = QueryServer::from_arguments(&arguments)
+            .await
+            .unwrap()
+            .unwrap()
+            .with_access(Some(Arc::new(access)))
+            .with_sessions(sessions.clone());
+        let address = server.listener.local_addr().unwrap();
+        server.spawn(databases(Database::default()));
+
+        // When
+        let idle = TcpStream::connect(address).await.unwrap();
+        let (idle_reader, _idle_writer) = idle.into_split();
+        let stream = TcpStream::connect(address).await.unwrap();
+        let client_address = stream.local_addr().unwrap().to_string();
+        let (reader, mut writer) = stream.into_split();
+        writer.write_all(b"fetch sessions;\n").await.unwrap();
+        let mut lines = BufReader::new(reader).lines();
+        let fetched: serde_json::Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        let id = fetched
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|session| session["address"] == client_address.as_str())
+            .unwrap()["id"]
+            .clone();
+        writer
+            .write_all(format!("close session {};\n", id.as_str().unwrap()).as_bytes())
+            .await
+            .unwrap();
+
+        // Then
+        assert_eq!(
+            fetched.as_array().unwrap().iter().find(|session| session["id"] == id).unwrap()["role"],
+            "admin"
+        );
+        assert!(lines.next_line().await.unwrap().unwrap().contains(&format!(r#""id":{id}"#)));
+        assert!(lines.next_line().await.unwrap().unwrap().contains("SESSION_CLOSED"));
+        assert_eq!(lines.next_line().await.unwrap(), None);
+        let mut idle_lines = BufReader::new(idle_reader).lines();
+        assert!(idle_lines.next_line().await.unwrap().unwrap().contains("was idle"));
+        assert_eq!(idle_lines.next_line().await.unwrap(), None);
+        assert!(sessions.execute(Some(Role::Admin), "fetch sessions").unwrap().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_reject_commands_exceeding_rate_limit() {
+        // Given
+        let arguments = HashMap::from([
+            ("query-address".to_string(), vec!["127.0.0.1:0".to_string()]),
+            ("client-command-rate".to_string(), vec!["1".to_string()]),
+        ]);
+        let sessions = Arc::new(Sessions::from_arguments(&arguments).unwrap());
+        let server = QueryServer::from_arguments(&arguments)
+            .await
+            .unwrap()
+            .unwrap()
+            .with_sessions(sessions.clone());
+        let address = server.listener.local_addr().unwrap();
+        server.spawn(databases(Database::default()));
+
+        // When
+        let stream = TcpStream::connect(address).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        writer.write_all(b"fetch agents;\n#2 fetch agents;\n").await.unwrap();
+        let mut lines = BufReader::new(reader).lines();
+        let accepted = lines.next_line().await.unwrap().unwrap();
+        let rejected = lines.next_line().await.unwrap().unwrap();
+
+        // Then
+        assert_eq!(accepted, "[]");
+        assert!(rejected.contains(r#""id":"2""#) && rejected.contains("RATE_LIMIT_EXCEEDED"));
+        let fetched = sessions.execute(Some(Role::Admin), "fetch sessions").unwrap().unwrap();
+        assert_eq!(fetched[0]["rate_limited"], "1");
+        assert_eq!(fetched[0]["commands"], "1");
+    }
+}
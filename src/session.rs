@@ -0,0 +1,423 @@
+extern crate peg;
+
+use crate::access::Role;
+use crate::database::Database;
+use crate::graph::error::DatabaseError;
+use crate::graph::expiry::Expiry;
+use crate::graph::GraphResults;
+use crate::namespace::{Databases, Namespaces};
+use crate::parser::command_parser;
+use crate::rate::RateLimit;
+use peg::error::ParseError;
+use peg::str::LineCol;
+use rustc_hash::FxHashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+enum SessionCommand {
+    Fetch,
+    Close(u64),
+}
+
+enum ClientCommand {
+    Use(String),
+    Begin,
+    Commit,
+    Rollback,
+}
+
+command_parser! {
+    grammar session_parser() for str {
+        pub rule command() -> SessionCommand = command:(fetch_sessions() / close_session()) _ ";"? _ { command }
+
+        rule fetch_sessions() -> SessionCommand = _ keyword("fetch") _ keyword("sessions") {
+            SessionCommand::Fetch
+        }
+
+        rule close_session() -> SessionCommand = _ keyword("close") _ keyword("session") _ id:$(['0'..='9']+) {?
+            id.parse().map(SessionCommand::Close).or(Err("session id"))
+        }
+
+        pub rule client_command() -> ClientCommand = command:(use_database() / begin() / commit() / rollback()) _ ";"? _ { command }
+
+        rule use_database() -> ClientCommand = _ keyword("use") _ name:$(['a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-']+) {
+            ClientCommand::Use(name.to_string())
+        }
+
+        rule begin() -> ClientCommand = _ keyword("begin") { ClientCommand::Begin }
+
+        rule commit() -> ClientCommand = _ keyword("commit") { ClientCommand::Commit }
+
+        rule rollback() -> ClientCommand = _ keyword("rollback") { ClientCommand::Rollback }
+    }
+}
+
+struct Session {
+    address: SocketAddr,
+    client: Option<String>,
+    role: Option<Role>,
+    /// Name of the database selected by `use <name>`
+    database: String,
+    /// Changes queued since `begin`, or `None` if there is no pending transaction
+    transaction: Option<Vec<String>>,
+    /// Seconds since Unix epoch
+    connected_at: u64,
+    last_active: Instant,
+    commands: u64,
+    /// Number of commands rejected by rate limit
+    rate_limited: u64,
+    closed: Arc<Notify>,
+}
+
+/// Connections of query server clients, with identity and role of each client. Session is opened when client connects
+/// and ended when it disconnects, is idle longer than idle timeout, or is closed by admin with `close session <id>`.
+/// Admins can list open sessions with `fetch sessions`. Each session has its own selected database and pending
+/// transaction (see `execute_from`).
+pub struct Sessions {
+    sessions: Mutex<BTreeMap<u64, Session>>,
+    next_id: AtomicU64,
+    /// Time after which session without any command is closed, or `None` if idle sessions are kept open
+    pub idle_timeout: Option<Duration>,
+    /// Limit of commands of each session, so flooding client can't take over blocking threads
+    pub command_rate: Option<RateLimit>,
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Sessions {
+            sessions: Mutex::default(),
+            next_id: AtomicU64::new(1),
+            idle_timeout: Some(Self::DEFAULT_IDLE_TIMEOUT),
+            command_rate: None,
+        }
+    }
+}
+
+impl Sessions {
+    const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+    /// Create sessions from `--session-idle-timeout` argument, given in seconds (default is 15 minutes, while 0 keeps
+    /// idle sessions open), and `--client-command-rate` argument, given in commands per second.
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> Result<Sessions, DatabaseError> {
+        let idle_timeout = match arguments.get("session-idle-timeout").and_then(|values| values.first()) {
+            None => Some(Self::DEFAULT_IDLE_TIMEOUT),
+            Some(value) => {
+                let seconds: u64 = value.parse().map_err(|_| DatabaseError::InvalidTimeout(value.clone()))?;
+                (seconds > 0).then_some(Duration::from_secs(seconds))
+            }
+        };
+
+        Ok(Sessions {
+            idle_timeout,
+            command_rate: RateLimit::from_arguments(arguments, "client-command-rate")?,
+            ..Sessions::default()
+        })
+    }
+
+    /// Open session of connected client, and return its id with notification which is sent when session is closed by
+    /// admin.
+    pub fn open(&self, address: SocketAddr, role: Option<Role>) -> (u64, Arc<Notify>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let closed = Arc::new(Notify::new());
+
+        let session = Session {
+            address,
+            client: None,
+            role,
+            database: Namespaces::DEFAULT.to_string(),
+            transaction: None,
+            connected_at: Expiry::now(),
+            last_active: Instant::now(),
+            commands: 0,
+            rate_limited: 0,
+            closed: closed.clone(),
+        };
+        self.sessions.lock().unwrap().insert(id, session);
+
+        (id, closed)
+    }
+
+    /// Record command executed by the session, with role (and client name, if it has authenticated) after the command.
+    pub fn record(&self, id: u64, role: Option<Role>, client: Option<String>) {
+        if let Some(session) = self.sessions.lock().unwrap().get_mut(&id) {
+            session.role = role;
+            session.client = client.or(session.client.take());
+            session.last_active = Instant::now();
+            session.commands += 1;
+        }
+    }
+
+    /// Record command of the session which was rejected by rate limit.
+    pub fn limit(&self, id: u64) {
+        if let Some(session) = self.sessions.lock().unwrap().get_mut(&id) {
+            session.rate_limited += 1;
+        }
+    }
+
+    pub fn end(&self, id: u64) {
+        self.sessions.lock().unwrap().remove(&id);
+    }
+
+    /// Execute session command if client with given role is admin, or return `None` if given command is not session
+    /// command.
+    pub fn execute(&self, role: Option<Role>, command: &str) -> Option<GraphResults> {
+        let command = session_parser::command(command).ok()?;
+        if role < Some(Role::Admin) {
+            return Some(Err(DatabaseError::PermissionDenied("command requires admin role".to_string())));
+        }
+
+        Some(match command {
+            SessionCommand::Fetch => Ok(self.fetch()),
+            SessionCommand::Close(id) => self.close(id),
+        })
+    }
+
+    /// Execute command of given session on its database. Besides admin commands (see `execute`), client can select
+    /// database of its session with `use <name>` and start transaction with `begin`. Changes of nodes and connections
+    /// are then queued until `commit` executes all of them (or none of them, if any of them fails) or `rollback`
+    /// discards them, while other commands are executed immediately, without seeing queued changes.
+    pub fn execute_from(
+        &self,
+        id: u64,
+        source: &str,
+        role: Option<Role>,
+        command: &str,
+        databases: &Databases,
+    ) -> Result<GraphResults, ParseError<LineCol>> {
+        if let Some(result) = self.execute(role, command) {
+            return Ok(result);
+        }
+
+        let client_command = session_parser::client_command(command).ok();
+        let queued = client_command.is_none() && Role::required(command) == Role::Writer && self.in_transaction(id);
+        if client_command.is_none() && !queued {
+            return match self.database(id, databases) {
+                Ok(database) => database.execute_from(source, role, command),
+                Err(error) => Ok(Err(error)),
+            };
+        }
+        if let Err(error) = Role::authorize(role, command) {
+            return Ok(Err(error));
+        }
+
+        Ok(match client_command {
+            Some(ClientCommand::Use(name)) => self.select(id, name, databases),
+            Some(ClientCommand::Begin) => self.begin(id),
+            Some(ClientCommand::Commit) => self.commit(id, source, role, databases),
+            Some(ClientCommand::Rollback) => self.rollback(id),
+            None => self.queue(id, command),
+        })
+    }
+
+    /// Database selected by given session.
+    pub fn database(&self, id: u64, databases: &Databases) -> Result<Arc<Database>, DatabaseError> {
+        let name = self.with_session(id, |session| Ok(session.database.clone()))?;
+
+        databases.read().unwrap().get(&name).cloned().ok_or(DatabaseError::DatabaseNotFound(name))
+    }
+
+    fn in_transaction(&self, id: u64) -> bool {
+        self.with_session(id, |session| Ok(session.transaction.is_some())).unwrap_or_default()
+    }
+
+    fn select(&self, id: u64, name: String, databases: &Databases) -> GraphResults {
+        if !databases.read().unwrap().contains_key(&name) {
+            return Err(DatabaseError::DatabaseNotFound(name));
+        }
+
+        self.with_session(id, |session| {
+            // Queued changes are committed to the database where the transaction was started
+            if session.transaction.is_some() {
+                return Err(DatabaseError::TransactionAlreadyStarted(id.to_string()));
+            }
+            session.database.clone_from(&name);
+
+            let mut result = FxHashMap::default();
+            result.insert("database".to_string(), name);
+            Ok(vec![result])
+        })
+    }
+
+    fn begin(&self, id: u64) -> GraphResults {
+        self.with_session(id, |session| {
+            if session.transaction.is_some() {
+                return Err(DatabaseError::TransactionAlreadyStarted(id.to_string()));
+            }
+            session.transaction = Some(vec![]);
+
+            Ok(vec![Self::as_transaction("pending", 0)])
+        })
+    }
+
+    fn queue(&self, id: u64, command: &str) -> GraphResults {
+        self.with_session(id, |session| {
+            let transaction = session.transaction.as_mut().ok_or(DatabaseError::TransactionNotStarted(id.to_string()))?;
+            transaction.push(command.to_string());
+
+            Ok(vec![Self::as_transaction("pending", transaction.len())])
+        })
+    }
+
+    fn commit(&self, id: u64, source: &str, role: Option<Role>, databases: &Databases) -> GraphResults {
+        let commands = self.with_session(id, |session| {
+            session.transaction.take().ok_or(DatabaseError::TransactionNotStarted(id.to_string()))
+        })?;
+
+        // Session isn't locked while changes are executed, so admins can list sessions in the meantime
+        self.database(id, databases)?.execute_transaction(source, role, &commands)
+    }
+
+    fn rollback(&self, id: u64) -> GraphResults {
+        self.with_session(id, |session| {
+            let commands = session.transaction.take().ok_or(DatabaseError::TransactionNotStarted(id.to_string()))?;
+
+            Ok(vec![Self::as_transaction("rolled_back", commands.len())])
+        })
+    }
+
+    fn with_session<T>(&self, id: u64, action: impl FnOnce(&mut Session) -> Result<T, DatabaseError>) -> Result<T, DatabaseError> {
+        match self.sessions.lock().unwrap().get_mut(&id) {
+            Some(session) => action(session),
+            None => Err(DatabaseError::SessionNotFound(id.to_string())),
+        }
+    }
+
+    fn as_transaction(state: &str, queued: usize) -> FxHashMap<String, String> {
+        let mut result = FxHashMap::default();
+        result.insert("transaction".to_string(), state.to_string());
+        result.insert("queued".to_string(), queued.to_string());
+        result
+    }
+
+    fn fetch(&self) -> Vec<FxHashMap<String, String>> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, session)| Self::as_result(*id, session))
+            .collect()
+    }
+
+    fn close(&self, id: u64) -> GraphResults {
+        let session = self
+            .sessions
+            .lock()
+            .unwrap()
+            .remove(&id)
+            .ok_or(DatabaseError::SessionNotFound(id.to_string()))?;
+        // Permit is stored, so connection is closed even if it's executing command at the moment
+        session.closed.notify_one();
+
+        Ok(vec![Self::as_result(id, &session)])
+    }
+
+    fn as_result(id: u64, session: &Session) -> FxHashMap<String, String> {
+        let mut result = FxHashMap::default();
+        result.insert("id".to_string(), id.to_string());
+        result.insert("address".to_string(), session.address.to_string());
+        result.insert("client".to_string(), session.client.clone().unwrap_or_default());
+        result.insert("role".to_string(), session.role.map(|role| role.to_string()).unwrap_or_default());
+        result.insert("database".to_string(), session.database.clone());
+        // Number of queued changes of pending transaction, or empty if there is none
+        let queued = session.transaction.as_ref().map(|commands| commands.len().to_string());
+        result.insert("transaction".to_string(), queued.unwrap_or_default());
+        result.insert("connected_at".to_string(), session.connected_at.to_string());
+        result.insert("idle".to_string(), session.last_active.elapsed().as_secs().to_string());
+        result.insert("commands".to_string(), session.commands.to_string());
+        result.insert("rate_limited".to_string(), session.rate_limited.to_string());
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::genesis::Genesis;
+
+    #[test]
+    fn should_track_and_close_sessions() {
+        // Given
+        let sessions = Sessions::from_arguments(&HashMap::from([("session-idle-timeout".to_string(), vec!["0".to_string()])])).unwrap();
+        let (first, _) = sessions.open("127.0.0.1:4000".parse().unwrap(), Some(Role::Reader));
+        let (second, _) = sessions.open("127.0.0.1:4001".parse().unwrap(), None);
+        sessions.record(first, Some(Role::Writer), Some("ingest".to_string()));
+        sessions.record(first, Some(Role::Writer), None);
+        sessions.limit(first);
+        sessions.end(second);
+
+        // When
+        let fetched = sessions.execute(Some(Role::Admin), "FETCH sessions;").unwrap().unwrap();
+        let denied = sessions.execute(Some(Role::Writer), "fetch sessions").unwrap();
+        let closed_session = sessions.execute(Some(Role::Admin), &format!("close session {first}")).unwrap().unwrap();
+        let missing = sessions.execute(Some(Role::Admin), &format!("close session {first}")).unwrap();
+
+        // Then
+        assert_eq!(sessions.idle_timeout, None);
+        assert_eq!(fetched.len(), 1);
+        assert_eq!(fetched[0]["client"], "ingest");
+        assert_eq!(fetched[0]["role"], "writer");
+        assert_eq!(fetched[0]["commands"], "2");
+        assert_eq!(fetched[0]["rate_limited"], "1");
+        assert_eq!(fetched[0]["database"], "default");
+        assert_eq!(denied.err().unwrap().code(), "PERMISSION_DENIED");
+        assert_eq!(closed_session[0]["address"], "127.0.0.1:4000");
+        assert_eq!(missing.err().unwrap().code(), "SESSION_NOT_FOUND");
+        assert!(sessions.fetch().is_empty());
+        assert!(sessions.execute(Some(Role::Admin), "fetch nodes").is_none());
+        assert!(Sessions::from_arguments(&HashMap::from([("session-idle-timeout".to_string(), vec!["soon".to_string()])])).is_err());
+    }
+
+    #[test]
+    fn should_select_database_and_commit_transaction() {
+        // Given
+        let database = Database::default();
+        database.execute("define node User(name)").unwrap().unwrap();
+        let databases = Namespaces::new(Arc::new(database), Genesis::default(), HashMap::new()).shared();
+        databases.write().unwrap().insert("staging".to_string(), Arc::new(Database::default()));
+        let sessions = Sessions::default();
+        let (id, _) = sessions.open("127.0.0.1:4000".parse().unwrap(), Some(Role::Writer));
+        let execute = |command: &str| sessions.execute_from(id, "test", Some(Role::Writer), command, &databases).unwrap();
+
+        // When
+        let selected = execute("use staging;").unwrap();
+        let missing = execute("use production");
+        execute("use default").unwrap();
+        execute("begin").unwrap();
+        let started = execute("begin");
+        let queued = execute(r#"add node User(name="John")"#).unwrap();
+        let fetched = execute(r#"fetch node User where (name!="")"#).unwrap();
+        let pending = sessions.execute(Some(Role::Admin), "fetch sessions").unwrap().unwrap();
+        let switched = execute("use staging");
+        execute(r#"add node Song(name="Intro")"#).unwrap();
+        let failed = execute("commit");
+        execute("begin").unwrap();
+        execute(r#"add node User(name="John")"#).unwrap();
+        execute(r#"add node User(name="Jane")"#).unwrap();
+        let committed = execute("commit;").unwrap();
+        execute("begin").unwrap();
+        execute(r#"add node User(name="Bob")"#).unwrap();
+        let rolled_back = execute("rollback").unwrap();
+        let not_started = execute("commit");
+
+        // Then
+        assert_eq!(selected[0]["database"], "staging");
+        assert_eq!(missing.err().unwrap().code(), "DATABASE_NOT_FOUND");
+        assert_eq!(started.err().unwrap().code(), "TRANSACTION_ALREADY_STARTED");
+        assert_eq!(queued[0]["queued"], "1");
+        assert!(fetched.is_empty());
+        assert_eq!(pending[0]["database"], "default");
+        assert_eq!(pending[0]["transaction"], "1");
+        assert_eq!(switched.err().unwrap().code(), "TRANSACTION_ALREADY_STARTED");
+        assert_eq!(failed.err().unwrap().code(), "NODE_NOT_DEFINED");
+        assert_eq!(committed.len(), 2);
+        assert_eq!(rolled_back[0]["transaction"], "rolled_back");
+        assert_eq!(not_started.err().unwrap().code(), "TRANSACTION_NOT_STARTED");
+        assert_eq!(execute(r#"fetch node User where (name!="")"#).unwrap().len(), 2);
+        assert_eq!(sessions.fetch()[0]["transaction"], "");
+    }
+}
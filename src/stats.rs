@@ -0,0 +1,260 @@
+extern crate peg;
+
+use crate::chain::encryption::{LineFile, StorageEncryption};
+use crate::graph::error::DatabaseError;
+use crate::graph::GraphResults;
+use crate::parser::command_parser;
+use peg::error::ParseError;
+use peg::str::LineCol;
+use regex::Regex;
+use rustc_hash::FxHashMap;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+static VALUES: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#""(\\.|[^"\\])*""#).unwrap());
+static NUMBERS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b[0-9]+\b").unwrap());
+
+command_parser! {
+    grammar stats_parser() for str {
+        pub rule fetch_stats() = _ keyword("fetch") _ keyword("query") _ keyword("stats") _ ";"? _
+    }
+}
+
+/// Measurement of one executed command. Parsing ends when the command starts using graph or chain, so command which
+/// wasn't parsed has no execution time.
+pub struct Measurement {
+    pub parse: Duration,
+    pub execution: Duration,
+    pub rows: usize,
+    pub visited: usize,
+    pub failed: bool,
+}
+
+impl Measurement {
+    pub fn new(started: Instant, executed_at: Option<Instant>, result: &Result<GraphResults, ParseError<LineCol>>, visited: usize) -> Measurement {
+        let now = Instant::now();
+        let executed_at = executed_at.unwrap_or(now);
+
+        Measurement {
+            parse: executed_at.duration_since(started),
+            execution: now.duration_since(executed_at),
+            rows: result.as_ref().ok().and_then(|result| result.as_ref().ok()).map_or(0, Vec::len),
+            visited,
+            failed: !matches!(result, Ok(Ok(_))),
+        }
+    }
+}
+
+/// Aggregated measurements of commands with the same pattern.
+#[derive(Default)]
+struct Statistics {
+    count: u64,
+    errors: u64,
+    parse: Duration,
+    max_parse: Duration,
+    execution: Duration,
+    max_execution: Duration,
+    rows: u64,
+    visited: u64,
+}
+
+/// Statistics of executed commands, aggregated by command pattern - command with values and numbers replaced by `?`,
+/// e.g. `fetch node User($id="?") join Playlist($weight>"?")`, so operators can find which access patterns are slow
+/// or visit many nodes. Patterns of commands slower than threshold given by `--slow-query-ms=100` argument are logged
+/// to standard error, or as JSON lines to file given by `--slow-query-log="slow.jsonl"` argument (encrypted as archive,
+/// if storage encryption is configured).
+#[derive(Default)]
+pub struct QueryStats {
+    statistics: Mutex<FxHashMap<String, Statistics>>,
+    threshold: Option<Duration>,
+    log: Option<(String, Mutex<LineFile>)>,
+}
+
+impl QueryStats {
+    /// Patterns which are aggregated, so commands with unique structure can't grow statistics without limit.
+    const MAX_PATTERNS: usize = 1024;
+    const OTHER_PATTERN: &'static str = "(other)";
+
+    pub fn from_arguments(arguments: &HashMap<String, Vec<String>>, encryption: Option<StorageEncryption>) -> Result<QueryStats, DatabaseError> {
+        let argument = |name: &str| arguments.get(name).and_then(|values| values.first());
+
+        let threshold = argument("slow-query-ms")
+            .map(|value| {
+                value
+                    .parse()
+                    .map(Duration::from_millis)
+                    .map_err(|_| DatabaseError::InvalidSlowQueryConfiguration(format!("slow-query-ms={value}")))
+            })
+            .transpose()?;
+
+        Ok(QueryStats {
+            statistics: Mutex::default(),
+            threshold,
+            log: argument("slow-query-log").map(|path| (path.clone(), Mutex::new(LineFile::new(path, encryption)))),
+        })
+    }
+
+    /// Pattern of the command, with quoted values and numbers replaced by `?`.
+    pub fn pattern(command: &str) -> String {
+        let pattern = VALUES.replace_all(command, "\"?\"");
+        let pattern = NUMBERS.replace_all(&pattern, "?");
+
+        pattern
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+            .trim_end_matches(';')
+            .trim_end()
+            .to_string()
+    }
+
+    pub fn record(&self, command: &str, measurement: Measurement) {
+        let pattern = Self::pattern(command);
+        if self
+            .threshold
+            .is_some_and(|threshold| measurement.parse + measurement.execution >= threshold)
+        {
+            self.log_slow_query(&pattern, &measurement);
+        }
+
+        let mut statistics = self.statistics.lock().unwrap();
+        let pattern = if statistics.len() < Self::MAX_PATTERNS || statistics.contains_key(&pattern) {
+            pattern
+        } else {
+            Self::OTHER_PATTERN.to_string()
+        };
+
+        let statistics = statistics.entry(pattern).or_default();
+        statistics.count += 1;
+        statistics.errors += measurement.failed as u64;
+        statistics.parse += measurement.parse;
+        statistics.max_parse = statistics.max_parse.max(measurement.parse);
+        statistics.execution += measurement.execution;
+        statistics.max_execution = statistics.max_execution.max(measurement.execution);
+        statistics.rows += measurement.rows as u64;
+        statistics.visited += measurement.visited as u64;
+    }
+
+    /// Execute `fetch query stats` command, or return `None` if given command is not statistics command. Patterns are
+    /// sorted by total execution time, so the most expensive patterns come first.
+    pub fn execute(&self, command: &str) -> Option<GraphResults> {
+        stats_parser::fetch_stats(command).ok()?;
+
+        let statistics = self.statistics.lock().unwrap();
+        let mut patterns: Vec<(&String, &Statistics)> = statistics.iter().collect();
+        patterns.sort_by(|(first, first_statistics), (second, second_statistics)| {
+            second_statistics.execution.cmp(&first_statistics.execution).then(first.cmp(second))
+        });
+
+        let results = patterns
+            .into_iter()
+            .map(|(pattern, statistics)| {
+                let average = |total: Duration| (total / statistics.count as u32).as_micros().to_string();
+
+                let mut result = FxHashMap::default();
+                result.insert("pattern".to_string(), pattern.clone());
+                result.insert("count".to_string(), statistics.count.to_string());
+                result.insert("errors".to_string(), statistics.errors.to_string());
+                result.insert("avg_parse_us".to_string(), average(statistics.parse));
+                result.insert("max_parse_us".to_string(), statistics.max_parse.as_micros().to_string());
+                result.insert("avg_execution_us".to_string(), average(statistics.execution));
+                result.insert("max_execution_us".to_string(), statistics.max_execution.as_micros().to_string());
+                result.insert("total_execution_us".to_string(), statistics.execution.as_micros().to_string());
+                result.insert("rows".to_string(), statistics.rows.to_string());
+                result.insert("visited".to_string(), statistics.visited.to_string());
+                result
+            })
+            .collect();
+
+        Some(Ok(results))
+    }
+
+    /// Log pattern of slow command instead of its text, so values (e.g. secret attributes) don't end up in the log.
+    fn log_slow_query(&self, pattern: &str, measurement: &Measurement) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        let entry = json!({
+            "timestamp": timestamp,
+            "pattern": pattern,
+            "parse_us": measurement.parse.as_micros(),
+            "execution_us": measurement.execution.as_micros(),
+            "rows": measurement.rows,
+            "visited": measurement.visited,
+        });
+
+        let Some((path, file)) = &self.log else {
+            eprintln!("Slow query: {entry}");
+            return;
+        };
+
+        if let Err(error) = file.lock().unwrap().append(&[entry.to_string()]) {
+            eprintln!("{}", DatabaseError::FileError(path.clone(), error));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn should_aggregate_statistics_by_command_pattern() {
+        // Given
+        let path = env::temp_dir().join("should_aggregate_statistics_by_command_pattern.jsonl");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let arguments = HashMap::from([
+            ("slow-query-ms".to_string(), vec!["0".to_string()]),
+            ("slow-query-log".to_string(), vec![path.to_string()]),
+        ]);
+        let database = Database {
+            stats: QueryStats::from_arguments(&arguments, None).unwrap(),
+            ..Database::default()
+        };
+        database.execute("define node User(name)").unwrap().unwrap();
+        let john = database.execute("add node User(name=\"John\")").unwrap().unwrap();
+        database.execute("add node User(name=\"Jane\")").unwrap().unwrap();
+
+        // When
+        database
+            .execute(&format!("fetch node User($id=\"{}\")", john[0]["$id"]))
+            .unwrap()
+            .unwrap();
+        let missing = database.execute("fetch node User($id=\"missing\")").unwrap();
+        let stats = database.execute("fetch query stats;").unwrap().unwrap();
+        let encrypted_path = env::temp_dir().join("should_aggregate_statistics_by_command_pattern.encrypted.jsonl");
+        let _ = fs::remove_file(&encrypted_path);
+        let encrypted_arguments = HashMap::from([
+            ("slow-query-ms".to_string(), vec!["0".to_string()]),
+            ("slow-query-log".to_string(), vec![encrypted_path.to_str().unwrap().to_string()]),
+        ]);
+        let encrypted = QueryStats::from_arguments(&encrypted_arguments, Some(StorageEncryption::from_passphrase("secret"))).unwrap();
+        encrypted.record("fetch query stats", Measurement::new(Instant::now(), None, &Ok(Ok(vec![])), 0));
+
+        // Then
+        let fetch = stats.iter().find(|row| row["pattern"] == "fetch node User($id=\"?\")").unwrap();
+        let add = stats.iter().find(|row| row["pattern"] == "add node User(name=\"?\")").unwrap();
+        assert!(missing.is_err());
+        assert_eq!(fetch["count"], "2");
+        assert_eq!(fetch["errors"], "1");
+        assert_eq!(fetch["rows"], "1");
+        assert!(fetch["visited"].parse::<usize>().unwrap() >= 2);
+        assert_eq!(add["count"], "2");
+        assert_eq!(stats.len(), 3);
+        let log = fs::read_to_string(path).unwrap();
+        assert_eq!(log.lines().count(), 5);
+        assert!(log.contains(r#""pattern":"add node User(name=\"?\")""#));
+        assert!(!log.contains("John"));
+        assert!(fs::read_to_string(&encrypted_path).unwrap().lines().all(|line| !line.contains("pattern")));
+        assert_eq!(QueryStats::pattern("fetch blocks from 10 to 20;"), "fetch blocks from ? to ?");
+        assert!(QueryStats::from_arguments(&HashMap::from([("slow-query-ms".to_string(), vec!["fast".to_string()])]), None).is_err());
+
+        fs::remove_file(path).unwrap();
+        fs::remove_file(encrypted_path).unwrap();
+    }
+}
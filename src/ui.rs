@@ -0,0 +1,350 @@
+use crate::access::{AccessControl, ClientToken, Role};
+use crate::database::Database;
+use crate::graph::error::DatabaseError;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+use rustc_hash::FxHashMap;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::{select, time};
+
+/// Web page which visualizes the graph and the chain timeline, for demos and debugging convergence of peers. Page
+/// reads the graph (`/api/graph`) and the latest blocks (`/api/chain`) over HTTP, and reloads them when WebSocket
+/// (`/ws`) reports that the chain height, the last block or the number of nodes was changed. Data is served only to
+/// clients with token given by `token` query parameter (or bearer token of `Authorization` header), which is verified
+/// the same way as by `authenticate <token>` command of query server. Secret attributes are served only to admins.
+pub struct UiServer {
+    listener: TcpListener,
+    access: Option<Arc<AccessControl>>,
+}
+
+impl UiServer {
+    /// Nodes sent to the page, so large graph doesn't freeze the browser.
+    const MAX_NODES: usize = 2000;
+    const MAX_BLOCKS: usize = 100;
+    const PAGE: &'static str = include_str!("ui/index.html");
+    /// Key suffix used to accept WebSocket connection (RFC 6455).
+    const WEBSOCKET_GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+    /// Size of request line and headers, larger requests are rejected.
+    const MAX_HEADER_SIZE: u64 = 8 * 1024;
+
+    /// Listen on address given by `--ui="127.0.0.1:8080"` argument, if it is given.
+    pub async fn from_arguments(arguments: &HashMap<String, Vec<String>>) -> io::Result<Option<UiServer>> {
+        match arguments.get("ui").and_then(|values| values.first()) {
+            None => Ok(None),
+            Some(address) => Ok(Some(UiServer {
+                listener: TcpListener::bind(address).await?,
+                access: None,
+            })),
+        }
+    }
+
+    pub fn with_access(mut self, access: Option<Arc<AccessControl>>) -> UiServer {
+        self.access = access;
+        self
+    }
+
+    pub fn spawn(self, database: Arc<Database>) {
+        tokio::spawn(async move {
+            loop {
+                match self.listener.accept().await {
+                    Ok((stream, address)) => {
+                        let (database, access) = (database.clone(), self.access.clone());
+                        tokio::spawn(async move {
+                            if let Err(error) = Self::handle_client(stream, database, access).await {
+                                eprintln!("UI client {address} :: {error}");
+                            }
+                        });
+                    }
+                    Err(error) => eprintln!("UI server :: {error}"),
+                }
+            }
+        });
+    }
+
+    async fn handle_client(mut stream: TcpStream, database: Arc<Database>, access: Option<Arc<AccessControl>>) -> io::Result<()> {
+        let mut reader = BufReader::new(&mut stream).take(Self::MAX_HEADER_SIZE);
+        let mut request = String::new();
+        reader.read_line(&mut request).await?;
+
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+            }
+        }
+        if reader.limit() == 0 {
+            return Self::respond(&mut stream, "431 Request Header Fields Too Large", "text/plain", "Request is too large").await;
+        }
+
+        let target = match request.split_whitespace().collect::<Vec<&str>>()[..] {
+            ["GET", target, _] => target,
+            _ => return Self::respond(&mut stream, "405 Method Not Allowed", "text/plain", "Method not allowed").await,
+        };
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+        // Page itself doesn't contain any data
+        if path == "/" {
+            return Self::respond(&mut stream, "200 OK", "text/html; charset=utf-8", Self::PAGE).await;
+        }
+
+        let token = query
+            .split('&')
+            .find_map(|parameter| parameter.strip_prefix("token="))
+            .or_else(|| headers.get("authorization").and_then(|value| value.strip_prefix("Bearer ")));
+        let role = match Self::role(access.as_deref(), token, &database) {
+            Ok(role) => role,
+            Err(error) => return Self::respond(&mut stream, "401 Unauthorized", "text/plain", &error.to_string()).await,
+        };
+
+        match (path, headers.get("sec-websocket-key")) {
+            ("/api/graph", _) => Self::respond(&mut stream, "200 OK", "application/json", &Self::graph(&database, role).to_string()).await,
+            ("/api/chain", _) => Self::respond(&mut stream, "200 OK", "application/json", &Self::chain(&database).to_string()).await,
+            ("/ws", Some(key)) => {
+                let accept = STANDARD.encode(digest(&SHA1_FOR_LEGACY_USE_ONLY, format!("{key}{}", Self::WEBSOCKET_GUID).as_bytes()));
+                let response = format!(
+                    "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+                );
+                stream.write_all(response.as_bytes()).await?;
+                Self::stream_updates(stream, database).await
+            }
+            _ => Self::respond(&mut stream, "404 Not Found", "text/plain", "Not found").await,
+        }
+    }
+
+    /// Role of the client with given token, from access file or signed by local wallet. Default role of access control
+    /// isn't used, as data would be served to anyone who can reach the page.
+    fn role(access: Option<&AccessControl>, token: Option<&str>, database: &Database) -> Result<Role, DatabaseError> {
+        let token = token.ok_or(DatabaseError::PermissionDenied("client is not authenticated".to_string()))?;
+        let authority = || database.chain.read().unwrap().signer.get_public_key();
+
+        match access {
+            Some(access) => Ok(access.verify(token, authority)?.0),
+            None => Ok(ClientToken::verify(token, &authority())?.role),
+        }
+    }
+
+    async fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> io::Result<()> {
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await
+    }
+
+    /// Send state of the database each time it is changed, until client closes the connection. Frames sent by client
+    /// are not interpreted, as the page doesn't send any messages.
+    async fn stream_updates(stream: TcpStream, database: Arc<Database>) -> io::Result<()> {
+        let (mut reader, mut writer) = stream.into_split();
+        let mut timer = time::interval(Duration::from_secs(1));
+        let mut buffer = [0u8; 1024];
+        let mut last_state = Value::Null;
+
+        loop {
+            select! {
+                read = reader.read(&mut buffer) => {
+                    if read? == 0 {
+                        return Ok(());
+                    }
+                },
+                _ = timer.tick() => {
+                    let state = Self::state(&database);
+                    if state != last_state {
+                        writer.write_all(&text_frame(&state.to_string())).await?;
+                        last_state = state;
+                    }
+                },
+            }
+        }
+    }
+
+    fn state(database: &Database) -> Value {
+        let nodes = database.graph.read().unwrap().nodes.len();
+        let chain = database.chain.read().unwrap();
+
+        json!({
+            "height": chain.blocks.len(),
+            "hash": chain.blocks.last().map(|block| block.hash.clone()).unwrap_or_default(),
+            "nodes": nodes,
+        })
+    }
+
+    /// Nodes which are not soft deleted, with their attributes (secret attributes only for admin), and edges between
+    /// them.
+    fn graph(database: &Database, role: Role) -> Value {
+        let graph = database.graph.read().unwrap();
+        let mut keys: Vec<&String> = graph.nodes.iter().filter(|(_, node)| !node.is_deleted()).map(|(key, _)| key).collect();
+        keys.sort();
+        let truncated = keys.len() > Self::MAX_NODES;
+        keys.truncate(Self::MAX_NODES);
+
+        let nodes: Vec<Value> = keys
+            .iter()
+            .map(|key| {
+                let mut attributes: FxHashMap<String, String> = graph.nodes.get(key).unwrap().attributes.clone();
+                if role < Role::Admin {
+                    graph.redact(std::slice::from_mut(&mut attributes));
+                }
+                json!({ "key": key, "attributes": attributes })
+            })
+            .collect();
+        let edges: Vec<Value> = keys
+            .iter()
+            .flat_map(|key| {
+                graph
+                    .nodes
+                    .get(key)
+                    .unwrap()
+                    .edges
+                    .iter()
+                    .map(move |edge| json!({ "from": key, "to": format!("{}:{}", edge.to_node_id, edge.to_node), "weight": edge.weight }))
+            })
+            .collect();
+
+        json!({ "nodes": nodes, "edges": edges, "truncated": truncated })
+    }
+
+    /// The latest blocks, newest first.
+    fn chain(database: &Database) -> Value {
+        let chain = database.chain.read().unwrap();
+
+        chain
+            .blocks
+            .iter()
+            .rev()
+            .take(Self::MAX_BLOCKS)
+            .map(|block| {
+                json!({
+                    "id": block.id,
+                    "hash": block.hash,
+                    "timestamp": block.timestamp,
+                    "validator": block.validator,
+                    "data": block.data,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Unmasked WebSocket text frame, as sent by server.
+fn text_frame(text: &str) -> Vec<u8> {
+    let mut frame = vec![0x81];
+    match text.len() {
+        length if length < 126 => frame.push(length as u8),
+        length if length <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend((length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend((length as u64).to_be_bytes());
+        }
+    }
+    frame.extend(text.as_bytes());
+
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn should_serve_graph_and_accept_websocket() {
+        // Given
+        let arguments = HashMap::from([("ui".to_string(), vec!["127.0.0.1:0".to_string()])]);
+        let server = UiServer::from_arguments(&arguments).await.unwrap().unwrap();
+        let address = server.listener.local_addr().unwrap();
+        let database = Database::default();
+        database.execute("define node User(name, email secret)").unwrap().unwrap();
+        database
+            .execute("add node User($id=\"1\", name=\"John\", email=\"john@example.com\")")
+            .unwrap()
+            .unwrap();
+        let token = |role: &str| database.execute(&format!("create token for role {role} expires 1h")).unwrap().unwrap()[0]["token"].clone();
+        let (reader, admin) = (token("reader"), token("admin"));
+        server.spawn(Arc::new(database));
+        let get = |request: String| async move {
+            let mut response = String::new();
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            stream.write_all(request.as_bytes()).await.unwrap();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        // When
+        let anonymous = get("GET /api/graph HTTP/1.1\r\n\r\n".to_string()).await;
+        let graph = get(format!("GET /api/graph?token={reader} HTTP/1.1\r\n\r\n")).await;
+        let admin_graph = get(format!("GET /api/graph?token={admin} HTTP/1.1\r\n\r\n")).await;
+        // Whole request is read by the server, so connection isn't reset before the response is received
+        let oversized = get(format!("GET /api/graph HTTP/1.1\r\nCookie: {}", "a".repeat(8 * 1024 - 33))).await;
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let request = format!("GET /ws?token={reader} HTTP/1.1\r\nUpgrade: websocket\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n");
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        let status = lines.next_line().await.unwrap().unwrap();
+        let mut handshake = vec![];
+        while let Some(line) = lines.next_line().await.unwrap().filter(|line| !line.is_empty()) {
+            handshake.push(line);
+        }
+        let mut frame = [0u8; 2];
+        lines.get_mut().read_exact(&mut frame).await.unwrap();
+
+        // Then
+        assert!(anonymous.starts_with("HTTP/1.1 401 Unauthorized"));
+        assert!(graph.starts_with("HTTP/1.1 200 OK"));
+        assert!(graph.contains(r#""key":"1:User""#));
+        assert!(!graph.contains("john@example.com"));
+        assert!(admin_graph.contains("john@example.com"));
+        assert!(oversized.starts_with("HTTP/1.1 431 Request Header Fields Too Large"));
+        assert_eq!(status, "HTTP/1.1 101 Switching Protocols");
+        assert!(handshake.contains(&"Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=".to_string()));
+        assert_eq!(frame[0], 0x81);
+    }
+
+    #[tokio::test]
+    async fn should_serve_data_only_to_client_with_role() {
+        // Given
+        let arguments = HashMap::from([("ui".to_string(), vec!["127.0.0.1:0".to_string()])]);
+        let access = serde_json::from_str(r#"{"clients":[{"name":"viewer","token":"secret","role":"reader"}]}"#).unwrap();
+        let server = UiServer::from_arguments(&arguments)
+            .await
+            .unwrap()
+            .unwrap()
+            .with_access(Some(Arc::new(access)));
+        let address = server.listener.local_addr().unwrap();
+        server.spawn(Arc::new(Database::default()));
+        let get = |request: &'static str| async move {
+            let mut response = String::new();
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            stream.write_all(request.as_bytes()).await.unwrap();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        // When
+        let page = get("GET / HTTP/1.1\r\n\r\n").await;
+        let anonymous = get("GET /api/chain HTTP/1.1\r\n\r\n").await;
+        let invalid = get("GET /api/chain?token=other HTTP/1.1\r\n\r\n").await;
+        let reader = get("GET /api/chain?token=secret HTTP/1.1\r\n\r\n").await;
+        let bearer = get("GET /api/graph HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n").await;
+
+        // Then
+        assert!(page.starts_with("HTTP/1.1 200 OK"));
+        assert!(anonymous.starts_with("HTTP/1.1 401 Unauthorized"));
+        assert!(invalid.starts_with("HTTP/1.1 401 Unauthorized"));
+        assert!(reader.starts_with("HTTP/1.1 200 OK"));
+        assert!(bearer.starts_with("HTTP/1.1 200 OK"));
+    }
+}